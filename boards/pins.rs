@@ -0,0 +1,159 @@
+//! Build-time pin map, shared by the build scripts of the demo crates.
+//!
+//! `BOARD` names a file in this directory, e.g. `BOARD=c3-devkit` for
+//! `c3-devkit.toml`. Its `[pins]` table is turned into `$OUT_DIR/board.rs`
+//! with a const per pin, e.g. `LED_PIN`, a macro taking the pin out of the
+//! peripherals, e.g. `led_pin!(peripherals)` for `peripherals.GPIO2`, and the
+//! whole map as `PINS`, e.g. `[("led", 2), ..]`.
+//!
+//! The roles of [`LISTS`] may also take an array of pins, e.g.
+//! `button = [4, 5]` for two buttons: their pins are `BUTTON_PINS` and
+//! `button_pins!(peripherals)`, an array of `AnyPin`, whether one pin or
+//! several is given. A single pin also gets `BUTTON_PIN` and `button_pin!`
+//! and is `button` in `PINS`; several are `button0`, `button1`..
+
+use std::{collections::BTreeMap, fmt::Write as _, path::Path};
+
+/// Pin roles known to the demos: key in `[pins]` and generated const.
+const ROLES: &[(&str, &str)] = &[
+    ("led", "LED_PIN"),
+    ("accent_led", "ACCENT_LED_PIN"),
+    ("button", "BUTTON_PIN"),
+    ("i2c_sda", "I2C_SDA"),
+    ("i2c_scl", "I2C_SCL"),
+    ("fan_pwm", "FAN_PWM_PIN"),
+    ("fan_tach", "FAN_TACH_PIN"),
+    ("battery", "BATTERY_PIN"),
+    ("stay_awake", "STAY_AWAKE_PIN"),
+];
+
+/// Pin roles that may take an array of pins: key in `[pins]` and generated
+/// const.
+const LISTS: &[(&str, &str)] = &[("button", "BUTTON_PINS")];
+
+/// Highest GPIO number of `chip`.
+fn max_gpio(chip: &str) -> Option<u8> {
+    match chip {
+        "esp32c3" => Some(21),
+        "esp32c6" => Some(30),
+        _ => None,
+    }
+}
+
+/// Write `board.rs` for the board picked with `BOARD`, `default` if unset,
+/// for a firmware on `chip` using the pins `required`.
+///
+/// # Panics
+///
+/// With a readable message if the board file is missing or invalid, is for
+/// another chip, lacks a required pin or assigns a pin twice.
+pub fn generate(chip: &str, required: &[&str], default: &str) {
+    println!("cargo:rerun-if-env-changed=BOARD");
+    let board = std::env::var("BOARD").unwrap_or_else(|_| default.into());
+    let boards = Path::new(env!("CARGO_MANIFEST_DIR")).join("../boards");
+    let file = boards.join(format!("{board}.toml"));
+    println!("cargo:rerun-if-changed={}", file.display());
+
+    let fail = |msg: String| -> ! { panic!("\n\nboard `{board}`: {msg}\n\n") };
+
+    let text = std::fs::read_to_string(&file).unwrap_or_else(|_| {
+        fail(format!(
+            "no {} (see boards/ for the known boards)",
+            file.display()
+        ))
+    });
+    let table: toml::Table = text
+        .parse()
+        .unwrap_or_else(|e| fail(format!("invalid TOML: {e}")));
+
+    match table.get("chip").and_then(toml::Value::as_str) {
+        Some(c) if c == chip => {}
+        Some(c) => fail(format!("is an {c} board, this firmware is for the {chip}")),
+        None => fail("no `chip`".into()),
+    }
+    let max = max_gpio(chip).unwrap_or_else(|| fail(format!("unknown chip {chip}")));
+
+    let pins = table
+        .get("pins")
+        .and_then(toml::Value::as_table)
+        .unwrap_or_else(|| fail("no [pins] table".into()));
+
+    let mut assigned: BTreeMap<u8, &str> = BTreeMap::new();
+    let mut out = format!("/// The board the pins are for.\npub const BOARD: &str = {board:?};\n");
+    let mut map = String::new();
+    for (role, value) in pins {
+        let Some((_, name)) = ROLES.iter().find(|(r, _)| r == role) else {
+            let known: Vec<_> = ROLES.iter().map(|(r, _)| *r).collect();
+            fail(format!("unknown pin `{role}`, expected one of {known:?}"));
+        };
+        let list = LISTS.iter().find(|(r, _)| r == role).map(|(_, list)| *list);
+        let values = match (value.as_array(), list) {
+            (Some(values), Some(_)) if !values.is_empty() => values.iter().collect(),
+            (Some(_), _) => fail(format!("`{role}` takes a single pin, not {value}")),
+            (None, _) => vec![value],
+        };
+
+        let mut pins_of_role = Vec::new();
+        for value in values {
+            let pin = value
+                .as_integer()
+                .and_then(|p| u8::try_from(p).ok())
+                .filter(|p| *p <= max)
+                .unwrap_or_else(|| fail(format!("`{role}` = {value} is not a GPIO0..=GPIO{max}")));
+            if let Some(other) = assigned.insert(pin, role) {
+                fail(format!(
+                    "GPIO{pin} is assigned to both `{other}` and `{role}`"
+                ));
+            }
+            pins_of_role.push(pin);
+        }
+
+        if let [pin] = pins_of_role[..] {
+            let _ = write!(
+                out,
+                "\n/// GPIO of `{role}`.\npub const {name}: u8 = {pin};\n\n\
+                 /// `$peripherals.GPIO{pin}`, the pin of `{role}`.\n\
+                 macro_rules! {} {{\n    ($peripherals:ident) => {{\n        \
+                 $peripherals.GPIO{pin}\n    }};\n}}\n",
+                name.to_lowercase(),
+            );
+            let _ = write!(map, "({role:?}, {pin}), ");
+        } else {
+            for (i, pin) in pins_of_role.iter().enumerate() {
+                let _ = write!(map, "(\"{role}{i}\", {pin}), ");
+            }
+        }
+        if let Some(list) = list {
+            let numbers: Vec<_> = pins_of_role.iter().map(u8::to_string).collect();
+            let anys: Vec<_> = pins_of_role
+                .iter()
+                .map(|pin| format!("$peripherals.GPIO{pin}.into()"))
+                .collect();
+            let _ = write!(
+                out,
+                "\n/// GPIOs of `{role}`.\npub const {list}: &[u8] = &[{}];\n\n\
+                 /// The pins of `{role}` taken out of `$peripherals`.\n\
+                 macro_rules! {} {{\n    ($peripherals:ident) => {{{{\n        \
+                 let pins: [esp_hal::gpio::AnyPin<'static>; {}] = [{}];\n        \
+                 pins\n    }}}};\n}}\n",
+                numbers.join(", "),
+                list.to_lowercase(),
+                pins_of_role.len(),
+                anys.join(", "),
+            );
+        }
+    }
+    let _ = write!(
+        out,
+        "\n/// GPIO of every pin role.\npub const PINS: &[(&str, u8)] = &[{map}];\n"
+    );
+
+    for role in required {
+        if !pins.contains_key(*role) {
+            fail(format!("no `{role}` pin, which this firmware needs"));
+        }
+    }
+
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    std::fs::write(Path::new(&out_dir).join("board.rs"), out).unwrap();
+}
@@ -1,6 +1,21 @@
+// Pin map of the board picked with `BOARD`, see `boards/`.
+#[path = "../boards/pins.rs"]
+mod board;
+
 fn main() {
+    let mut required = vec!["led", "button", "i2c_sda", "i2c_scl"];
+    if std::env::var_os("CARGO_FEATURE_LOW_POWER").is_some() {
+        required.extend(["battery", "stay_awake"]);
+    }
+    if std::env::var_os("CARGO_FEATURE_ACCENT").is_some() {
+        required.push("accent_led");
+    }
+    board::generate("esp32c3", &required, "esp-rust-board");
     linker_be_nice();
     println!("cargo:rustc-link-arg=-Tlinkall.x");
+    if std::env::var_os("CARGO_FEATURE_DEFMT").is_some() {
+        println!("cargo:rustc-link-arg=-Tdefmt.x");
+    }
 }
 
 fn linker_be_nice() {
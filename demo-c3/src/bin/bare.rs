@@ -0,0 +1,201 @@
+//! Minimal Thing brought up by hand, without the `EspThing` trait.
+//!
+//! It does what [`wot_esp_thing::EspThing::run`] does, minus mDNS and the
+//! error handling, to show the moving parts: heap, scheduler, Wi-Fi,
+//! embassy-net, the TD and a single picoserve task serving it together with
+//! the die temperature property.
+
+#![no_std]
+#![no_main]
+#![recursion_limit = "1024"]
+#![feature(impl_trait_in_assoc_type)]
+
+extern crate alloc;
+
+use alloc::{format, string::String};
+use embassy_executor::Spawner;
+use embassy_time::{Duration, Timer};
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    interrupt::software::SoftwareInterruptControl,
+    timer::timg::TimerGroup,
+    tsens::{Config as TsensConfig, TemperatureSensor},
+};
+use esp_radio::wifi::ControllerConfig;
+use picoserve::AppWithStateBuilder;
+use wot_esp_thing::{
+    configuration, connection, entropy, get_urn_or_uuid, mk_static, net_task, properties, td,
+    td_routes, web_task, wifi, Outcome, SelfTest, ThingCore, HTTP_BUFFER, TCP_RX_BUFFER,
+    TCP_TX_BUFFER,
+};
+use wot_td::{
+    builder::{
+        BuildableHumanReadableInfo, BuildableInteractionAffordance, ReadableWriteableDataSchema,
+        SpecializableDataSchema,
+    },
+    thing::FormOperation,
+    Thing,
+};
+
+const NAME: &str = "bare";
+
+/// Bytes of RAM given to the heap.
+const HEAP_SIZE: usize = 144 * 1024;
+
+/// One socket for DHCP, one for the web task.
+const SOCKETS: usize = 2;
+
+#[derive(Clone, Copy)]
+struct AppState {
+    die_sensor: &'static TemperatureSensor<'static>,
+    core: &'static ThingCore,
+}
+
+impl AsRef<ThingCore> for AppState {
+    fn as_ref(&self) -> &ThingCore {
+        self.core
+    }
+}
+
+impl SelfTest for AppState {
+    const CHECKS: &'static [&'static str] = &["die_sensor"];
+
+    async fn check(&self, name: &str) -> Outcome {
+        match name {
+            // The operating range of the chip.
+            "die_sensor" => {
+                let t = self.die_sensor.get_temperature().to_celsius();
+                if (-40.0..=125.0).contains(&t) {
+                    Outcome::Ok
+                } else {
+                    Outcome::Failed
+                }
+            }
+            _ => Outcome::Skipped,
+        }
+    }
+}
+
+struct AppProps;
+
+impl AppWithStateBuilder for AppProps {
+    type State = AppState;
+    type PathRouter = impl picoserve::routing::PathRouter<Self::State>;
+
+    fn build_app(self) -> picoserve::Router<Self::PathRouter, Self::State> {
+        properties!(td_routes::<AppState>(), AppState, {
+            "die_temperature" => get: |s| s.die_sensor.get_temperature().to_celsius();
+        })
+    }
+}
+
+fn build_td(base_uri: String, id: String) -> Thing {
+    Thing::builder(NAME)
+        .finish_extend()
+        .id(id)
+        .base(base_uri)
+        .description("Minimal Thing exposing the die temperature")
+        .security(|builder| builder.no_sec().required().with_key("nosec_sc"))
+        .property("die_temperature", |p| {
+            p.finish_extend_data_schema()
+                .attype("TemperatureProperty")
+                .title("Die temperature")
+                .form(|f| {
+                    f.href("/properties/die_temperature")
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .number()
+                .read_only()
+                .unit("Celsius")
+        })
+        .build()
+        .unwrap()
+}
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[esp_rtos::main]
+async fn main(spawner: Spawner) {
+    #[cfg(not(feature = "defmt"))]
+    esp_println::logger::init_logger_from_env();
+    let peripherals =
+        esp_hal::init(esp_hal::Config::default().with_cpu_clock(esp_hal::clock::CpuClock::max()));
+    esp_alloc::heap_allocator!(size: HEAP_SIZE);
+
+    configuration::init(&configuration::Configuration {
+        name: NAME,
+        version: td::FIRMWARE_VERSION,
+        board: None,
+        pins: &[],
+        features: configuration::FEATURES,
+        heap_size: HEAP_SIZE,
+        web_tasks: 1,
+        sockets: SOCKETS,
+        tcp_buffers: (TCP_RX_BUFFER, TCP_TX_BUFFER),
+        http_buffer: HTTP_BUFFER,
+        sample_interval_ms: None,
+    });
+
+    // The scheduler esp-radio and embassy run on.
+    let timg0 = TimerGroup::new(peripherals.TIMG0);
+    let sw_int = SoftwareInterruptControl::new(peripherals.SW_INTERRUPT);
+    esp_rtos::start(timg0.timer0, sw_int.software_interrupt0);
+
+    let state = mk_static!(
+        AppState,
+        AppState {
+            die_sensor: mk_static!(
+                TemperatureSensor<'static>,
+                TemperatureSensor::new(peripherals.TSENS, TsensConfig::default()).unwrap()
+            ),
+            core: mk_static!(ThingCore, ThingCore::new(NAME)),
+        }
+    );
+
+    // Wi-Fi station joining the first configured network.
+    let (mut controller, interfaces) =
+        esp_radio::wifi::new(peripherals.WIFI, ControllerConfig::default()).unwrap();
+    controller
+        .set_config(&wifi::config(wifi::configured().next().unwrap()))
+        .unwrap();
+
+    // Network stack configured over DHCP.
+    let mac = interfaces.station.mac_address();
+    let seed = entropy::net_seed(&mut entropy::CountingRng::new(), mac);
+    let (stack, runner) = embassy_net::new(
+        interfaces.station,
+        embassy_net::Config::dhcpv4(Default::default()),
+        mk_static!(
+            embassy_net::StackResources<SOCKETS>,
+            embassy_net::StackResources::new()
+        ),
+        seed,
+    );
+    spawner.spawn(connection(controller, stack, false).unwrap());
+    spawner.spawn(net_task(runner).unwrap());
+
+    let base_uri = loop {
+        if let Some(config) = stack.config_v4() {
+            break format!("http://{}", config.address.address());
+        }
+        Timer::after(Duration::from_millis(500)).await;
+    };
+
+    // The TD, with the version and connectivity affordances every Thing has.
+    let meta = td::TdMetadata {
+        version: td::FIRMWARE_VERSION,
+        support: td::SUPPORT_URL,
+        links: &[],
+        group: &[],
+    };
+    let id = mk_static!(String, get_urn_or_uuid(stack, NAME));
+    state.core.set_id(id.as_str());
+    let thing = build_td(base_uri, id.clone());
+    td::set(td::serialize_with_metadata(&thing, &meta).unwrap()).await;
+
+    let app = mk_static!(picoserve::AppRouter<AppProps>, AppProps.build_app());
+
+    web_task::<AppProps>(0, stack, app, true, state).await;
+}
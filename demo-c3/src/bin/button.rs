@@ -10,44 +10,58 @@ use portable_atomic::AtomicBool;
 
 use alloc::string::String;
 use embassy_executor::Spawner;
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch::Watch};
+use embassy_futures::select::select_array;
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, pubsub::PubSubChannel};
+use embassy_time::{Duration, Instant, Timer};
 use esp_alloc as _;
 use esp_backtrace as _;
-use esp_hal::gpio::{Input, InputConfig, Pull};
-use esp_println::println;
+use esp_hal::gpio::{Event, Input, InputConfig, Pull};
 use picoserve::{
     extract::State,
-    response::{self},
-    routing::get,
-    AppWithStateBuilder,
-};
-use wot_td::{
-    builder::{
-        BuildableHumanReadableInfo, BuildableInteractionAffordance, ReadableWriteableDataSchema,
-        SpecializableDataSchema,
-    },
-    Thing,
+    response::StatusCode,
+    routing::{get, PathRouter},
+    AppWithStateBuilder, Router,
 };
+use wot_esp_demo_core::things::button::{toggle, EdgeEvent, MAX_BUTTONS};
+use wot_td::{builder::Error, Thing};
 
 use wot_esp_thing::{
-    mk_static, td_routes, to_json_response, EspThing as _, SseEvents, TdCell, TdState,
+    info, latency::LatencyLayer, mk_static, respond_negotiated, td_routes, Accept, EventLog,
+    EventStream, LastEventId, PubSubEvents, SelfTest, Sequenced, ThingCore,
 };
+
+/// Pins of the board picked with `BOARD` at build time, see `boards/`.
+#[macro_use]
+#[allow(dead_code, unused_macros)]
+mod board {
+    include!(concat!(env!("OUT_DIR"), "/board.rs"));
+}
+
+/// Buttons of the board, one per pin of `button` in its pin map.
+const BUTTONS: usize = board::BUTTON_PINS.len();
+const _: () = assert!(BUTTONS <= MAX_BUTTONS, "more buttons than MAX_BUTTONS");
+
 #[derive(Clone, Copy)]
 struct AppState {
-    on: &'static AtomicBool,
-    td: &'static TdCell,
+    /// Toggle state of each button.
+    on: &'static [AtomicBool; BUTTONS],
+    core: &'static ThingCore,
 }
 
-impl TdState for AppState {
-    fn td(&self) -> &'static str {
-        self.td.get()
+impl AsRef<ThingCore> for AppState {
+    fn as_ref(&self) -> &ThingCore {
+        self.core
     }
 }
 
+// The buttons have no loopback to test them against.
+impl SelfTest for AppState {}
+
 impl wot_esp_thing::EspThingState for AppState {
     fn new(
         spawner: embassy_executor::Spawner,
         peripherals: esp_hal::peripherals::Peripherals,
+        core: &'static ThingCore,
     ) -> (&'static Self, wot_esp_thing::NetworkPeripherals<'static>) {
         let net = wot_esp_thing::NetworkPeripherals {
             timg0: peripherals.TIMG0,
@@ -58,23 +72,20 @@ impl wot_esp_thing::EspThingState for AppState {
         let app_state = mk_static!(
             AppState,
             AppState {
-                on: mk_static!(AtomicBool, AtomicBool::new(false)),
-                td: mk_static!(TdCell, TdCell::new()),
+                on: mk_static!(
+                    [AtomicBool; BUTTONS],
+                    [const { AtomicBool::new(false) }; BUTTONS]
+                ),
+                core,
             }
         );
 
-        let btn = Input::new(
-            peripherals.GPIO9,
-            InputConfig::default().with_pull(Pull::Up),
-        );
-        spawner.spawn(update_task(app_state, btn).expect("update_task"));
+        let buttons = button_pins!(peripherals)
+            .map(|pin| Input::new(pin, InputConfig::default().with_pull(Pull::Up)));
+        spawner.spawn(update_task(app_state, buttons).expect("update_task"));
 
         (app_state, net)
     }
-
-    fn set_td(&self, td: &'static str) {
-        self.td.set(td);
-    }
 }
 
 #[derive(Default)]
@@ -83,73 +94,122 @@ struct AppProps;
 impl wot_esp_thing::EspThing<AppProps> for AppProps {
     const NAME: &'static str = "button";
 
-    fn build_td(name: &str, base_uri: String, id: String) -> Thing {
-        Thing::builder(name)
-            .finish_extend()
-            .id(id)
-            .base(base_uri)
-            .description("Example Thing exposing a toggle button")
-            .security(|builder| builder.no_sec().required().with_key("nosec_sc"))
-            .property("on", |p| {
-                p.finish_extend_data_schema()
-                    .attype("OnOffProperty")
-                    .title("On/Off")
-                    .description("On if the property is true, off otherwise")
-                    .form(|f| {
-                        f.href("/properties/on")
-                            .op(wot_td::thing::FormOperation::ReadProperty)
-                    })
-                    .bool()
-                    .read_only()
-            })
-            .event("on", |b| {
-                b.data(|b| b.finish_extend().bool()).form(|form_builder| {
-                    form_builder
-                        .href("/events/on")
-                        .op(wot_td::thing::FormOperation::SubscribeEvent)
-                        .op(wot_td::thing::FormOperation::UnsubscribeEvent)
-                        .subprotocol("sse")
-                })
-            })
-            .build()
-            .unwrap()
+    const BOARD: Option<&'static str> = Some(board::BOARD);
+    const PINS: &'static [(&'static str, u8)] = board::PINS;
+
+    fn build_td(name: &str, base_uri: String, id: String) -> Result<Thing, Error> {
+        wot_esp_demo_core::things::button::td_with_buttons(name, base_uri, id, BUTTONS)
     }
 }
 
+/// Add the `on` property and event of `button` to `router`; 404 for a
+/// button past the ones of the board.
+fn toggle_routes(
+    router: Router<impl PathRouter<AppState>, AppState>,
+    button: usize,
+) -> Router<impl PathRouter<AppState>, AppState> {
+    let toggle = toggle(button, BUTTONS);
+    router
+        .route(
+            toggle.property,
+            get(
+                move |State(state): State<AppState>, accept: Accept| async move {
+                    let on = state.on.get(button).ok_or(StatusCode::NOT_FOUND)?;
+                    Ok::<_, StatusCode>(respond_negotiated(
+                        accept,
+                        &on.load(core::sync::atomic::Ordering::Relaxed),
+                    ))
+                },
+            ),
+        )
+        .route(
+            toggle.event,
+            get(move |LastEventId(last_event_id)| async move {
+                let events = EVENTS.get(button).ok_or(StatusCode::NOT_FOUND)?;
+                Ok::<_, StatusCode>(EventStream(PubSubEvents::new(
+                    events.subscriber().unwrap(),
+                    &EVENTS_LOG[button],
+                    last_event_id,
+                )))
+            }),
+        )
+}
+
 impl AppWithStateBuilder for AppProps {
     type State = AppState;
     type PathRouter = impl picoserve::routing::PathRouter<Self::State>;
 
     fn build_app(self) -> picoserve::Router<Self::PathRouter, Self::State> {
-        td_routes::<AppState>()
+        let router = td_routes::<AppState>();
+        let router = toggle_routes(router, 0);
+        let router = toggle_routes(router, 1);
+        let router = toggle_routes(router, 2);
+        let router = toggle_routes(router, 3);
+
+        router
             .route(
-                "/properties/on",
-                get(|State(state): State<AppState>| async move {
-                    let on = state.on.load(core::sync::atomic::Ordering::Relaxed);
-                    to_json_response(&on)
+                "/events/raw",
+                get(async move |LastEventId(last_event_id)| {
+                    EventStream(PubSubEvents::new(
+                        RAW_EVENTS.subscriber().unwrap(),
+                        &RAW_EVENTS_LOG,
+                        last_event_id,
+                    ))
                 }),
             )
-            .route(
-                "/events/on",
-                get(async move || response::EventStream(SseEvents(WATCH.receiver().unwrap()))),
-            )
+            .layer(LatencyLayer)
     }
 }
 
-static WATCH: Watch<CriticalSectionRawMutex, bool, 2> = Watch::new();
+/// Toggles of each button, queued per subscriber so quick presses are not
+/// collapsed.
+static EVENTS: [PubSubChannel<CriticalSectionRawMutex, Sequenced<bool>, 4, 2, 1>; BUTTONS] =
+    [const { PubSubChannel::new() }; BUTTONS];
+static EVENTS_LOG: [EventLog<bool>; BUTTONS] = [const { EventLog::new() }; BUTTONS];
 
-#[embassy_executor::task]
-async fn update_task(state: &'static AppState, mut btn: Input<'static>) -> ! {
-    let sender = WATCH.sender();
+/// Presses and releases, on a channel of their own so a slow `raw` client
+/// never costs an `on` client its toggles, or the other way round.
+static RAW_EVENTS: PubSubChannel<CriticalSectionRawMutex, Sequenced<EdgeEvent>, 8, 2, 1> =
+    PubSubChannel::new();
+static RAW_EVENTS_LOG: EventLog<EdgeEvent> = EventLog::new();
 
-    loop {
-        btn.wait_for_low().await;
+/// Time the contacts are left to settle after an edge.
+const DEBOUNCE: Duration = Duration::from_millis(20);
 
-        let on = !state.on.fetch_not(core::sync::atomic::Ordering::AcqRel);
-        println!("Pressed status {on}");
+/// Wait for an edge on any button, toggling its `on` at each press.
+#[embassy_executor::task]
+async fn update_task(state: &'static AppState, buttons: [Input<'static>; BUTTONS]) -> ! {
+    let raw = RAW_EVENTS.immediate_publisher();
+    // Each input with whether it is pressed.
+    let mut buttons = buttons.map(|input| (input, false));
 
-        sender.send(on);
-        btn.wait_for_high().await;
+    // Every press is paired with its release, even one shorter than the
+    // debounce time: the release is then seen once the contacts settled.
+    loop {
+        let waits = buttons.each_mut().map(|(input, pressed)| {
+            input.wait_for(if *pressed {
+                Event::HighLevel
+            } else {
+                Event::LowLevel
+            })
+        });
+        let ((), button) = select_array(waits).await;
+        let pressed = &mut buttons[button].1;
+        *pressed = !*pressed;
+
+        raw.publish_immediate(RAW_EVENTS_LOG.record(EdgeEvent {
+            button: (BUTTONS > 1).then_some(button as u8),
+            pressed: *pressed,
+            uptime_ms: Instant::now().as_millis(),
+        }));
+        if *pressed {
+            let on = !state.on[button].fetch_not(core::sync::atomic::Ordering::AcqRel);
+            info!("Button {} pressed, status {}", button, on);
+            EVENTS[button]
+                .immediate_publisher()
+                .publish_immediate(EVENTS_LOG[button].record(on));
+        }
+        Timer::after(DEBOUNCE).await;
     }
 }
 
@@ -157,5 +217,5 @@ esp_bootloader_esp_idf::esp_app_desc!();
 
 #[esp_rtos::main]
 async fn main(spawner: Spawner) {
-    AppProps::run(spawner).await;
+    wot_esp_thing::run!(AppProps, spawner);
 }
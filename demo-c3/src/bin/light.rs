@@ -7,76 +7,214 @@ extern crate alloc;
 
 use alloc::string::String;
 use embassy_executor::Spawner;
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
+#[cfg(feature = "accent")]
+use embassy_sync::signal::Signal;
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex, watch::Watch};
+use embassy_time::{with_deadline, with_timeout, Duration, Instant, Timer};
 use esp_alloc as _;
 use esp_backtrace as _;
 use esp_hal::rmt::Rmt;
 use picoserve::{
-    extract::State,
-    response::StatusCode,
-    routing::get,
+    response::{Response, StatusCode},
+    routing::{get, post},
     AppWithStateBuilder,
 };
 
-use smart_leds::{brightness, colors::WHITE, gamma, SmartLedsWrite, RGB8};
-use wot_esp_thing::{
-    mk_static, td_routes, to_json_response, EspThing as _, TdCell, TdState,
+use smart_leds::{
+    brightness,
+    colors::{BLUE, GREEN, RED, WHITE},
+    gamma, SmartLedsWrite, RGB8,
 };
-use wot_td::{
-    builder::{
-        BuildableHumanReadableInfo, BuildableInteractionAffordance, IntegerDataSchemaBuilderLike,
-        ObjectDataSchemaBuilderLike, SpecializableDataSchema,
+use wot_esp_demo_core::{
+    smartled::{self, limit_current, CurrentModel, Rgb, PROGRESS_END},
+    things::light::{
+        luma, off_timer_remaining, vacation_plan, LastActive, LightState, OffTimerInput, SunriseInput,
+        VacationSchedule, ACCENT_STRIP, OFF_TIMER_MAX_BODY, SUNRISE_MAX_BODY,
     },
-    Thing,
 };
+#[cfg(feature = "flash-store")]
+use wot_esp_thing::flash;
+use wot_esp_thing::{
+    audit,
+    boot_status::StatusLed,
+    capabilities::Hardware,
+    clock, info,
+    latency::LatencyLayer,
+    mk_static, properties, respond_negotiated,
+    smartled::{ColorOrder, LedAdapterError, LedTiming, SmartLedsAdapter, TimingProfile},
+    td_routes, warn, Accept, ActionBody, JsonBody, Outcome, SelfTest, ThingCore,
+};
+#[cfg(feature = "group")]
+use wot_esp_thing::{group, td};
+use wot_td::{builder::Error, Thing};
+
+/// Pins of the board picked with `BOARD` at build time, see `boards/`.
+#[macro_use]
+#[allow(dead_code, unused_macros)]
+mod board {
+    include!(concat!(env!("OUT_DIR"), "/board.rs"));
+}
+
+/// Pixels of the light driven through the RMT.
+const LED_COUNT: usize = 1;
+
+/// Pulse timings and color order of the light's LED, the on-board WS2812B.
+const LED_CHIP: (LedTiming, ColorOrder) = (LedTiming::WS2812B, ColorOrder::Grb);
 
-struct Light<'a> {
-    on: bool,
-    color: RGB8,
-    brightness: u8,
-    led: esp_hal_smartled::SmartLedsAdapter<'a, 25>,
+/// Pixels of the accent strip.
+#[cfg(feature = "accent")]
+const ACCENT_LED_COUNT: usize = 60;
+
+/// Pulse timings and color order of the accent strip: a WS2812B strip, or
+/// e.g. `(LedTiming::WS2811, ColorOrder::Rgb)` for a 400 kHz WS2811 one.
+#[cfg(feature = "accent")]
+const ACCENT_LED_CHIP: (LedTiming, ColorOrder) = (LedTiming::WS2812B, ColorOrder::Grb);
+
+/// `LEDS` pixels on an RMT channel.
+struct Light<'a, const LEDS: usize> {
+    state: LightState<RGB8>,
+    /// Most the LEDs may draw, 0 for no limit.
+    power_budget_ma: u32,
+    /// Estimated draw of the frame last written.
+    estimated_ma: u32,
+    /// Profile last set through `ledTimingProfile`.
+    timing: TimingProfile,
+    led: SmartLedsAdapter<'a, LEDS>,
 }
 
-impl Light<'_> {
+/// The light, on RMT channel 0.
+type MainLight = Light<'static, LED_COUNT>;
+
+/// The accent strip, on RMT channel 1.
+#[cfg(feature = "accent")]
+type AccentLight = Light<'static, ACCENT_LED_COUNT>;
+
+impl<const LEDS: usize> Light<'_, LEDS> {
     fn update(&mut self) {
-        let b = if self.on { self.brightness } else { 0 };
-        let c = gamma([self.color].into_iter());
+        self.write(self.state.color, self.state.shown_brightness())
+            .unwrap();
+    }
+
+    /// Send `rgb` at brightness `b` to the LEDs, dimmed to the power budget,
+    /// leaving the state as is.
+    fn write(&mut self, rgb: RGB8, b: u8) -> Result<(), LedAdapterError> {
+        let c = brightness(gamma(core::iter::repeat_n(rgb, LEDS)), b);
+
+        let mut frame: [Rgb; LEDS] = [(0, 0, 0); LEDS];
+        for (pixel, c) in frame.iter_mut().zip(c) {
+            *pixel = (c.r, c.g, c.b);
+        }
+        limit_current(&mut frame, self.power_budget_ma, &CurrentModel::WS2812B);
+        self.estimated_ma = CurrentModel::WS2812B.estimate_ma(&frame);
 
-        self.led.write(brightness(c, b)).unwrap();
+        self.led
+            .write(frame.into_iter().map(|(r, g, b)| RGB8 { r, g, b }))
     }
 
+    /// Turn on at the last active state, see [`LightState::power`], or off.
     pub fn power(&mut self, on: bool) {
-        self.on = on;
+        self.state.power(on);
         self.update();
     }
 
     pub fn brightness(&mut self, b: u8) {
-        self.brightness = b;
+        self.state.set_brightness(b);
         self.update();
     }
 
     pub fn rgb(&mut self, rgb: RGB8) {
-        self.color = rgb;
+        self.state.set_color(rgb);
+        self.update();
+    }
+
+    pub fn power_budget(&mut self, ma: u32) {
+        self.power_budget_ma = ma;
+        self.update();
+    }
+
+    /// Send the pulse timings of `profile` from now on, and the current
+    /// frame with them.
+    pub fn timing_profile(&mut self, profile: TimingProfile) {
+        match self.led.set_timing(profile.timing(), profile.clock_hz()) {
+            Ok(()) => {
+                let symbols = profile.symbols();
+                info!(
+                    "LED timing {}: 0 = {}/{} ticks, 1 = {}/{} ticks",
+                    profile.as_str(),
+                    symbols.zero.0,
+                    symbols.zero.1,
+                    symbols.one.0,
+                    symbols.one.1
+                );
+                self.timing = profile;
+                self.update();
+            }
+            Err(e) => warn!("Cannot set LED timing {}: {:?}", profile.as_str(), e),
+        }
+    }
+
+    /// Show the animation frame `rgb` at brightness `b`, as one update.
+    pub fn show(&mut self, rgb: RGB8, b: u8) {
+        self.state.animate(rgb, b);
         self.update();
     }
 }
 
 #[derive(Clone, Copy)]
 struct AppState {
-    light: &'static Mutex<CriticalSectionRawMutex, &'static mut Light<'static>>,
-    td: &'static TdCell,
+    light: &'static Mutex<CriticalSectionRawMutex, &'static mut MainLight>,
+    #[cfg(feature = "accent")]
+    accent: &'static Mutex<CriticalSectionRawMutex, &'static mut AccentLight>,
+    core: &'static ThingCore,
+}
+
+impl AsRef<ThingCore> for AppState {
+    fn as_ref(&self) -> &ThingCore {
+        self.core
+    }
+}
+
+/// How long the self-test shows its pattern.
+const SELF_TEST_FLASH: Duration = Duration::from_millis(200);
+
+impl SelfTest for AppState {
+    const CHECKS: &'static [&'static str] = &["led"];
+
+    async fn check(&self, name: &str) -> Outcome {
+        match name {
+            // Flash red, green and blue over RMT, then show the light as it was.
+            "led" => {
+                let mut light = self.light.lock().await;
+                let mut flashed = Ok(());
+                for rgb in [RED, GREEN, BLUE] {
+                    flashed = flashed.and(light.write(rgb, 64));
+                    Timer::after(SELF_TEST_FLASH).await;
+                }
+                let (color, b) = (light.state.color, light.state.shown_brightness());
+                flashed.and(light.write(color, b)).into()
+            }
+            _ => Outcome::Skipped,
+        }
+    }
 }
 
-impl TdState for AppState {
-    fn td(&self) -> &'static str {
-        self.td.get()
+/// Brightness of the boot status blinks.
+const STATUS_BRIGHTNESS: u8 = 32;
+
+impl StatusLed for AppState {
+    fn set(&self, on: bool) {
+        // Blue, so that a boot blink is not mistaken for the light turned on.
+        if let Ok(mut light) = self.light.try_lock() {
+            let _ = light.write(BLUE, if on { STATUS_BRIGHTNESS } else { 0 });
+        }
     }
 }
 
 impl wot_esp_thing::EspThingState for AppState {
     fn new(
-        _spawner: embassy_executor::Spawner,
+        spawner: embassy_executor::Spawner,
         peripherals: esp_hal::peripherals::Peripherals,
+        core: &'static ThingCore,
     ) -> (&'static Self, wot_esp_thing::NetworkPeripherals<'static>) {
         let net = wot_esp_thing::NetworkPeripherals {
             timg0: peripherals.TIMG0,
@@ -86,42 +224,110 @@ impl wot_esp_thing::EspThingState for AppState {
 
         let rmt = Rmt::new(peripherals.RMT, esp_hal::time::Rate::from_mhz(80)).unwrap();
 
-        let rmt_buffer = alloc::boxed::Box::leak(alloc::boxed::Box::new(
-            esp_hal_smartled::smart_led_buffer!(1),
-        ));
+        // Off, to come back on as it was last before the reboot.
+        #[cfg(feature = "flash-store")]
+        let last = {
+            flash::open(peripherals.FLASH);
+            flash::read::<LastActive<RGB8>>(LIGHT_KEY)
+        };
+        #[cfg(not(feature = "flash-store"))]
+        let last = None::<LastActive<RGB8>>;
+        let last = last.unwrap_or(LastActive {
+            brightness: 100,
+            color: WHITE,
+        });
 
         let light = mk_static!(
-            Light,
+            MainLight,
             Light {
-                on: false,
-                brightness: 100,
-                color: WHITE,
-                led: esp_hal_smartled::SmartLedsAdapter::new(
+                state: LightState::new(last.brightness, last.color),
+                power_budget_ma: 0,
+                estimated_ma: 0,
+                timing: TimingProfile::Standard,
+                led: SmartLedsAdapter::with_chip(
                     rmt.channel0,
-                    peripherals.GPIO2,
-                    rmt_buffer
-                )
+                    led_pin!(peripherals),
+                    LED_CHIP.0,
+                    LED_CHIP.1
+                ),
             }
         );
 
         let light = mk_static!(
-            Mutex<CriticalSectionRawMutex, &'static mut Light>,
+            Mutex<CriticalSectionRawMutex, &'static mut MainLight>,
             Mutex::new(light)
         );
 
+        #[cfg(feature = "accent")]
+        let accent = {
+            let accent = mk_static!(
+                AccentLight,
+                Light {
+                    state: LightState::new(100, WHITE),
+                    power_budget_ma: 0,
+                    estimated_ma: 0,
+                    timing: TimingProfile::Standard,
+                    led: SmartLedsAdapter::with_chip(
+                        rmt.channel1,
+                        accent_led_pin!(peripherals),
+                        ACCENT_LED_CHIP.0,
+                        ACCENT_LED_CHIP.1
+                    ),
+                }
+            );
+
+            mk_static!(
+                Mutex<CriticalSectionRawMutex, &'static mut AccentLight>,
+                Mutex::new(accent)
+            )
+        };
+
         let app_state = mk_static!(
             AppState,
             AppState {
                 light,
-                td: mk_static!(TdCell, TdCell::new()),
+                #[cfg(feature = "accent")]
+                accent,
+                core,
             }
         );
 
+        // Drawn before the radio is up, good enough to vary the vacation times.
+        VACATION.sender().send(Vacation {
+            seed: wot_esp_thing::entropy::CountingRng::new().random(),
+            ..Vacation::default()
+        });
+
+        spawner.spawn(off_timer_task(light).expect("off_timer_task"));
+        spawner.spawn(effect_task(light).expect("effect_task"));
+        spawner.spawn(vacation_task(light).expect("vacation_task"));
+        #[cfg(feature = "flash-store")]
+        spawner.spawn(persist_task(light).expect("persist_task"));
+        #[cfg(feature = "accent")]
+        spawner.spawn(accent_task(accent).expect("accent_task"));
+
         (app_state, net)
     }
 
-    fn set_td(&self, td: &'static str) {
-        self.td.set(td);
+    fn status_led(&'static self) -> Option<&'static dyn StatusLed> {
+        Some(self)
+    }
+
+    fn safe_state(&self) {
+        // The LEDs keep the last frame through a reset of the chip.
+        if let Ok(mut light) = self.light.try_lock() {
+            light.state.power(false);
+            let _ = light.write(WHITE, 0);
+        }
+        #[cfg(feature = "accent")]
+        if let Ok(mut accent) = self.accent.try_lock() {
+            accent.state.power(false);
+            let _ = accent.write(WHITE, 0);
+        }
+    }
+
+    fn detected(&self, hardware: &str) -> bool {
+        hardware != ACCENT_STRIP.name || cfg!(feature = "accent")
     }
 }
 
@@ -131,74 +337,29 @@ struct AppProps;
 impl wot_esp_thing::EspThing<AppProps> for AppProps {
     const NAME: &'static str = "light";
 
-    fn build_td(name: &str, base_uri: String, id: String) -> Thing {
-        Thing::builder(name)
-            .finish_extend()
-            .id(id)
-            .base(base_uri)
-            .description("Example Thing controlling a light source")
-            .security(|builder| builder.no_sec().required().with_key("nosec_sc"))
-            .property("on", |p| {
-                p.finish_extend_data_schema()
-                    .attype("OnOffProperty")
-                    .title("On/Off")
-                    .description("The light source is on if the property is true, off otherwise")
-                    .form(|f| {
-                        f.href("/properties/on")
-                            .op(wot_td::thing::FormOperation::ReadProperty)
-                            .op(wot_td::thing::FormOperation::WriteProperty)
-                    })
-                    .bool()
-            })
-            .property("brightness", |p| {
-                p.finish_extend_data_schema()
-                    .attype("BrightnessProperty")
-                    .title("Light source brightness")
-                    .description("Light source color expressed as 8bit rgb")
-                    .form(|f| {
-                        f.href("/properties/brightness")
-                            .op(wot_td::thing::FormOperation::ReadProperty)
-                            .op(wot_td::thing::FormOperation::WriteProperty)
-                    })
-                    .integer()
-                    .minimum(0)
-                    .maximum(255)
-            })
-            .property("color", |p| {
-                p.finish_extend_data_schema()
-                    .attype("ColorProperty")
-                    .title("Light source color")
-                    .description("Light source color expressed as 8bit rgb")
-                    .form(|f| {
-                        f.href("/properties/color")
-                            .op(wot_td::thing::FormOperation::ReadProperty)
-                            .op(wot_td::thing::FormOperation::WriteProperty)
-                    })
-                    .object()
-                    .property("r", true, |b| {
-                        b.finish_extend()
-                            .integer()
-                            .title("Red")
-                            .minimum(0)
-                            .maximum(255)
-                    })
-                    .property("g", true, |b| {
-                        b.finish_extend()
-                            .integer()
-                            .title("Green")
-                            .minimum(0)
-                            .maximum(255)
-                    })
-                    .property("b", true, |b| {
-                        b.finish_extend()
-                            .integer()
-                            .title("Blue")
-                            .minimum(0)
-                            .maximum(255)
-                    })
-            })
-            .build()
-            .unwrap()
+    const BOARD: Option<&'static str> = Some(board::BOARD);
+    const PINS: &'static [(&'static str, u8)] = board::PINS;
+    const FIELD_PROPERTIES: &'static [&'static str] = &["color", "vacationSchedule"];
+    const OPTIONAL_HARDWARE: &'static [Hardware] = &[ACCENT_STRIP];
+
+    #[cfg(feature = "group")]
+    const GROUP: &'static [td::GroupProperty] = &[
+        td::GroupProperty {
+            name: "groupOn",
+            property: "on",
+        },
+        td::GroupProperty {
+            name: "groupBrightness",
+            property: "brightness",
+        },
+        td::GroupProperty {
+            name: "groupColor",
+            property: "color",
+        },
+    ];
+
+    fn build_td(name: &str, base_uri: String, id: String) -> Result<Thing, Error> {
+        wot_esp_demo_core::things::light::td(name, base_uri, id)
     }
 }
 
@@ -207,43 +368,342 @@ impl AppWithStateBuilder for AppProps {
     type PathRouter = impl picoserve::routing::PathRouter<Self::State>;
 
     fn build_app(self) -> picoserve::Router<Self::PathRouter, Self::State> {
-        td_routes::<AppState>()
+        let router = properties!(td_routes::<AppState>(), AppState, {
+            "on" => get: |s| s.light.lock().await.state.on,
+                put: |s, on: bool| {
+                    cancel_effect();
+                    suspend_vacation();
+                    let mut light = s.light.lock().await;
+                    light.power(on);
+                    // A running timer is left alone when the light is turned on.
+                    if !on {
+                        OFF_AT.sender().send(None);
+                    }
+                };
+            "brightness" => get: |s| s.light.lock().await.state.brightness,
+                put: |s, b: u8| {
+                    cancel_effect();
+                    s.light.lock().await.brightness(b);
+                };
+            "color" => get: |s| s.light.lock().await.state.color,
+                put: |s, rgb: RGB8| {
+                    cancel_effect();
+                    s.light.lock().await.rgb(rgb);
+                };
+            "powerBudget_mA" => get: |s| s.light.lock().await.power_budget_ma,
+                put: |s, ma: u32| s.light.lock().await.power_budget(ma);
+            "estimatedCurrent_mA" => get: |s| s.light.lock().await.estimated_ma;
+            "luma" => get: |s| {
+                let state = s.light.lock().await.state;
+                let RGB8 { r, g, b } = state.color;
+                luma((r, g, b), state.shown_brightness())
+            };
+            "ledTimingProfile" => get: |s| s.light.lock().await.timing.as_str(),
+                put: |s, profile: String| {
+                    if let Some(profile) = TimingProfile::parse(&profile) {
+                        s.light.lock().await.timing_profile(profile);
+                    }
+                },
+                validate: |profile| TimingProfile::parse(profile)
+                    .map(|_| ())
+                    .ok_or("Unknown LED timing profile, expected standard or conservative");
+            "lastActiveState" => get: |s| s.light.lock().await.state.last_active();
+            "offTimerRemaining" => get: |_s| OFF_AT.try_get().flatten().map(|at| {
+                off_timer_remaining(at.as_millis(), Instant::now().as_millis())
+            });
+            "vacationSchedule" => get: |_s| vacation().schedule,
+                put: |_s, schedule: VacationSchedule| {
+                    VACATION.sender().send(Vacation { schedule, ..vacation() });
+                },
+                validate: |schedule| schedule.validate();
+        });
+
+        // Written by accent_task, so that the strip never holds up the light.
+        #[cfg(feature = "accent")]
+        let router = properties!(router, AppState, {
+            "accent/on" => get: |s| s.accent.lock().await.state.on,
+                put: |s, on: bool| {
+                    s.accent.lock().await.state.power(on);
+                    ACCENT_FRAME.signal(());
+                };
+            "accent/brightness" => get: |s| s.accent.lock().await.state.brightness,
+                put: |s, b: u8| {
+                    s.accent.lock().await.state.set_brightness(b);
+                    ACCENT_FRAME.signal(());
+                };
+            "accent/color" => get: |s| s.accent.lock().await.state.color,
+                put: |s, rgb: RGB8| {
+                    s.accent.lock().await.state.set_color(rgb);
+                    ACCENT_FRAME.signal(());
+                };
+        });
+
+        #[cfg(feature = "group")]
+        let router = {
+            let router = group::proxy(router, "/properties/groupOn", "/properties/on");
+            let router = group::proxy(
+                router,
+                "/properties/groupBrightness",
+                "/properties/brightness",
+            );
+            group::proxy(router, "/properties/groupColor", "/properties/color")
+        };
+
+        router
             .route(
-                "/properties/on",
-                get(|State(state): State<AppState>| async move {
-                    to_json_response(&state.light.lock().await.on)
-                })
-                .put(
-                    |State(AppState { light, .. }), picoserve::extract::Json::<_>(on)| async move {
-                        light.lock().await.power(on);
-                        StatusCode::NO_CONTENT
-                    },
-                ),
+                "/properties/vacationMode",
+                get(async move |accept: Accept| respond_negotiated(accept, &vacation().enabled))
+                    .put(async move |JsonBody::<bool>(enabled)| {
+                        // The schedule is in local time, so it cannot run without the time.
+                        if enabled && clock::unix_ms().is_none() {
+                            return Err(Response::new(
+                                StatusCode::CONFLICT,
+                                "The time is not known yet: vacationMode needs it from SNTP",
+                            )
+                            .with_header("Content-Type", "text/plain"));
+                        }
+                        audit::record("vacationMode", &enabled);
+                        VACATION.sender().send(Vacation {
+                            enabled,
+                            suspended_until: None,
+                            ..vacation()
+                        });
+                        Ok(StatusCode::NO_CONTENT)
+                    }),
             )
             .route(
-                "/properties/brightness",
-                get(|State(state): State<AppState>| async move {
-                    to_json_response(&state.light.lock().await.brightness)
-                })
-                .put(
-                    |State(AppState { light, .. }), picoserve::extract::Json::<_>(b)| async move {
-                        light.lock().await.brightness(b);
+                "/actions/offTimer",
+                post(
+                    |ActionBody::<OffTimerInput, OFF_TIMER_MAX_BODY>(input)| async move {
+                        // A new timer replaces the running one, 0 cancels it.
+                        let delay = Duration::from_secs(input.delay_s.into());
+                        let at = (input.delay_s > 0).then(|| Instant::now() + delay);
+                        OFF_AT.sender().send(at);
+
                         StatusCode::NO_CONTENT
                     },
                 ),
             )
             .route(
-                "/properties/color",
-                get(|State(state): State<AppState>| async move {
-                    to_json_response(&state.light.lock().await.color)
-                })
-                .put(
-                    |State(AppState { light, .. }), picoserve::extract::Json::<_>(rgb)| async move {
-                        light.lock().await.rgb(rgb);
+                "/actions/sunrise",
+                post(
+                    |ActionBody::<SunriseInput, SUNRISE_MAX_BODY>(input)| async move {
+                        EFFECT.sender().send(Some(Effect::Sunrise {
+                            start: Instant::now(),
+                            duration: Duration::from_secs(input.duration_s.into()),
+                            target: input.target_brightness,
+                        }));
+
                         StatusCode::NO_CONTENT
                     },
                 ),
             )
+            .layer(LatencyLayer)
+    }
+}
+
+/// A light animation, run by [`effect_task`].
+#[derive(Clone, Copy, PartialEq)]
+enum Effect {
+    /// See [`smartled::sunrise`].
+    Sunrise {
+        start: Instant,
+        duration: Duration,
+        target: u8,
+    },
+}
+
+/// Interval between two frames of an effect.
+const EFFECT_STEP: Duration = Duration::from_millis(100);
+
+/// The running effect, `None` if none. A new effect replaces the running one.
+static EFFECT: Watch<CriticalSectionRawMutex, Option<Effect>, 1> = Watch::new();
+
+fn cancel_effect() {
+    EFFECT.sender().send(None);
+}
+
+/// Play the effect in [`EFFECT`] frame by frame until it ends or changes.
+#[embassy_executor::task]
+async fn effect_task(light: &'static Mutex<CriticalSectionRawMutex, &'static mut MainLight>) -> ! {
+    let mut receiver = EFFECT.receiver().unwrap();
+
+    loop {
+        let Some(effect) = receiver.try_get().flatten() else {
+            receiver.changed().await;
+            continue;
+        };
+
+        let Effect::Sunrise {
+            start,
+            duration,
+            target,
+        } = effect;
+        #[allow(clippy::cast_possible_truncation)]
+        let progress = (start.elapsed().as_millis() * u64::from(PROGRESS_END)
+            / duration.as_millis())
+        .min(u64::from(PROGRESS_END)) as u16;
+        let ((r, g, b), brightness) = smartled::sunrise(progress, target);
+
+        {
+            let mut light = light.lock().await;
+            // A property write may have cancelled the effect meanwhile.
+            if EFFECT.try_get().flatten() != Some(effect) {
+                continue;
+            }
+            light.show(RGB8::new(r, g, b), brightness);
+            // The light stays at the last frame, which it comes back at too.
+            if progress == PROGRESS_END {
+                light.state.settle();
+            }
+        }
+
+        if progress == PROGRESS_END {
+            EFFECT.sender().send(None);
+        } else {
+            let _ = with_timeout(EFFECT_STEP, receiver.changed()).await;
+        }
+    }
+}
+
+/// Set when the accent strip changes, for [`accent_task`] to send its frame.
+#[cfg(feature = "accent")]
+static ACCENT_FRAME: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Send the frame of the accent strip on each change, in a task of its own:
+/// the strip has its own lock and RMT channel, so a write to it never waits
+/// on the light, nor the light on it.
+#[cfg(feature = "accent")]
+#[embassy_executor::task]
+async fn accent_task(
+    accent: &'static Mutex<CriticalSectionRawMutex, &'static mut AccentLight>,
+) -> ! {
+    loop {
+        ACCENT_FRAME.wait().await;
+        accent.lock().await.update();
+    }
+}
+
+/// When the off timer turns the light off, `None` if no timer runs.
+static OFF_AT: Watch<CriticalSectionRawMutex, Option<Instant>, 1> = Watch::new();
+
+/// Turn the light off at the deadline in [`OFF_AT`], restarting whenever it
+/// changes.
+#[embassy_executor::task]
+async fn off_timer_task(
+    light: &'static Mutex<CriticalSectionRawMutex, &'static mut MainLight>,
+) -> ! {
+    let mut receiver = OFF_AT.receiver().unwrap();
+
+    loop {
+        match receiver.try_get().flatten() {
+            Some(at) => {
+                if with_deadline(at, receiver.changed()).await.is_err() {
+                    let mut light = light.lock().await;
+                    // A timer set while waiting for the lock replaces this one.
+                    if OFF_AT.try_get().flatten() == Some(at) {
+                        cancel_effect();
+                        light.power(false);
+                        OFF_AT.sender().send(None);
+                    }
+                }
+            }
+            None => {
+                receiver.changed().await;
+            }
+        }
+    }
+}
+
+/// The `vacationMode`, run by [`vacation_task`].
+#[derive(Clone, Copy, Default)]
+struct Vacation {
+    enabled: bool,
+    schedule: VacationSchedule,
+    /// Until when a change by hand holds, in milliseconds since the Unix
+    /// epoch: the next scheduled change when it was made.
+    suspended_until: Option<u64>,
+    /// Picks the times of the changes, see [`vacation_plan`].
+    seed: u32,
+}
+
+static VACATION: Watch<CriticalSectionRawMutex, Vacation, 1> = Watch::new();
+
+fn vacation() -> Vacation {
+    VACATION.try_get().unwrap_or_default()
+}
+
+/// Hold a change by hand until the next scheduled one.
+fn suspend_vacation() {
+    let vacation = vacation();
+    if let (true, Some(now)) = (vacation.enabled, clock::unix_ms()) {
+        let plan = vacation_plan(now, &vacation.schedule, vacation.seed);
+        VACATION.sender().send(Vacation {
+            suspended_until: Some(plan.next_change_ms),
+            ..vacation
+        });
+    }
+}
+
+/// Turn the light on and off as the [`VACATION`] schedule says, at each
+/// change and whenever the mode or the schedule is written.
+#[embassy_executor::task]
+async fn vacation_task(
+    light: &'static Mutex<CriticalSectionRawMutex, &'static mut MainLight>,
+) -> ! {
+    let mut receiver = VACATION.receiver().unwrap();
+
+    loop {
+        let vacation = receiver.try_get().unwrap_or_default();
+        let (true, Some(now)) = (vacation.enabled, clock::unix_ms()) else {
+            receiver.changed().await;
+            continue;
+        };
+
+        let plan = vacation_plan(now, &vacation.schedule, vacation.seed);
+        if vacation.suspended_until.is_none_or(|until| now >= until) {
+            let mut light = light.lock().await;
+            if light.state.on != plan.on {
+                info!(
+                    "Vacation mode: turning the light {}",
+                    if plan.on { "on" } else { "off" }
+                );
+                cancel_effect();
+                light.power(plan.on);
+            }
+        }
+
+        let wait = Duration::from_millis(plan.next_change_ms.saturating_sub(now));
+        let _ = with_timeout(wait, receiver.changed()).await;
+    }
+}
+
+/// Key of the last active state in the flash store.
+#[cfg(feature = "flash-store")]
+const LIGHT_KEY: &str = "light";
+
+/// How long the last active state must stay the same before it is
+/// persisted, so that a color dragged across a picker costs one flash write
+/// rather than one per step; `/debug/flash` shows the writes it saves.
+#[cfg(feature = "flash-store")]
+const PERSIST_AFTER: Duration = Duration::from_secs(30);
+
+/// Persist the last active state of the light once it settles.
+#[cfg(feature = "flash-store")]
+#[embassy_executor::task]
+async fn persist_task(light: &'static Mutex<CriticalSectionRawMutex, &'static mut MainLight>) -> ! {
+    let mut persisted = light.lock().await.state.last_active();
+    let mut seen = persisted;
+
+    loop {
+        Timer::after(PERSIST_AFTER).await;
+        let last = light.lock().await.state.last_active();
+        if last != seen {
+            // Still changing, wait for it to settle.
+            seen = last;
+        } else if last != persisted && flash::write(LIGHT_KEY, &last) {
+            persisted = last;
+        }
     }
 }
 
@@ -251,5 +711,5 @@ esp_bootloader_esp_idf::esp_app_desc!();
 
 #[esp_rtos::main]
 async fn main(spawner: Spawner) {
-    AppProps::run(spawner).await;
+    wot_esp_thing::run!(AppProps, spawner);
 }
@@ -0,0 +1,360 @@
+//! Up to eight SHTC3 probes in one enclosure, each on a channel of a
+//! TCA9548A I2C multiplexer strapped to [`MUX_ADDRESS`].
+//!
+//! The channels are scanned at start-up; the TD and the routes keep the
+//! probes found, and the measurement task measures them in turn.
+
+#![no_std]
+#![no_main]
+#![recursion_limit = "1024"]
+#![feature(impl_trait_in_assoc_type)]
+
+extern crate alloc;
+
+use core::cell::Cell;
+
+use alloc::string::String;
+
+use embassy_executor::Spawner;
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, CriticalSectionMutex},
+    mutex::Mutex,
+};
+use embassy_time::{Duration, Timer};
+use esp_alloc as _;
+use esp_backtrace as _;
+use esp_hal::{
+    delay::Delay,
+    i2c::master::{Config, I2c},
+    Blocking,
+};
+use picoserve::{
+    extract::State,
+    routing::{get, PathRouter},
+    AppWithStateBuilder, Router,
+};
+use wot_esp_demo_core::things::multitherm::{
+    self, parse_id, parse_measurement, temperatures, Probe, CHANNELS, MUX_ADDRESS, PROBES,
+    SHTC3_ADDRESS, SHTC3_MEASURE, SHTC3_READ_ID, SHTC3_SLEEP, SHTC3_WAKEUP,
+};
+use wot_td::{builder::Error, Thing};
+
+#[cfg(feature = "i2c-scan")]
+use picoserve::routing::post;
+#[cfg(feature = "i2c-scan")]
+use wot_esp_thing::i2c_scan;
+use wot_esp_thing::{
+    capabilities::{CapabilitiesLayer, Hardware},
+    error, heartbeat, info,
+    latency::LatencyLayer,
+    mk_static, respond_negotiated, respond_negotiated_result, warn, Accept, Outcome, SelfTest,
+    ThingCore, WriteGuard,
+};
+
+/// Pins of the board picked with `BOARD` at build time, see `boards/`.
+#[macro_use]
+#[allow(dead_code, unused_macros)]
+mod board {
+    include!(concat!(env!("OUT_DIR"), "/board.rs"));
+}
+
+type Bus = I2c<'static, Blocking>;
+
+#[derive(Clone, Copy)]
+struct AppState {
+    /// The mux and, through it, the probe of the channel selected.
+    i2c: &'static Mutex<CriticalSectionRawMutex, Bus>,
+    /// Every probe as last measured, [`Probe::Absent`] for those not found
+    /// at start-up.
+    probes: &'static CriticalSectionMutex<Cell<[Probe; CHANNELS]>>,
+    core: &'static ThingCore,
+}
+
+impl AppState {
+    fn probe(&self, channel: usize) -> Probe {
+        self.probes.lock(Cell::get)[channel]
+    }
+
+    fn set_probe(&self, channel: usize, probe: Probe) {
+        self.probes.lock(|probes| {
+            let mut all = probes.get();
+            all[channel] = probe;
+            probes.set(all);
+        });
+    }
+}
+
+impl AsRef<ThingCore> for AppState {
+    fn as_ref(&self) -> &ThingCore {
+        self.core
+    }
+}
+
+impl SelfTest for AppState {
+    const CHECKS: &'static [&'static str] = &["mux"];
+
+    async fn check(&self, name: &str) -> Outcome {
+        match name {
+            // Deselects every channel; the next measurement selects its own.
+            "mux" => self.i2c.lock().await.write(MUX_ADDRESS, &[0]).into(),
+            _ => Outcome::Skipped,
+        }
+    }
+}
+
+/// Time the SHTC3 takes to wake up, in microseconds.
+const WAKEUP_US: u32 = 240;
+
+/// Longest SHTC3 measurement in normal mode (12.1 ms).
+const MEASUREMENT_TIME: Duration = Duration::from_millis(13);
+
+/// The ID register of the SHTC3 on the channel selected, if one answers.
+fn read_id(i2c: &mut Bus) -> Option<u16> {
+    i2c.write(SHTC3_ADDRESS, &SHTC3_WAKEUP).ok()?;
+    Delay::new().delay_micros(WAKEUP_US);
+    let mut answer = [0; 3];
+    let read = i2c.write_read(SHTC3_ADDRESS, &SHTC3_READ_ID, &mut answer);
+    let _ = i2c.write(SHTC3_ADDRESS, &SHTC3_SLEEP);
+    read.ok()?;
+    parse_id(&answer)
+}
+
+/// Find the probes: an SHTC3 answering on a channel of the mux.
+fn scan(i2c: &mut Bus) -> [Probe; CHANNELS] {
+    let mut probes = [Probe::Absent; CHANNELS];
+
+    // With every channel off, only a sensor upstream of the mux answers.
+    if i2c.write(MUX_ADDRESS, &[0]).is_err() {
+        warn!("No TCA9548A answering at 0x{:02x}", MUX_ADDRESS);
+        return probes;
+    }
+    if read_id(i2c).is_some() {
+        error!("An SHTC3 answers upstream of the mux, the probes cannot be told apart");
+        return probes;
+    }
+
+    for (channel, probe) in probes.iter_mut().enumerate() {
+        let Some(select) = multitherm::select(channel) else {
+            continue;
+        };
+        if i2c.write(MUX_ADDRESS, &[select]).is_err() {
+            warn!("Cannot select channel {}", channel);
+            continue;
+        }
+        if let Some(id) = read_id(i2c) {
+            info!("SHTC3 {:04x} on channel {}", id, channel);
+            *probe = Probe::Pending;
+        }
+    }
+    let _ = i2c.write(MUX_ADDRESS, &[0]);
+
+    probes
+}
+
+impl wot_esp_thing::EspThingState for AppState {
+    fn new(
+        spawner: embassy_executor::Spawner,
+        peripherals: esp_hal::peripherals::Peripherals,
+        core: &'static ThingCore,
+    ) -> (&'static Self, wot_esp_thing::NetworkPeripherals<'static>) {
+        let net = wot_esp_thing::NetworkPeripherals {
+            timg0: peripherals.TIMG0,
+            sw_interrupt: peripherals.SW_INTERRUPT,
+            wifi: peripherals.WIFI,
+        };
+
+        let sda = i2c_sda!(peripherals);
+        let scl = i2c_scl!(peripherals);
+
+        let mut i2c = I2c::new(
+            peripherals.I2C0,
+            Config::default().with_frequency(esp_hal::time::Rate::from_khz(100)),
+        )
+        .expect("Cannot access the I2C bus")
+        .with_sda(sda)
+        .with_scl(scl);
+
+        let probes = scan(&mut i2c);
+
+        let app_state = mk_static!(
+            AppState,
+            AppState {
+                i2c: mk_static!(
+                    Mutex<CriticalSectionRawMutex, Bus>,
+                    Mutex::new(i2c)
+                ),
+                probes: mk_static!(
+                    CriticalSectionMutex<Cell<[Probe; CHANNELS]>>,
+                    CriticalSectionMutex::new(Cell::new(probes))
+                ),
+                core,
+            }
+        );
+
+        spawner.spawn(measurement_task(app_state).expect("measurement_task"));
+
+        (app_state, net)
+    }
+
+    /// A probe is found when an SHTC3 answered on its channel.
+    fn detected(&self, hardware: &str) -> bool {
+        match PROBES.iter().position(|probe| probe.name == hardware) {
+            Some(channel) => self.probe(channel) != Probe::Absent,
+            None => true,
+        }
+    }
+}
+
+#[derive(Default)]
+struct AppProps;
+
+impl wot_esp_thing::EspThing<AppProps> for AppProps {
+    const NAME: &'static str = "multitherm";
+
+    const BOARD: Option<&'static str> = Some(board::BOARD);
+    const PINS: &'static [(&'static str, u8)] = board::PINS;
+    const SAMPLE_INTERVAL: Option<Duration> = Some(MEASUREMENT_INTERVAL);
+    const OPTIONAL_HARDWARE: &'static [Hardware] = &PROBES;
+
+    fn build_td(name: &str, base_uri: String, id: String) -> Result<Thing, Error> {
+        multitherm::td(name, base_uri, id)
+    }
+}
+
+/// Routes of the probe properties, `temperatureN` for channel N.
+const PROBE_PATHS: [&str; CHANNELS] = [
+    "/properties/temperature0",
+    "/properties/temperature1",
+    "/properties/temperature2",
+    "/properties/temperature3",
+    "/properties/temperature4",
+    "/properties/temperature5",
+    "/properties/temperature6",
+    "/properties/temperature7",
+];
+
+/// Add the property of the probe on `channel` to `router`.
+fn probe_route(
+    router: Router<impl PathRouter<AppState>, AppState>,
+    channel: usize,
+) -> Router<impl PathRouter<AppState>, AppState> {
+    router.route(
+        PROBE_PATHS[channel],
+        get(
+            move |State(state): State<AppState>, accept: Accept| async move {
+                respond_negotiated_result(
+                    accept,
+                    state.probe(channel).temperature().ok_or(()),
+                    "No measurement from this probe.",
+                )
+            },
+        ),
+    )
+}
+
+impl AppWithStateBuilder for AppProps {
+    type State = AppState;
+    type PathRouter = impl PathRouter<Self::State>;
+
+    fn build_app(self) -> Router<Self::PathRouter, Self::State> {
+        let router = wot_esp_thing::td_routes::<AppState>();
+        let router = probe_route(router, 0);
+        let router = probe_route(router, 1);
+        let router = probe_route(router, 2);
+        let router = probe_route(router, 3);
+        let router = probe_route(router, 4);
+        let router = probe_route(router, 5);
+        let router = probe_route(router, 6);
+        let router = probe_route(router, 7);
+
+        // The bus lock pauses the measurements for the time of the scan.
+        #[cfg(feature = "i2c-scan")]
+        let router = router.route(
+            "/debug/i2c-scan",
+            post(
+                async move |_guard: WriteGuard,
+                            State(state): State<AppState>,
+                            authorized: i2c_scan::Authorized| {
+                    i2c_scan::serve(authorized, async {
+                        let mut i2c = state.i2c.lock().await;
+                        // Deselect the channels, so only the main bus answers.
+                        let _ = i2c.write(MUX_ADDRESS, &[0]);
+                        Some(wot_esp_demo_core::i2c_scan::scan(|address| {
+                            i2c.write(address, &[]).is_ok()
+                        }))
+                    })
+                    .await
+                },
+            ),
+        );
+
+        router
+            .route(
+                "/properties/temperatures",
+                get(async move |State(state): State<AppState>, accept: Accept| {
+                    let temperatures = temperatures(&state.probes.lock(Cell::get));
+                    respond_negotiated(accept, &temperatures)
+                }),
+            )
+            .layer(CapabilitiesLayer)
+            .layer(LatencyLayer)
+    }
+}
+
+/// Time between two rounds of measurements.
+const MEASUREMENT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Measure the probe on `channel`, `None` when the channel switch or the
+/// measurement fails.
+async fn measure(state: &AppState, channel: usize) -> Option<(f32, f32)> {
+    let mut i2c = state.i2c.lock().await;
+    i2c.write(MUX_ADDRESS, &[multitherm::select(channel)?])
+        .ok()?;
+
+    i2c.write(SHTC3_ADDRESS, &SHTC3_WAKEUP).ok()?;
+    Timer::after_micros(WAKEUP_US.into()).await;
+    i2c.write(SHTC3_ADDRESS, &SHTC3_MEASURE).ok()?;
+    Timer::after(MEASUREMENT_TIME).await;
+
+    let mut answer = [0; 6];
+    let read = i2c.read(SHTC3_ADDRESS, &mut answer);
+    let _ = i2c.write(SHTC3_ADDRESS, &SHTC3_SLEEP);
+    read.ok()?;
+    parse_measurement(&answer)
+}
+
+/// Measure every probe found in turn, once per [`MEASUREMENT_INTERVAL`].
+///
+/// A failure marks only the probe of its channel unhealthy, until its next
+/// measurement succeeds.
+#[embassy_executor::task]
+async fn measurement_task(state: &'static AppState) -> ! {
+    loop {
+        heartbeat!("measurement", MEASUREMENT_INTERVAL);
+
+        for channel in 0..CHANNELS {
+            let previous = state.probe(channel);
+            if previous == Probe::Absent {
+                continue;
+            }
+
+            let probe = match measure(state, channel).await {
+                Some((temperature, _)) => Probe::Healthy(temperature),
+                None => Probe::Unhealthy,
+            };
+            if probe == Probe::Unhealthy && previous != Probe::Unhealthy {
+                warn!("Probe {} unhealthy", channel);
+            }
+            state.set_probe(channel, probe);
+        }
+
+        Timer::after(MEASUREMENT_INTERVAL).await;
+    }
+}
+
+esp_bootloader_esp_idf::esp_app_desc!();
+
+#[esp_rtos::main]
+async fn main(spawner: Spawner) {
+    wot_esp_thing::run!(AppProps, spawner);
+}
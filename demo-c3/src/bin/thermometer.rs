@@ -5,82 +5,203 @@
 
 extern crate alloc;
 
+use core::cell::Cell;
+
+#[cfg(feature = "low-power")]
+use alloc::boxed::Box;
 use alloc::string::String;
 
 use embassy_executor::Spawner;
 use embassy_sync::{
-    blocking_mutex::raw::CriticalSectionRawMutex,
-    mutex::Mutex,
+    blocking_mutex::{raw::CriticalSectionRawMutex, CriticalSectionMutex},
+    signal::Signal,
     watch::Watch,
 };
-use embassy_time::{Duration, Timer};
+#[cfg(feature = "i2c-scan")]
+use embassy_time::with_timeout;
+use embassy_time::{Duration, Instant, Timer};
 use esp_alloc as _;
 use esp_backtrace as _;
+#[cfg(feature = "low-power")]
+use esp_hal::{
+    analog::adc::{Adc, AdcCalCurve, AdcConfig, Attenuation},
+    gpio::{Input, InputConfig, Pull},
+    peripherals::ADC1,
+};
 use esp_hal::{
-    i2c::master::{Config, I2c},
+    gpio::{AnyPin, Pin},
+    i2c::master::{Config, ConfigError, I2c},
+    peripherals::I2C0,
     tsens::{Config as TsensConfig, TemperatureSensor},
     Blocking,
 };
 use picoserve::{
     extract::State,
-    response::{self},
-    routing::get,
+    response::{self, Response, StatusCode},
+    routing::{get, post},
     AppWithStateBuilder,
 };
-use shtcx::{self, sensor_class::Sht2Gen, shtc3, PowerMode, ShtCx};
-use wot_td::{
-    builder::{
-        BuildableDataSchema, BuildableHumanReadableInfo, BuildableInteractionAffordance,
-        ReadableWriteableDataSchema, SpecializableDataSchema,
+use serde_json::json;
+use shtcx::{sensor_class::Sht2Gen, shtc3, PowerMode, ShtCx};
+#[cfg(feature = "low-power")]
+use wot_esp_demo_core::low_power;
+use wot_esp_demo_core::things::{
+    temperature_changed,
+    thermometer::{
+        frost_risk, sensor_info, with_units, FrostWarning, MeasurementMode, Offsets, Reading,
+        UnitsSystem, MAX_HUMIDITY_OFFSET, MAX_TEMPERATURE_OFFSET, SHTC3,
     },
-    Thing,
 };
+use wot_td::{builder::Error, Thing};
 
+#[cfg(feature = "i2c-scan")]
+use wot_esp_thing::i2c_scan;
+#[cfg(feature = "thing-model")]
+use wot_esp_thing::tm::ThingModel;
 use wot_esp_thing::{
-    mk_static, to_json_response, to_json_result, EspThing as _, SseEvents, TdCell, TdState,
+    alarm::Direction,
+    capabilities::{CapabilitiesLayer, Hardware},
+    computed, heartbeat, info,
+    latency::LatencyLayer,
+    mk_static, properties, respond_cached_result, respond_json_negotiated, respond_negotiated,
+    respond_negotiated_field, td, to_json_response, warn, Accept, Alarm, Cached, EventLog,
+    EventStream, Field, LastEventId, Outcome, SelfTest, Sequenced, Slot, SseEvents, ThingCore,
+    WriteGuard,
 };
 
+/// Pins of the board picked with `BOARD` at build time, see `boards/`.
+#[macro_use]
+#[allow(dead_code, unused_macros)]
+mod board {
+    include!(concat!(env!("OUT_DIR"), "/board.rs"));
+}
+
+type Sensor = ShtCx<Sht2Gen, I2c<'static, Blocking>>;
+
+/// The bus of the SHTC3, kept to build its driver again, see
+/// [`reinit_sensor`].
+struct SensorBus {
+    i2c: I2C0<'static>,
+    sda: AnyPin<'static>,
+    scl: AnyPin<'static>,
+}
+
+impl SensorBus {
+    /// A new I2C driver on the bus.
+    fn i2c(&self) -> Result<I2c<'static, Blocking>, ConfigError> {
+        // SAFETY: the slot of the sensor dropped the driver built before, so
+        // the bus is used by one driver at a time.
+        let (i2c, sda, scl) = unsafe {
+            (
+                self.i2c.clone_unchecked(),
+                self.sda.clone_unchecked(),
+                self.scl.clone_unchecked(),
+            )
+        };
+        Ok(I2c::new(
+            i2c,
+            Config::default().with_frequency(esp_hal::time::Rate::from_khz(100)),
+        )?
+        .with_sda(sda)
+        .with_scl(scl))
+    }
+
+    /// A new I2C driver with the SHTC3 on it, storing its ID register in
+    /// `sensor_id`, `None` if it does not answer.
+    fn build(
+        &self,
+        sensor_id: &CriticalSectionMutex<Cell<Option<u16>>>,
+    ) -> Result<Sensor, ConfigError> {
+        let mut sht = shtc3(self.i2c()?);
+        let id = sht.raw_id_register().ok();
+        match id {
+            Some(id) => info!("SHTC3 ID register: {:04x}", id),
+            None => warn!("No SHTC3 answering on the I2C bus"),
+        }
+        sensor_id.lock(|i| i.set(id));
+        Ok(sht)
+    }
+}
+
 #[derive(Clone, Copy)]
 struct AppState {
-    sensor: &'static Mutex<
-        CriticalSectionRawMutex,
-        &'static mut ShtCx<Sht2Gen, &'static mut I2c<'static, Blocking>>,
-    >,
+    /// Empty if rebuilding it failed.
+    sensor: &'static Slot<Sensor>,
+    bus: &'static SensorBus,
     die_sensor: &'static TemperatureSensor<'static>,
-    td: &'static TdCell,
+    /// Read by the measurement loop before every measurement.
+    mode: &'static CriticalSectionMutex<Cell<MeasurementMode>>,
+    /// Applied by the measurement loop to every reading.
+    offsets: &'static CriticalSectionMutex<Cell<Offsets>>,
+    /// The units of every temperature served, see [`set_units`].
+    units: &'static CriticalSectionMutex<Cell<UnitsSystem>>,
+    /// The latest reading, offsets included and in `units`; `None` until
+    /// the first measurement or after a failed one.
+    reading: &'static CriticalSectionMutex<Cell<Option<Cached<Reading>>>>,
+    /// The ID register read when the sensor was last built, `None` if it
+    /// did not answer.
+    sensor_id: &'static CriticalSectionMutex<Cell<Option<u16>>>,
+    /// Whether the stay-awake pin was held at boot.
+    #[cfg(feature = "low-power")]
+    stay_awake: bool,
+    /// The latest battery voltage in millivolts, `None` until
+    /// [`battery_task`] read it.
+    #[cfg(feature = "low-power")]
+    battery_mv: &'static CriticalSectionMutex<Cell<Option<u32>>>,
+    core: &'static ThingCore,
 }
 
 impl AppState {
-    /// Returns the latest temperature measurement in degrees celsius.
-    async fn get_temperature(&self) -> Result<f32, shtcx::Error<esp_hal::i2c::master::Error>> {
-        let t = self
-            .sensor
-            .lock()
-            .await
-            .get_temperature_measurement_result()?
-            .as_degrees_celsius();
-        Ok(t)
+    /// Returns the latest temperature reading in the units system, offset
+    /// included.
+    fn get_temperature(&self) -> Result<Cached<f32>, ()> {
+        self.reading
+            .lock(Cell::get)
+            .map(|r| r.map(|r| r.temperature))
+            .ok_or(())
     }
 
-    /// Returns the latest humidity measurement in percent.
-    async fn get_humidity(&self) -> Result<f32, shtcx::Error<esp_hal::i2c::master::Error>> {
-        Ok(self
-            .sensor
-            .lock()
-            .await
-            .get_humidity_measurement_result()?
-            .as_percent())
+    /// Returns the latest humidity reading in percent, offset included.
+    fn get_humidity(&self) -> Result<Cached<f32>, ()> {
+        self.reading
+            .lock(Cell::get)
+            .map(|r| r.map(|r| r.humidity))
+            .ok_or(())
     }
 
-    /// Returns the ESP32-C3 internal die temperature in degrees celsius.
+    /// Returns the ESP32-C3 internal die temperature in the units system.
     fn get_die_temperature(&self) -> f32 {
-        self.die_sensor.get_temperature().to_celsius()
+        let celsius = self.die_sensor.get_temperature().to_celsius();
+        self.units.lock(Cell::get).temperature(celsius)
+    }
+
+    /// The power mode of the next measurement.
+    fn power_mode(&self) -> PowerMode {
+        match self.mode.lock(Cell::get) {
+            MeasurementMode::Normal => PowerMode::NormalMode,
+            MeasurementMode::LowPower => PowerMode::LowPower,
+        }
     }
 }
 
-impl TdState for AppState {
-    fn td(&self) -> &'static str {
-        self.td.get()
+impl AsRef<ThingCore> for AppState {
+    fn as_ref(&self) -> &ThingCore {
+        self.core
+    }
+}
+
+impl SelfTest for AppState {
+    const CHECKS: &'static [&'static str] = &["sensor"];
+
+    async fn check(&self, name: &str) -> Outcome {
+        match name {
+            // Only an SHTC3 answers the ID register read at its address 0x70.
+            "sensor" => match self.sensor.lock().await.as_mut() {
+                Some(sensor) => sensor.device_identifier().into(),
+                None => Outcome::Fail,
+            },
+            _ => Outcome::Skipped,
+        }
     }
 }
 
@@ -88,6 +209,7 @@ impl wot_esp_thing::EspThingState for AppState {
     fn new(
         spawner: embassy_executor::Spawner,
         peripherals: esp_hal::peripherals::Peripherals,
+        core: &'static ThingCore,
     ) -> (&'static Self, wot_esp_thing::NetworkPeripherals<'static>) {
         let net = wot_esp_thing::NetworkPeripherals {
             timg0: peripherals.TIMG0,
@@ -96,41 +218,44 @@ impl wot_esp_thing::EspThingState for AppState {
         };
 
         // Initialize temperature sensor
-        let sda = peripherals.GPIO10;
-        let scl = peripherals.GPIO8;
-
-        let i2c = mk_static!(
-            I2c<'static, Blocking>,
-            I2c::new(
-                peripherals.I2C0,
-                Config::default().with_frequency(esp_hal::time::Rate::from_khz(100))
-            )
-            .expect("Cannot access the thermometer")
-            .with_sda(sda)
-            .with_scl(scl)
+        let bus = mk_static!(
+            SensorBus,
+            SensorBus {
+                i2c: peripherals.I2C0,
+                sda: i2c_sda!(peripherals).degrade(),
+                scl: i2c_scl!(peripherals).degrade(),
+            }
         );
-
-        let sht = mk_static!(
-            ShtCx < Sht2Gen,
-            &'static mut I2c<'static, Blocking>>,
-            shtc3(i2c)
+        let sensor_id = mk_static!(
+            CriticalSectionMutex<Cell<Option<u16>>>,
+            CriticalSectionMutex::new(Cell::new(None))
         );
-
         let sensor = mk_static!(
-            Mutex<
-                CriticalSectionRawMutex,
-            &'static mut
-                ShtCx<
-                Sht2Gen,&'static mut
-                I2c<
-                'static,
-            Blocking,
-            >
-                >
-                >,
-            Mutex::<CriticalSectionRawMutex, _>::new(sht)
+            Slot<Sensor>,
+            Slot::new(Some(
+                bus.build(sensor_id).expect("Cannot access the thermometer")
+            ))
         );
 
+        // Held to GND at boot: served even in the batched energy mode.
+        #[cfg(feature = "low-power")]
+        let stay_awake = Input::new(
+            stay_awake_pin!(peripherals),
+            InputConfig::default().with_pull(Pull::Up),
+        )
+        .is_low();
+
+        #[cfg(feature = "low-power")]
+        let battery: BatteryReader = {
+            let mut adc_config = AdcConfig::new();
+            let mut pin = adc_config.enable_pin_with_cal::<_, AdcCalCurve<ADC1<'static>>>(
+                battery_pin!(peripherals),
+                Attenuation::_11dB,
+            );
+            let mut adc = Adc::new(peripherals.ADC1, adc_config);
+            Box::new(move || adc.read_oneshot(&mut pin).ok())
+        };
+
         let die_sensor = mk_static!(
             TemperatureSensor<'static>,
             TemperatureSensor::new(peripherals.TSENS, TsensConfig::default())
@@ -141,18 +266,75 @@ impl wot_esp_thing::EspThingState for AppState {
             AppState,
             AppState {
                 sensor,
+                bus,
                 die_sensor,
-                td: mk_static!(TdCell, TdCell::new()),
+                mode: mk_static!(
+                    CriticalSectionMutex<Cell<MeasurementMode>>,
+                    CriticalSectionMutex::new(Cell::new(MeasurementMode::Normal))
+                ),
+                offsets: mk_static!(
+                    CriticalSectionMutex<Cell<Offsets>>,
+                    CriticalSectionMutex::new(Cell::new(Offsets::default()))
+                ),
+                units: mk_static!(
+                    CriticalSectionMutex<Cell<UnitsSystem>>,
+                    CriticalSectionMutex::new(Cell::new(UnitsSystem::Si))
+                ),
+                reading: mk_static!(
+                    CriticalSectionMutex<Cell<Option<Cached<Reading>>>>,
+                    CriticalSectionMutex::new(Cell::new(None))
+                ),
+                sensor_id,
+                #[cfg(feature = "low-power")]
+                stay_awake,
+                #[cfg(feature = "low-power")]
+                battery_mv: mk_static!(
+                    CriticalSectionMutex<Cell<Option<u32>>>,
+                    CriticalSectionMutex::new(Cell::new(None))
+                ),
+                core,
             }
         );
 
         spawner.spawn(temperature_write_task(app_state).expect("temperature_write_task"));
+        spawner.spawn(over_temperature_task().expect("over_temperature_task"));
+        spawner.spawn(frost_warning_task().expect("frost_warning_task"));
+        #[cfg(feature = "rules")]
+        spawner.spawn(temperature_rules_task().expect("temperature_rules_task"));
+        #[cfg(feature = "low-power")]
+        spawner.spawn(battery_task(app_state, battery).expect("battery_task"));
 
         (app_state, net)
     }
 
-    fn set_td(&self, td: &'static str) {
-        self.td.set(td);
+    /// Only an SHTC3 answers the ID register read at start-up.
+    fn detected(&self, hardware: &str) -> bool {
+        hardware != SHTC3.name || self.sensor_id.lock(Cell::get).is_some()
+    }
+
+    #[cfg(feature = "low-power")]
+    fn stay_awake(&self) -> bool {
+        self.stay_awake
+    }
+
+    /// The latest reading, once the measurement loop has taken one.
+    async fn sample(&self) -> Option<serde_json::Value> {
+        for _ in 0..3 {
+            if let Some(Cached { value: reading, .. }) = self.reading.lock(Cell::get) {
+                return Some(json!({
+                    "temperature": reading.temperature,
+                    "humidity": reading.humidity,
+                    "dewPoint": reading.dew_point,
+                }));
+            }
+            Timer::after(MEASUREMENT_INTERVAL).await;
+        }
+        None
+    }
+
+    #[cfg(feature = "low-power")]
+    fn battery_mv(&self) -> Option<u32> {
+        self.battery_mv.lock(Cell::get)
     }
 }
 
@@ -162,64 +344,20 @@ struct AppProps;
 impl wot_esp_thing::EspThing<AppProps> for AppProps {
     const NAME: &'static str = "shtc3";
 
-    fn build_td(name: &str, base_uri: String, id: String) -> Thing {
-        Thing::builder(name)
-            .finish_extend()
-            .id(id)
-            .base(base_uri)
-            .description("Example Thing exposing a shtc3 sensor")
-            .security(|builder| builder.no_sec().required().with_key("nosec_sc"))
-            .property("temperature", |p| {
-                p.finish_extend_data_schema()
-                    .attype("TemperatureProperty")
-                    .title("Temperature")
-                    .description("Current temperature")
-                    .form(|f| {
-                        f.href("/properties/temperature")
-                            .op(wot_td::thing::FormOperation::ReadProperty)
-                    })
-                    .number()
-                    .read_only()
-                    .unit("Celsius")
-            })
-            .property("humidity", |p| {
-                p.finish_extend_data_schema()
-                    .attype("HumidityProperty")
-                    .title("Humidity")
-                    .description("Current humidity")
-                    .form(|f| {
-                        f.href("/properties/humidity")
-                            .op(wot_td::thing::FormOperation::ReadProperty)
-                    })
-                    .number()
-                    .read_only()
-                    .unit("%")
-            })
-            .property("die_temperature", |p| {
-                p.finish_extend_data_schema()
-                    .attype("TemperatureProperty")
-                    .title("Die temperature")
-                    .description("ESP32-C3 internal die temperature")
-                    .form(|f| {
-                        f.href("/properties/die_temperature")
-                            .op(wot_td::thing::FormOperation::ReadProperty)
-                    })
-                    .number()
-                    .read_only()
-                    .unit("Celsius")
-            })
-            .event("temperature", |b| {
-                b.data(|b| b.finish_extend().number().unit("Celsius"))
-                    .form(|form_builder| {
-                        form_builder
-                            .href("/events/temperature")
-                            .op(wot_td::thing::FormOperation::SubscribeEvent)
-                            .op(wot_td::thing::FormOperation::UnsubscribeEvent)
-                            .subprotocol("sse")
-                    })
-            })
-            .build()
-            .unwrap()
+    const BOARD: Option<&'static str> = Some(board::BOARD);
+    const PINS: &'static [(&'static str, u8)] = board::PINS;
+    const SAMPLE_INTERVAL: Option<Duration> = Some(MEASUREMENT_INTERVAL);
+    const OPTIONAL_HARDWARE: &'static [Hardware] = &[SHTC3];
+    const FIELD_PROPERTIES: &'static [&'static str] = &["sensorInfo"];
+
+    #[cfg(feature = "thing-model")]
+    const THING_MODEL: Option<ThingModel> = Some(ThingModel {
+        json: include_str!("../../tm/thermometer.tm.json"),
+        url: "https://raw.githubusercontent.com/wot-rust/wot-esp-hal-demo/main/demo-c3/tm/thermometer.tm.json",
+    });
+
+    fn build_td(name: &str, base_uri: String, id: String) -> Result<Thing, Error> {
+        wot_esp_demo_core::things::thermometer::td(name, base_uri, id)
     }
 }
 
@@ -228,69 +366,488 @@ impl AppWithStateBuilder for AppProps {
     type PathRouter = impl picoserve::routing::PathRouter<Self::State>;
 
     fn build_app(self) -> picoserve::Router<Self::PathRouter, Self::State> {
-        wot_esp_thing::td_routes::<AppState>()
+        let router = OVER_TEMPERATURE.routes(
+            wot_esp_thing::td_routes::<AppState>(),
+            "/properties/over_temperature_threshold",
+            "/events/over_temperature",
+        );
+
+        let router = properties!(router, AppState, {
+            "measurementMode" => get: |s| s.mode.lock(Cell::get).as_str(),
+                put: |s, mode: String| {
+                    if let Some(mode) = MeasurementMode::parse(&mode) {
+                        s.mode.lock(|m| m.set(mode));
+                    }
+                },
+                validate: |mode| MeasurementMode::parse(mode)
+                    .map(|_| ())
+                    .ok_or("Unknown measurement mode, expected normal or lowpower");
+            "temperatureOffset" => get: |s| s.offsets.lock(Cell::get).temperature,
+                put: |s, temperature: f32| {
+                    s.offsets.lock(|o| o.set(Offsets { temperature, ..o.get() }));
+                },
+                validate: |t| if t.abs() <= MAX_TEMPERATURE_OFFSET {
+                    Ok(())
+                } else {
+                    Err("Temperature offset out of range, expected -5 to 5")
+                };
+            "humidityOffset" => get: |s| s.offsets.lock(Cell::get).humidity,
+                put: |s, humidity: f32| {
+                    s.offsets.lock(|o| o.set(Offsets { humidity, ..o.get() }));
+                },
+                validate: |h| if h.abs() <= MAX_HUMIDITY_OFFSET {
+                    Ok(())
+                } else {
+                    Err("Humidity offset out of range, expected -10 to 10")
+                };
+            "unitsSystem" => get: |s| s.units.lock(Cell::get).as_str(),
+                put: |s, units: String| {
+                    if let Some(units) = UnitsSystem::parse(&units) {
+                        set_units(&s, units).await;
+                    }
+                },
+                validate: |units| UnitsSystem::parse(units)
+                    .map(|_| ())
+                    .ok_or("Unknown units system, expected si or imperial");
+        });
+
+        let router = computed(router, "/properties/dewPoint", |s: &AppState| {
+            s.reading.lock(Cell::get)?.value.dew_point
+        });
+        let router = computed(router, "/properties/heatIndex", |s: &AppState| {
+            s.reading.lock(Cell::get)?.value.heat_index
+        });
+
+        // Run by the measurement loop, between two measurements.
+        #[cfg(feature = "i2c-scan")]
+        let router = router.route(
+            "/debug/i2c-scan",
+            post(
+                async move |_guard: WriteGuard, authorized: i2c_scan::Authorized| {
+                    i2c_scan::serve(authorized, async {
+                        SCANNED.reset();
+                        COMMANDS.signal(Command::ScanBus);
+                        with_timeout(SCAN_TIMEOUT, SCANNED.wait())
+                            .await
+                            .ok()
+                            .flatten()
+                    })
+                    .await
+                },
+            ),
+        );
+
+        router
             .route(
                 "/properties/temperature",
-                get(async move |State(state): State<AppState>| {
-                    to_json_result(
-                        state.get_temperature().await,
+                get(async move |State(state): State<AppState>, accept: Accept| {
+                    respond_cached_result(
+                        accept,
+                        state.get_temperature(),
                         "Failed to read temperature value.",
                     )
                 }),
             )
             .route(
                 "/properties/humidity",
-                get(async move |State(state): State<AppState>| {
-                    to_json_result(
-                        state.get_humidity().await,
+                get(async move |State(state): State<AppState>, accept: Accept| {
+                    respond_cached_result(
+                        accept,
+                        state.get_humidity(),
                         "Failed to read humidity value.",
                     )
                 }),
             )
+            .route(
+                "/properties/sensorInfo",
+                get(
+                    async move |State(state): State<AppState>, accept: Accept, field: Field| {
+                        let id = state.sensor_id.lock(Cell::get);
+                        respond_negotiated_field(accept, &sensor_info(id), field)
+                    },
+                ),
+            )
             .route(
                 "/properties/die_temperature",
-                get(async move |State(state): State<AppState>| {
-                    to_json_response(&state.get_die_temperature())
+                get(async move |State(state): State<AppState>, accept: Accept| {
+                    respond_negotiated(accept, &state.get_die_temperature())
+                }),
+            )
+            .route(
+                "/actions/conditionSensor",
+                get(async move |accept: Accept| {
+                    respond_json_negotiated(
+                        accept,
+                        to_json_response(&CONDITIONING.lock(Cell::get).to_json()),
+                    )
+                })
+                .post(async move |_guard: WriteGuard| {
+                    if let Conditioning::Running { .. } = CONDITIONING.lock(Cell::get) {
+                        return Err(Response::new(
+                            StatusCode::CONFLICT,
+                            "Conditioning already running",
+                        )
+                        .with_header("Content-Type", "text/plain"));
+                    }
+                    let status = Conditioning::Running { progress: 0 };
+                    CONDITIONING.lock(|c| c.set(status));
+                    COMMANDS.signal(Command::ConditionSensor);
+
+                    Ok(
+                        Response::new(StatusCode::CREATED, status.to_json().to_string())
+                            .with_header("Content-Type", "application/json")
+                            .with_header("Location", "/actions/conditionSensor"),
+                    )
                 }),
             )
+            .route(
+                "/actions/reinitSensor",
+                post(
+                    async move |_guard: WriteGuard, State(state): State<AppState>| {
+                        reinit_sensor(&state).await
+                    },
+                ),
+            )
             .route(
                 "/events/temperature",
-                get(async move || response::EventStream(SseEvents(WATCH.receiver().unwrap()))),
+                get(async move |LastEventId(last_event_id)| {
+                    EventStream(SseEvents::new(
+                        WATCH.receiver().unwrap(),
+                        &WATCH_LOG,
+                        last_event_id,
+                    ))
+                }),
+            )
+            .route(
+                "/events/frostWarning",
+                get(async move |LastEventId(last_event_id)| {
+                    EventStream(SseEvents::new(
+                        FROST_WATCH.receiver().unwrap(),
+                        &FROST_LOG,
+                        last_event_id,
+                    ))
+                }),
+            )
+            .route(
+                "/events/unitsChanged",
+                get(async move |LastEventId(last_event_id)| {
+                    EventStream(SseEvents::new(
+                        UNITS_WATCH.receiver().unwrap(),
+                        &UNITS_LOG,
+                        last_event_id,
+                    ))
+                }),
             )
+            .layer(CapabilitiesLayer)
+            .layer(LatencyLayer)
     }
 }
 
+/// Switch every temperature served to `units`: the reading, the
+/// over-temperature threshold and the TD, then tell the `unitsChanged`
+/// subscribers to fetch the TD again.
+///
+/// The TD is rewritten only when the units change, and [`td::update`] frees
+/// the copy it replaces, so the writes can go on for as long as the Thing
+/// runs. The write may wait for a response still sending the TD before the
+/// last.
+async fn set_units(state: &AppState, units: UnitsSystem) {
+    let previous = state.units.lock(|u| u.replace(units));
+    if previous == units {
+        return;
+    }
+    info!("Reporting temperatures in {}", units.temperature_unit());
+
+    state
+        .reading
+        .lock(|r| r.set(r.get().map(|r| r.map(|r| r.convert(previous, units)))));
+    // The threshold keeps its temperature.
+    let threshold = previous.to_celsius(OVER_TEMPERATURE.threshold());
+    OVER_TEMPERATURE.set_threshold(units.temperature(threshold));
+
+    if let Err(e) = td::update(|td| with_units(td, units)).await {
+        warn!("Cannot rewrite the TD units: {}", e);
+    }
+    UNITS_WATCH.sender().send(UNITS_LOG.record(units.as_str()));
+}
+
+/// The `reinitSensor` debug action: drop the I2C driver and the SHTC3 on it
+/// and build them again, e.g. after replugging the sensor. The measurements
+/// wait meanwhile. Answers with the `sensorInfo` read by the new driver,
+/// with 503 if the sensor does not answer.
+async fn reinit_sensor(state: &AppState) -> impl response::IntoResponse {
+    info!("Rebuilding the sensor");
+    if let Err(e) = state
+        .sensor
+        .rebuild(|| state.bus.build(state.sensor_id))
+        .await
+    {
+        warn!("Cannot rebuild the I2C bus: {}", e);
+    }
+
+    let id = state.sensor_id.lock(Cell::get);
+    let status = if id.is_some() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Response::new(status, sensor_info(id).to_string())
+        .with_header("Content-Type", "application/json")
+}
+
+/// Commands for [`temperature_write_task`], which owns the measurement cycle.
+enum Command {
+    /// Run the condensation recovery sequence.
+    ConditionSensor,
+    /// Scan the I2C bus, see [`scan_bus`].
+    #[cfg(feature = "i2c-scan")]
+    ScanBus,
+}
+
+/// Longest wait for the measurement loop to scan the bus: past it, e.g.
+/// while the sensor is conditioned, the scan answers 503.
+#[cfg(feature = "i2c-scan")]
+const SCAN_TIMEOUT: Duration = Duration::from_ticks(3 * MEASUREMENT_INTERVAL.as_ticks());
+
+/// Drop the SHTC3 driver, probe the bus with a driver of its own, then
+/// build the SHTC3 again; `None` if the bus cannot be built.
+#[cfg(feature = "i2c-scan")]
+async fn scan_bus(state: &AppState) -> Option<alloc::vec::Vec<u8>> {
+    let mut sensor = state.sensor.lock().await;
+    // Release the bus before building on it again.
+    *sensor = None;
+    let found = match state.bus.i2c() {
+        Ok(mut i2c) => Some(wot_esp_demo_core::i2c_scan::scan(|address| {
+            i2c.write(address, &[]).is_ok()
+        })),
+        Err(e) => {
+            warn!("Cannot build the I2C bus: {}", e);
+            None
+        }
+    };
+    *sensor = state.bus.build(state.sensor_id).ok();
+    found
+}
+
+/// Progress of the `conditionSensor` action, as reported by a query.
+#[derive(Clone, Copy)]
+enum Conditioning {
+    Idle,
+    Running { progress: u8 },
+    Completed,
+}
+
+impl Conditioning {
+    fn to_json(self) -> serde_json::Value {
+        match self {
+            Self::Idle => json!({ "status": "idle" }),
+            Self::Running { progress } => json!({ "status": "running", "progress": progress }),
+            Self::Completed => json!({ "status": "completed" }),
+        }
+    }
+}
+
+/// Back-to-back high-power measurements warming the sensor.
+const HEATING_TIME: Duration = Duration::from_secs(5 * 60);
+
+/// Rest after the heating, so that the first event is not a hot reading.
+const COOLDOWN_TIME: Duration = Duration::from_secs(60);
+
+/// Longest SHTC3 measurement in normal mode (12.1 ms).
+const MEASUREMENT_TIME: Duration = Duration::from_millis(13);
+
+fn set_progress(elapsed: Duration) {
+    let total = (HEATING_TIME + COOLDOWN_TIME).as_millis();
+    #[allow(clippy::cast_possible_truncation)]
+    let progress = (elapsed.as_millis() * 100 / total).min(99) as u8;
+    CONDITIONING.lock(|c| c.set(Conditioning::Running { progress }));
+}
+
+/// The condensation recovery sequence of the `conditionSensor` action.
+///
+/// The SHTC3 has no heater: measuring continuously in normal mode warms it
+/// enough to evaporate the condensate.
+async fn condition_sensor(state: &AppState) {
+    info!("Conditioning the sensor");
+    let start = Instant::now();
+
+    while start.elapsed() < HEATING_TIME {
+        let mut guard = state.sensor.lock().await;
+        let Some(sensor) = guard.as_mut() else {
+            break;
+        };
+        if sensor.start_measurement(PowerMode::NormalMode).is_ok() {
+            Timer::after(MEASUREMENT_TIME).await;
+            let _ = sensor.get_measurement_result();
+        }
+        drop(guard);
+        set_progress(start.elapsed());
+    }
+
+    while start.elapsed() < HEATING_TIME + COOLDOWN_TIME {
+        Timer::after(Duration::from_secs(1)).await;
+        set_progress(start.elapsed());
+    }
+
+    CONDITIONING.lock(|c| c.set(Conditioning::Completed));
+    info!("Sensor conditioned");
+}
+
+/// Time between two measurements.
+const MEASUREMENT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Age past which a reading is answered with 503 rather than served: three
+/// measurement intervals, the measurement loop being wedged or the sensor
+/// being conditioned.
+const STALE_AFTER: Duration = Duration::from_ticks(3 * MEASUREMENT_INTERVAL.as_ticks());
+
 #[embassy_executor::task]
-#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
 async fn temperature_write_task(state: &'static AppState) -> ! {
     let sender = WATCH.sender();
-    let mut last_temp = state.get_temperature().await.unwrap_or(-500.0);
+    let mut last_temp = -500.0;
 
     loop {
-        state
-            .sensor
-            .lock()
-            .await
-            .start_measurement(PowerMode::NormalMode)
-            .unwrap();
+        heartbeat!("measurement", MEASUREMENT_INTERVAL);
+        match COMMANDS.try_take() {
+            Some(Command::ConditionSensor) => condition_sensor(state).await,
+            #[cfg(feature = "i2c-scan")]
+            Some(Command::ScanBus) => SCANNED.signal(scan_bus(state).await),
+            None => {}
+        }
 
-        Timer::after(Duration::from_secs(1)).await;
-        let temperature = state.get_temperature().await;
+        // The mode only changes between measurements.
+        let started = match state.sensor.lock().await.as_mut() {
+            Some(sensor) => sensor.start_measurement(state.power_mode()).is_ok(),
+            None => false,
+        };
 
-        if let Ok(temperature) = temperature {
-            if ((last_temp - temperature) * 100f32) as u32 / 10 != 0 {
-                sender.send(temperature);
+        Timer::after(MEASUREMENT_INTERVAL).await;
+        // A rebuild in between drops the measurement started.
+        let measurement = match state.sensor.lock().await.as_mut() {
+            Some(sensor) if started => sensor.get_measurement_result().ok(),
+            _ => None,
+        };
+
+        // The only place the offsets and the units system are applied: the
+        // properties, the events and the alarm all take the reading from here.
+        let units = state.units.lock(Cell::get);
+        let reading = measurement.map(|m| {
+            let (temperature, humidity) = state
+                .offsets
+                .lock(Cell::get)
+                .apply(m.temperature.as_degrees_celsius(), m.humidity.as_percent());
+            Reading::new(temperature, humidity, units)
+        });
+        state
+            .reading
+            .lock(|r| r.set(reading.map(|r| Cached::new(r, STALE_AFTER))));
+
+        if let Some(reading @ Reading { temperature, .. }) = reading {
+            READINGS.sender().send((reading, units));
+            TEMPERATURE.sender().send(temperature);
+            if temperature_changed(last_temp, temperature) {
+                sender.send(WATCH_LOG.record(temperature));
                 last_temp = temperature;
             }
         }
     }
 }
 
-static WATCH: Watch<CriticalSectionRawMutex, f32, 2> = Watch::new();
+/// A one-shot read of the battery pin by the ADC, in millivolts on the pin;
+/// `None` while the conversion is running. The pin is the board's.
+#[cfg(feature = "low-power")]
+type BatteryReader = Box<dyn FnMut() -> Option<u16>>;
+
+/// Read the battery voltage every [`MEASUREMENT_INTERVAL`], for
+/// [`AppState::battery_mv`].
+#[cfg(feature = "low-power")]
+#[embassy_executor::task]
+async fn battery_task(state: &'static AppState, mut read: BatteryReader) -> ! {
+    loop {
+        heartbeat!("battery", MEASUREMENT_INTERVAL);
+        // The first read starts a conversion, done within microseconds.
+        let mv = loop {
+            match read() {
+                Some(mv) => break low_power::battery_mv(mv),
+                None => Timer::after(Duration::from_millis(1)).await,
+            }
+        };
+        if state.battery_mv.lock(|b| b.replace(Some(mv))).is_none() {
+            info!("Battery: {} mV", mv);
+        }
+        Timer::after(MEASUREMENT_INTERVAL).await;
+    }
+}
+
+static WATCH: Watch<CriticalSectionRawMutex, Sequenced<f32>, 2> = Watch::new();
+static WATCH_LOG: EventLog<f32> = EventLog::new();
+
+/// Every temperature reading, for the over-temperature alarm and the rules.
+static TEMPERATURE: Watch<CriticalSectionRawMutex, f32, 2> = Watch::new();
+static OVER_TEMPERATURE: Alarm = Alarm::new(30.0, Direction::Above, 0.5);
+
+#[embassy_executor::task]
+async fn over_temperature_task() -> ! {
+    OVER_TEMPERATURE.run(&TEMPERATURE).await
+}
+
+/// Every reading with the units it is in, for the frost warning.
+static READINGS: Watch<CriticalSectionRawMutex, (Reading, UnitsSystem), 1> = Watch::new();
+static FROST_WATCH: Watch<CriticalSectionRawMutex, Sequenced<FrostWarning>, 2> = Watch::new();
+static FROST_LOG: EventLog<FrostWarning> = EventLog::new();
+
+/// Send a `frostWarning` event each time the risk of frost begins or ends.
+///
+/// The two conditions, on the temperature and on the dew point, do not fit
+/// an [`Alarm`] and its single writable threshold.
+#[embassy_executor::task]
+async fn frost_warning_task() -> ! {
+    let mut readings = READINGS.receiver().unwrap();
+    let mut warning = false;
+
+    loop {
+        let (reading, units) = readings.changed().await;
+        let Some(dew_point) = reading.dew_point else {
+            continue;
+        };
+        let risk = frost_risk(
+            units.to_celsius(reading.temperature),
+            units.to_celsius(dew_point),
+        );
+        if risk == warning {
+            continue;
+        }
+        warning = risk;
+        info!(
+            "Frost warning {}",
+            if warning { "raised" } else { "cleared" }
+        );
+        FROST_WATCH.sender().send(FROST_LOG.record(FrostWarning {
+            warning,
+            temperature: reading.temperature,
+            dew_point,
+        }));
+    }
+}
+
+#[cfg(feature = "rules")]
+#[embassy_executor::task]
+async fn temperature_rules_task() -> ! {
+    wot_esp_thing::rules::watch("temperature", &TEMPERATURE, core::convert::identity).await
+}
+static UNITS_WATCH: Watch<CriticalSectionRawMutex, Sequenced<&'static str>, 2> = Watch::new();
+static UNITS_LOG: EventLog<&'static str> = EventLog::new();
+
+static COMMANDS: Signal<CriticalSectionRawMutex, Command> = Signal::new();
+/// The addresses found by [`scan_bus`], for the `/debug/i2c-scan` waiting.
+#[cfg(feature = "i2c-scan")]
+static SCANNED: Signal<CriticalSectionRawMutex, Option<alloc::vec::Vec<u8>>> = Signal::new();
+static CONDITIONING: CriticalSectionMutex<Cell<Conditioning>> =
+    CriticalSectionMutex::new(Cell::new(Conditioning::Idle));
 
 esp_bootloader_esp_idf::esp_app_desc!();
 
 #[esp_rtos::main]
 async fn main(spawner: Spawner) {
-    AppProps::run(spawner).await;
+    wot_esp_thing::run!(AppProps, spawner);
 }
@@ -1,6 +1,18 @@
+// Pin map of the board picked with `BOARD`, see `boards/`.
+#[path = "../boards/pins.rs"]
+mod board;
+
 fn main() {
+    board::generate(
+        "esp32c6",
+        &["button", "i2c_sda", "i2c_scl", "fan_pwm", "fan_tach"],
+        "c6-devkit",
+    );
     linker_be_nice();
     println!("cargo:rustc-link-arg=-Tlinkall.x");
+    if std::env::var_os("CARGO_FEATURE_DEFMT").is_some() {
+        println!("cargo:rustc-link-arg=-Tdefmt.x");
+    }
 }
 
 fn linker_be_nice() {
@@ -29,23 +29,26 @@ use esp_hal::{
 };
 use picoserve::{
     extract::State,
-    response::{self, StatusCode},
+    response::StatusCode,
     routing::get,
     AppWithStateBuilder,
 };
 use portable_atomic::{AtomicBool, AtomicI16, Ordering};
 use sht4x_rjw::asynch::SHT4x;
+use wot_esp_demo_core::things::{fan, temperature_changed};
 use wot_esp_thing::{
-    mk_static, td_routes, to_json_response, to_json_result, EspThing as _, PowerSaveMode,
-    SseEvents, TdCell, TdState,
-};
-use wot_td::{
-    builder::{
-        BuildableDataSchema, BuildableHumanReadableInfo, BuildableInteractionAffordance,
-        IntegerDataSchemaBuilderLike, ReadableWriteableDataSchema, SpecializableDataSchema,
-    },
-    Thing,
+    audit, heartbeat, latency::LatencyLayer, mk_static, respond_negotiated,
+    respond_negotiated_result, td_routes, Accept, EventLog, EventStream, JsonBody, LastEventId,
+    Outcome, PowerSaveMode, SelfTest, Sequenced, SseEvents, ThingCore,
 };
+use wot_td::{builder::Error, Thing};
+
+/// Pins of the board picked with `BOARD` at build time, see `boards/`.
+#[macro_use]
+#[allow(dead_code, unused_macros)]
+mod board {
+    include!(concat!(env!("OUT_DIR"), "/board.rs"));
+}
 
 static FAN_RPM: AtomicI16 = AtomicI16::new(0);
 
@@ -60,7 +63,7 @@ struct AppState {
         &'static CriticalSectionMutex<esp_hal::ledc::channel::Channel<'static, LowSpeed>>,
     fan_on: &'static AtomicBool,
     fan_duty: &'static CriticalSectionMutex<core::cell::Cell<u8>>,
-    td: &'static TdCell,
+    core: &'static ThingCore,
 }
 
 impl AppState {
@@ -106,7 +109,7 @@ impl AppState {
             let _ = ch.set_duty(effective);
         });
         if was != on {
-            ON_WATCH.sender().send(on);
+            ON_WATCH.sender().send(ON_WATCH_LOG.record(on));
         }
     }
 
@@ -115,9 +118,21 @@ impl AppState {
     }
 }
 
-impl TdState for AppState {
-    fn td(&self) -> &'static str {
-        self.td.get()
+impl AsRef<ThingCore> for AppState {
+    fn as_ref(&self) -> &ThingCore {
+        self.core
+    }
+}
+
+impl SelfTest for AppState {
+    const CHECKS: &'static [&'static str] = &["sensor"];
+
+    async fn check(&self, name: &str) -> Outcome {
+        match name {
+            // A measurement addresses the SHT41 and checks its CRC.
+            "sensor" => self.get_temperature().await.into(),
+            _ => Outcome::Skipped,
+        }
     }
 }
 
@@ -125,6 +140,7 @@ impl wot_esp_thing::EspThingState for AppState {
     fn new(
         spawner: embassy_executor::Spawner,
         peripherals: esp_hal::peripherals::Peripherals,
+        core: &'static ThingCore,
     ) -> (&'static Self, wot_esp_thing::NetworkPeripherals<'static>) {
         let net = wot_esp_thing::NetworkPeripherals {
             timg0: peripherals.TIMG0,
@@ -132,14 +148,14 @@ impl wot_esp_thing::EspThingState for AppState {
             wifi: peripherals.WIFI,
         };
 
-        // --- SHT41 via Qwiic (LP_I2C: GPIO6/GPIO7) ---
+        // --- SHT41 via Qwiic ---
         let i2c = I2c::new(
                 peripherals.I2C0,
                 I2cConfig::default().with_frequency(esp_hal::time::Rate::from_khz(100))
             )
             .expect("Cannot access I2C")
-            .with_sda(peripherals.GPIO6)
-            .with_scl(peripherals.GPIO7)
+            .with_sda(i2c_sda!(peripherals))
+            .with_scl(i2c_scl!(peripherals))
             .into_async();
 
         let sht = mk_static!(
@@ -173,11 +189,12 @@ impl wot_esp_thing::EspThingState for AppState {
             })
             .unwrap();
 
-        let mut fan_channel = ledc.channel(channel::Number::Channel0, peripherals.GPIO2);
+        let mut fan_channel = ledc.channel(channel::Number::Channel0, fan_pwm_pin!(peripherals));
         fan_channel
             .configure(channel::config::Config {
                 timer: lstimer0,
-                duty_pct: 100,
+                // Stopped until `on` is written, see `safe_state`.
+                duty_pct: 0,
                 drive_mode: esp_hal::gpio::DriveMode::PushPull,
             })
             .unwrap();
@@ -187,8 +204,11 @@ impl wot_esp_thing::EspThingState for AppState {
             CriticalSectionMutex::new(fan_channel)
         );
 
-        // --- Fan tach via PCNT (GPIO3, internal pull-up) ---
-        let tach_pin = Input::new(peripherals.GPIO3, InputConfig::default().with_pull(Pull::Up));
+        // --- Fan tach via PCNT (internal pull-up) ---
+        let tach_pin = Input::new(
+            fan_tach_pin!(peripherals),
+            InputConfig::default().with_pull(Pull::Up),
+        );
         let tach_signal = tach_pin.peripheral_input();
 
         let pcnt = mk_static!(Pcnt<'static>, Pcnt::new(peripherals.PCNT));
@@ -201,7 +221,7 @@ impl wot_esp_thing::EspThingState for AppState {
         unit_ref.resume();
 
         // --- State ---
-        let fan_on = mk_static!(AtomicBool, AtomicBool::new(true));
+        let fan_on = mk_static!(AtomicBool, AtomicBool::new(false));
         let fan_duty = mk_static!(
             CriticalSectionMutex<core::cell::Cell<u8>>,
             CriticalSectionMutex::new(core::cell::Cell::new(100))
@@ -215,13 +235,13 @@ impl wot_esp_thing::EspThingState for AppState {
                 fan_channel,
                 fan_on,
                 fan_duty,
-                td: mk_static!(TdCell, TdCell::new()),
+                core,
             }
         );
 
-        // --- BOOT button (GPIO9) toggles property `on` ---
+        // --- BOOT button toggles property `on` ---
         let btn = Input::new(
-            peripherals.GPIO9,
+            button_pin!(peripherals),
             InputConfig::default().with_pull(Pull::Up),
         );
 
@@ -232,8 +252,31 @@ impl wot_esp_thing::EspThingState for AppState {
         (app_state, net)
     }
 
-    fn set_td(&self, td: &'static str) {
-        self.td.set(td);
+    /// The properties of [`AppProps::UDP_PROPERTIES`], as their routes
+    /// serve them.
+    async fn read_property(&self, name: &str) -> Option<Result<serde_json::Value, &'static str>> {
+        let value = match name {
+            "temperature" => self
+                .get_temperature()
+                .await
+                .map_err(|_| "Failed to read temperature"),
+            "humidity" => self
+                .get_humidity()
+                .await
+                .map_err(|_| "Failed to read humidity"),
+            "die_temperature" => Ok(self.get_die_temperature()),
+            "on" => return Some(Ok(self.get_fan_on().into())),
+            "speed" => return Some(Ok(self.get_fan_speed().into())),
+            "rpm" => return Some(Ok(self.get_fan_rpm().into())),
+            _ => return None,
+        };
+        Some(value.map(Into::into))
+    }
+
+    fn safe_state(&self) {
+        // A motor spinning up on every reset can catch a finger or a cable;
+        // the speed is kept for when `on` is written.
+        self.set_fan_on(false);
     }
 }
 
@@ -243,130 +286,23 @@ struct AppProps;
 impl wot_esp_thing::EspThing<AppProps> for AppProps {
     const NAME: &'static str = "fan";
 
+    const BOARD: Option<&'static str> = Some(board::BOARD);
+    const PINS: &'static [(&'static str, u8)] = board::PINS;
+    const SAMPLE_INTERVAL: Option<Duration> = Some(MEASUREMENT_INTERVAL);
+    const UDP_PROPERTIES: &'static [&'static str] = &[
+        "temperature",
+        "humidity",
+        "die_temperature",
+        "on",
+        "speed",
+        "rpm",
+    ];
+
     // Maximum power-save breaks WiFi on ESP32-C6 (esp-rs/esp-hal#3014, #3075, #3079).
     const WIFI_POWER_SAVE: PowerSaveMode = PowerSaveMode::None;
 
-    fn build_td(name: &str, base_uri: String, id: String) -> Thing {
-        Thing::builder(name)
-            .finish_extend()
-            .id(id)
-            .base(base_uri)
-            .description(
-                "Noctua 5V fan controller with SHT41 sensor; BOOT toggles fan on/off",
-            )
-            .security(|builder| builder.no_sec().required().with_key("nosec_sc"))
-            .property("temperature", |p| {
-                p.finish_extend_data_schema()
-                    .attype("TemperatureProperty")
-                    .title("Temperature")
-                    .description("Ambient temperature from SHT41")
-                    .form(|f| {
-                        f.href("/properties/temperature")
-                            .op(wot_td::thing::FormOperation::ReadProperty)
-                    })
-                    .number()
-                    .read_only()
-                    .unit("Celsius")
-            })
-            .property("humidity", |p| {
-                p.finish_extend_data_schema()
-                    .attype("HumidityProperty")
-                    .title("Humidity")
-                    .description("Relative humidity from SHT41")
-                    .form(|f| {
-                        f.href("/properties/humidity")
-                            .op(wot_td::thing::FormOperation::ReadProperty)
-                    })
-                    .number()
-                    .read_only()
-                    .unit("%")
-            })
-            .property("die_temperature", |p| {
-                p.finish_extend_data_schema()
-                    .attype("TemperatureProperty")
-                    .title("Die temperature")
-                    .description("ESP32-C6 internal die temperature")
-                    .form(|f| {
-                        f.href("/properties/die_temperature")
-                            .op(wot_td::thing::FormOperation::ReadProperty)
-                    })
-                    .number()
-                    .read_only()
-                    .unit("Celsius")
-            })
-            .property("on", |p| {
-                p.finish_extend_data_schema()
-                    .attype("OnOffProperty")
-                    .title("Fan on/off")
-                    .description(
-                        "Whether the fan is running. Writable over HTTP; toggled by the BOOT button",
-                    )
-                    .form(|f| {
-                        f.href("/properties/on")
-                            .op(wot_td::thing::FormOperation::ReadProperty)
-                            .op(wot_td::thing::FormOperation::WriteProperty)
-                    })
-                    .bool()
-            })
-            .property("speed", |p| {
-                p.finish_extend_data_schema()
-                    .attype("LevelProperty")
-                    .title("Fan speed")
-                    .description("Fan PWM duty cycle (0-100%)")
-                    .form(|f| {
-                        f.href("/properties/speed")
-                            .op(wot_td::thing::FormOperation::ReadProperty)
-                            .op(wot_td::thing::FormOperation::WriteProperty)
-                    })
-                    .integer()
-                    .minimum(0)
-                    .maximum(100)
-                    .unit("percent")
-            })
-            .property("rpm", |p| {
-                p.finish_extend_data_schema()
-                    .attype("SpeedProperty")
-                    .title("Fan RPM")
-                    .description("Measured fan speed in revolutions per minute")
-                    .form(|f| {
-                        f.href("/properties/rpm")
-                            .op(wot_td::thing::FormOperation::ReadProperty)
-                    })
-                    .integer()
-                    .read_only()
-                    .unit("rpm")
-            })
-            .event("on", |b| {
-                b.data(|b| b.finish_extend().bool()).form(|form_builder| {
-                    form_builder
-                        .href("/events/on")
-                        .op(wot_td::thing::FormOperation::SubscribeEvent)
-                        .op(wot_td::thing::FormOperation::UnsubscribeEvent)
-                        .subprotocol("sse")
-                })
-            })
-            .event("temperature", |b| {
-                b.data(|b| b.finish_extend().number().unit("Celsius"))
-                    .form(|form_builder| {
-                        form_builder
-                            .href("/events/temperature")
-                            .op(wot_td::thing::FormOperation::SubscribeEvent)
-                            .op(wot_td::thing::FormOperation::UnsubscribeEvent)
-                            .subprotocol("sse")
-                    })
-            })
-            .event("rpm", |b| {
-                b.data(|b| b.finish_extend().integer().unit("rpm"))
-                    .form(|form_builder| {
-                        form_builder
-                            .href("/events/rpm")
-                            .op(wot_td::thing::FormOperation::SubscribeEvent)
-                            .op(wot_td::thing::FormOperation::UnsubscribeEvent)
-                            .subprotocol("sse")
-                    })
-            })
-            .build()
-            .unwrap()
+    fn build_td(name: &str, base_uri: String, id: String) -> Result<Thing, Error> {
+        fan::td(name, base_uri, id)
     }
 }
 
@@ -378,8 +314,9 @@ impl AppWithStateBuilder for AppProps {
         td_routes::<AppState>()
             .route(
                 "/properties/temperature",
-                get(async move |State(state): State<AppState>| {
-                    to_json_result(
+                get(async move |State(state): State<AppState>, accept: Accept| {
+                    respond_negotiated_result(
+                        accept,
                         state.get_temperature().await,
                         "Failed to read temperature",
                     )
@@ -387,66 +324,92 @@ impl AppWithStateBuilder for AppProps {
             )
             .route(
                 "/properties/humidity",
-                get(async move |State(state): State<AppState>| {
-                    to_json_result(state.get_humidity().await, "Failed to read humidity")
+                get(async move |State(state): State<AppState>, accept: Accept| {
+                    respond_negotiated_result(
+                        accept,
+                        state.get_humidity().await,
+                        "Failed to read humidity",
+                    )
                 }),
             )
             .route(
                 "/properties/die_temperature",
-                get(async move |State(state): State<AppState>| {
-                    to_json_response(&state.get_die_temperature())
+                get(async move |State(state): State<AppState>, accept: Accept| {
+                    respond_negotiated(accept, &state.get_die_temperature())
                 }),
             )
             .route(
                 "/properties/on",
-                get(|State(state): State<AppState>| async move {
-                    to_json_response(&state.get_fan_on())
+                get(|State(state): State<AppState>, accept: Accept| async move {
+                    respond_negotiated(accept, &state.get_fan_on())
                 })
                 .put(
-                    |State(state): State<AppState>,
-                     picoserve::extract::Json::<_>(on)| async move {
+                    |State(state): State<AppState>, JsonBody::<_>(on)| async move {
                         state.set_fan_on(on);
+                        audit::record("on", &on);
                         StatusCode::NO_CONTENT
                     },
                 ),
             )
             .route(
                 "/properties/speed",
-                get(|State(state): State<AppState>| async move {
-                    to_json_response(&state.get_fan_speed())
+                get(|State(state): State<AppState>, accept: Accept| async move {
+                    respond_negotiated(accept, &state.get_fan_speed())
                 })
                 .put(
-                    |State(state): State<AppState>,
-                     picoserve::extract::Json::<_>(speed)| async move {
+                    |State(state): State<AppState>, JsonBody::<_>(speed)| async move {
                         state.set_fan_speed(speed);
+                        audit::record("speed", &speed);
                         StatusCode::NO_CONTENT
                     },
                 ),
             )
             .route(
                 "/properties/rpm",
-                get(async move |State(state): State<AppState>| {
-                    to_json_response(&state.get_fan_rpm())
+                get(async move |State(state): State<AppState>, accept: Accept| {
+                    respond_negotiated(accept, &state.get_fan_rpm())
                 }),
             )
             .route(
                 "/events/on",
-                get(async move || response::EventStream(SseEvents(ON_WATCH.receiver().unwrap()))),
+                get(async move |LastEventId(last_event_id)| {
+                    EventStream(SseEvents::new(
+                        ON_WATCH.receiver().unwrap(),
+                        &ON_WATCH_LOG,
+                        last_event_id,
+                    ))
+                }),
             )
             .route(
                 "/events/temperature",
-                get(async move || response::EventStream(SseEvents(WATCH.receiver().unwrap()))),
+                get(async move |LastEventId(last_event_id)| {
+                    EventStream(SseEvents::new(
+                        WATCH.receiver().unwrap(),
+                        &WATCH_LOG,
+                        last_event_id,
+                    ))
+                }),
             )
             .route(
                 "/events/rpm",
-                get(async move || response::EventStream(SseEvents(RPM_WATCH.receiver().unwrap()))),
+                get(async move |LastEventId(last_event_id)| {
+                    EventStream(SseEvents::new(
+                        RPM_WATCH.receiver().unwrap(),
+                        &RPM_WATCH_LOG,
+                        last_event_id,
+                    ))
+                }),
             )
+            .layer(LatencyLayer)
     }
 }
 
-static WATCH: Watch<CriticalSectionRawMutex, f32, 2> = Watch::new();
-static RPM_WATCH: Watch<CriticalSectionRawMutex, i16, 2> = Watch::new();
-static ON_WATCH: Watch<CriticalSectionRawMutex, bool, 2> = Watch::new();
+static WATCH: Watch<CriticalSectionRawMutex, Sequenced<f32>, 2> = Watch::new();
+static WATCH_LOG: EventLog<f32> = EventLog::new();
+static RPM_WATCH: Watch<CriticalSectionRawMutex, Sequenced<i16>, 2> = Watch::new();
+static RPM_WATCH_LOG: EventLog<i16> = EventLog::new();
+static ON_WATCH: Watch<CriticalSectionRawMutex, Sequenced<bool>, 2> = Watch::new();
+static ON_WATCH_LOG: EventLog<bool> = EventLog::new();
 
 #[embassy_executor::task]
 async fn tach_sample_task(unit: &'static esp_hal::pcnt::unit::Unit<'static, 0>) -> ! {
@@ -456,28 +419,30 @@ async fn tach_sample_task(unit: &'static esp_hal::pcnt::unit::Unit<'static, 0>)
         Timer::after(Duration::from_secs(1)).await;
         let count = unit.value();
         unit.clear();
-        let rpm = (count as i32 * 60) / 2;
-        let rpm_i16 = rpm as i16;
-        FAN_RPM.store(rpm_i16, Ordering::Relaxed);
-        if ((rpm_i16 - last_rpm).unsigned_abs() / 10) != 0 {
-            sender.send(rpm_i16);
-            last_rpm = rpm_i16;
+        let rpm = fan::rpm(count);
+        FAN_RPM.store(rpm, Ordering::Relaxed);
+        if fan::rpm_changed(last_rpm, rpm) {
+            sender.send(RPM_WATCH_LOG.record(rpm));
+            last_rpm = rpm;
         }
     }
 }
 
+/// Time between two measurements.
+const MEASUREMENT_INTERVAL: Duration = Duration::from_secs(1);
+
 #[embassy_executor::task]
-#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
 async fn temperature_write_task(state: &'static AppState) -> ! {
     let sender = WATCH.sender();
     let mut last_temp = state.get_temperature().await.unwrap_or(-500.0);
 
     loop {
-        Timer::after(Duration::from_secs(1)).await;
+        heartbeat!("measurement", MEASUREMENT_INTERVAL);
+        Timer::after(MEASUREMENT_INTERVAL).await;
 
         if let Ok(temp) = state.get_temperature().await {
-            if ((last_temp - temp) * 100f32) as u32 / 10 != 0 {
-                sender.send(temp);
+            if temperature_changed(last_temp, temp) {
+                sender.send(WATCH_LOG.record(temp));
                 last_temp = temp;
             }
         }
@@ -499,5 +464,5 @@ esp_bootloader_esp_idf::esp_app_desc!();
 
 #[esp_rtos::main]
 async fn main(spawner: Spawner) {
-    AppProps::run(spawner).await;
+    wot_esp_thing::run!(AppProps, spawner);
 }
@@ -0,0 +1,65 @@
+//! The routes counted by `wot_esp_thing::affordance_stats`, taken from the
+//! forms of the TD.
+
+use alloc::{string::String, vec::Vec};
+
+use serde_json::Value;
+
+/// The HTTP method of a form with operation `op`, `None` for operations
+/// the demos do not serve.
+#[must_use]
+pub fn method(op: &str) -> Option<&'static str> {
+    match op {
+        "readproperty" | "observeproperty" | "unobserveproperty" | "queryaction"
+        | "subscribeevent" | "unsubscribeevent" => Some("GET"),
+        "writeproperty" => Some("PUT"),
+        "invokeaction" => Some("POST"),
+        _ => None,
+    }
+}
+
+/// The path of `href`, which is relative or, in a TD instantiated from a
+/// Thing Model, absolute, without its URI template, e.g. `{?field}`.
+#[must_use]
+pub fn path(href: &str) -> &str {
+    let path = match href.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("/", |i| &rest[i..]),
+        None => href,
+    };
+    path.find(['{', '?']).map_or(path, |i| &path[..i])
+}
+
+/// Every `(method, path)` the TD `td` has a form for, each once: the TD
+/// itself at `GET /`, then those of the properties, actions and events.
+#[must_use]
+pub fn routes(td: &Value) -> Vec<(&'static str, String)> {
+    let mut routes = alloc::vec![("GET", String::from("/"))];
+
+    let affordances = ["properties", "actions", "events"]
+        .iter()
+        .filter_map(|member| td.get(member)?.as_object())
+        .flat_map(|affordances| affordances.values());
+    let forms = affordances
+        .filter_map(|affordance| affordance.get("forms")?.as_array())
+        .flatten();
+
+    for form in forms {
+        let Some(href) = form.get("href").and_then(Value::as_str) else {
+            continue;
+        };
+        // `op` is a string or an array of them.
+        let ops = match form.get("op") {
+            Some(Value::String(op)) => alloc::vec![op.as_str()],
+            Some(Value::Array(ops)) => ops.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        for method in ops.into_iter().filter_map(method) {
+            let path = path(href);
+            if !routes.iter().any(|(m, p)| *m == method && p == path) {
+                routes.push((method, path.into()));
+            }
+        }
+    }
+
+    routes
+}
@@ -0,0 +1,56 @@
+//! Edge-triggered threshold alarms with hysteresis.
+
+/// Which side of the threshold raises the alarm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Above,
+    Below,
+}
+
+/// Alarm state of a numeric source, updated with every new value.
+///
+/// The alarm becomes active once a value is beyond the threshold and clears
+/// only once a value is back by more than the hysteresis, so a value hovering
+/// around the threshold does not flap.
+#[derive(Clone, Copy, Debug)]
+pub struct Trigger {
+    direction: Direction,
+    hysteresis: f32,
+    active: bool,
+}
+
+impl Trigger {
+    #[must_use]
+    pub const fn new(direction: Direction, hysteresis: f32) -> Self {
+        Self {
+            direction,
+            hysteresis,
+            active: false,
+        }
+    }
+
+    #[must_use]
+    pub const fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Compare `value` with `threshold`, returning the new state when the
+    /// alarm becomes active (`true`) or clears (`false`).
+    ///
+    /// `NaN` values leave the state unchanged.
+    pub fn update(&mut self, threshold: f32, value: f32) -> Option<bool> {
+        let (beyond, back) = match self.direction {
+            Direction::Above => (value > threshold, value < threshold - self.hysteresis),
+            Direction::Below => (value < threshold, value > threshold + self.hysteresis),
+        };
+
+        if !self.active && beyond {
+            self.active = true;
+        } else if self.active && back {
+            self.active = false;
+        } else {
+            return None;
+        }
+        Some(self.active)
+    }
+}
@@ -0,0 +1,160 @@
+//! Several property reads and writes in one request, see
+//! `wot_esp_thing::batch`.
+//!
+//! A gateway sends up to [`MAX_OPS`] operations to `POST /batch` and gets an
+//! outcome per operation, in the same order, each with its own status: an
+//! unknown property or an invalid value fails that entry alone. An outcome
+//! whose value does not fit in the response keeps its status, marked
+//! `truncated`:
+//!
+//! ```text
+//! [{"op":"read","name":"temperature"},{"op":"write","name":"on","value":true}]
+//! [{"status":200,"value":21.5},{"status":204}]
+//! ```
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::{Display, Write as _};
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Operations a batch takes at most.
+pub const MAX_OPS: usize = 8;
+
+/// Most bytes of a batch request body.
+pub const MAX_BATCH_BODY: usize = 1024;
+
+/// Bytes an outcome takes once truncated, its separator included, e.g.
+/// `,{"status":200,"truncated":true}`.
+const TRUNCATED_LEN: usize = 32;
+
+/// A read of the property `name`, or a write of `value` to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Op {
+    pub name: String,
+    /// The value written, `None` for a read.
+    pub write: Option<Value>,
+}
+
+impl Op {
+    fn parse(entry: Value) -> Result<Self, &'static str> {
+        let Value::Object(mut entry) = entry else {
+            return Err("operation must be an object");
+        };
+        let Some(Value::String(name)) = entry.remove("name") else {
+            return Err("operation must have a name");
+        };
+        let write = match entry.get("op").and_then(Value::as_str) {
+            Some("read") => None,
+            Some("write") => Some(entry.remove("value").ok_or("write must have a value")?),
+            _ => return Err("op must be read or write"),
+        };
+
+        Ok(Self { name, write })
+    }
+}
+
+/// The operations of the batch `body`, each parsed on its own so that a
+/// malformed one fails alone.
+///
+/// # Errors
+///
+/// If `body` is not an array, or has more than [`MAX_OPS`] entries.
+pub fn parse(body: Value) -> Result<Vec<Result<Op, &'static str>>, String> {
+    let Value::Array(entries) = body else {
+        return Err("batch must be an array of operations".to_string());
+    };
+    if entries.len() > MAX_OPS {
+        return Err(format!("batch must have at most {MAX_OPS} operations"));
+    }
+
+    Ok(entries.into_iter().map(Op::parse).collect())
+}
+
+/// The outcome of an [`Op`], with the status its own request would get.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Outcome {
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Outcome {
+    fn failed(status: u16, error: &impl Display) -> Self {
+        Self {
+            status,
+            value: None,
+            error: Some(error.to_string()),
+        }
+    }
+
+    /// A read of `value`, 200.
+    #[must_use]
+    pub fn read(value: Value) -> Self {
+        Self {
+            status: 200,
+            value: Some(value),
+            error: None,
+        }
+    }
+
+    /// A write applied, 204.
+    #[must_use]
+    pub fn written() -> Self {
+        Self {
+            status: 204,
+            value: None,
+            error: None,
+        }
+    }
+
+    /// A malformed operation or a value refused with `error`, 400.
+    #[must_use]
+    pub fn invalid(error: &impl Display) -> Self {
+        Self::failed(400, error)
+    }
+
+    /// A property the Thing does not have, 404.
+    #[must_use]
+    pub fn unknown() -> Self {
+        Self::failed(404, &"no such property")
+    }
+
+    /// A write to a read-only property, 405.
+    #[must_use]
+    pub fn read_only() -> Self {
+        Self::failed(405, &"property is read-only")
+    }
+}
+
+/// The JSON array of `outcomes`, at most `limit` bytes long: an outcome
+/// that does not fit, with room kept for the others truncated, loses its
+/// value or error and is marked `truncated`.
+#[must_use]
+pub fn respond(outcomes: &[Outcome], limit: usize) -> String {
+    let mut response = String::from("[");
+    for (i, outcome) in outcomes.iter().enumerate() {
+        if i > 0 {
+            response.push(',');
+        }
+        let entry = serde_json::to_string(outcome).unwrap_or_default();
+        let reserved = (outcomes.len() - i - 1) * TRUNCATED_LEN + 1;
+        if response.len() + entry.len() + reserved <= limit {
+            response.push_str(&entry);
+        } else {
+            let _ = write!(
+                response,
+                "{{\"status\":{},\"truncated\":true}}",
+                outcome.status
+            );
+        }
+    }
+    response.push(']');
+    response
+}
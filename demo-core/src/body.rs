@@ -0,0 +1,28 @@
+//! Write bodies, deserialized with `serde-json-core` into fixed-size types.
+//!
+//! Each body type has its size bound as a const next to it, the most a route
+//! reads before answering 413; see `wot_esp_thing::JsonBody`.
+
+use alloc::string::ToString;
+use core::fmt::Display;
+
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+pub use serde_json_core::de::Error;
+
+/// Deserialize the whole of `body` as `T`.
+///
+/// # Errors
+///
+/// If `body` is not JSON for `T`, or has more than whitespace after it.
+pub fn parse<T: DeserializeOwned>(body: &[u8]) -> Result<T, Error> {
+    serde_json_core::from_slice(body).map(|(value, _)| value)
+}
+
+/// The JSON body of a 400 response for `error`, e.g.
+/// `{"error":"Invalid type"}`.
+#[must_use]
+pub fn error(error: &impl Display) -> Value {
+    json!({ "error": error.to_string() })
+}
@@ -0,0 +1,47 @@
+//! Phases of bringing a Thing up, and how a status LED shows them.
+
+/// Where `EspThing::run` is at, published as `wot_esp_thing::boot_status`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BootStatus {
+    /// Hardware and scheduler coming up.
+    Starting,
+    /// Associating with the configured network.
+    WifiConnecting,
+    /// Associated, waiting for DHCP.
+    WaitingForIp,
+    /// Serving the Thing; the LED is left to the app.
+    Running,
+    /// Bring-up failed, the chip resets shortly.
+    Error,
+}
+
+impl BootStatus {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Starting => "starting",
+            Self::WifiConnecting => "wifi connecting",
+            Self::WaitingForIp => "waiting for IP",
+            Self::Running => "running",
+            Self::Error => "error",
+        }
+    }
+
+    /// The blink pattern of the status LED, repeated until the status
+    /// changes: on and off times in milliseconds, alternating, starting
+    /// with on. Empty once the LED is left to the app.
+    #[must_use]
+    pub fn blink_ms(self) -> &'static [u16] {
+        match self {
+            // Steady on.
+            Self::Starting => &[1000, 0],
+            // Fast.
+            Self::WifiConnecting => &[100, 100],
+            // Slow.
+            Self::WaitingForIp => &[500, 500],
+            Self::Running => &[],
+            // Three short flashes, then a pause.
+            Self::Error => &[100, 150, 100, 150, 100, 1000],
+        }
+    }
+}
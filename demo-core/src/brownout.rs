@@ -0,0 +1,35 @@
+//! The threshold of the brown-out detector, the `BROWNOUT_LEVEL` build-time
+//! setting applied by `wot_esp_thing::brownout`.
+//!
+//! The levels are those of ESP-IDF's `ESP_BROWNOUT_DET_LVL`: the detector
+//! resets the chip once the supply drops below the threshold of its level,
+//! from 2.51 V at level 7 up to 3.27 V at level 2.
+
+/// Level of an unset `BROWNOUT_LEVEL`, the lowest threshold, as ESP-IDF.
+pub const DEFAULT_LEVEL: u8 = 7;
+
+/// The supply voltage under which the detector at `level` trips, in
+/// millivolts, as characterized by Espressif; `None` for a level the chips
+/// do not offer.
+#[must_use]
+pub const fn threshold_mv(level: u8) -> Option<u16> {
+    match level {
+        2 => Some(3270),
+        3 => Some(3100),
+        4 => Some(2920),
+        5 => Some(2760),
+        6 => Some(2640),
+        7 => Some(2510),
+        _ => None,
+    }
+}
+
+/// The level of the `BROWNOUT_LEVEL` setting, [`DEFAULT_LEVEL`] when unset
+/// or not a level of [`threshold_mv`].
+#[must_use]
+pub fn level(setting: Option<&str>) -> u8 {
+    setting
+        .and_then(|level| level.trim().parse().ok())
+        .filter(|&level| threshold_mv(level).is_some())
+        .unwrap_or(DEFAULT_LEVEL)
+}
@@ -0,0 +1,73 @@
+//! Optional hardware found at start-up and the affordances that need it,
+//! see `wot_esp_thing::capabilities`.
+
+use alloc::{string::String, vec::Vec};
+
+use serde_json::{json, Map, Value};
+
+use crate::affordance_stats::path;
+
+/// Hardware a board may not have, e.g. a sensor left unpopulated.
+#[derive(Clone, Copy, Debug)]
+pub struct Hardware {
+    /// E.g. `shtc3`, as listed in the `capabilities` property.
+    pub name: &'static str,
+    /// The properties, actions and events served only with it.
+    pub affordances: &'static [&'static str],
+}
+
+/// Remove from `td` the affordances of the `hardware` not `detected`, and
+/// add the `capabilities` property listing the detected one.
+///
+/// Returns the paths of the forms removed.
+pub fn restrict(
+    td: &mut Map<String, Value>,
+    hardware: &[Hardware],
+    detected: &[&str],
+) -> Vec<String> {
+    let mut absent = Vec::new();
+
+    let missing = hardware.iter().filter(|h| !detected.contains(&h.name));
+    for name in missing.flat_map(|h| h.affordances) {
+        for member in ["properties", "actions", "events"] {
+            let Some(Value::Object(affordances)) = td.get_mut(member) else {
+                continue;
+            };
+            let Some(affordance) = affordances.remove(*name) else {
+                continue;
+            };
+            let forms = affordance.get("forms").and_then(Value::as_array);
+            let hrefs = forms
+                .into_iter()
+                .flatten()
+                .filter_map(|f| f["href"].as_str());
+            for path in hrefs.map(path) {
+                if !absent.iter().any(|p| p == path) {
+                    absent.push(path.into());
+                }
+            }
+        }
+    }
+
+    let names: Vec<_> = hardware.iter().map(|h| h.name).collect();
+    let capabilities = json!({
+        "title": "Capabilities",
+        "description": "Optional hardware found at start-up",
+        "type": "array",
+        "items": { "type": "string", "enum": names },
+        "readOnly": true,
+        "forms": [{
+            "href": "/properties/capabilities",
+            "op": ["readproperty"],
+            "contentType": "application/json",
+        }],
+    });
+    if let Value::Object(properties) = td
+        .entry("properties")
+        .or_insert_with(|| Value::Object(Map::new()))
+    {
+        properties.insert("capabilities".into(), capabilities);
+    }
+
+    absent
+}
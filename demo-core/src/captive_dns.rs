@@ -0,0 +1,71 @@
+//! DNS answers for the captive portal, built in place in fixed buffers.
+
+use core::net::Ipv4Addr;
+
+const HEADER_LEN: usize = 12;
+const TYPE_A: u16 = 1;
+const CLASS_IN: u16 = 1;
+/// Seconds clients may cache the answer.
+const TTL: u32 = 60;
+/// Name pointer, type, class, TTL, data length and the address.
+const ANSWER_LEN: usize = 2 + 2 + 2 + 4 + 2 + 4;
+
+fn read_u16(buf: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*buf.get(at)?, *buf.get(at + 1)?]))
+}
+
+/// Write into `response` the answer to `query` resolving any name to `ip`.
+///
+/// Returns the response length, or `None` when `query` is not a standard
+/// query with a single question (it is then ignored). Queries for other types
+/// than `A` get an empty answer.
+#[must_use]
+pub fn answer(query: &[u8], response: &mut [u8], ip: Ipv4Addr) -> Option<usize> {
+    let flags = read_u16(query, 2)?;
+    let is_query = flags & 0x8000 == 0;
+    let opcode = (flags >> 11) & 0xf;
+    if !is_query || opcode != 0 || read_u16(query, 4)? != 1 {
+        return None;
+    }
+
+    // Skip the question name, a sequence of labels ending with an empty one.
+    let mut at = HEADER_LEN;
+    loop {
+        let len = usize::from(*query.get(at)?);
+        at += 1;
+        if len == 0 {
+            break;
+        }
+        // Compression pointers are not expected in a question.
+        if len > 63 {
+            return None;
+        }
+        at += len;
+    }
+    let qtype = read_u16(query, at)?;
+    let qclass = read_u16(query, at + 2)?;
+    let question_end = at + 4;
+
+    let answers = u16::from(qtype == TYPE_A && qclass == CLASS_IN);
+    let len = question_end + usize::from(answers) * ANSWER_LEN;
+    let response = response.get_mut(..len)?;
+
+    response[..question_end].copy_from_slice(&query[..question_end]);
+    // Response, authoritative, recursion desired copied from the query.
+    response[2..4].copy_from_slice(&(0x8400 | (flags & 0x0100)).to_be_bytes());
+    response[6..8].copy_from_slice(&answers.to_be_bytes());
+    response[8..12].fill(0);
+
+    if answers > 0 {
+        let answer = &mut response[question_end..];
+        // Pointer to the question name.
+        answer[0..2].copy_from_slice(&0xc00c_u16.to_be_bytes());
+        answer[2..4].copy_from_slice(&TYPE_A.to_be_bytes());
+        answer[4..6].copy_from_slice(&CLASS_IN.to_be_bytes());
+        answer[6..10].copy_from_slice(&TTL.to_be_bytes());
+        answer[10..12].copy_from_slice(&4_u16.to_be_bytes());
+        answer[12..16].copy_from_slice(&ip.octets());
+    }
+
+    Some(len)
+}
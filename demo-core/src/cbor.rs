@@ -0,0 +1,73 @@
+//! CBOR (RFC 8949) property values, for consumers asking for
+//! `application/cbor` instead of parsing JSON.
+//!
+//! A value is encoded from the same `Serialize` impl as its JSON, so both
+//! carry the same data: `true` is `0xf5`, `{"r":128}` is
+//! `0xa1 0x61 0x72 0x18 0x80`. Floats take the shortest width that keeps
+//! them exact.
+
+use alloc::{string::String, vec::Vec};
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// Media type of a CBOR body.
+pub const CONTENT_TYPE: &str = "application/cbor";
+
+/// `value` encoded as CBOR, `None` if its `Serialize` impl fails.
+#[must_use]
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Option<Vec<u8>> {
+    let mut body = Vec::new();
+    ciborium::into_writer(value, &mut body).ok()?;
+    Some(body)
+}
+
+/// Advertise CBOR on the readable properties of the serialized TD `td`:
+/// each gets a second read form, with the `href` of its first JSON one and
+/// the [`CONTENT_TYPE`]. Write-only properties are left as they are.
+///
+/// # Errors
+///
+/// Returns an error if `td` is not a JSON object.
+pub fn advertise(td: &str) -> Result<String, serde_json::Error> {
+    let mut td: Map<String, Value> = serde_json::from_str(td)?;
+
+    let affordances = td.get_mut("properties").and_then(Value::as_object_mut);
+    for forms in affordances.into_iter().flat_map(|all| {
+        all.values_mut()
+            .filter_map(|affordance| affordance.get_mut("forms")?.as_array_mut())
+    }) {
+        let Some(href) = forms
+            .iter()
+            .find(|form| reads_json(form))
+            .and_then(|form| form["href"].as_str())
+            .map(String::from)
+        else {
+            continue;
+        };
+
+        forms.push(json!({
+            "href": href,
+            "op": "readproperty",
+            "contentType": CONTENT_TYPE,
+        }));
+    }
+
+    serde_json::to_string(&td)
+}
+
+/// Whether `form` reads the property as JSON, the default content type.
+fn reads_json(form: &Value) -> bool {
+    let reads = match &form["op"] {
+        Value::String(op) => op == "readproperty",
+        Value::Array(ops) => ops.iter().any(|op| op == "readproperty"),
+        // The default op of a property form reads it.
+        Value::Null => true,
+        _ => false,
+    };
+    let json = form
+        .get("contentType")
+        .and_then(Value::as_str)
+        .is_none_or(|content_type| content_type == "application/json");
+    reads && json
+}
@@ -0,0 +1,384 @@
+//! Validation of the settings baked in from the environment at build time,
+//! see `wot_esp_thing::config`.
+//!
+//! Each check takes the value as set and returns why it is rejected, without
+//! the name of the setting: the same check serves `SSID` and `SSID_1`. The
+//! checks on the Wi-Fi credentials are `const`, so a bad one fails the build.
+
+use alloc::vec::Vec;
+use core::net::Ipv4Addr;
+
+use crate::{
+    brownout,
+    http::parse_url,
+    low_power::{MAX_WAKE_INTERVAL_MIN, MIN_WAKE_INTERVAL_MIN},
+    origin,
+    peers::MAX_STRIKE_WINDOW_S,
+    shutdown::{MAX_WAKE_MIN, MIN_WAKE_MIN},
+    sse,
+    wear::MAX_WRITES_PER_HOUR,
+    wifi::RadioSettings,
+};
+
+/// Longest SSID, in bytes.
+pub const MAX_SSID_LEN: usize = 32;
+
+/// Longest EAP identity, username or password, in bytes.
+pub const MAX_EAP_LEN: usize = 128;
+
+/// A setting read at build time and the check of its value.
+#[derive(Clone, Copy)]
+pub struct Setting {
+    /// Name of the env var, e.g. `TDD_URL`.
+    pub name: &'static str,
+    /// `None` when unset, which every check accepts.
+    pub value: Option<&'static str>,
+    pub check: fn(&str) -> Result<(), &'static str>,
+}
+
+/// A setting whose value its check rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Problem {
+    pub name: &'static str,
+    pub reason: &'static str,
+}
+
+/// Every setting of `settings` that is set and rejected, in order.
+#[must_use]
+pub fn problems(settings: &[Setting]) -> Vec<Problem> {
+    settings
+        .iter()
+        .filter_map(|setting| {
+            let reason = (setting.check)(setting.value?).err()?;
+            Some(Problem {
+                name: setting.name,
+                reason,
+            })
+        })
+        .collect()
+}
+
+/// An SSID: 1 to 32 bytes.
+///
+/// # Errors
+///
+/// If it is empty or too long.
+pub const fn ssid(ssid: &str) -> Result<(), &'static str> {
+    if ssid.is_empty() || ssid.len() > MAX_SSID_LEN {
+        Err("must be 1 to 32 bytes long")
+    } else {
+        Ok(())
+    }
+}
+
+/// A WPA2 passphrase: 8 to 63 printable ASCII characters, or the 64 hex
+/// digits of the key itself. Empty for an open network.
+///
+/// # Errors
+///
+/// If it is too short or too long, or has other characters.
+pub const fn passphrase(passphrase: &str) -> Result<(), &'static str> {
+    let bytes = passphrase.as_bytes();
+    if bytes.is_empty() {
+        return Ok(());
+    }
+
+    let hex_key = bytes.len() == 64;
+    if !hex_key && (bytes.len() < 8 || bytes.len() > 63) {
+        return Err("must be 8 to 63 characters long, or 64 hex digits");
+    }
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if hex_key && !b.is_ascii_hexdigit() {
+            return Err("of 64 characters must be hex digits");
+        }
+        if !(b == b' ' || b.is_ascii_graphic()) {
+            return Err("must be printable ASCII");
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+/// An EAP identity, username or password: 1 to 128 bytes.
+///
+/// # Errors
+///
+/// If it is empty or too long.
+pub const fn eap(value: &str) -> Result<(), &'static str> {
+    if value.is_empty() || value.len() > MAX_EAP_LEN {
+        Err("must be 1 to 128 bytes long")
+    } else {
+        Ok(())
+    }
+}
+
+/// The URL of a server the Thing sends requests to: `http://host[:port]/..`,
+/// as `crate::http::parse_url` takes it.
+///
+/// # Errors
+///
+/// If it is not such a URL or has spaces or control characters.
+pub fn http_url(url: &str) -> Result<(), &'static str> {
+    if !printable(url) || parse_url(url).is_none() {
+        return Err("must be an http:// URL with a host, e.g. http://192.168.1.10:8080/path");
+    }
+    Ok(())
+}
+
+/// The URL the Thing is reached at, e.g. through a reverse proxy:
+/// `http://` or `https://` and a host.
+///
+/// # Errors
+///
+/// If it is not such a URL or has spaces or control characters.
+pub fn base_url(url: &str) -> Result<(), &'static str> {
+    let host = url
+        .strip_prefix("http://")
+        .or_else(|| url.strip_prefix("https://"))
+        .map(|rest| rest.split(['/', ':']).next().unwrap_or(""));
+    match host {
+        Some(host) if !host.is_empty() && printable(url) => Ok(()),
+        _ => Err("must be an http:// or https:// URL with a host"),
+    }
+}
+
+/// Where to report issues: a [`base_url`] or a `mailto:` address.
+///
+/// # Errors
+///
+/// If it is neither.
+pub fn support_url(url: &str) -> Result<(), &'static str> {
+    match url.strip_prefix("mailto:") {
+        Some(address) if address.contains('@') && printable(address) => Ok(()),
+        Some(_) => Err("must be a mailto: URL with an address"),
+        None => base_url(url).map_err(|_| "must be an http(s):// URL or a mailto: address"),
+    }
+}
+
+/// A host name or an IPv4 address.
+///
+/// # Errors
+///
+/// If it is not a valid host name, e.g. it has spaces or an empty label.
+pub fn host(host: &str) -> Result<(), &'static str> {
+    if host.parse::<Ipv4Addr>().is_ok() {
+        return Ok(());
+    }
+
+    let label = |label: &str| {
+        (1..=63).contains(&label.len())
+            && label
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+    };
+    let all_digits = host.bytes().all(|b| b.is_ascii_digit() || b == b'.');
+    if host.len() > 253 || all_digits || !host.split('.').all(label) {
+        return Err("must be a host name or an IPv4 address");
+    }
+    Ok(())
+}
+
+/// A port, from 1 to 65535.
+///
+/// # Errors
+///
+/// If it is not a number in that range.
+pub fn port(port: &str) -> Result<(), &'static str> {
+    match port.parse::<u16>() {
+        Ok(port) if port > 0 => Ok(()),
+        _ => Err("must be a port from 1 to 65535"),
+    }
+}
+
+/// A log level as `ESP_LOG` takes it: `off`, `error`, `warn`, `info`,
+/// `debug` or `trace`, any case. Only the part before the first comma counts.
+///
+/// # Errors
+///
+/// If it is not one of those.
+pub fn log_level(level: &str) -> Result<(), &'static str> {
+    let level = level.split(',').next().unwrap_or("").trim();
+    let known = ["off", "error", "warn", "info", "debug", "trace"];
+    if known.iter().any(|known| known.eq_ignore_ascii_case(level)) {
+        Ok(())
+    } else {
+        Err("must be off, error, warn, info, debug or trace")
+    }
+}
+
+/// The minutes between two wake-ups in the `batched` energy mode.
+///
+/// # Errors
+///
+/// If it is not a whole number of minutes from 1 to 1440.
+pub fn wake_interval(minutes: &str) -> Result<(), &'static str> {
+    match minutes.trim().parse::<u32>() {
+        Ok(minutes) if (MIN_WAKE_INTERVAL_MIN..=MAX_WAKE_INTERVAL_MIN).contains(&minutes) => Ok(()),
+        _ => Err("must be a whole number of minutes from 1 to 1440"),
+    }
+}
+
+/// The minutes a Thing sleeps after the `shutdown` action, see
+/// [`crate::shutdown`].
+///
+/// # Errors
+///
+/// If it is not a whole number of minutes from 1 to 10080.
+pub fn shutdown_wake(minutes: &str) -> Result<(), &'static str> {
+    match minutes.trim().parse::<u32>() {
+        Ok(minutes) if (MIN_WAKE_MIN..=MAX_WAKE_MIN).contains(&minutes) => Ok(()),
+        _ => Err("must be a whole number of minutes from 1 to 10080"),
+    }
+}
+
+/// The partial requests a client may leave within the strike window before
+/// it is refused, see [`crate::peers::StrikePolicy`].
+///
+/// # Errors
+///
+/// If it is not a whole number from 1 to 255.
+pub fn strike_limit(limit: &str) -> Result<(), &'static str> {
+    match limit.trim().parse::<u8>() {
+        Ok(1..) => Ok(()),
+        _ => Err("must be a whole number from 1 to 255"),
+    }
+}
+
+/// The seconds over which the partial requests of a client are counted, see
+/// [`crate::peers::StrikePolicy`].
+///
+/// # Errors
+///
+/// If it is not a whole number of seconds from 1 to 3600.
+pub fn strike_window(seconds: &str) -> Result<(), &'static str> {
+    match seconds.trim().parse::<u32>() {
+        Ok(seconds) if (1..=MAX_STRIKE_WINDOW_S).contains(&seconds) => Ok(()),
+        _ => Err("must be a whole number of seconds from 1 to 3600"),
+    }
+}
+
+/// The writes per hour a key of the flash store may average, see
+/// [`crate::wear`].
+///
+/// # Errors
+///
+/// If it is not a whole number from 1 to 3600.
+pub fn flash_max_writes(writes: &str) -> Result<(), &'static str> {
+    match writes.trim().parse::<u32>() {
+        Ok(writes) if (1..=MAX_WRITES_PER_HOUR).contains(&writes) => Ok(()),
+        _ => Err("must be a whole number of writes per hour from 1 to 3600"),
+    }
+}
+
+/// The seconds between the comments keeping an idle event stream alive,
+/// see [`crate::sse::keepalive_s`].
+///
+/// # Errors
+///
+/// If it is not a whole number of seconds from 1 to 3599, below the stream
+/// lifetime.
+pub fn sse_keepalive(seconds: &str) -> Result<(), &'static str> {
+    match seconds.trim().parse::<u64>() {
+        Ok(seconds) if (1..sse::MAX_STREAM_LIFETIME_S).contains(&seconds) => Ok(()),
+        _ => Err("must be a whole number of seconds from 1 to 3599"),
+    }
+}
+
+/// The level of the brown-out detector, see [`crate::brownout`].
+///
+/// # Errors
+///
+/// If it is not a whole number from 2 to 7.
+pub fn brownout_level(level: &str) -> Result<(), &'static str> {
+    match level.trim().parse::<u8>() {
+        Ok(level) if brownout::threshold_mv(level).is_some() => Ok(()),
+        _ => Err("must be a level from 2 to 7"),
+    }
+}
+
+/// The hosts allowed to write from a web page, comma-separated, see
+/// [`crate::origin`].
+///
+/// # Errors
+///
+/// If an entry is not a host, `host:port`, `[IPv6]:port` or `*`.
+pub fn write_origins(origins: &str) -> Result<(), &'static str> {
+    origins.split(',').try_for_each(origin::entry)
+}
+
+/// The bearer token letting a write from any web page through.
+///
+/// # Errors
+///
+/// If it is not 16 to 128 printable ASCII characters.
+pub fn write_token(token: &str) -> Result<(), &'static str> {
+    if (16..=128).contains(&token.len()) && token.bytes().all(|b| b.is_ascii_graphic()) {
+        Ok(())
+    } else {
+        Err("must be 16 to 128 printable ASCII characters")
+    }
+}
+
+/// The namespace of the Thing ids, see [`crate::names::device_urn`].
+///
+/// # Errors
+///
+/// If it is not 2 to 32 letters, digits or hyphens, starting and ending
+/// with a letter or digit, or is `urn`.
+pub fn urn_org(org: &str) -> Result<(), &'static str> {
+    let alphanumeric = |b: Option<&u8>| b.is_some_and(u8::is_ascii_alphanumeric);
+    let bytes = org.as_bytes();
+    if (2..=32).contains(&bytes.len())
+        && bytes
+            .iter()
+            .all(|b| b.is_ascii_alphanumeric() || *b == b'-')
+        && alphanumeric(bytes.first())
+        && alphanumeric(bytes.last())
+        && !org.eq_ignore_ascii_case("urn")
+    {
+        Ok(())
+    } else {
+        Err("must be 2 to 32 letters, digits or hyphens, not starting or ending with a hyphen")
+    }
+}
+
+/// The prefix of every route, see [`crate::prefix::normalize`].
+///
+/// # Errors
+///
+/// If it does not normalize to a prefix.
+pub fn route_prefix(prefix: &str) -> Result<(), &'static str> {
+    crate::prefix::normalize(prefix).map(|_| ())
+}
+
+/// A regulatory domain, see [`RadioSettings::parse`].
+///
+/// # Errors
+///
+/// If it is not two capital letters or `01`.
+pub fn country(country: &str) -> Result<(), &'static str> {
+    RadioSettings::parse(Some(country), None)
+        .map(|_| ())
+        .map_err(|_| "must be two capital letters, e.g. JP, or 01")
+}
+
+/// A transmit power limit, see [`RadioSettings::parse`].
+///
+/// # Errors
+///
+/// If it is not a whole number of dBm from 2 to 20.
+pub fn max_tx_power(dbm: &str) -> Result<(), &'static str> {
+    RadioSettings::parse(None, Some(dbm))
+        .map(|_| ())
+        .map_err(|_| "must be a whole number of dBm from 2 to 20")
+}
+
+/// Whether `value` has no spaces or control characters.
+fn printable(value: &str) -> bool {
+    value.bytes().all(|b| b.is_ascii_graphic() || !b.is_ascii())
+}
@@ -0,0 +1,67 @@
+//! The `configuration` property: what a firmware runs with, as built.
+//!
+//! Every value comes from the const the firmware itself uses, so the
+//! property answers "what exactly is running on that board" without the
+//! source of the matching commit.
+
+use serde_json::{json, Map, Value};
+
+/// The effective configuration of a firmware.
+pub struct Configuration<'a> {
+    pub name: &'a str,
+    pub version: &'a str,
+    /// The board file picked at build time, `None` for a firmware without
+    /// a pin map.
+    pub board: Option<&'a str>,
+    /// GPIO of every pin role of the board, e.g. `("led", 2)`.
+    pub pins: &'a [(&'a str, u8)],
+    /// Every optional feature, and whether it is built in.
+    pub features: &'a [(&'a str, bool)],
+    /// Bytes given to the heap.
+    pub heap_size: usize,
+    /// Web tasks serving HTTP, the concurrent connections.
+    pub web_tasks: usize,
+    /// Sockets of the network stack.
+    pub sockets: usize,
+    /// Bytes of the TCP receive and send buffers of a web task.
+    pub tcp_buffers: (usize, usize),
+    /// Bytes of the HTTP request buffer of a web task.
+    pub http_buffer: usize,
+    /// Milliseconds between two measurements, `None` for a Thing that does
+    /// not sample.
+    pub sample_interval_ms: Option<u64>,
+}
+
+impl Configuration<'_> {
+    /// The property value: the enabled features by name, the pins as an
+    /// object from role to GPIO.
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        let features: Value = self
+            .features
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect();
+        let pins: Map<_, _> = self
+            .pins
+            .iter()
+            .map(|(role, pin)| ((*role).into(), (*pin).into()))
+            .collect();
+
+        json!({
+            "name": self.name,
+            "version": self.version,
+            "board": self.board,
+            "pins": pins,
+            "features": features,
+            "heapSize": self.heap_size,
+            "webTasks": self.web_tasks,
+            "sockets": self.sockets,
+            "tcpRxBuffer": self.tcp_buffers.0,
+            "tcpTxBuffer": self.tcp_buffers.1,
+            "httpBuffer": self.http_buffer,
+            "sampleInterval": self.sample_interval_ms,
+        })
+    }
+}
@@ -0,0 +1,55 @@
+//! Delivery counts of an SSE stream, see `wot_esp_thing::events`.
+//!
+//! A stream counts the events it sent and those its subscriber dropped for
+//! falling behind, and the most events it saw queued at once, its lag. A
+//! stream ended by the Thing sends them as a last SSE comment, which
+//! `EventSource` ignores; every stream adds them into the totals served at
+//! `/debug/events` when it closes:
+//!
+//! ```text
+//! : delivered=118 dropped=3 maxLag=4
+//! ```
+//!
+//! Drops with a lag near the channel capacity point at a slow consumer; gaps
+//! in the ids a gateway sees with no drops counted point at the network.
+
+use alloc::{format, string::String};
+
+/// The counts of one stream.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Delivery {
+    /// Events written, replayed ones included.
+    pub delivered: u32,
+    /// Events the subscriber fell too far behind to receive.
+    pub dropped: u32,
+    /// Most events queued for the subscriber at once.
+    pub max_lag: u32,
+}
+
+impl Delivery {
+    /// Count an event written.
+    pub fn deliver(&mut self) {
+        self.delivered = self.delivered.saturating_add(1);
+    }
+
+    /// Count `lost` events dropped, as reported by the channel.
+    pub fn drop_events(&mut self, lost: u64) {
+        let lost = u32::try_from(lost).unwrap_or(u32::MAX);
+        self.dropped = self.dropped.saturating_add(lost);
+    }
+
+    /// Note `queued` events waiting for the subscriber.
+    pub fn lag(&mut self, queued: u64) {
+        let queued = u32::try_from(queued).unwrap_or(u32::MAX);
+        self.max_lag = self.max_lag.max(queued);
+    }
+
+    /// The text of the SSE comment closing the stream.
+    #[must_use]
+    pub fn comment(&self) -> String {
+        format!(
+            "delivered={} dropped={} maxLag={}",
+            self.delivered, self.dropped, self.max_lag
+        )
+    }
+}
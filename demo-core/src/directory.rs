@@ -0,0 +1,30 @@
+//! Registration with a Thing Description Directory (WoT Discovery §7.3),
+//! see `wot_esp_thing::directory`.
+
+use alloc::{format, string::String};
+
+use serde_json::Value;
+
+/// The service a directory is advertised as over DNS-SD.
+pub const SERVICE: &str = "_directory._sub._wot._tcp.local";
+
+/// Path of the directory's TD when its `td` TXT entry is missing.
+pub const DEFAULT_TD_PATH: &str = "/.well-known/wot";
+
+/// The base of the directory API, from the TD `directory_td` of the
+/// directory found at `ip:port`: its `base`, else the host itself.
+#[must_use]
+pub fn base(directory_td: &Value, ip: &str, port: u16) -> String {
+    match directory_td.get("base").and_then(Value::as_str) {
+        Some(base) => base.trim_end_matches('/').into(),
+        None => format!("http://{ip}:{port}"),
+    }
+}
+
+/// Where the TD `td` is created or updated on the directory at `base`,
+/// `PUT {base}/things/{id}`; `None` for a TD without an `id`.
+#[must_use]
+pub fn registration_url(base: &str, td: &Value) -> Option<String> {
+    let id = td.get("id")?.as_str()?;
+    Some(format!("{}/things/{id}", base.trim_end_matches('/')))
+}
@@ -0,0 +1,250 @@
+//! DNS-SD browsing over one-shot mDNS queries (RFC 6762 §5.1).
+//!
+//! A query sent from an ephemeral port with the unicast-response bit gets the
+//! answers back to that port, so a plain UDP socket is enough to find the
+//! other instances of a service: every host answering is one of them. The
+//! port and TXT entries of an instance come with the answer as the `SRV` and
+//! `TXT` records of [`instance`].
+//!
+//! Before announcing its names the Thing probes for them (RFC 6762 §8.1):
+//! it asks for them with [`probe_query`], and a response of another host
+//! holding one, see [`claims`], makes it try the next of
+//! [`host_alternative`] or [`instance_alternative`].
+
+use alloc::{format, string::String};
+
+const HEADER_LEN: usize = 12;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+/// Class `IN` with the unicast-response (QU) bit.
+const CLASS_IN_QU: u16 = 0x8001;
+
+fn read_u16(buf: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([*buf.get(at)?, *buf.get(at + 1)?]))
+}
+
+/// Write into `buf` the header of a query with id `id` and `questions`
+/// questions.
+fn write_header(buf: &mut [u8], id: u16, questions: u16) -> Option<()> {
+    let header = buf.get_mut(..HEADER_LEN)?;
+    header.fill(0);
+    header[0..2].copy_from_slice(&id.to_be_bytes());
+    header[4..6].copy_from_slice(&questions.to_be_bytes());
+    Some(())
+}
+
+/// Write into `buf` at `at` a question for `name` of type `ty`, class `IN`
+/// with the unicast-response bit.
+///
+/// Returns the position after it, or `None` when `buf` is too small or a
+/// label of `name` is longer than 63 bytes.
+fn write_question(buf: &mut [u8], mut at: usize, name: &str, ty: u16) -> Option<usize> {
+    for label in name.split('.') {
+        let len = u8::try_from(label.len()).ok().filter(|len| *len <= 63)?;
+        let end = at + 1 + label.len();
+        let field = buf.get_mut(at..end)?;
+        field[0] = len;
+        field[1..].copy_from_slice(label.as_bytes());
+        at = end;
+    }
+    let end = at + 5;
+    let tail = buf.get_mut(at..end)?;
+    tail[0] = 0;
+    tail[1..3].copy_from_slice(&ty.to_be_bytes());
+    tail[3..5].copy_from_slice(&CLASS_IN_QU.to_be_bytes());
+    Some(end)
+}
+
+/// Write into `buf` a `PTR` query with id `id` for the instances of
+/// `service` (e.g. `_wot._tcp.local`).
+///
+/// Returns the query length, or `None` when `buf` is too small or a label of
+/// `service` is longer than 63 bytes.
+#[must_use]
+pub fn ptr_query(id: u16, service: &str, buf: &mut [u8]) -> Option<usize> {
+    write_header(buf, id, 1)?;
+    write_question(buf, HEADER_LEN, service, TYPE_PTR)
+}
+
+/// Write into `buf` a probe for `names`, e.g. `light-1234.local` and
+/// `light._wot._tcp.local`: a query for the records of any type of each.
+///
+/// Returns the query length, or `None` when `buf` is too small or a label is
+/// longer than 63 bytes. The records proposed are left out of the authority
+/// section, so two hosts probing for a name at once both keep it; the
+/// first to announce it makes the other rename when it next restarts.
+#[must_use]
+pub fn probe_query(names: &[&str], buf: &mut [u8]) -> Option<usize> {
+    write_header(buf, 0, u16::try_from(names.len()).ok()?)?;
+    names.iter().try_fold(HEADER_LEN, |at, name| {
+        write_question(buf, at, name, TYPE_ANY)
+    })
+}
+
+/// Whether `response` answers the query `id` with at least one record.
+#[must_use]
+pub fn is_answer(response: &[u8], id: u16) -> bool {
+    let (Some(rid), Some(flags), Some(answers)) = (
+        read_u16(response, 0),
+        read_u16(response, 2),
+        read_u16(response, 6),
+    ) else {
+        return false;
+    };
+    let is_response = flags & 0x8000 != 0;
+    let rcode = flags & 0xf;
+
+    rid == id && is_response && rcode == 0 && answers > 0
+}
+
+/// A service instance, from the records of an answer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instance<'a> {
+    /// The port of the `SRV` record.
+    pub port: u16,
+    /// The value of the `td` TXT entry, the path of the instance's TD in
+    /// WoT Discovery, if any.
+    pub td: Option<&'a str>,
+}
+
+/// The position after the name at `at`, compressed or not.
+fn skip_name(buf: &[u8], mut at: usize) -> Option<usize> {
+    loop {
+        let len = *buf.get(at)?;
+        match len {
+            0 => return Some(at + 1),
+            // A pointer ends the name.
+            len if len & 0xc0 == 0xc0 => return Some(at + 2),
+            len => at += 1 + usize::from(len),
+        }
+    }
+}
+
+/// The value of the entry `key` of the TXT record data `txt`.
+fn txt_value<'a>(mut txt: &'a [u8], key: &str) -> Option<&'a str> {
+    while let Some((&len, rest)) = txt.split_first() {
+        let (entry, next) = rest.split_at_checked(usize::from(len))?;
+        if let Some(value) = core::str::from_utf8(entry)
+            .ok()
+            .and_then(|entry| entry.strip_prefix(key)?.strip_prefix('='))
+        {
+            return Some(value);
+        }
+        txt = next;
+    }
+    None
+}
+
+/// Whether the name at `at` of `buf` is `name`, ignoring ASCII case.
+fn name_is(buf: &[u8], mut at: usize, name: &str) -> Option<bool> {
+    let mut labels = name.split('.');
+    // Bounds the pointers followed, should they loop.
+    for _ in 0..128 {
+        let len = *buf.get(at)?;
+        match len {
+            0 => return Some(labels.next().is_none()),
+            len if len & 0xc0 == 0xc0 => at = usize::from(read_u16(buf, at)? & 0x3fff),
+            len => {
+                let label = buf.get(at + 1..at + 1 + usize::from(len))?;
+                if !labels
+                    .next()
+                    .is_some_and(|expected| expected.as_bytes().eq_ignore_ascii_case(label))
+                {
+                    return Some(false);
+                }
+                at += 1 + usize::from(len);
+            }
+        }
+    }
+    None
+}
+
+/// Whether `response`, from another host, holds a record named `name` in
+/// any section: that host claims the name. Queries claim nothing.
+#[must_use]
+pub fn claims(response: &[u8], name: &str) -> bool {
+    let records = || -> Option<bool> {
+        if read_u16(response, 2)? & 0x8000 == 0 {
+            return Some(false);
+        }
+        let questions = read_u16(response, 4)?;
+        let records = [6, 8, 10]
+            .iter()
+            .map(|at| read_u16(response, *at).map(usize::from))
+            .sum::<Option<usize>>()?;
+
+        let mut at = HEADER_LEN;
+        for _ in 0..questions {
+            at = skip_name(response, at)? + 4;
+        }
+        for _ in 0..records {
+            if name_is(response, at, name)? {
+                return Some(true);
+            }
+            at = skip_name(response, at)?;
+            at += 10 + usize::from(read_u16(response, at + 8)?);
+        }
+        Some(false)
+    };
+    records().unwrap_or(false)
+}
+
+/// The hostname to try after `n - 1` conflicts: `host`, then `host-2`,
+/// `host-3`…
+#[must_use]
+pub fn host_alternative(host: &str, n: u32) -> String {
+    if n <= 1 {
+        host.into()
+    } else {
+        format!("{host}-{n}")
+    }
+}
+
+/// The instance name to try after `n - 1` conflicts: `instance`, then
+/// `instance (2)`, `instance (3)`… as RFC 6763 §4.1 suggests.
+#[must_use]
+pub fn instance_alternative(instance: &str, n: u32) -> String {
+    if n <= 1 {
+        instance.into()
+    } else {
+        format!("{instance} ({n})")
+    }
+}
+
+/// The instance in the answer `response` to the query `id`, from its `SRV`
+/// record and `TXT` record, in any section; `None` without an `SRV` record.
+#[must_use]
+pub fn instance(response: &[u8], id: u16) -> Option<Instance<'_>> {
+    if !is_answer(response, id) {
+        return None;
+    }
+    let questions = read_u16(response, 4)?;
+    let records = [6, 8, 10]
+        .iter()
+        .map(|at| read_u16(response, *at).map(usize::from))
+        .sum::<Option<usize>>()?;
+
+    let mut at = HEADER_LEN;
+    for _ in 0..questions {
+        at = skip_name(response, at)? + 4;
+    }
+
+    let (mut port, mut td) = (None, None);
+    for _ in 0..records {
+        at = skip_name(response, at)?;
+        let ty = read_u16(response, at)?;
+        let len = usize::from(read_u16(response, at + 8)?);
+        let data = response.get(at + 10..at + 10 + len)?;
+        match ty {
+            // Priority and weight come first.
+            TYPE_SRV => port = port.or(read_u16(data, 4)),
+            TYPE_TXT => td = td.or(txt_value(data, "td")),
+            _ => {}
+        }
+        at += 10 + len;
+    }
+
+    Some(Instance { port: port?, td })
+}
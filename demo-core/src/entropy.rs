@@ -0,0 +1,112 @@
+//! Health of the hardware random number generator, checked once at start-up
+//! on a sample before the network stack is seeded from it, see
+//! `wot_esp_thing::entropy`.
+//!
+//! The checks are the monobit and runs tests of FIPS 140-2, scaled down to
+//! the [`SAMPLE_LEN`] bytes of the sample: they catch a generator stuck at
+//! a value or repeating a short pattern, not a subtly biased one.
+
+use serde::Serialize;
+
+/// Bytes drawn for the checks.
+pub const SAMPLE_LEN: usize = 256;
+
+#[allow(clippy::cast_possible_truncation)]
+const BITS: u32 = SAMPLE_LEN as u32 * 8;
+
+/// Most a count of ones, or of runs, may be away from half the bits: four
+/// standard deviations, `sqrt(BITS) / 2` each.
+const MAX_DEVIATION: u32 = 91;
+
+/// Longest run of equal bits allowed, as in FIPS 140-2.
+const MAX_RUN: u32 = 25;
+
+/// Where the seed of the network stack came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeedSource {
+    /// The hardware generator, with the radio on and the checks passed.
+    Hardware,
+    /// [`fallback_seed`], the checks having failed.
+    Fallback,
+}
+
+impl SeedSource {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Hardware => "hardware",
+            Self::Fallback => "fallback",
+        }
+    }
+}
+
+/// The outcome of the checks on a sample.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Health {
+    pub passed: bool,
+    /// Bits set, about half of them.
+    pub ones: u32,
+    /// Runs of equal bits, about half the bits too.
+    pub runs: u32,
+    pub longest_run: u32,
+}
+
+/// Check `sample`, of [`SAMPLE_LEN`] bytes.
+#[must_use]
+pub fn check(sample: &[u8; SAMPLE_LEN]) -> Health {
+    let ones = sample.iter().map(|byte| byte.count_ones()).sum();
+
+    let mut runs = 0;
+    let mut longest_run = 0;
+    let mut run = 0;
+    let mut last = None;
+    for bit in sample
+        .iter()
+        .flat_map(|byte| (0..8).map(move |i| (byte >> (7 - i)) & 1))
+    {
+        if last == Some(bit) {
+            run += 1;
+        } else {
+            runs += 1;
+            run = 1;
+            last = Some(bit);
+        }
+        longest_run = longest_run.max(run);
+    }
+
+    let balanced = |count: u32| count.abs_diff(BITS / 2) <= MAX_DEVIATION;
+    Health {
+        passed: balanced(ones) && balanced(runs) && longest_run <= MAX_RUN,
+        ones,
+        runs,
+        longest_run,
+    }
+}
+
+/// A seed for when the generator fails its checks: the random bytes baked
+/// in at build time, the MAC address and the uptime in ticks, mixed. It
+/// differs between boards and boots, but is no secret.
+#[must_use]
+pub fn fallback_seed(build: &[u8], mac: [u8; 6], ticks: u64) -> u64 {
+    let word = |bytes: &[u8]| {
+        let mut word = [0; 8];
+        word[..bytes.len()].copy_from_slice(bytes);
+        u64::from_le_bytes(word)
+    };
+
+    build
+        .chunks(8)
+        .map(word)
+        .chain([word(&mac), ticks])
+        .fold(0, |seed, word| splitmix64(seed ^ word))
+}
+
+/// The finalizer of the splitmix generator, spreading every bit of `x` over
+/// the result.
+const fn splitmix64(x: u64) -> u64 {
+    let x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
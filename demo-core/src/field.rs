@@ -0,0 +1,98 @@
+//! Single-member reads of object-valued properties, with `?field=`.
+//!
+//! `GET /properties/color?field=r` answers `128` instead of the whole
+//! `{"r":128,"g":0,"b":255}`, for consumers on a slow link. Only top-level
+//! members are reachable; an unknown one gets a 404 with [`not_found`].
+
+use alloc::{string::String, vec::Vec};
+
+use serde_json::{json, Map, Value};
+
+/// Name of the query parameter and of the URI variable in the TD.
+pub const FIELD: &str = "field";
+
+/// The `field` parameter of the query string `query`, e.g. `r` for
+/// `field=r&x=1`, the first one when repeated.
+///
+/// The value is taken as is: member names need no percent-encoding.
+#[must_use]
+pub fn from_query(query: &str) -> Option<&str> {
+    query.split('&').find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        (name == FIELD).then_some(value)
+    })
+}
+
+/// The member `field` of the object `value`, `None` when it has none or is
+/// not an object.
+#[must_use]
+pub fn project(value: Value, field: &str) -> Option<Value> {
+    match value {
+        Value::Object(mut members) => members.remove(field),
+        _ => None,
+    }
+}
+
+/// The body of the 404 for the unknown member `field`, e.g.
+/// `{"error":"no such member","field":"x"}`.
+#[must_use]
+pub fn not_found(field: &str) -> Value {
+    json!({ "error": "no such member", FIELD: field })
+}
+
+/// Advertise `?field=` on the `properties` of the serialized TD `td` that
+/// declare their members: each gets a `field` URI variable listing them and
+/// a read form with the `{?field}` template. The other forms are left as
+/// they are, so a consumer ignoring the template still reads the whole
+/// object.
+///
+/// # Errors
+///
+/// Returns an error if `td` is not a JSON object.
+pub fn advertise(td: &str, properties: &[&str]) -> Result<String, serde_json::Error> {
+    let mut td: Map<String, Value> = serde_json::from_str(td)?;
+
+    let affordances = td.get_mut("properties").and_then(Value::as_object_mut);
+    for affordance in affordances.into_iter().flat_map(|all| {
+        all.iter_mut()
+            .filter(|(name, _)| properties.contains(&name.as_str()))
+            .filter_map(|(_, affordance)| affordance.as_object_mut())
+    }) {
+        let members: Vec<Value> = match affordance.get("properties").and_then(Value::as_object) {
+            Some(members) if !members.is_empty() => {
+                members.keys().map(|name| name.as_str().into()).collect()
+            }
+            _ => continue,
+        };
+        let Some(href) = affordance
+            .get("forms")
+            .and_then(Value::as_array)
+            .and_then(|forms| forms.first())
+            .and_then(|form| form.get("href"))
+            .and_then(Value::as_str)
+            .map(String::from)
+        else {
+            continue;
+        };
+
+        affordance.insert(
+            "uriVariables".into(),
+            json!({
+                FIELD: {
+                    "type": "string",
+                    "description": "Read only this member of the object",
+                    "enum": members,
+                },
+            }),
+        );
+        if let Some(Value::Array(forms)) = affordance.get_mut("forms") {
+            forms.push(json!({
+                "href": href + "{?field}",
+                "op": ["readproperty"],
+                "contentType": "application/json",
+            }));
+        }
+    }
+
+    serde_json::to_string(&td)
+}
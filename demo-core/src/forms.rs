@@ -0,0 +1,36 @@
+//! Forms shared by the Thing Descriptions.
+
+use alloc::string::String;
+
+use wot_td::{builder::FormBuilder, hlist::Nil, thing::FormOperation};
+
+/// Form for an event streamed over Server-Sent Events at `href`.
+///
+/// Use inside `.event(name, |b| b.form(|f| sse_event_form(f, href)))`.
+#[must_use]
+pub fn sse_event_form(
+    f: FormBuilder<Nil, (), Nil>,
+    href: &'static str,
+) -> FormBuilder<Nil, String, Nil> {
+    f.href(href)
+        .op(FormOperation::SubscribeEvent)
+        .op(FormOperation::UnsubscribeEvent)
+        .subprotocol("sse")
+        .content_type("text/event-stream")
+}
+
+/// Additional form observing a property through the SSE stream at `href`.
+///
+/// Pair it with `.observable(true)` on the property; the stream is the same one
+/// advertised by the matching event, which is kept for spec compliance.
+#[must_use]
+pub fn sse_observe_form(
+    f: FormBuilder<Nil, (), Nil>,
+    href: &'static str,
+) -> FormBuilder<Nil, String, Nil> {
+    f.href(href)
+        .op(FormOperation::ObserveProperty)
+        .op(FormOperation::UnobserveProperty)
+        .subprotocol("sse")
+        .content_type("text/event-stream")
+}
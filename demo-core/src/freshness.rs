@@ -0,0 +1,34 @@
+//! How old a cached sensor value is, and whether it may still be served,
+//! see `wot_esp_thing::Cached`.
+//!
+//! A value younger than its bound is served with its age in whole seconds,
+//! as the HTTP `Age` header. An older one means the task sampling it is
+//! wedged: it is answered with 503 and a `Retry-After` of the bound, rather
+//! than served as if it were current.
+
+/// Whether a cached value may be served.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Freshness {
+    /// Served, with `Age: age_s`.
+    Fresh { age_s: u64 },
+    /// Older than its bound, answered with 503 and `Retry-After: retry_after_s`.
+    Stale { age_s: u64, retry_after_s: u64 },
+}
+
+impl Freshness {
+    /// The freshness of a value captured `age_ms` ago and served for up to
+    /// `max_age_ms`.
+    #[must_use]
+    pub const fn of(age_ms: u64, max_age_ms: u64) -> Self {
+        if age_ms <= max_age_ms {
+            return Self::Fresh {
+                age_s: age_ms / 1000,
+            };
+        }
+        let retry_after_s = max_age_ms.div_ceil(1000);
+        Self::Stale {
+            age_s: age_ms / 1000,
+            retry_after_s: if retry_after_s == 0 { 1 } else { retry_after_s },
+        }
+    }
+}
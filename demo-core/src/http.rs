@@ -0,0 +1,36 @@
+//! Parsing of the URLs and HTTP/1.1 responses of the outbound requests.
+
+/// The status code and body of the complete `response`.
+///
+/// Returns `None` until the head, up to the empty line, has been read.
+#[must_use]
+pub fn parse_response(response: &[u8]) -> Option<(u16, &[u8])> {
+    let head_len = response.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let status_line = response[..head_len].split(|b| *b == b'\r').next()?;
+
+    let mut parts = status_line.split(|b| *b == b' ');
+    if !parts.next()?.starts_with(b"HTTP/1.") {
+        return None;
+    }
+    let status = core::str::from_utf8(parts.next()?).ok()?.parse().ok()?;
+
+    Some((status, &response[head_len + 4..]))
+}
+
+/// The host, port and path of the `http://` URL `url`.
+///
+/// The port defaults to 80 and the path to `/`; `None` for other schemes.
+#[must_use]
+pub fn parse_url(url: &str) -> Option<(&str, u16, &str)> {
+    let rest = url.strip_prefix("http://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(at) => rest.split_at(at),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (authority, 80),
+    };
+
+    (!host.is_empty()).then_some((host, port, path))
+}
@@ -0,0 +1,47 @@
+//! The addresses answering on an I2C bus, see `wot_esp_thing::i2c_scan`.
+//!
+//! Every 7-bit address outside the reserved ones, [`FIRST`] to [`LAST`], is
+//! probed with a zero-length write; those acknowledging it are listed in
+//! hex, e.g. an SHT4x and an SHTC3:
+//!
+//! ```json
+//! {"addresses":["0x44","0x70"]}
+//! ```
+
+use alloc::{format, vec::Vec};
+
+use serde_json::{json, Value};
+
+/// First address probed: 0x00 to 0x07 are reserved.
+pub const FIRST: u8 = 0x08;
+
+/// Last address probed: 0x78 to 0x7f are reserved.
+pub const LAST: u8 = 0x77;
+
+/// Shortest time between two scans, in milliseconds, so that the sensors
+/// are not disturbed mid-measurement over and over.
+pub const SCAN_INTERVAL_MS: u64 = 5000;
+
+/// The addresses from [`FIRST`] to [`LAST`] for which `acks` is true, in
+/// order.
+pub fn scan(mut acks: impl FnMut(u8) -> bool) -> Vec<u8> {
+    (FIRST..=LAST).filter(|&address| acks(address)).collect()
+}
+
+/// The body of the response listing `found`.
+#[must_use]
+pub fn to_json(found: &[u8]) -> Value {
+    let addresses: Vec<_> = found
+        .iter()
+        .map(|address| format!("0x{address:02x}"))
+        .collect();
+    json!({ "addresses": addresses })
+}
+
+/// Seconds to wait, rounded up, before a scan at `now_ms` may follow the
+/// one at `last_ms`; `None` if it may run now.
+#[must_use]
+pub fn retry_after_s(last_ms: Option<u64>, now_ms: u64) -> Option<u64> {
+    let next = last_ms? + SCAN_INTERVAL_MS;
+    (now_ms < next).then(|| (next - now_ms).div_ceil(1000))
+}
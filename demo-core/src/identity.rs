@@ -0,0 +1,110 @@
+//! The title and location a device is given at run time, see
+//! `wot_esp_thing::identity`.
+
+use alloc::{format, string::String};
+
+use serde_json::{json, Map, Value};
+
+/// Longest title or location, in bytes.
+pub const MAX_LEN: usize = 32;
+
+/// A title, e.g. `Greenhouse North`, and an optional location.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Identity {
+    pub title: String,
+    pub location: Option<String>,
+}
+
+impl Identity {
+    /// Parse `{"title":"Greenhouse North","location":"north"}`; `location`
+    /// may be left out or `null`.
+    ///
+    /// # Errors
+    ///
+    /// Why `body` is not a valid identity.
+    pub fn parse(body: &Value) -> Result<Self, &'static str> {
+        let title = body
+            .get("title")
+            .and_then(Value::as_str)
+            .ok_or("title must be a string")?;
+        let location = match body.get("location") {
+            None | Some(Value::Null) => None,
+            Some(Value::String(location)) => Some(location.as_str()),
+            Some(_) => return Err("location must be a string or null"),
+        };
+
+        check(title)?;
+        location.map(check).transpose()?;
+
+        Ok(Self {
+            title: title.trim().into(),
+            location: location.map(|location| location.trim().into()),
+        })
+    }
+
+    /// The DNS-SD instance name: the title, then the location after a `-`,
+    /// see [`instance_label`].
+    #[must_use]
+    pub fn instance_name(&self) -> String {
+        match &self.location {
+            Some(location) => format!(
+                "{}-{}",
+                instance_label(&self.title),
+                instance_label(location)
+            ),
+            None => instance_label(&self.title),
+        }
+    }
+
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        json!({ "title": self.title, "location": self.location })
+    }
+}
+
+/// Whether `value` is a usable title or location.
+fn check(value: &str) -> Result<(), &'static str> {
+    if value.trim().is_empty() {
+        Err("title and location must not be empty")
+    } else if value.len() > MAX_LEN {
+        Err("title and location must be at most 32 bytes")
+    } else if value.chars().any(char::is_control) {
+        Err("title and location must not hold control characters")
+    } else {
+        Ok(())
+    }
+}
+
+/// `value` as part of a DNS-SD instance name, without the control
+/// characters an instance name cannot hold and the `.` and `\` that would
+/// need escaping, which not every browser does.
+#[must_use]
+pub fn instance_label(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !c.is_control() && *c != '.' && *c != '\\')
+        .collect()
+}
+
+/// The TD title of a Thing titled `title` and announced over mDNS as
+/// `instance`: the title, then the instance in parentheses unless it is the
+/// title, so that what a browser lists can be told apart in the TDs.
+#[must_use]
+pub fn titled(title: &str, instance: &str) -> String {
+    if instance.is_empty() || instance == title {
+        title.into()
+    } else {
+        format!("{title} ({instance})")
+    }
+}
+
+/// Set the `title` of the serialized TD `td`.
+///
+/// # Errors
+///
+/// Returns an error if `td` is not a JSON object.
+pub fn retitle_json(td: &str, title: &str) -> Result<String, serde_json::Error> {
+    let mut td: Map<String, Value> = serde_json::from_str(td)?;
+    td.insert("title".into(), Value::String(title.into()));
+    serde_json::to_string(&td)
+}
@@ -0,0 +1,18 @@
+//! Property values serialized into a fixed-size buffer, so that reading one
+//! allocates nothing; see `wot_esp_thing::InlineJson`.
+//!
+//! Numbers go through `ryu` and `itoa` as in `serde_json`, and the JSON
+//! text is the same, e.g. `21.5`, `true` or `{"r":255,"g":0,"b":0}`. A value
+//! that does not fit is left to `serde_json` on the heap.
+
+use serde::Serialize;
+
+/// Bytes of a value serialized inline: any scalar, and the small objects
+/// such as a color or a reading.
+pub const INLINE_LEN: usize = 128;
+
+/// Serialize `value` as JSON into `buf`, returning the length written;
+/// `None` if it does not fit.
+pub fn serialize<T: Serialize + ?Sized>(value: &T, buf: &mut [u8]) -> Option<usize> {
+    serde_json_core::to_slice(value, buf).ok()
+}
@@ -0,0 +1,49 @@
+//! SHA-256 of the served TD, so a consumer can tell a tampered or spoofed
+//! TD from the one the Thing serves, see `wot_esp_thing::td`.
+//!
+//! The digest covers the bytes of the TD as served at `/`, uncompressed and
+//! without rebasing. The full digest is served in hex at
+//! `/.well-known/wot.sha256`; mDNS carries its first [`TXT_DIGITS`] hex
+//! digits under [`TXT_KEY`], which is enough to cross-check the two. The
+//! same digits make the ETag of the TD, see [`etag`].
+
+use alloc::{format, string::String};
+use core::fmt::Write as _;
+
+use sha2::{Digest, Sha256};
+
+/// Key of the TXT entry carrying the digest.
+pub const TXT_KEY: &str = "tdhash";
+
+/// Hex digits of the digest in the TXT entry, 64 of its 256 bits.
+pub const TXT_DIGITS: usize = 16;
+
+/// The SHA-256 of `td`.
+#[must_use]
+pub fn sha256(td: &str) -> [u8; 32] {
+    Sha256::digest(td.as_bytes()).into()
+}
+
+/// The strong ETag of the TD of `digest`, e.g. `"3f2a9c01d4b7e688"`, with
+/// `-gz` before the closing quote for its gzipped copy.
+#[must_use]
+pub fn etag(digest: &[u8; 32], gzip: bool) -> String {
+    format!("\"{}{}\"", txt(digest), if gzip { "-gz" } else { "" })
+}
+
+/// `bytes` in lowercase hex.
+#[must_use]
+pub fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(2 * bytes.len()), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+/// The value of the TXT entry for `digest`.
+#[must_use]
+pub fn txt(digest: &[u8; 32]) -> String {
+    hex(&digest[..TXT_DIGITS / 2])
+}
@@ -0,0 +1,56 @@
+//! Buckets and route classes of the request latency histogram, see
+//! `wot_esp_thing::latency`.
+
+/// Upper bounds of the buckets but the last, in microseconds; the last
+/// bucket takes everything slower.
+pub const BUCKET_BOUNDS_US: [u64; 5] = [1_000, 5_000, 20_000, 100_000, 500_000];
+
+/// Buckets of the histogram.
+pub const BUCKETS: usize = BUCKET_BOUNDS_US.len() + 1;
+
+/// Name of each bucket in the JSON report.
+pub const BUCKET_NAMES: [&str; BUCKETS] =
+    ["le1ms", "le5ms", "le20ms", "le100ms", "le500ms", "gt500ms"];
+
+/// The bucket of a request that took `us` microseconds.
+#[must_use]
+pub fn bucket(us: u64) -> usize {
+    BUCKET_BOUNDS_US
+        .iter()
+        .position(|&bound| us <= bound)
+        .unwrap_or(BUCKET_BOUNDS_US.len())
+}
+
+/// The kinds of request timed, each with its own histogram.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RouteClass {
+    Td,
+    PropertyRead,
+    PropertyWrite,
+}
+
+impl RouteClass {
+    pub const ALL: [Self; 3] = [Self::Td, Self::PropertyRead, Self::PropertyWrite];
+
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Td => "td",
+            Self::PropertyRead => "propertyRead",
+            Self::PropertyWrite => "propertyWrite",
+        }
+    }
+
+    /// The class of a `method` request to `path`, `None` for requests not
+    /// timed: actions, event streams, which last as long as the client
+    /// listens, and the debug routes.
+    #[must_use]
+    pub fn of(method: &str, path: &str) -> Option<Self> {
+        match (method, path) {
+            ("GET", "/") => Some(Self::Td),
+            ("GET", path) if path.starts_with("/properties/") => Some(Self::PropertyRead),
+            ("PUT", path) if path.starts_with("/properties/") => Some(Self::PropertyWrite),
+            _ => None,
+        }
+    }
+}
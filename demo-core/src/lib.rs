@@ -0,0 +1,61 @@
+//! The parts of the demos that do not touch the hardware.
+//!
+//! Thing Description builders, content negotiation, naming and the other
+//! pure helpers live here, free of `esp-hal`, so they build and are tested on
+//! the host:
+//!
+//! ```sh
+//! cargo test --target x86_64-unknown-linux-gnu -p wot-esp-demo-core
+//! ```
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod affordance_stats;
+pub mod alarm;
+pub mod batch;
+pub mod body;
+pub mod boot_status;
+pub mod brownout;
+pub mod capabilities;
+pub mod captive_dns;
+pub mod cbor;
+pub mod config;
+pub mod configuration;
+pub mod delivery;
+pub mod directory;
+pub mod dns_sd;
+pub mod entropy;
+pub mod field;
+pub mod forms;
+pub mod freshness;
+pub mod http;
+pub mod i2c_scan;
+pub mod identity;
+pub mod inline;
+pub mod integrity;
+pub mod latency;
+pub mod link_format;
+pub mod low_power;
+pub mod names;
+pub mod negotiate;
+pub mod origin;
+pub mod peers;
+pub mod prefix;
+pub mod resolve;
+pub mod rules;
+pub mod schema;
+pub mod self_test;
+pub mod shutdown;
+pub mod smartled;
+pub mod sntp;
+pub mod sse;
+pub mod store;
+pub mod tasks;
+pub mod td;
+pub mod things;
+pub mod tm;
+pub mod udp;
+pub mod wear;
+pub mod wifi;
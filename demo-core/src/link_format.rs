@@ -0,0 +1,22 @@
+//! CoRE Link Format (RFC 6690) links.
+
+use alloc::{format, string::String};
+
+/// CoAP Content-Format number of `application/td+json`.
+pub const TD_CONTENT_FORMAT: u16 = 432;
+
+/// Media type of a link-format document, answered with 406 to a client not
+/// accepting it.
+pub const CONTENT_TYPE: &str = "application/link-format";
+
+/// The body of `/.well-known/core`: the TD as a `wot.thing` resource.
+#[must_use]
+pub fn well_known_core() -> String {
+    link("/.well-known/wot", "wot.thing", TD_CONTENT_FORMAT)
+}
+
+/// A single link as `<href>;rt="rt";ct=ct`.
+#[must_use]
+pub fn link(href: &str, resource_type: &str, content_format: u16) -> String {
+    format!("<{href}>;rt=\"{resource_type}\";ct={content_format}")
+}
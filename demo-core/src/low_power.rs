@@ -0,0 +1,142 @@
+//! The energy modes of a battery-powered Thing, see
+//! `wot_esp_thing::low_power`.
+//!
+//! In the `batched` mode the Thing does not serve anything: it wakes up
+//! every few minutes, pushes one reading to a webhook and deep-sleeps again.
+
+use alloc::string::String;
+
+use serde_json::{json, Map, Value};
+
+/// The `energyMode` property.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EnergyMode {
+    /// Serve the Thing, events included, until the next reset.
+    #[default]
+    Continuous,
+    /// Push a reading on each wake-up, then deep-sleep.
+    Batched,
+}
+
+/// Marks a word of RTC memory as holding an [`EnergyMode`], rather than
+/// what it held at power-on.
+const MODE_MAGIC: u32 = 0x454E_0000;
+
+impl EnergyMode {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Continuous => "continuous",
+            Self::Batched => "batched",
+        }
+    }
+
+    /// The mode named `name`, `None` for an unknown one.
+    #[must_use]
+    pub fn parse(name: &str) -> Option<Self> {
+        [Self::Continuous, Self::Batched]
+            .into_iter()
+            .find(|mode| mode.as_str() == name)
+    }
+
+    /// The word kept in RTC memory across deep sleep.
+    #[must_use]
+    pub fn to_word(self) -> u32 {
+        MODE_MAGIC | self as u32
+    }
+
+    /// The mode stored as `word` by [`Self::to_word`], `None` for anything
+    /// else, e.g. the content of RTC memory after a power cycle.
+    #[must_use]
+    pub fn from_word(word: u32) -> Option<Self> {
+        match word ^ MODE_MAGIC {
+            0 => Some(Self::Continuous),
+            1 => Some(Self::Batched),
+            _ => None,
+        }
+    }
+}
+
+/// Shortest time between two wake-ups, in minutes.
+pub const MIN_WAKE_INTERVAL_MIN: u32 = 1;
+
+/// Longest time between two wake-ups, in minutes: a day.
+pub const MAX_WAKE_INTERVAL_MIN: u32 = 24 * 60;
+
+/// Time between two wake-ups unless set, in minutes.
+pub const DEFAULT_WAKE_INTERVAL_MIN: u32 = 10;
+
+/// The wake interval in minutes of the `WAKE_INTERVAL_MIN` build-time
+/// setting, clamped to the range above and the default when unset or not a
+/// number.
+#[must_use]
+pub fn wake_interval_min(setting: Option<&str>) -> u32 {
+    setting
+        .and_then(|minutes| minutes.trim().parse().ok())
+        .map_or(DEFAULT_WAKE_INTERVAL_MIN, |minutes: u32| {
+            minutes.clamp(MIN_WAKE_INTERVAL_MIN, MAX_WAKE_INTERVAL_MIN)
+        })
+}
+
+/// Ratio of the divider between the battery and the ADC pin: two equal
+/// resistors halve the voltage into the range of the ADC.
+pub const BATTERY_DIVIDER: u32 = 2;
+
+/// Battery voltage in millivolts of `adc_mv` read on the ADC pin.
+#[must_use]
+pub fn battery_mv(adc_mv: u16) -> u32 {
+    u32::from(adc_mv) * BATTERY_DIVIDER
+}
+
+/// The body pushed to the webhook: the Thing `id`, the members of
+/// `reading` and the battery voltage, e.g.
+/// `{"id":"urn:uuid:..","temperature":21.5,"humidity":40.2,"battery_mV":3712}`.
+///
+/// A `reading` that is not an object is pushed as its `reading` member.
+#[must_use]
+pub fn payload(id: &str, reading: Option<Value>, battery_mv: Option<u32>) -> Value {
+    let mut payload = Map::new();
+    payload.insert("id".into(), id.into());
+    match reading {
+        Some(Value::Object(reading)) => payload.extend(reading),
+        Some(reading) => {
+            payload.insert("reading".into(), reading);
+        }
+        None => {}
+    }
+    if let Some(mv) = battery_mv {
+        payload.insert("battery_mV".into(), mv.into());
+    }
+    Value::Object(payload)
+}
+
+/// Add the `energyMode` property to the serialized TD `td`.
+///
+/// # Errors
+///
+/// Returns an error if `td` is not a JSON object.
+pub fn add_energy_mode(td: &str) -> Result<String, serde_json::Error> {
+    let mut td: Map<String, Value> = serde_json::from_str(td)?;
+
+    let energy_mode = json!({
+        "title": "Energy mode",
+        "description": "continuous serves the Thing; batched pushes a reading to the webhook \
+                        on each wake-up and deep-sleeps in between, until the stay-awake pin \
+                        is held at boot",
+        "type": "string",
+        "enum": ["continuous", "batched"],
+        "forms": [{
+            "href": "/properties/energyMode",
+            "op": ["readproperty", "writeproperty"],
+            "contentType": "application/json",
+        }],
+    });
+
+    if let Value::Object(properties) = td
+        .entry("properties")
+        .or_insert_with(|| Value::Object(Map::new()))
+    {
+        properties.insert("energyMode".into(), energy_mode);
+    }
+    serde_json::to_string(&td)
+}
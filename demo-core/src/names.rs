@@ -0,0 +1,49 @@
+//! Names the Thing is known by on the network.
+
+use core::fmt::Write;
+
+use alloc::{format, string::String};
+
+/// The mDNS hostname of the Thing `name` with the hardware address `hw`.
+///
+/// The suffix is the last four address bytes, last first, in decimal.
+#[must_use]
+pub fn hostname(name: &str, hw: &[u8]) -> String {
+    let len = hw.len();
+
+    format!(
+        "{name}-{}{}{}{}",
+        hw[len - 1],
+        hw[len - 2],
+        hw[len - 3],
+        hw[len - 4]
+    )
+}
+
+/// The id of the Thing `name` without a random UUID, from its hardware
+/// address `hw`: `urn:dev:mac:{hex}` as in RFC 9039, or
+/// `urn:{org}:{name}:{hex}` in the namespace `org` of `URN_ORG`.
+#[must_use]
+pub fn device_urn(org: Option<&str>, name: &str, hw: &[u8]) -> String {
+    let hex = hw.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    });
+    match org {
+        Some(org) => format!("urn:{org}:{name}:{hex}"),
+        None => format!("urn:dev:mac:{hex}"),
+    }
+}
+
+/// The hardware address `hw` as colon-separated lowercase hex.
+#[must_use]
+pub fn mac(hw: &[u8]) -> String {
+    let mut mac = String::with_capacity(hw.len() * 3);
+    for (i, byte) in hw.iter().enumerate() {
+        if i > 0 {
+            mac.push(':');
+        }
+        let _ = write!(mac, "{byte:02x}");
+    }
+    mac
+}
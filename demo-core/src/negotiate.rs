@@ -0,0 +1,154 @@
+//! `Accept`, `Accept-Encoding` and `If-None-Match` request header matching.
+
+use alloc::vec::Vec;
+
+use serde::Serialize;
+
+use crate::{cbor, inline};
+
+/// Media type of a JSON body.
+pub const JSON: &str = "application/json";
+
+/// Whether the `Accept` header value `header` allows responding with `media_type`.
+///
+/// Handles `*/*` and `type/*` ranges and treats `q=0` as an explicit refusal.
+/// An empty header accepts anything.
+#[must_use]
+pub fn accepts(header: &str, media_type: &str) -> bool {
+    if header.trim().is_empty() {
+        return true;
+    }
+
+    let main_type = media_type.split('/').next().unwrap_or(media_type);
+
+    header.split(',').any(|range| {
+        let mut params = range.split(';');
+        let range = params.next().unwrap_or("").trim();
+        let refused = params.any(|p| {
+            p.trim()
+                .strip_prefix("q=")
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .is_some_and(|q| q == 0.0)
+        });
+
+        !refused
+            && (range == "*/*"
+                || range.eq_ignore_ascii_case(media_type)
+                || range
+                    .strip_suffix("/*")
+                    .is_some_and(|t| t.eq_ignore_ascii_case(main_type)))
+    })
+}
+
+/// Whether the `Accept` header value `header` prefers `media_type` to
+/// `other`, e.g. `application/cbor` to `application/json` for
+/// `application/cbor, application/json;q=0.5`.
+///
+/// Each media type weighs the `q` of the most specific range matching it. On
+/// equal weights the one named by the more specific range wins, so
+/// `application/cbor, */*` prefers CBOR; `*/*` alone, or an empty header,
+/// prefers neither.
+#[must_use]
+pub fn prefers(header: &str, media_type: &str, other: &str) -> bool {
+    let (q, specificity) = weight(header, media_type);
+    let (other_q, other_specificity) = weight(header, other);
+    q > 0.0 && (q, specificity) > (other_q, other_specificity)
+}
+
+/// The `q` of `media_type` in `header` and how specific the range giving it
+/// is: 3 for the media type itself, 2 for `type/*`, 1 for `*/*`, 0 when no
+/// range matches (then `q` is 0).
+fn weight(header: &str, media_type: &str) -> (f32, u8) {
+    let main_type = media_type.split('/').next().unwrap_or(media_type);
+
+    let mut weight = (0.0, 0);
+    for range in header.split(',') {
+        let mut params = range.split(';');
+        let range = params.next().unwrap_or("").trim();
+        let specificity = if range.eq_ignore_ascii_case(media_type) {
+            3
+        } else if range
+            .strip_suffix("/*")
+            .is_some_and(|t| t.eq_ignore_ascii_case(main_type))
+        {
+            2
+        } else if range == "*/*" {
+            1
+        } else {
+            continue;
+        };
+        let q = params
+            .find_map(|p| p.trim().strip_prefix("q="))
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if specificity > weight.1 {
+            weight = (q, specificity);
+        }
+    }
+    weight
+}
+
+/// Whether the `Accept-Encoding` header value `header` allows `coding`.
+///
+/// A `q=0` parameter is an explicit refusal; `*` matches any coding.
+#[must_use]
+pub fn accepts_encoding(header: &str, coding: &str) -> bool {
+    header.split(',').any(|item| {
+        let mut params = item.split(';');
+        let name = params.next().unwrap_or("").trim();
+        let refused = params.any(|p| {
+            p.trim()
+                .strip_prefix("q=")
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .is_some_and(|q| q == 0.0)
+        });
+
+        !refused && (name == "*" || name.eq_ignore_ascii_case(coding))
+    })
+}
+
+/// Whether the `If-None-Match` header value `header` lists `etag`, so the
+/// client's copy is current.
+///
+/// Compares weakly, as the header requires: a `W/` prefix is ignored on both
+/// sides. `*` matches any tag.
+#[must_use]
+pub fn etag_matches(header: &str, etag: &str) -> bool {
+    fn opaque(tag: &str) -> &str {
+        let tag = tag.trim();
+        tag.strip_prefix("W/").unwrap_or(tag)
+    }
+    let etag = opaque(etag);
+
+    header.trim() == "*" || header.split(',').any(|tag| opaque(tag) == etag)
+}
+
+/// The body of a property value for the `Accept` header `header`, with its
+/// media type: CBOR when the client prefers it, JSON when it accepts it, as
+/// `wot_esp_thing::respond_negotiated`. `Err` holds the media type to name
+/// in the HTTP 406 the client gets otherwise.
+///
+/// The JSON is the text of [`inline::serialize`], the one the boards send
+/// for a value fitting [`inline::INLINE_LEN`].
+///
+/// # Panics
+///
+/// Panics if `value` cannot be serialized.
+pub fn property<T: Serialize + ?Sized>(
+    header: &str,
+    value: &T,
+) -> Result<(&'static str, Vec<u8>), &'static str> {
+    if prefers(header, cbor::CONTENT_TYPE, JSON) {
+        return Ok((cbor::CONTENT_TYPE, cbor::to_vec(value).unwrap()));
+    }
+    if !accepts(header, JSON) {
+        return Err(JSON);
+    }
+
+    let mut buf = [0; inline::INLINE_LEN];
+    let body = match inline::serialize(value, &mut buf) {
+        Some(len) => buf[..len].to_vec(),
+        None => serde_json::to_vec(value).unwrap(),
+    };
+    Ok((JSON, body))
+}
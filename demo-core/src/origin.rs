@@ -0,0 +1,184 @@
+//! Which web pages may write to the Thing, see `wot_esp_thing::origin`.
+//!
+//! Any page a browser visits can have it send a write to the Thing, e.g. a
+//! `fetch` to `http://light.local/properties/on`, also through DNS
+//! rebinding. The browser tags such a request with the `Origin` of the
+//! page, or at least a `Referer`: a write from a page on another host than
+//! the Thing's own, or one allowed with `WRITE_ORIGINS`, is refused unless
+//! it carries the `WRITE_TOKEN` bearer token. A request with neither header
+//! does not come from a page, e.g. curl or a native consumer, and passes.
+//!
+//! Hosts are compared without case or trailing dot, IPv6 literals as
+//! addresses. An entry without a port allows any port, one with a port only
+//! that one, the origin's port defaulting to that of its scheme:
+//!
+//! ```text
+//! light.local        allows http://light.local and http://light.local:8080
+//! 192.0.2.7:8080     allows http://192.0.2.7:8080, not http://192.0.2.7
+//! [fe80::1]          allows http://[fe80:0:0::1]:8081
+//! ```
+
+use alloc::{format, string::String};
+use core::net::Ipv6Addr;
+
+/// The `WRITE_ORIGINS` entry allowing every page, which turns the check off.
+pub const ANY: &str = "*";
+
+/// Who may write.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Policy<'a> {
+    /// Hosts of the Thing, on any port: its address, mDNS name and public
+    /// base.
+    pub own: &'a [&'a str],
+    /// The `WRITE_ORIGINS` entries, `host`, `host:port` or [`ANY`].
+    pub allowed: &'a [&'a str],
+    /// The `WRITE_TOKEN` letting any write through, if set.
+    pub token: Option<&'a str>,
+}
+
+/// The headers of a write the check reads.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Headers<'a> {
+    pub origin: Option<&'a str>,
+    pub referer: Option<&'a str>,
+    pub authorization: Option<&'a str>,
+}
+
+/// Check the write with `headers` against `policy`.
+///
+/// # Errors
+///
+/// Returns the `Origin`, or the `Referer`, of a write refused.
+pub fn check<'a>(policy: &Policy<'_>, headers: &Headers<'a>) -> Result<(), &'a str> {
+    if policy.allowed.contains(&ANY) || bearer(headers.authorization, policy.token) {
+        return Ok(());
+    }
+    let Some(source) = headers.origin.or(headers.referer) else {
+        return Ok(());
+    };
+
+    if policy
+        .own
+        .iter()
+        .chain(policy.allowed)
+        .any(|entry| matches(source, entry))
+    {
+        Ok(())
+    } else {
+        Err(source)
+    }
+}
+
+/// Whether the `Authorization` header `authorization` carries `token`,
+/// never without a token.
+#[must_use]
+pub fn bearer(authorization: Option<&str>, token: Option<&str>) -> bool {
+    let (Some(authorization), Some(token)) = (authorization, token) else {
+        return false;
+    };
+    let Some((scheme, presented)) = authorization.trim().split_once(' ') else {
+        return false;
+    };
+    let presented = presented.trim();
+
+    // Compared in full whatever the first difference, to not leak it in the timing.
+    scheme.eq_ignore_ascii_case("Bearer")
+        && presented.len() == token.len()
+        && presented
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// The `WWW-Authenticate` header of HTTP 401, see [`unauthorized`].
+pub const CHALLENGE: &str = "Bearer";
+
+/// The body of HTTP 401 refusing a request without `token` to a route open
+/// to the token holder only, e.g. `PUT /debug/loglevel`; `None` if
+/// `authorization` carries it, see [`bearer`].
+#[must_use]
+pub fn unauthorized(authorization: Option<&str>, token: Option<&str>) -> Option<&'static str> {
+    if bearer(authorization, token) {
+        None
+    } else if token.is_none() {
+        Some("Build the Thing with WRITE_TOKEN to use this route")
+    } else {
+        Some("Send Authorization: Bearer <WRITE_TOKEN>")
+    }
+}
+
+/// Whether the page `url`, an `Origin` or `Referer` value, is on the host
+/// of `entry`, and on its port if it has one.
+#[must_use]
+pub fn matches(url: &str, entry: &str) -> bool {
+    let (Some((host, port)), Some((entry_host, entry_port))) = (origin(url), host_port(entry))
+    else {
+        return false;
+    };
+
+    host == entry_host && entry_port.is_none_or(|entry_port| entry_port == port)
+}
+
+/// The host and port of the page `url`, `None` for an opaque origin such as
+/// `null` or a scheme without a default port.
+#[must_use]
+pub fn origin(url: &str) -> Option<(String, u16)> {
+    let (scheme, rest) = url.trim().split_once("://")?;
+    let default_port = if scheme.eq_ignore_ascii_case("http") {
+        80
+    } else if scheme.eq_ignore_ascii_case("https") {
+        443
+    } else {
+        return None;
+    };
+
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let (host, port) = host_port(authority)?;
+    Some((host, port.unwrap_or(default_port)))
+}
+
+/// `authority` as a host in its normal form, and its port if given.
+fn host_port(authority: &str) -> Option<(String, Option<u16>)> {
+    let authority = authority.trim();
+
+    // A bare IPv6 address, as an entry may give it.
+    if let Ok(address) = authority.parse::<Ipv6Addr>() {
+        return Some((format!("[{address}]"), None));
+    }
+    if let Some(rest) = authority.strip_prefix('[') {
+        let (address, rest) = rest.split_once(']')?;
+        let address: Ipv6Addr = address.parse().ok()?;
+        let port = match rest {
+            "" => None,
+            port => Some(port.strip_prefix(':')?.parse().ok()?),
+        };
+        return Some((format!("[{address}]"), port));
+    }
+
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, Some(port.parse().ok()?)),
+        None => (authority, None),
+    };
+    let host = host.strip_suffix('.').unwrap_or(host);
+    if host.is_empty() || host.contains([':', '[', ']']) {
+        return None;
+    }
+    Some((host.to_ascii_lowercase(), port))
+}
+
+/// A `WRITE_ORIGINS` entry, or a host of [`Policy::own`].
+///
+/// # Errors
+///
+/// If it is neither [`ANY`] nor a host with an optional port.
+pub fn entry(entry: &str) -> Result<(), &'static str> {
+    if entry.trim() == ANY || host_port(entry).is_some() {
+        Ok(())
+    } else {
+        Err("must be a host, host:port, [IPv6]:port or *")
+    }
+}
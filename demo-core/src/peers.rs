@@ -0,0 +1,338 @@
+//! Connections held per remote address, the peers refused for timing out
+//! and the server timeouts, see `wot_esp_thing::peers`.
+
+use serde_json::{json, Value};
+
+/// Keep-alive connections a peer may hold; the ones past it are closed
+/// after their response.
+pub const MAX_KEEP_ALIVE: usize = 2;
+
+/// Open connections per peer address, for at most `N` connections.
+pub struct Peers<A, const N: usize> {
+    held: [Option<(A, usize)>; N],
+}
+
+impl<A: Copy + PartialEq, const N: usize> Peers<A, N> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { held: [None; N] }
+    }
+
+    /// Count a connection from `peer`, returning whether it may be kept
+    /// alive: not when the peer already holds [`MAX_KEEP_ALIVE`].
+    pub fn open(&mut self, peer: A) -> bool {
+        if let Some((_, count)) = self.entry(peer) {
+            *count += 1;
+            return *count <= MAX_KEEP_ALIVE;
+        }
+        if let Some(free) = self.held.iter_mut().find(|e| e.is_none()) {
+            *free = Some((peer, 1));
+        }
+        true
+    }
+
+    /// Count a connection from `peer` as closed.
+    pub fn close(&mut self, peer: A) {
+        let Some(entry) = self
+            .held
+            .iter_mut()
+            .find(|e| matches!(e, Some((p, _)) if *p == peer))
+        else {
+            return;
+        };
+        if let Some((_, count)) = entry {
+            *count -= 1;
+            if *count > 0 {
+                return;
+            }
+        }
+        *entry = None;
+    }
+
+    /// Connections open from `peer`.
+    #[must_use]
+    pub fn held(&self, peer: A) -> usize {
+        self.iter()
+            .find(|(p, _)| *p == peer)
+            .map_or(0, |(_, count)| count)
+    }
+
+    /// Every peer with its open connections.
+    pub fn iter(&self) -> impl Iterator<Item = (A, usize)> + '_ {
+        self.held.iter().flatten().copied()
+    }
+
+    fn entry(&mut self, peer: A) -> Option<&mut (A, usize)> {
+        self.held.iter_mut().flatten().find(|(p, _)| *p == peer)
+    }
+}
+
+impl<A: Copy + PartialEq, const N: usize> Default for Peers<A, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Partial requests a peer may leave within the strike window unless set.
+pub const DEFAULT_STRIKE_LIMIT: u8 = 3;
+
+/// Window over which the partial requests of a peer are counted unless set,
+/// in seconds.
+pub const DEFAULT_STRIKE_WINDOW_S: u32 = 60;
+
+/// Longest strike window accepted, in seconds: an hour.
+pub const MAX_STRIKE_WINDOW_S: u32 = 3600;
+
+/// How long a peer past its strike limit is refused, in milliseconds.
+pub const REFUSE_MS: u64 = 300_000;
+
+/// When a peer is refused: past `limit` partial requests within
+/// `window_ms`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StrikePolicy {
+    pub limit: u8,
+    pub window_ms: u64,
+}
+
+impl StrikePolicy {
+    pub const DEFAULT: Self = Self {
+        limit: DEFAULT_STRIKE_LIMIT,
+        window_ms: DEFAULT_STRIKE_WINDOW_S as u64 * 1000,
+    };
+
+    /// The policy of the `STRIKE_LIMIT` and `STRIKE_WINDOW_S` build-time
+    /// settings, each clamped to its range and the default when unset or not
+    /// a number.
+    #[must_use]
+    pub fn from_settings(limit: Option<&str>, window_s: Option<&str>) -> Self {
+        let limit = limit
+            .and_then(|limit| limit.trim().parse().ok())
+            .map_or(DEFAULT_STRIKE_LIMIT, |limit: u8| limit.max(1));
+        let window_s = window_s
+            .and_then(|seconds| seconds.trim().parse().ok())
+            .map_or(DEFAULT_STRIKE_WINDOW_S, |seconds: u32| {
+                seconds.clamp(1, MAX_STRIKE_WINDOW_S)
+            });
+        Self {
+            limit,
+            window_ms: u64::from(window_s) * 1000,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Strike<A> {
+    peer: A,
+    count: u8,
+    since_ms: u64,
+    refused_until_ms: Option<u64>,
+}
+
+/// Partial requests per peer address, for at most `N` peers, to refuse a
+/// client that keeps opening connections and dribbling bytes.
+///
+/// When the table is full, the peer struck longest ago makes room for a new
+/// one, a refused peer last.
+pub struct Strikes<A, const N: usize> {
+    struck: [Option<Strike<A>>; N],
+}
+
+impl<A: Copy + PartialEq, const N: usize> Strikes<A, N> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { struck: [None; N] }
+    }
+
+    /// Count a partial request of `peer` at `now_ms`, returning whether it
+    /// is refused from now on under `policy`.
+    pub fn strike(&mut self, peer: A, now_ms: u64, policy: &StrikePolicy) -> bool {
+        let fresh = Strike {
+            peer,
+            count: 0,
+            since_ms: now_ms,
+            refused_until_ms: None,
+        };
+        let slot = self.position(peer).unwrap_or_else(|| self.room());
+        let Some(entry) = self.struck.get_mut(slot) else {
+            return false;
+        };
+        let strike = entry.get_or_insert(fresh);
+        if now_ms.saturating_sub(strike.since_ms) > policy.window_ms {
+            *strike = fresh;
+        }
+        strike.count = strike.count.saturating_add(1);
+        if strike.count > policy.limit {
+            strike.refused_until_ms = Some(now_ms + REFUSE_MS);
+        }
+        strike.refused_until_ms.is_some()
+    }
+
+    /// Whether connections from `peer` are refused at `now_ms`; a refusal
+    /// over is forgotten, with the strikes that led to it.
+    pub fn refused(&mut self, peer: A, now_ms: u64) -> bool {
+        let Some(slot) = self.position(peer) else {
+            return false;
+        };
+        match self.struck[slot].and_then(|strike| strike.refused_until_ms) {
+            Some(until) if now_ms < until => true,
+            Some(_) => {
+                self.struck[slot] = None;
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Every peer refused at `now_ms`, with the milliseconds left.
+    pub fn iter_refused(&self, now_ms: u64) -> impl Iterator<Item = (A, u64)> + '_ {
+        self.struck.iter().flatten().filter_map(move |strike| {
+            let until = strike.refused_until_ms.filter(|&until| until > now_ms)?;
+            Some((strike.peer, until - now_ms))
+        })
+    }
+
+    fn position(&self, peer: A) -> Option<usize> {
+        self.struck
+            .iter()
+            .position(|e| matches!(e, Some(strike) if strike.peer == peer))
+    }
+
+    fn room(&self) -> usize {
+        self.struck
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, e)| match e {
+                None => (0, 0),
+                Some(strike) => (
+                    1 + u8::from(strike.refused_until_ms.is_some()),
+                    strike.since_ms,
+                ),
+            })
+            .map_or(0, |(slot, _)| slot)
+    }
+}
+
+impl<A: Copy + PartialEq, const N: usize> Default for Strikes<A, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Why the server gave up on a connection, each counted at `/debug/peers`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Closed {
+    /// No request came before the start-read timeout, e.g. a browser
+    /// preconnect left idle. Not held against the client.
+    StartReadTimeout,
+    /// A request was started but not finished in time.
+    ReadTimeout,
+    /// The client did not take the response in time.
+    WriteTimeout,
+    /// The request could not be parsed.
+    ParseError,
+}
+
+impl Closed {
+    pub const ALL: [Self; 4] = [
+        Self::StartReadTimeout,
+        Self::ReadTimeout,
+        Self::WriteTimeout,
+        Self::ParseError,
+    ];
+
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::StartReadTimeout => "startReadTimeout",
+            Self::ReadTimeout => "readTimeout",
+            Self::WriteTimeout => "writeTimeout",
+            Self::ParseError => "parseError",
+        }
+    }
+
+    /// Whether the client is struck for it, see [`Strikes`]: only a partial
+    /// request is.
+    #[must_use]
+    pub fn strikes(self) -> bool {
+        self == Self::ReadTimeout
+    }
+}
+
+/// Shortest timeout accepted, in milliseconds.
+pub const MIN_TIMEOUT_MS: u32 = 100;
+
+/// Longest timeout accepted, in milliseconds.
+pub const MAX_TIMEOUT_MS: u32 = 300_000;
+
+/// The timeouts of the HTTP server, in milliseconds, served at
+/// `/debug/timeouts`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Wait for the first request of a connection.
+    pub start_read_request_ms: u32,
+    /// Wait for the next request of a kept-alive connection.
+    pub persistent_start_read_request_ms: u32,
+    /// Wait for the rest of a request once it started.
+    pub read_request_ms: u32,
+    /// Wait for the client to take each part of the response.
+    pub write_ms: u32,
+}
+
+impl Timeouts {
+    pub const DEFAULT: Self = Self {
+        start_read_request_ms: 5_000,
+        persistent_start_read_request_ms: 30_000,
+        read_request_ms: 1_000,
+        write_ms: 1_000,
+    };
+
+    /// Whether a connection closed cleanly `elapsed_ms` after it was
+    /// accepted, having served `requests`, ran into the start-read timeout
+    /// rather than being closed by the client.
+    #[must_use]
+    pub fn start_read_timed_out(&self, requests: u64, elapsed_ms: u64) -> bool {
+        requests == 0 && elapsed_ms >= u64::from(self.start_read_request_ms)
+    }
+
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        json!({
+            "startReadRequest_ms": self.start_read_request_ms,
+            "persistentStartReadRequest_ms": self.persistent_start_read_request_ms,
+            "readRequest_ms": self.read_request_ms,
+            "write_ms": self.write_ms,
+        })
+    }
+
+    /// The timeouts with the members of `body` written, e.g.
+    /// `{"write_ms":2000}`; the others are kept.
+    ///
+    /// # Errors
+    ///
+    /// Why `body` is not a valid change, nothing is written then.
+    pub fn merge(self, body: &Value) -> Result<Self, &'static str> {
+        let body = body.as_object().ok_or("expected an object")?;
+        let mut timeouts = self;
+        for (name, value) in body {
+            let field = match name.as_str() {
+                "startReadRequest_ms" => &mut timeouts.start_read_request_ms,
+                "persistentStartReadRequest_ms" => &mut timeouts.persistent_start_read_request_ms,
+                "readRequest_ms" => &mut timeouts.read_request_ms,
+                "write_ms" => &mut timeouts.write_ms,
+                _ => return Err("unknown timeout"),
+            };
+            *field = value
+                .as_u64()
+                .and_then(|ms| u32::try_from(ms).ok())
+                .filter(|ms| (MIN_TIMEOUT_MS..=MAX_TIMEOUT_MS).contains(ms))
+                .ok_or("timeouts must be integers from 100 to 300000")?;
+        }
+        Ok(timeouts)
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
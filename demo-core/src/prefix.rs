@@ -0,0 +1,134 @@
+//! The path prefix of every route of a Thing sharing a reverse proxy host
+//! with others, see `wot_esp_thing::prefix`.
+//!
+//! With `ROUTE_PREFIX=/light1` the routes move under the prefix, e.g.
+//! `/properties/on` to `/light1/properties/on`, and the hrefs of the TD with
+//! them, so two Things behind one proxy need no rewrite rules. The TD is
+//! still served at `/` for direct access, and the well-known URIs, e.g.
+//! `/.well-known/wot`, stay at the root as RFC 8615 requires:
+//!
+//! ```text
+//! /properties/on                    /light1/properties/on
+//! http://192.0.2.7/actions/fade     http://192.0.2.7/light1/actions/fade
+//! /.well-known/wot                  /.well-known/wot
+//! udp://192.0.2.7:5684              udp://192.0.2.7:5684
+//! ```
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use serde_json::{Map, Value};
+
+/// Paths kept at the root whatever the prefix.
+pub const WELL_KNOWN: &str = "/.well-known/";
+
+/// `raw` as a prefix: one leading slash, no trailing or repeated one, e.g.
+/// `light1/` becomes `/light1`.
+///
+/// # Errors
+///
+/// If it has no segment, a character other than letters, digits, `-`, `.`,
+/// `_`, `~` and `/`, a `.` or `..` segment, or starts with `/.well-known`.
+pub fn normalize(raw: &str) -> Result<String, &'static str> {
+    let segments: Vec<&str> = raw.split('/').filter(|s| !s.is_empty()).collect();
+
+    if segments.is_empty() {
+        return Err("must name a path, e.g. /light1");
+    }
+    if !raw
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b"-._~/".contains(&b))
+    {
+        return Err("must be letters, digits, -, ., _, ~ and /");
+    }
+    if segments.iter().any(|s| *s == "." || *s == "..") {
+        return Err("must not have . or .. segments");
+    }
+    if segments[0] == ".well-known" {
+        return Err("must not be under /.well-known");
+    }
+
+    Ok(segments.iter().fold(String::new(), |prefix, segment| {
+        format!("{prefix}/{segment}")
+    }))
+}
+
+/// `href` under `prefix`: a path or an `http(s)` URL gets it before its
+/// path, unless the path is well-known; other schemes are left as they are.
+#[must_use]
+pub fn href(href: &str, prefix: &str) -> String {
+    let under = |path: &str| {
+        if path.starts_with(WELL_KNOWN) {
+            path.to_string()
+        } else if path.starts_with('/') {
+            format!("{prefix}{path}")
+        } else {
+            format!("{prefix}/{path}")
+        }
+    };
+
+    match href.split_once("://") {
+        Some((scheme, rest))
+            if scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https") =>
+        {
+            let (authority, path) = rest.find('/').map_or((rest, "/"), |i| rest.split_at(i));
+            format!("{scheme}://{authority}{}", under(path))
+        }
+        Some(_) => href.to_string(),
+        None => under(href),
+    }
+}
+
+/// `path` of a request with `prefix` taken off, as the route was registered;
+/// `path` itself if it is not under `prefix`.
+#[must_use]
+pub fn strip<'a>(path: &'a str, prefix: &str) -> &'a str {
+    match path.strip_prefix(prefix) {
+        Some("") => "/",
+        Some(rest) if rest.starts_with('/') => rest,
+        _ => path,
+    }
+}
+
+/// Put the hrefs of the forms and links of the TD object `td` under
+/// `prefix`, see [`href`].
+pub fn prefix(td: &mut Map<String, Value>, prefix: &str) {
+    let rewrite = |value: &mut Value| {
+        if let Some(h) = value.as_str() {
+            *value = href(h, prefix).into();
+        }
+    };
+
+    for (member, value) in td.iter_mut() {
+        let entries: Vec<&mut Value> = match member.as_str() {
+            "properties" | "actions" | "events" => value
+                .as_object_mut()
+                .into_iter()
+                .flat_map(|affordances| affordances.values_mut())
+                .filter_map(|affordance| affordance.get_mut("forms")?.as_array_mut())
+                .flatten()
+                .collect(),
+            "forms" | "links" => value.as_array_mut().into_iter().flatten().collect(),
+            _ => continue,
+        };
+        for entry in entries {
+            if let Some(value) = entry.get_mut("href") {
+                rewrite(value);
+            }
+        }
+    }
+}
+
+/// [`prefix`] on the serialized TD `td`.
+///
+/// # Errors
+///
+/// Returns an error if `td` is not a JSON object.
+pub fn prefix_json(td: &str, prefix_path: &str) -> Result<String, serde_json::Error> {
+    let mut td: Map<String, Value> = serde_json::from_str(td)?;
+    prefix(&mut td, prefix_path);
+    serde_json::to_string(&td)
+}
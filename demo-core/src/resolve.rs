@@ -0,0 +1,117 @@
+//! Cache of host name lookups, keeping failures as well as addresses.
+//!
+//! A failed lookup is remembered for a while that doubles with each failure
+//! in a row, so a name that does not resolve is retried with backoff instead
+//! of on every request.
+
+use alloc::{string::String, vec::Vec};
+
+/// Host names remembered at once.
+pub const CACHE_LEN: usize = 4;
+
+/// How long an address is used when the answer carries no TTL.
+pub const DEFAULT_TTL_MS: u64 = 5 * 60 * 1000;
+
+/// Wait before retrying a name that failed to resolve once.
+const FIRST_RETRY_MS: u64 = 1000;
+
+/// Longest wait before retrying a name that keeps failing.
+const MAX_RETRY_MS: u64 = 5 * 60 * 1000;
+
+/// Wait before retrying a name after `failures` lookups in a row failed.
+#[must_use]
+pub fn backoff_ms(failures: u32) -> u64 {
+    FIRST_RETRY_MS
+        .saturating_mul(1 << failures.saturating_sub(1).min(16))
+        .min(MAX_RETRY_MS)
+}
+
+struct Entry<A> {
+    host: String,
+    addr: Option<A>,
+    failures: u32,
+    expires_ms: u64,
+}
+
+/// The last lookups of up to [`CACHE_LEN`] names.
+pub struct Cache<A> {
+    entries: Vec<Entry<A>>,
+}
+
+impl<A: Copy> Cache<A> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// The cached outcome for `host` at `now_ms`: `Some(None)` while a failed
+    /// lookup is backing off, `None` when the name has to be looked up.
+    #[must_use]
+    pub fn get(&self, host: &str, now_ms: u64) -> Option<Option<A>> {
+        self.entries
+            .iter()
+            .find(|e| e.host == host && e.expires_ms > now_ms)
+            .map(|e| e.addr)
+    }
+
+    /// Remember the address `host` resolved to for `ttl_ms`, or
+    /// [`DEFAULT_TTL_MS`] when unknown.
+    pub fn resolved(&mut self, host: &str, addr: A, ttl_ms: Option<u64>, now_ms: u64) {
+        let entry = self.entry(host);
+        entry.addr = Some(addr);
+        entry.failures = 0;
+        entry.expires_ms = now_ms.saturating_add(ttl_ms.unwrap_or(DEFAULT_TTL_MS));
+    }
+
+    /// Remember that `host` failed to resolve, returning the wait before it
+    /// is looked up again.
+    pub fn failed(&mut self, host: &str, now_ms: u64) -> u64 {
+        let entry = self.entry(host);
+        entry.addr = None;
+        entry.failures = entry.failures.saturating_add(1);
+        let wait = backoff_ms(entry.failures);
+        entry.expires_ms = now_ms.saturating_add(wait);
+        wait
+    }
+
+    /// The entry of `host`, taking over the one expiring first when full.
+    fn entry(&mut self, host: &str) -> &mut Entry<A> {
+        let at = match self.entries.iter().position(|e| e.host == host) {
+            Some(at) => at,
+            None if self.entries.len() < CACHE_LEN => {
+                self.entries.push(Entry {
+                    host: host.into(),
+                    addr: None,
+                    failures: 0,
+                    expires_ms: 0,
+                });
+                self.entries.len() - 1
+            }
+            None => {
+                let at = self
+                    .entries
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, e)| e.expires_ms)
+                    .map_or(0, |(at, _)| at);
+                self.entries[at] = Entry {
+                    host: host.into(),
+                    addr: None,
+                    failures: 0,
+                    expires_ms: 0,
+                };
+                at
+            }
+        };
+
+        &mut self.entries[at]
+    }
+}
+
+impl<A: Copy> Default for Cache<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,165 @@
+//! Automation rules: write to another Thing when a local value crosses a
+//! threshold, see `wot_esp_thing::rules`.
+//!
+//! ```json
+//! {"source":"temperature","comparison":">","threshold":28,
+//!  "target":"http://192.0.2.7/properties/on","payload":"true","cooldownS":300}
+//! ```
+
+use alloc::string::{String, ToString};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+/// Rules a Thing holds.
+pub const MAX_RULES: usize = 4;
+
+/// Longest cooldown, a day.
+pub const MAX_COOLDOWN_S: u32 = 24 * 60 * 60;
+
+/// Placeholder of the payload replaced with the value that fired the rule.
+pub const VALUE: &str = "{value}";
+
+/// How the value of the source is compared with the threshold.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Comparison {
+    #[serde(rename = ">")]
+    Above,
+    #[serde(rename = ">=")]
+    AtLeast,
+    #[serde(rename = "<")]
+    Below,
+    #[serde(rename = "<=")]
+    AtMost,
+    #[serde(rename = "==")]
+    Equal,
+}
+
+impl Comparison {
+    /// Whether `value` compares so with `threshold`.
+    #[must_use]
+    pub fn holds(self, value: f32, threshold: f32) -> bool {
+        match self {
+            Self::Above => value > threshold,
+            Self::AtLeast => value >= threshold,
+            Self::Below => value < threshold,
+            Self::AtMost => value <= threshold,
+            #[allow(clippy::float_cmp)]
+            Self::Equal => value == threshold,
+        }
+    }
+}
+
+/// Write `payload` to `target` when the value of `source` compares with
+/// `threshold`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Rule {
+    /// A property or event of this Thing, e.g. `temperature`.
+    pub source: String,
+    pub comparison: Comparison,
+    pub threshold: f32,
+    /// The `http://` URL of the property written, e.g. of another Thing.
+    pub target: String,
+    /// The JSON written, where [`VALUE`] stands for the value of the source.
+    pub payload: String,
+    /// Seconds after firing during which the rule does not fire again.
+    #[serde(default = "default_cooldown_s")]
+    pub cooldown_s: u32,
+}
+
+const fn default_cooldown_s() -> u32 {
+    60
+}
+
+impl Rule {
+    /// Check the rule against the `sources` of this Thing.
+    ///
+    /// # Errors
+    ///
+    /// If the source is unknown, the threshold is not finite, the target is
+    /// not an `http://` URL, the payload is not JSON once rendered or the
+    /// cooldown is longer than [`MAX_COOLDOWN_S`].
+    pub fn validate(&self, sources: &[&str]) -> Result<(), &'static str> {
+        if !sources.contains(&self.source.as_str()) {
+            return Err("Unknown source");
+        }
+        if !self.threshold.is_finite() {
+            return Err("Threshold must be a finite number");
+        }
+        config::http_url(&self.target).map_err(|_| "Target must be an http:// URL")?;
+        if serde_json::from_str::<serde_json::Value>(&self.render(self.threshold)).is_err() {
+            return Err("Payload must be JSON, with {value} for the value");
+        }
+        if self.cooldown_s > MAX_COOLDOWN_S {
+            return Err("Cooldown must be at most 86400 s");
+        }
+        Ok(())
+    }
+
+    /// The payload with every [`VALUE`] replaced with `value`.
+    #[must_use]
+    pub fn render(&self, value: f32) -> String {
+        self.payload.replace(VALUE, &value.to_string())
+    }
+}
+
+/// Firing state of a rule, updated with every value of its source.
+///
+/// A rule fires when its comparison starts to hold, and again only once it
+/// stopped holding in between, so a value staying beyond the threshold
+/// writes the target once. A value that starts to hold within the cooldown
+/// fires when the cooldown is over, if it still holds then.
+#[derive(Clone, Copy, Debug)]
+pub struct RuleState {
+    armed: bool,
+    last_fired_ms: Option<u64>,
+}
+
+impl RuleState {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            armed: true,
+            last_fired_ms: None,
+        }
+    }
+
+    /// When the rule last fired, in milliseconds since boot.
+    #[must_use]
+    pub const fn last_fired_ms(&self) -> Option<u64> {
+        self.last_fired_ms
+    }
+
+    /// Compare `value` as `rule` says at `now_ms`, returning whether the rule
+    /// fires.
+    ///
+    /// `NaN` values leave the state unchanged.
+    pub fn update(&mut self, rule: &Rule, value: f32, now_ms: u64) -> bool {
+        if value.is_nan() {
+            return false;
+        }
+        if !rule.comparison.holds(value, rule.threshold) {
+            self.armed = true;
+            return false;
+        }
+
+        let cooldown_ms = u64::from(rule.cooldown_s) * 1000;
+        let cooled = self
+            .last_fired_ms
+            .is_none_or(|last| now_ms.saturating_sub(last) >= cooldown_ms);
+        if !self.armed || !cooled {
+            return false;
+        }
+        self.armed = false;
+        self.last_fired_ms = Some(now_ms);
+        true
+    }
+}
+
+impl Default for RuleState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,175 @@
+//! Validation of action inputs against their data schema, for the subset
+//! the TDs declare: `type`, `minimum` and `maximum`, `enum` and the
+//! required members of an object.
+//!
+//! Each input type declares its schema with [`InputSchema`], next to it and
+//! in step with the TD builder; `wot_esp_thing::ActionBody` checks a body
+//! against it before the handler runs.
+
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::Display;
+
+use serde_json::{json, Map, Value};
+
+/// A data schema of an action input.
+#[derive(Clone, Copy, Debug)]
+pub enum Schema {
+    Boolean,
+    Integer {
+        minimum: Option<i64>,
+        maximum: Option<i64>,
+    },
+    Number {
+        minimum: Option<f64>,
+        maximum: Option<f64>,
+    },
+    /// A string, one of `one_of` unless it is empty.
+    String {
+        one_of: &'static [&'static str],
+    },
+    Object(&'static [Member]),
+}
+
+/// A member of a [`Schema::Object`].
+#[derive(Clone, Copy, Debug)]
+pub struct Member {
+    pub name: &'static str,
+    pub required: bool,
+    pub schema: Schema,
+}
+
+/// The schema of an action input type.
+pub trait InputSchema {
+    const SCHEMA: Schema;
+}
+
+/// The first part of a value its schema rejects.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    /// JSON Pointer to the value, e.g. `/color/r`; empty for the whole
+    /// value.
+    pub path: String,
+    pub message: String,
+}
+
+impl Violation {
+    /// The JSON body of a 400 response, e.g.
+    /// `{"error":"must be at most 255","path":"/color/r"}`.
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        json!({ "error": self.message, "path": self.path })
+    }
+}
+
+impl Schema {
+    /// Check `value` against the schema.
+    ///
+    /// # Errors
+    ///
+    /// The first violation, members in the order of the schema.
+    pub fn validate(&self, value: &Value) -> Result<(), Violation> {
+        self.check(value, &mut String::new())
+    }
+
+    fn check(&self, value: &Value, path: &mut String) -> Result<(), Violation> {
+        match *self {
+            Self::Boolean if value.is_boolean() => Ok(()),
+            Self::Boolean => Err(violation(path, "expected a boolean")),
+            Self::Integer { minimum, maximum } => {
+                // Beyond i64 is beyond any maximum the TDs declare.
+                let n = match (value.as_i64(), value.as_u64()) {
+                    (Some(n), _) => n,
+                    (None, Some(_)) => i64::MAX,
+                    _ => return Err(violation(path, "expected an integer")),
+                };
+                in_bounds(path, n, minimum, maximum)
+            }
+            Self::Number { minimum, maximum } => {
+                let Some(n) = value.as_f64() else {
+                    return Err(violation(path, "expected a number"));
+                };
+                in_bounds(path, n, minimum, maximum)
+            }
+            Self::String { one_of } => match value.as_str() {
+                None => Err(violation(path, "expected a string")),
+                Some(s) if !one_of.is_empty() && !one_of.contains(&s) => Err(violation(
+                    path,
+                    format!("must be one of {}", one_of.join(", ")),
+                )),
+                Some(_) => Ok(()),
+            },
+            Self::Object(members) => {
+                let Some(object) = value.as_object() else {
+                    return Err(violation(path, "expected an object"));
+                };
+                for member in members {
+                    let len = path.len();
+                    path.push('/');
+                    path.push_str(member.name);
+                    match object.get(member.name) {
+                        Some(value) => member.schema.check(value, path)?,
+                        None if member.required => return Err(violation(path, "is required")),
+                        None => {}
+                    }
+                    path.truncate(len);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The schema as TD JSON, to compare with the TD builder's.
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        match *self {
+            Self::Boolean => json!({ "type": "boolean" }),
+            Self::Integer { minimum, maximum } => bounded("integer", minimum, maximum),
+            Self::Number { minimum, maximum } => bounded("number", minimum, maximum),
+            Self::String { one_of: &[] } => json!({ "type": "string" }),
+            Self::String { one_of } => json!({ "type": "string", "enum": one_of }),
+            Self::Object(members) => {
+                let properties: Map<String, Value> = members
+                    .iter()
+                    .map(|m| (m.name.into(), m.schema.to_json()))
+                    .collect();
+                let required: Vec<_> = members
+                    .iter()
+                    .filter(|m| m.required)
+                    .map(|m| m.name)
+                    .collect();
+                json!({ "type": "object", "properties": properties, "required": required })
+            }
+        }
+    }
+}
+
+fn violation(path: &str, message: impl Into<String>) -> Violation {
+    Violation {
+        path: path.into(),
+        message: message.into(),
+    }
+}
+
+fn in_bounds<T: PartialOrd + Display>(
+    path: &str,
+    n: T,
+    minimum: Option<T>,
+    maximum: Option<T>,
+) -> Result<(), Violation> {
+    match (minimum, maximum) {
+        (Some(min), _) if n < min => Err(violation(path, format!("must be at least {min}"))),
+        (_, Some(max)) if n > max => Err(violation(path, format!("must be at most {max}"))),
+        _ => Ok(()),
+    }
+}
+
+fn bounded<T: Into<Value>>(kind: &str, minimum: Option<T>, maximum: Option<T>) -> Value {
+    let mut schema = json!({ "type": kind });
+    if let Some(minimum) = minimum {
+        schema["minimum"] = minimum.into();
+    }
+    if let Some(maximum) = maximum {
+        schema["maximum"] = maximum.into();
+    }
+    schema
+}
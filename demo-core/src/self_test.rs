@@ -0,0 +1,111 @@
+//! Results of the hardware self-test, see `wot_esp_thing::SelfTest`, and
+//! the reads of the start-up check of the `self-test` feature, see
+//! `wot_esp_thing::http_client`.
+//!
+//! The start-up check reads the TD and every readable property over the
+//! network, through the routes a consumer uses, and logs a table of the
+//! outcomes:
+//!
+//! ```text
+//! td               /                                ok     200
+//! humidity         /properties/humidity             failed 503
+//! temperature      /properties/temperature          ok     200
+//! ```
+
+use alloc::{format, string::String, vec::Vec};
+
+use serde_json::{Map, Value};
+
+use crate::affordance_stats::path;
+
+/// Name of the read of the TD in the start-up check.
+pub const TD_READ: &str = "td";
+
+/// Outcome of one hardware check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Ok,
+    Failed,
+    /// The check does not apply to this board or wiring.
+    Skipped,
+}
+
+impl Outcome {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+        }
+    }
+}
+
+/// `Ok` for a successful peripheral call, `Failed` for an error.
+impl<T, E> From<Result<T, E>> for Outcome {
+    fn from(result: Result<T, E>) -> Self {
+        match result {
+            Ok(_) => Self::Ok,
+            Err(_) => Self::Failed,
+        }
+    }
+}
+
+/// The outcome of every check by name, e.g. `{"sensor":"ok","led":"failed"}`.
+#[must_use]
+pub fn results<'a>(outcomes: impl IntoIterator<Item = (&'a str, Outcome)>) -> Value {
+    Value::Object(
+        outcomes
+            .into_iter()
+            .map(|(name, outcome)| (String::from(name), outcome.as_str().into()))
+            .collect::<Map<_, _>>(),
+    )
+}
+
+/// The reads of the start-up check as `(name, path)`: [`TD_READ`] at `/`,
+/// then each property at the path of its first `readproperty` form, by
+/// name.
+///
+/// Absolute hrefs, e.g. of a TD with a public base, are read at their path
+/// on the Thing itself.
+#[must_use]
+pub fn reads(td: &Value) -> Vec<(String, String)> {
+    let mut reads = alloc::vec![(String::from(TD_READ), String::from("/"))];
+
+    let Some(properties) = td.get("properties").and_then(Value::as_object) else {
+        return reads;
+    };
+    for (name, property) in properties {
+        let forms = property
+            .get("forms")
+            .and_then(Value::as_array)
+            .map_or(&[][..], Vec::as_slice);
+        let read = forms.iter().find(|form| match form.get("op") {
+            Some(Value::String(op)) => op == "readproperty",
+            Some(Value::Array(ops)) => ops.iter().any(|op| op == "readproperty"),
+            _ => false,
+        });
+        if let Some(href) = read.and_then(|form| form.get("href")?.as_str()) {
+            reads.push((name.clone(), path(href).into()));
+        }
+    }
+
+    reads
+}
+
+/// Outcome of a read answered with `status`.
+#[must_use]
+pub fn read_outcome(status: u16) -> Outcome {
+    if (200..300).contains(&status) {
+        Outcome::Ok
+    } else {
+        Outcome::Failed
+    }
+}
+
+/// A line of the table of the start-up check: the read, its outcome and
+/// `detail`, the status or why the request failed.
+#[must_use]
+pub fn row(name: &str, path: &str, outcome: Outcome, detail: &str) -> String {
+    format!("{name:<16} {path:<32} {:<6} {detail}", outcome.as_str())
+}
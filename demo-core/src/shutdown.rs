@@ -0,0 +1,22 @@
+//! When a Thing shut down with the `shutdown` action wakes up again, see
+//! `wot_esp_thing::shutdown`.
+
+/// Shortest deep sleep after a shutdown, in minutes.
+pub const MIN_WAKE_MIN: u32 = 1;
+
+/// Longest deep sleep after a shutdown, in minutes: a week.
+pub const MAX_WAKE_MIN: u32 = 7 * 24 * 60;
+
+/// Deep sleep after a shutdown unless set, in minutes: a day.
+pub const DEFAULT_WAKE_MIN: u32 = 24 * 60;
+
+/// The deep sleep in minutes of the `SHUTDOWN_WAKE_MIN` build-time setting,
+/// clamped to the range above and the default when unset or not a number.
+#[must_use]
+pub fn wake_min(setting: Option<&str>) -> u32 {
+    setting
+        .and_then(|minutes| minutes.trim().parse().ok())
+        .map_or(DEFAULT_WAKE_MIN, |minutes: u32| {
+            minutes.clamp(MIN_WAKE_MIN, MAX_WAKE_MIN)
+        })
+}
@@ -0,0 +1,392 @@
+//! Color effects of the RGB light, as pure functions of their progress, and
+//! the wire format and current draw of the addressable LED chips driving it.
+
+/// An RGB color.
+pub type Rgb = (u8, u8, u8);
+
+/// Length of the RMT buffer driving `led_count` pixels sent in `order`: one
+/// pulse per bit of each, plus the reset ending the frame.
+///
+/// The buffer `wot_esp_thing::smartled::SmartLedsAdapter` allocates for its
+/// pixel count.
+#[must_use]
+pub const fn rmt_buffer_len(led_count: usize, order: ColorOrder) -> usize {
+    led_count * order.bytes_per_pixel() * 8 + 1
+}
+
+/// Pulse timings of a single-wire LED chip, in nanoseconds, from its
+/// datasheet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LedTiming {
+    /// High time of a 0 bit.
+    pub t0h: u32,
+    /// Low time of a 0 bit.
+    pub t0l: u32,
+    /// High time of a 1 bit.
+    pub t1h: u32,
+    /// Low time of a 1 bit.
+    pub t1l: u32,
+    /// Low time latching the frame.
+    pub reset: u32,
+}
+
+impl LedTiming {
+    /// WS2812B at 800 kHz.
+    pub const WS2812B: Self = Self {
+        t0h: 400,
+        t0l: 850,
+        t1h: 800,
+        t1l: 450,
+        reset: 50_000,
+    };
+
+    /// WS2811 in its 400 kHz (low speed) mode.
+    pub const WS2811: Self = Self {
+        t0h: 500,
+        t0l: 2000,
+        t1h: 1200,
+        t1l: 1300,
+        reset: 50_000,
+    };
+
+    /// SK6812 (RGB and RGBW) at 800 kHz.
+    pub const SK6812: Self = Self {
+        t0h: 300,
+        t0l: 900,
+        t1h: 600,
+        t1l: 600,
+        reset: 80_000,
+    };
+
+    /// The symbol durations at an RMT clock of `clock_hz`, rounded to the
+    /// nearest tick.
+    #[must_use]
+    pub fn symbols(&self, clock_hz: u32) -> Symbols {
+        #[allow(clippy::cast_possible_truncation)]
+        let ticks =
+            |ns: u32| ((u64::from(ns) * u64::from(clock_hz) + 500_000_000) / 1_000_000_000) as u32;
+        let pulse = |ns| u16::try_from(ticks(ns)).map_or(MAX_TICKS, |t| t.min(MAX_TICKS));
+
+        Symbols {
+            zero: (pulse(self.t0h), pulse(self.t0l)),
+            one: (pulse(self.t1h), pulse(self.t1l)),
+            reset: pulse(self.reset),
+        }
+    }
+}
+
+/// Longest half of an RMT pulse code, in ticks: its 15-bit length field.
+pub const MAX_TICKS: u16 = 0x7fff;
+
+/// Durations of the RMT symbols for a [`LedTiming`], in clock ticks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Symbols {
+    /// High and low ticks of a 0 bit.
+    pub zero: (u16, u16),
+    /// High and low ticks of a 1 bit.
+    pub one: (u16, u16),
+    /// Low ticks latching the frame.
+    pub reset: u16,
+}
+
+impl Symbols {
+    /// The RMT pulse code of a high then low pulse, as laid out in
+    /// `esp_hal::rmt::PulseCode`: the first length in bits 0–14 and its
+    /// level in bit 15, the second in bits 16–30 and 31.
+    const fn code((high, low): (u16, u16)) -> u32 {
+        1 << 15 | high as u32 | (low as u32) << 16
+    }
+
+    /// The pulse code of a 0 or a 1 bit.
+    #[must_use]
+    pub const fn bit(&self, one: bool) -> u32 {
+        Self::code(if one { self.one } else { self.zero })
+    }
+
+    /// The pulse code ending a frame: low for the reset time, then a length
+    /// of 0, which stops the transmission.
+    #[must_use]
+    pub const fn latch(&self) -> u32 {
+        self.reset as u32
+    }
+
+    /// The pulse codes of `bytes`, most significant bit first.
+    pub fn encode(self, bytes: &[u8]) -> impl Iterator<Item = u32> + '_ {
+        bytes
+            .iter()
+            .flat_map(|&byte| (0..8).rev().map(move |bit| byte & 1 << bit != 0))
+            .map(move |one| self.bit(one))
+    }
+
+    /// The pulse timings these symbols send at an RMT clock of `clock_hz`,
+    /// after rounding to ticks.
+    #[must_use]
+    pub fn timing(&self, clock_hz: u32) -> LedTiming {
+        #[allow(clippy::cast_possible_truncation)]
+        let ns = |ticks: u16| (u64::from(ticks) * 1_000_000_000 / u64::from(clock_hz)) as u32;
+
+        LedTiming {
+            t0h: ns(self.zero.0),
+            t0l: ns(self.zero.1),
+            t1h: ns(self.one.0),
+            t1l: ns(self.one.1),
+            reset: ns(self.reset),
+        }
+    }
+}
+
+/// The divider of an RMT channel turning the `rmt_clock_hz` it is clocked at
+/// into `clock_hz`, `None` unless it is a whole number from 1 to 255.
+#[must_use]
+pub fn clock_divider(rmt_clock_hz: u32, clock_hz: u32) -> Option<u8> {
+    if clock_hz == 0 || !rmt_clock_hz.is_multiple_of(clock_hz) {
+        return None;
+    }
+    u8::try_from(rmt_clock_hz / clock_hz)
+        .ok()
+        .filter(|&divider| divider > 0)
+}
+
+/// Shortest and longest pulses a chip reads correctly, in nanoseconds, from
+/// its datasheet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimingLimits {
+    pub t0h: (u32, u32),
+    pub t0l: (u32, u32),
+    pub t1h: (u32, u32),
+    pub t1l: (u32, u32),
+    /// Shortest low time latching the frame.
+    pub reset: u32,
+}
+
+impl TimingLimits {
+    /// WS2812B: ±150 ns around each nominal pulse, a reset of 50 µs or more.
+    pub const WS2812B: Self = Self {
+        t0h: (250, 550),
+        t0l: (700, 1000),
+        t1h: (650, 950),
+        t1l: (300, 600),
+        reset: 50_000,
+    };
+
+    /// Whether every pulse of `timing` is within the limits.
+    #[must_use]
+    pub fn admit(&self, timing: &LedTiming) -> bool {
+        let within = |ns: u32, (min, max): (u32, u32)| (min..=max).contains(&ns);
+
+        within(timing.t0h, self.t0h)
+            && within(timing.t0l, self.t0l)
+            && within(timing.t1h, self.t1h)
+            && within(timing.t1l, self.t1l)
+            && timing.reset >= self.reset
+    }
+}
+
+/// The WS2812B timing the light sends, the `ledTimingProfile` property.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimingProfile {
+    /// [`LedTiming::WS2812B`] at the 80 MHz RMT clock.
+    Standard,
+    /// For cold enclosures and long cables: a 0 bit shorter and a 1 bit
+    /// longer, so the two stay apart when the edges slow down, and a longer
+    /// reset, at a 10 MHz clock whose 100 ns ticks fit them exactly.
+    Conservative,
+}
+
+impl TimingProfile {
+    /// [`Self::Conservative`], still within [`TimingLimits::WS2812B`].
+    const CONSERVATIVE: LedTiming = LedTiming {
+        t0h: 300,
+        t0l: 900,
+        t1h: 900,
+        t1l: 400,
+        reset: 80_000,
+    };
+
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Standard => "standard",
+            Self::Conservative => "conservative",
+        }
+    }
+
+    /// The profile named `name`, `None` for an unknown one.
+    #[must_use]
+    pub fn parse(name: &str) -> Option<Self> {
+        [Self::Standard, Self::Conservative]
+            .into_iter()
+            .find(|profile| profile.as_str() == name)
+    }
+
+    /// The pulse timings of the profile.
+    #[must_use]
+    pub fn timing(self) -> LedTiming {
+        match self {
+            Self::Standard => LedTiming::WS2812B,
+            Self::Conservative => Self::CONSERVATIVE,
+        }
+    }
+
+    /// The RMT clock the profile is sent at.
+    #[must_use]
+    pub fn clock_hz(self) -> u32 {
+        match self {
+            Self::Standard => 80_000_000,
+            Self::Conservative => 10_000_000,
+        }
+    }
+
+    /// [`Self::timing`] encoded at [`Self::clock_hz`].
+    #[must_use]
+    pub fn symbols(self) -> Symbols {
+        self.timing().symbols(self.clock_hz())
+    }
+}
+
+/// Order in which a chip expects the color bytes of a pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorOrder {
+    /// WS2812B, SK6812 RGB.
+    Grb,
+    /// Most WS2811 strips.
+    Rgb,
+    /// SK6812 RGBW: the white shared by the three channels moves to the
+    /// white LED.
+    Grbw,
+}
+
+impl ColorOrder {
+    /// Bytes sent per pixel.
+    #[must_use]
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Grb | Self::Rgb => 3,
+            Self::Grbw => 4,
+        }
+    }
+
+    /// The bytes of `rgb` as sent on the wire, the first
+    /// [`Self::bytes_per_pixel`] of them.
+    #[must_use]
+    pub fn bytes(self, (r, g, b): Rgb) -> [u8; 4] {
+        match self {
+            Self::Grb => [g, r, b, 0],
+            Self::Rgb => [r, g, b, 0],
+            Self::Grbw => {
+                let w = r.min(g).min(b);
+                [g - w, r - w, b - w, w]
+            }
+        }
+    }
+}
+
+/// Current drawn by a pixel: a constant for the chip plus, per channel, a
+/// share proportional to its duty.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CurrentModel {
+    /// Drawn with all channels off, in microamperes.
+    pub idle_ua: u32,
+    /// Drawn by the red, green and blue channel at 255, in microamperes.
+    pub channel_ua: [u32; 3],
+}
+
+impl CurrentModel {
+    /// WS2812B: about 1 mA idle and 20 mA per channel, 61 mA at full white.
+    pub const WS2812B: Self = Self {
+        idle_ua: 1000,
+        channel_ua: [20_000; 3],
+    };
+
+    fn channels_ua(&self, (r, g, b): Rgb) -> u64 {
+        [r, g, b]
+            .into_iter()
+            .zip(self.channel_ua)
+            .map(|(c, full)| u64::from(c) * u64::from(full) / 255)
+            .sum()
+    }
+
+    /// Estimated draw of `frame`, in milliamperes, rounded up.
+    #[must_use]
+    pub fn estimate_ma(&self, frame: &[Rgb]) -> u32 {
+        let ua: u64 = frame
+            .iter()
+            .map(|&pixel| u64::from(self.idle_ua) + self.channels_ua(pixel))
+            .sum();
+        u32::try_from(ua.div_ceil(1000)).unwrap_or(u32::MAX)
+    }
+}
+
+/// Dim `frame` uniformly until its [`CurrentModel::estimate_ma`] is within
+/// `budget_ma`; 0 means no limit.
+///
+/// The idle current cannot be dimmed away: with a budget below it the frame
+/// turns black.
+pub fn limit_current(frame: &mut [Rgb], budget_ma: u32, model: &CurrentModel) {
+    if budget_ma == 0 {
+        return;
+    }
+
+    let idle = u64::from(model.idle_ua) * frame.len() as u64;
+    let channels: u64 = frame.iter().map(|&pixel| model.channels_ua(pixel)).sum();
+    let available = (u64::from(budget_ma) * 1000).saturating_sub(idle);
+    if channels <= available {
+        return;
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    let scale = |c: u8| (u64::from(c) * available / channels) as u8;
+    for (r, g, b) in frame {
+        (*r, *g, *b) = (scale(*r), scale(*g), scale(*b));
+    }
+}
+
+/// Progress at the end of an effect, in thousandths.
+pub const PROGRESS_END: u16 = 1000;
+
+/// Colors of the sunrise at increasing progress: deep red, orange, warm white.
+const SUNRISE_KEYFRAMES: [(u16, Rgb); 5] = [
+    (0, (255, 0, 0)),
+    (250, (255, 40, 0)),
+    (500, (255, 110, 10)),
+    (750, (255, 170, 70)),
+    (PROGRESS_END, (255, 214, 170)),
+];
+
+#[allow(clippy::cast_possible_truncation)]
+fn lerp(from: u8, to: u8, num: u32, den: u32) -> u8 {
+    let (from, to) = (i64::from(from), i64::from(to));
+    (from + (to - from) * i64::from(num) / i64::from(den)) as u8
+}
+
+/// Color and brightness of a sunrise ending at `target_brightness`, at
+/// `progress` thousandths of its duration.
+///
+/// The color is interpolated between the keyframes. The brightness follows
+/// the square of the progress, so it rises evenly to the eye instead of
+/// jumping at the start.
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn sunrise(progress: u16, target_brightness: u8) -> (Rgb, u8) {
+    let progress = progress.min(PROGRESS_END);
+
+    let next = SUNRISE_KEYFRAMES
+        .iter()
+        .position(|(at, _)| *at >= progress)
+        .unwrap_or(SUNRISE_KEYFRAMES.len() - 1)
+        .max(1);
+    let (from_at, from) = SUNRISE_KEYFRAMES[next - 1];
+    let (to_at, to) = SUNRISE_KEYFRAMES[next];
+    let (num, den) = (u32::from(progress - from_at), u32::from(to_at - from_at));
+    let color = (
+        lerp(from.0, to.0, num, den),
+        lerp(from.1, to.1, num, den),
+        lerp(from.2, to.2, num, den),
+    );
+
+    let end = u64::from(PROGRESS_END);
+    let brightness =
+        u64::from(target_brightness) * u64::from(progress) * u64::from(progress) / (end * end);
+
+    (color, brightness as u8)
+}
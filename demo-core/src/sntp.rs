@@ -0,0 +1,45 @@
+//! SNTP (RFC 4330) client packets, see `wot_esp_thing::sntp`.
+
+/// Seconds from the NTP era (1900) to the Unix epoch (1970).
+pub const NTP_UNIX_OFFSET_S: u64 = 2_208_988_800;
+
+/// Size of an SNTP packet without extensions.
+pub const PACKET_LEN: usize = 48;
+
+/// A client request: version 4, mode 3 (client), everything else zero.
+pub const REQUEST: [u8; PACKET_LEN] = {
+    let mut request = [0; PACKET_LEN];
+    request[0] = (4 << 3) | 3;
+    request
+};
+
+/// The transmit time of the server answer `response`, in milliseconds since
+/// the Unix epoch.
+///
+/// `None` for anything but a synchronized server answer: too short, not in
+/// mode 4 (server), a kiss-o'-death (stratum 0) or an unsynchronized server
+/// (leap indicator 3). Times are taken in NTP era 0, which ends in 2036.
+#[must_use]
+pub fn parse(response: &[u8]) -> Option<u64> {
+    if response.len() < PACKET_LEN {
+        return None;
+    }
+    let leap = response[0] >> 6;
+    let mode = response[0] & 0x7;
+    let stratum = response[1];
+    if mode != 4 || stratum == 0 || leap == 3 {
+        return None;
+    }
+
+    let word = |at: usize| {
+        u64::from(u32::from_be_bytes([
+            response[at],
+            response[at + 1],
+            response[at + 2],
+            response[at + 3],
+        ]))
+    };
+    let seconds = word(40).checked_sub(NTP_UNIX_OFFSET_S)?;
+    let millis = (word(44) * 1000) >> 32;
+    Some(seconds * 1000 + millis)
+}
@@ -0,0 +1,96 @@
+//! Server-Sent Events framing: the bytes `wot_esp_thing::events` writes on
+//! the boards and `demo-sim` writes on the host.
+//!
+//! A `value_changed` event carries its sequence number as the `id:` field,
+//! for `Last-Event-ID`, and the value in the `data:` field:
+//!
+//! ```text
+//! event:value_changed
+//! id:3
+//! data:{"data":21.5}
+//!
+//! ```
+//!
+//! An idle stream gets a bare comment, [`KEEPALIVE`], every `SSE_KEEPALIVE_S`
+//! seconds, see [`keepalive_s`], and a stream the Thing ends gets a
+//! `stream_end` frame holding a comment, which `EventSource` never
+//! dispatches.
+
+use alloc::{format, string::String};
+
+use serde::Serialize;
+#[cfg(not(feature = "bare-events"))]
+use serde::{ser::SerializeStruct, Serializer};
+
+/// The headers of a stream, after `Connection: close`: it has no length,
+/// the Thing closes the connection to end it.
+pub const HEADERS: [(&str, &str); 2] = [
+    ("Cache-Control", "no-cache"),
+    ("Content-Type", "text/event-stream"),
+];
+
+/// Largest serialized `data:` field; a value beyond it is sent as `null`.
+pub const DATA_LEN: usize = 256;
+
+/// A comment keeping an idle stream alive.
+pub const KEEPALIVE: &str = ":\n\n";
+
+/// Seconds after which a stream is ended, and the client expected to
+/// reconnect: an hour.
+pub const MAX_STREAM_LIFETIME_S: u64 = 60 * 60;
+
+/// Seconds between the [`KEEPALIVE`] comments of an unset `SSE_KEEPALIVE_S`.
+pub const DEFAULT_KEEPALIVE_S: u64 = 15;
+
+/// The seconds between the [`KEEPALIVE`] comments of the `SSE_KEEPALIVE_S`
+/// build-time setting, [`DEFAULT_KEEPALIVE_S`] when unset or not a number,
+/// and clamped from 1 to below [`MAX_STREAM_LIFETIME_S`]: a stream always
+/// gets one before it ends.
+#[must_use]
+pub fn keepalive_s(setting: Option<&str>) -> u64 {
+    setting
+        .and_then(|seconds| seconds.trim().parse().ok())
+        .map_or(DEFAULT_KEEPALIVE_S, |seconds: u64| {
+            seconds.clamp(1, MAX_STREAM_LIFETIME_S - 1)
+        })
+}
+
+/// The event data as sent: the value as the `data` member of an object.
+#[cfg(not(feature = "bare-events"))]
+struct Envelope<'a, T>(&'a T);
+
+#[cfg(not(feature = "bare-events"))]
+impl<T: Serialize> Serialize for Envelope<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut envelope = serializer.serialize_struct("Envelope", 1)?;
+        envelope.serialize_field("data", self.0)?;
+        envelope.end()
+    }
+}
+
+/// Serialize `value` as the `data:` field into `buf`: `{"data":<value>}`,
+/// or the value alone with the `bare-events` feature. `None` if it does not
+/// fit in `buf`, when it is sent as `null`.
+pub fn data<'b, T: Serialize>(value: &T, buf: &'b mut [u8]) -> Option<&'b str> {
+    #[cfg(not(feature = "bare-events"))]
+    let len = serde_json_core::to_slice(&Envelope(value), buf).ok()?;
+    #[cfg(feature = "bare-events")]
+    let len = serde_json_core::to_slice(value, buf).ok()?;
+
+    core::str::from_utf8(&buf[..len]).ok()
+}
+
+/// The frame of the event `name` with `data`, and the `id:` field if any.
+#[must_use]
+pub fn event(name: &str, id: Option<u32>, data: &str) -> String {
+    match id {
+        Some(id) => format!("event:{name}\nid:{id}\ndata:{data}\n\n"),
+        None => format!("event:{name}\ndata:{data}\n\n"),
+    }
+}
+
+/// The frame of the event `name` holding the comment `text` alone.
+#[must_use]
+pub fn comment(name: &str, text: &str) -> String {
+    format!("event:{name}\n: {text}\n\n")
+}
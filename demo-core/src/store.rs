@@ -0,0 +1,239 @@
+//! A key-value store on NOR flash, behind `wot_esp_thing::flash`.
+//!
+//! Each write appends a record to a log filling the store, and a read takes
+//! the last record of its key. When the log reaches the end, the store is
+//! compacted: the last value of each key is kept in RAM, every sector is
+//! erased, and the values are written back from the start, so the sectors
+//! are erased evenly.
+//!
+//! A record is an 8-byte header, the key and the value, padded with erased
+//! bytes to the write size of the flash:
+//!
+//! ```text
+//! [RECORD, key length, value length (u16 LE), FNV-1a of key and value (u32 LE)]
+//! ```
+//!
+//! Every write of a key and every sector erase goes through [`Wear`], the
+//! counting hooks `/debug/flash` reports, see [`crate::wear`]. The values
+//! moved by a compaction are not counted as writes of their keys: the erases
+//! it costs are.
+
+use alloc::{vec, vec::Vec};
+
+use embedded_storage::nor_flash::NorFlash;
+
+/// First byte of a record; an erased one ends the log.
+const RECORD: u8 = 0x5a;
+const ERASED: u8 = 0xff;
+const HEADER: usize = 8;
+
+/// Counting hooks, called by [`Store`] for each write and erase it makes.
+pub trait Wear {
+    /// `bytes` were written to `key`, header and padding included.
+    fn write(&mut self, key: &'static str, bytes: usize);
+    /// A sector was erased.
+    fn erase(&mut self);
+}
+
+/// Why a write did not go through.
+#[derive(Debug, PartialEq, Eq)]
+pub enum StoreError<E> {
+    /// The flash reported an error.
+    Flash(E),
+    /// The key is empty or longer than 255 bytes, or the value longer than
+    /// 65535.
+    TooLong,
+    /// The last value of every key, with the new one, does not fit.
+    Full,
+}
+
+/// A key-value store over `flash`, reporting to `wear`.
+pub struct Store<F, W> {
+    flash: F,
+    wear: W,
+    /// Offset of the end of the log.
+    end: u32,
+}
+
+impl<F: NorFlash, W: Wear> Store<F, W> {
+    #[allow(clippy::cast_possible_truncation)]
+    const SECTOR: u32 = F::ERASE_SIZE as u32;
+
+    /// The store on `flash`, its log read from the start to find its end.
+    ///
+    /// A torn or corrupted record ends the log there, and marks the store
+    /// full, so the next write compacts it rather than writing over it.
+    ///
+    /// # Errors
+    ///
+    /// If the flash cannot be read.
+    pub fn new(flash: F, wear: W) -> Result<Self, F::Error> {
+        let mut store = Self {
+            flash,
+            wear,
+            end: 0,
+        };
+        let mut corrupted = false;
+        store.end = store.scan(|_, _| {}, &mut corrupted)?;
+        if corrupted {
+            store.end = store.capacity();
+        }
+        Ok(store)
+    }
+
+    /// Sectors of the store.
+    pub fn sectors(&self) -> u32 {
+        self.capacity() / Self::SECTOR
+    }
+
+    /// The last value written to `key`, `None` if it was never written.
+    ///
+    /// # Errors
+    ///
+    /// If the flash cannot be read.
+    pub fn read(&mut self, key: &str) -> Result<Option<Vec<u8>>, F::Error> {
+        let mut last = None;
+        self.scan(
+            |k, value| {
+                if k == key.as_bytes() {
+                    last = Some(value.to_vec());
+                }
+            },
+            &mut false,
+        )?;
+        Ok(last)
+    }
+
+    /// Append `value` as the last one of `key`, compacting the store first
+    /// if it does not fit.
+    ///
+    /// # Errors
+    ///
+    /// If the key or the value is too long, the store is full of the values
+    /// of other keys, or the flash reports an error.
+    pub fn write(&mut self, key: &'static str, value: &[u8]) -> Result<(), StoreError<F::Error>> {
+        let record = record::<F>(key.as_bytes(), value).ok_or(StoreError::TooLong)?;
+        if self.end as usize + record.len() > self.capacity() as usize {
+            self.compact(key)?;
+        }
+        if self.end as usize + record.len() > self.capacity() as usize {
+            return Err(StoreError::Full);
+        }
+
+        self.flash
+            .write(self.end, &record)
+            .map_err(StoreError::Flash)?;
+        self.wear.write(key, record.len());
+        self.end += len(&record);
+        Ok(())
+    }
+
+    /// Erase every sector and write back the last value of each key but
+    /// `skip`, about to be written.
+    fn compact(&mut self, skip: &str) -> Result<(), StoreError<F::Error>> {
+        let mut live: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+        self.scan(
+            |key, value| match live.iter_mut().find(|(k, _)| k == key) {
+                Some((_, last)) => *last = value.to_vec(),
+                None => live.push((key.to_vec(), value.to_vec())),
+            },
+            &mut false,
+        )
+        .map_err(StoreError::Flash)?;
+
+        for sector_start in (0..self.capacity()).step_by(Self::SECTOR as usize) {
+            self.flash
+                .erase(sector_start, sector_start + Self::SECTOR)
+                .map_err(StoreError::Flash)?;
+            self.wear.erase();
+        }
+        self.end = 0;
+
+        for (key, value) in live.iter().filter(|(key, _)| key != skip.as_bytes()) {
+            let Some(record) = record::<F>(key, value) else {
+                continue;
+            };
+            if self.end as usize + record.len() > self.capacity() as usize {
+                return Err(StoreError::Full);
+            }
+            self.flash
+                .write(self.end, &record)
+                .map_err(StoreError::Flash)?;
+            self.end += len(&record);
+        }
+        Ok(())
+    }
+
+    /// Call `f` with the key and value of each valid record, in the order
+    /// they were written, and return the end of the log. `corrupted` is set
+    /// if a record that is neither valid nor erased ends it.
+    fn scan(
+        &mut self,
+        mut f: impl FnMut(&[u8], &[u8]),
+        corrupted: &mut bool,
+    ) -> Result<u32, F::Error> {
+        let mut offset = 0;
+        let mut header = vec![0; padded::<F>(HEADER)];
+
+        while offset as usize + header.len() <= self.capacity() as usize {
+            self.flash.read(offset, &mut header)?;
+            if header[..HEADER].iter().all(|b| *b == ERASED) {
+                break;
+            }
+            let key_len = usize::from(header[1]);
+            let value_len = usize::from(u16::from_le_bytes([header[2], header[3]]));
+            let size = padded::<F>(HEADER + key_len + value_len);
+            if header[0] != RECORD || offset as usize + size > self.capacity() as usize {
+                *corrupted = true;
+                break;
+            }
+
+            let mut record = vec![0; size];
+            self.flash.read(offset, &mut record)?;
+            let (key, value) = record[HEADER..HEADER + key_len + value_len].split_at(key_len);
+            if header[4..HEADER] != checksum(key, value).to_le_bytes() {
+                *corrupted = true;
+                break;
+            }
+            f(key, value);
+            offset += len(&record);
+        }
+        Ok(offset)
+    }
+
+    fn capacity(&self) -> u32 {
+        u32::try_from(self.flash.capacity()).unwrap_or(u32::MAX) / Self::SECTOR * Self::SECTOR
+    }
+}
+
+/// The record of `value` for `key`, `None` if either is too long.
+fn record<F: NorFlash>(key: &[u8], value: &[u8]) -> Option<Vec<u8>> {
+    let key_len = u8::try_from(key.len()).ok().filter(|len| *len > 0)?;
+    let value_len = u16::try_from(value.len()).ok()?;
+
+    let mut record = vec![ERASED; padded::<F>(HEADER + key.len() + value.len())];
+    record[0] = RECORD;
+    record[1] = key_len;
+    record[2..4].copy_from_slice(&value_len.to_le_bytes());
+    record[4..HEADER].copy_from_slice(&checksum(key, value).to_le_bytes());
+    record[HEADER..HEADER + key.len()].copy_from_slice(key);
+    record[HEADER + key.len()..HEADER + key.len() + value.len()].copy_from_slice(value);
+    Some(record)
+}
+
+/// `len` rounded up to the read and write sizes of the flash.
+fn padded<F: NorFlash>(len: usize) -> usize {
+    len.next_multiple_of(F::WRITE_SIZE.max(F::READ_SIZE))
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn len(record: &[u8]) -> u32 {
+    record.len() as u32
+}
+
+/// FNV-1a of `key` then `value`.
+fn checksum(key: &[u8], value: &[u8]) -> u32 {
+    key.iter().chain(value).fold(0x811c_9dc5, |hash, b| {
+        (hash ^ u32::from(*b)).wrapping_mul(0x0100_0193)
+    })
+}
@@ -0,0 +1,11 @@
+//! When a task heartbeat is stale, see `wot_esp_thing::tasks`.
+
+/// Periods a task may miss before it is flagged.
+pub const STALE_PERIODS: u64 = 3;
+
+/// Whether a task expected to beat every `period_s` seconds, last heard of
+/// `age_s` seconds ago, is stale.
+#[must_use]
+pub fn is_stale(age_s: u64, period_s: u64) -> bool {
+    age_s > period_s.saturating_mul(STALE_PERIODS)
+}
@@ -0,0 +1,495 @@
+//! Post-processing applied to every Thing Description before it is served.
+
+use alloc::{format, string::String, vec::Vec};
+
+use serde_json::{json, Map, Value};
+
+/// A TD-level link to another resource served by the Thing.
+pub struct TdLink {
+    pub href: &'static str,
+    pub rel: &'static str,
+    pub content_type: Option<&'static str>,
+}
+
+/// A write-only property forwarding writes to the sibling Things, see
+/// `wot_esp_thing::group`.
+pub struct GroupProperty {
+    /// Name of the group property, e.g. `groupOn`.
+    pub name: &'static str,
+    /// The property written on every sibling, whose schema it takes.
+    pub property: &'static str,
+}
+
+/// Metadata merged into the TD produced by a Thing's builder.
+pub struct TdMetadata {
+    /// Becomes `version.instance`.
+    pub version: &'static str,
+    /// Becomes `support`, omitted when `None`.
+    pub support: Option<&'static str>,
+    /// Appended to `links`.
+    pub links: &'static [TdLink],
+    /// Added to `properties`.
+    pub group: &'static [GroupProperty],
+}
+
+/// The media type of a TD.
+pub const TD_MEDIA_TYPE: &str = "application/td+json";
+
+/// `Vary` of the TD, served gzipped to the clients accepting it.
+pub const TD_VARY: &str = "Accept-Encoding";
+
+/// `Cache-Control` of the TD, which changes with the identity of the Thing:
+/// a copy is revalidated with its ETag on every use.
+pub const TD_CACHE_CONTROL: &str = "no-cache";
+
+/// The headers of the TD served at `/` with `etag`, see
+/// [`crate::integrity::etag`], `gzip` or not: the same on the boards and in
+/// the simulator.
+#[must_use]
+pub fn headers(etag: &str, gzip: bool) -> Vec<(&'static str, &str)> {
+    let mut headers = alloc::vec![
+        ("Content-Type", TD_MEDIA_TYPE),
+        ("Vary", TD_VARY),
+        ("Cache-Control", TD_CACHE_CONTROL),
+        ("ETag", etag),
+    ];
+    if gzip {
+        headers.insert(1, ("Content-Encoding", "gzip"));
+    }
+    headers
+}
+
+/// The headers of HTTP 304 to a client holding the TD tagged `etag`.
+#[must_use]
+pub fn not_modified_headers(etag: &str) -> [(&'static str, &str); 3] {
+    [
+        ("ETag", etag),
+        ("Vary", TD_VARY),
+        ("Cache-Control", TD_CACHE_CONTROL),
+    ]
+}
+
+/// Serialize `thing` with the common `version`, `support` and `links` members
+/// and the affordances every Thing exposes.
+///
+/// # Errors
+///
+/// Returns an error if the TD cannot be serialized.
+pub fn serialize_with_metadata(
+    thing: &wot_td::Thing,
+    meta: &TdMetadata,
+) -> Result<String, serde_json::Error> {
+    let mut td = serde_json::to_value(thing)?;
+
+    if let Value::Object(td) = &mut td {
+        merge_metadata(td, meta);
+    }
+
+    serde_json::to_string(&td)
+}
+
+/// Member of a [`fallback`] TD holding the error of the builder.
+pub const TD_ERROR: &str = "tdError";
+
+/// The TD served in place of the one of the Thing `name` when its builder
+/// failed with `error`: its id, title and base, `meta` and the affordances
+/// every Thing exposes, but none of its own, and the error as [`TD_ERROR`].
+///
+/// # Errors
+///
+/// Returns an error if the TD cannot be serialized.
+pub fn fallback(
+    name: &str,
+    base_uri: &str,
+    id: &str,
+    error: &str,
+    meta: &TdMetadata,
+) -> Result<String, serde_json::Error> {
+    let mut td = Map::new();
+    td.insert(
+        "@context".into(),
+        "https://www.w3.org/2022/wot/td/v1.1".into(),
+    );
+    td.insert("id".into(), id.into());
+    td.insert("title".into(), name.into());
+    td.insert("base".into(), base_uri.into());
+    td.insert(
+        "securityDefinitions".into(),
+        json!({ "nosec_sc": { "scheme": "nosec" } }),
+    );
+    td.insert("security".into(), "nosec_sc".into());
+    td.insert(TD_ERROR.into(), error.into());
+    merge_metadata(&mut td, meta);
+
+    serde_json::to_string(&td)
+}
+
+/// Add `meta` and the affordances every Thing exposes to the TD object `td`.
+pub fn merge_metadata(td: &mut Map<String, Value>, meta: &TdMetadata) {
+    merge_affordances(td);
+    merge_group(td, meta.group);
+    #[cfg(not(feature = "bare-events"))]
+    envelope_events(td);
+
+    td.insert("version".into(), json!({ "instance": meta.version }));
+
+    if let Some(support) = meta.support {
+        td.insert("support".into(), Value::String(support.into()));
+    }
+
+    if meta.links.is_empty() {
+        return;
+    }
+
+    let links = td
+        .entry("links")
+        .or_insert_with(|| Value::Array(alloc::vec::Vec::new()));
+
+    if let Value::Array(links) = links {
+        links.extend(meta.links.iter().map(|link| {
+            let mut l = json!({ "href": link.href, "rel": link.rel });
+            if let Some(ty) = link.content_type {
+                l["type"] = Value::String(ty.into());
+            }
+            l
+        }));
+    }
+}
+
+/// Add the `online_since`, `network`, `txPower`, `lastSelfTest` and
+/// `configuration` properties, the `selfTest` action, the `shutdown` action
+/// with the `shutdown` feature and the `connectivity` event to `td`.
+///
+/// All are served by every Thing built with the same features, see `wot_esp_thing::connectivity`,
+/// `wot_esp_thing::SelfTest`, `wot_esp_thing::shutdown` and
+/// `crate::configuration`.
+fn merge_affordances(td: &mut Map<String, Value>) {
+    let online_since = json!({
+        "title": "Online since",
+        "description": "Uptime in milliseconds at the last successful Wi-Fi association",
+        "type": "integer",
+        "unit": "ms",
+        "minimum": 0,
+        "readOnly": true,
+        "forms": [{
+            "href": "/properties/online_since",
+            "op": ["readproperty"],
+            "contentType": "application/json",
+        }],
+    });
+
+    let network = json!({
+        "title": "Network",
+        "description": "IPv4 configuration of the station, from DHCP, the regulatory \
+                        settings of the radio, and whether the free heap is below the \
+                        low-memory threshold",
+        "type": "object",
+        "properties": {
+            "ip": { "type": "string", "format": "ipv4" },
+            "prefix": { "type": "integer", "minimum": 0, "maximum": 32 },
+            "gateway": { "type": "string", "format": "ipv4" },
+            "dns": { "type": "array", "items": { "type": "string", "format": "ipv4" } },
+            "mac": { "type": "string" },
+            "country": { "type": "string" },
+            "maxTxPower": { "type": "integer", "unit": "dBm" },
+            "heapLow": { "type": "boolean" },
+        },
+        "required": ["dns", "mac", "heapLow"],
+        "readOnly": true,
+        "forms": [{
+            "href": "/properties/network",
+            "op": ["readproperty"],
+            "contentType": "application/json",
+        }],
+    });
+
+    let tx_power = json!({
+        "title": "Transmit power",
+        "description": "Wi-Fi transmit power, up to the maxTxPower set at build time",
+        "type": "integer",
+        "unit": "dBm",
+        "minimum": 2,
+        "maximum": 20,
+        "forms": [{
+            "href": "/properties/txPower",
+            "op": ["readproperty", "writeproperty"],
+            "contentType": "application/json",
+        }],
+    });
+
+    let connectivity = json!({
+        "title": "Connectivity",
+        "description": "Wi-Fi connection lost or (re)established",
+        "data": {
+            "type": "object",
+            "properties": {
+                "state": { "type": "string", "enum": ["connected", "disconnected"] },
+                "ip": { "type": "string", "format": "ipv4" },
+                "ssid": { "type": "string" },
+            },
+            "required": ["state"],
+        },
+        "forms": [{
+            "href": "/events/connectivity",
+            "op": ["subscribeevent", "unsubscribeevent"],
+            "subprotocol": "sse",
+            "contentType": "text/event-stream",
+        }],
+    });
+
+    let results = json!({
+        "type": "object",
+        "additionalProperties": { "type": "string", "enum": ["ok", "failed", "skipped"] },
+    });
+
+    let self_test = json!({
+        "title": "Self-test",
+        "description": "Run the hardware checks of the Thing, e.g. probe the sensor or \
+                        flash the LED, and report the outcome of each",
+        "idempotent": false,
+        "output": results.clone(),
+        "forms": [{
+            "href": "/actions/selfTest",
+            "op": ["invokeaction"],
+            "contentType": "application/json",
+        }],
+    });
+
+    let last_self_test = json!({
+        "title": "Last self-test",
+        "description": "Outcome of the last selfTest and the uptime in milliseconds it ran \
+                        at, and with the self-test feature of each read of the start-up \
+                        check, null before either",
+        "type": "object",
+        "properties": {
+            "at": { "type": "integer", "unit": "ms", "minimum": 0 },
+            "results": results.clone(),
+            "startup": results,
+        },
+        "readOnly": true,
+        "forms": [{
+            "href": "/properties/lastSelfTest",
+            "op": ["readproperty"],
+            "contentType": "application/json",
+        }],
+    });
+
+    let configuration = json!({
+        "title": "Configuration",
+        "description": "What the firmware runs with, as built: board and pins, features, heap, \
+                        web tasks and their buffers, and the sampling interval",
+        "type": "object",
+        "properties": {
+            "name": { "type": "string" },
+            "version": { "type": "string" },
+            "board": { "type": "string", "description": "null without a pin map" },
+            "pins": { "type": "object", "additionalProperties": { "type": "integer" } },
+            "features": { "type": "array", "items": { "type": "string" } },
+            "heapSize": { "type": "integer", "unit": "byte" },
+            "webTasks": { "type": "integer" },
+            "sockets": { "type": "integer" },
+            "tcpRxBuffer": { "type": "integer", "unit": "byte" },
+            "tcpTxBuffer": { "type": "integer", "unit": "byte" },
+            "httpBuffer": { "type": "integer", "unit": "byte" },
+            "sampleInterval": {
+                "type": "integer",
+                "unit": "ms",
+                "description": "null for a Thing that does not sample",
+            },
+        },
+        "readOnly": true,
+        "forms": [{
+            "href": "/properties/configuration",
+            "op": ["readproperty"],
+            "contentType": "application/json",
+        }],
+    });
+
+    let affordances = [
+        ("properties", "online_since", online_since),
+        ("properties", "network", network),
+        ("properties", "txPower", tx_power),
+        ("properties", "lastSelfTest", last_self_test),
+        ("properties", "configuration", configuration),
+        ("actions", "selfTest", self_test),
+        ("events", "connectivity", connectivity),
+    ];
+    #[cfg(feature = "shutdown")]
+    let affordances = affordances
+        .into_iter()
+        .chain([("actions", "shutdown", shutdown())]);
+
+    for (member, name, affordance) in affordances {
+        if let Value::Object(affordances) = td
+            .entry(member)
+            .or_insert_with(|| Value::Object(Map::new()))
+        {
+            affordances.insert(name.into(), affordance);
+        }
+    }
+}
+
+/// The `shutdown` action, see `wot_esp_thing::shutdown`.
+#[cfg(feature = "shutdown")]
+fn shutdown() -> Value {
+    json!({
+        "title": "Shut down",
+        "description": "Withdraw the mDNS records, refuse new connections and enter deep \
+                        sleep until the wake-up timer, a reset or a power cycle. Needs \
+                        Authorization: Bearer <WRITE_TOKEN>",
+        "idempotent": true,
+        "forms": [{
+            "href": "/actions/shutdown",
+            "op": ["invokeaction"],
+        }],
+    })
+}
+
+/// Wrap the `data` schema of every event of `td` in an object whose `data`
+/// member it becomes, as `wot_esp_thing::events` sends `{"data":<value>}`.
+#[cfg(not(feature = "bare-events"))]
+fn envelope_events(td: &mut Map<String, Value>) {
+    let Some(Value::Object(events)) = td.get_mut("events") else {
+        return;
+    };
+
+    for event in events.values_mut() {
+        if let Some(data) = event.get_mut("data") {
+            *data = json!({
+                "type": "object",
+                "properties": { "data": data.take() },
+                "required": ["data"],
+            });
+        }
+    }
+}
+
+/// Add the `group` properties to `td`, with the schema of the property they
+/// forward to.
+fn merge_group(td: &mut Map<String, Value>, group: &[GroupProperty]) {
+    let Some(Value::Object(properties)) = td.get_mut("properties") else {
+        return;
+    };
+
+    for g in group {
+        let Some(Value::Object(property)) = properties.get(g.property) else {
+            continue;
+        };
+
+        let mut affordance = property.clone();
+        for member in ["@type", "observable", "readOnly", "forms"] {
+            affordance.remove(member);
+        }
+        affordance.insert("title".into(), format!("Group {}", g.property).into());
+        affordance.insert(
+            "description".into(),
+            format!(
+                "Written to `{}` of every sibling Thing found over mDNS; the response \
+                 lists the outcome per sibling",
+                g.property
+            )
+            .into(),
+        );
+        affordance.insert("writeOnly".into(), true.into());
+        affordance.insert(
+            "forms".into(),
+            json!([{
+                "href": format!("/properties/{}", g.name),
+                "op": ["writeproperty"],
+                "contentType": "application/json",
+            }]),
+        );
+
+        properties.insert(g.name.into(), Value::Object(affordance));
+    }
+}
+
+/// Make `base` the base of `td`, e.g. the public URL of a reverse proxy.
+///
+/// Relative hrefs become absolute under `base`, so that they keep any path
+/// prefix of it, and absolute hrefs under the previous base move along.
+pub fn rebase(td: &mut Map<String, Value>, base: &str) {
+    let old = match td.get("base") {
+        Some(Value::String(old)) => String::from(old.trim_end_matches('/')),
+        _ => String::new(),
+    };
+    let new = base.trim_end_matches('/');
+
+    for value in td.values_mut() {
+        rebase_hrefs(value, &old, new);
+    }
+    td.insert("base".into(), Value::String(base.into()));
+}
+
+/// [`rebase`] the serialized TD `td`.
+///
+/// # Errors
+///
+/// Returns an error if `td` is not a JSON object.
+pub fn rebase_json(td: &str, base: &str) -> Result<String, serde_json::Error> {
+    let mut td: Map<String, Value> = serde_json::from_str(td)?;
+    rebase(&mut td, base);
+    serde_json::to_string(&td)
+}
+
+fn rebase_hrefs(value: &mut Value, old: &str, new: &str) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                match value {
+                    Value::String(href) if key == "href" => {
+                        if let Some(rebased) = rebase_href(href, old, new) {
+                            *href = rebased;
+                        }
+                    }
+                    value => rebase_hrefs(value, old, new),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                rebase_hrefs(value, old, new);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn rebase_href(href: &str, old: &str, new: &str) -> Option<String> {
+    if href.starts_with('/') && !href.starts_with("//") {
+        return Some(format!("{new}{href}"));
+    }
+
+    let path = href
+        .strip_prefix(old)
+        .filter(|path| !old.is_empty() && (path.is_empty() || path.starts_with(['/', '?', '#'])))?;
+    Some(format!("{new}{path}"))
+}
+
+/// The base of the TD as seen through a reverse proxy, from the
+/// `X-Forwarded-Host` and `X-Forwarded-Proto` request headers.
+///
+/// Only the first value of each header counts, the one set by the proxy
+/// closest to the client. The protocol defaults to `http`; a missing host,
+/// or one that could not be a host name, gives `None`.
+#[must_use]
+pub fn forwarded_base(host: Option<&str>, proto: Option<&str>) -> Option<String> {
+    fn first(header: &str) -> &str {
+        header.split(',').next().unwrap_or("").trim()
+    }
+
+    let host = first(host?);
+    let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']');
+    if host.is_empty() || !host.chars().all(valid) {
+        return None;
+    }
+
+    let proto = match proto.map(first) {
+        None => "http",
+        Some(proto) if proto.eq_ignore_ascii_case("http") => "http",
+        Some(proto) if proto.eq_ignore_ascii_case("https") => "https",
+        Some(_) => return None,
+    };
+
+    Some(format!("{proto}://{host}"))
+}
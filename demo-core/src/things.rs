@@ -0,0 +1,16 @@
+//! Thing Descriptions of the demos and the logic deciding when they emit
+//! events.
+
+pub mod button;
+pub mod fan;
+pub mod light;
+pub mod multitherm;
+pub mod thermometer;
+
+/// Whether the temperature moved enough from `last` to `now`, in degrees
+/// Celsius, to be worth an event: at least a tenth of a degree either way.
+#[must_use]
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn temperature_changed(last: f32, now: f32) -> bool {
+    ((last - now).abs() * 100f32) as u32 / 10 != 0
+}
@@ -0,0 +1,201 @@
+//! The toggle button Thing (`demo-c3`, `button`).
+//!
+//! A board may list several buttons, see `boards/pins.rs`: each toggles
+//! its own `onN` property, N from 0 in the order of the pins, and the `raw`
+//! event tells them apart with a `button` member. A single button is `on`,
+//! as it always was.
+
+use alloc::{format, string::String};
+
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use wot_td::{
+    builder::{
+        BuildableDataSchema, BuildableHumanReadableInfo, BuildableInteractionAffordance,
+        EnumerableDataSchema, Error, IntegerDataSchemaBuilderLike, ObjectDataSchemaBuilderLike,
+        ReadableWriteableDataSchema, SpecializableDataSchema,
+    },
+    thing::FormOperation,
+    Thing,
+};
+
+use crate::forms::{sse_event_form, sse_observe_form};
+
+/// Buttons a board may list.
+pub const MAX_BUTTONS: usize = 4;
+
+/// The `on` affordances of a button: property name, property and event
+/// href.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Toggle {
+    pub name: &'static str,
+    pub property: &'static str,
+    pub event: &'static str,
+}
+
+const SINGLE: Toggle = Toggle {
+    name: "on",
+    property: "/properties/on",
+    event: "/events/on",
+};
+
+const TOGGLES: [Toggle; MAX_BUTTONS] = [
+    Toggle {
+        name: "on0",
+        property: "/properties/on0",
+        event: "/events/on0",
+    },
+    Toggle {
+        name: "on1",
+        property: "/properties/on1",
+        event: "/events/on1",
+    },
+    Toggle {
+        name: "on2",
+        property: "/properties/on2",
+        event: "/events/on2",
+    },
+    Toggle {
+        name: "on3",
+        property: "/properties/on3",
+        event: "/events/on3",
+    },
+];
+
+/// The affordances of `button` on a board with `buttons`: `on` when it is
+/// the only one, `onN` otherwise, also for a button past them.
+///
+/// # Panics
+///
+/// If `button` is not below [`MAX_BUTTONS`].
+#[must_use]
+pub fn toggle(button: usize, buttons: usize) -> Toggle {
+    if button == 0 && buttons == 1 {
+        SINGLE
+    } else {
+        TOGGLES[button]
+    }
+}
+
+/// A debounced transition of a button, sent as the `raw` event data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EdgeEvent {
+    /// Index of the button among several, `None` for the only one.
+    pub button: Option<u8>,
+    pub pressed: bool,
+    /// Uptime of the transition in milliseconds.
+    pub uptime_ms: u64,
+}
+
+impl EdgeEvent {
+    /// Name of the edge in the event data.
+    #[must_use]
+    pub fn edge(&self) -> &'static str {
+        if self.pressed {
+            "pressed"
+        } else {
+            "released"
+        }
+    }
+}
+
+impl Serialize for EdgeEvent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut event =
+            serializer.serialize_struct("EdgeEvent", 2 + usize::from(self.button.is_some()))?;
+        if let Some(button) = self.button {
+            event.serialize_field("button", &button)?;
+        }
+        event.serialize_field("edge", self.edge())?;
+        event.serialize_field("uptime_ms", &self.uptime_ms)?;
+        event.end()
+    }
+}
+
+/// The Thing Description of a single button, as served at `/`.
+///
+/// # Errors
+///
+/// Returns the error of the builder, e.g. for a malformed affordance.
+pub fn td(name: &str, base_uri: String, id: String) -> Result<Thing, Error> {
+    td_with_buttons(name, base_uri, id, 1)
+}
+
+/// The Thing Description of a board with `buttons`, at most
+/// [`MAX_BUTTONS`]: one `on` property and event per button, see [`toggle`].
+///
+/// # Errors
+///
+/// Returns the error of the builder, e.g. for a malformed affordance.
+pub fn td_with_buttons(
+    name: &str,
+    base_uri: String,
+    id: String,
+    buttons: usize,
+) -> Result<Thing, Error> {
+    let mut thing = Thing::builder(name)
+        .finish_extend()
+        .id(id)
+        .base(base_uri)
+        .description("Example Thing exposing a toggle button")
+        .security(|builder| builder.no_sec().required().with_key("nosec_sc"));
+
+    for button in 0..buttons {
+        let toggle = toggle(button, buttons);
+        thing = thing
+            .property(toggle.name, |p| {
+                let p = p
+                    .finish_extend_data_schema()
+                    .attype("OnOffProperty")
+                    .title("On/Off");
+                let p = if buttons == 1 {
+                    p.description("On if the property is true, off otherwise")
+                } else {
+                    p.description(format!(
+                        "Toggled by button {button}, on if the property is true"
+                    ))
+                };
+                p.form(|f| {
+                    f.href(toggle.property)
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .form(|f| sse_observe_form(f, toggle.event))
+                .observable(true)
+                .bool()
+                .read_only()
+            })
+            .event(toggle.name, |b| {
+                b.data(|b| b.finish_extend().bool())
+                    .form(|f| sse_event_form(f, toggle.event))
+            });
+    }
+
+    thing
+        .event("raw", |b| {
+            b.description("Every press and release, in order, for automations reacting to holds")
+                .data(|b| {
+                    let b = b.finish_extend().object();
+                    let b = if buttons == 1 {
+                        b
+                    } else {
+                        b.property("button", true, |b| {
+                            b.finish_extend()
+                                .integer()
+                                .minimum(0)
+                                .maximum(buttons as i64 - 1)
+                                .description("The button, as N in onN")
+                        })
+                    };
+                    b.property("edge", true, |b| {
+                        b.finish_extend()
+                            .enumeration("pressed")
+                            .enumeration("released")
+                    })
+                    .property("uptime_ms", true, |b| {
+                        b.finish_extend().integer().minimum(0).unit("ms")
+                    })
+                })
+                .form(|f| sse_event_form(f, "/events/raw"))
+        })
+        .build()
+}
@@ -0,0 +1,148 @@
+//! The fan controller Thing (`demo-c6`, `fan`).
+
+use alloc::string::String;
+
+use wot_td::{
+    builder::{
+        BuildableDataSchema, BuildableHumanReadableInfo, BuildableInteractionAffordance, Error,
+        IntegerDataSchemaBuilderLike, ReadableWriteableDataSchema, SpecializableDataSchema,
+    },
+    thing::FormOperation,
+    Thing,
+};
+
+use crate::forms::{sse_event_form, sse_observe_form};
+
+/// The Thing Description, as served at `/`.
+///
+/// # Errors
+///
+/// Returns the error of the builder, e.g. for a malformed affordance.
+pub fn td(name: &str, base_uri: String, id: String) -> Result<Thing, Error> {
+    Thing::builder(name)
+        .finish_extend()
+        .id(id)
+        .base(base_uri)
+        .description("Noctua 5V fan controller with SHT41 sensor; BOOT toggles fan on/off")
+        .security(|builder| builder.no_sec().required().with_key("nosec_sc"))
+        .property("temperature", |p| {
+            p.finish_extend_data_schema()
+                .attype("TemperatureProperty")
+                .title("Temperature")
+                .description("Ambient temperature from SHT41")
+                .form(|f| {
+                    f.href("/properties/temperature")
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .form(|f| sse_observe_form(f, "/events/temperature"))
+                .observable(true)
+                .number()
+                .read_only()
+                .unit("Celsius")
+        })
+        .property("humidity", |p| {
+            p.finish_extend_data_schema()
+                .attype("HumidityProperty")
+                .title("Humidity")
+                .description("Relative humidity from SHT41")
+                .form(|f| {
+                    f.href("/properties/humidity")
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .number()
+                .read_only()
+                .unit("%")
+        })
+        .property("die_temperature", |p| {
+            p.finish_extend_data_schema()
+                .attype("TemperatureProperty")
+                .title("Die temperature")
+                .description("ESP32-C6 internal die temperature")
+                .form(|f| {
+                    f.href("/properties/die_temperature")
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .number()
+                .read_only()
+                .unit("Celsius")
+        })
+        .property("on", |p| {
+            p.finish_extend_data_schema()
+                .attype("OnOffProperty")
+                .title("Fan on/off")
+                .description(
+                    "Whether the fan is running. Writable over HTTP; toggled by the BOOT button",
+                )
+                .form(|f| {
+                    f.href("/properties/on")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .form(|f| sse_observe_form(f, "/events/on"))
+                .observable(true)
+                .bool()
+        })
+        .property("speed", |p| {
+            p.finish_extend_data_schema()
+                .attype("LevelProperty")
+                .title("Fan speed")
+                .description("Fan PWM duty cycle (0-100%)")
+                .form(|f| {
+                    f.href("/properties/speed")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .integer()
+                .minimum(0)
+                .maximum(100)
+                .unit("percent")
+        })
+        .property("rpm", |p| {
+            p.finish_extend_data_schema()
+                .attype("SpeedProperty")
+                .title("Fan RPM")
+                .description("Measured fan speed in revolutions per minute")
+                .form(|f| {
+                    f.href("/properties/rpm")
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .form(|f| sse_observe_form(f, "/events/rpm"))
+                .observable(true)
+                .integer()
+                .read_only()
+                .unit("rpm")
+        })
+        .event("on", |b| {
+            b.data(|b| b.finish_extend().bool())
+                .form(|f| sse_event_form(f, "/events/on"))
+        })
+        .event("temperature", |b| {
+            b.data(|b| b.finish_extend().number().unit("Celsius"))
+                .form(|f| sse_event_form(f, "/events/temperature"))
+        })
+        .event("rpm", |b| {
+            b.data(|b| b.finish_extend().integer().unit("rpm"))
+                .form(|f| sse_event_form(f, "/events/rpm"))
+        })
+        .build()
+}
+
+/// Fan speed from the tachometer `pulses` counted over one second; the fan
+/// gives two pulses per revolution.
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn rpm(pulses: i16) -> i16 {
+    (i32::from(pulses) * 60 / 2) as i16
+}
+
+/// Whether the speed moved enough from `last` to `now` to be worth an event.
+#[must_use]
+pub fn rpm_changed(last: i16, now: i16) -> bool {
+    (i32::from(now) - i32::from(last)).unsigned_abs() / 10 != 0
+}
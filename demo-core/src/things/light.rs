@@ -0,0 +1,717 @@
+//! The RGB light source Thing (`demo-c3`, `light`).
+
+use alloc::string::String;
+
+use serde::{Deserialize, Serialize};
+use wot_td::{
+    builder::{
+        BuildableDataSchema, BuildableHumanReadableInfo, BuildableInteractionAffordance,
+        EnumerableDataSchema, Error, IntegerDataSchemaBuilderLike, ObjectDataSchemaBuilderLike,
+        ReadableWriteableDataSchema, SpecializableDataSchema,
+    },
+    thing::FormOperation,
+    Thing,
+};
+
+use crate::{
+    capabilities::Hardware,
+    schema::{InputSchema, Member, Schema},
+    smartled::{Rgb, TimingProfile},
+};
+
+/// The Thing Description, as served at `/`.
+///
+/// # Errors
+///
+/// Returns the error of the builder, e.g. for a malformed affordance.
+pub fn td(name: &str, base_uri: String, id: String) -> Result<Thing, Error> {
+    Thing::builder(name)
+        .finish_extend()
+        .id(id)
+        .base(base_uri)
+        .description("Example Thing controlling a light source")
+        .security(|builder| builder.no_sec().required().with_key("nosec_sc"))
+        .property("on", |p| {
+            p.finish_extend_data_schema()
+                .attype("OnOffProperty")
+                .title("On/Off")
+                .description("The light source is on if the property is true, off otherwise")
+                .form(|f| {
+                    f.href("/properties/on")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .bool()
+        })
+        .property("brightness", |p| {
+            p.finish_extend_data_schema()
+                .attype("BrightnessProperty")
+                .title("Light source brightness")
+                .description("Light source color expressed as 8bit rgb")
+                .form(|f| {
+                    f.href("/properties/brightness")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .integer()
+                .minimum(0)
+                .maximum(255)
+        })
+        .property("color", |p| {
+            p.finish_extend_data_schema()
+                .attype("ColorProperty")
+                .title("Light source color")
+                .description("Light source color expressed as 8bit rgb")
+                .form(|f| {
+                    f.href("/properties/color")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .object()
+                .property("r", true, |b| {
+                    b.finish_extend()
+                        .integer()
+                        .title("Red")
+                        .minimum(0)
+                        .maximum(255)
+                })
+                .property("g", true, |b| {
+                    b.finish_extend()
+                        .integer()
+                        .title("Green")
+                        .minimum(0)
+                        .maximum(255)
+                })
+                .property("b", true, |b| {
+                    b.finish_extend()
+                        .integer()
+                        .title("Blue")
+                        .minimum(0)
+                        .maximum(255)
+                })
+        })
+        .property("accentOn", |p| {
+            p.finish_extend_data_schema()
+                .attype("OnOffProperty")
+                .title("Accent On/Off")
+                .description("The accent strip is on if the property is true, off otherwise")
+                .form(|f| {
+                    f.href("/properties/accent/on")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .bool()
+        })
+        .property("accentBrightness", |p| {
+            p.finish_extend_data_schema()
+                .attype("BrightnessProperty")
+                .title("Accent strip brightness")
+                .form(|f| {
+                    f.href("/properties/accent/brightness")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .integer()
+                .minimum(0)
+                .maximum(255)
+        })
+        .property("accentColor", |p| {
+            p.finish_extend_data_schema()
+                .attype("ColorProperty")
+                .title("Accent strip color")
+                .description("Color of every pixel of the accent strip, expressed as 8bit rgb")
+                .form(|f| {
+                    f.href("/properties/accent/color")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .object()
+                .property("r", true, |b| {
+                    b.finish_extend()
+                        .integer()
+                        .title("Red")
+                        .minimum(0)
+                        .maximum(255)
+                })
+                .property("g", true, |b| {
+                    b.finish_extend()
+                        .integer()
+                        .title("Green")
+                        .minimum(0)
+                        .maximum(255)
+                })
+                .property("b", true, |b| {
+                    b.finish_extend()
+                        .integer()
+                        .title("Blue")
+                        .minimum(0)
+                        .maximum(255)
+                })
+        })
+        .property("offTimerRemaining", |p| {
+            p.finish_extend_data_schema()
+                .title("Off timer remaining")
+                .description("Seconds until the off timer turns the light off, null if none runs")
+                .form(|f| {
+                    f.href("/properties/offTimerRemaining")
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .integer()
+                .minimum(0)
+                .read_only()
+                .unit("s")
+        })
+        .property("powerBudget_mA", |p| {
+            p.finish_extend_data_schema()
+                .title("Power budget")
+                .description(
+                    "Most current the LEDs may draw: brighter frames are dimmed uniformly to \
+                     fit. 0 means no limit.",
+                )
+                .form(|f| {
+                    f.href("/properties/powerBudget_mA")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .integer()
+                .minimum(0)
+                .unit("mA")
+        })
+        .property("estimatedCurrent_mA", |p| {
+            p.finish_extend_data_schema()
+                .title("Estimated current")
+                .description("Current the LEDs draw for the frame shown, after the power budget")
+                .form(|f| {
+                    f.href("/properties/estimatedCurrent_mA")
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .integer()
+                .minimum(0)
+                .read_only()
+                .unit("mA")
+        })
+        .property("luma", |p| {
+            p.finish_extend_data_schema()
+                .title("Perceived brightness")
+                .description(
+                    "Brightness of the light as the eye perceives it: the Rec. 709 luma of the \
+                     color shown, scaled by the brightness, 0 while off",
+                )
+                .form(|f| {
+                    f.href("/properties/luma")
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .integer()
+                .minimum(0)
+                .maximum(255)
+                .read_only()
+        })
+        .property("ledTimingProfile", |p| {
+            p.finish_extend_data_schema()
+                .title("LED timing profile")
+                .description(
+                    "`standard` sends the WS2812B datasheet timings; `conservative` sets the 0 \
+                     and 1 bits further apart, within the datasheet limits, for cold \
+                     enclosures and long cables where colors glitch",
+                )
+                .form(|f| {
+                    f.href("/properties/ledTimingProfile")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .enumeration(TimingProfile::Standard.as_str())
+                .enumeration(TimingProfile::Conservative.as_str())
+        })
+        .property("lastActiveState", |p| {
+            p.finish_extend_data_schema()
+                .title("Last active state")
+                .description(
+                    "Brightness and color the light comes back at when turned on: those it \
+                     last had while visibly on, or written while off",
+                )
+                .form(|f| {
+                    f.href("/properties/lastActiveState")
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .object()
+                .property("brightness", true, |b| {
+                    b.finish_extend().integer().minimum(1).maximum(255)
+                })
+                .property("color", true, |b| {
+                    b.finish_extend()
+                        .object()
+                        .property("r", true, |b| {
+                            b.finish_extend().integer().minimum(0).maximum(255)
+                        })
+                        .property("g", true, |b| {
+                            b.finish_extend().integer().minimum(0).maximum(255)
+                        })
+                        .property("b", true, |b| {
+                            b.finish_extend().integer().minimum(0).maximum(255)
+                        })
+                })
+                .read_only()
+        })
+        .property("vacationMode", |p| {
+            p.finish_extend_data_schema()
+                .title("Vacation mode")
+                .description(
+                    "Turn the light on and off at random times within the vacationSchedule \
+                     window every evening. Needs the time from SNTP: enabling it before then \
+                     fails with 409. Turning the light on or off by hand suspends it until \
+                     the next scheduled change.",
+                )
+                .form(|f| {
+                    f.href("/properties/vacationMode")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .bool()
+        })
+        .property("vacationSchedule", |p| {
+            p.finish_extend_data_schema()
+                .title("Vacation schedule")
+                .description(
+                    "Daily window of the vacationMode, in hours of the local time: the light \
+                     turns on at startHour and off at endHour, the next day if earlier, each \
+                     moved by up to jitterMin minutes either way at random",
+                )
+                .form(|f| {
+                    f.href("/properties/vacationSchedule")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .object()
+                .property("startHour", true, |b| {
+                    b.finish_extend().integer().minimum(0).maximum(23).unit("h")
+                })
+                .property("endHour", true, |b| {
+                    b.finish_extend().integer().minimum(0).maximum(23).unit("h")
+                })
+                .property("jitterMin", true, |b| {
+                    b.finish_extend()
+                        .integer()
+                        .minimum(0)
+                        .maximum(i64::from(MAX_JITTER_MIN))
+                        .unit("min")
+                })
+                .property("utcOffsetMin", true, |b| {
+                    b.finish_extend()
+                        .integer()
+                        .description("Offset of the local time from UTC")
+                        .minimum(i64::from(MIN_UTC_OFFSET_MIN))
+                        .maximum(i64::from(MAX_UTC_OFFSET_MIN))
+                        .unit("min")
+                })
+        })
+        .action("offTimer", |b| {
+            b.title("Off timer")
+                .description(
+                    "Turn the light off after delay_s seconds, replacing any running timer; \
+                     0 cancels it. Turning the light off by hand cancels it as well.",
+                )
+                .input(|b| {
+                    b.finish_extend().object().property("delay_s", true, |b| {
+                        b.finish_extend()
+                            .integer()
+                            .minimum(0)
+                            .maximum(i64::from(MAX_OFF_DELAY_S))
+                            .unit("s")
+                    })
+                })
+                .form(|f| {
+                    f.href("/actions/offTimer")
+                        .op(FormOperation::InvokeAction)
+                        .content_type("application/json")
+                })
+        })
+        .action("sunrise", |b| {
+            b.title("Sunrise")
+                .description(
+                    "Ramp from off through deep red, orange and warm white to \
+                     target_brightness over duration_s seconds. Writing any property \
+                     cancels it.",
+                )
+                .input(|b| {
+                    b.finish_extend()
+                        .object()
+                        .property("duration_s", true, |b| {
+                            b.finish_extend()
+                                .integer()
+                                .minimum(1)
+                                .maximum(i64::from(MAX_SUNRISE_S))
+                                .unit("s")
+                        })
+                        .property("target_brightness", true, |b| {
+                            b.finish_extend().integer().minimum(0).maximum(255)
+                        })
+                })
+                .form(|f| {
+                    f.href("/actions/sunrise")
+                        .op(FormOperation::InvokeAction)
+                        .content_type("application/json")
+                })
+        })
+        .build()
+}
+
+/// The second LED strip on the `accent_led` pin, which only firmwares built
+/// with the `accent` feature drive, and its affordances.
+pub const ACCENT_STRIP: Hardware = Hardware {
+    name: "accentStrip",
+    affordances: &["accentOn", "accentBrightness", "accentColor"],
+};
+
+/// Longest delay accepted by the `offTimer` action, a day.
+pub const MAX_OFF_DELAY_S: u32 = 24 * 60 * 60;
+
+/// Longest duration accepted by the `sunrise` action, an hour.
+pub const MAX_SUNRISE_S: u32 = 60 * 60;
+
+/// Input of the `offTimer` action.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct OffTimerInput {
+    /// Seconds until the light turns off, at most [`MAX_OFF_DELAY_S`]; 0
+    /// cancels the running timer.
+    pub delay_s: u32,
+}
+
+impl InputSchema for OffTimerInput {
+    const SCHEMA: Schema = Schema::Object(&[Member {
+        name: "delay_s",
+        required: true,
+        schema: Schema::Integer {
+            minimum: Some(0),
+            maximum: Some(MAX_OFF_DELAY_S as i64),
+        },
+    }]);
+}
+
+/// Longest `offTimer` body read, `{"delay_s":86400}` with room for
+/// whitespace.
+pub const OFF_TIMER_MAX_BODY: usize = 64;
+
+/// Input of the `sunrise` action.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SunriseInput {
+    /// Seconds to reach the target, from 1 to [`MAX_SUNRISE_S`].
+    pub duration_s: u32,
+    pub target_brightness: u8,
+}
+
+impl InputSchema for SunriseInput {
+    const SCHEMA: Schema = Schema::Object(&[
+        Member {
+            name: "duration_s",
+            required: true,
+            schema: Schema::Integer {
+                minimum: Some(1),
+                maximum: Some(MAX_SUNRISE_S as i64),
+            },
+        },
+        Member {
+            name: "target_brightness",
+            required: true,
+            schema: Schema::Integer {
+                minimum: Some(0),
+                maximum: Some(255),
+            },
+        },
+    ]);
+}
+
+/// Longest `sunrise` body read, `{"duration_s":3600,"target_brightness":255}`
+/// with room for whitespace.
+pub const SUNRISE_MAX_BODY: usize = 128;
+
+/// The perceived brightness of `color` shown at `brightness`, from 0 to
+/// 255: its Rec. 709 luma, 0.2126 R + 0.7152 G + 0.0722 B, scaled by the
+/// brightness and rounded. Green looks far brighter than blue at the same
+/// value.
+#[must_use]
+pub fn luma((r, g, b): Rgb, brightness: u8) -> u8 {
+    // In ten-thousandths: white is 2 550 000, times 255 still fits a u32.
+    let luma = 2126 * u32::from(r) + 7152 * u32::from(g) + 722 * u32::from(b);
+    let scaled = (luma * u32::from(brightness) + 1_275_000) / 2_550_000;
+    u8::try_from(scaled).unwrap_or(u8::MAX)
+}
+
+/// Whole seconds left, rounded up, until the off timer due at `deadline_ms`
+/// fires, at `now_ms` on the monotonic clock; zero once it is due.
+#[must_use]
+pub fn off_timer_remaining(deadline_ms: u64, now_ms: u64) -> u64 {
+    deadline_ms.saturating_sub(now_ms).div_ceil(1000)
+}
+
+/// The `lastActiveState` property: the brightness and color the light came
+/// back at when last turned on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LastActive<C> {
+    /// Never 0: a light faded to 0 keeps the brightness it had before.
+    pub brightness: u8,
+    pub color: C,
+}
+
+/// What the light shows, with the state it was last visibly on in.
+///
+/// Turning the light on restores that state rather than showing what was set
+/// last, which after a fade to off is brightness 0: the light would look
+/// dead. An animation, e.g. the `sunrise`, shows its frames with
+/// [`Self::animate`] without touching the state restored; any write cancels
+/// it and starts from that state again, so turning the light on mid-fade
+/// shows the light as it was before the fade.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LightState<C> {
+    pub on: bool,
+    pub brightness: u8,
+    pub color: C,
+    last_active: LastActive<C>,
+    /// Whether an animation frame is shown.
+    animating: bool,
+}
+
+impl<C: Copy> LightState<C> {
+    /// Off, to come on at `brightness` and `color`.
+    #[must_use]
+    pub const fn new(brightness: u8, color: C) -> Self {
+        Self {
+            on: false,
+            brightness,
+            color,
+            last_active: LastActive {
+                brightness: if brightness == 0 { 255 } else { brightness },
+                color,
+            },
+            animating: false,
+        }
+    }
+
+    /// The state restored when the light is turned on.
+    #[must_use]
+    pub const fn last_active(&self) -> LastActive<C> {
+        self.last_active
+    }
+
+    /// The brightness the LEDs show, 0 while off.
+    #[must_use]
+    pub const fn shown_brightness(&self) -> u8 {
+        if self.on {
+            self.brightness
+        } else {
+            0
+        }
+    }
+
+    /// Turn the light on at its last active state, or off.
+    pub fn power(&mut self, on: bool) {
+        self.animating = false;
+        self.on = on;
+        if on {
+            self.brightness = self.last_active.brightness;
+            self.color = self.last_active.color;
+        }
+    }
+
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.cancel_animation();
+        self.brightness = brightness;
+        self.remember();
+    }
+
+    pub fn set_color(&mut self, color: C) {
+        self.cancel_animation();
+        self.color = color;
+        self.remember();
+    }
+
+    /// Show a frame of an animation: on, at `brightness` and `color`, with
+    /// the last active state kept.
+    pub fn animate(&mut self, color: C, brightness: u8) {
+        self.animating = true;
+        self.on = true;
+        self.brightness = brightness;
+        self.color = color;
+    }
+
+    /// End the animation on its last frame, which becomes the last active
+    /// state.
+    pub fn settle(&mut self) {
+        self.animating = false;
+        self.remember();
+    }
+
+    /// Back to the last active state, from an animation frame.
+    fn cancel_animation(&mut self) {
+        if self.animating {
+            self.animating = false;
+            self.brightness = self.last_active.brightness;
+            self.color = self.last_active.color;
+        }
+    }
+
+    /// Keep the color, and the brightness unless 0, to restore. A write while
+    /// the light is off counts too: it sets what turning it on shows.
+    fn remember(&mut self) {
+        if self.brightness > 0 {
+            self.last_active.brightness = self.brightness;
+        }
+        self.last_active.color = self.color;
+    }
+}
+
+/// Most minutes a `vacationMode` change moves either way.
+pub const MAX_JITTER_MIN: u16 = 120;
+
+/// Furthest west a local time may be from UTC, in minutes.
+pub const MIN_UTC_OFFSET_MIN: i16 = -12 * 60;
+
+/// Furthest east a local time may be from UTC, in minutes.
+pub const MAX_UTC_OFFSET_MIN: i16 = 14 * 60;
+
+const MINUTE_MS: i64 = 60 * 1000;
+const HOUR_MS: i64 = 60 * MINUTE_MS;
+const DAY_MS: i64 = 24 * HOUR_MS;
+
+/// The `vacationSchedule` property.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VacationSchedule {
+    /// Hour of the local time the light turns on.
+    pub start_hour: u8,
+    /// Hour of the local time the light turns off, the next day if not
+    /// after `start_hour`.
+    pub end_hour: u8,
+    /// Most minutes each change moves either way, at random.
+    pub jitter_min: u16,
+    /// Offset of the local time from UTC, in minutes.
+    pub utc_offset_min: i16,
+}
+
+impl Default for VacationSchedule {
+    /// From 18:00 to 23:00 UTC, give or take half an hour.
+    fn default() -> Self {
+        Self {
+            start_hour: 18,
+            end_hour: 23,
+            jitter_min: 30,
+            utc_offset_min: 0,
+        }
+    }
+}
+
+impl VacationSchedule {
+    /// Length of the window in minutes, before jitter.
+    fn window_min(self) -> u32 {
+        let hours = (u32::from(self.end_hour) + 24 - u32::from(self.start_hour)) % 24;
+        hours * 60
+    }
+
+    /// Check the schedule before it replaces the running one.
+    ///
+    /// # Errors
+    ///
+    /// If an hour or the offset is out of range, the window is empty, or the
+    /// jitter could swap the changes of a day, or overlap those of the next.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.start_hour > 23 || self.end_hour > 23 {
+            return Err("startHour and endHour must be from 0 to 23");
+        }
+        if self.start_hour == self.end_hour {
+            return Err("startHour and endHour must differ");
+        }
+        if self.jitter_min > MAX_JITTER_MIN {
+            return Err("jitterMin must be at most 120");
+        }
+        if !(MIN_UTC_OFFSET_MIN..=MAX_UTC_OFFSET_MIN).contains(&self.utc_offset_min) {
+            return Err("utcOffsetMin must be from -720 to 840");
+        }
+        let jitter = 2 * u32::from(self.jitter_min);
+        let window = self.window_min();
+        if jitter >= window || window + jitter >= 24 * 60 {
+            return Err("jitterMin must be less than half the window and of the time off");
+        }
+        Ok(())
+    }
+
+    /// The window of local day `day`, in milliseconds of the local time:
+    /// when the light turns on and off, jitter included.
+    fn window(self, day: i64, seed: u32) -> (i64, i64) {
+        let on = day * DAY_MS + i64::from(self.start_hour) * HOUR_MS;
+        let off = on + i64::from(self.window_min()) * MINUTE_MS;
+        (
+            on + self.jitter(seed, day, 0),
+            off + self.jitter(seed, day, 1),
+        )
+    }
+
+    /// Whole minutes from `-jitter_min` to `jitter_min`, the same for the
+    /// same `seed`, `day` and `change`.
+    fn jitter(self, seed: u32, day: i64, change: u64) -> i64 {
+        let span = 2 * u64::from(self.jitter_min) + 1;
+        #[allow(clippy::cast_sign_loss)]
+        let mut x = (u64::from(seed) << 32) ^ ((day as u64) << 1) ^ change;
+        // splitmix64
+        x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        x ^= x >> 31;
+        #[allow(clippy::cast_possible_wrap)]
+        let minutes = (x % span) as i64 - i64::from(self.jitter_min);
+        minutes * MINUTE_MS
+    }
+}
+
+/// What the `vacationMode` does at a given time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VacationPlan {
+    /// Whether the light is on.
+    pub on: bool,
+    /// When it changes next, in milliseconds since the Unix epoch.
+    pub next_change_ms: u64,
+}
+
+/// The plan of `schedule` at `now_ms`, milliseconds since the Unix epoch.
+///
+/// The times are drawn from `seed`, picked at random at start-up, so the
+/// same seed gives the same plan: the light is not turned on and off every
+/// time it is evaluated.
+#[must_use]
+pub fn vacation_plan(now_ms: u64, schedule: &VacationSchedule, seed: u32) -> VacationPlan {
+    let offset = i64::from(schedule.utc_offset_min) * MINUTE_MS;
+    #[allow(clippy::cast_possible_wrap)]
+    let local = now_ms as i64 + offset;
+    let today = local.div_euclid(DAY_MS);
+
+    // The window of yesterday may run past midnight, and with the jitter
+    // the one of tomorrow may start before it.
+    let windows = [today - 1, today, today + 1].map(|day| schedule.window(day, seed));
+    let lit = windows
+        .iter()
+        .find(|&&(on, off)| (on..off).contains(&local));
+    let (on, next) = if let Some(&(_, off)) = lit {
+        (true, off)
+    } else {
+        let next = windows.iter().map(|&(on, _)| on).find(|&on| on > local);
+        (false, next.unwrap_or(windows[2].0))
+    };
+
+    #[allow(clippy::cast_sign_loss)]
+    VacationPlan {
+        on,
+        next_change_ms: (next - offset) as u64,
+    }
+}
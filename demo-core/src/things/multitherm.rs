@@ -0,0 +1,224 @@
+//! Up to eight SHTC3 probes behind a TCA9548A I2C multiplexer
+//! (`demo-c3`, `multitherm`).
+//!
+//! Every SHTC3 answers at the same address, so the mux gives each one a
+//! channel of its own. The channels are scanned at start-up and the TD keeps
+//! the `temperatureN` property of each probe found, see
+//! `wot_esp_thing::capabilities`.
+
+use alloc::{format, string::String};
+
+use serde_json::{Map, Value};
+
+use wot_td::{
+    builder::{
+        BuildableDataSchema, BuildableHumanReadableInfo, BuildableInteractionAffordance, Error,
+        ReadableWriteableDataSchema, SpecializableDataSchema,
+    },
+    thing::FormOperation,
+    Thing,
+};
+
+use crate::capabilities::Hardware;
+
+/// Channels of the mux.
+pub const CHANNELS: usize = 8;
+
+/// Address of the mux with A0–A2 high. The TCA9548A defaults to 0x70, the
+/// address of every SHTC3, so it must be strapped to another one.
+pub const MUX_ADDRESS: u8 = 0x77;
+
+/// Address of the SHTC3.
+pub const SHTC3_ADDRESS: u8 = 0x70;
+
+/// SHTC3 command leaving sleep mode.
+pub const SHTC3_WAKEUP: [u8; 2] = [0x35, 0x17];
+
+/// SHTC3 command entering sleep mode.
+pub const SHTC3_SLEEP: [u8; 2] = [0xB0, 0x98];
+
+/// SHTC3 command reading the ID register, answered with 3 bytes.
+pub const SHTC3_READ_ID: [u8; 2] = [0xEF, 0xC8];
+
+/// SHTC3 command measuring in normal mode, temperature first and without
+/// clock stretching, answered with 6 bytes once done.
+pub const SHTC3_MEASURE: [u8; 2] = [0x78, 0x66];
+
+/// Properties of the probes, `temperatureN` for channel N.
+pub const PROBE_NAMES: [&str; CHANNELS] = [
+    "temperature0",
+    "temperature1",
+    "temperature2",
+    "temperature3",
+    "temperature4",
+    "temperature5",
+    "temperature6",
+    "temperature7",
+];
+
+/// The probe of each channel, listed in the `capabilities` property as
+/// `probeN` when found.
+pub const PROBES: [Hardware; CHANNELS] = [
+    Hardware {
+        name: "probe0",
+        affordances: &["temperature0"],
+    },
+    Hardware {
+        name: "probe1",
+        affordances: &["temperature1"],
+    },
+    Hardware {
+        name: "probe2",
+        affordances: &["temperature2"],
+    },
+    Hardware {
+        name: "probe3",
+        affordances: &["temperature3"],
+    },
+    Hardware {
+        name: "probe4",
+        affordances: &["temperature4"],
+    },
+    Hardware {
+        name: "probe5",
+        affordances: &["temperature5"],
+    },
+    Hardware {
+        name: "probe6",
+        affordances: &["temperature6"],
+    },
+    Hardware {
+        name: "probe7",
+        affordances: &["temperature7"],
+    },
+];
+
+/// The byte selecting `channel` alone on the mux, `None` past the last one.
+#[must_use]
+pub fn select(channel: usize) -> Option<u8> {
+    (channel < CHANNELS).then(|| 1 << channel)
+}
+
+/// CRC-8 of the SHTC3 words: polynomial 0x31, initial value 0xFF.
+#[must_use]
+pub fn crc8(data: &[u8]) -> u8 {
+    data.iter().fold(0xFF, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 == 0 {
+                crc << 1
+            } else {
+                (crc << 1) ^ 0x31
+            }
+        })
+    })
+}
+
+/// The ID register of the answer to [`SHTC3_READ_ID`], `None` when its CRC
+/// fails or it is not an SHTC3's.
+#[must_use]
+pub fn parse_id(answer: &[u8; 3]) -> Option<u16> {
+    let id = u16::from_be_bytes([answer[0], answer[1]]);
+    (crc8(&answer[..2]) == answer[2] && id & 0x083F == 0x0807).then_some(id)
+}
+
+/// Temperature in degrees Celsius and humidity in percent of the answer to
+/// [`SHTC3_MEASURE`], `None` when a CRC fails.
+#[must_use]
+pub fn parse_measurement(answer: &[u8; 6]) -> Option<(f32, f32)> {
+    let word = |i: usize| {
+        (crc8(&answer[i..i + 2]) == answer[i + 2])
+            .then(|| f32::from(u16::from_be_bytes([answer[i], answer[i + 1]])))
+    };
+    let temperature = -45.0 + 175.0 * word(0)? / 65536.0;
+    let humidity = 100.0 * word(3)? / 65536.0;
+    Some((temperature, humidity))
+}
+
+/// A probe as last measured.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Probe {
+    /// Not found at start-up.
+    #[default]
+    Absent,
+    /// Found, with no measurement yet.
+    Pending,
+    /// The latest temperature, in degrees Celsius.
+    Healthy(f32),
+    /// Found, but the channel switch or the latest measurement failed.
+    Unhealthy,
+}
+
+impl Probe {
+    /// The temperature, when the latest measurement succeeded.
+    #[must_use]
+    pub fn temperature(self) -> Option<f32> {
+        match self {
+            Self::Healthy(temperature) => Some(temperature),
+            _ => None,
+        }
+    }
+}
+
+/// The `temperatures` property: the temperature of every probe found,
+/// `null` for one not measured or unhealthy, e.g.
+/// `{"temperature0":21.5,"temperature3":null}`.
+#[must_use]
+pub fn temperatures(probes: &[Probe; CHANNELS]) -> Value {
+    let temperatures: Map<String, Value> = PROBE_NAMES
+        .iter()
+        .zip(probes)
+        .filter(|(_, probe)| **probe != Probe::Absent)
+        .map(|(name, probe)| ((*name).into(), probe.temperature().into()))
+        .collect();
+    Value::Object(temperatures)
+}
+
+/// The Thing Description with every probe, as served at `/` once those not
+/// found are left out.
+///
+/// # Errors
+///
+/// Returns the error of the builder, e.g. for a malformed affordance.
+pub fn td(name: &str, base_uri: String, id: String) -> Result<Thing, Error> {
+    let mut thing = Thing::builder(name)
+        .finish_extend()
+        .id(id)
+        .base(base_uri)
+        .description("Example Thing exposing SHTC3 probes behind a TCA9548A I2C multiplexer")
+        .security(|builder| builder.no_sec().required().with_key("nosec_sc"));
+
+    for (channel, probe) in PROBE_NAMES.iter().enumerate() {
+        thing = thing.property(*probe, |p| {
+            p.finish_extend_data_schema()
+                .attype("TemperatureProperty")
+                .title(format!("Temperature {channel}"))
+                .description(format!("Temperature of the probe on mux channel {channel}"))
+                .form(|f| {
+                    f.href(format!("/properties/{probe}"))
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .number()
+                .read_only()
+                .unit("Celsius")
+        });
+    }
+
+    thing
+        .property("temperatures", |p| {
+            p.finish_extend_data_schema()
+                .title("Temperatures")
+                .description(
+                    "Temperature of every probe found, null for one whose latest measurement \
+                     failed",
+                )
+                .form(|f| {
+                    f.href("/properties/temperatures")
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .object()
+                .read_only()
+        })
+        .build()
+}
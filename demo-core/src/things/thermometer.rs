@@ -0,0 +1,593 @@
+//! The SHTC3 hygro-thermometer Thing (`demo-c3`, `thermometer`).
+
+use alloc::{format, string::String};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use wot_td::{
+    builder::{
+        BuildableDataSchema, BuildableHumanReadableInfo, BuildableInteractionAffordance,
+        EnumerableDataSchema, Error, NumberDataSchemaBuilderLike, ObjectDataSchemaBuilderLike,
+        ReadableWriteableDataSchema, SpecializableDataSchema,
+    },
+    thing::FormOperation,
+    Thing,
+};
+
+use crate::{
+    capabilities::Hardware,
+    forms::{sse_event_form, sse_observe_form},
+};
+
+/// How the SHTC3 measures, the `measurementMode` property.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeasurementMode {
+    Normal,
+    LowPower,
+}
+
+impl MeasurementMode {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::LowPower => "lowpower",
+        }
+    }
+
+    /// The mode named `name`, `None` for an unknown one.
+    #[must_use]
+    pub fn parse(name: &str) -> Option<Self> {
+        [Self::Normal, Self::LowPower]
+            .into_iter()
+            .find(|mode| mode.as_str() == name)
+    }
+}
+
+/// Largest `temperatureOffset` either way, in degrees Celsius.
+pub const MAX_TEMPERATURE_OFFSET: f32 = 5.0;
+
+/// Largest `humidityOffset` either way, in percent.
+pub const MAX_HUMIDITY_OFFSET: f32 = 10.0;
+
+/// Calibration offsets added to every reading.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Offsets {
+    pub temperature: f32,
+    pub humidity: f32,
+}
+
+impl Offsets {
+    /// `temperature` and `humidity` corrected, the humidity kept within
+    /// 0–100 %.
+    #[must_use]
+    pub fn apply(self, temperature: f32, humidity: f32) -> (f32, f32) {
+        (
+            temperature + self.temperature,
+            (humidity + self.humidity).clamp(0.0, 100.0),
+        )
+    }
+}
+
+/// Temperatures the Magnus formula of [`dew_point`] holds for, in degrees
+/// Celsius; readings outside are clamped to it.
+pub const DEW_POINT_RANGE: core::ops::RangeInclusive<f32> = -45.0..=60.0;
+
+/// Lowest humidity [`dew_point`] uses, in percent: the formula takes its
+/// logarithm, so 0 % is computed as this instead.
+pub const DEW_POINT_MIN_HUMIDITY: f32 = 0.1;
+
+/// Dew point in degrees Celsius, from the Magnus formula, of air at
+/// `temperature` degrees Celsius and `humidity` percent relative humidity.
+///
+/// Out-of-range readings are clamped rather than producing `NaN`; `None`
+/// only for a `NaN` reading.
+#[must_use]
+pub fn dew_point(temperature: f32, humidity: f32) -> Option<f32> {
+    // Magnus coefficients over water, Sonntag (1990).
+    const A: f32 = 17.62;
+    const B: f32 = 243.12;
+
+    if temperature.is_nan() || humidity.is_nan() {
+        return None;
+    }
+    let t = temperature.clamp(*DEW_POINT_RANGE.start(), *DEW_POINT_RANGE.end());
+    let rh = humidity.clamp(DEW_POINT_MIN_HUMIDITY, 100.0);
+
+    let gamma = libm::logf(rh / 100.0) + A * t / (B + t);
+    Some(B * gamma / (A - gamma))
+}
+
+/// Temperature from which [`heat_index`] applies the Rothfusz regression, in
+/// degrees Celsius; below it the heat index is the temperature.
+pub const HEAT_INDEX_MIN: f32 = 27.0;
+
+/// Heat index in degrees Celsius, the temperature felt, of air at
+/// `temperature` degrees Celsius and `humidity` percent relative humidity.
+///
+/// From the Rothfusz regression of the NOAA, with its adjustments for a
+/// low and a high humidity; `None` only for a `NaN` reading.
+#[must_use]
+pub fn heat_index(temperature: f32, humidity: f32) -> Option<f32> {
+    if temperature.is_nan() || humidity.is_nan() {
+        return None;
+    }
+    if temperature < HEAT_INDEX_MIN {
+        return Some(temperature);
+    }
+
+    // The regression is in degrees Fahrenheit.
+    let t = temperature * 1.8 + 32.0;
+    let rh = humidity.clamp(0.0, 100.0);
+    let mut hi = -42.379 + 2.049_015_2 * t + 10.143_331 * rh
+        - 0.224_755_4 * t * rh
+        - 6.837_83e-3 * t * t
+        - 5.481_717e-2 * rh * rh
+        + 1.228_74e-3 * t * t * rh
+        + 8.5282e-4 * t * rh * rh
+        - 1.99e-6 * t * t * rh * rh;
+    if rh < 13.0 && (80.0..=112.0).contains(&t) {
+        hi -= (13.0 - rh) / 4.0 * libm::sqrtf((17.0 - (t - 95.0).abs()) / 17.0);
+    } else if rh > 85.0 && (80.0..=87.0).contains(&t) {
+        hi += (rh - 85.0) / 10.0 * ((87.0 - t) / 5.0);
+    }
+    Some((hi - 32.0) / 1.8)
+}
+
+/// Temperature under which a dew point below [`FROST_DEW_POINT`] raises the
+/// `frostWarning` event, in degrees Celsius.
+pub const FROST_TEMPERATURE: f32 = 4.0;
+
+/// Dew point under which frost forms on surfaces cooler than the air, in
+/// degrees Celsius.
+pub const FROST_DEW_POINT: f32 = 0.0;
+
+/// Whether frost may form: the dew point is below [`FROST_DEW_POINT`] while
+/// the air is under [`FROST_TEMPERATURE`], both in degrees Celsius.
+#[must_use]
+pub fn frost_risk(temperature: f32, dew_point: f32) -> bool {
+    dew_point < FROST_DEW_POINT && temperature < FROST_TEMPERATURE
+}
+
+/// The `frostWarning` event data: whether the risk of [`frost_risk`] begins
+/// or ends, with the reading that changed it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrostWarning {
+    pub warning: bool,
+    pub temperature: f32,
+    pub dew_point: f32,
+}
+
+/// The units temperatures are reported in, the `unitsSystem` property.
+///
+/// Every temperature served follows it: the properties, the events and the
+/// over-temperature threshold. `temperatureOffset` calibrates the sensor and
+/// stays in degrees Celsius.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnitsSystem {
+    #[default]
+    Si,
+    Imperial,
+}
+
+impl UnitsSystem {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Si => "si",
+            Self::Imperial => "imperial",
+        }
+    }
+
+    /// The system named `name`, `None` for an unknown one.
+    #[must_use]
+    pub fn parse(name: &str) -> Option<Self> {
+        [Self::Si, Self::Imperial]
+            .into_iter()
+            .find(|units| units.as_str() == name)
+    }
+
+    /// The TD `unit` of a temperature.
+    #[must_use]
+    pub fn temperature_unit(self) -> &'static str {
+        match self {
+            Self::Si => "Celsius",
+            Self::Imperial => "Fahrenheit",
+        }
+    }
+
+    /// `celsius` degrees Celsius in these units.
+    #[must_use]
+    pub fn temperature(self, celsius: f32) -> f32 {
+        match self {
+            Self::Si => celsius,
+            Self::Imperial => celsius * 1.8 + 32.0,
+        }
+    }
+
+    /// A temperature in these units back in degrees Celsius.
+    #[must_use]
+    pub fn to_celsius(self, temperature: f32) -> f32 {
+        match self {
+            Self::Si => temperature,
+            Self::Imperial => (temperature - 32.0) / 1.8,
+        }
+    }
+}
+
+/// A measurement as served, offsets applied and temperatures in the units
+/// system.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reading {
+    pub temperature: f32,
+    pub humidity: f32,
+    /// `None` only for a `NaN` measurement, see [`dew_point`].
+    pub dew_point: Option<f32>,
+    /// `None` only for a `NaN` measurement, see [`heat_index`].
+    pub heat_index: Option<f32>,
+}
+
+impl Reading {
+    /// The reading of `temperature` degrees Celsius and `humidity` percent,
+    /// in `units`.
+    #[must_use]
+    pub fn new(temperature: f32, humidity: f32, units: UnitsSystem) -> Self {
+        Self {
+            temperature: units.temperature(temperature),
+            humidity,
+            dew_point: dew_point(temperature, humidity).map(|t| units.temperature(t)),
+            heat_index: heat_index(temperature, humidity).map(|t| units.temperature(t)),
+        }
+    }
+
+    /// The reading in `to`, from `from`.
+    #[must_use]
+    pub fn convert(self, from: UnitsSystem, to: UnitsSystem) -> Self {
+        let convert = |t| to.temperature(from.to_celsius(t));
+        Self {
+            temperature: convert(self.temperature),
+            humidity: self.humidity,
+            dew_point: self.dew_point.map(convert),
+            heat_index: self.heat_index.map(convert),
+        }
+    }
+}
+
+/// The properties and events reporting a temperature in the
+/// [`UnitsSystem`].
+const TEMPERATURES: &[&str] = &[
+    "temperature",
+    "dewPoint",
+    "heatIndex",
+    "die_temperature",
+    "over_temperature_threshold",
+    "over_temperature",
+    "frostWarning",
+];
+
+/// Set the `unit` of every temperature of the serialized TD `td` to the one
+/// of `units`.
+///
+/// # Errors
+///
+/// Returns an error if `td` is not a JSON object.
+pub fn with_units(td: &str, units: UnitsSystem) -> Result<String, serde_json::Error> {
+    fn set_unit(schema: &mut Value, unit: &str) {
+        match schema {
+            Value::Object(members) => {
+                for (key, value) in members {
+                    if key == "unit" {
+                        *value = Value::String(unit.into());
+                    } else {
+                        set_unit(value, unit);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| set_unit(item, unit)),
+            _ => {}
+        }
+    }
+
+    let mut td: Value = serde_json::from_str(td)?;
+    for member in ["properties", "events"] {
+        for name in TEMPERATURES {
+            if let Some(affordance) = td.get_mut(member).and_then(|a| a.get_mut(*name)) {
+                set_unit(affordance, units.temperature_unit());
+            }
+        }
+    }
+    serde_json::to_string(&td)
+}
+
+/// The SHTC3, which some boards leave unpopulated, and the affordances
+/// that need it; `sensorInfo` and `die_temperature` are served without it.
+pub const SHTC3: Hardware = Hardware {
+    name: "shtc3",
+    affordances: &[
+        "temperature",
+        "humidity",
+        "dewPoint",
+        "heatIndex",
+        "over_temperature_threshold",
+        "over_temperature",
+        "frostWarning",
+        "temperatureOffset",
+        "humidityOffset",
+        "measurementMode",
+        "conditionSensor",
+    ],
+};
+
+/// The `sensorInfo` property for the ID register read at start-up, `None`
+/// when the sensor did not answer.
+#[must_use]
+pub fn sensor_info(id_register: Option<u16>) -> Value {
+    json!({
+        "type": "SHTC3",
+        "id": id_register.map(|id| format!("0x{id:04X}")),
+        "present": id_register.is_some(),
+    })
+}
+
+/// The Thing Description, as served at `/`.
+///
+/// # Errors
+///
+/// Returns the error of the builder, e.g. for a malformed affordance.
+pub fn td(name: &str, base_uri: String, id: String) -> Result<Thing, Error> {
+    Thing::builder(name)
+        .finish_extend()
+        .id(id)
+        .base(base_uri)
+        .description("Example Thing exposing a shtc3 sensor")
+        .security(|builder| builder.no_sec().required().with_key("nosec_sc"))
+        .property("temperature", |p| {
+            p.finish_extend_data_schema()
+                .attype("TemperatureProperty")
+                .title("Temperature")
+                .description("Current temperature, temperatureOffset included")
+                .form(|f| {
+                    f.href("/properties/temperature")
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .form(|f| sse_observe_form(f, "/events/temperature"))
+                .observable(true)
+                .number()
+                .read_only()
+                .unit("Celsius")
+        })
+        .property("humidity", |p| {
+            p.finish_extend_data_schema()
+                .attype("HumidityProperty")
+                .title("Humidity")
+                .description("Current humidity, humidityOffset included")
+                .form(|f| {
+                    f.href("/properties/humidity")
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .number()
+                .read_only()
+                .unit("%")
+        })
+        .property("dewPoint", |p| {
+            p.finish_extend_data_schema()
+                .attype("TemperatureProperty")
+                .title("Dew point")
+                .description("Computed from temperature and humidity with the Magnus formula")
+                .form(|f| {
+                    f.href("/properties/dewPoint")
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .number()
+                .read_only()
+                .unit("Celsius")
+        })
+        .property("heatIndex", |p| {
+            p.finish_extend_data_schema()
+                .attype("TemperatureProperty")
+                .title("Heat index")
+                .description(
+                    "Temperature felt, from temperature and humidity with the Rothfusz \
+                     regression; the temperature itself below 27 °C",
+                )
+                .form(|f| {
+                    f.href("/properties/heatIndex")
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .number()
+                .read_only()
+                .unit("Celsius")
+        })
+        .property("die_temperature", |p| {
+            p.finish_extend_data_schema()
+                .attype("TemperatureProperty")
+                .title("Die temperature")
+                .description("ESP32-C3 internal die temperature")
+                .form(|f| {
+                    f.href("/properties/die_temperature")
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .number()
+                .read_only()
+                .unit("Celsius")
+        })
+        .property("over_temperature_threshold", |p| {
+            p.finish_extend_data_schema()
+                .title("Over-temperature threshold")
+                .description("Temperature above which the over_temperature alarm is raised")
+                .form(|f| {
+                    f.href("/properties/over_temperature_threshold")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .number()
+                .unit("Celsius")
+        })
+        .property("temperatureOffset", |p| {
+            p.finish_extend_data_schema()
+                .title("Temperature offset")
+                .description(
+                    "Added to every temperature reading, so the temperature property, its \
+                     events and the over-temperature alarm report the corrected value",
+                )
+                .form(|f| {
+                    f.href("/properties/temperatureOffset")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .number()
+                .minimum(-f64::from(MAX_TEMPERATURE_OFFSET))
+                .maximum(f64::from(MAX_TEMPERATURE_OFFSET))
+                .unit("Celsius")
+        })
+        .property("humidityOffset", |p| {
+            p.finish_extend_data_schema()
+                .title("Humidity offset")
+                .description(
+                    "Added to every humidity reading, so the humidity property reports the \
+                     corrected value, kept within 0-100 %",
+                )
+                .form(|f| {
+                    f.href("/properties/humidityOffset")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .number()
+                .minimum(-f64::from(MAX_HUMIDITY_OFFSET))
+                .maximum(f64::from(MAX_HUMIDITY_OFFSET))
+                .unit("%")
+        })
+        .property("sensorInfo", |p| {
+            p.finish_extend_data_schema()
+                .title("Sensor info")
+                .description(
+                    "Sensor type and ID register, read at start-up; the id is null and \
+                     present false when the sensor did not answer",
+                )
+                .form(|f| {
+                    f.href("/properties/sensorInfo")
+                        .op(FormOperation::ReadProperty)
+                        .content_type("application/json")
+                })
+                .object()
+                .property("type", true, |b| b.finish_extend().string())
+                .property("id", true, |b| {
+                    b.finish_extend()
+                        .string()
+                        .description("The 16-bit ID register in hex, e.g. 0x887C")
+                })
+                .property("present", true, |b| b.finish_extend().bool())
+                .read_only()
+        })
+        .property("measurementMode", |p| {
+            p.finish_extend_data_schema()
+                .title("Measurement mode")
+                .description(
+                    "`normal` measures in about 12 ms; `lowpower` in under 1 ms and with less \
+                     energy, but with a lower repeatability, so readings are noisier. A change \
+                     applies from the next measurement.",
+                )
+                .form(|f| {
+                    f.href("/properties/measurementMode")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .enumeration(MeasurementMode::Normal.as_str())
+                .enumeration(MeasurementMode::LowPower.as_str())
+        })
+        .property("unitsSystem", |p| {
+            p.finish_extend_data_schema()
+                .title("Units system")
+                .description(
+                    "`si` reports temperatures in degrees Celsius, `imperial` in degrees \
+                     Fahrenheit, over-temperature threshold included. A change rewrites the \
+                     units of this TD and is announced with the unitsChanged event.",
+                )
+                .form(|f| {
+                    f.href("/properties/unitsSystem")
+                        .op(FormOperation::ReadProperty)
+                        .op(FormOperation::WriteProperty)
+                        .content_type("application/json")
+                })
+                .enumeration(UnitsSystem::Si.as_str())
+                .enumeration(UnitsSystem::Imperial.as_str())
+        })
+        .event("temperature", |b| {
+            b.data(|b| b.finish_extend().number().unit("Celsius"))
+                .form(|f| sse_event_form(f, "/events/temperature"))
+        })
+        .event("over_temperature", |b| {
+            b.description("Temperature rose above the threshold, or fell half a degree below it")
+                .data(|b| {
+                    b.finish_extend()
+                        .object()
+                        .property("state", true, |b| {
+                            b.finish_extend()
+                                .string()
+                                .description("`active` or `cleared`")
+                        })
+                        .property("value", true, |b| {
+                            b.finish_extend().number().unit("Celsius")
+                        })
+                })
+                .form(|f| sse_event_form(f, "/events/over_temperature"))
+        })
+        .event("frostWarning", |b| {
+            b.description(
+                "Frost may form: the dew point fell below 0 °C with the temperature under \
+                 4 °C (warning true), or no longer does (warning false)",
+            )
+            .data(|b| {
+                b.finish_extend()
+                    .object()
+                    .property("warning", true, |b| b.finish_extend().bool())
+                    .property("temperature", true, |b| {
+                        b.finish_extend().number().unit("Celsius")
+                    })
+                    .property("dewPoint", true, |b| {
+                        b.finish_extend().number().unit("Celsius")
+                    })
+            })
+            .form(|f| sse_event_form(f, "/events/frostWarning"))
+        })
+        .event("unitsChanged", |b| {
+            b.description("The unitsSystem changed, and with it the TD: fetch it again")
+                .data(|b| {
+                    b.finish_extend()
+                        .enumeration(UnitsSystem::Si.as_str())
+                        .enumeration(UnitsSystem::Imperial.as_str())
+                })
+                .form(|f| sse_event_form(f, "/events/unitsChanged"))
+        })
+        .action("conditionSensor", |b| {
+            b.title("Condition sensor")
+                .description(
+                    "Recover from condensation: 5 minutes of back-to-back high-power \
+                     measurements warm the sensor, then 1 minute of cool-down. No \
+                     temperature events are sent meanwhile; query the action for the \
+                     progress.",
+                )
+                .form(|f| {
+                    f.href("/actions/conditionSensor")
+                        .op(FormOperation::InvokeAction)
+                        .content_type("application/json")
+                })
+                .form(|f| {
+                    f.href("/actions/conditionSensor")
+                        .op(FormOperation::QueryAction)
+                        .content_type("application/json")
+                })
+        })
+        .build()
+}
@@ -0,0 +1,52 @@
+//! Thing Model placeholder substitution.
+
+use alloc::string::String;
+
+/// Replace every `{{KEY}}` placeholder in `template` with its value from `vars`.
+///
+/// Values are escaped for use inside JSON strings. Unknown placeholders are left
+/// untouched.
+#[must_use]
+pub fn substitute(template: &str, vars: &[(&str, &str)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+
+        let value = after.find("}}").and_then(|end| {
+            let key = &after[..end];
+            vars.iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| (*v, end + 2))
+        });
+
+        match value {
+            Some((value, consumed)) => {
+                push_escaped(&mut out, value);
+                rest = &after[consumed..];
+            }
+            None => {
+                out.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+
+    out
+}
+
+fn push_escaped(out: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = core::fmt::Write::write_fmt(out, format_args!("\\u{:04x}", u32::from(c)));
+            }
+            c => out.push(c),
+        }
+    }
+}
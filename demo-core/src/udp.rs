@@ -0,0 +1,133 @@
+//! JSON requests in single UDP datagrams, a transport a border router can
+//! bridge to Thread or Zigbee, see `wot_esp_thing::udp`.
+//!
+//! A request reads a property, and the reply carries the same value as its
+//! HTTP route:
+//!
+//! ```json
+//! {"op":"readproperty","name":"temperature"}
+//! {"op":"readproperty","name":"temperature","value":21.5}
+//! ```
+//!
+//! Failures are answered with an error object, e.g.
+//! `{"error":"unknown property","name":"color"}`. A reply never takes more
+//! than [`MAX_DATAGRAM`] bytes: an array value too long loses its last items
+//! and the reply gets `"truncated":true`.
+
+use alloc::{format, string::String, vec::Vec};
+
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+/// Port listened on, unless set with `UDP_PORT`.
+pub const DEFAULT_PORT: u16 = 5684;
+
+/// Longest request.
+pub const MAX_REQUEST: usize = 256;
+
+/// Longest reply: what fits the minimum IPv6 MTU of 1280 bytes, so a reply
+/// is never fragmented on its way to or over Thread.
+pub const MAX_DATAGRAM: usize = 1232;
+
+/// The only operation served.
+pub const READ_PROPERTY: &str = "readproperty";
+
+/// `subprotocol` of the forms advertising the transport in the TD.
+pub const SUBPROTOCOL: &str = "udp-json";
+
+#[derive(Deserialize)]
+struct Request {
+    op: String,
+    name: String,
+}
+
+/// The name of the property the request `datagram` reads, or the error
+/// reply to send back.
+///
+/// # Errors
+///
+/// If the request is longer than [`MAX_REQUEST`], not a request object or
+/// not a [`READ_PROPERTY`].
+pub fn read_request(datagram: &[u8]) -> Result<String, Vec<u8>> {
+    if datagram.len() > MAX_REQUEST {
+        return Err(to_vec(&json!({ "error": "request too large" })));
+    }
+    let Ok(request) = serde_json::from_slice::<Request>(datagram) else {
+        return Err(to_vec(&json!({ "error": "invalid request" })));
+    };
+    if request.op != READ_PROPERTY {
+        return Err(to_vec(&json!({ "error": "unknown op", "op": request.op })));
+    }
+    Ok(request.name)
+}
+
+/// The reply to a read of the property `name`, given its `value`: `None` if
+/// there is no such property, the reason if reading it failed.
+#[must_use]
+pub fn read_reply(name: &str, value: Option<Result<Value, &str>>) -> Vec<u8> {
+    let value = match value {
+        Some(Ok(value)) => value,
+        Some(Err(reason)) => return to_vec(&json!({ "error": reason, "name": name })),
+        None => return to_vec(&json!({ "error": "unknown property", "name": name })),
+    };
+
+    let mut reply = Map::new();
+    reply.insert("op".into(), READ_PROPERTY.into());
+    reply.insert("name".into(), name.into());
+    reply.insert("value".into(), value);
+    loop {
+        let datagram = to_vec(&reply);
+        if datagram.len() <= MAX_DATAGRAM {
+            return datagram;
+        }
+        match reply.get_mut("value") {
+            Some(Value::Array(items)) if !items.is_empty() => {
+                items.pop();
+                reply.insert("truncated".into(), true.into());
+            }
+            _ => return to_vec(&json!({ "error": "value too large", "name": name })),
+        }
+    }
+}
+
+fn to_vec(reply: &impl serde::Serialize) -> Vec<u8> {
+    serde_json::to_vec(reply).unwrap_or_default()
+}
+
+/// Advertise the transport on the `properties` of the serialized TD `td`
+/// served over it: each gets a read form with the `udp://` URL of `port` on
+/// the host of the TD `base`, tagged with the [`SUBPROTOCOL`]. The request
+/// names the property, so the forms of every property share the URL.
+///
+/// # Errors
+///
+/// Returns an error if `td` is not a JSON object.
+pub fn advertise(td: &str, properties: &[&str], port: u16) -> Result<String, serde_json::Error> {
+    let mut td: Map<String, Value> = serde_json::from_str(td)?;
+
+    let Some(host) = td
+        .get("base")
+        .and_then(Value::as_str)
+        .and_then(crate::http::parse_url)
+        .map(|(host, _, _)| String::from(host))
+    else {
+        return serde_json::to_string(&td);
+    };
+    let href = format!("udp://{host}:{port}");
+
+    let affordances = td.get_mut("properties").and_then(Value::as_object_mut);
+    for forms in affordances.into_iter().flat_map(|all| {
+        all.iter_mut()
+            .filter(|(name, _)| properties.contains(&name.as_str()))
+            .filter_map(|(_, affordance)| affordance.get_mut("forms")?.as_array_mut())
+    }) {
+        forms.push(json!({
+            "href": href,
+            "op": READ_PROPERTY,
+            "contentType": "application/json",
+            "subprotocol": SUBPROTOCOL,
+        }));
+    }
+
+    serde_json::to_string(&td)
+}
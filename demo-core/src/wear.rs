@@ -0,0 +1,119 @@
+//! Flash wear accounting: how often each key of a store is written, see
+//! `wot_esp_thing::flash`.
+//!
+//! The writes of a key are counted per hour of uptime over the last day, so
+//! its rate is the writes per hour averaged over that day, or over the
+//! uptime while it is shorter. A key written faster than the limit, e.g. a
+//! property persisted on every change without debouncing, is flagged.
+
+/// Hours the rate is averaged over.
+pub const WINDOW_H: usize = 24;
+
+/// Writes per hour a key may average before it is flagged, unless set with
+/// `FLASH_MAX_WRITES_PER_HOUR`.
+pub const DEFAULT_MAX_WRITES_PER_HOUR: u32 = 6;
+
+/// Highest `FLASH_MAX_WRITES_PER_HOUR`, one write a second.
+pub const MAX_WRITES_PER_HOUR: u32 = 3600;
+
+const HOUR_MS: u64 = 60 * 60 * 1000;
+
+/// The writes of one key.
+#[derive(Clone, Copy, Debug)]
+pub struct KeyWear {
+    /// Writes since boot.
+    pub writes: u32,
+    /// Bytes written since boot.
+    pub bytes: u64,
+    /// Writes in each hour of the window, indexed by the hour of uptime
+    /// modulo [`WINDOW_H`].
+    hourly: [u32; WINDOW_H],
+    /// Hour of uptime of the last write.
+    last_hour: u64,
+}
+
+impl KeyWear {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            writes: 0,
+            bytes: 0,
+            hourly: [0; WINDOW_H],
+            last_hour: 0,
+        }
+    }
+
+    /// Count a write of `bytes` at `now_ms`, milliseconds since boot.
+    pub fn record(&mut self, bytes: usize, now_ms: u64) {
+        let hour = now_ms / HOUR_MS;
+        self.expire(hour);
+        self.hourly[slot(hour)] += 1;
+        self.writes = self.writes.saturating_add(1);
+        self.bytes = self.bytes.saturating_add(bytes as u64);
+    }
+
+    /// Writes per hour over the last day at `now_ms`, or over the uptime,
+    /// counted as at least an hour, while it is shorter.
+    #[must_use]
+    pub fn rate(&self, now_ms: u64) -> f32 {
+        let hour = now_ms / HOUR_MS;
+        let mut current = *self;
+        current.expire(hour);
+        let writes: u32 = current.hourly.iter().sum();
+        #[allow(clippy::cast_precision_loss)]
+        let hours = (hour + 1).min(WINDOW_H as u64) as f32;
+        #[allow(clippy::cast_precision_loss)]
+        let rate = writes as f32 / hours;
+        rate
+    }
+
+    /// Whether [`Self::rate`] is above `max_per_hour`.
+    #[must_use]
+    pub fn is_worn(&self, now_ms: u64, max_per_hour: u32) -> bool {
+        #[allow(clippy::cast_precision_loss)]
+        let max = max_per_hour as f32;
+        self.rate(now_ms) > max
+    }
+
+    /// Clear the hours that left the window since the last write, so the
+    /// one of `hour` counts from zero.
+    fn expire(&mut self, hour: u64) {
+        let elapsed = hour.saturating_sub(self.last_hour);
+        for h in 1..=elapsed.min(WINDOW_H as u64) {
+            self.hourly[slot(self.last_hour + h)] = 0;
+        }
+        self.last_hour = self.last_hour.max(hour);
+    }
+}
+
+impl Default for KeyWear {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+const fn slot(hour: u64) -> usize {
+    (hour % WINDOW_H as u64) as usize
+}
+
+/// The erase cycles each sector of a store of `sectors` went through, for
+/// `erases` sector erases spread evenly over them as a log-structured store
+/// does; `None` for an empty store.
+#[must_use]
+pub fn cycles_per_sector(erases: u32, sectors: u32) -> Option<f32> {
+    #[allow(clippy::cast_precision_loss)]
+    (sectors > 0).then(|| erases as f32 / sectors as f32)
+}
+
+/// The writes per hour of the `FLASH_MAX_WRITES_PER_HOUR` build-time
+/// setting, clamped to 1 to [`MAX_WRITES_PER_HOUR`] and the default when
+/// unset or not a number.
+#[must_use]
+pub fn max_writes_per_hour(setting: Option<&str>) -> u32 {
+    setting
+        .and_then(|writes| writes.trim().parse().ok())
+        .map_or(DEFAULT_MAX_WRITES_PER_HOUR, |writes: u32| {
+            writes.clamp(1, MAX_WRITES_PER_HOUR)
+        })
+}
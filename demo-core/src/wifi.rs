@@ -0,0 +1,133 @@
+//! Wi-Fi networks the station may join, the order to try them in and the
+//! regulatory settings of the radio.
+
+use core::ops::RangeInclusive;
+
+use alloc::vec::Vec;
+
+/// How the station authenticates to a network.
+#[derive(Clone, Copy, Debug)]
+pub enum Auth {
+    /// WPA2/WPA3-Personal pre-shared key, empty for an open network.
+    Psk(&'static str),
+    /// WPA2-Enterprise, PEAP with MSCHAPv2.
+    Enterprise {
+        /// Outer (anonymous) identity.
+        identity: &'static str,
+        username: &'static str,
+        password: &'static str,
+    },
+}
+
+/// A network the station may join.
+#[derive(Clone, Copy, Debug)]
+pub struct Credentials {
+    pub ssid: &'static str,
+    pub auth: Auth,
+    /// The SSID is not broadcast, so it never shows up in a scan.
+    pub hidden: bool,
+}
+
+/// Order `networks` by the signal strength `seen` in a scan.
+///
+/// Networks seen in the scan come first, strongest first, followed by the
+/// ones that were not seen in priority order. Hidden networks are never seen,
+/// so they keep their priority slot instead.
+#[must_use]
+pub fn order_by_signal(networks: &[Credentials], seen: &[(&str, i8)]) -> Vec<Credentials> {
+    let strength = |ssid: &str| {
+        seen.iter()
+            .filter(|(s, _)| *s == ssid)
+            .map(|(_, rssi)| *rssi)
+            .max()
+    };
+
+    let mut visible: Vec<_> = networks
+        .iter()
+        .filter(|n| !n.hidden)
+        .map(|n| (*n, strength(n.ssid)))
+        .collect();
+    // Stable, so equally strong and unseen networks keep their priority order.
+    visible.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+    let mut visible = visible.into_iter().map(|(n, _)| n);
+    networks
+        .iter()
+        .map(|n| {
+            if n.hidden {
+                *n
+            } else {
+                visible.next().unwrap()
+            }
+        })
+        .collect()
+}
+
+/// Transmit power the radio takes, in dBm.
+pub const TX_POWER_DBM: RangeInclusive<i8> = 2..=20;
+
+/// Regulatory domain and transmit power limit of the radio.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RadioSettings {
+    /// ISO 3166-1 alpha-2 country code, or `01` for the world-safe domain.
+    pub country: [u8; 2],
+    /// Most the radio may transmit with, in dBm.
+    pub max_tx_power: i8,
+}
+
+impl Default for RadioSettings {
+    fn default() -> Self {
+        Self {
+            country: *b"01",
+            max_tx_power: *TX_POWER_DBM.end(),
+        }
+    }
+}
+
+impl RadioSettings {
+    /// Settings from a `country` code such as `JP` and a `max_tx_power` in
+    /// dBm, each left at its default when `None`.
+    ///
+    /// # Errors
+    ///
+    /// With a message naming the setting that is invalid.
+    pub fn parse(country: Option<&str>, max_tx_power: Option<&str>) -> Result<Self, &'static str> {
+        let mut settings = Self::default();
+
+        if let Some(country) = country {
+            settings.country = match country.as_bytes() {
+                [a, b] if a.is_ascii_uppercase() && b.is_ascii_uppercase() => [*a, *b],
+                b"01" => *b"01",
+                _ => return Err("WIFI_COUNTRY must be two capital letters, e.g. JP, or 01"),
+            };
+        }
+
+        if let Some(max_tx_power) = max_tx_power {
+            settings.max_tx_power = max_tx_power
+                .parse()
+                .ok()
+                .filter(|dbm| TX_POWER_DBM.contains(dbm))
+                .ok_or("WIFI_MAX_TX_POWER must be a whole number of dBm from 2 to 20")?;
+        }
+
+        Ok(settings)
+    }
+
+    /// The country code as text.
+    #[must_use]
+    pub fn country(&self) -> &str {
+        core::str::from_utf8(&self.country).unwrap_or("01")
+    }
+
+    /// Whether the radio may be set to transmit with `dbm`.
+    #[must_use]
+    pub fn allows(&self, dbm: i8) -> bool {
+        (*TX_POWER_DBM.start()..=self.max_tx_power).contains(&dbm)
+    }
+}
+
+/// `dbm` in the quarter-dBm steps the Wi-Fi driver takes.
+#[must_use]
+pub fn quarter_dbm(dbm: i8) -> i8 {
+    dbm.saturating_mul(4)
+}
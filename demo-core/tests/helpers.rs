@@ -0,0 +1,3160 @@
+use std::net::Ipv4Addr;
+
+use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+use wot_esp_demo_core::{
+    affordance_stats::{self, routes},
+    alarm::{Direction, Trigger},
+    batch::{self, Op, MAX_OPS},
+    body,
+    boot_status::BootStatus,
+    brownout,
+    capabilities::{restrict, Hardware},
+    captive_dns::answer,
+    cbor,
+    config::{self, problems, Problem, Setting},
+    configuration::Configuration,
+    delivery::Delivery,
+    directory,
+    dns_sd::{
+        claims, host_alternative, instance, instance_alternative, is_answer, probe_query,
+        ptr_query, Instance,
+    },
+    entropy::{check, fallback_seed, SAMPLE_LEN},
+    field::{self, advertise, from_query, project},
+    freshness::Freshness,
+    http::{parse_response, parse_url},
+    i2c_scan,
+    identity::{retitle_json, titled, Identity},
+    inline::{self, INLINE_LEN},
+    integrity,
+    latency::{bucket, RouteClass, BUCKETS},
+    link_format::{self, link, well_known_core, TD_CONTENT_FORMAT},
+    low_power::{self, add_energy_mode, battery_mv, payload, wake_interval_min, EnergyMode},
+    names::{device_urn, hostname, mac},
+    negotiate::{self, accepts, accepts_encoding, etag_matches, prefers},
+    origin::{self, Headers, Policy},
+    peers::{Closed, Peers, StrikePolicy, Strikes, Timeouts, MAX_KEEP_ALIVE, REFUSE_MS},
+    prefix::{self, normalize, prefix_json, strip},
+    resolve::{backoff_ms, Cache, DEFAULT_TTL_MS},
+    rules::{Comparison, Rule, RuleState},
+    schema::{InputSchema, Member, Schema, Violation},
+    self_test::{read_outcome, reads, results, row, Outcome},
+    shutdown,
+    smartled::{
+        clock_divider, limit_current, rmt_buffer_len, sunrise, ColorOrder, CurrentModel, LedTiming,
+        Symbols, TimingLimits, TimingProfile, PROGRESS_END,
+    },
+    sntp,
+    store::{self, Store, StoreError},
+    tasks::is_stale,
+    td::{self, forwarded_base, merge_metadata, rebase, rebase_json, GroupProperty, TdMetadata},
+    things::{
+        button::{self, EdgeEvent, MAX_BUTTONS},
+        fan, light, multitherm, temperature_changed,
+        thermometer::{
+            dew_point, frost_risk, heat_index, sensor_info, with_units, FrostWarning,
+            MeasurementMode, Offsets, Reading, UnitsSystem,
+        },
+    },
+    tm::substitute,
+    udp::{self, read_reply, read_request},
+    wear::{self, cycles_per_sector, max_writes_per_hour, KeyWear},
+    wifi::{order_by_signal, quarter_dbm, Auth, Credentials, RadioSettings},
+};
+
+#[test]
+fn accept_header() {
+    assert!(accepts("", "application/json"));
+    assert!(accepts("*/*", "application/json"));
+    assert!(accepts("application/*", "application/json"));
+    assert!(accepts("text/html, Application/JSON", "application/json"));
+    assert!(accepts("application/json;q=0.5", "application/json"));
+    assert!(!accepts("application/json;q=0", "application/json"));
+    assert!(!accepts("text/*", "application/json"));
+    assert!(!accepts("application/td+json", "application/json"));
+}
+
+#[test]
+fn accept_encoding_header() {
+    assert!(accepts_encoding("gzip, deflate", "gzip"));
+    assert!(accepts_encoding("*", "gzip"));
+    assert!(accepts_encoding("GZIP;q=0.8", "gzip"));
+    assert!(!accepts_encoding("gzip;q=0", "gzip"));
+    assert!(!accepts_encoding("", "gzip"));
+    assert!(!accepts_encoding("br", "gzip"));
+}
+
+#[test]
+fn if_none_match_header() {
+    let etag = "\"9f2c41d0a7b3e615\"";
+    assert!(etag_matches("\"9f2c41d0a7b3e615\"", etag));
+    assert!(etag_matches("\"0000\", W/\"9f2c41d0a7b3e615\"", etag));
+    assert!(etag_matches("*", etag));
+    assert!(!etag_matches("\"9f2c41d0a7b3e615-gz\"", etag));
+    assert!(!etag_matches("", etag));
+}
+
+#[test]
+fn core_link() {
+    assert_eq!(
+        link("/.well-known/wot", "wot.thing", TD_CONTENT_FORMAT),
+        r#"</.well-known/wot>;rt="wot.thing";ct=432"#
+    );
+    assert_eq!(
+        well_known_core(),
+        r#"</.well-known/wot>;rt="wot.thing";ct=432"#
+    );
+
+    // Served to the clients accepting it, answered with 406 otherwise.
+    for accept in ["", "application/link-format", "application/*", "*/*"] {
+        assert!(accepts(accept, link_format::CONTENT_TYPE), "{accept}");
+    }
+    for accept in ["application/json", "application/link-format;q=0, */*;q=0"] {
+        assert!(!accepts(accept, link_format::CONTENT_TYPE), "{accept}");
+    }
+}
+
+#[test]
+fn http_responses() {
+    assert_eq!(
+        parse_response(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n"),
+        Some((204, &b""[..]))
+    );
+    assert_eq!(
+        parse_response(b"HTTP/1.0 200 OK\r\n\r\n{\"on\":true}"),
+        Some((200, &b"{\"on\":true}"[..]))
+    );
+    assert_eq!(parse_response(b"HTTP/1.1 200 OK\r\nContent-"), None);
+    assert_eq!(parse_response(b"SSH-2.0\r\n\r\n"), None);
+}
+
+#[test]
+fn http_urls() {
+    assert_eq!(
+        parse_url("http://192.0.2.7/properties/on"),
+        Some(("192.0.2.7", 80, "/properties/on"))
+    );
+    assert_eq!(
+        parse_url("http://tdd.local:8081"),
+        Some(("tdd.local", 8081, "/"))
+    );
+    assert_eq!(parse_url("https://example.com/"), None);
+    assert_eq!(parse_url("http://example.com:http/"), None);
+    assert_eq!(parse_url("http:///td"), None);
+}
+
+#[test]
+fn latency_buckets() {
+    for (us, expected) in [
+        (0, 0),
+        (1_000, 0),
+        (1_001, 1),
+        (5_000, 1),
+        (19_999, 2),
+        (100_000, 3),
+        (499_999, 4),
+        (500_001, 5),
+        (u64::MAX, 5),
+    ] {
+        assert_eq!(bucket(us), expected, "{us} us");
+    }
+    assert_eq!(bucket(u64::MAX), BUCKETS - 1);
+}
+
+#[test]
+fn latency_route_classes() {
+    assert_eq!(RouteClass::of("GET", "/"), Some(RouteClass::Td));
+    assert_eq!(
+        RouteClass::of("GET", "/properties/temperature"),
+        Some(RouteClass::PropertyRead)
+    );
+    assert_eq!(
+        RouteClass::of("PUT", "/properties/on"),
+        Some(RouteClass::PropertyWrite)
+    );
+    assert_eq!(RouteClass::of("GET", "/events/temperature"), None);
+    assert_eq!(RouteClass::of("POST", "/actions/selfTest"), None);
+    assert_eq!(RouteClass::of("GET", "/debug/latency"), None);
+    assert_eq!(RouteClass::of("HEAD", "/"), None);
+}
+
+#[test]
+fn peer_connections() {
+    let mut peers: Peers<u8, 4> = Peers::new();
+    assert!(peers.open(7));
+    assert!(peers.open(7));
+    // A third connection of the same peer is closed after its response.
+    assert!(!peers.open(7));
+    assert!(peers.open(9));
+    assert_eq!(peers.held(7), MAX_KEEP_ALIVE + 1);
+
+    peers.close(7);
+    peers.close(7);
+    assert_eq!(peers.held(7), 1);
+    assert!(peers.open(7));
+    peers.close(9);
+    peers.close(9);
+    assert_eq!(peers.iter().collect::<Vec<_>>(), [(7, 2)]);
+}
+
+#[test]
+fn peer_strikes() {
+    let policy = StrikePolicy::DEFAULT;
+    let mut strikes: Strikes<u8, 2> = Strikes::new();
+    for s in 0..u64::from(policy.limit) {
+        assert!(!strikes.strike(7, s * 1000, &policy));
+    }
+    assert!(!strikes.refused(7, 5000));
+    // One more within the minute refuses the peer.
+    assert!(strikes.strike(7, 10_000, &policy));
+    assert!(strikes.refused(7, 10_001));
+    assert_eq!(
+        strikes.iter_refused(10_000).collect::<Vec<_>>(),
+        [(7, REFUSE_MS)]
+    );
+    assert!(!strikes.refused(7, 10_000 + REFUSE_MS));
+    assert!(!strikes.strike(7, 10_000 + REFUSE_MS, &policy));
+
+    // Strikes older than a minute are forgotten.
+    for s in 0..u64::from(policy.limit) {
+        assert!(!strikes.strike(9, s * 61_000, &policy));
+    }
+    assert!(!strikes.strike(9, 200_000, &policy));
+
+    // A full table keeps the refused peer over the others.
+    for _ in 0..=policy.limit {
+        strikes.strike(5, 300_000, &policy);
+    }
+    assert!(!strikes.strike(3, 300_000, &policy));
+    assert!(strikes.refused(5, 300_001));
+
+    // A stricter policy refuses the second partial request within 10 s.
+    let strict = StrikePolicy::from_settings(Some("1"), Some("10"));
+    assert_eq!(strict.window_ms, 10_000);
+    let mut strikes: Strikes<u8, 1> = Strikes::new();
+    assert!(!strikes.strike(7, 0, &strict));
+    assert!(!strikes.strike(7, 10_001, &strict));
+    assert!(strikes.strike(7, 15_000, &strict));
+
+    assert_eq!(
+        StrikePolicy::from_settings(None, None),
+        StrikePolicy::DEFAULT
+    );
+    assert_eq!(
+        StrikePolicy::from_settings(Some("0"), Some("86400")),
+        StrikePolicy {
+            limit: 1,
+            window_ms: 3_600_000
+        }
+    );
+    assert_eq!(
+        StrikePolicy::from_settings(Some("many"), Some("")),
+        StrikePolicy::DEFAULT
+    );
+
+    // Only a partial request is held against the client.
+    let struck: Vec<_> = Closed::ALL.into_iter().filter(|c| c.strikes()).collect();
+    assert_eq!(struck, [Closed::ReadTimeout]);
+}
+
+#[test]
+fn server_timeouts() {
+    let timeouts = Timeouts::DEFAULT
+        .merge(&serde_json::json!({ "write_ms": 3000, "readRequest_ms": 2000 }))
+        .unwrap();
+    assert_eq!(timeouts.write_ms, 3000);
+    assert_eq!(timeouts.read_request_ms, 2000);
+    assert_eq!(
+        timeouts.start_read_request_ms,
+        Timeouts::DEFAULT.start_read_request_ms
+    );
+    assert_eq!(timeouts.to_json()["write_ms"], 3000);
+    // An idle connection closed at the start-read timeout, not by the client.
+    assert!(timeouts.start_read_timed_out(0, 5000));
+    assert!(!timeouts.start_read_timed_out(0, 120));
+    assert!(!timeouts.start_read_timed_out(1, 35_000));
+    assert_eq!(Timeouts::DEFAULT.merge(&timeouts.to_json()), Ok(timeouts));
+
+    for body in [
+        serde_json::json!({ "write_ms": 99 }),
+        serde_json::json!({ "write_ms": 300_001 }),
+        serde_json::json!({ "write_ms": 1.5 }),
+        serde_json::json!({ "write_ms": "1000" }),
+        serde_json::json!({ "keepAlive_ms": 1000 }),
+        serde_json::json!([1000]),
+    ] {
+        assert!(Timeouts::DEFAULT.merge(&body).is_err(), "{body}");
+    }
+}
+
+#[test]
+fn energy_mode() {
+    for mode in [EnergyMode::Continuous, EnergyMode::Batched] {
+        assert_eq!(EnergyMode::parse(mode.as_str()), Some(mode));
+        assert_eq!(EnergyMode::from_word(mode.to_word()), Some(mode));
+    }
+    assert_eq!(EnergyMode::parse("eco"), None);
+    // RTC memory holds anything after a power cycle.
+    assert_eq!(EnergyMode::from_word(0), None);
+    assert_eq!(EnergyMode::from_word(0xFFFF_FFFF), None);
+    assert_eq!(
+        EnergyMode::from_word(EnergyMode::Batched.to_word() + 1),
+        None
+    );
+
+    assert_eq!(
+        wake_interval_min(None),
+        low_power::DEFAULT_WAKE_INTERVAL_MIN
+    );
+    assert_eq!(wake_interval_min(Some("30")), 30);
+    assert_eq!(wake_interval_min(Some("0")), 1);
+    assert_eq!(wake_interval_min(Some("100000")), 24 * 60);
+    assert_eq!(
+        wake_interval_min(Some("ten")),
+        low_power::DEFAULT_WAKE_INTERVAL_MIN
+    );
+
+    assert_eq!(shutdown::wake_min(None), shutdown::DEFAULT_WAKE_MIN);
+    assert_eq!(shutdown::wake_min(Some("90")), 90);
+    assert_eq!(shutdown::wake_min(Some("0")), 1);
+    assert_eq!(shutdown::wake_min(Some("100000")), 7 * 24 * 60);
+}
+
+#[test]
+fn low_power_payload() {
+    assert_eq!(battery_mv(1856), 3712);
+
+    let reading = serde_json::json!({ "temperature": 21.5, "humidity": 40.0 });
+    assert_eq!(
+        payload("urn:x", Some(reading), Some(3712)),
+        serde_json::json!({
+            "id": "urn:x",
+            "temperature": 21.5,
+            "humidity": 40.0,
+            "battery_mV": 3712,
+        })
+    );
+    assert_eq!(
+        payload("urn:x", Some(serde_json::json!(21.5)), None),
+        serde_json::json!({ "id": "urn:x", "reading": 21.5 })
+    );
+    assert_eq!(
+        payload("urn:x", None, Some(3712)),
+        serde_json::json!({ "id": "urn:x", "battery_mV": 3712 })
+    );
+
+    let td: serde_json::Value =
+        serde_json::from_str(&add_energy_mode(r#"{"title":"shtc3"}"#).unwrap()).unwrap();
+    assert_eq!(
+        td["properties"]["energyMode"]["enum"],
+        serde_json::json!(["continuous", "batched"])
+    );
+    assert!(add_energy_mode("[]").is_err());
+}
+
+#[test]
+fn config_wifi() {
+    assert!(config::ssid("home").is_ok());
+    assert!(config::ssid(&"x".repeat(32)).is_ok());
+    assert!(config::ssid(&"x".repeat(33)).is_err());
+    assert!(config::ssid("").is_err());
+
+    assert!(config::passphrase("").is_ok());
+    assert!(config::passphrase("correct horse").is_ok());
+    assert!(config::passphrase(&"ab12".repeat(16)).is_ok());
+    assert!(config::passphrase("short").is_err());
+    assert!(config::passphrase(&"x".repeat(64)).is_err());
+    assert!(config::passphrase("pässwörter").is_err());
+    assert!(config::passphrase("tab\there!").is_err());
+
+    assert!(config::eap("alice@example.com").is_ok());
+    assert!(config::eap(&"x".repeat(129)).is_err());
+
+    assert!(config::country("JP").is_ok());
+    assert!(config::country("jp").is_err());
+    assert!(config::max_tx_power("8").is_ok());
+    assert!(config::max_tx_power("21").is_err());
+    assert!(config::strike_limit("5").is_ok());
+    assert!(config::strike_limit("0").is_err());
+    assert!(config::strike_limit("256").is_err());
+    assert!(config::strike_window("600").is_ok());
+    assert!(config::strike_window("0").is_err());
+    assert!(config::strike_window("3601").is_err());
+}
+
+#[test]
+fn config_settings() {
+    assert!(config::http_url("http://192.168.1.10:8081").is_ok());
+    assert!(config::http_url("http://tdd.lan/api").is_ok());
+    assert!(config::http_url("https://tdd.lan").is_err());
+    assert!(config::http_url("http://tdd.lan:port").is_err());
+    assert!(config::http_url("http://tdd lan/").is_err());
+
+    assert!(config::base_url("https://things.example.com/shtc3").is_ok());
+    assert!(config::base_url("http://[fd00::1]:8080").is_ok());
+    assert!(config::base_url("things.example.com").is_err());
+    assert!(config::base_url("https:///path").is_err());
+
+    assert!(config::support_url("mailto:ops@example.com").is_ok());
+    assert!(config::support_url("https://example.com/issues").is_ok());
+    assert!(config::support_url("mailto:ops").is_err());
+    assert!(config::support_url("ops@example.com").is_err());
+
+    assert!(config::host("192.168.1.10").is_ok());
+    assert!(config::host("logs.lan").is_ok());
+    assert!(config::host("192.168.1.300").is_err());
+    assert!(config::host("-logs.lan").is_err());
+    assert!(config::host("logs..lan").is_err());
+    assert!(config::host("logs lan").is_err());
+
+    assert!(config::port("514").is_ok());
+    assert!(config::port("0").is_err());
+    assert!(config::port("65536").is_err());
+
+    assert!(config::log_level("DEBUG,esp_radio=warn").is_ok());
+    assert!(config::log_level("verbose").is_err());
+    assert!(config::wake_interval("15").is_ok());
+    assert!(config::wake_interval("0").is_err());
+    assert!(config::shutdown_wake("10080").is_ok());
+    assert!(config::shutdown_wake("10081").is_err());
+    assert!(config::flash_max_writes("12").is_ok());
+    assert!(config::flash_max_writes("0").is_err());
+    assert!(config::flash_max_writes("hourly").is_err());
+    assert!(config::brownout_level("4").is_ok());
+    assert!(config::brownout_level("1").is_err());
+    assert!(config::brownout_level("8").is_err());
+
+    let settings = [
+        Setting {
+            name: "SYSLOG_PORT",
+            value: Some("syslog"),
+            check: config::port,
+        },
+        Setting {
+            name: "DEBUG_PORT",
+            value: Some("8081"),
+            check: config::port,
+        },
+        Setting {
+            name: "TDD_URL",
+            value: None,
+            check: config::http_url,
+        },
+    ];
+    assert_eq!(
+        problems(&settings),
+        [Problem {
+            name: "SYSLOG_PORT",
+            reason: "must be a port from 1 to 65535",
+        }]
+    );
+}
+
+#[test]
+fn task_staleness() {
+    assert!(!is_stale(0, 1));
+    assert!(!is_stale(3, 1));
+    assert!(is_stale(4, 1));
+    assert!(!is_stale(90, 30));
+    assert!(is_stale(91, 30));
+}
+
+#[test]
+fn resolve_cache() {
+    let mut cache = Cache::new();
+    assert_eq!(cache.get("tdd.local", 0), None);
+
+    cache.resolved("tdd.local", 7, None, 0);
+    assert_eq!(cache.get("tdd.local", DEFAULT_TTL_MS - 1), Some(Some(7)));
+    assert_eq!(cache.get("tdd.local", DEFAULT_TTL_MS), None);
+
+    // Failures back off, longer each time, until a lookup succeeds again.
+    assert_eq!(cache.failed("broker", 0), 1000);
+    assert_eq!(cache.get("broker", 999), Some(None));
+    assert_eq!(cache.failed("broker", 1000), 2000);
+    cache.resolved("broker", 9, Some(60_000), 3000);
+    assert_eq!(cache.failed("broker", 63_000), 1000);
+    assert_eq!(backoff_ms(u32::MAX), 5 * 60 * 1000);
+
+    // Full, the entry expiring first makes room.
+    cache.resolved("a", 1, None, 0);
+    cache.resolved("b", 2, None, 0);
+    cache.resolved("d", 4, None, 0);
+    assert_eq!(cache.get("broker", 63_000), None);
+    assert_eq!(cache.get("d", 0), Some(Some(4)));
+    assert_eq!(cache.get("tdd.local", 0), Some(Some(7)));
+}
+
+#[test]
+fn self_test_results() {
+    let sensor: Result<u8, ()> = Err(());
+    assert_eq!(
+        results([
+            ("sensor", sensor.into()),
+            ("led", Outcome::Ok),
+            ("button", Outcome::Skipped),
+        ]),
+        serde_json::json!({ "sensor": "failed", "led": "ok", "button": "skipped" })
+    );
+    assert_eq!(results([]), serde_json::json!({}));
+}
+
+#[test]
+fn self_test_reads() {
+    let td = serde_json::json!({
+        "properties": {
+            "temperature": {
+                "forms": [
+                    { "href": "/events/temperature", "op": ["observeproperty"] },
+                    { "href": "/properties/temperature{?field}", "op": ["readproperty"] },
+                ],
+            },
+            // Absolute, as with a public base.
+            "on": {
+                "forms": [{
+                    "href": "http://proxy.example/light/properties/on",
+                    "op": "readproperty",
+                }],
+            },
+            "target": {
+                "writeOnly": true,
+                "forms": [{ "href": "/properties/target", "op": ["writeproperty"] }],
+            },
+        },
+    });
+    let paths: Vec<_> = reads(&td)
+        .iter()
+        .map(|(name, path)| format!("{name} {path}"))
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            "td /",
+            "on /light/properties/on",
+            "temperature /properties/temperature"
+        ]
+    );
+    assert_eq!(reads(&serde_json::Value::Null).len(), 1);
+
+    assert_eq!(read_outcome(200), Outcome::Ok);
+    assert_eq!(read_outcome(204), Outcome::Ok);
+    assert_eq!(read_outcome(404), Outcome::Failed);
+    assert_eq!(read_outcome(503), Outcome::Failed);
+    assert_eq!(
+        row("td", "/", Outcome::Ok, "200"),
+        format!("td{:14} /{:32}ok     200", "", "")
+    );
+}
+
+#[test]
+fn boot_status_blinks() {
+    let statuses = [
+        BootStatus::Starting,
+        BootStatus::WifiConnecting,
+        BootStatus::WaitingForIp,
+        BootStatus::Running,
+        BootStatus::Error,
+    ];
+    for (i, status) in statuses.iter().enumerate() {
+        let blink = status.blink_ms();
+        assert_eq!(blink.len() % 2, 0, "{status:?} ends on an on time");
+        assert!(
+            blink.is_empty() || blink.iter().sum::<u16>() > 0,
+            "{status:?}"
+        );
+        for other in &statuses[i + 1..] {
+            assert_ne!(blink, other.blink_ms(), "{status:?} and {other:?}");
+        }
+    }
+    assert!(BootStatus::Running.blink_ms().is_empty());
+}
+
+#[test]
+fn brownout_levels() {
+    assert_eq!(brownout::level(None), brownout::DEFAULT_LEVEL);
+    assert_eq!(brownout::level(Some("4")), 4);
+    assert_eq!(brownout::level(Some("9")), brownout::DEFAULT_LEVEL);
+    assert_eq!(brownout::level(Some("high")), brownout::DEFAULT_LEVEL);
+
+    // A lower level trips at a higher supply voltage.
+    let thresholds: Vec<_> = (2..=7).filter_map(brownout::threshold_mv).collect();
+    assert_eq!(thresholds.len(), 6);
+    assert!(thresholds.windows(2).all(|pair| pair[0] > pair[1]));
+    assert_eq!(brownout::threshold_mv(1), None);
+}
+
+#[test]
+fn capabilities() {
+    const SENSOR: Hardware = Hardware {
+        name: "shtc3",
+        affordances: &["temperature", "conditionSensor"],
+    };
+    const BUTTON: Hardware = Hardware {
+        name: "button",
+        affordances: &["pressed"],
+    };
+    let form = |href: &str| serde_json::json!({ "forms": [{ "href": href }] });
+    let mut td = serde_json::json!({
+        "properties": {
+            "temperature": form("/properties/temperature"),
+            "pressed": form("/properties/pressed"),
+            "die_temperature": form("/properties/die_temperature"),
+        },
+        "actions": { "conditionSensor": form("/actions/conditionSensor") },
+        "events": { "temperature": form("/events/temperature") },
+    });
+
+    let absent = restrict(td.as_object_mut().unwrap(), &[SENSOR, BUTTON], &["button"]);
+
+    assert_eq!(
+        absent,
+        [
+            "/properties/temperature",
+            "/events/temperature",
+            "/actions/conditionSensor"
+        ]
+    );
+    assert!(td["properties"].get("temperature").is_none());
+    assert!(td["events"].get("temperature").is_none());
+    assert_eq!(td["actions"], serde_json::json!({}));
+    assert_eq!(
+        td["properties"]["pressed"]["forms"][0]["href"],
+        "/properties/pressed"
+    );
+    assert!(td["properties"].get("die_temperature").is_some());
+    let capabilities = &td["properties"]["capabilities"];
+    assert_eq!(
+        capabilities["items"]["enum"],
+        serde_json::json!(["shtc3", "button"])
+    );
+    assert_eq!(capabilities["readOnly"], true);
+}
+
+#[test]
+fn schema_validation() {
+    const COLOR: Schema = Schema::Object(&[
+        Member {
+            name: "r",
+            required: true,
+            schema: Schema::Integer {
+                minimum: Some(0),
+                maximum: Some(255),
+            },
+        },
+        Member {
+            name: "g",
+            required: true,
+            schema: Schema::Integer {
+                minimum: Some(0),
+                maximum: Some(255),
+            },
+        },
+    ]);
+    const FADE: Schema = Schema::Object(&[
+        Member {
+            name: "duration_s",
+            required: true,
+            schema: Schema::Number {
+                minimum: Some(0.1),
+                maximum: Some(60.0),
+            },
+        },
+        Member {
+            name: "curve",
+            required: false,
+            schema: Schema::String {
+                one_of: &["linear", "ease"],
+            },
+        },
+        Member {
+            name: "loop",
+            required: false,
+            schema: Schema::Boolean,
+        },
+        Member {
+            name: "color",
+            required: true,
+            schema: COLOR,
+        },
+    ]);
+    let violation = |value: serde_json::Value| FADE.validate(&value).unwrap_err();
+    let at = |path: &str, message: &str| Violation {
+        path: path.into(),
+        message: message.into(),
+    };
+
+    let valid = serde_json::json!({
+        "duration_s": 2.5,
+        "curve": "ease",
+        "color": { "r": 255, "g": 0 },
+        "extra": null,
+    });
+    assert_eq!(FADE.validate(&valid), Ok(()));
+    assert_eq!(
+        violation(serde_json::json!([])),
+        at("", "expected an object")
+    );
+    // Types.
+    assert_eq!(
+        violation(serde_json::json!({ "duration_s": "2", "color": {} })),
+        at("/duration_s", "expected a number")
+    );
+    assert_eq!(
+        violation(serde_json::json!({ "duration_s": 1, "loop": 1, "color": {} })),
+        at("/loop", "expected a boolean")
+    );
+    // Bounds.
+    assert_eq!(
+        violation(serde_json::json!({ "duration_s": 0, "color": {} })),
+        at("/duration_s", "must be at least 0.1")
+    );
+    assert_eq!(
+        violation(serde_json::json!({ "duration_s": 61, "color": {} })),
+        at("/duration_s", "must be at most 60")
+    );
+    // Enums.
+    assert_eq!(
+        violation(serde_json::json!({ "duration_s": 1, "curve": "bounce", "color": {} })),
+        at("/curve", "must be one of linear, ease")
+    );
+    // Required members, and the first violation of nested objects.
+    assert_eq!(
+        violation(serde_json::json!({ "duration_s": 1 })),
+        at("/color", "is required")
+    );
+    assert_eq!(
+        violation(serde_json::json!({ "duration_s": 1, "color": { "r": 256, "g": -1 } })),
+        at("/color/r", "must be at most 255")
+    );
+    assert_eq!(
+        violation(serde_json::json!({ "duration_s": 1, "color": { "r": 1.5 } })),
+        at("/color/r", "expected an integer")
+    );
+    assert_eq!(
+        violation(serde_json::json!({ "duration_s": 1, "color": { "r": 0 } })),
+        at("/color/g", "is required")
+    );
+    assert_eq!(
+        violation(serde_json::json!({ "duration_s": 1, "color": { "r": 0, "g": u64::MAX } })),
+        at("/color/g", "must be at most 255")
+    );
+
+    assert_eq!(
+        at("/color/r", "must be at most 255").to_json(),
+        serde_json::json!({ "error": "must be at most 255", "path": "/color/r" })
+    );
+    assert_eq!(
+        light::SunriseInput::SCHEMA.validate(&serde_json::json!({ "duration_s": 0 })),
+        Err(at("/duration_s", "must be at least 1"))
+    );
+}
+
+#[test]
+fn identity() {
+    let identity = Identity::parse(&serde_json::json!({
+        "title": "Greenhouse North",
+        "location": "row 2.b",
+    }))
+    .unwrap();
+    assert_eq!(identity.title, "Greenhouse North");
+    // The TD keeps the title as is, mDNS gets it without the dot.
+    assert_eq!(identity.instance_name(), "Greenhouse North-row 2b");
+
+    let identity = Identity::parse(&serde_json::json!({ "title": " Serre ", "location": null }));
+    assert_eq!(identity.unwrap().instance_name(), "Serre");
+
+    for body in [
+        serde_json::json!({ "location": "north" }),
+        serde_json::json!({ "title": "  " }),
+        serde_json::json!({ "title": "a".repeat(33) }),
+        serde_json::json!({ "title": "a\nb" }),
+        serde_json::json!({ "title": "a", "location": 2 }),
+    ] {
+        assert!(Identity::parse(&body).is_err(), "{body}");
+    }
+
+    let td = retitle_json(r#"{"title":"light","id":"urn:x"}"#, "Greenhouse North").unwrap();
+    assert_eq!(td, r#"{"id":"urn:x","title":"Greenhouse North"}"#);
+
+    assert_eq!(titled("light", "light-4321"), "light (light-4321)");
+    assert_eq!(titled("light", "light"), "light");
+    assert_eq!(titled("light", ""), "light");
+}
+
+#[test]
+fn names() {
+    assert_eq!(hostname("light", &[0xaa, 0xbb, 1, 2, 3, 4]), "light-4321");
+    let hw = [0x30, 0xae, 0xa4, 0x01, 0x02, 0x0f];
+    assert_eq!(device_urn(None, "fan", &hw), "urn:dev:mac:30aea401020f");
+    assert_eq!(
+        device_urn(Some("acme"), "fan", &hw),
+        "urn:acme:fan:30aea401020f"
+    );
+    assert_eq!(
+        mac(&[0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0x0f]),
+        "aa:bb:cc:dd:ee:0f"
+    );
+}
+
+#[test]
+fn template_substitution() {
+    let vars = [("BASE", "http://192.0.2.1"), ("TITLE", "a \"b\"\n")];
+
+    assert_eq!(
+        substitute("{{BASE}}/properties/{{TITLE}}", &vars),
+        "http://192.0.2.1/properties/a \\\"b\\\"\\u000a"
+    );
+    assert_eq!(substitute("{{ID}} {{BASE", &vars), "{{ID}} {{BASE");
+    assert_eq!(substitute("{{X}}{{BASE}}", &vars), "{{X}}http://192.0.2.1");
+
+    // A placeholder inside an href of the Thing Model.
+    let tm = r#"{"links":[{"rel":"type","href":"{{BASE}}/tm.json"}],"forms":[{"href":"{{BASE}}/properties/temperature?id={{ID}}"}]}"#;
+    let vars = [("BASE", "http://192.0.2.1"), ("ID", "urn:dev:mac:0011")];
+    let td: serde_json::Value = serde_json::from_str(&substitute(tm, &vars)).unwrap();
+    assert_eq!(td["links"][0]["href"], "http://192.0.2.1/tm.json");
+    assert_eq!(
+        td["forms"][0]["href"],
+        "http://192.0.2.1/properties/temperature?id=urn:dev:mac:0011"
+    );
+}
+
+/// A standard query for `name` with the given type.
+fn query(name: &str, qtype: u16) -> Vec<u8> {
+    let mut q = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in name.split('.') {
+        q.push(label.len() as u8);
+        q.extend_from_slice(label.as_bytes());
+    }
+    q.push(0);
+    q.extend_from_slice(&qtype.to_be_bytes());
+    q.extend_from_slice(&1_u16.to_be_bytes());
+    q
+}
+
+#[test]
+fn captive_dns_a() {
+    let q = query("connectivitycheck.gstatic.com", 1);
+    let mut response = [0; 512];
+    let len = answer(&q, &mut response, Ipv4Addr::new(192, 168, 4, 1)).unwrap();
+    let response = &response[..len];
+
+    assert_eq!(len, q.len() + 16);
+    assert_eq!(&response[..2], &[0x12, 0x34]);
+    // Response, authoritative, recursion desired.
+    assert_eq!(&response[2..4], &[0x85, 0x00]);
+    // One question, one answer.
+    assert_eq!(&response[4..8], &[0, 1, 0, 1]);
+    assert_eq!(&response[12..q.len()], &q[12..]);
+    assert_eq!(&response[len - 4..], &[192, 168, 4, 1]);
+}
+
+#[test]
+fn captive_dns_other() {
+    let mut response = [0; 512];
+
+    // AAAA: no answer.
+    let q = query("example.com", 28);
+    let len = answer(&q, &mut response, Ipv4Addr::LOCALHOST).unwrap();
+    assert_eq!(len, q.len());
+    assert_eq!(&response[6..8], &[0, 0]);
+
+    // Responses and truncated queries are ignored.
+    let mut r = query("example.com", 1);
+    r[2] |= 0x80;
+    assert_eq!(answer(&r, &mut response, Ipv4Addr::LOCALHOST), None);
+    assert_eq!(
+        answer(&q[..q.len() - 1], &mut response, Ipv4Addr::LOCALHOST),
+        None
+    );
+}
+
+#[test]
+fn dns_sd_query() {
+    let mut buf = [0; 64];
+    let len = ptr_query(0x1234, "_wot._tcp.local", &mut buf).unwrap();
+
+    let mut expected = vec![0x12, 0x34, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    expected.extend_from_slice(b"\x04_wot\x04_tcp\x05local\x00\x00\x0c\x80\x01");
+    assert_eq!(&buf[..len], expected);
+
+    assert_eq!(ptr_query(1, "_wot._tcp.local", &mut buf[..20]), None);
+    assert_eq!(ptr_query(1, &"a".repeat(64), &mut [0; 128]), None);
+}
+
+#[test]
+fn dns_sd_answers() {
+    let answer = [0x12, 0x34, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+    assert!(is_answer(&answer, 0x1234));
+    assert!(!is_answer(&answer, 0x4321));
+    assert!(!is_answer(&answer[..7], 0x1234));
+
+    // Queries and empty answers are not.
+    let mut query = answer;
+    query[2] = 0;
+    assert!(!is_answer(&query, 0x1234));
+    let mut empty = answer;
+    empty[7] = 0;
+    assert!(!is_answer(&empty, 0x1234));
+}
+
+/// An answer to query 0x1234 for `_wot._tcp.local`: the `PTR` record to
+/// `tdd`, then `txt` and the `SRV` record of port 8081 as additional
+/// records, the names compressed.
+fn directory_answer(txt: &[&str]) -> Vec<u8> {
+    let mut answer = vec![0x12, 0x34, 0x84, 0, 0, 1, 0, 1, 0, 0, 0, 2];
+    answer.extend_from_slice(b"\x04_wot\x04_tcp\x05local\x00\x00\x0c\x80\x01");
+    // PTR, to the instance at offset 45.
+    answer.extend_from_slice(b"\xc0\x0c\x00\x0c\x00\x01\x00\x00\x11\x94\x00\x06");
+    answer.extend_from_slice(b"\x03tdd\xc0\x0c");
+
+    let data: Vec<u8> = txt
+        .iter()
+        .flat_map(|entry| [&[entry.len() as u8][..], entry.as_bytes()].concat())
+        .collect();
+    answer.extend_from_slice(b"\xc0\x2d\x00\x10\x80\x01\x00\x00\x11\x94\x00");
+    answer.push(data.len() as u8);
+    answer.extend_from_slice(&data);
+
+    answer.extend_from_slice(b"\xc0\x2d\x00\x21\x80\x01\x00\x00\x00\x78\x00\x08");
+    answer.extend_from_slice(b"\x00\x00\x00\x00\x1f\x91\xc0\x2d");
+    answer
+}
+
+#[test]
+fn dns_sd_instance() {
+    let answer = directory_answer(&["type=Directory", "td=/td"]);
+    assert_eq!(
+        instance(&answer, 0x1234),
+        Some(Instance {
+            port: 8081,
+            td: Some("/td"),
+        })
+    );
+    assert_eq!(instance(&answer, 0x4321), None);
+    // Cut short.
+    assert_eq!(instance(&answer[..answer.len() - 4], 0x1234), None);
+
+    let answer = directory_answer(&["type=Directory", "tdx=/no"]);
+    assert_eq!(instance(&answer, 0x1234).unwrap().td, None);
+}
+
+#[test]
+fn dns_sd_probe() {
+    let mut buf = [0; 64];
+    let len = probe_query(&["light-4321.local", "light._wot._tcp.local"], &mut buf).unwrap();
+
+    let mut expected = vec![0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0];
+    expected.extend_from_slice(b"\x0alight-4321\x05local\x00\x00\xff\x80\x01");
+    expected.extend_from_slice(b"\x05light\x04_wot\x04_tcp\x05local\x00\x00\xff\x80\x01");
+    assert_eq!(&buf[..len], expected);
+    assert_eq!(probe_query(&["light-4321.local"], &mut buf[..20]), None);
+
+    // The directory answer names `_wot._tcp.local` and, compressed,
+    // `tdd._wot._tcp.local`.
+    let answer = directory_answer(&["type=Directory"]);
+    assert!(claims(&answer, "tdd._wot._tcp.local"));
+    assert!(claims(&answer, "TDD._wot._tcp.local"));
+    assert!(claims(&answer, "_wot._tcp.local"));
+    assert!(!claims(&answer, "light._wot._tcp.local"));
+    assert!(!claims(&answer, "tdd._wot._tcp"));
+    // Cut short.
+    assert!(!claims(&answer[..answer.len() - 4], "x.local"));
+
+    // Another host's probe, a query, claims nothing yet.
+    assert!(!claims(&expected, "light-4321.local"));
+
+    assert_eq!(host_alternative("light-4321", 1), "light-4321");
+    assert_eq!(host_alternative("light-4321", 2), "light-4321-2");
+    assert_eq!(instance_alternative("light", 1), "light");
+    assert_eq!(instance_alternative("light", 3), "light (3)");
+}
+
+#[test]
+fn directory_registration() {
+    let td = serde_json::json!({ "id": "urn:uuid:0804d572-cce8-422a-bb7c-4412fcd56f06" });
+    assert_eq!(
+        directory::registration_url("http://192.0.2.5:8081/", &td).as_deref(),
+        Some("http://192.0.2.5:8081/things/urn:uuid:0804d572-cce8-422a-bb7c-4412fcd56f06")
+    );
+    assert_eq!(
+        directory::registration_url("http://tdd", &serde_json::json!({})),
+        None
+    );
+
+    // The base of the directory's TD wins over its address.
+    let tdd = serde_json::json!({ "base": "http://tdd.example/api/" });
+    assert_eq!(
+        directory::base(&tdd, "192.0.2.5", 8081),
+        "http://tdd.example/api"
+    );
+    assert_eq!(
+        directory::base(&serde_json::json!({}), "192.0.2.5", 8081),
+        "http://192.0.2.5:8081"
+    );
+}
+
+const fn network(ssid: &'static str, hidden: bool) -> Credentials {
+    Credentials {
+        ssid,
+        auth: Auth::Psk(""),
+        hidden,
+    }
+}
+
+fn ssids(networks: &[Credentials]) -> Vec<&str> {
+    networks.iter().map(|n| n.ssid).collect()
+}
+
+#[test]
+fn networks_by_signal() {
+    let networks = [
+        network("a", false),
+        network("hidden", true),
+        network("b", false),
+        network("c", false),
+    ];
+
+    assert_eq!(
+        ssids(&order_by_signal(
+            &networks,
+            &[("c", -40), ("b", -70), ("b", -60)]
+        )),
+        ["c", "hidden", "b", "a"]
+    );
+    assert_eq!(
+        ssids(&order_by_signal(&networks, &[])),
+        ["a", "hidden", "b", "c"]
+    );
+}
+
+#[test]
+fn radio_settings() {
+    assert_eq!(
+        RadioSettings::parse(None, None),
+        Ok(RadioSettings::default())
+    );
+    assert_eq!(RadioSettings::default().country(), "01");
+
+    let jp = RadioSettings::parse(Some("JP"), Some("14")).unwrap();
+    assert_eq!(jp.country(), "JP");
+    assert_eq!(jp.max_tx_power, 14);
+    assert!(jp.allows(2));
+    assert!(jp.allows(14));
+    assert!(!jp.allows(15));
+    assert!(!jp.allows(1));
+    assert_eq!(quarter_dbm(14), 56);
+
+    assert!(RadioSettings::parse(Some("jp"), None).is_err());
+    assert!(RadioSettings::parse(Some("JPN"), None).is_err());
+    assert!(RadioSettings::parse(None, Some("21")).is_err());
+    assert!(RadioSettings::parse(None, Some("high")).is_err());
+}
+
+#[test]
+fn temperature_events() {
+    assert!(!temperature_changed(21.0, 21.05));
+    assert!(temperature_changed(21.0, 21.2));
+    assert!(temperature_changed(21.2, 21.0));
+    assert!(temperature_changed(-500.0, 21.0));
+}
+
+#[test]
+fn measurement_modes() {
+    for mode in [MeasurementMode::Normal, MeasurementMode::LowPower] {
+        assert_eq!(MeasurementMode::parse(mode.as_str()), Some(mode));
+    }
+    assert_eq!(MeasurementMode::parse("Normal"), None);
+    assert_eq!(MeasurementMode::parse(""), None);
+}
+
+#[test]
+fn button_edges() {
+    for (pressed, edge) in [(true, "pressed"), (false, "released")] {
+        let event = EdgeEvent {
+            button: None,
+            pressed,
+            uptime_ms: 10_805_123,
+        };
+        let data = serde_json::to_value(event).unwrap();
+        assert_eq!(
+            data,
+            serde_json::json!({ "edge": edge, "uptime_ms": 10_805_123 })
+        );
+    }
+
+    // One of several buttons tells which.
+    let event = EdgeEvent {
+        button: Some(1),
+        pressed: true,
+        uptime_ms: 42,
+    };
+    assert_eq!(
+        serde_json::to_value(event).unwrap(),
+        serde_json::json!({ "button": 1, "edge": "pressed", "uptime_ms": 42 })
+    );
+}
+
+#[test]
+fn button_toggles() {
+    let single = button::toggle(0, 1);
+    assert_eq!(
+        (single.name, single.property, single.event),
+        ("on", "/properties/on", "/events/on")
+    );
+    // The routes past the only button do not clash with it.
+    assert_eq!(button::toggle(1, 1).name, "on1");
+    for n in 0..MAX_BUTTONS {
+        let toggle = button::toggle(n, MAX_BUTTONS);
+        assert_eq!(toggle.name, format!("on{n}"));
+        assert_eq!(toggle.property, format!("/properties/on{n}"));
+        assert_eq!(toggle.event, format!("/events/on{n}"));
+    }
+}
+
+#[test]
+fn thermometer_dew_point() {
+    let approx = |t: f32, rh: f32, expected: f32| {
+        let dew = dew_point(t, rh).unwrap();
+        assert!((dew - expected).abs() < 0.05, "{t} °C {rh} %: {dew}");
+    };
+    approx(20.0, 50.0, 9.26);
+    approx(25.0, 100.0, 25.0);
+    approx(0.0, 80.0, -3.04);
+
+    // Clamped rather than NaN: 0 % humidity, and below the formula's range.
+    for (t, rh) in [(20.0, 0.0), (20.0, -3.0), (-60.0, 50.0), (90.0, 120.0)] {
+        assert!(dew_point(t, rh).unwrap().is_finite(), "{t} °C {rh} %");
+    }
+    assert_eq!(dew_point(-60.0, 50.0), dew_point(-45.0, 50.0));
+
+    assert_eq!(dew_point(f32::NAN, 50.0), None);
+    assert_eq!(dew_point(20.0, f32::NAN), None);
+}
+
+#[test]
+fn thermometer_heat_index() {
+    // From the NOAA heat index table, in degrees Fahrenheit.
+    let approx = |t_f: f32, rh: f32, expected_f: f32| {
+        let celsius = |f: f32| (f - 32.0) / 1.8;
+        let hi = heat_index(celsius(t_f), rh).unwrap();
+        assert!(
+            (hi - celsius(expected_f)).abs() < 0.5,
+            "{t_f} °F {rh} %: {hi}"
+        );
+    };
+    approx(90.0, 60.0, 100.0);
+    approx(100.0, 40.0, 109.0);
+    approx(86.0, 90.0, 105.0);
+    approx(96.0, 65.0, 121.0);
+    approx(104.0, 55.0, 137.0);
+    // The adjustments for a dry and a humid air.
+    approx(90.0, 5.0, 84.5);
+    approx(82.0, 100.0, 96.0);
+
+    // The temperature itself below 27 °C.
+    assert_eq!(heat_index(26.9, 90.0), Some(26.9));
+    assert_eq!(heat_index(-5.0, 50.0), Some(-5.0));
+    assert_eq!(heat_index(f32::NAN, 50.0), None);
+    assert_eq!(heat_index(30.0, f32::NAN), None);
+
+    let imperial = Reading::new(32.0, 60.0, UnitsSystem::Imperial);
+    let si = Reading::new(32.0, 60.0, UnitsSystem::Si);
+    let hi = UnitsSystem::Imperial.temperature(si.heat_index.unwrap());
+    assert!((imperial.heat_index.unwrap() - hi).abs() < 1e-3);
+}
+
+#[test]
+fn thermometer_frost_warning() {
+    assert!(frost_risk(2.0, -1.5));
+    assert!(!frost_risk(4.0, -1.5));
+    assert!(!frost_risk(2.0, 0.0));
+    assert!(!frost_risk(f32::NAN, -1.5));
+    // Dry cold air: 2 °C at 60 % has its dew point below 0 °C.
+    assert!(frost_risk(2.0, dew_point(2.0, 60.0).unwrap()));
+    assert!(!frost_risk(2.0, dew_point(2.0, 95.0).unwrap()));
+
+    assert_eq!(
+        serde_json::to_value(FrostWarning {
+            warning: true,
+            temperature: 2.5,
+            dew_point: -1.5,
+        })
+        .unwrap(),
+        serde_json::json!({ "warning": true, "temperature": 2.5, "dewPoint": -1.5 })
+    );
+}
+
+#[test]
+fn thermometer_sensor_info() {
+    assert_eq!(
+        sensor_info(Some(0x887c)),
+        serde_json::json!({ "type": "SHTC3", "id": "0x887C", "present": true })
+    );
+    assert_eq!(
+        sensor_info(None),
+        serde_json::json!({ "type": "SHTC3", "id": null, "present": false })
+    );
+}
+
+#[test]
+fn thermometer_offsets() {
+    assert_eq!(Offsets::default().apply(21.5, 40.0), (21.5, 40.0));
+
+    let offsets = Offsets {
+        temperature: -1.5,
+        humidity: 5.0,
+    };
+    assert_eq!(offsets.apply(21.5, 40.0), (20.0, 45.0));
+    // Humidity stays a percentage.
+    assert_eq!(offsets.apply(21.5, 98.0), (20.0, 100.0));
+    let offsets = Offsets {
+        temperature: 0.0,
+        humidity: -10.0,
+    };
+    assert_eq!(offsets.apply(0.0, 4.0), (0.0, 0.0));
+}
+
+#[test]
+fn thermometer_units() {
+    for units in [UnitsSystem::Si, UnitsSystem::Imperial] {
+        assert_eq!(UnitsSystem::parse(units.as_str()), Some(units));
+        assert!((units.to_celsius(units.temperature(21.5)) - 21.5).abs() < 1e-4);
+    }
+    assert_eq!(UnitsSystem::parse("metric"), None);
+    assert_eq!(UnitsSystem::Imperial.temperature(100.0), 212.0);
+    assert_eq!(UnitsSystem::Imperial.temperature(-40.0), -40.0);
+
+    // The dew point follows the temperature, the humidity stays a percentage.
+    let si = Reading::new(20.0, 50.0, UnitsSystem::Si);
+    let imperial = Reading::new(20.0, 50.0, UnitsSystem::Imperial);
+    assert_eq!(imperial.temperature, 68.0);
+    assert_eq!(imperial.humidity, 50.0);
+    let dew = imperial.dew_point.unwrap();
+    assert!((dew - UnitsSystem::Imperial.temperature(si.dew_point.unwrap())).abs() < 1e-3);
+    let back = imperial.convert(UnitsSystem::Imperial, UnitsSystem::Si);
+    assert!((back.temperature - 20.0).abs() < 1e-4);
+    assert!((back.dew_point.unwrap() - si.dew_point.unwrap()).abs() < 1e-4);
+}
+
+#[test]
+fn thermometer_td_units() {
+    let td = serde_json::json!({
+        "properties": {
+            "temperature": { "type": "number", "unit": "Celsius" },
+            "temperatureOffset": { "type": "number", "unit": "Celsius" },
+            "humidity": { "type": "number", "unit": "%" },
+        },
+        "events": {
+            "over_temperature": { "data": { "properties": {
+                "state": { "type": "string" },
+                "value": { "type": "number", "unit": "Celsius" },
+            } } },
+        },
+    })
+    .to_string();
+
+    let imperial: serde_json::Value =
+        serde_json::from_str(&with_units(&td, UnitsSystem::Imperial).unwrap()).unwrap();
+    assert_eq!(imperial["properties"]["temperature"]["unit"], "Fahrenheit");
+    assert_eq!(
+        imperial["events"]["over_temperature"]["data"]["properties"]["value"]["unit"],
+        "Fahrenheit"
+    );
+    // The calibration stays in degrees Celsius.
+    assert_eq!(
+        imperial["properties"]["temperatureOffset"]["unit"],
+        "Celsius"
+    );
+    assert_eq!(imperial["properties"]["humidity"]["unit"], "%");
+
+    let si = with_units(&imperial.to_string(), UnitsSystem::Si).unwrap();
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&si).unwrap(),
+        serde_json::from_str::<serde_json::Value>(&td).unwrap()
+    );
+}
+
+#[test]
+fn multitherm_shtc3_frames() {
+    // The example of the Sensirion datasheets.
+    assert_eq!(multitherm::crc8(&[0xBE, 0xEF]), 0x92);
+
+    let id = [0x08, 0x87, multitherm::crc8(&[0x08, 0x87])];
+    assert_eq!(multitherm::parse_id(&id), Some(0x0887));
+    assert_eq!(multitherm::parse_id(&[0x08, 0x87, id[2] ^ 1]), None);
+    // Another Sensirion sensor at the same address.
+    let other = [0x04, 0x00, multitherm::crc8(&[0x04, 0x00])];
+    assert_eq!(multitherm::parse_id(&other), None);
+
+    // 0x6666 is 25 °C, 0x8000 is 50 %.
+    let mut answer = [0x66, 0x66, 0, 0x80, 0x00, 0];
+    answer[2] = multitherm::crc8(&answer[..2]);
+    answer[5] = multitherm::crc8(&answer[3..5]);
+    let (temperature, humidity) = multitherm::parse_measurement(&answer).unwrap();
+    assert!((temperature - 25.0).abs() < 0.01, "{temperature}");
+    assert_eq!(humidity, 50.0);
+    answer[5] ^= 1;
+    assert_eq!(multitherm::parse_measurement(&answer), None);
+
+    assert_eq!(multitherm::select(0), Some(0x01));
+    assert_eq!(multitherm::select(7), Some(0x80));
+    assert_eq!(multitherm::select(8), None);
+}
+
+#[test]
+fn multitherm_temperatures() {
+    use multitherm::Probe;
+
+    let mut probes = [Probe::Absent; multitherm::CHANNELS];
+    assert_eq!(multitherm::temperatures(&probes), serde_json::json!({}));
+
+    probes[0] = Probe::Healthy(21.5);
+    probes[3] = Probe::Unhealthy;
+    probes[5] = Probe::Pending;
+    assert_eq!(
+        multitherm::temperatures(&probes),
+        serde_json::json!({ "temperature0": 21.5, "temperature3": null, "temperature5": null })
+    );
+
+    for (channel, probe) in multitherm::PROBES.iter().enumerate() {
+        assert_eq!(probe.affordances, [multitherm::PROBE_NAMES[channel]]);
+    }
+}
+
+#[test]
+fn light_off_timer() {
+    assert_eq!(light::off_timer_remaining(301_000, 1_000), 300);
+    assert_eq!(light::off_timer_remaining(301_000, 1_001), 300);
+    assert_eq!(light::off_timer_remaining(301_000, 300_999), 1);
+    assert_eq!(light::off_timer_remaining(301_000, 301_000), 0);
+    assert_eq!(light::off_timer_remaining(301_000, 400_000), 0);
+}
+
+#[test]
+fn light_luma() {
+    // The Rec. 709 weights, at full brightness.
+    assert_eq!(light::luma((255, 255, 255), 255), 255);
+    assert_eq!(light::luma((255, 0, 0), 255), 54);
+    assert_eq!(light::luma((0, 255, 0), 255), 182);
+    assert_eq!(light::luma((0, 0, 255), 255), 18);
+    assert_eq!(light::luma((0, 0, 0), 255), 0);
+
+    // Scaled by the brightness, rounded.
+    assert_eq!(light::luma((255, 255, 255), 128), 128);
+    assert_eq!(light::luma((0, 255, 0), 128), 92);
+    assert_eq!(light::luma((255, 255, 255), 0), 0);
+
+    // Never above the brightness: white is the brightest color.
+    let warm = (255, 147, 41);
+    assert_eq!(light::luma(warm, 180), 115);
+    assert!((0..=255).all(|b| light::luma(warm, b) <= b));
+}
+
+#[test]
+fn light_last_active() {
+    use light::{LastActive, LightState};
+
+    const RED: (u8, u8, u8) = (255, 0, 0);
+    const BLUE: (u8, u8, u8) = (0, 0, 255);
+    let last = |brightness, color| LastActive { brightness, color };
+
+    let mut state = LightState::new(100, RED);
+    assert_eq!((state.on, state.shown_brightness()), (false, 0));
+    state.power(true);
+    assert_eq!((state.on, state.brightness, state.color), (true, 100, RED));
+
+    // Faded to 0 while on, then off and on again: back at the last brightness.
+    for b in [60, 20, 0] {
+        state.set_brightness(b);
+    }
+    assert_eq!(state.last_active(), last(20, RED));
+    state.power(false);
+    state.power(true);
+    assert_eq!((state.brightness, state.color), (20, RED));
+
+    // A write while off sets what turning on shows.
+    state.power(false);
+    state.set_color(BLUE);
+    state.set_brightness(200);
+    assert!(!state.on);
+    state.power(true);
+    assert_eq!((state.brightness, state.color), (200, BLUE));
+
+    // Turning on mid-animation cancels it and shows the state from before.
+    state.animate(RED, 5);
+    assert_eq!(state.last_active(), last(200, BLUE));
+    state.power(true);
+    assert_eq!((state.brightness, state.color), (200, BLUE));
+
+    // So does a write, which applies to the state from before.
+    state.animate(RED, 5);
+    state.set_brightness(150);
+    assert_eq!((state.brightness, state.color), (150, BLUE));
+
+    // An animation run to its end stays on its last frame.
+    state.animate(RED, 180);
+    state.settle();
+    state.power(false);
+    state.power(true);
+    assert_eq!((state.brightness, state.color), (180, RED));
+
+    assert_eq!(LightState::new(0, RED).last_active(), last(255, RED));
+    assert_eq!(
+        serde_json::to_value(last(20, RED)).unwrap(),
+        serde_json::json!({ "brightness": 20, "color": [255, 0, 0] })
+    );
+}
+
+/// Milliseconds since the Unix epoch of `hour:minute` UTC on day `day`.
+fn utc(day: u64, hour: u64, minute: u64) -> u64 {
+    ((day * 24 + hour) * 60 + minute) * 60_000
+}
+
+#[test]
+fn vacation_windows() {
+    use light::{vacation_plan, VacationPlan, VacationSchedule};
+
+    // 22:00 to 02:00, across midnight, without jitter.
+    let schedule = VacationSchedule {
+        start_hour: 22,
+        end_hour: 2,
+        jitter_min: 0,
+        utc_offset_min: 0,
+    };
+    assert_eq!(schedule.validate(), Ok(()));
+    let plan = |now| vacation_plan(now, &schedule, 7);
+
+    assert_eq!(
+        plan(utc(100, 21, 59)),
+        VacationPlan {
+            on: false,
+            next_change_ms: utc(100, 22, 0),
+        }
+    );
+    assert_eq!(
+        plan(utc(100, 22, 0)),
+        VacationPlan {
+            on: true,
+            next_change_ms: utc(101, 2, 0),
+        }
+    );
+    // Past midnight, still in the window of the day before.
+    assert_eq!(
+        plan(utc(101, 1, 59)),
+        VacationPlan {
+            on: true,
+            next_change_ms: utc(101, 2, 0),
+        }
+    );
+    assert_eq!(
+        plan(utc(101, 2, 0)),
+        VacationPlan {
+            on: false,
+            next_change_ms: utc(101, 22, 0),
+        }
+    );
+
+    // In UTC+2 the window is 20:00 to 00:00 UTC.
+    let schedule = VacationSchedule {
+        utc_offset_min: 120,
+        ..schedule
+    };
+    let plan = vacation_plan(utc(100, 23, 30), &schedule, 7);
+    assert!(plan.on);
+    assert_eq!(plan.next_change_ms, utc(101, 0, 0));
+    let plan = vacation_plan(utc(101, 0, 0), &schedule, 7);
+    assert!(!plan.on);
+    assert_eq!(plan.next_change_ms, utc(101, 20, 0));
+}
+
+#[test]
+fn vacation_jitter() {
+    use light::{vacation_plan, VacationSchedule};
+
+    // 00:00 to 04:00 with an hour of jitter: the window of a day may start
+    // the evening before.
+    let schedule = VacationSchedule {
+        start_hour: 0,
+        end_hour: 4,
+        jitter_min: 60,
+        utc_offset_min: 0,
+    };
+    assert_eq!(schedule.validate(), Ok(()));
+
+    for seed in 0..50 {
+        let mut now = utc(200, 12, 0);
+        let mut on = false;
+        // Two days of changes: each within the jitter, and alternating.
+        for day in [201, 202] {
+            for (hour, lit) in [(0, true), (4, false)] {
+                let plan = vacation_plan(now, &schedule, seed);
+                assert_eq!(plan.on, on, "seed {seed}");
+                let due = utc(day, hour, 0);
+                assert!(
+                    plan.next_change_ms.abs_diff(due) <= 60 * 60_000,
+                    "seed {seed}"
+                );
+                // Just before the change, and at it.
+                assert_eq!(
+                    vacation_plan(plan.next_change_ms - 1, &schedule, seed).on,
+                    on
+                );
+                now = plan.next_change_ms;
+                on = lit;
+            }
+        }
+        assert_eq!(
+            vacation_plan(utc(201, 12, 0), &schedule, seed),
+            vacation_plan(utc(201, 12, 0), &schedule, seed)
+        );
+    }
+
+    // Some other seed moves the changes.
+    let at = |seed| vacation_plan(utc(200, 12, 0), &schedule, seed).next_change_ms;
+    assert!((1..50).any(|seed| at(seed) != at(0)));
+}
+
+#[test]
+fn vacation_schedule_checks() {
+    use light::VacationSchedule;
+
+    let valid = VacationSchedule::default();
+    assert_eq!(valid.validate(), Ok(()));
+    for invalid in [
+        VacationSchedule {
+            start_hour: 24,
+            ..valid
+        },
+        VacationSchedule {
+            end_hour: 18,
+            ..valid
+        },
+        // Could turn off before it turns on.
+        VacationSchedule {
+            start_hour: 18,
+            end_hour: 19,
+            jitter_min: 30,
+            ..valid
+        },
+        // Could turn on for the next day before it turns off.
+        VacationSchedule {
+            start_hour: 18,
+            end_hour: 17,
+            jitter_min: 30,
+            ..valid
+        },
+        VacationSchedule {
+            utc_offset_min: 15 * 60,
+            ..valid
+        },
+    ] {
+        assert!(invalid.validate().is_err(), "{invalid:?}");
+    }
+
+    assert_eq!(
+        body::parse::<VacationSchedule>(
+            br#"{"startHour":19,"endHour":1,"jitterMin":45,"utcOffsetMin":-300}"#
+        ),
+        Ok(VacationSchedule {
+            start_hour: 19,
+            end_hour: 1,
+            jitter_min: 45,
+            utc_offset_min: -300,
+        })
+    );
+}
+
+#[test]
+fn sntp_answer() {
+    assert_eq!(sntp::REQUEST[0], 0x23);
+
+    // 2024-01-01T00:00:00.5Z from a stratum 2 server.
+    let mut answer = [0; sntp::PACKET_LEN];
+    answer[0] = 0x24;
+    answer[1] = 2;
+    let seconds = 1_704_067_200 + sntp::NTP_UNIX_OFFSET_S;
+    answer[40..44].copy_from_slice(&u32::try_from(seconds).unwrap().to_be_bytes());
+    answer[44..48].copy_from_slice(&0x8000_0000_u32.to_be_bytes());
+    assert_eq!(sntp::parse(&answer), Some(1_704_067_200_500));
+
+    assert_eq!(sntp::parse(&answer[..47]), None);
+    let mut kiss = answer;
+    kiss[1] = 0;
+    assert_eq!(sntp::parse(&kiss), None);
+    let mut unsynchronized = answer;
+    unsynchronized[0] |= 0xC0;
+    assert_eq!(sntp::parse(&unsynchronized), None);
+    let mut request = answer;
+    request[0] = sntp::REQUEST[0];
+    assert_eq!(sntp::parse(&request), None);
+}
+
+#[test]
+fn write_bodies() {
+    assert_eq!(body::parse::<bool>(b" true "), Ok(true));
+    assert_eq!(body::parse::<u8>(b"256"), Err(body::Error::InvalidNumber));
+    assert_eq!(
+        body::parse::<bool>(b"true false"),
+        Err(body::Error::TrailingCharacters)
+    );
+    assert_eq!(
+        body::parse::<light::OffTimerInput>(br#"{"delay_s":"soon"}"#).map_err(|e| body::error(&e)),
+        Err(serde_json::json!({ "error": "Invalid type" })),
+    );
+}
+
+#[test]
+fn light_action_bodies_fit() {
+    // The largest bodies the TD allows, pretty-printed as clients may send them.
+    let off_timer = light::OffTimerInput {
+        delay_s: light::MAX_OFF_DELAY_S,
+    };
+    let json = serde_json::to_string_pretty(&off_timer).unwrap();
+    assert!(json.len() <= light::OFF_TIMER_MAX_BODY, "{json}");
+    assert_eq!(body::parse(json.as_bytes()), Ok(off_timer));
+
+    let sunrise = light::SunriseInput {
+        duration_s: light::MAX_SUNRISE_S,
+        target_brightness: u8::MAX,
+    };
+    let json = serde_json::to_string_pretty(&sunrise).unwrap();
+    assert!(json.len() <= light::SUNRISE_MAX_BODY, "{json}");
+    assert_eq!(body::parse(json.as_bytes()), Ok(sunrise));
+}
+
+#[test]
+fn fan_rpm() {
+    assert_eq!(fan::rpm(0), 0);
+    assert_eq!(fan::rpm(50), 1500);
+
+    assert!(!fan::rpm_changed(1500, 1509));
+    assert!(fan::rpm_changed(1500, 1510));
+    assert!(fan::rpm_changed(1510, 1500));
+    assert!(fan::rpm_changed(i16::MIN, i16::MAX));
+}
+
+/// The state changes of a fresh trigger fed `values` against `threshold`.
+fn alarm_edges(direction: Direction, threshold: f32, values: &[f32]) -> Vec<(usize, bool)> {
+    let mut trigger = Trigger::new(direction, 0.5);
+    values
+        .iter()
+        .enumerate()
+        .filter_map(|(i, value)| Some((i, trigger.update(threshold, *value)?)))
+        .collect()
+}
+
+#[test]
+fn alarm_above() {
+    // Noise around the threshold raises the alarm once, and it only clears
+    // below the hysteresis band.
+    let values = [29.0, 30.0, 30.1, 29.9, 30.2, 29.6, 29.5, 29.4, 29.0, 30.5];
+    assert_eq!(
+        alarm_edges(Direction::Above, 30.0, &values),
+        [(2, true), (7, false), (9, true)]
+    );
+}
+
+#[test]
+fn alarm_below() {
+    let values = [5.0, 4.9, 5.2, 5.5, 5.6, 4.0];
+    assert_eq!(
+        alarm_edges(Direction::Below, 5.0, &values),
+        [(1, true), (4, false), (5, true)]
+    );
+}
+
+#[test]
+fn alarm_ignores_nan() {
+    let mut trigger = Trigger::new(Direction::Above, 0.5);
+    assert_eq!(trigger.update(30.0, f32::NAN), None);
+    assert_eq!(trigger.update(30.0, 31.0), Some(true));
+    assert_eq!(trigger.update(30.0, f32::NAN), None);
+    assert!(trigger.is_active());
+}
+
+#[test]
+fn smartled_buffer() {
+    // The `smart_led_buffer!(1)` length the light was written against.
+    assert_eq!(rmt_buffer_len(1, ColorOrder::Grb), 25);
+    assert_eq!(rmt_buffer_len(8, ColorOrder::Rgb), 193);
+    assert_eq!(rmt_buffer_len(8, ColorOrder::Grbw), 257);
+}
+
+#[test]
+fn led_timing_symbols() {
+    for (timing, zero, one, reset) in [
+        (LedTiming::WS2812B, (32, 68), (64, 36), 4000),
+        (LedTiming::WS2811, (40, 160), (96, 104), 4000),
+        (LedTiming::SK6812, (24, 72), (48, 48), 6400),
+    ] {
+        assert_eq!(
+            timing.symbols(80_000_000),
+            Symbols { zero, one, reset },
+            "{timing:?}"
+        );
+    }
+    // A reset too long for one pulse code is cut to the longest.
+    let slow = LedTiming {
+        reset: 1_000_000,
+        ..LedTiming::WS2811
+    };
+    assert_eq!(slow.symbols(80_000_000).reset, 0x7fff);
+}
+
+#[test]
+fn led_pulse_codes() {
+    // (level, length) of both halves of a pulse code.
+    let halves = |code: u32| {
+        (
+            (code >> 15 & 1, code & 0x7fff),
+            (code >> 31, code >> 16 & 0x7fff),
+        )
+    };
+
+    let symbols = LedTiming::WS2811.symbols(80_000_000);
+    let codes: Vec<_> = symbols.encode(&[0b1000_0001, 0]).map(halves).collect();
+    assert_eq!(codes.len(), 16);
+    assert_eq!(codes[0], ((1, 96), (0, 104)));
+    assert_eq!(codes[1], ((1, 40), (0, 160)));
+    assert_eq!(codes[7], ((1, 96), (0, 104)));
+    assert!(codes[8..].iter().all(|&c| c == ((1, 40), (0, 160))));
+    // Low for the reset, then a length of 0 ending the transmission.
+    assert_eq!(halves(symbols.latch()), ((0, 4000), (0, 0)));
+
+    // A GRB pixel starts with green.
+    let green_first: Vec<_> = symbols
+        .encode(&ColorOrder::Grb.bytes((0, 255, 0))[..3])
+        .collect();
+    assert!(green_first[..8].iter().all(|&c| c == symbols.bit(true)));
+    assert!(green_first[8..].iter().all(|&c| c == symbols.bit(false)));
+}
+
+#[test]
+fn led_timing_profile() {
+    let limits = TimingLimits::WS2812B;
+    assert!(limits.admit(&LedTiming::WS2812B));
+    assert!(!limits.admit(&LedTiming::SK6812));
+
+    for profile in [TimingProfile::Standard, TimingProfile::Conservative] {
+        assert_eq!(TimingProfile::parse(profile.as_str()), Some(profile));
+        // As sent, after rounding to the ticks of the profile's clock.
+        let sent = profile.symbols().timing(profile.clock_hz());
+        assert!(limits.admit(&sent), "{profile:?}: {sent:?}");
+    }
+    assert_eq!(TimingProfile::parse("fast"), None);
+
+    let conservative = TimingProfile::Conservative.symbols();
+    assert_eq!(
+        conservative,
+        Symbols {
+            zero: (3, 9),
+            one: (9, 4),
+            reset: 800
+        }
+    );
+    let sent = conservative.timing(TimingProfile::Conservative.clock_hz());
+    let standard = LedTiming::WS2812B;
+    assert!(sent.t0h < standard.t0h && sent.t1h > standard.t1h);
+    assert!(sent.t1h - sent.t0h > standard.t1h - standard.t0h);
+    assert!(sent.reset > standard.reset);
+
+    // Re-encoded, the bits change and the channel divides the 80 MHz clock.
+    assert_ne!(
+        conservative.bit(true),
+        TimingProfile::Standard.symbols().bit(true)
+    );
+    for (profile, divider) in [
+        (TimingProfile::Standard, 1),
+        (TimingProfile::Conservative, 8),
+    ] {
+        assert_eq!(clock_divider(80_000_000, profile.clock_hz()), Some(divider));
+    }
+    assert_eq!(clock_divider(80_000_000, 3_000_000), None);
+    assert_eq!(clock_divider(80_000_000, 100_000), None);
+    assert_eq!(clock_divider(80_000_000, 0), None);
+}
+
+#[test]
+fn led_color_order() {
+    let orange = (255, 120, 40);
+    assert_eq!(ColorOrder::Grb.bytes(orange)[..3], [120, 255, 40]);
+    assert_eq!(ColorOrder::Rgb.bytes(orange)[..3], [255, 120, 40]);
+    assert_eq!(ColorOrder::Grbw.bytes(orange), [80, 215, 0, 40]);
+    assert_eq!(ColorOrder::Grbw.bytes((200, 200, 200)), [0, 0, 0, 200]);
+    assert_eq!(ColorOrder::Rgb.bytes_per_pixel(), 3);
+    assert_eq!(ColorOrder::Grbw.bytes_per_pixel(), 4);
+}
+
+#[test]
+fn led_current_budget() {
+    let model = CurrentModel::WS2812B;
+    let white = [(255, 255, 255); 144];
+    assert_eq!(model.estimate_ma(&white), 8784);
+    assert_eq!(model.estimate_ma(&[(0, 0, 0); 144]), 144);
+
+    for (budget, expected) in [
+        (0, 8784),
+        (10_000, 8784),
+        (2000, 1974),
+        (500, 483),
+        (100, 144),
+    ] {
+        let mut frame = white;
+        limit_current(&mut frame, budget, &model);
+        assert_eq!(model.estimate_ma(&frame), expected, "{budget} mA");
+        // Dimmed uniformly.
+        assert!(frame.iter().all(|&pixel| pixel == frame[0]), "{budget} mA");
+    }
+
+    // Colors keep their proportions.
+    let mut frame = [(200, 100, 0)];
+    limit_current(&mut frame, 10, &model);
+    assert_eq!(frame, [(76, 38, 0)]);
+}
+
+#[test]
+fn sunrise_brightness() {
+    assert_eq!(sunrise(0, 200).1, 0);
+    assert_eq!(sunrise(PROGRESS_END, 200).1, 200);
+    assert_eq!(sunrise(PROGRESS_END + 1, 200), sunrise(PROGRESS_END, 200));
+
+    let mut last = 0;
+    for progress in 0..=PROGRESS_END {
+        let (_, brightness) = sunrise(progress, 255);
+        assert!(brightness >= last, "brightness drops at {progress}");
+        last = brightness;
+    }
+}
+
+#[test]
+fn sunrise_hue() {
+    // Deep red, through orange, to warm white.
+    assert_eq!(sunrise(0, 255).0, (255, 0, 0));
+    let (r, g, b) = sunrise(500, 255).0;
+    assert!(r == 255 && g > 80 && g < 160 && b < 40);
+    let (r, g, b) = sunrise(PROGRESS_END, 255).0;
+    assert!(r == 255 && g > 200 && b > 150);
+
+    // Green and blue only ever rise: the hue moves from red towards white.
+    let mut last = sunrise(0, 255).0;
+    for progress in 1..=PROGRESS_END {
+        let (color, _) = sunrise(progress, 255);
+        assert!(
+            color.1 >= last.1 && color.2 >= last.2,
+            "hue goes back at {progress}"
+        );
+        last = color;
+    }
+}
+
+#[test]
+fn group_properties() {
+    let mut td = serde_json::json!({
+        "properties": {
+            "on": {
+                "@type": "OnOffProperty",
+                "type": "boolean",
+                "forms": [{ "href": "/properties/on" }],
+            },
+        },
+    });
+    let meta = TdMetadata {
+        version: "1",
+        support: None,
+        links: &[],
+        group: &[
+            GroupProperty {
+                name: "groupOn",
+                property: "on",
+            },
+            GroupProperty {
+                name: "groupLevel",
+                property: "level",
+            },
+        ],
+    };
+    merge_metadata(td.as_object_mut().unwrap(), &meta);
+
+    let group = &td["properties"]["groupOn"];
+    assert_eq!(group["type"], "boolean");
+    assert_eq!(group["writeOnly"], true);
+    assert!(group.get("@type").is_none());
+    assert_eq!(group["forms"][0]["href"], "/properties/groupOn");
+    assert_eq!(group["forms"][0]["op"][0], "writeproperty");
+    // Properties the Thing does not have are skipped.
+    assert!(td["properties"].get("groupLevel").is_none());
+    // Every Thing gets the self-test, and the shutdown with its feature.
+    assert_eq!(
+        td["actions"]["selfTest"]["forms"][0]["href"],
+        "/actions/selfTest"
+    );
+    assert_eq!(
+        td["actions"].get("shutdown").is_some(),
+        cfg!(feature = "shutdown")
+    );
+    // And the transmit power.
+    assert_eq!(td["properties"]["txPower"]["maximum"], 20);
+    assert_eq!(
+        td["properties"]["txPower"]["forms"][0]["op"][1],
+        "writeproperty"
+    );
+    // And the configuration.
+    assert_eq!(td["properties"]["configuration"]["readOnly"], true);
+}
+
+#[test]
+#[cfg(not(feature = "bare-events"))]
+fn event_envelope() {
+    let mut td = serde_json::json!({
+        "events": {
+            "temperature": {
+                "data": { "type": "number", "unit": "Celsius" },
+                "forms": [{ "href": "/events/temperature" }],
+            },
+        },
+    });
+    let meta = TdMetadata {
+        version: "1",
+        support: None,
+        links: &[],
+        group: &[],
+    };
+    merge_metadata(td.as_object_mut().unwrap(), &meta);
+
+    let data = &td["events"]["temperature"]["data"];
+    assert_eq!(data["type"], "object");
+    assert_eq!(data["required"], serde_json::json!(["data"]));
+    assert_eq!(
+        data["properties"]["data"],
+        serde_json::json!({ "type": "number", "unit": "Celsius" })
+    );
+    // The common events too.
+    let data = &td["events"]["connectivity"]["data"];
+    assert_eq!(data["properties"]["data"]["required"][0], "state");
+}
+
+#[test]
+fn batch_operations() {
+    use serde_json::json;
+
+    let ops = batch::parse(json!([
+        {"op": "read", "name": "temperature"},
+        {"op": "write", "name": "on", "value": true},
+        {"op": "write", "name": "on"},
+        {"op": "delete", "name": "on"},
+        {"op": "read"},
+        42,
+    ]))
+    .unwrap();
+    assert_eq!(
+        ops,
+        [
+            Ok(Op {
+                name: "temperature".into(),
+                write: None
+            }),
+            Ok(Op {
+                name: "on".into(),
+                write: Some(json!(true))
+            }),
+            Err("write must have a value"),
+            Err("op must be read or write"),
+            Err("operation must have a name"),
+            Err("operation must be an object"),
+        ]
+    );
+
+    assert!(batch::parse(json!({"op": "read", "name": "on"})).is_err());
+    let read = json!({"op": "read", "name": "on"});
+    assert!(batch::parse(json!(vec![read.clone(); MAX_OPS])).is_ok());
+    assert!(batch::parse(json!(vec![read; MAX_OPS + 1])).is_err());
+}
+
+#[test]
+fn batch_response() {
+    use serde_json::json;
+
+    let outcomes = [
+        batch::Outcome::read(json!(21.5)),
+        batch::Outcome::written(),
+        batch::Outcome::unknown(),
+        batch::Outcome::invalid(&"Level above 100"),
+        batch::Outcome::read_only(),
+    ];
+    let full = batch::respond(&outcomes, 2048);
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&full).unwrap(),
+        json!([
+            {"status": 200, "value": 21.5},
+            {"status": 204},
+            {"status": 404, "error": "no such property"},
+            {"status": 400, "error": "Level above 100"},
+            {"status": 405, "error": "property is read-only"},
+        ])
+    );
+
+    // A value too large for the buffer keeps its status, and so do the next.
+    let outcomes = [
+        batch::Outcome::read(json!("x".repeat(300))),
+        batch::Outcome::read(json!(1)),
+        batch::Outcome::unknown(),
+    ];
+    let truncated = batch::respond(&outcomes, 256);
+    assert!(truncated.len() <= 256);
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&truncated).unwrap(),
+        json!([
+            {"status": 200, "truncated": true},
+            {"status": 200, "value": 1},
+            {"status": 404, "error": "no such property"},
+        ])
+    );
+
+    // The worst case still fits: every outcome truncated.
+    let outcomes = vec![batch::Outcome::read(json!("x".repeat(100))); MAX_OPS];
+    let all = batch::respond(&outcomes, 8 * 32 + 1);
+    assert!(all.len() <= 8 * 32 + 1, "{all}");
+    assert!(!all.contains("xxx"));
+}
+
+#[test]
+fn affordance_routes() {
+    let td = serde_json::json!({
+        "properties": {
+            "on": {
+                "forms": [
+                    { "href": "/properties/on", "op": ["readproperty", "writeproperty"] },
+                    { "href": "/events/on", "op": ["observeproperty", "unobserveproperty"] },
+                ],
+            },
+            // Absolute, as instantiated from a Thing Model.
+            "level": {
+                "forms": [{ "href": "http://192.0.2.1/properties/level", "op": "readproperty" }],
+            },
+        },
+        "actions": {
+            "fade": {
+                "forms": [{ "href": "/actions/fade", "op": ["invokeaction", "queryaction"] }],
+            },
+        },
+        "events": {
+            "on": {
+                "forms": [{ "href": "/events/on", "op": ["subscribeevent", "unsubscribeevent"] }],
+            },
+        },
+    });
+
+    let routes: Vec<_> = routes(&td)
+        .iter()
+        .map(|(method, path)| format!("{method} {path}"))
+        .collect();
+    assert_eq!(
+        routes,
+        [
+            "GET /",
+            "GET /properties/level",
+            "GET /properties/on",
+            "PUT /properties/on",
+            "GET /events/on",
+            "POST /actions/fade",
+            "GET /actions/fade",
+        ]
+    );
+
+    assert_eq!(affordance_stats::path("http://thing.local"), "/");
+    assert_eq!(
+        affordance_stats::path("/properties/color{?field}"),
+        "/properties/color"
+    );
+    assert_eq!(affordance_stats::method("readallproperties"), None);
+}
+
+#[test]
+fn property_field() {
+    assert_eq!(from_query("field=r"), Some("r"));
+    assert_eq!(from_query("x=1&field=g&field=b"), Some("g"));
+    assert_eq!(from_query("fields=r"), None);
+    assert_eq!(from_query(""), None);
+
+    let color = serde_json::json!({ "r": 128, "g": 0, "b": 255 });
+    assert_eq!(project(color.clone(), "r"), Some(128.into()));
+    assert_eq!(project(color, "x"), None);
+    assert_eq!(project(true.into(), "r"), None);
+    assert_eq!(
+        field::not_found("x").to_string(),
+        r#"{"error":"no such member","field":"x"}"#
+    );
+
+    let td = serde_json::json!({
+        "properties": {
+            "color": {
+                "type": "object",
+                "properties": { "r": {}, "g": {}, "b": {} },
+                "forms": [{ "href": "/properties/color", "op": ["readproperty", "writeproperty"] }],
+            },
+            "on": { "type": "boolean", "forms": [{ "href": "/properties/on" }] },
+            "pins": { "type": "object", "forms": [{ "href": "/properties/pins" }] },
+        },
+    });
+    let td: serde_json::Value =
+        serde_json::from_str(&advertise(&td.to_string(), &["color", "on", "pins"]).unwrap())
+            .unwrap();
+
+    let color = &td["properties"]["color"];
+    assert_eq!(
+        color["uriVariables"]["field"]["enum"]
+            .as_array()
+            .unwrap()
+            .len(),
+        3
+    );
+    assert_eq!(color["forms"][0]["href"], "/properties/color");
+    assert_eq!(color["forms"][1]["href"], "/properties/color{?field}");
+    assert_eq!(color["forms"][1]["op"][0], "readproperty");
+    // No members to pick from.
+    assert!(td["properties"]["on"].get("uriVariables").is_none());
+    assert!(td["properties"]["pins"].get("uriVariables").is_none());
+}
+
+#[test]
+fn configuration_json() {
+    let configuration = Configuration {
+        name: "light",
+        version: "0.1.0",
+        board: Some("c3-devkit"),
+        pins: &[("led", 8), ("button", 9)],
+        features: &[("ui", true), ("syslog", false), ("group", true)],
+        heap_size: 200 * 1024,
+        web_tasks: 4,
+        sockets: 11,
+        tcp_buffers: (1024, 1024),
+        http_buffer: 2048,
+        sample_interval_ms: None,
+    };
+    assert_eq!(
+        configuration.to_json(),
+        serde_json::json!({
+            "name": "light",
+            "version": "0.1.0",
+            "board": "c3-devkit",
+            "pins": { "led": 8, "button": 9 },
+            "features": ["ui", "group"],
+            "heapSize": 204_800,
+            "webTasks": 4,
+            "sockets": 11,
+            "tcpRxBuffer": 1024,
+            "tcpTxBuffer": 1024,
+            "httpBuffer": 2048,
+            "sampleInterval": null,
+        })
+    );
+}
+
+#[test]
+fn td_public_base() {
+    let mut td = serde_json::json!({
+        "base": "http://192.168.1.5",
+        "properties": {
+            "on": { "forms": [{ "href": "/properties/on" }] },
+            // A property named `href` is not a link.
+            "href": { "type": "object", "properties": { "href": { "type": "string" } } },
+        },
+        "links": [
+            { "href": "http://192.168.1.5/.well-known/core" },
+            { "href": "http://192.168.1.50/elsewhere" },
+            { "href": "https://example.com/model.tm.json" },
+        ],
+    });
+    rebase(
+        td.as_object_mut().unwrap(),
+        "https://things.example.com/light1/",
+    );
+
+    assert_eq!(td["base"], "https://things.example.com/light1/");
+    assert_eq!(
+        td["properties"]["on"]["forms"][0]["href"],
+        "https://things.example.com/light1/properties/on"
+    );
+    assert_eq!(
+        td["properties"]["href"]["properties"]["href"],
+        serde_json::json!({ "type": "string" })
+    );
+    assert_eq!(
+        td["links"][0]["href"],
+        "https://things.example.com/light1/.well-known/core"
+    );
+    assert_eq!(td["links"][1]["href"], "http://192.168.1.50/elsewhere");
+    assert_eq!(td["links"][2]["href"], "https://example.com/model.tm.json");
+
+    // Rebasing again, per request, moves the now absolute hrefs along.
+    let td = rebase_json(&td.to_string(), "http://proxy.lan:8080").unwrap();
+    let td: serde_json::Value = serde_json::from_str(&td).unwrap();
+    assert_eq!(td["base"], "http://proxy.lan:8080");
+    assert_eq!(
+        td["properties"]["on"]["forms"][0]["href"],
+        "http://proxy.lan:8080/properties/on"
+    );
+
+    assert!(rebase_json("[]", "http://proxy.lan").is_err());
+}
+
+#[test]
+fn td_forwarded_base() {
+    assert_eq!(forwarded_base(None, Some("https")), None);
+    assert_eq!(
+        forwarded_base(Some("things.example.com"), None).as_deref(),
+        Some("http://things.example.com")
+    );
+    assert_eq!(
+        forwarded_base(Some("things.example.com, 10.0.0.1"), Some("HTTPS, http")).as_deref(),
+        Some("https://things.example.com")
+    );
+    assert_eq!(
+        forwarded_base(Some("[fd00::1]:8443"), Some("https")).as_deref(),
+        Some("https://[fd00::1]:8443")
+    );
+    assert_eq!(forwarded_base(Some(""), None), None);
+    assert_eq!(forwarded_base(Some("evil.com/\"x"), None), None);
+    assert_eq!(
+        forwarded_base(Some("things.example.com"), Some("ftp")),
+        None
+    );
+}
+
+fn rule(comparison: Comparison, payload: &str, cooldown_s: u32) -> Rule {
+    Rule {
+        source: "temperature".into(),
+        comparison,
+        threshold: 28.0,
+        target: "http://192.0.2.7/properties/on".into(),
+        payload: payload.into(),
+        cooldown_s,
+    }
+}
+
+#[test]
+fn rule_fires_once_per_crossing() {
+    let rule = rule(Comparison::Above, "true", 60);
+    let mut state = RuleState::new();
+
+    assert!(!state.update(&rule, 27.0, 0));
+    assert!(state.update(&rule, 28.5, 1_000));
+    assert_eq!(state.last_fired_ms(), Some(1_000));
+    // Staying above writes the target once.
+    assert!(!state.update(&rule, 29.0, 120_000));
+    assert!(!state.update(&rule, f32::NAN, 130_000));
+
+    // Back below, then above within the cooldown: fires once it is over.
+    assert!(!state.update(&rule, 27.0, 10_000));
+    assert!(!state.update(&rule, 28.5, 20_000));
+    assert!(state.update(&rule, 28.5, 61_000));
+    assert_eq!(state.last_fired_ms(), Some(61_000));
+
+    // Already beyond at boot.
+    let mut state = RuleState::new();
+    assert!(state.update(&rule, 30.0, 0));
+}
+
+#[test]
+fn rule_comparisons() {
+    assert!(Comparison::AtLeast.holds(28.0, 28.0));
+    assert!(!Comparison::Above.holds(28.0, 28.0));
+    assert!(Comparison::Below.holds(1.0, 28.0));
+    assert!(Comparison::AtMost.holds(28.0, 28.0));
+    assert!(Comparison::Equal.holds(1.0, 1.0));
+
+    let rule: Rule = serde_json::from_str(
+        r#"{"source":"temperature","comparison":"<=","threshold":18,
+            "target":"http://192.0.2.7/properties/level","payload":"{\"level\":{value}}"}"#,
+    )
+    .unwrap();
+    assert_eq!(rule.comparison, Comparison::AtMost);
+    assert_eq!(rule.cooldown_s, 60);
+    assert_eq!(rule.render(17.5), r#"{"level":17.5}"#);
+    assert!(serde_json::from_str::<Rule>(r#"{"source":"temperature","comparison":"!="}"#).is_err());
+}
+
+#[test]
+fn rule_checks() {
+    let sources = ["temperature", "humidity"];
+    assert_eq!(
+        rule(Comparison::Above, "true", 60).validate(&sources),
+        Ok(())
+    );
+    assert_eq!(
+        rule(Comparison::Above, "{value}", 0).validate(&sources),
+        Ok(())
+    );
+    assert_eq!(
+        rule(Comparison::Above, "true", 60).validate(&["humidity"]),
+        Err("Unknown source")
+    );
+    assert_eq!(
+        rule(Comparison::Above, "{\"on\":", 60).validate(&sources),
+        Err("Payload must be JSON, with {value} for the value")
+    );
+    assert_eq!(
+        rule(Comparison::Above, "true", 86_401).validate(&sources),
+        Err("Cooldown must be at most 86400 s")
+    );
+
+    let mut bad = rule(Comparison::Above, "true", 60);
+    bad.threshold = f32::INFINITY;
+    assert_eq!(
+        bad.validate(&sources),
+        Err("Threshold must be a finite number")
+    );
+    bad.threshold = 28.0;
+    bad.target = "https://192.0.2.7/properties/on".into();
+    assert_eq!(bad.validate(&sources), Err("Target must be an http:// URL"));
+}
+
+#[test]
+fn accept_prefers() {
+    let cbor = |header| prefers(header, "application/cbor", "application/json");
+    assert!(cbor("application/cbor"));
+    assert!(cbor("application/json;q=0.5, application/cbor"));
+    assert!(cbor("application/cbor, */*"));
+    assert!(cbor("application/*;q=0.2, application/cbor;q=0.9"));
+
+    assert!(!cbor(""));
+    assert!(!cbor("*/*"));
+    assert!(!cbor("application/json"));
+    assert!(!cbor("application/cbor;q=0.5, application/json"));
+    assert!(!cbor("application/cbor;q=0, */*"));
+    // Equal weights from equally specific ranges: JSON stays the default.
+    assert!(!cbor("application/cbor, application/json"));
+}
+
+/// `value` through CBOR and back, as JSON.
+fn cbor_round_trip<T: serde::Serialize>(value: &T) -> serde_json::Value {
+    let body = cbor::to_vec(value).unwrap();
+    ciborium::from_reader(body.as_slice()).unwrap()
+}
+
+#[test]
+fn cbor_matches_json() {
+    assert_eq!(cbor::to_vec(&true).unwrap(), [0xf5]);
+    assert_eq!(
+        cbor::to_vec(&serde_json::json!({ "r": 128 })).unwrap(),
+        [0xa1, 0x61, b'r', 0x18, 0x80]
+    );
+    // 21.5 is exact in a half float.
+    assert_eq!(cbor::to_vec(&21.5_f32).unwrap(), [0xf9, 0x4d, 0x60]);
+
+    let vacation = light::VacationSchedule::default();
+    let values = [
+        serde_json::to_value(true).unwrap(),
+        serde_json::to_value(21.4_f32).unwrap(),
+        serde_json::to_value(-3_i16).unwrap(),
+        serde_json::to_value(200_u8).unwrap(),
+        serde_json::to_value("continuous").unwrap(),
+        serde_json::to_value(Some(81_234_u64)).unwrap(),
+        serde_json::to_value(None::<u64>).unwrap(),
+        serde_json::to_value(vacation).unwrap(),
+        sensor_info(Some(0x887c)),
+        serde_json::json!({ "r": 128, "g": 0, "b": 255 }),
+        serde_json::json!({ "ip": "192.0.2.7", "prefix": 24, "dns": ["192.0.2.1"] }),
+    ];
+    for value in values {
+        assert_eq!(cbor_round_trip(&value), value);
+    }
+
+    // Straight from the types, not through a JSON value.
+    assert_eq!(cbor_round_trip(&21.4_f32), serde_json::json!(21.4_f32));
+    assert_eq!(
+        cbor_round_trip(&vacation),
+        serde_json::to_value(vacation).unwrap()
+    );
+}
+
+#[test]
+fn cbor_forms() {
+    let td = serde_json::json!({
+        "properties": {
+            "on": {
+                "forms": [{
+                    "href": "/properties/on",
+                    "op": ["readproperty", "writeproperty"],
+                    "contentType": "application/json",
+                }],
+            },
+            "temperature": {
+                "forms": [
+                    { "href": "/events/temperature", "op": ["observeproperty"], "contentType": "text/event-stream" },
+                    { "href": "/properties/temperature", "op": "readproperty" },
+                ],
+            },
+            "groupOn": {
+                "forms": [{ "href": "/properties/groupOn", "op": "writeproperty" }],
+            },
+        },
+    });
+
+    let td = cbor::advertise(&td.to_string()).unwrap();
+    let td: serde_json::Value = serde_json::from_str(&td).unwrap();
+    let cbor_form = |href: &str| {
+        serde_json::json!({
+            "href": href,
+            "op": "readproperty",
+            "contentType": "application/cbor",
+        })
+    };
+    assert_eq!(
+        td["properties"]["on"]["forms"][1],
+        cbor_form("/properties/on")
+    );
+    assert_eq!(
+        td["properties"]["temperature"]["forms"][2],
+        cbor_form("/properties/temperature")
+    );
+    assert_eq!(
+        td["properties"]["groupOn"]["forms"]
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+
+    assert!(cbor::advertise("[]").is_err());
+}
+
+#[test]
+fn flash_wear() {
+    const HOUR_MS: u64 = 60 * 60 * 1000;
+
+    let mut wear = KeyWear::new();
+    for _ in 0..12 {
+        wear.record(8, 10 * 60 * 1000);
+    }
+    assert_eq!((wear.writes, wear.bytes), (12, 96));
+    // Within the first hour, the rate is over that hour.
+    assert!((wear.rate(HOUR_MS / 2) - 12.0).abs() < 1e-6);
+    assert!(wear.is_worn(HOUR_MS / 2, 6));
+    assert!(!wear.is_worn(HOUR_MS / 2, 12));
+
+    // Averaged over the uptime, then over the last day.
+    assert!((wear.rate(3 * HOUR_MS + 1) - 3.0).abs() < 1e-6);
+    assert!((wear.rate(23 * HOUR_MS) - 0.5).abs() < 1e-6);
+    assert!(!wear.is_worn(23 * HOUR_MS, 6));
+
+    // The burst leaves the window a day later, the totals stay.
+    wear.record(8, 30 * HOUR_MS);
+    assert!((wear.rate(30 * HOUR_MS) - 1.0 / 24.0).abs() < 1e-6);
+    assert_eq!(wear.writes, 13);
+    assert!(wear.rate(60 * HOUR_MS).abs() < 1e-6);
+
+    assert_eq!(cycles_per_sector(12, 4), Some(3.0));
+    assert_eq!(cycles_per_sector(12, 0), None);
+
+    assert_eq!(max_writes_per_hour(None), wear::DEFAULT_MAX_WRITES_PER_HOUR);
+    assert_eq!(max_writes_per_hour(Some("12")), 12);
+    assert_eq!(max_writes_per_hour(Some("0")), 1);
+    assert_eq!(
+        max_writes_per_hour(Some("100000")),
+        wear::MAX_WRITES_PER_HOUR
+    );
+    assert_eq!(
+        max_writes_per_hour(Some("hourly")),
+        wear::DEFAULT_MAX_WRITES_PER_HOUR
+    );
+}
+
+/// NOR flash in RAM: writes can only clear bits, erases set whole sectors.
+struct RamFlash([u8; 4 * 256]);
+
+impl ErrorType for RamFlash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for RamFlash {
+    const READ_SIZE: usize = 4;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        embedded_storage::nor_flash::check_read(self, offset, bytes.len())?;
+        bytes.copy_from_slice(&self.0[offset as usize..offset as usize + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.0.len()
+    }
+}
+
+impl NorFlash for RamFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = 256;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        embedded_storage::nor_flash::check_erase(self, from, to)?;
+        self.0[from as usize..to as usize].fill(0xff);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        embedded_storage::nor_flash::check_write(self, offset, bytes.len())?;
+        for (cell, b) in self.0[offset as usize..].iter_mut().zip(bytes) {
+            *cell &= b;
+        }
+        Ok(())
+    }
+}
+
+/// The calls of the store to its hooks.
+#[derive(Default)]
+struct Counted {
+    writes: Vec<(&'static str, usize)>,
+    erases: u32,
+}
+
+impl store::Wear for &mut Counted {
+    fn write(&mut self, key: &'static str, bytes: usize) {
+        self.writes.push((key, bytes));
+    }
+
+    fn erase(&mut self) {
+        self.erases += 1;
+    }
+}
+
+#[test]
+fn flash_store() {
+    let mut flash = RamFlash([0xff; 4 * 256]);
+    let mut wear = Counted::default();
+
+    let mut store = Store::new(&mut flash, &mut wear).unwrap();
+    assert_eq!(store.sectors(), 4);
+    assert_eq!(store.read("light").unwrap(), None);
+    store.write("light", b"{\"brightness\":180}").unwrap();
+    store.write("rules", b"[]").unwrap();
+    store.write("light", b"{\"brightness\":90}").unwrap();
+    assert_eq!(
+        store.read("light").unwrap().as_deref(),
+        Some(&b"{\"brightness\":90}"[..])
+    );
+    assert_eq!(store.write("", b"1"), Err(StoreError::TooLong));
+
+    // Each write is counted with its record, 8 bytes of header and the key
+    // included, padded to 4; the values survive a reboot.
+    assert_eq!(wear.writes, [("light", 32), ("rules", 16), ("light", 32)]);
+    assert_eq!(wear.erases, 0);
+    let mut store = Store::new(&mut flash, &mut wear).unwrap();
+    assert_eq!(store.read("rules").unwrap().as_deref(), Some(&b"[]"[..]));
+
+    // Filling the log compacts it: every sector erased once, the last value
+    // of each key kept, the moved ones not counted as writes.
+    for b in 0..64u8 {
+        store.write("color", &[b; 3]).unwrap();
+    }
+    assert_eq!(wear.erases, 4);
+    assert_eq!(wear.writes.len(), 3 + 64);
+    let mut store = Store::new(&mut flash, &mut wear).unwrap();
+    assert_eq!(store.read("color").unwrap().as_deref(), Some(&[63; 3][..]));
+    assert_eq!(
+        store.read("light").unwrap().as_deref(),
+        Some(&b"{\"brightness\":90}"[..])
+    );
+    assert_eq!(store.write("rules", &[0; 1024]), Err(StoreError::Full));
+
+    // A torn record ends the log and the next write compacts around it.
+    let end = flash.0.iter().rposition(|b| *b != 0xff).unwrap() + 1;
+    let end = end.next_multiple_of(4);
+    flash.0[end..end + 4].copy_from_slice(&[0x5a, 5, 200, 0]);
+    let erases = wear.erases;
+    let mut store = Store::new(&mut flash, &mut wear).unwrap();
+    store.write("rules", b"[1]").unwrap();
+    assert_eq!(store.read("rules").unwrap().as_deref(), Some(&b"[1]"[..]));
+    assert_eq!(store.read("color").unwrap().as_deref(), Some(&[63; 3][..]));
+    assert_eq!(wear.erases, erases + 4);
+}
+
+#[test]
+fn udp_requests() {
+    let json = |datagram: &[u8]| serde_json::from_slice::<serde_json::Value>(datagram).unwrap();
+
+    assert_eq!(
+        read_request(br#"{"op":"readproperty","name":"rpm"}"#),
+        Ok("rpm".into())
+    );
+    assert_eq!(
+        json(&read_request(br#"{"op":"writeproperty","name":"on"}"#).unwrap_err()),
+        serde_json::json!({ "error": "unknown op", "op": "writeproperty" })
+    );
+    assert_eq!(
+        json(&read_request(b"rpm").unwrap_err()),
+        serde_json::json!({ "error": "invalid request" })
+    );
+    assert_eq!(
+        json(&read_request(&[b' '; udp::MAX_REQUEST + 1]).unwrap_err()),
+        serde_json::json!({ "error": "request too large" })
+    );
+
+    assert_eq!(
+        json(&read_reply("rpm", Some(Ok(serde_json::json!(1180))))),
+        serde_json::json!({ "op": "readproperty", "name": "rpm", "value": 1180 })
+    );
+    assert_eq!(
+        json(&read_reply(
+            "temperature",
+            Some(Err("Failed to read temperature"))
+        )),
+        serde_json::json!({ "error": "Failed to read temperature", "name": "temperature" })
+    );
+    assert_eq!(
+        json(&read_reply("color", None)),
+        serde_json::json!({ "error": "unknown property", "name": "color" })
+    );
+
+    // Never fragmented: a long array is cut short, a long string refused.
+    let items: Vec<u32> = (0..1000).collect();
+    let reply = read_reply("log", Some(Ok(serde_json::json!(items))));
+    assert!(reply.len() <= udp::MAX_DATAGRAM);
+    let reply = json(&reply);
+    assert_eq!(reply["truncated"], true);
+    let kept = reply["value"].as_array().unwrap().len();
+    assert!(kept > 0);
+    assert_eq!(reply["value"], serde_json::json!(items[..kept]));
+    assert_eq!(
+        json(&read_reply("log", Some(Ok("x".repeat(2000).into())))),
+        serde_json::json!({ "error": "value too large", "name": "log" })
+    );
+}
+
+#[test]
+fn udp_forms() {
+    let td = serde_json::json!({
+        "base": "http://192.0.2.7/",
+        "properties": {
+            "rpm": { "forms": [{ "href": "/properties/rpm" }] },
+            "speed": { "forms": [{ "href": "/properties/speed" }] },
+        },
+    });
+
+    let td = udp::advertise(&td.to_string(), &["rpm"], 5684).unwrap();
+    let td: serde_json::Value = serde_json::from_str(&td).unwrap();
+    assert_eq!(
+        td["properties"]["rpm"]["forms"][1],
+        serde_json::json!({
+            "href": "udp://192.0.2.7:5684",
+            "op": "readproperty",
+            "contentType": "application/json",
+            "subprotocol": "udp-json",
+        })
+    );
+    assert_eq!(
+        td["properties"]["speed"]["forms"].as_array().unwrap().len(),
+        1
+    );
+}
+
+#[test]
+fn entropy_checks() {
+    // A xorshift stream passes, like the hardware generator should.
+    let mut x: u32 = 0x2545_f491;
+    let sample: [u8; SAMPLE_LEN] = core::array::from_fn(|_| {
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        x.to_le_bytes()[0]
+    });
+    assert!(check(&sample).passed);
+
+    // Stuck, alternating and repeating generators fail.
+    let stuck = check(&[0; SAMPLE_LEN]);
+    assert_eq!((stuck.passed, stuck.ones, stuck.runs), (false, 0, 1));
+    assert_eq!(stuck.longest_run, 2048);
+    let alternating = check(&[0x55; SAMPLE_LEN]);
+    assert_eq!((alternating.passed, alternating.ones), (false, 1024));
+    assert_eq!(alternating.runs, 2048);
+    let repeating: [u8; SAMPLE_LEN] = core::array::from_fn(|i| [0xff, 0x00, 0x0f, 0xf0][i % 4]);
+    let repeating = check(&repeating);
+    assert_eq!((repeating.ones, repeating.longest_run), (1024, 12));
+    assert!(!repeating.passed);
+
+    let mac = [0x30, 0xae, 0xa4, 0x01, 0x02, 0x03];
+    let seed = fallback_seed(&[7; 16], mac, 1000);
+    assert_eq!(seed, fallback_seed(&[7; 16], mac, 1000));
+    assert_ne!(seed, fallback_seed(&[7; 16], mac, 1001));
+    assert_ne!(seed, fallback_seed(&[8; 16], mac, 1000));
+    let other = [0x30, 0xae, 0xa4, 0x01, 0x02, 0x04];
+    assert_ne!(seed, fallback_seed(&[7; 16], other, 1000));
+}
+
+#[test]
+fn td_integrity() {
+    // The "abc" vector of FIPS 180-2.
+    let digest = integrity::sha256("abc");
+    assert_eq!(
+        integrity::hex(&digest),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(integrity::txt(&digest), "ba7816bf8f01cfea");
+    assert_eq!(integrity::txt(&digest).len(), integrity::TXT_DIGITS);
+    assert_ne!(
+        integrity::sha256(r#"{"title":"a"}"#),
+        integrity::sha256(r#"{"title":"b"}"#)
+    );
+}
+
+/// The headers of the TD, which the boards and the simulator both send.
+#[test]
+fn td_headers() {
+    let digest = integrity::sha256("abc");
+    let etag = integrity::etag(&digest, false);
+    assert_eq!(etag, "\"ba7816bf8f01cfea\"");
+    assert_eq!(integrity::etag(&digest, true), "\"ba7816bf8f01cfea-gz\"");
+
+    assert_eq!(
+        td::headers(&etag, false),
+        [
+            ("Content-Type", "application/td+json"),
+            ("Vary", "Accept-Encoding"),
+            ("Cache-Control", "no-cache"),
+            ("ETag", "\"ba7816bf8f01cfea\""),
+        ]
+    );
+    assert_eq!(
+        td::headers("\"x-gz\"", true),
+        [
+            ("Content-Type", "application/td+json"),
+            ("Content-Encoding", "gzip"),
+            ("Vary", "Accept-Encoding"),
+            ("Cache-Control", "no-cache"),
+            ("ETag", "\"x-gz\""),
+        ]
+    );
+    assert!(etag_matches(&etag, &etag));
+}
+
+#[test]
+fn cached_freshness() {
+    // Three one-second measurement intervals.
+    let max_age_ms = 3_000;
+    assert_eq!(Freshness::of(0, max_age_ms), Freshness::Fresh { age_s: 0 });
+    assert_eq!(
+        Freshness::of(1_999, max_age_ms),
+        Freshness::Fresh { age_s: 1 }
+    );
+    assert_eq!(
+        Freshness::of(3_000, max_age_ms),
+        Freshness::Fresh { age_s: 3 }
+    );
+    assert_eq!(
+        Freshness::of(3_001, max_age_ms),
+        Freshness::Stale {
+            age_s: 3,
+            retry_after_s: 3
+        }
+    );
+    assert_eq!(
+        Freshness::of(600_000, max_age_ms),
+        Freshness::Stale {
+            age_s: 600,
+            retry_after_s: 3
+        }
+    );
+
+    // Retry-After is never 0, which would invite a retry storm.
+    assert_eq!(
+        Freshness::of(200, 100),
+        Freshness::Stale {
+            age_s: 0,
+            retry_after_s: 1
+        }
+    );
+    assert_eq!(
+        Freshness::of(5_000, 2_500),
+        Freshness::Stale {
+            age_s: 5,
+            retry_after_s: 3
+        }
+    );
+}
+
+#[test]
+fn origin_matching() {
+    use origin::matches;
+
+    // Any port unless the entry has one, the origin's defaulting to its scheme's.
+    assert!(matches("http://light.local", "light.local"));
+    assert!(matches("http://light.local:8080", "light.local"));
+    assert!(matches("http://LIGHT.local.", "light.local"));
+    assert!(matches("http://192.0.2.7:8080", "192.0.2.7:8080"));
+    assert!(!matches("http://192.0.2.7", "192.0.2.7:8080"));
+    assert!(matches("http://192.0.2.7", "192.0.2.7:80"));
+    assert!(matches(
+        "https://things.example.com",
+        "things.example.com:443"
+    ));
+    assert!(!matches(
+        "http://things.example.com",
+        "things.example.com:443"
+    ));
+
+    // IPv6 literals compare as addresses, bracketed or not in the entry.
+    assert!(matches("http://[fe80::1]", "[fe80::1]"));
+    assert!(matches("http://[FE80:0:0::1]:8081", "fe80::1"));
+    assert!(matches("http://[fe80::1]:8081", "[fe80::1]:8081"));
+    assert!(!matches("http://[fe80::1]", "[fe80::1]:8081"));
+    assert!(!matches("http://[fe80::2]", "[fe80::1]"));
+
+    // A Referer has a path, maybe credentials; the host must match whole.
+    assert!(matches("http://192.0.2.7/ui?tab=1", "192.0.2.7"));
+    assert!(matches("http://user@192.0.2.7/", "192.0.2.7"));
+    assert!(!matches("http://192.0.2.7.evil.example/", "192.0.2.7"));
+    assert!(!matches("http://evil.example/192.0.2.7", "192.0.2.7"));
+
+    // Opaque origins and other schemes match nothing.
+    assert!(!matches("null", "192.0.2.7"));
+    assert!(!matches("file:///ui.html", "192.0.2.7"));
+    assert!(!matches("http://192.0.2.7:http", "192.0.2.7"));
+
+    assert_eq!(
+        origin::origin("http://[::1]:8080/x"),
+        Some(("[::1]".into(), 8080))
+    );
+    assert!(origin::entry("*").is_ok());
+    assert!(origin::entry("[fe80::1]:8081").is_ok());
+    assert!(origin::entry("light.local:port").is_err());
+}
+
+#[test]
+fn origin_checks() {
+    let policy = Policy {
+        own: &["192.0.2.7", "light-a1b2c3.local"],
+        allowed: &["gateway.lan:8080"],
+        token: Some("0123456789abcdef"),
+    };
+    let from = |origin| Headers {
+        origin: Some(origin),
+        ..Headers::default()
+    };
+
+    // No Origin nor Referer: curl or a native consumer.
+    assert_eq!(origin::check(&policy, &Headers::default()), Ok(()));
+    assert_eq!(origin::check(&policy, &from("http://192.0.2.7")), Ok(()));
+    assert_eq!(
+        origin::check(&policy, &from("http://light-a1b2c3.local")),
+        Ok(())
+    );
+    assert_eq!(
+        origin::check(&policy, &from("http://gateway.lan:8080")),
+        Ok(())
+    );
+    assert_eq!(
+        origin::check(&policy, &from("http://gateway.lan")),
+        Err("http://gateway.lan")
+    );
+    assert_eq!(origin::check(&policy, &from("null")), Err("null"));
+
+    // Referer stands in for a missing Origin.
+    let referer = Headers {
+        referer: Some("http://evil.example/page"),
+        ..Headers::default()
+    };
+    assert_eq!(
+        origin::check(&policy, &referer),
+        Err("http://evil.example/page")
+    );
+
+    // The token lets any page through, a wrong one does not.
+    let with_token = |authorization| Headers {
+        authorization: Some(authorization),
+        ..from("http://evil.example")
+    };
+    assert_eq!(
+        origin::check(&policy, &with_token("Bearer 0123456789abcdef")),
+        Ok(())
+    );
+    assert_eq!(
+        origin::check(&policy, &with_token("bearer 0123456789abcdef")),
+        Ok(())
+    );
+    assert!(origin::check(&policy, &with_token("Bearer 0123456789abcdee")).is_err());
+    assert!(origin::check(&policy, &with_token("Basic 0123456789abcdef")).is_err());
+    let no_token = Policy {
+        token: None,
+        ..policy
+    };
+    assert!(origin::check(&no_token, &with_token("Bearer ")).is_err());
+    assert!(origin::bearer(
+        Some("Bearer 0123456789abcdef"),
+        policy.token
+    ));
+    assert!(!origin::bearer(None, policy.token));
+    assert!(!origin::bearer(Some("Bearer "), None));
+
+    // The 401 of a route open to the token holder only, as /debug/loglevel.
+    assert_eq!(
+        origin::unauthorized(Some("Bearer 0123456789abcdef"), policy.token),
+        None
+    );
+    assert_eq!(
+        origin::unauthorized(None, policy.token),
+        Some("Send Authorization: Bearer <WRITE_TOKEN>")
+    );
+    assert!(origin::unauthorized(Some("Bearer 0123456789abcdee"), policy.token).is_some());
+    assert_eq!(
+        origin::unauthorized(Some("Bearer "), None),
+        Some("Build the Thing with WRITE_TOKEN to use this route")
+    );
+
+    // * turns the check off.
+    let any = Policy {
+        allowed: &["*"],
+        ..policy
+    };
+    assert_eq!(origin::check(&any, &from("http://evil.example")), Ok(()));
+
+    assert!(config::write_origins("gateway.lan:8080, [fe80::1]").is_ok());
+    assert!(config::write_origins("gateway.lan:x").is_err());
+    assert!(config::write_token("short").is_err());
+    assert!(config::write_token("0123456789abcdef").is_ok());
+    assert!(config::urn_org("acme").is_ok());
+    assert!(config::urn_org("acme-labs2").is_ok());
+    for org in ["a", "-acme", "acme-", "ac me", "URN", &"a".repeat(33)] {
+        assert!(config::urn_org(org).is_err(), "{org}");
+    }
+}
+
+#[test]
+fn i2c_bus_scan() {
+    let on_bus = [0x44, 0x70, 0x77, 0x03, 0x78];
+    let mut probed = Vec::new();
+    let found = i2c_scan::scan(|address| {
+        probed.push(address);
+        on_bus.contains(&address)
+    });
+    assert_eq!(found, [0x44, 0x70, 0x77]);
+    assert_eq!(probed.len(), 0x70);
+    assert_eq!(probed.first(), Some(&0x08));
+    assert_eq!(probed.last(), Some(&0x77));
+    assert_eq!(
+        i2c_scan::to_json(&found),
+        serde_json::json!({ "addresses": ["0x44", "0x70", "0x77"] })
+    );
+    assert_eq!(
+        i2c_scan::to_json(&[]),
+        serde_json::json!({ "addresses": [] })
+    );
+
+    assert_eq!(i2c_scan::retry_after_s(None, 1000), None);
+    assert_eq!(i2c_scan::retry_after_s(Some(1000), 1000), Some(5));
+    assert_eq!(i2c_scan::retry_after_s(Some(1000), 5001), Some(1));
+    assert_eq!(i2c_scan::retry_after_s(Some(1000), 6000), None);
+}
+
+#[test]
+fn inline_values() {
+    fn inline(value: &impl serde::Serialize) -> String {
+        let mut buf = [0; INLINE_LEN];
+        let len = inline::serialize(value, &mut buf).expect("fits inline");
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+
+    let sunrise = light::SunriseInput {
+        duration_s: 600,
+        target_brightness: 200,
+    };
+    let object = serde_json::json!({ "temperature": 21.5, "unit": "°C", "ok": true });
+    assert_eq!(inline(&21.5f32), serde_json::to_string(&21.5f32).unwrap());
+    assert_eq!(inline(&0.1f32), serde_json::to_string(&0.1f32).unwrap());
+    assert_eq!(inline(&-40i16), "-40");
+    assert_eq!(inline(&u32::MAX), "4294967295");
+    assert_eq!(inline(&true), "true");
+    assert_eq!(inline(&f32::NAN), "null");
+    assert_eq!(inline(&None::<u8>), "null");
+    assert_eq!(
+        inline(&"a \"quoted\"\n"),
+        serde_json::to_string("a \"quoted\"\n").unwrap()
+    );
+    assert_eq!(inline(&sunrise), serde_json::to_string(&sunrise).unwrap());
+    assert_eq!(inline(&object), serde_json::to_string(&object).unwrap());
+
+    let long = "x".repeat(INLINE_LEN);
+    assert_eq!(inline::serialize(&long, &mut [0; INLINE_LEN]), None);
+    assert_eq!(inline::serialize(&12345u32, &mut [0; 4]), None);
+}
+
+/// A property body as the boards and `demo-sim` send it.
+#[test]
+fn negotiated_property() {
+    let info = sensor_info(None);
+    let golden: serde_json::Value =
+        serde_json::from_str(include_str!("golden/sensor_info.json")).unwrap();
+    assert_eq!(info, golden);
+
+    let (content_type, body) = negotiate::property("application/json", &info).unwrap();
+    assert_eq!(content_type, negotiate::JSON);
+    assert_eq!(body, br#"{"id":null,"present":false,"type":"SHTC3"}"#);
+    assert_eq!(negotiate::property("", &21.5_f32).unwrap().1, b"21.5");
+
+    let (content_type, body) = negotiate::property("application/cbor", &true).unwrap();
+    assert_eq!(
+        (content_type, body.as_slice()),
+        (cbor::CONTENT_TYPE, &[0xf5][..])
+    );
+
+    assert_eq!(
+        negotiate::property("text/html", &info),
+        Err(negotiate::JSON)
+    );
+
+    // Past the inline buffer, the same text from the heap.
+    let long = "x".repeat(INLINE_LEN);
+    let (_, body) = negotiate::property("*/*", &long).unwrap();
+    assert_eq!(body, serde_json::to_vec(&long).unwrap());
+}
+
+/// The frames of `golden/events.sse`, which `demo-sim` sends as well.
+#[cfg(not(feature = "bare-events"))]
+#[test]
+fn sse_frames() {
+    use wot_esp_demo_core::sse;
+
+    let mut frames = String::new();
+    for (id, value) in [(1, 21.5_f32), (2, 22.25)] {
+        let mut buf = [0; sse::DATA_LEN];
+        frames += &sse::event(
+            "value_changed",
+            Some(id),
+            sse::data(&value, &mut buf).unwrap(),
+        );
+    }
+    assert_eq!(frames, include_str!("golden/events.sse"));
+
+    assert_eq!(
+        sse::event("events_lost", None, "3"),
+        "event:events_lost\ndata:3\n\n"
+    );
+    assert_eq!(
+        sse::comment("stream_end", &Delivery::default().comment()),
+        "event:stream_end\n: delivered=0 dropped=0 maxLag=0\n\n"
+    );
+
+    // Too long for the field: sent as null.
+    let long = "x".repeat(sse::DATA_LEN);
+    assert_eq!(sse::data(&long, &mut [0; sse::DATA_LEN]), None);
+}
+
+/// The keepalive interval of `SSE_KEEPALIVE_S`, always below the stream
+/// lifetime.
+#[test]
+fn sse_keepalive() {
+    use wot_esp_demo_core::sse::{keepalive_s, DEFAULT_KEEPALIVE_S, MAX_STREAM_LIFETIME_S};
+
+    assert_eq!(keepalive_s(None), DEFAULT_KEEPALIVE_S);
+    assert_eq!(keepalive_s(Some("30")), 30);
+    assert_eq!(keepalive_s(Some("0")), 1);
+    assert_eq!(keepalive_s(Some("3600")), MAX_STREAM_LIFETIME_S - 1);
+    assert_eq!(keepalive_s(Some("86400")), MAX_STREAM_LIFETIME_S - 1);
+    assert_eq!(keepalive_s(Some("soon")), DEFAULT_KEEPALIVE_S);
+
+    assert!(config::sse_keepalive("15").is_ok());
+    assert!(config::sse_keepalive("3599").is_ok());
+    assert!(config::sse_keepalive("3600").is_err());
+    assert!(config::sse_keepalive("0").is_err());
+}
+
+#[test]
+fn delivery_counts() {
+    let mut delivery = Delivery::default();
+    assert_eq!(delivery.comment(), "delivered=0 dropped=0 maxLag=0");
+
+    delivery.deliver();
+    delivery.deliver();
+    delivery.lag(5);
+    delivery.lag(2);
+    delivery.drop_events(3);
+    assert_eq!(
+        delivery,
+        Delivery {
+            delivered: 2,
+            dropped: 3,
+            max_lag: 5,
+        }
+    );
+    assert_eq!(delivery.comment(), "delivered=2 dropped=3 maxLag=5");
+
+    delivery.drop_events(u64::MAX);
+    delivery.lag(u64::MAX);
+    assert_eq!(delivery.dropped, u32::MAX);
+    assert_eq!(delivery.max_lag, u32::MAX);
+}
+
+#[test]
+fn route_prefix() {
+    assert_eq!(normalize("/light1").as_deref(), Ok("/light1"));
+    assert_eq!(normalize("light1/").as_deref(), Ok("/light1"));
+    assert_eq!(normalize("//lab//light1/").as_deref(), Ok("/lab/light1"));
+    for raw in [
+        "",
+        "/",
+        "//",
+        "/a b",
+        "/light?1",
+        "/a/../b",
+        "/.well-known/wot",
+    ] {
+        assert!(normalize(raw).is_err(), "{raw}");
+    }
+    assert!(config::route_prefix("/light1").is_ok());
+    assert!(config::route_prefix("/").is_err());
+
+    assert_eq!(prefix::href("/properties/on", "/l"), "/l/properties/on");
+    assert_eq!(prefix::href("properties/on", "/l"), "/l/properties/on");
+    assert_eq!(
+        prefix::href("http://192.0.2.7/actions/fade", "/l"),
+        "http://192.0.2.7/l/actions/fade"
+    );
+    assert_eq!(
+        prefix::href("https://thing.lan", "/l"),
+        "https://thing.lan/l/"
+    );
+    assert_eq!(prefix::href("/.well-known/wot", "/l"), "/.well-known/wot");
+    assert_eq!(
+        prefix::href("http://192.0.2.7/.well-known/wot", "/l"),
+        "http://192.0.2.7/.well-known/wot"
+    );
+    assert_eq!(
+        prefix::href("udp://192.0.2.7:5684", "/l"),
+        "udp://192.0.2.7:5684"
+    );
+
+    assert_eq!(strip("/l/properties/on", "/l"), "/properties/on");
+    assert_eq!(strip("/l", "/l"), "/");
+    assert_eq!(strip("/l/", "/l"), "/");
+    assert_eq!(strip("/light1/x", "/l"), "/light1/x");
+    assert_eq!(strip("/.well-known/wot", "/l"), "/.well-known/wot");
+
+    let td = serde_json::json!({
+        "title": "/title",
+        "base": "http://192.0.2.7/",
+        "properties": {
+            "on": { "forms": [{ "href": "/properties/on" }] },
+        },
+        "events": {
+            "fault": { "forms": [{ "href": "udp://192.0.2.7:5684" }] },
+        },
+        "forms": [{ "href": "/properties" }],
+        "links": [{ "href": "/.well-known/wot", "rel": "alternate" }],
+    });
+    let prefixed: serde_json::Value =
+        serde_json::from_str(&prefix_json(&td.to_string(), "/l").unwrap()).unwrap();
+    assert_eq!(
+        prefixed,
+        serde_json::json!({
+            "title": "/title",
+            "base": "http://192.0.2.7/",
+            "properties": {
+                "on": { "forms": [{ "href": "/l/properties/on" }] },
+            },
+            "events": {
+                "fault": { "forms": [{ "href": "udp://192.0.2.7:5684" }] },
+            },
+            "forms": [{ "href": "/l/properties" }],
+            "links": [{ "href": "/.well-known/wot", "rel": "alternate" }],
+        })
+    );
+    assert!(prefix_json("[]", "/l").is_err());
+}
@@ -0,0 +1,249 @@
+//! Each demo's TD against the golden file in `tests/golden`.
+//!
+//! The whole serialized TD must equal the golden one as JSON, so any member
+//! added, dropped or changed by a builder or a `wot-td` upgrade shows up
+//! here. The golden files are pretty-printed with sorted keys.
+
+use serde_json::Value;
+use wot_esp_demo_core::{
+    schema::InputSchema,
+    td::{fallback, serialize_with_metadata, TdLink, TdMetadata, TD_ERROR},
+    things,
+};
+use wot_td::{builder::Error, Thing};
+
+const BASE: &str = "http://192.0.2.1";
+const ID: &str = "urn:example/test";
+
+/// A demo's TD builder, taking the Thing name, base URL and id.
+type Build = fn(&str, String, String) -> Result<Thing, Error>;
+
+fn td(build: Build, name: &str) -> Value {
+    serde_json::to_value(build(name, BASE.into(), ID.into()).unwrap()).unwrap()
+}
+
+/// Whether every member of `golden` is in `actual`, at `path`.
+///
+/// A golden string also matches a one-element array holding it, as `@type`,
+/// `op` and `security` may be serialized either way.
+fn assert_contains(actual: &Value, golden: &Value, path: &str) {
+    match (actual, golden) {
+        (Value::Object(actual), Value::Object(golden)) => {
+            for (key, golden) in golden {
+                let path = format!("{path}/{key}");
+                let actual = actual
+                    .get(key)
+                    .unwrap_or_else(|| panic!("{path} is missing"));
+                assert_contains(actual, golden, &path);
+            }
+        }
+        (Value::Array(actual), Value::Array(golden)) => {
+            assert_eq!(actual.len(), golden.len(), "{path} length");
+            for (i, (actual, golden)) in actual.iter().zip(golden).enumerate() {
+                assert_contains(actual, golden, &format!("{path}/{i}"));
+            }
+        }
+        (Value::Array(actual), Value::String(_)) if actual.len() == 1 => {
+            assert_contains(&actual[0], golden, path);
+        }
+        _ => assert_eq!(actual, golden, "{path}"),
+    }
+}
+
+fn check(build: Build, name: &str, golden: &str) {
+    let golden: Value = serde_json::from_str(golden).unwrap();
+    let actual = td(build, name);
+    assert!(
+        actual == golden,
+        "{name} TD differs from the golden file:\n{}",
+        serde_json::to_string_pretty(&actual).unwrap()
+    );
+}
+
+#[test]
+fn button() {
+    check(
+        things::button::td,
+        "button",
+        include_str!("golden/button.json"),
+    );
+}
+
+/// Several buttons get an `onN` property and event each, and a `button`
+/// member in `raw`.
+#[test]
+fn buttons() {
+    let td = things::button::td_with_buttons("button", BASE.into(), ID.into(), 2).unwrap();
+    let td = serde_json::to_value(td).unwrap();
+
+    assert!(td["properties"].get("on").is_none());
+    for n in 0..2 {
+        let name = format!("on{n}");
+        let property = &td["properties"][&name];
+        assert_eq!(property["forms"][0]["href"], format!("/properties/{name}"));
+        assert_eq!(property["forms"][1]["href"], format!("/events/{name}"));
+        assert_eq!(
+            td["events"][&name]["forms"][0]["href"],
+            format!("/events/{name}")
+        );
+    }
+    assert!(td["properties"].get("on2").is_none());
+    let raw = &td["events"]["raw"]["data"];
+    assert_eq!(raw["properties"]["button"]["maximum"], 1);
+    assert_eq!(
+        raw["required"],
+        serde_json::json!(["button", "edge", "uptime_ms"])
+    );
+}
+
+#[test]
+fn light() {
+    check(
+        things::light::td,
+        "light",
+        include_str!("golden/light.json"),
+    );
+}
+
+/// The action inputs are checked against schemas declared apart from the
+/// TD builder, see `wot_esp_demo_core::schema`.
+#[test]
+fn light_action_schemas() {
+    let td = td(things::light::td, "light");
+    assert_contains(
+        &td["actions"]["offTimer"]["input"],
+        &things::light::OffTimerInput::SCHEMA.to_json(),
+        "/actions/offTimer/input",
+    );
+    assert_contains(
+        &td["actions"]["sunrise"]["input"],
+        &things::light::SunriseInput::SCHEMA.to_json(),
+        "/actions/sunrise/input",
+    );
+}
+
+#[test]
+fn thermometer() {
+    check(
+        things::thermometer::td,
+        "shtc3",
+        include_str!("golden/thermometer.json"),
+    );
+}
+
+#[test]
+fn multitherm() {
+    check(
+        things::multitherm::td,
+        "multitherm",
+        include_str!("golden/multitherm.json"),
+    );
+}
+
+#[test]
+fn fan() {
+    check(things::fan::td, "fan", include_str!("golden/fan.json"));
+}
+
+/// Every TD reads back through the `wot-td` serializer unchanged, and each
+/// observable property has an `sse` form observing the stream of the event
+/// of the same name.
+#[test]
+fn observable_round_trip() {
+    let builds: [(Build, &str); 5] = [
+        (things::button::td, "button"),
+        (things::light::td, "light"),
+        (things::thermometer::td, "shtc3"),
+        (things::multitherm::td, "multitherm"),
+        (things::fan::td, "fan"),
+    ];
+    for (build, name) in builds {
+        let json = serde_json::to_string(&build(name, BASE.into(), ID.into()).unwrap()).unwrap();
+        let thing: Thing = serde_json::from_str(&json).unwrap();
+        let td: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_value(thing).unwrap(), td, "{name}");
+
+        for (property, affordance) in td["properties"].as_object().unwrap() {
+            if affordance.get("observable") != Some(&Value::Bool(true)) {
+                continue;
+            }
+            let href = format!("/events/{property}");
+            let sse = affordance["forms"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|form| form["subprotocol"] == "sse")
+                .unwrap_or_else(|| panic!("{name}/{property} has no sse form"));
+            assert_eq!(sse["href"], href, "{name}/{property}");
+            assert_eq!(
+                sse["op"],
+                serde_json::json!(["observeproperty", "unobserveproperty"]),
+                "{name}/{property}"
+            );
+            assert_eq!(td["events"][property]["forms"][0]["href"], href);
+        }
+    }
+}
+
+/// The `version`, `support` and `links` merged into every TD, against
+/// `golden/metadata.json`.
+#[test]
+fn metadata() {
+    let meta = TdMetadata {
+        version: "0.2.0+3fff183",
+        support: Some("mailto:ops@example.com"),
+        links: &[TdLink {
+            href: "/metrics",
+            rel: "item",
+            content_type: Some("text/plain"),
+        }],
+        group: &[],
+    };
+    let thing = things::fan::td("fan", BASE.into(), ID.into()).unwrap();
+    let td: Value = serde_json::from_str(&serialize_with_metadata(&thing, &meta).unwrap()).unwrap();
+    let golden: Value = serde_json::from_str(include_str!("golden/metadata.json")).unwrap();
+
+    for member in ["version", "support", "links"] {
+        assert_eq!(td[member], golden[member], "{member}");
+    }
+
+    // The low-heap flag of wot_esp_thing::heap, always sent.
+    let network = &td["properties"]["network"];
+    assert_eq!(network["properties"]["heapLow"]["type"], "boolean");
+    assert!(network["required"]
+        .as_array()
+        .unwrap()
+        .contains(&"heapLow".into()));
+
+    // Without them, no support and no links.
+    let meta = TdMetadata {
+        support: None,
+        links: &[],
+        ..meta
+    };
+    let td: Value = serde_json::from_str(&serialize_with_metadata(&thing, &meta).unwrap()).unwrap();
+    assert!(td.get("support").is_none());
+    assert!(td.get("links").is_none());
+}
+
+#[test]
+fn fallback_td() {
+    let meta = TdMetadata {
+        version: "0.2.0+test",
+        support: None,
+        links: &[],
+        group: &[],
+    };
+    let td = fallback("light", BASE, ID, "duplicated form", &meta).unwrap();
+    let td: Value = serde_json::from_str(&td).unwrap();
+
+    assert_eq!(td["title"], "light");
+    assert_eq!(td["id"], ID);
+    assert_eq!(td["base"], BASE);
+    assert_eq!(td["security"], "nosec_sc");
+    assert_eq!(td[TD_ERROR], "duplicated form");
+    assert_eq!(td["version"]["instance"], "0.2.0+test");
+    // The affordances every Thing exposes, but none of the light's.
+    assert!(td["properties"].get("lastSelfTest").is_some());
+    assert!(td["properties"].get("on").is_none());
+}
@@ -0,0 +1,19 @@
+//! The hygro-thermometer with the SHTC3 replaced by slow sine waves, see
+//! [`demo_sim::thermometer`].
+
+use std::{env, io, net::TcpListener, thread, time::Instant};
+
+use demo_sim::{serve, thermometer};
+
+fn main() -> io::Result<()> {
+    let address = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:8080".into());
+    let listener = TcpListener::bind(address)?;
+    let td = thermometer::td(format!("http://{}", listener.local_addr()?));
+
+    let start = Instant::now();
+    thread::spawn(move || thermometer::sample(move || start.elapsed().as_secs_f32()));
+
+    serve(&listener, thermometer::routes(td, start))
+}
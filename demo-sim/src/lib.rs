@@ -0,0 +1,426 @@
+//! Run the demos on a host, with their peripherals mocked.
+//!
+//! A small blocking HTTP/1.1 server on `std::net` stands in for embassy-net
+//! and picoserve. The TD comes from the same builders and metadata as on the
+//! boards ([`wot_esp_demo_core`]), and so do the responses: the content
+//! negotiation and bodies of [`negotiate::property`], the TD headers and
+//! ETag of [`td`] and [`integrity`], and the SSE frames of [`sse`]. The
+//! tests check them against the golden files of `demo-core/tests/golden`.
+//!
+//! ```sh
+//! cargo run -p demo-sim --bin thermometer [address:port]
+//! ```
+
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::{Condvar, Mutex, OnceLock, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use wot_esp_demo_core::{
+    delivery::Delivery,
+    integrity, link_format,
+    negotiate::{self, accepts, etag_matches},
+    sse, td,
+};
+
+pub mod thermometer;
+
+/// Interval after which an idle stream gets a keepalive comment, from
+/// `SSE_KEEPALIVE_S` at build time as on the boards.
+fn keepalive_interval() -> Duration {
+    Duration::from_secs(sse::keepalive_s(option_env!("SSE_KEEPALIVE_S")))
+}
+
+/// Time after which a stream is ended, as `wot_esp_thing::MAX_STREAM_LIFETIME`.
+const MAX_STREAM_LIFETIME: Duration = Duration::from_secs(sse::MAX_STREAM_LIFETIME_S);
+
+/// Number of past events retained per channel for `Last-Event-ID` replay.
+const EVENT_LOG_LEN: usize = 8;
+
+/// A parsed request line and its headers; the body is discarded.
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn read(reader: &mut impl BufRead) -> io::Result<Option<Self>> {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_owned();
+        let path = parts.next().unwrap_or_default().to_owned();
+
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            reader.read_line(&mut line)?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_owned(), value.trim().to_owned()));
+            }
+        }
+
+        let request = Self {
+            method,
+            path,
+            headers,
+        };
+        let len = request
+            .header("Content-Length")
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(0);
+        io::copy(&mut reader.take(len), &mut io::sink())?;
+
+        Ok(Some(request))
+    }
+
+    /// The value of the header `name`, compared case-insensitively.
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Whether the `Accept` header allows `media_type`; a missing one does.
+    #[must_use]
+    pub fn accepts(&self, media_type: &str) -> bool {
+        accepts(self.header("Accept").unwrap_or_default(), media_type)
+    }
+
+    /// The `Last-Event-ID` sent by a reconnecting SSE client.
+    #[must_use]
+    pub fn last_event_id(&self) -> Option<u32> {
+        self.header("Last-Event-ID")?.trim().parse().ok()
+    }
+}
+
+/// Write a complete response.
+///
+/// # Errors
+///
+/// Returns an error if the client went away.
+pub fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    respond_with(stream, status, &[("Content-Type", content_type)], body)
+}
+
+/// Write a complete response with `headers`.
+///
+/// # Errors
+///
+/// Returns an error if the client went away.
+pub fn respond_with(
+    stream: &mut TcpStream,
+    status: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 {status}\r\n")?;
+    for (name, value) in headers {
+        write!(stream, "{name}: {value}\r\n")?;
+    }
+    write!(stream, "Content-Length: {}\r\n\r\n", body.len())?;
+    stream.write_all(body)
+}
+
+/// `value` as CBOR if the client prefers it, as JSON if it accepts it, HTTP
+/// 406 otherwise, see [`negotiate::property`].
+///
+/// # Errors
+///
+/// Returns an error if the client went away.
+///
+/// # Panics
+///
+/// Panics if `value` cannot be serialized.
+pub fn respond_negotiated(
+    request: &Request,
+    stream: &mut TcpStream,
+    value: &impl Serialize,
+) -> io::Result<()> {
+    match negotiate::property(request.header("Accept").unwrap_or_default(), value) {
+        Ok((content_type, body)) => respond(stream, "200 OK", content_type, &body),
+        Err(media_type) => not_acceptable(stream, media_type),
+    }
+}
+
+fn not_acceptable(stream: &mut TcpStream, media_type: &str) -> io::Result<()> {
+    respond(
+        stream,
+        "406 Not Acceptable",
+        "text/plain",
+        media_type.as_bytes(),
+    )
+}
+
+/// Sequence counter and ring of the last events of a channel.
+///
+/// The host counterpart of `wot_esp_thing::EventLog` and the `Watch` next to
+/// it: streams wait on it for the latest value.
+pub struct EventLog<T> {
+    ring: Mutex<(u32, VecDeque<(u32, T)>)>,
+    changed: Condvar,
+}
+
+impl<T: Clone + Serialize> EventLog<T> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            ring: Mutex::new((1, VecDeque::new())),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Assign the next sequence number to `value` and wake the streams.
+    pub fn record(&self, value: T) {
+        let mut ring = self.ring.lock().unwrap_or_else(PoisonError::into_inner);
+        let id = ring.0;
+        ring.0 = id.wrapping_add(1).max(1);
+        if ring.1.len() == EVENT_LOG_LEN {
+            ring.1.pop_front();
+        }
+        ring.1.push_back((id, value));
+        self.changed.notify_all();
+    }
+
+    /// `last_event_id` if this log assigned it, not one from a previous run.
+    fn issued(&self, last_event_id: Option<u32>) -> Option<u32> {
+        let ring = self.ring.lock().unwrap_or_else(PoisonError::into_inner);
+        last_event_id.filter(|&id| id < ring.0)
+    }
+
+    fn since(&self, last_id: u32) -> Vec<(u32, T)> {
+        let ring = self.ring.lock().unwrap_or_else(PoisonError::into_inner);
+        ring.1
+            .iter()
+            .filter(|(id, _)| *id > last_id)
+            .cloned()
+            .collect()
+    }
+
+    /// The latest event if it is newer than `last_id`, waiting up to
+    /// `timeout` for one.
+    fn latest_after(&self, last_id: u32, timeout: Duration) -> Option<(u32, T)> {
+        let ring = self.ring.lock().unwrap_or_else(PoisonError::into_inner);
+        let stale = |ring: &mut (u32, VecDeque<(u32, T)>)| {
+            ring.1.back().is_none_or(|(id, _)| *id <= last_id)
+        };
+        let (ring, _) = self
+            .changed
+            .wait_timeout_while(ring, timeout, stale)
+            .unwrap_or_else(PoisonError::into_inner);
+
+        ring.1.back().filter(|(id, _)| *id > last_id).cloned()
+    }
+
+    /// Stream the events as `value_changed` Server-Sent Events.
+    ///
+    /// Replays the events newer than `last_event_id`, then sends the latest
+    /// value whenever it changes, or a keepalive comment when idle, until
+    /// [`MAX_STREAM_LIFETIME`] is reached. An id from a previous run replays
+    /// nothing and gets every new event.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error once the client went away.
+    pub fn stream(&self, stream: &mut TcpStream, last_event_id: Option<u32>) -> io::Result<()> {
+        // No length: the connection is closed to end the stream.
+        stream.write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n")?;
+        for (name, value) in sse::HEADERS {
+            write!(stream, "{name}: {value}\r\n")?;
+        }
+        stream.write_all(b"\r\n")?;
+
+        let mut delivery = Delivery::default();
+        let mut last_sent = 0;
+        if let Some(last_event_id) = self.issued(last_event_id) {
+            last_sent = last_event_id;
+            for (id, value) in self.since(last_event_id) {
+                write_frame(stream, &value_changed(id, &value))?;
+                delivery.deliver();
+                last_sent = id;
+            }
+        }
+
+        let deadline = Instant::now() + MAX_STREAM_LIFETIME;
+        while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            match self.latest_after(last_sent, keepalive_interval().min(left)) {
+                Some((id, value)) => {
+                    write_frame(stream, &value_changed(id, &value))?;
+                    delivery.deliver();
+                    last_sent = id;
+                }
+                None => write_frame(stream, sse::KEEPALIVE)?,
+            }
+        }
+
+        write_frame(stream, &sse::comment("stream_end", &delivery.comment()))?;
+        // Closing it tells the client to reconnect.
+        stream.shutdown(Shutdown::Both)
+    }
+}
+
+impl<T: Clone + Serialize> Default for EventLog<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The `value_changed` frame of the event `id`, as the firmware sends it.
+fn value_changed(id: u32, value: &impl Serialize) -> String {
+    let mut buf = [0; sse::DATA_LEN];
+    let data = sse::data(value, &mut buf).unwrap_or("null");
+    sse::event("value_changed", Some(id), data)
+}
+
+fn write_frame(stream: &mut TcpStream, frame: &str) -> io::Result<()> {
+    stream.write_all(frame.as_bytes())?;
+    stream.flush()
+}
+
+/// Connectivity of the simulated station, always connected.
+static CONNECTIVITY: EventLog<serde_json::Value> = EventLog::new();
+
+/// When [`serve`] started, the uptime origin.
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+/// The `lastSelfTest` property, set by the `selfTest` action.
+static LAST_SELF_TEST: Mutex<Option<serde_json::Value>> = Mutex::new(None);
+
+/// Serve the routes every Thing has: the TD, its discovery links and the
+/// connectivity affordances.
+///
+/// Returns `false` when `request` is for none of them.
+///
+/// # Errors
+///
+/// Returns an error if the client went away.
+pub fn common_routes(request: &Request, stream: &mut TcpStream, td: &str) -> io::Result<bool> {
+    match request.path.as_str() {
+        "/" if request.accepts(td::TD_MEDIA_TYPE) => {
+            // Served uncompressed, with the headers of the boards.
+            let etag = integrity::etag(&integrity::sha256(td), false);
+            if request
+                .header("If-None-Match")
+                .is_some_and(|header| etag_matches(header, &etag))
+            {
+                let headers = td::not_modified_headers(&etag);
+                respond_with(stream, "304 Not Modified", &headers, b"")?;
+            } else {
+                respond_with(stream, "200 OK", &td::headers(&etag, false), td.as_bytes())?;
+            }
+        }
+        "/" => not_acceptable(stream, td::TD_MEDIA_TYPE)?,
+        "/.well-known/wot" => {
+            stream.write_all(b"HTTP/1.1 302 Found\r\nLocation: /\r\nContent-Length: 0\r\n\r\n")?;
+        }
+        "/.well-known/core" if request.accepts(link_format::CONTENT_TYPE) => {
+            let body = link_format::well_known_core();
+            respond(stream, "200 OK", link_format::CONTENT_TYPE, body.as_bytes())?;
+        }
+        "/.well-known/core" => not_acceptable(stream, link_format::CONTENT_TYPE)?,
+        // Online since the simulation started.
+        "/properties/online_since" => respond_negotiated(request, stream, &Some(0))?,
+        "/properties/network" => respond_negotiated(request, stream, &network(stream)?)?,
+        "/properties/lastSelfTest" => {
+            let last = LAST_SELF_TEST
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone();
+            respond_negotiated(request, stream, &last)?;
+        }
+        "/actions/selfTest" if request.method == "POST" => {
+            respond_negotiated(request, stream, &self_test())?;
+        }
+        // Nothing to announce or put to sleep: just stop.
+        #[cfg(feature = "shutdown")]
+        "/actions/shutdown" if request.method == "POST" => {
+            respond(stream, "204 No Content", "text/plain", b"")?;
+            stream.flush()?;
+            std::process::exit(0);
+        }
+        "/events/connectivity" => CONNECTIVITY.stream(stream, request.last_event_id())?,
+        _ => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+/// Run the `selfTest` action: the simulation has no hardware to check.
+fn self_test() -> serde_json::Value {
+    let results = wot_esp_demo_core::self_test::results([]);
+    let at = STARTED
+        .get()
+        .map_or(0, |started| started.elapsed().as_millis());
+    *LAST_SELF_TEST
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) =
+        Some(serde_json::json!({ "at": at, "results": results.clone() }));
+    results
+}
+
+/// The `network` property of the host interface `stream` was accepted on.
+fn network(stream: &TcpStream) -> io::Result<serde_json::Value> {
+    let ip = stream.local_addr()?.ip();
+    Ok(serde_json::json!({
+        "ip": ip,
+        "dns": [],
+        "mac": "00:00:00:00:00:00",
+        "heapLow": false,
+    }))
+}
+
+/// Accept connections on `listener`, a thread each, and answer every request
+/// with `handler`; requests it does not serve get HTTP 404.
+///
+/// # Errors
+///
+/// Returns an error if `listener` fails.
+pub fn serve<H>(listener: &TcpListener, handler: H) -> io::Result<()>
+where
+    H: Fn(&Request, &mut TcpStream) -> io::Result<bool> + Copy + Send + 'static,
+{
+    let address = listener.local_addr()?;
+    STARTED.get_or_init(Instant::now);
+    CONNECTIVITY.record(serde_json::json!({
+        "state": "connected",
+        "ip": address.ip(),
+        "ssid": "sim",
+    }));
+    println!("Serving on http://{address}/");
+
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        thread::spawn(move || -> io::Result<()> {
+            let mut reader = BufReader::new(stream.try_clone()?);
+            while let Some(request) = Request::read(&mut reader)? {
+                if !["GET", "PUT", "POST"].contains(&request.method.as_str()) {
+                    respond(&mut stream, "405 Method Not Allowed", "text/plain", b"")?;
+                } else if !handler(&request, &mut stream)? {
+                    respond(&mut stream, "404 Not Found", "text/plain", b"Not Found")?;
+                }
+            }
+            Ok(())
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,113 @@
+//! The hygro-thermometer with the SHTC3 replaced by slow sine waves.
+
+use std::{
+    f32::consts::TAU,
+    io,
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant},
+};
+
+use wot_esp_demo_core::{
+    names::device_urn,
+    td::{serialize_with_metadata, TdMetadata},
+    things::{
+        temperature_changed,
+        thermometer::{self, dew_point, heat_index},
+    },
+};
+
+use crate::{common_routes, respond_negotiated, EventLog, Request};
+
+/// Name of the Thing, as on the board.
+pub const NAME: &str = "shtc3";
+
+/// The metadata merged into the TD.
+pub const META: TdMetadata = TdMetadata {
+    version: concat!(env!("CARGO_PKG_VERSION"), "+sim"),
+    support: None,
+    links: &[],
+    group: &[],
+};
+
+/// Seconds for the readings to go through a full cycle.
+const PERIOD: f32 = 600.0;
+
+/// The `temperature` events, recorded by [`sample`].
+pub static TEMPERATURE: EventLog<f32> = EventLog::new();
+
+fn temperature(t: f32) -> f32 {
+    22.0 + 3.0 * (TAU * t / PERIOD).sin()
+}
+
+fn humidity(t: f32) -> f32 {
+    45.0 + 10.0 * (TAU * t / PERIOD).cos()
+}
+
+/// The id of the simulated Thing.
+#[must_use]
+pub fn id() -> String {
+    device_urn(Some("sim"), NAME, &[0; 6])
+}
+
+/// The TD served at `/` by a Thing on `base_uri`.
+///
+/// # Panics
+///
+/// Panics if the TD cannot be built, as on the board.
+#[must_use]
+pub fn td(base_uri: String) -> &'static str {
+    let td = thermometer::td(NAME, base_uri, id()).expect("TD");
+    serialize_with_metadata(&td, &META).expect("TD").leak()
+}
+
+/// Record a `temperature` event whenever the reading at `uptime` changes,
+/// sampling like `temperature_write_task` does.
+pub fn sample(uptime: impl Fn() -> f32) -> ! {
+    let mut last_temp = temperature(uptime());
+    loop {
+        thread::sleep(Duration::from_secs(1));
+        let temp = temperature(uptime());
+        if temperature_changed(last_temp, temp) {
+            TEMPERATURE.record(temp);
+            last_temp = temp;
+        }
+    }
+}
+
+/// The handler of [`crate::serve`] serving `td`, with the readings of
+/// `start` on.
+pub fn routes(
+    td: &'static str,
+    start: Instant,
+) -> impl Fn(&Request, &mut TcpStream) -> io::Result<bool> + Copy + Send + 'static {
+    let uptime = move || start.elapsed().as_secs_f32();
+
+    move |request, stream| {
+        match request.path.as_str() {
+            "/properties/temperature" => {
+                respond_negotiated(request, stream, &temperature(uptime()))?;
+            }
+            "/properties/humidity" => respond_negotiated(request, stream, &humidity(uptime()))?,
+            "/properties/dewPoint" => {
+                let t = uptime();
+                respond_negotiated(request, stream, &dew_point(temperature(t), humidity(t)))?;
+            }
+            "/properties/heatIndex" => {
+                let t = uptime();
+                respond_negotiated(request, stream, &heat_index(temperature(t), humidity(t)))?;
+            }
+            // The die runs warmer than the room.
+            "/properties/die_temperature" => {
+                respond_negotiated(request, stream, &(temperature(uptime()) + 15.0))?;
+            }
+            // There is no SHTC3 to read the ID register of.
+            "/properties/sensorInfo" => {
+                respond_negotiated(request, stream, &thermometer::sensor_info(None))?;
+            }
+            "/events/temperature" => TEMPERATURE.stream(stream, request.last_event_id())?,
+            _ => return common_routes(request, stream, td),
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,178 @@
+//! The responses of the simulated thermometer against the golden files of
+//! `demo-core/tests/golden`, which the boards are checked against too.
+
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::OnceLock,
+    thread,
+    time::Instant,
+};
+
+use demo_sim::{serve, thermometer};
+use serde_json::{Map, Value};
+use wot_esp_demo_core::{cbor, integrity, td};
+
+/// The address of the thermometer, started once for every test, without
+/// the sampling thread: the only events are those a test records.
+fn thing() -> SocketAddr {
+    static ADDRESS: OnceLock<SocketAddr> = OnceLock::new();
+    *ADDRESS.get_or_init(|| {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let td = thermometer::td(format!("http://{address}"));
+        thread::spawn(move || serve(&listener, thermometer::routes(td, Instant::now())));
+        address
+    })
+}
+
+/// A response: the status line, the headers and the body.
+struct Response {
+    status: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// `GET path` with `headers`; the body is read up to its `Content-Length`,
+/// or `stream_len` bytes of a stream.
+fn get(path: &str, headers: &[(&str, &str)], stream_len: usize) -> Response {
+    let mut stream = TcpStream::connect(thing()).unwrap();
+    write!(stream, "GET {path} HTTP/1.1\r\nHost: sim\r\n").unwrap();
+    for (name, value) in headers {
+        write!(stream, "{name}: {value}\r\n").unwrap();
+    }
+    stream.write_all(b"\r\n").unwrap();
+
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).unwrap();
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8(head).unwrap();
+    let mut lines = head.trim_end().lines();
+    let status = lines.next().unwrap().to_owned();
+    let headers: Vec<_> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_owned(), value.trim().to_owned()))
+        .collect();
+
+    let mut response = Response {
+        status,
+        headers,
+        body: Vec::new(),
+    };
+    let len = response
+        .header("Content-Length")
+        .map_or(stream_len, |len| len.parse().unwrap());
+    response.body = vec![0; len];
+    stream.read_exact(&mut response.body).unwrap();
+    response
+}
+
+fn golden(json: &str) -> Value {
+    serde_json::from_str(json).unwrap()
+}
+
+/// The TD is the golden one with the address, id and metadata of the
+/// simulator, served with the headers of the boards.
+#[test]
+fn td() {
+    let response = get("/", &[("Accept", td::TD_MEDIA_TYPE)], 0);
+    assert_eq!(response.status, "HTTP/1.1 200 OK");
+
+    let Value::Object(mut expected) = golden(include_str!(
+        "../../demo-core/tests/golden/thermometer.json"
+    )) else {
+        panic!("the golden TD is not an object");
+    };
+    expected.insert("base".into(), format!("http://{}", thing()).into());
+    expected.insert("id".into(), thermometer::id().into());
+    td::merge_metadata(&mut expected, &thermometer::META);
+    let actual: Map<String, Value> = serde_json::from_slice(&response.body).unwrap();
+    assert_eq!(Value::Object(actual), Value::Object(expected));
+
+    let body = std::str::from_utf8(&response.body).unwrap();
+    let etag = integrity::etag(&integrity::sha256(body), false);
+    for (name, value) in td::headers(&etag, false) {
+        assert_eq!(response.header(name), Some(value), "{name}");
+    }
+
+    let response = get(
+        "/",
+        &[("Accept", td::TD_MEDIA_TYPE), ("If-None-Match", &etag)],
+        0,
+    );
+    assert_eq!(response.status, "HTTP/1.1 304 Not Modified");
+    assert!(response.body.is_empty());
+    for (name, value) in td::not_modified_headers(&etag) {
+        assert_eq!(response.header(name), Some(value), "{name}");
+    }
+
+    let response = get("/", &[("Accept", "text/html")], 0);
+    assert_eq!(response.status, "HTTP/1.1 406 Not Acceptable");
+    assert_eq!(response.body, td::TD_MEDIA_TYPE.as_bytes());
+}
+
+/// A property is the golden value, as JSON or CBOR.
+#[test]
+fn property() {
+    let expected = golden(include_str!(
+        "../../demo-core/tests/golden/sensor_info.json"
+    ));
+
+    let response = get(
+        "/properties/sensorInfo",
+        &[("Accept", "application/json")],
+        0,
+    );
+    assert_eq!(response.status, "HTTP/1.1 200 OK");
+    assert_eq!(response.header("Content-Type"), Some("application/json"));
+    assert_eq!(
+        serde_json::from_slice::<Value>(&response.body).unwrap(),
+        expected
+    );
+
+    let response = get(
+        "/properties/sensorInfo",
+        &[("Accept", cbor::CONTENT_TYPE)],
+        0,
+    );
+    assert_eq!(response.header("Content-Type"), Some(cbor::CONTENT_TYPE));
+    assert_eq!(Some(response.body), cbor::to_vec(&expected));
+
+    let response = get("/properties/sensorInfo", &[("Accept", "text/html")], 0);
+    assert_eq!(response.status, "HTTP/1.1 406 Not Acceptable");
+    assert_eq!(response.body, b"application/json");
+}
+
+/// A reconnecting client gets the events it missed, framed as the golden
+/// stream.
+#[cfg(not(feature = "bare-events"))]
+#[test]
+fn events() {
+    let expected = include_str!("../../demo-core/tests/golden/events.sse");
+    thermometer::TEMPERATURE.record(21.5);
+    thermometer::TEMPERATURE.record(22.25);
+
+    let response = get(
+        "/events/temperature",
+        &[("Last-Event-ID", "0")],
+        expected.len(),
+    );
+    assert_eq!(response.status, "HTTP/1.1 200 OK");
+    assert_eq!(response.header("Connection"), Some("close"));
+    for (name, value) in wot_esp_demo_core::sse::HEADERS {
+        assert_eq!(response.header(name), Some(value), "{name}");
+    }
+    assert_eq!(std::str::from_utf8(&response.body).unwrap(), expected);
+}
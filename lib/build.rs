@@ -1,5 +1,119 @@
+use std::process::Command;
+
 fn main() {
-    // WiFi credentials are baked in via env! in lib.rs; rebuild when they change.
-    println!("cargo:rerun-if-env-changed=SSID");
-    println!("cargo:rerun-if-env-changed=PASSWORD");
+    // Settings are baked in via option_env! in config.rs; rebuild when they change.
+    for suffix in ["", "_1", "_2", "_3", "_4"] {
+        println!("cargo:rerun-if-env-changed=SSID{suffix}");
+        println!("cargo:rerun-if-env-changed=PASSWORD{suffix}");
+        println!("cargo:rerun-if-env-changed=SSID{suffix}_HIDDEN");
+        println!("cargo:rerun-if-env-changed=EAP_IDENTITY{suffix}");
+        println!("cargo:rerun-if-env-changed=EAP_USERNAME{suffix}");
+    }
+    println!("cargo:rerun-if-env-changed=AP_PASSPHRASE");
+    println!("cargo:rerun-if-env-changed=WIFI_COUNTRY");
+    println!("cargo:rerun-if-env-changed=WIFI_MAX_TX_POWER");
+    println!("cargo:rerun-if-env-changed=SUPPORT_URL");
+    println!("cargo:rerun-if-env-changed=PUBLIC_BASE_URL");
+    println!("cargo:rerun-if-env-changed=ESP_LOG");
+    println!("cargo:rerun-if-env-changed=SYSLOG_HOST");
+    println!("cargo:rerun-if-env-changed=SYSLOG_PORT");
+    println!("cargo:rerun-if-env-changed=TDD_URL");
+    println!("cargo:rerun-if-env-changed=DEBUG_PORT");
+    println!("cargo:rerun-if-env-changed=WEBHOOK_URL");
+    println!("cargo:rerun-if-env-changed=WAKE_INTERVAL_MIN");
+    println!("cargo:rerun-if-env-changed=SHUTDOWN_WAKE_MIN");
+    println!("cargo:rerun-if-env-changed=NTP_SERVER");
+    println!("cargo:rerun-if-env-changed=UDP_PORT");
+    println!("cargo:rerun-if-env-changed=FLASH_MAX_WRITES_PER_HOUR");
+    println!("cargo:rerun-if-env-changed=WRITE_ORIGINS");
+    println!("cargo:rerun-if-env-changed=WRITE_TOKEN");
+    println!("cargo:rerun-if-env-changed=URN_ORG");
+    println!("cargo:rerun-if-env-changed=ROUTE_PREFIX");
+    println!("cargo:rerun-if-env-changed=STRIKE_LIMIT");
+    println!("cargo:rerun-if-env-changed=STRIKE_WINDOW_S");
+    println!("cargo:rerun-if-env-changed=BROWNOUT_LEVEL");
+    println!("cargo:rerun-if-env-changed=SSE_KEEPALIVE_S");
+
+    // The app is nested under ROUTE_PREFIX only when it is set, see prefix.rs.
+    println!("cargo::rustc-check-cfg=cfg(route_prefix)");
+    if std::env::var_os("ROUTE_PREFIX").is_some() {
+        println!("cargo:rustc-cfg=route_prefix");
+    }
+
+    // Git revision reported as part of the TD `version`.
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs");
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .and_then(|o| String::from_utf8(o.stdout).ok())
+        .map_or_else(|| "unknown".into(), |h| h.trim().to_string());
+    println!("cargo:rustc-env=GIT_HASH={hash}");
+
+    #[cfg(feature = "ui")]
+    compress_ui();
+}
+
+/// Total compressed size allowed for the embedded UI assets.
+#[cfg(feature = "ui")]
+const UI_BUDGET: usize = 10 * 1024;
+
+/// Assets served under `/ui`: path, source file and content type.
+#[cfg(feature = "ui")]
+const UI_ASSETS: &[(&str, &str, &str)] = &[("/ui", "ui/index.html", "text/html; charset=utf-8")];
+
+/// 64-bit FNV-1a, enough to tell two builds of an asset apart.
+#[cfg(feature = "ui")]
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Gzip the `/ui` assets into `OUT_DIR` so the firmware embeds them
+/// precompressed, and write `assets.rs`, the table of `compress::Asset`s with
+/// their ETags.
+#[cfg(feature = "ui")]
+fn compress_ui() {
+    use std::{fmt::Write as _, io::Write as _};
+
+    let out = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    let dir = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    let mut total = 0;
+    let mut table = String::from("[\n");
+
+    for (i, (path, src, content_type)) in UI_ASSETS.iter().enumerate() {
+        println!("cargo:rerun-if-changed={src}");
+
+        let body = std::fs::read(src).expect("cannot read a UI asset");
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        gz.write_all(&body).unwrap();
+        let gz = gz.finish().unwrap();
+        total += gz.len();
+
+        let gz_path = out.join(format!("asset{i}.gz"));
+        std::fs::write(&gz_path, gz).unwrap();
+
+        let hash = fnv1a(&body);
+        let etag = format!("\"{hash:016x}\"");
+        let etag_gzip = format!("\"{hash:016x}-gz\"");
+        writeln!(
+            table,
+            "    crate::compress::Asset {{ path: {path:?}, content_type: {content_type:?}, \
+             identity: include_bytes!({:?}), gzip: include_bytes!({gz_path:?}), \
+             etag: {etag:?}, etag_gzip: {etag_gzip:?} }},",
+            dir.join(src),
+        )
+        .unwrap();
+    }
+    table.push(']');
+
+    assert!(
+        total <= UI_BUDGET,
+        "the UI assets are {total} bytes compressed, over the {UI_BUDGET} bytes budget"
+    );
+
+    std::fs::write(out.join("assets.rs"), table).unwrap();
 }
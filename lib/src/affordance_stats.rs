@@ -0,0 +1,94 @@
+//! Requests per affordance, served as JSON at `/debug/affordance-stats`.
+//!
+//! The routes counted are those of the TD forms, taken once at start-up
+//! with [`wot_esp_demo_core::affordance_stats::routes`]. Every request
+//! through [`crate::latency::LatencyLayer`] bumps the counter of its route,
+//! an atomic parallel to the route table, so counting allocates nothing.
+//! Routes of no affordance, e.g. the debug ones, are not counted, and
+//! routes are keyed without the [`crate::prefix`].
+//! `POST /debug/affordance-stats/reset` zeroes the counters:
+//!
+//! ```json
+//! {"/":{"GET":3},"/properties/on":{"GET":12,"PUT":0},..}
+//! ```
+//!
+//! An affordance never used since the last reset reads 0 and may be
+//! pruned.
+
+use core::cell::Cell;
+
+use portable_atomic::{AtomicU32, Ordering};
+
+use alloc::{string::String, vec::Vec};
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use picoserve::response::{IntoResponse, StatusCode};
+use serde_json::{Map, Value};
+
+use crate::{respond_json_negotiated, to_json_response, Accept, WriteGuard};
+
+/// Routes counted, those past it are not.
+pub const MAX_ROUTES: usize = 48;
+
+/// `(method, path)` of every counted route, the counter of each at the same
+/// index of [`COUNTS`].
+static ROUTES: CriticalSectionMutex<Cell<&'static [(&'static str, &'static str)]>> =
+    CriticalSectionMutex::new(Cell::new(&[]));
+
+static COUNTS: [AtomicU32; MAX_ROUTES] = [const { AtomicU32::new(0) }; MAX_ROUTES];
+
+/// Count the routes of the forms of the serialized TD `td` from now on.
+pub fn init(td: &str) {
+    let Ok(td) = serde_json::from_str::<Value>(td) else {
+        return;
+    };
+    let mut routes = wot_esp_demo_core::affordance_stats::routes(&td);
+    if routes.len() > MAX_ROUTES {
+        warn!(
+            "{} affordance routes, only the first {} are counted",
+            routes.len(),
+            MAX_ROUTES
+        );
+        routes.truncate(MAX_ROUTES);
+    }
+
+    let routes: Vec<(&'static str, &'static str)> = routes
+        .into_iter()
+        .map(|(method, path)| {
+            let path = String::from(crate::prefix::strip(&path));
+            (method, &*alloc::boxed::Box::leak(path.into_boxed_str()))
+        })
+        .collect();
+    let routes = alloc::boxed::Box::leak(routes.into_boxed_slice());
+    ROUTES.lock(|r| r.set(routes));
+}
+
+/// Count a `method` request to `path`, if it is a route of the TD.
+pub(crate) fn count(method: &str, path: &str) {
+    let routes = ROUTES.lock(Cell::get);
+    if let Some(i) = routes.iter().position(|(m, p)| *m == method && *p == path) {
+        COUNTS[i].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Handler for `GET /debug/affordance-stats`.
+pub(crate) async fn serve(accept: Accept) -> impl IntoResponse {
+    let mut report: Map<String, Value> = Map::new();
+    for ((method, path), count) in ROUTES.lock(Cell::get).iter().zip(&COUNTS) {
+        if let Value::Object(methods) = report
+            .entry(*path)
+            .or_insert_with(|| Value::Object(Map::new()))
+        {
+            methods.insert((*method).into(), count.load(Ordering::Relaxed).into());
+        }
+    }
+
+    respond_json_negotiated(accept, to_json_response(&report))
+}
+
+/// Handler for `POST /debug/affordance-stats/reset`.
+pub(crate) async fn reset(_guard: WriteGuard) -> impl IntoResponse {
+    for count in &COUNTS {
+        count.store(0, Ordering::Relaxed);
+    }
+    StatusCode::NO_CONTENT
+}
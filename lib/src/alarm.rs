@@ -0,0 +1,156 @@
+//! Threshold alarms on numeric properties.
+//!
+//! An [`Alarm`] watches a `Watch<f32>` fed by the measurement task and emits
+//! `{"state":"active"|"cleared","value":..}` events when the value crosses its
+//! writable threshold, with the hysteresis of
+//! [`wot_esp_demo_core::alarm::Trigger`].
+
+use core::cell::Cell;
+
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, CriticalSectionMutex},
+    watch::Watch,
+};
+use picoserve::{
+    response::{Response, StatusCode},
+    routing::{get, PathRouter},
+    Router,
+};
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use wot_esp_demo_core::alarm::Trigger;
+
+pub use wot_esp_demo_core::alarm::Direction;
+
+use crate::{
+    audit, respond_negotiated, write::InvalidValue, Accept, EventLog, EventStream, JsonBody,
+    LastEventId, Sequenced, SseEvents,
+};
+
+/// An alarm state change, sent as the alarm event data.
+#[derive(Clone, Copy, Debug)]
+pub struct AlarmEvent {
+    pub active: bool,
+    pub value: f32,
+}
+
+impl Serialize for AlarmEvent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let state = if self.active { "active" } else { "cleared" };
+        let mut event = serializer.serialize_struct("AlarmEvent", 2)?;
+        event.serialize_field("state", state)?;
+        event.serialize_field("value", &self.value)?;
+        event.end()
+    }
+}
+
+/// A threshold alarm, declared as a `static` next to its source `Watch`.
+///
+/// Run [`Self::run`] in a task and add its affordances with [`Self::routes`].
+pub struct Alarm {
+    threshold: CriticalSectionMutex<Cell<f32>>,
+    trigger: CriticalSectionMutex<Cell<Trigger>>,
+    watch: Watch<CriticalSectionRawMutex, Sequenced<AlarmEvent>, 2>,
+    log: EventLog<AlarmEvent>,
+}
+
+impl Alarm {
+    /// An alarm raised when the value is beyond `threshold` in `direction`,
+    /// cleared once it is back by more than `hysteresis`.
+    #[must_use]
+    pub const fn new(threshold: f32, direction: Direction, hysteresis: f32) -> Self {
+        Self {
+            threshold: CriticalSectionMutex::new(Cell::new(threshold)),
+            trigger: CriticalSectionMutex::new(Cell::new(Trigger::new(direction, hysteresis))),
+            watch: Watch::new(),
+            log: EventLog::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn threshold(&self) -> f32 {
+        self.threshold.lock(Cell::get)
+    }
+
+    /// Change the threshold; the next value is compared with it.
+    pub fn set_threshold(&self, threshold: f32) {
+        self.threshold.lock(|t| t.set(threshold));
+    }
+
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        self.trigger.lock(Cell::get).is_active()
+    }
+
+    /// Compare `value` with the threshold and emit an event on a state change.
+    pub fn update(&self, value: f32) {
+        let threshold = self.threshold();
+        let changed = self.trigger.lock(|trigger| {
+            let mut t = trigger.get();
+            let changed = t.update(threshold, value);
+            trigger.set(t);
+            changed
+        });
+
+        if let Some(active) = changed {
+            info!(
+                "Alarm {}: {}",
+                if active { "active" } else { "cleared" },
+                value
+            );
+            self.watch
+                .sender()
+                .send(self.log.record(AlarmEvent { active, value }));
+        }
+    }
+
+    /// Feed every value of `source` to [`Self::update`].
+    pub async fn run<const N: usize>(&self, source: &Watch<CriticalSectionRawMutex, f32, N>) -> ! {
+        let mut receiver = source.receiver().expect("alarm source receiver");
+        loop {
+            self.update(receiver.changed().await);
+        }
+    }
+
+    /// Serve the threshold as a writable number property at `threshold` and
+    /// the alarm event at `events`.
+    pub fn routes<S, R: PathRouter<S>>(
+        &'static self,
+        router: Router<R, S>,
+        threshold: &'static str,
+        events: &'static str,
+    ) -> Router<impl PathRouter<S>, S> {
+        router
+            .route(
+                threshold,
+                get(move |accept: Accept| async move {
+                    respond_negotiated(accept, &self.threshold())
+                })
+                .put(move |JsonBody::<f32>(value)| async move {
+                    if !value.is_finite() {
+                        return Err(InvalidValue("Threshold must be a finite number"));
+                    }
+                    self.set_threshold(value);
+                    audit::record(threshold.trim_start_matches("/properties/"), &value);
+                    Ok(StatusCode::NO_CONTENT)
+                }),
+            )
+            .route(
+                events,
+                get(move |LastEventId(last_event_id)| async move {
+                    // The watch serves a bounded number of concurrent subscribers.
+                    match self.watch.receiver() {
+                        Some(receiver) => Ok(EventStream(SseEvents::new(
+                            receiver,
+                            &self.log,
+                            last_event_id,
+                        ))),
+                        None => Err(Response::new(
+                            StatusCode::SERVICE_UNAVAILABLE,
+                            "Too many alarm subscribers.",
+                        )
+                        .with_header("Content-Type", "text/plain")),
+                    }
+                }),
+            )
+    }
+}
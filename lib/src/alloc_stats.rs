@@ -0,0 +1,81 @@
+//! Heap allocations per request, served as JSON at `/debug/allocations`
+//! (the `alloc-stats` feature, for debug builds).
+//!
+//! [`crate::latency::LatencyLayer`] reads the bytes allocated so far from
+//! the esp-alloc heap stats before and after each request it times, and
+//! counts the request per [`RouteClass`], as `allocating` if the heap grew
+//! in between. A scalar property read should never be `allocating`, see
+//! [`crate::inline`]. Several clients served at once blur the figures, as a
+//! request is charged the allocations of the others running meanwhile.
+//! `POST /debug/allocations/reset` zeroes them:
+//!
+//! ```json
+//! {"propertyRead":{"requests":41,"allocating":0,"bytes":0},..}
+//! ```
+
+use portable_atomic::{AtomicU32, Ordering};
+
+use alloc::string::String;
+use picoserve::response::{IntoResponse, StatusCode};
+use serde_json::{json, Map, Value};
+
+use crate::{latency::RouteClass, respond_json_negotiated, to_json_response, Accept, WriteGuard};
+
+/// Requests, allocating requests and bytes allocated, per class.
+struct Counters {
+    requests: AtomicU32,
+    allocating: AtomicU32,
+    bytes: AtomicU32,
+}
+
+static COUNTERS: [Counters; RouteClass::ALL.len()] = [const {
+    Counters {
+        requests: AtomicU32::new(0),
+        allocating: AtomicU32::new(0),
+        bytes: AtomicU32::new(0),
+    }
+}; RouteClass::ALL.len()];
+
+/// Bytes allocated on the heap since boot.
+pub(crate) fn allocated() -> usize {
+    esp_alloc::HEAP.stats().total_allocated
+}
+
+/// Count a request of `class`, started when [`allocated`] was `before`.
+pub(crate) fn record(class: RouteClass, before: usize) {
+    let bytes = u32::try_from(allocated().wrapping_sub(before)).unwrap_or(u32::MAX);
+    let counters = &COUNTERS[class as usize];
+    counters.requests.fetch_add(1, Ordering::Relaxed);
+    if bytes > 0 {
+        counters.allocating.fetch_add(1, Ordering::Relaxed);
+        counters.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+/// Handler for `GET /debug/allocations`.
+pub(crate) async fn serve(accept: Accept) -> impl IntoResponse {
+    let report: Map<String, Value> = RouteClass::ALL
+        .iter()
+        .zip(&COUNTERS)
+        .map(|(class, counters)| {
+            let counts = json!({
+                "requests": counters.requests.load(Ordering::Relaxed),
+                "allocating": counters.allocating.load(Ordering::Relaxed),
+                "bytes": counters.bytes.load(Ordering::Relaxed),
+            });
+            (class.as_str().into(), counts)
+        })
+        .collect();
+
+    respond_json_negotiated(accept, to_json_response(&report))
+}
+
+/// Handler for `POST /debug/allocations/reset`.
+pub(crate) async fn reset(_guard: WriteGuard) -> impl IntoResponse {
+    for counters in &COUNTERS {
+        counters.requests.store(0, Ordering::Relaxed);
+        counters.allocating.store(0, Ordering::Relaxed);
+        counters.bytes.store(0, Ordering::Relaxed);
+    }
+    StatusCode::NO_CONTENT
+}
@@ -0,0 +1,128 @@
+//! The last [`AUDIT_LEN`] property writes, kept in RAM.
+//!
+//! Writes through [`crate::properties!`] are recorded on their own; routes
+//! taking a [`crate::JsonBody`] by hand call [`record`] once the value is
+//! applied. The trail is served as JSON at `/debug/audit`, oldest first:
+//!
+//! ```json
+//! [{"property":"on","value":"false","at":10805123,"from":"192.0.2.7"}]
+//! ```
+//!
+//! `from` is the client of the connection the write came on: `serve_port`
+//! serves each connection through [`from_peer`], which holds its address in
+//! a cell while the connection is polled. A write made outside of a
+//! connection, e.g. by a rule, has `null`.
+
+use core::{
+    cell::{Cell, RefCell},
+    future::{poll_fn, Future},
+    pin::pin,
+};
+
+use alloc::format;
+use embassy_net::IpAddress;
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use embassy_time::Instant;
+use picoserve::response::IntoResponse;
+use serde_json::{json, Value};
+
+use crate::{respond_json_negotiated, to_json_response, Accept};
+
+/// Writes kept, the oldest dropped first.
+pub const AUDIT_LEN: usize = 32;
+
+/// Longest property name kept; longer names and values are cut and end
+/// with `…`.
+const NAME_LEN: usize = 24;
+
+/// Longest value kept, as JSON text.
+const VALUE_LEN: usize = 32;
+
+struct Entry {
+    property: heapless::String<NAME_LEN>,
+    value: heapless::String<VALUE_LEN>,
+    /// Uptime in milliseconds.
+    at: u64,
+    /// The client that made the write.
+    from: Option<IpAddress>,
+}
+
+/// The client of the connection being polled, see [`from_peer`].
+static PEER: CriticalSectionMutex<Cell<Option<IpAddress>>> =
+    CriticalSectionMutex::new(Cell::new(None));
+
+/// Run `serve`, the serving of a connection from `peer`, with `peer` as the
+/// client of the writes it records.
+///
+/// The web tasks take turns on one executor, so the cell is set around each
+/// poll of `serve` and cleared after it: the writes of the other connections
+/// never see it.
+pub(crate) async fn from_peer<F: Future>(peer: IpAddress, serve: F) -> F::Output {
+    let mut serve = pin!(serve);
+    poll_fn(|cx| {
+        PEER.lock(|cell| cell.set(Some(peer)));
+        let poll = serve.as_mut().poll(cx);
+        PEER.lock(|cell| cell.set(None));
+        poll
+    })
+    .await
+}
+
+static TRAIL: CriticalSectionMutex<RefCell<heapless::Deque<Entry, AUDIT_LEN>>> =
+    CriticalSectionMutex::new(RefCell::new(heapless::Deque::new()));
+
+/// `s` as is if it fits in `N` bytes, otherwise cut to end with `…`.
+fn truncate<const N: usize>(s: &str) -> heapless::String<N> {
+    let mut out = heapless::String::new();
+    if out.push_str(s).is_ok() {
+        return out;
+    }
+    for c in s.chars() {
+        if out.len() + c.len_utf8() + '…'.len_utf8() > N {
+            break;
+        }
+        let _ = out.push(c);
+    }
+    let _ = out.push('…');
+    out
+}
+
+/// Record that `property` was set to `value`, by the client of the
+/// connection being served if any.
+pub fn record(property: &str, value: &impl serde::Serialize) {
+    let value = serde_json::to_string(value).unwrap_or_default();
+    let entry = Entry {
+        property: truncate(property),
+        value: truncate(&value),
+        at: Instant::now().as_millis(),
+        from: PEER.lock(Cell::get),
+    };
+
+    TRAIL.lock(|trail| {
+        let mut trail = trail.borrow_mut();
+        if trail.is_full() {
+            trail.pop_front();
+        }
+        let _ = trail.push_back(entry);
+    });
+}
+
+/// Handler for `GET /debug/audit`.
+pub async fn serve(accept: Accept) -> impl IntoResponse {
+    let trail: Value = TRAIL.lock(|trail| {
+        trail
+            .borrow()
+            .iter()
+            .map(|e| {
+                json!({
+                    "property": e.property.as_str(),
+                    "value": e.value.as_str(),
+                    "at": e.at,
+                    "from": e.from.map(|peer| format!("{peer}")),
+                })
+            })
+            .collect()
+    });
+
+    respond_json_negotiated(accept, to_json_response(&trail))
+}
@@ -0,0 +1,119 @@
+//! `POST /batch`: several property reads and writes in one request, for a
+//! gateway saving radio airtime.
+//!
+//! Every [`crate::properties!`] registers a dispatcher running the same
+//! `get`, `validate` and `put` as its routes, so a Thing gets batches for
+//! its properties with no code of its own. The operations run one after the
+//! other, each through the first dispatcher knowing its property, and the
+//! outcomes are bounded to [`HTTP_BUFFER`], see
+//! [`wot_esp_demo_core::batch`]:
+//!
+//! ```text
+//! $ curl -X POST -d '[{"op":"read","name":"on"},{"op":"write","name":"brightness","value":300}]' http://<ip>/batch
+//! [{"status":200,"value":true},{"status":400,"error":"invalid value: integer `300`, expected u8"}]
+//! ```
+//!
+//! Properties served by routes written by hand, e.g. `network`, are not
+//! reachable and get a 404 entry.
+
+use core::{any::Any, cell::RefCell, future::Future, pin::Pin};
+
+use alloc::{boxed::Box, string::ToString, vec::Vec};
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use picoserve::response::{IntoResponse, Response, StatusCode};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use wot_esp_demo_core::body;
+
+pub use wot_esp_demo_core::batch::{Op, Outcome, MAX_BATCH_BODY, MAX_OPS};
+
+use crate::HTTP_BUFFER;
+
+/// The outcome of an [`Op`] on a property of the dispatcher, `None` for
+/// another property.
+pub type Pending<'a> = Pin<Box<dyn Future<Output = Option<Outcome>> + 'a>>;
+
+/// Run an [`Op`] against the app state, given as `&dyn Any`.
+pub type Dispatch = for<'a> fn(&'a dyn Any, &'a Op) -> Pending<'a>;
+
+/// Dispatchers kept, one per [`crate::properties!`] of the app.
+const MAX_DISPATCHERS: usize = 4;
+
+static DISPATCHERS: CriticalSectionMutex<RefCell<heapless::Vec<Dispatch, MAX_DISPATCHERS>>> =
+    CriticalSectionMutex::new(RefCell::new(heapless::Vec::new()));
+
+/// Add `dispatch` for the batches, once; called by [`crate::properties!`].
+#[doc(hidden)]
+pub fn register(dispatch: Dispatch) {
+    DISPATCHERS.lock(|dispatchers| {
+        let mut dispatchers = dispatchers.borrow_mut();
+        if dispatchers
+            .iter()
+            .any(|known| core::ptr::fn_addr_eq(*known, dispatch))
+        {
+            return;
+        }
+        if dispatchers.push(dispatch).is_err() {
+            warn!(
+                "More than {} properties! blocks, the last is left out of batches",
+                MAX_DISPATCHERS
+            );
+        }
+    });
+}
+
+/// `outcome` as the [`Pending`] of a [`Dispatch`].
+#[doc(hidden)]
+pub fn pending<'a>(outcome: impl Future<Output = Option<Outcome>> + 'a) -> Pending<'a> {
+    Box::pin(outcome)
+}
+
+/// The outcome of a read of `value`.
+#[doc(hidden)]
+pub fn read(value: &impl Serialize) -> Outcome {
+    Outcome::read(serde_json::to_value(value).unwrap_or(Value::Null))
+}
+
+/// The value of a write as `T`, or the outcome refusing it.
+#[doc(hidden)]
+pub fn value<T: DeserializeOwned>(value: &Value) -> Result<T, Outcome> {
+    T::deserialize(value).map_err(|e| Outcome::invalid(&e))
+}
+
+/// Run `op` through the first dispatcher knowing its property.
+async fn run(dispatchers: &[Dispatch], state: &dyn Any, op: &Op) -> Outcome {
+    for dispatch in dispatchers {
+        if let Some(outcome) = dispatch(state, op).await {
+            return outcome;
+        }
+    }
+    Outcome::unknown()
+}
+
+/// Handler for `POST /batch` on `state` with the JSON `body`: the outcomes
+/// of its operations, or 400 if it is not an array of at most [`MAX_OPS`].
+pub(crate) async fn serve<S: Any>(state: &S, body: Value) -> impl IntoResponse {
+    let ops = match wot_esp_demo_core::batch::parse(body) {
+        Ok(ops) => ops,
+        Err(error) => {
+            return Err(
+                Response::new(StatusCode::BAD_REQUEST, body::error(&error).to_string())
+                    .with_header("Content-Type", "application/json"),
+            )
+        }
+    };
+
+    let dispatchers = DISPATCHERS.lock(|dispatchers| dispatchers.borrow().clone());
+    let mut outcomes = Vec::with_capacity(ops.len());
+    for op in &ops {
+        outcomes.push(match op {
+            Ok(op) => run(&dispatchers, state, op).await,
+            Err(error) => Outcome::invalid(error),
+        });
+    }
+
+    Ok(
+        Response::ok(wot_esp_demo_core::batch::respond(&outcomes, HTTP_BUFFER))
+            .with_header("Content-Type", "application/json"),
+    )
+}
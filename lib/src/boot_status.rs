@@ -0,0 +1,56 @@
+//! The boot phase of the Thing, published by [`crate::EspThing::run`].
+//!
+//! Anything may watch [`BOOT_STATUS`], e.g. a display. Binaries with a spare
+//! LED return it from [`crate::EspThingState::status_led`] to have it blink
+//! the pattern of each phase until the Thing is running.
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, watch::Watch};
+use embassy_time::Timer;
+
+pub use wot_esp_demo_core::boot_status::BootStatus;
+
+/// The current boot phase. The status LED takes one receiver, two are left.
+pub static BOOT_STATUS: Watch<CriticalSectionRawMutex, BootStatus, 3> = Watch::new();
+
+/// An LED the boot phases can be shown on.
+pub trait StatusLed {
+    fn set(&self, on: bool);
+}
+
+pub(crate) fn publish(status: BootStatus) {
+    info!("Boot status: {}", status.as_str());
+    BOOT_STATUS.sender().send(status);
+}
+
+/// Blink the pattern of the current boot phase on `led`, see
+/// [`BootStatus::blink_ms`].
+#[embassy_executor::task]
+pub(crate) async fn status_led_task(led: &'static dyn StatusLed) {
+    let Some(mut receiver) = BOOT_STATUS.receiver() else {
+        warn!("No receiver left for the status LED");
+        return;
+    };
+
+    let mut status = receiver.get().await;
+    loop {
+        let blink = status.blink_ms();
+        if blink.is_empty() {
+            led.set(false);
+            status = receiver.changed().await;
+            continue;
+        }
+
+        let play = async {
+            loop {
+                for (i, &ms) in blink.iter().enumerate() {
+                    led.set(i % 2 == 0);
+                    Timer::after_millis(u64::from(ms)).await;
+                }
+            }
+        };
+        if let Either::Second(next) = select(play, receiver.changed()).await {
+            status = next;
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Why the chip last reset, and how many brown-outs it went through, served
+//! at `/debug/boot`.
+//!
+//! The count lives in RTC fast memory, which keeps its content through every
+//! reset but a power-on: it is zeroed on power-on and bumped on each
+//! brown-out reset, e.g. a relay coil pulling the supply down.
+//!
+//! ```json
+//! {"brownOuts":2,"resetReason":"SysBrownOut"}
+//! ```
+//!
+//! On an ESP32-C3 (the `esp32c3` feature) [`init`] also arms the detector
+//! itself, as ESP-IDF's `esp_brownout_init`: it trips below the threshold of
+//! `BROWNOUT_LEVEL`, see [`wot_esp_demo_core::brownout`], powers down the
+//! flash and the radio and resets the chip. Elsewhere the detector keeps the
+//! configuration the chip came up with.
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use esp_hal::{rtc_cntl::SocResetReason, system::Cpu};
+use picoserve::response::IntoResponse;
+use serde_json::json;
+
+#[cfg(feature = "esp32c3")]
+use wot_esp_demo_core::brownout;
+
+#[cfg(feature = "esp32c3")]
+use crate::config;
+use crate::{respond_json_negotiated, to_json_response, Accept};
+
+/// Marks [`BROWN_OUTS`] as counted since a power-on rather than left over
+/// from whatever the memory held.
+const MAGIC: u32 = 0x426f_4453;
+
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut COUNTED: u32 = 0;
+
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut BROWN_OUTS: u32 = 0;
+
+/// Name of the last reset reason, set by [`init`].
+static REASON: CriticalSectionMutex<Cell<&'static str>> =
+    CriticalSectionMutex::new(Cell::new("unknown"));
+
+/// The I2C block of the analog circuits holding the detector's threshold.
+#[cfg(feature = "esp32c3")]
+const I2C_ULP_CAL: u8 = 0x61;
+
+/// Register of [`I2C_ULP_CAL`] with the threshold in its bits 2..0.
+#[cfg(feature = "esp32c3")]
+const I2C_ULP_IR_FORCE: u8 = 5;
+
+#[cfg(feature = "esp32c3")]
+unsafe extern "C" {
+    fn esp_rom_regi2c_read(block: u8, block_hostid: u8, reg_add: u8) -> u8;
+    fn rom_i2c_writeReg(block: u8, block_hostid: u8, reg_add: u8, indata: u8);
+}
+
+/// Arm the detector at `level`, resetting the chip when it trips.
+#[cfg(feature = "esp32c3")]
+fn configure(level: u8) {
+    // The analog registers go through the ROM, as in esp-hal's `regi2c`.
+    unsafe {
+        let reg = esp_rom_regi2c_read(I2C_ULP_CAL, 0, I2C_ULP_IR_FORCE);
+        rom_i2c_writeReg(I2C_ULP_CAL, 0, I2C_ULP_IR_FORCE, (reg & !0b111) | level);
+    }
+
+    let rtc_cntl = esp_hal::peripherals::LPWR::regs();
+    // Pulse the counter clear, then enable with the reset after 0x3ff cycles
+    // of the slow clock, as `brownout_hal_config`.
+    rtc_cntl.brown_out().modify(|_, w| w.cnt_clr().set_bit());
+    rtc_cntl.brown_out().write(|w| unsafe {
+        w.int_wait().bits(2);
+        w.close_flash_ena().set_bit();
+        w.pd_rf_ena().set_bit();
+        w.rst_wait().bits(0x3ff);
+        w.rst_ena().set_bit();
+        w.rst_sel().set_bit();
+        w.ena().set_bit()
+    });
+}
+
+/// Read the reset reason and count a brown-out, and arm the detector; call
+/// once at start-up.
+pub fn init() {
+    #[cfg(feature = "esp32c3")]
+    let level = {
+        let level = brownout::level(config::BROWNOUT_LEVEL);
+        configure(level);
+        level
+    };
+
+    let reason = esp_hal::rtc_cntl::reset_reason(Cpu::ProCpu);
+
+    // Only the start-up code touches the counters, before any task runs.
+    let brown_outs = unsafe {
+        if COUNTED != MAGIC || reason == Some(SocResetReason::ChipPowerOn) {
+            COUNTED = MAGIC;
+            BROWN_OUTS = 0;
+        }
+        if reason == Some(SocResetReason::SysBrownOut) {
+            BROWN_OUTS = BROWN_OUTS.wrapping_add(1);
+        }
+        BROWN_OUTS
+    };
+
+    let name = match reason {
+        Some(reason) => &*alloc::boxed::Box::leak(alloc::format!("{reason:?}").into_boxed_str()),
+        None => "unknown",
+    };
+    REASON.lock(|r| r.set(name));
+
+    if reason == Some(SocResetReason::SysBrownOut) {
+        warn!("Reset by a brown-out, {} since power-on", brown_outs);
+    } else {
+        info!("Reset reason: {}", name);
+    }
+    #[cfg(feature = "esp32c3")]
+    info!(
+        "Brown-out reset below {} mV",
+        brownout::threshold_mv(level).unwrap_or_default()
+    );
+}
+
+/// Brown-out resets since the last power-on.
+#[must_use]
+pub fn brown_outs() -> u32 {
+    // A plain read of a value only written by `init`.
+    unsafe { BROWN_OUTS }
+}
+
+/// Handler for `GET /debug/boot`.
+pub(crate) async fn serve(accept: Accept) -> impl IntoResponse {
+    let report = json!({
+        "resetReason": REASON.lock(Cell::get),
+        "brownOuts": brown_outs(),
+    });
+    respond_json_negotiated(accept, to_json_response(&report))
+}
@@ -0,0 +1,139 @@
+//! Sensor values sampled by a task and served from its last capture.
+//!
+//! A [`Cached`] value carries when it was captured and the oldest it may be
+//! served, e.g. three sampling intervals. [`respond_cached_result`] serves it
+//! with `Age: <seconds>`, or with 503 and `Retry-After` once it is older than
+//! that, e.g. because the sampling task is wedged, see
+//! [`wot_esp_demo_core::freshness`]:
+//!
+//! ```text
+//! HTTP/1.1 503 Service Unavailable
+//! Retry-After: 3
+//!
+//! Stale value, 42 s old
+//! ```
+
+use alloc::format;
+
+use embassy_time::{Duration, Instant};
+use picoserve::{
+    io::Read,
+    response::{Body, Connection, HeadersIter, IntoResponse, Response, ResponseWriter, StatusCode},
+    ResponseSent,
+};
+
+pub use wot_esp_demo_core::freshness::Freshness;
+
+use crate::{respond_negotiated, Accept};
+
+/// A sampled value, when it was captured and how old it may be served.
+#[derive(Clone, Copy, Debug)]
+pub struct Cached<T> {
+    pub value: T,
+    pub captured: Instant,
+    /// Oldest the value is served.
+    pub max_age: Duration,
+}
+
+impl<T> Cached<T> {
+    /// `value`, captured now.
+    #[must_use]
+    pub fn new(value: T, max_age: Duration) -> Self {
+        Self {
+            value,
+            captured: Instant::now(),
+            max_age,
+        }
+    }
+
+    /// The value `f` derives from this one, captured at the same time.
+    #[must_use]
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Cached<U> {
+        Cached {
+            value: f(self.value),
+            captured: self.captured,
+            max_age: self.max_age,
+        }
+    }
+
+    /// Whether the value may still be served, and its age.
+    #[must_use]
+    pub fn freshness(&self) -> Freshness {
+        Freshness::of(
+            self.captured.elapsed().as_millis(),
+            self.max_age.as_millis(),
+        )
+    }
+}
+
+/// [`respond_negotiated`] with the `Ok` value and its `Age`, or HTTP 503
+/// with `Retry-After` if it is stale; HTTP 500 with `err_msg` on `Err`, as
+/// [`crate::respond_negotiated_result`] does.
+pub fn respond_cached_result<T: serde::Serialize, E>(
+    accept: Accept,
+    result: Result<Cached<T>, E>,
+    err_msg: &'static str,
+) -> impl IntoResponse {
+    let Ok(cached) = result else {
+        return Err(Err(Response::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            err_msg,
+        )
+        .with_header("Content-Type", "text/plain")));
+    };
+
+    match cached.freshness() {
+        Freshness::Fresh { age_s } => Ok(WithAge {
+            age_s,
+            response: respond_negotiated(accept, &cached.value),
+        }),
+        Freshness::Stale {
+            age_s,
+            retry_after_s,
+        } => Err(Ok(Response::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("Stale value, {age_s} s old"),
+        )
+        .with_header("Content-Type", "text/plain")
+        .with_header("Retry-After", retry_after_s))),
+    }
+}
+
+/// `response` with an `Age` header.
+struct WithAge<R> {
+    age_s: u64,
+    response: R,
+}
+
+impl<R: IntoResponse> IntoResponse for WithAge<R> {
+    async fn write_to<Rd: Read, W: ResponseWriter<Error = Rd::Error>>(
+        self,
+        connection: Connection<'_, Rd>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        let response_writer = AgeResponseWriter {
+            age_s: self.age_s,
+            response_writer,
+        };
+        self.response.write_to(connection, response_writer).await
+    }
+}
+
+struct AgeResponseWriter<W> {
+    age_s: u64,
+    response_writer: W,
+}
+
+impl<W: ResponseWriter> ResponseWriter for AgeResponseWriter<W> {
+    type Error = W::Error;
+
+    async fn write_response<R: Read<Error = Self::Error>, H: HeadersIter, B: Body>(
+        self,
+        connection: Connection<'_, R>,
+        response: Response<H, B>,
+    ) -> Result<ResponseSent, Self::Error> {
+        self.response_writer
+            .write_response(connection, response.with_header("Age", self.age_s))
+            .await
+    }
+}
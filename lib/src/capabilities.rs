@@ -0,0 +1,115 @@
+//! Optional hardware, probed for at start-up.
+//!
+//! A Thing lists the hardware its board may lack in
+//! [`crate::EspThing::OPTIONAL_HARDWARE`], and its state probes for it in
+//! [`crate::EspThingState::new`], e.g. an I2C device answering its address,
+//! and reports it with [`crate::EspThingState::detected`]. The TD then only
+//! has the affordances of the hardware found, with the read-only
+//! `capabilities` property listing it:
+//!
+//! ```json
+//! ["shtc3"]
+//! ```
+//!
+//! Add [`CapabilitiesLayer`] in `build_app` to answer 404 on the routes of
+//! the hardware missing, rather than failing to read it.
+
+use core::cell::Cell;
+
+use alloc::{string::String, vec::Vec};
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use picoserve::{
+    io::Read,
+    request::RequestParts,
+    response::{IntoResponse, Response, ResponseWriter, StatusCode},
+    routing::{Layer, Next},
+    ResponseSent,
+};
+use serde_json::{Map, Value};
+
+pub use wot_esp_demo_core::capabilities::Hardware;
+
+use crate::{error::ThingError, respond_negotiated, Accept};
+
+/// Names of the hardware found.
+static DETECTED: CriticalSectionMutex<Cell<&'static [&'static str]>> =
+    CriticalSectionMutex::new(Cell::new(&[]));
+
+/// Paths of the affordances of the hardware missing.
+static ABSENT: CriticalSectionMutex<Cell<&'static [String]>> =
+    CriticalSectionMutex::new(Cell::new(&[]));
+
+/// Leave out of the serialized TD `td` the affordances of the `hardware`
+/// not `detected`, and add the `capabilities` property.
+///
+/// `td` is returned as is for a Thing without optional hardware.
+pub(crate) fn restrict(
+    td: String,
+    hardware: &[Hardware],
+    detected: impl Fn(&str) -> bool,
+) -> Result<String, ThingError> {
+    if hardware.is_empty() {
+        return Ok(td);
+    }
+
+    let mut found = Vec::new();
+    for h in hardware {
+        if detected(h.name) {
+            info!("Found {}", h.name);
+            found.push(h.name);
+        } else {
+            warn!("No {}, its affordances are left out of the TD", h.name);
+        }
+    }
+
+    let mut td: Map<String, Value> = serde_json::from_str(&td)?;
+    let absent = wot_esp_demo_core::capabilities::restrict(&mut td, hardware, &found);
+
+    DETECTED.lock(|d| d.set(alloc::boxed::Box::leak(found.into_boxed_slice())));
+    ABSENT.lock(|a| a.set(alloc::boxed::Box::leak(absent.into_boxed_slice())));
+
+    Ok(serde_json::to_string(&td)?)
+}
+
+/// Whether `path` belongs to hardware missing.
+#[must_use]
+pub fn is_absent(path: &str) -> bool {
+    ABSENT.lock(Cell::get).iter().any(|p| p == path)
+}
+
+/// Handler for `GET /properties/capabilities`.
+pub(crate) async fn serve(accept: Accept) -> impl IntoResponse {
+    respond_negotiated(accept, &DETECTED.lock(Cell::get))
+}
+
+/// Answers 404 on the routes of the hardware missing.
+pub struct CapabilitiesLayer;
+
+impl<State, PathParameters> Layer<State, PathParameters> for CapabilitiesLayer {
+    type NextState = State;
+    type NextPathParameters = PathParameters;
+
+    async fn call_layer<
+        'a,
+        R: Read + 'a,
+        NextLayer: Next<'a, R, Self::NextState, Self::NextPathParameters>,
+        W: ResponseWriter<Error = R::Error>,
+    >(
+        &self,
+        next: NextLayer,
+        state: &State,
+        path_parameters: PathParameters,
+        request_parts: RequestParts<'_>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        if !is_absent(crate::prefix::strip(request_parts.path().encoded())) {
+            return next.run(state, path_parameters, response_writer).await;
+        }
+
+        let connection = next.into_connection();
+        Response::new(StatusCode::NOT_FOUND, "Hardware not present")
+            .with_header("Content-Type", "text/plain")
+            .write_to(connection, response_writer)
+            .await
+    }
+}
@@ -0,0 +1,42 @@
+//! DNS responder answering every `A` query with the maintenance AP address.
+//!
+//! Phones joining the maintenance network resolve their connectivity-check
+//! hosts to the Thing, get redirected to the status page and offer to open it
+//! as a captive portal. Queries are answered in place in fixed buffers.
+
+use core::net::Ipv4Addr;
+
+use embassy_net::{
+    udp::{PacketMetadata, UdpSocket},
+    Stack,
+};
+pub use wot_esp_demo_core::captive_dns::answer;
+
+#[embassy_executor::task]
+pub async fn dns_task(stack: Stack<'static>, ip: Ipv4Addr) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0; 1024];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buffer = [0; 1024];
+
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    socket.bind(53).unwrap();
+
+    let mut query = [0; 512];
+    let mut response = [0; 512];
+
+    loop {
+        let Ok((len, meta)) = socket.recv_from(&mut query).await else {
+            continue;
+        };
+        if let Some(len) = answer(&query[..len], &mut response, ip) {
+            let _ = socket.send_to(&response[..len], meta.endpoint).await;
+        }
+    }
+}
@@ -0,0 +1,29 @@
+//! Wall-clock time, once [`crate::sntp`] has set it.
+//!
+//! The clock is kept as the Unix time at boot: [`unix_ms`] adds the uptime to
+//! it, so it keeps running between two synchronizations.
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use embassy_time::Instant;
+
+/// Milliseconds since the Unix epoch at boot, `None` until set.
+static BOOT_MS: CriticalSectionMutex<Cell<Option<u64>>> =
+    CriticalSectionMutex::new(Cell::new(None));
+
+/// The time, in milliseconds since the Unix epoch; `None` until it is set,
+/// and for good without the `sntp` feature.
+#[must_use]
+pub fn unix_ms() -> Option<u64> {
+    BOOT_MS
+        .lock(Cell::get)
+        .map(|boot| boot + Instant::now().as_millis())
+}
+
+/// Set the time to `now_ms`, milliseconds since the Unix epoch.
+#[cfg(feature = "sntp")]
+pub(crate) fn set(now_ms: u64) {
+    let boot = now_ms.saturating_sub(Instant::now().as_millis());
+    BOOT_MS.lock(|b| b.set(Some(boot)));
+}
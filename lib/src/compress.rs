@@ -0,0 +1,178 @@
+//! Gzip content negotiation for the TD and the static assets.
+//!
+//! Static assets are compressed by the build script, the TD once at startup.
+//! Property values are too small to be worth compressing.
+
+use alloc::vec::Vec;
+
+use picoserve::response::{IntoResponse, Response, StatusCode};
+pub use wot_esp_demo_core::negotiate::{accepts_encoding, etag_matches};
+
+/// `Cache-Control` of the static assets: their ETag changes with their
+/// content, so a day-old copy is still current.
+pub const ASSET_CACHE_CONTROL: &str = "max-age=86400, immutable";
+
+/// A static asset embedded in the firmware, listed by the build script.
+pub struct Asset {
+    pub path: &'static str,
+    pub content_type: &'static str,
+    pub identity: &'static [u8],
+    pub gzip: &'static [u8],
+    /// Strong ETag of `identity`, a hash taken at build time.
+    pub etag: &'static str,
+    /// Strong ETag of `gzip`.
+    pub etag_gzip: &'static str,
+}
+
+/// Whether the client sent `Accept-Encoding: gzip`.
+#[derive(Clone, Copy)]
+pub struct AcceptEncoding {
+    gzip: bool,
+}
+
+impl AcceptEncoding {
+    #[must_use]
+    pub fn gzip(&self) -> bool {
+        self.gzip
+    }
+}
+
+impl<'r, S> picoserve::extract::FromRequestParts<'r, S> for AcceptEncoding {
+    type Rejection = core::convert::Infallible;
+
+    async fn from_request_parts(
+        _state: &'r S,
+        request_parts: &picoserve::request::RequestParts<'r>,
+    ) -> Result<Self, Self::Rejection> {
+        let gzip = request_parts
+            .headers()
+            .get("Accept-Encoding")
+            .and_then(|v| v.as_str().ok())
+            .is_some_and(|v| accepts_encoding(v, "gzip"));
+
+        Ok(Self { gzip })
+    }
+}
+
+/// The `If-None-Match` request header, `None` when absent or too long to
+/// keep.
+pub struct IfNoneMatch(pub Option<heapless::String<128>>);
+
+impl IfNoneMatch {
+    /// Whether the client's copy has `etag`.
+    #[must_use]
+    pub fn matches(&self, etag: &str) -> bool {
+        self.0
+            .as_ref()
+            .is_some_and(|header| etag_matches(header, etag))
+    }
+}
+
+impl<'r, S> picoserve::extract::FromRequestParts<'r, S> for IfNoneMatch {
+    type Rejection = core::convert::Infallible;
+
+    async fn from_request_parts(
+        _state: &'r S,
+        request_parts: &picoserve::request::RequestParts<'r>,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(Self(
+            request_parts
+                .headers()
+                .get("If-None-Match")
+                .and_then(|v| v.as_str().ok())
+                .and_then(|v| heapless::String::try_from(v).ok()),
+        ))
+    }
+}
+
+/// Respond with `gzip` when the client accepts it and it is available, with
+/// `identity` otherwise.
+///
+/// Both variants carry `Vary: Accept-Encoding` so caches keep them apart, and
+/// `cache_control` as `Cache-Control`.
+pub fn precompressed(
+    accept_encoding: AcceptEncoding,
+    content_type: &'static str,
+    cache_control: &'static str,
+    identity: &'static [u8],
+    gzip: &'static [u8],
+) -> impl IntoResponse {
+    if accept_encoding.gzip() && !gzip.is_empty() {
+        Ok(Response::ok(gzip)
+            .with_header("Content-Type", content_type)
+            .with_header("Content-Encoding", "gzip")
+            .with_header("Vary", "Accept-Encoding")
+            .with_header("Cache-Control", cache_control))
+    } else {
+        Err(Response::ok(identity)
+            .with_header("Content-Type", content_type)
+            .with_header("Vary", "Accept-Encoding")
+            .with_header("Cache-Control", cache_control))
+    }
+}
+
+/// Serve `asset` like [`precompressed`], with its ETag and
+/// [`ASSET_CACHE_CONTROL`], or answer 304 when the client's copy is current.
+pub fn asset(
+    asset: &'static Asset,
+    accept_encoding: AcceptEncoding,
+    if_none_match: &IfNoneMatch,
+) -> impl IntoResponse {
+    let gzip = accept_encoding.gzip() && !asset.gzip.is_empty();
+    let etag = if gzip { asset.etag_gzip } else { asset.etag };
+
+    if if_none_match.matches(etag) {
+        return Err(Response::new(StatusCode::NOT_MODIFIED, "")
+            .with_header("ETag", etag)
+            .with_header("Vary", "Accept-Encoding")
+            .with_header("Cache-Control", ASSET_CACHE_CONTROL));
+    }
+
+    Ok(if gzip {
+        Ok(Response::ok(asset.gzip)
+            .with_header("Content-Type", asset.content_type)
+            .with_header("Content-Encoding", "gzip")
+            .with_header("Vary", "Accept-Encoding")
+            .with_header("Cache-Control", ASSET_CACHE_CONTROL)
+            .with_header("ETag", etag))
+    } else {
+        Err(Response::ok(asset.identity)
+            .with_header("Content-Type", asset.content_type)
+            .with_header("Vary", "Accept-Encoding")
+            .with_header("Cache-Control", ASSET_CACHE_CONTROL)
+            .with_header("ETag", etag))
+    })
+}
+
+/// CRC-32 (IEEE) as required by the gzip trailer.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Compress `data` into a gzip member.
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    // Magic, deflate, no flags, no mtime, max compression, unknown OS.
+    const HEADER: [u8; 10] = [0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 2, 0xff];
+
+    let deflated = miniz_oxide::deflate::compress_to_vec(data, 9);
+
+    let mut out = Vec::with_capacity(HEADER.len() + deflated.len() + 8);
+    out.extend_from_slice(&HEADER);
+    out.extend_from_slice(&deflated);
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
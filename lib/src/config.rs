@@ -0,0 +1,265 @@
+//! Every setting baked in from the environment at build time, read here and
+//! nowhere else.
+//!
+//! The Wi-Fi credentials are checked while building: an `SSID_2` of 33
+//! bytes fails the build with `SSID_2 must be 1 to 32 bytes long`. The other
+//! settings are checked by [`validate`] before the Thing brings anything up;
+//! each one rejected is logged with the reason:
+//!
+//! ```text
+//! Invalid SYSLOG_PORT: must be a port from 1 to 65535
+//! ```
+//!
+//! A new setting gets its const below and, unless it can be checked at
+//! build time, an entry in [`SETTINGS`]; its check goes in
+//! [`wot_esp_demo_core::config`], with a host test.
+
+use wot_esp_demo_core::config::{self, problems, Setting};
+
+/// The credentials of a network as set, see [`crate::wifi`].
+#[derive(Clone, Copy)]
+pub struct Network {
+    pub ssid: Option<&'static str>,
+    pub password: Option<&'static str>,
+    pub hidden: Option<&'static str>,
+    pub eap_identity: Option<&'static str>,
+    pub eap_username: Option<&'static str>,
+}
+
+/// Read the credentials of the network with `$suffix` and check them,
+/// failing the build with the name of the setting rejected.
+macro_rules! network {
+    ($suffix:literal) => {{
+        let network = Network {
+            ssid: option_env!(concat!("SSID", $suffix)),
+            password: option_env!(concat!("PASSWORD", $suffix)),
+            hidden: option_env!(concat!("SSID", $suffix, "_HIDDEN")),
+            eap_identity: option_env!(concat!("EAP_IDENTITY", $suffix)),
+            eap_username: option_env!(concat!("EAP_USERNAME", $suffix)),
+        };
+
+        if let Some(ssid) = network.ssid {
+            assert!(
+                config::ssid(ssid).is_ok(),
+                concat!("SSID", $suffix, " must be 1 to 32 bytes long")
+            );
+        }
+        if let Some(password) = network.password {
+            if network.eap_identity.is_none() {
+                assert!(
+                    config::passphrase(password).is_ok(),
+                    concat!(
+                        "PASSWORD",
+                        $suffix,
+                        " must be 8 to 63 printable ASCII characters, or 64 hex digits"
+                    )
+                );
+            } else {
+                assert!(
+                    config::eap(password).is_ok(),
+                    concat!("PASSWORD", $suffix, " must be 1 to 128 bytes long")
+                );
+            }
+        }
+        if let Some(identity) = network.eap_identity {
+            assert!(
+                config::eap(identity).is_ok(),
+                concat!("EAP_IDENTITY", $suffix, " must be 1 to 128 bytes long")
+            );
+        }
+        if let Some(username) = network.eap_username {
+            assert!(
+                config::eap(username).is_ok(),
+                concat!("EAP_USERNAME", $suffix, " must be 1 to 128 bytes long")
+            );
+        }
+
+        network
+    }};
+}
+
+/// `SSID`/`PASSWORD` first, then `SSID_1`/`PASSWORD_1` up to
+/// `SSID_4`/`PASSWORD_4`.
+pub const NETWORKS: [Network; 5] = [
+    network!(""),
+    network!("_1"),
+    network!("_2"),
+    network!("_3"),
+    network!("_4"),
+];
+
+/// WPA2 passphrase of the maintenance network.
+#[cfg(feature = "maintenance-ap")]
+pub const AP_PASSPHRASE: &str = env!("AP_PASSPHRASE");
+
+#[cfg(feature = "maintenance-ap")]
+const _: () = assert!(
+    !AP_PASSPHRASE.is_empty() && config::passphrase(AP_PASSPHRASE).is_ok(),
+    "AP_PASSPHRASE must be 8 to 63 printable ASCII characters, or 64 hex digits"
+);
+
+pub const WIFI_COUNTRY: Option<&str> = option_env!("WIFI_COUNTRY");
+pub const WIFI_MAX_TX_POWER: Option<&str> = option_env!("WIFI_MAX_TX_POWER");
+pub const SUPPORT_URL: Option<&str> = option_env!("SUPPORT_URL");
+pub const PUBLIC_BASE_URL: Option<&str> = option_env!("PUBLIC_BASE_URL");
+pub const ESP_LOG: Option<&str> = option_env!("ESP_LOG");
+pub const SYSLOG_HOST: Option<&str> = option_env!("SYSLOG_HOST");
+pub const SYSLOG_PORT: Option<&str> = option_env!("SYSLOG_PORT");
+pub const TDD_URL: Option<&str> = option_env!("TDD_URL");
+pub const DEBUG_PORT: Option<&str> = option_env!("DEBUG_PORT");
+pub const WEBHOOK_URL: Option<&str> = option_env!("WEBHOOK_URL");
+pub const WAKE_INTERVAL_MIN: Option<&str> = option_env!("WAKE_INTERVAL_MIN");
+pub const SHUTDOWN_WAKE_MIN: Option<&str> = option_env!("SHUTDOWN_WAKE_MIN");
+pub const NTP_SERVER: Option<&str> = option_env!("NTP_SERVER");
+pub const UDP_PORT: Option<&str> = option_env!("UDP_PORT");
+pub const FLASH_MAX_WRITES_PER_HOUR: Option<&str> = option_env!("FLASH_MAX_WRITES_PER_HOUR");
+pub const WRITE_ORIGINS: Option<&str> = option_env!("WRITE_ORIGINS");
+pub const WRITE_TOKEN: Option<&str> = option_env!("WRITE_TOKEN");
+pub const URN_ORG: Option<&str> = option_env!("URN_ORG");
+pub const ROUTE_PREFIX: Option<&str> = option_env!("ROUTE_PREFIX");
+pub const STRIKE_LIMIT: Option<&str> = option_env!("STRIKE_LIMIT");
+pub const STRIKE_WINDOW_S: Option<&str> = option_env!("STRIKE_WINDOW_S");
+pub const BROWNOUT_LEVEL: Option<&str> = option_env!("BROWNOUT_LEVEL");
+pub const SSE_KEEPALIVE_S: Option<&str> = option_env!("SSE_KEEPALIVE_S");
+
+/// The settings checked at start-up, by [`validate`].
+pub const SETTINGS: &[Setting] = &[
+    Setting {
+        name: "WIFI_COUNTRY",
+        value: WIFI_COUNTRY,
+        check: config::country,
+    },
+    Setting {
+        name: "WIFI_MAX_TX_POWER",
+        value: WIFI_MAX_TX_POWER,
+        check: config::max_tx_power,
+    },
+    Setting {
+        name: "SUPPORT_URL",
+        value: SUPPORT_URL,
+        check: config::support_url,
+    },
+    Setting {
+        name: "PUBLIC_BASE_URL",
+        value: PUBLIC_BASE_URL,
+        check: config::base_url,
+    },
+    Setting {
+        name: "ESP_LOG",
+        value: ESP_LOG,
+        check: config::log_level,
+    },
+    // Empty turns syslog off.
+    Setting {
+        name: "SYSLOG_HOST",
+        value: match SYSLOG_HOST {
+            Some("") => None,
+            host => host,
+        },
+        check: config::host,
+    },
+    Setting {
+        name: "SYSLOG_PORT",
+        value: SYSLOG_PORT,
+        check: config::port,
+    },
+    Setting {
+        name: "TDD_URL",
+        value: TDD_URL,
+        check: config::http_url,
+    },
+    Setting {
+        name: "DEBUG_PORT",
+        value: DEBUG_PORT,
+        check: config::port,
+    },
+    Setting {
+        name: "WEBHOOK_URL",
+        value: WEBHOOK_URL,
+        check: config::http_url,
+    },
+    Setting {
+        name: "WAKE_INTERVAL_MIN",
+        value: WAKE_INTERVAL_MIN,
+        check: config::wake_interval,
+    },
+    Setting {
+        name: "SHUTDOWN_WAKE_MIN",
+        value: SHUTDOWN_WAKE_MIN,
+        check: config::shutdown_wake,
+    },
+    Setting {
+        name: "NTP_SERVER",
+        value: NTP_SERVER,
+        check: config::host,
+    },
+    Setting {
+        name: "UDP_PORT",
+        value: UDP_PORT,
+        check: config::port,
+    },
+    Setting {
+        name: "FLASH_MAX_WRITES_PER_HOUR",
+        value: FLASH_MAX_WRITES_PER_HOUR,
+        check: config::flash_max_writes,
+    },
+    Setting {
+        name: "WRITE_ORIGINS",
+        value: WRITE_ORIGINS,
+        check: config::write_origins,
+    },
+    Setting {
+        name: "WRITE_TOKEN",
+        value: WRITE_TOKEN,
+        check: config::write_token,
+    },
+    Setting {
+        name: "URN_ORG",
+        value: URN_ORG,
+        check: config::urn_org,
+    },
+    Setting {
+        name: "ROUTE_PREFIX",
+        value: ROUTE_PREFIX,
+        check: config::route_prefix,
+    },
+    Setting {
+        name: "STRIKE_LIMIT",
+        value: STRIKE_LIMIT,
+        check: config::strike_limit,
+    },
+    Setting {
+        name: "STRIKE_WINDOW_S",
+        value: STRIKE_WINDOW_S,
+        check: config::strike_window,
+    },
+    Setting {
+        name: "BROWNOUT_LEVEL",
+        value: BROWNOUT_LEVEL,
+        check: config::brownout_level,
+    },
+    Setting {
+        name: "SSE_KEEPALIVE_S",
+        value: SSE_KEEPALIVE_S,
+        check: config::sse_keepalive,
+    },
+];
+
+/// Check [`SETTINGS`], and enter the safe mode if any is rejected: log
+/// each one with the reason, then deep-sleep until the next reset, before
+/// the radio is up. The settings are baked in, so retrying cannot help.
+pub(crate) fn validate() {
+    let problems = problems(SETTINGS);
+    if problems.is_empty() {
+        return;
+    }
+
+    for problem in &problems {
+        error!("Invalid {}: {}", problem.name, problem.reason);
+    }
+    error!("Safe mode: fix the settings above and rebuild");
+
+    // Nothing uses the RTC yet.
+    let mut rtc = esp_hal::rtc_cntl::Rtc::new(unsafe { esp_hal::peripherals::LPWR::steal() });
+    rtc.sleep_deep(&[]);
+}
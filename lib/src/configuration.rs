@@ -0,0 +1,95 @@
+//! The `configuration` property, assembled once at start-up from the consts
+//! the firmware runs with, see [`wot_esp_demo_core::configuration`].
+//!
+//! The same values are logged as the start-up banner.
+
+use core::cell::Cell;
+
+use alloc::string::String;
+
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use picoserve::response::{IntoResponse, Response};
+
+pub use wot_esp_demo_core::configuration::Configuration;
+
+use crate::{respond_json_negotiated, respond_negotiated, Accept};
+
+/// The features of this crate, and whether they are built in.
+pub const FEATURES: &[(&str, bool)] = &[
+    ("uuid-id", cfg!(feature = "uuid-id")),
+    ("ui", cfg!(feature = "ui")),
+    ("debug-log", cfg!(feature = "debug-log")),
+    ("debug-tasks", cfg!(feature = "debug-tasks")),
+    ("debug-port", cfg!(feature = "debug-port")),
+    ("syslog", cfg!(feature = "syslog")),
+    ("maintenance-ap", cfg!(feature = "maintenance-ap")),
+    ("dns", cfg!(feature = "dns")),
+    ("http-client", cfg!(feature = "http-client")),
+    ("self-test", cfg!(feature = "self-test")),
+    ("group", cfg!(feature = "group")),
+    ("directory", cfg!(feature = "directory")),
+    ("low-power", cfg!(feature = "low-power")),
+    ("sntp", cfg!(feature = "sntp")),
+    ("rules", cfg!(feature = "rules")),
+    ("flash-store", cfg!(feature = "flash-store")),
+    ("udp", cfg!(feature = "udp")),
+    ("shutdown", cfg!(feature = "shutdown")),
+    ("bare-events", cfg!(feature = "bare-events")),
+    ("defmt", cfg!(feature = "defmt")),
+];
+
+/// The property as JSON, `null` before [`init`].
+static CONFIGURATION: CriticalSectionMutex<Cell<&'static str>> =
+    CriticalSectionMutex::new(Cell::new("null"));
+
+/// Log `configuration` and serve it from now on.
+pub fn init(configuration: &Configuration) {
+    info!(
+        "Configuration of {} {}",
+        configuration.name, configuration.version
+    );
+    if let Some(board) = configuration.board {
+        info!("  board {}", board);
+    }
+    for (role, pin) in configuration.pins {
+        info!("  {} on GPIO{}", role, pin);
+    }
+    let mut features = String::new();
+    for (name, _) in configuration.features.iter().filter(|(_, on)| *on) {
+        if !features.is_empty() {
+            features.push_str(", ");
+        }
+        features.push_str(name);
+    }
+    info!("  features: {}", features.as_str());
+    info!(
+        "  heap {} bytes, {} web tasks, {} sockets",
+        configuration.heap_size, configuration.web_tasks, configuration.sockets
+    );
+    info!(
+        "  TCP buffers {}/{} bytes, HTTP buffer {} bytes",
+        configuration.tcp_buffers.0, configuration.tcp_buffers.1, configuration.http_buffer
+    );
+    if let Some(interval) = configuration.sample_interval_ms {
+        info!("  sampling every {} ms", interval);
+    }
+
+    // Serialized like the TD; a `Value` always serializes.
+    let json = serde_json::to_string(&configuration.to_json()).unwrap_or_default();
+    let json = alloc::boxed::Box::leak(json.into_boxed_str());
+    CONFIGURATION.lock(|c| c.set(json));
+}
+
+/// Handler for `GET /properties/configuration`.
+pub async fn serve(accept: Accept) -> impl IntoResponse {
+    let json = CONFIGURATION.lock(Cell::get);
+    if accept.cbor() {
+        // Kept as JSON, parsed again for the rare CBOR read.
+        let value: serde_json::Value = serde_json::from_str(json).unwrap_or_default();
+        return Ok(respond_negotiated(accept, &value));
+    }
+    Err(respond_json_negotiated(
+        accept,
+        Response::ok(json).with_header("Content-Type", "application/json"),
+    ))
+}
@@ -0,0 +1,200 @@
+//! Wi-Fi connectivity published by the [`crate::connection`] task.
+//!
+//! Every Thing exposes it as the `connectivity` event and the read-only
+//! `online_since` and `network` properties, all merged into the TD by
+//! [`wot_esp_demo_core::td::merge_metadata`].
+//!
+//! The lease is copied into a [`NetInfo`] by [`net_info_task`]: the stack
+//! config is gone while the network flaps, so request handlers read the copy
+//! and report the last-known address instead.
+
+use core::{
+    cell::{Cell, RefCell},
+    net::Ipv4Addr,
+};
+
+use alloc::vec::Vec;
+use embassy_futures::select::{select, Either};
+use embassy_net::{Stack, StaticConfigV4};
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, CriticalSectionMutex},
+    watch::Watch,
+};
+use embassy_time::{Duration, Instant, Timer};
+use picoserve::response::{IntoResponse, Response, StatusCode};
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use serde_json::json;
+
+use crate::{
+    respond_negotiated, respond_negotiated_field, Accept, EventLog, EventStream, Field,
+    LastEventId, Sequenced, SseEvents,
+};
+
+/// State of the Wi-Fi station, sent as the `connectivity` event data.
+#[derive(Clone, Copy, Debug)]
+pub enum Connectivity {
+    Disconnected,
+    /// Associated to `ssid` and configured with the address `ip`.
+    Connected {
+        ip: Ipv4Addr,
+        ssid: &'static str,
+    },
+}
+
+impl Serialize for Connectivity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Disconnected => {
+                let mut event = serializer.serialize_struct("Connectivity", 1)?;
+                event.serialize_field("state", "disconnected")?;
+                event.end()
+            }
+            Self::Connected { ip, ssid } => {
+                let mut event = serializer.serialize_struct("Connectivity", 3)?;
+                event.serialize_field("state", "connected")?;
+                event.serialize_field("ip", ip)?;
+                event.serialize_field("ssid", ssid)?;
+                event.end()
+            }
+        }
+    }
+}
+
+static WATCH: Watch<CriticalSectionRawMutex, Sequenced<Connectivity>, 2> = Watch::new();
+static WATCH_LOG: EventLog<Connectivity> = EventLog::new();
+
+static ONLINE_SINCE: CriticalSectionMutex<Cell<Option<Instant>>> =
+    CriticalSectionMutex::new(Cell::new(None));
+
+static STACK: CriticalSectionMutex<Cell<Option<Stack<'static>>>> =
+    CriticalSectionMutex::new(Cell::new(None));
+
+/// The station's DHCP lease, as last seen by [`net_info_task`].
+#[derive(Clone, Debug)]
+pub struct NetInfo {
+    pub config: StaticConfigV4,
+    /// Whether the lease is current; `false` while the network is down and
+    /// `config` is the last-known one.
+    pub up: bool,
+}
+
+static NET_INFO: CriticalSectionMutex<RefCell<Option<NetInfo>>> =
+    CriticalSectionMutex::new(RefCell::new(None));
+
+/// How often a current lease is read again, as a renewal may change it
+/// without the config going down.
+const LEASE_POLL: Duration = Duration::from_secs(10);
+
+/// The last lease seen, `None` before the first one.
+#[must_use]
+pub fn net_info() -> Option<NetInfo> {
+    NET_INFO.lock(|info| info.borrow().clone())
+}
+
+/// Keep [`net_info`] in step with the station `stack`.
+#[embassy_executor::task]
+pub(crate) async fn net_info_task(stack: Stack<'static>) {
+    loop {
+        stack.wait_config_up().await;
+        if let Some(config) = stack.config_v4() {
+            NET_INFO.lock(|info| *info.borrow_mut() = Some(NetInfo { config, up: true }));
+        }
+
+        if let Either::First(()) = select(stack.wait_config_down(), Timer::after(LEASE_POLL)).await
+        {
+            NET_INFO.lock(|info| {
+                if let Some(info) = info.borrow_mut().as_mut() {
+                    info.up = false;
+                }
+            });
+        }
+    }
+}
+
+/// The station address, once `stack` has one.
+pub(crate) async fn wait_address(stack: Stack<'_>) -> Ipv4Addr {
+    loop {
+        stack.wait_config_up().await;
+        // The lease may be lost again before it is read.
+        if let Some(config) = stack.config_v4() {
+            return config.address.address();
+        }
+        Timer::after(Duration::from_millis(500)).await;
+    }
+}
+
+/// Make the station `stack` readable as the `network` property.
+pub(crate) fn set_stack(stack: Stack<'static>) {
+    STACK.lock(|s| s.set(Some(stack)));
+}
+
+/// The station stack, once the connection task started.
+pub(crate) fn stack() -> Option<Stack<'static>> {
+    STACK.lock(Cell::get)
+}
+
+/// Record a successful association with the access point.
+pub(crate) fn associated() {
+    ONLINE_SINCE.lock(|since| since.set(Some(Instant::now())));
+}
+
+/// Notify the `connectivity` subscribers of a state transition.
+pub(crate) fn publish(state: Connectivity) {
+    WATCH.sender().send(WATCH_LOG.record(state));
+}
+
+/// Milliseconds since boot at the last successful association, if any.
+#[must_use]
+pub fn online_since() -> Option<u64> {
+    ONLINE_SINCE.lock(Cell::get).map(|since| since.as_millis())
+}
+
+pub(crate) async fn read_online_since(accept: Accept) -> impl IntoResponse {
+    respond_negotiated(accept, &online_since())
+}
+
+/// The last-known DHCP lease, the MAC address and the low-heap flag of
+/// [`crate::heap::is_low`], or one member of it with `?field=`.
+pub(crate) async fn read_network(accept: Accept, field: Field) -> impl IntoResponse {
+    let network = stack().map(|stack| {
+        let mac = wot_esp_demo_core::names::mac(stack.hardware_address().as_bytes());
+        let radio = crate::wifi::radio();
+        match net_info() {
+            Some(NetInfo { config, .. }) => json!({
+                "ip": config.address.address(),
+                "prefix": config.address.prefix_len(),
+                "gateway": config.gateway,
+                "dns": config.dns_servers.iter().collect::<Vec<_>>(),
+                "mac": mac,
+                "country": radio.country(),
+                "maxTxPower": radio.max_tx_power,
+                "heapLow": crate::heap::is_low(),
+            }),
+            None => json!({
+                "dns": [],
+                "mac": mac,
+                "country": radio.country(),
+                "maxTxPower": radio.max_tx_power,
+                "heapLow": crate::heap::is_low(),
+            }),
+        }
+    });
+
+    respond_negotiated_field(accept, &network, field)
+}
+
+pub(crate) async fn events(LastEventId(last_event_id): LastEventId) -> impl IntoResponse {
+    // The watch serves a bounded number of concurrent subscribers.
+    match WATCH.receiver() {
+        Some(receiver) => Ok(EventStream(SseEvents::new(
+            receiver,
+            &WATCH_LOG,
+            last_event_id,
+        ))),
+        None => Err(Response::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Too many connectivity subscribers.",
+        )
+        .with_header("Content-Type", "text/plain")),
+    }
+}
@@ -0,0 +1,63 @@
+//! The `/debug` routes on a port of their own, so a firewall can keep them
+//! from the network the Things are used on while port 80 serves only the
+//! WoT affordances.
+//!
+//! [`DEBUG_TASKS`] web tasks serve them on [`port`], set with the
+//! `DEBUG_PORT` env var at build time, 8081 by default:
+//!
+//! ```sh
+//! curl http://<ip>:8081/debug/latency
+//! ```
+
+use core::future::Future;
+
+use embassy_net::Stack;
+use picoserve::{routing::PathRouter, AppWithStateBuilder, Router};
+
+use crate::{debug_routes, mk_static, WebTask, WEB_TASKS};
+
+/// Port of the debug routes, from the `DEBUG_PORT` build-time env var,
+/// 8081 by default.
+#[must_use]
+pub fn port() -> u16 {
+    crate::config::DEBUG_PORT
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(8081)
+}
+
+/// Web tasks serving the debug routes, the concurrent debug connections.
+pub const DEBUG_TASKS: usize = 1;
+
+/// Sockets of the debug web tasks.
+pub const DEBUG_STACK_SIZE: usize = DEBUG_TASKS;
+
+/// The app of the debug port: the `/debug` routes only, with no state.
+pub struct DebugApp;
+
+impl AppWithStateBuilder for DebugApp {
+    type State = ();
+    type PathRouter = impl PathRouter<()>;
+
+    fn build_app(self) -> Router<Self::PathRouter, ()> {
+        debug_routes(Router::new())
+    }
+}
+
+/// Serve the debug routes on the station `stack`, closing every connection
+/// after its response so one client cannot hold the port.
+pub(crate) fn serve(stack: Stack<'static>) -> impl Future<Output = ()> {
+    let app = mk_static!(picoserve::AppRouter<DebugApp>, DebugApp.build_app());
+    let port = port();
+
+    let tasks: [_; DEBUG_TASKS] = core::array::from_fn(|id| {
+        alloc::boxed::Box::pin(<() as WebTask<DebugApp>>::spawn(
+            port,
+            WEB_TASKS + id,
+            stack,
+            app,
+            false,
+            &(),
+        ))
+    });
+    embassy_futures::join::join_array(tasks)
+}
@@ -0,0 +1,202 @@
+//! Registration with a Thing Description Directory.
+//!
+//! [`directory_task`] puts the TD served, see [`crate::td::served`], to
+//! `{base}/things/{id}` of the directory at start-up and every
+//! [`REFRESH_EVERY`], so a TD replaced by [`crate::td::update`] is registered
+//! at the next refresh. The directory is `TDD_URL` when
+//! set at build time, e.g. `TDD_URL=http://192.168.1.10:8081`. Otherwise it
+//! is looked up with a one-shot mDNS query for
+//! [`wot_esp_demo_core::directory::SERVICE`] (WoT Discovery): the first
+//! directory answering is used, its port taken from the `SRV` record and the
+//! path of its TD from the `td` TXT entry; that TD gives the API `base`.
+//! The others are logged.
+//!
+//! The directory found is kept until [`MAX_FAILURES`] registrations in a row
+//! fail, then looked up again.
+
+use alloc::{format, string::String, vec};
+
+use embassy_net::{
+    udp::{PacketMetadata, UdpSocket},
+    IpAddress, IpEndpoint, Stack,
+};
+use embassy_time::{with_deadline, Duration, Instant, Timer};
+use serde_json::Value;
+use wot_esp_demo_core::{
+    directory::{base, registration_url, DEFAULT_TD_PATH, SERVICE},
+    dns_sd,
+};
+
+use crate::{connectivity, http_client::Client, td};
+
+/// The directory set at build time, instead of looking one up.
+pub use crate::config::TDD_URL;
+
+/// Sockets used: the mDNS query socket and the registration connection.
+pub const DIRECTORY_STACK_SIZE: usize = 2;
+
+/// How often the registration is renewed.
+pub const REFRESH_EVERY: Duration = Duration::from_secs(10 * 60);
+
+/// Failed registrations in a row after which the directory is looked up
+/// again.
+pub const MAX_FAILURES: u32 = 3;
+
+/// Time before retrying a failed lookup or registration.
+const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// How long the answers to a lookup are collected.
+const BROWSE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Time given to the directory to serve its TD or take ours.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest TD read from the directory.
+const MAX_TD_LEN: usize = 8 * 1024;
+
+/// The first directory answering a lookup: its address, port and TD path.
+async fn browse(stack: Stack<'_>) -> Option<(IpAddress, u16, String)> {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0; 4096];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_buffer = [0; 64];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+
+    let mut query = [0; 64];
+    #[allow(clippy::cast_possible_truncation)]
+    let id = Instant::now().as_ticks() as u16;
+    let len = dns_sd::ptr_query(id, SERVICE, &mut query)?;
+    let mdns = IpEndpoint::new(IpAddress::v4(224, 0, 0, 251), 5353);
+    if socket.bind(0).is_err() || socket.send_to(&query[..len], mdns).await.is_err() {
+        warn!("Directory: cannot send the mDNS query");
+        return None;
+    }
+
+    let mut found = None;
+    let deadline = Instant::now() + BROWSE_WINDOW;
+    let mut response = [0; 1500];
+    while let Ok(received) = with_deadline(deadline, socket.recv_from(&mut response)).await {
+        let Ok((len, meta)) = received else {
+            continue;
+        };
+        let Some(instance) = dns_sd::instance(&response[..len], id) else {
+            continue;
+        };
+        let ip = meta.endpoint.addr;
+        match &found {
+            None => {
+                let td = instance.td.unwrap_or(DEFAULT_TD_PATH);
+                found = Some((ip, instance.port, td.into()));
+            }
+            Some((first, ..)) if *first != ip => {
+                info!("Directory: also found {}:{}, not used", ip, instance.port);
+            }
+            Some(_) => {}
+        }
+    }
+
+    found
+}
+
+/// The API base of the first directory answering a lookup.
+async fn discover(stack: Stack<'_>) -> Option<String> {
+    let (ip, port, td_path) = browse(stack).await?;
+    let ip = format!("{ip}");
+
+    let mut buf = vec![0; MAX_TD_LEN];
+    let url = format!("http://{ip}:{port}{td_path}");
+    let directory_td = match Client::new(stack)
+        .with_timeout(TIMEOUT)
+        .get(&url, &mut buf)
+        .await
+    {
+        Ok(response) if response.status == 200 => {
+            serde_json::from_slice(response.body).unwrap_or(Value::Null)
+        }
+        _ => Value::Null,
+    };
+
+    let base = base(&directory_td, &ip, port);
+    info!("Directory: found {}", base.as_str());
+    Some(base)
+}
+
+/// Put `td` to the directory at `base`.
+async fn register(stack: Stack<'_>, base: &str, td: &str, url: &str) -> bool {
+    let mut buf = [0; 512];
+    match Client::new(stack)
+        .with_timeout(TIMEOUT)
+        .put_json(url, td.as_bytes(), &mut buf)
+        .await
+    {
+        Ok(response) if (200..300).contains(&response.status) => true,
+        Ok(response) => {
+            warn!("Directory: {} answered {}", base, response.status);
+            false
+        }
+        Err(e) => {
+            warn!("Directory: {} failed: {}", base, e.as_str());
+            false
+        }
+    }
+}
+
+/// Keep the TD served registered with the directory.
+#[embassy_executor::task]
+pub async fn directory_task(stack: Stack<'static>) {
+    let mut directory: Option<String> = TDD_URL.map(Into::into);
+    let mut registered = false;
+    let mut failures = 0;
+
+    loop {
+        connectivity::wait_address(stack).await;
+
+        if directory.is_none() {
+            directory = discover(stack).await;
+        }
+        let Some(base) = &directory else {
+            Timer::after(RETRY_AFTER).await;
+            continue;
+        };
+        let Some(td) = td::served() else {
+            Timer::after(RETRY_AFTER).await;
+            continue;
+        };
+        let Ok(thing) = serde_json::from_str::<Value>(td.json()) else {
+            return;
+        };
+        let Some(url) = registration_url(base, &thing) else {
+            warn!("Directory: the TD has no id");
+            return;
+        };
+
+        // The TD is released before waiting, so that `td::set` can replace it.
+        let registered_now = register(stack, base, td.json(), &url).await;
+        drop(td);
+        if registered_now {
+            if !registered {
+                info!("Directory: registered with {}", base.as_str());
+            }
+            registered = true;
+            failures = 0;
+            Timer::after(REFRESH_EVERY).await;
+            continue;
+        }
+
+        registered = false;
+        failures += 1;
+        // An explicit directory is kept whatever happens.
+        if failures >= MAX_FAILURES && TDD_URL.is_none() {
+            info!("Directory: {} unreachable, looking again", base.as_str());
+            directory = None;
+            failures = 0;
+        }
+        Timer::after(RETRY_AFTER).await;
+    }
+}
@@ -0,0 +1,93 @@
+//! Host name resolution over the DNS socket of the stack.
+//!
+//! [`resolve`] answers numeric IPv4 addresses directly and keeps the last
+//! lookups in a [`Cache`], so endpoints configured by name (`SYSLOG_HOST`, the
+//! URLs of [`crate::http_client`]) are looked up again only once their entry
+//! expires, and a name that does not resolve is retried with backoff.
+
+use core::{cell::RefCell, net::Ipv4Addr};
+
+use embassy_net::{
+    dns::{DnsQueryType, Error},
+    IpAddress, Stack,
+};
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use embassy_time::Instant;
+use wot_esp_demo_core::resolve::Cache;
+
+/// Sockets used: the DNS resolver of the stack.
+pub const DNS_STACK_SIZE: usize = 1;
+
+static CACHE: CriticalSectionMutex<RefCell<Cache<IpAddress>>> =
+    CriticalSectionMutex::new(RefCell::new(Cache::new()));
+
+/// Why a name did not resolve.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResolveError {
+    /// The lookup failed.
+    Dns(Error),
+    /// A recent lookup failed and the next is not due yet.
+    BackingOff,
+}
+
+/// The address of `host`, a name or a numeric IPv4 address.
+///
+/// # Errors
+///
+/// Returns an error if the lookup failed, now or within the backoff of an
+/// earlier failure.
+pub async fn resolve(stack: Stack<'_>, host: &str) -> Result<IpAddress, ResolveError> {
+    lookup(stack, host, true).await
+}
+
+/// [`resolve`] without the warning when the lookup fails, for
+/// [`crate::syslog`]: a warning would be queued for the collector that does
+/// not resolve, and fail again.
+///
+/// # Errors
+///
+/// As [`resolve`].
+pub(crate) async fn resolve_quietly(
+    stack: Stack<'_>,
+    host: &str,
+) -> Result<IpAddress, ResolveError> {
+    lookup(stack, host, false).await
+}
+
+/// [`resolve`], logging a failed lookup if `log_failure`.
+async fn lookup(
+    stack: Stack<'_>,
+    host: &str,
+    log_failure: bool,
+) -> Result<IpAddress, ResolveError> {
+    if let Ok(ip) = host.parse::<Ipv4Addr>() {
+        return Ok(ip.into());
+    }
+
+    let now = Instant::now().as_millis();
+    match CACHE.lock(|c| c.borrow().get(host, now)) {
+        Some(Some(ip)) => return Ok(ip),
+        Some(None) => return Err(ResolveError::BackingOff),
+        None => {}
+    }
+
+    // embassy-net does not pass the TTL of the answer on.
+    let lookup = stack
+        .dns_query(host, DnsQueryType::A)
+        .await
+        .and_then(|addrs| addrs.first().copied().ok_or(Error::Failed));
+    let now = Instant::now().as_millis();
+    match lookup {
+        Ok(ip) => {
+            CACHE.lock(|c| c.borrow_mut().resolved(host, ip, None, now));
+            Ok(ip)
+        }
+        Err(e) => {
+            let wait = CACHE.lock(|c| c.borrow_mut().failed(host, now));
+            if log_failure {
+                warn!("Cannot resolve {}, retrying in {} ms", host, wait);
+            }
+            Err(ResolveError::Dns(e))
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! The random numbers of the Thing, all drawn through [`CountingRng`], and
+//! their health, served at `/debug/entropy`.
+//!
+//! [`net_seed`] checks a sample of the hardware generator before seeding the
+//! network stack from it, and falls back to a seed mixed from the build,
+//! the MAC address and the uptime when the sample fails, see
+//! [`wot_esp_demo_core::entropy`]:
+//!
+//! ```json
+//! {"seedSource":"hardware","bytesDrawn":272,
+//!  "selfTest":{"passed":true,"ones":1019,"runs":1031,"longestRun":11}}
+//! ```
+//!
+//! `seedSource` and `selfTest` are `null` until the network stack is
+//! seeded.
+
+use core::cell::Cell;
+
+use portable_atomic::{AtomicU32, Ordering};
+
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use embassy_time::Instant;
+use esp_hal::rng::Rng;
+use picoserve::response::IntoResponse;
+use serde_json::json;
+use wot_esp_demo_core::entropy::{check, fallback_seed, Health, SeedSource, SAMPLE_LEN};
+
+use crate::{respond_json_negotiated, to_json_response, Accept, UUID_SEED};
+
+/// Bytes drawn since boot.
+static DRAWN: AtomicU32 = AtomicU32::new(0);
+
+static SEED: CriticalSectionMutex<Cell<Option<(SeedSource, Health)>>> =
+    CriticalSectionMutex::new(Cell::new(None));
+
+/// The hardware generator, counting the bytes drawn from it.
+#[derive(Clone, Copy)]
+pub struct CountingRng(Rng);
+
+impl CountingRng {
+    #[must_use]
+    pub fn new() -> Self {
+        Self(Rng::new())
+    }
+
+    pub fn random(&self) -> u32 {
+        DRAWN.fetch_add(4, Ordering::Relaxed);
+        self.0.random()
+    }
+}
+
+impl Default for CountingRng {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl rand_core::RngCore for CountingRng {
+    fn next_u32(&mut self) -> u32 {
+        self.random()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        u64::from(self.random()) << 32 | u64::from(self.random())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        #[allow(clippy::cast_possible_truncation)]
+        DRAWN.fetch_add(dest.len() as u32, Ordering::Relaxed);
+        self.0.read(dest);
+    }
+}
+
+/// The seed of a network stack on the interface with the `mac` address:
+/// from `rng` once it passed the checks, which need the radio on.
+pub fn net_seed(rng: &mut CountingRng, mac: [u8; 6]) -> u64 {
+    let mut sample = [0; SAMPLE_LEN];
+    rand_core::RngCore::fill_bytes(rng, &mut sample);
+    let health = check(&sample);
+
+    let (source, seed) = if health.passed {
+        (SeedSource::Hardware, rand_core::RngCore::next_u64(rng))
+    } else {
+        warn!(
+            "RNG failed its checks ({} ones, {} runs, longest {}), using a fallback seed",
+            health.ones, health.runs, health.longest_run
+        );
+        let ticks = Instant::now().as_ticks();
+        (SeedSource::Fallback, fallback_seed(&UUID_SEED, mac, ticks))
+    };
+    SEED.lock(|s| s.set(Some((source, health))));
+    seed
+}
+
+/// Handler for `GET /debug/entropy`.
+pub(crate) async fn serve(accept: Accept) -> impl IntoResponse {
+    let seed = SEED.lock(Cell::get);
+    respond_json_negotiated(
+        accept,
+        to_json_response(&json!({
+            "seedSource": seed.map(|(source, _)| source.as_str()),
+            "bytesDrawn": DRAWN.load(Ordering::Relaxed),
+            "selfTest": seed.map(|(_, health)| health),
+        })),
+    )
+}
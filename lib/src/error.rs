@@ -0,0 +1,63 @@
+//! Failures bringing up a Thing in [`crate::EspThing::try_run`].
+
+use core::fmt;
+
+use esp_radio::wifi::WifiError;
+
+/// Why a Thing could not be brought up.
+#[derive(Debug)]
+pub enum ThingError {
+    /// The Wi-Fi controller could not be created or configured.
+    Wifi(WifiError),
+    /// The network did not come up, the step named by the payload.
+    Net(&'static str),
+    /// A task could not be spawned, named by the payload: its pool is full.
+    Spawn(&'static str),
+    /// The Thing Description could not be produced.
+    Td(serde_json::Error),
+    /// Hardware the Thing needs is missing or failed, described by the payload.
+    Peripheral(&'static str),
+    /// A build-time setting is invalid, described by the payload.
+    Config(&'static str),
+}
+
+impl ThingError {
+    /// Whether a reset may clear the error: the network can come back, while
+    /// a bad TD, setting, peripheral or task pool fails again on every boot.
+    #[must_use]
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Wifi(_) | Self::Net(_))
+    }
+}
+
+impl fmt::Display for ThingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Wifi(e) => write!(f, "Wi-Fi: {e:?}"),
+            Self::Net(step) => write!(f, "network: {step} failed"),
+            Self::Spawn(task) => write!(f, "cannot spawn {task}"),
+            Self::Td(e) => write!(f, "Thing Description: {e}"),
+            Self::Peripheral(what) => write!(f, "peripheral: {what}"),
+            Self::Config(what) => write!(f, "configuration: {what}"),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ThingError {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{}", defmt::Display2Format(self));
+    }
+}
+
+impl From<WifiError> for ThingError {
+    fn from(e: WifiError) -> Self {
+        Self::Wifi(e)
+    }
+}
+
+impl From<serde_json::Error> for ThingError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Td(e)
+    }
+}
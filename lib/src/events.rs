@@ -0,0 +1,494 @@
+//! Server-Sent Events plumbing shared by the demos.
+//!
+//! Every event carries a per-channel sequence number (the SSE `id:` field) and
+//! the last [`EVENT_LOG_LEN`] events are kept in an [`EventLog`] so that
+//! reconnecting clients can resume via `Last-Event-ID`.
+//!
+//! A stream ends after [`MAX_STREAM_LIFETIME`] so that the connection slot of
+//! a client that vanished without closing it is eventually freed; live clients
+//! reconnect and resume from their last id. An idle stream gets a keepalive
+//! comment every `SSE_KEEPALIVE_S` seconds, 15 by default.
+//!
+//! The value is serialized into the `data:` field as `{"data":<value>}`, the
+//! shape of the event data schemas of the TD. The `bare-events` feature sends
+//! the value alone instead, for clients not migrated yet.
+//!
+//! The frames are those of [`wot_esp_demo_core::sse`], written as they are
+//! through an [`EventStream`]: `demo-sim` writes the same bytes.
+//!
+//! Every stream counts its events delivered and dropped, see
+//! [`wot_esp_demo_core::delivery`], and adds them into the totals served as
+//! JSON at `/debug/events` when it closes:
+//!
+//! ```json
+//! {"closed":6,"delivered":412,"dropped":3,"maxLag":8,"open":1,"slowStreams":1}
+//! ```
+
+use core::cell::RefCell;
+
+use portable_atomic::{AtomicU32, Ordering};
+
+use alloc::string::String;
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, CriticalSectionMutex},
+    pubsub::{Subscriber, WaitResult},
+    watch::Receiver,
+};
+use embassy_time::{with_timeout, Duration, Instant};
+use picoserve::{
+    io::{Read, Write},
+    response::{
+        custom::{CustomBody, CustomResponse},
+        Connection, IntoResponse, ResponseWriter, StatusCode,
+    },
+    ResponseSent,
+};
+use serde::Serialize;
+use serde_json::json;
+use wot_esp_demo_core::{delivery::Delivery, sse};
+
+use crate::{config, respond_json_negotiated, to_json_response, Accept, EVENT_COUNTERS};
+
+/// Time after which a stream is ended, and the client expected to reconnect.
+///
+/// The keepalive comments fit in the send buffer for a long time, so this
+/// bounds how long a half-open connection holds a web task, should the TCP
+/// keepalive of [`keepalive_interval`] not catch it first.
+pub const MAX_STREAM_LIFETIME: Duration = Duration::from_secs(sse::MAX_STREAM_LIFETIME_S);
+
+/// Interval after which an idle stream gets a keepalive comment, and an
+/// idle connection a TCP keepalive, `SSE_KEEPALIVE_S`: always below
+/// [`MAX_STREAM_LIFETIME`].
+pub(crate) fn keepalive_interval() -> Duration {
+    Duration::from_secs(sse::keepalive_s(config::SSE_KEEPALIVE_S))
+}
+
+/// An event value tagged with its per-channel sequence number.
+///
+/// The sequence number is written as the SSE `id:` field so clients can resume
+/// with `Last-Event-ID` after a reconnect.
+#[derive(Clone, Copy, Debug)]
+pub struct Sequenced<T> {
+    pub id: u32,
+    pub value: T,
+}
+
+/// Number of past events retained per channel for `Last-Event-ID` replay.
+pub const EVENT_LOG_LEN: usize = 8;
+
+pub use sse::DATA_LEN as EVENT_DATA_LEN;
+
+struct EventRing<T> {
+    next_id: u32,
+    events: heapless::Deque<Sequenced<T>, EVENT_LOG_LEN>,
+}
+
+/// Sequence counter and ring of the last [`EVENT_LOG_LEN`] events of a channel.
+///
+/// Lives next to the channel's `Watch` (or `PubSubChannel`) static;
+/// [`Self::record`] assigns the next sequence number and the result is what
+/// gets sent through the channel.
+pub struct EventLog<T> {
+    inner: CriticalSectionMutex<RefCell<EventRing<T>>>,
+}
+
+impl<T: Copy> EventLog<T> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            inner: CriticalSectionMutex::new(RefCell::new(EventRing {
+                next_id: 1,
+                events: heapless::Deque::new(),
+            })),
+        }
+    }
+
+    /// Assign the next sequence number to `value` and remember it for replay.
+    pub fn record(&self, value: T) -> Sequenced<T> {
+        self.inner.lock(|ring| {
+            let mut ring = ring.borrow_mut();
+            let event = Sequenced {
+                id: ring.next_id,
+                value,
+            };
+            ring.next_id = ring.next_id.wrapping_add(1).max(1);
+            if ring.events.is_full() {
+                ring.events.pop_front();
+            }
+            let _ = ring.events.push_back(event);
+            event
+        })
+    }
+
+    /// `last_event_id` if this log assigned it, `None` for an id from before
+    /// a reboot, once the counter started over: such a client gets every
+    /// event instead of none.
+    #[must_use]
+    pub fn issued(&self, last_event_id: Option<u32>) -> Option<u32> {
+        self.inner
+            .lock(|ring| last_event_id.filter(|&id| id < ring.borrow().next_id))
+    }
+
+    /// Retained events newer than `last_id`, oldest first.
+    ///
+    /// Events that already fell out of the ring are skipped; the client is
+    /// expected to re-read the property.
+    #[must_use]
+    pub fn since(&self, last_id: u32) -> heapless::Vec<Sequenced<T>, EVENT_LOG_LEN> {
+        self.inner.lock(|ring| {
+            ring.borrow()
+                .events
+                .iter()
+                .filter(|e| e.id > last_id)
+                .copied()
+                .collect()
+        })
+    }
+}
+
+impl<T: Copy> Default for EventLog<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The `Last-Event-ID` request header sent by reconnecting SSE clients.
+pub struct LastEventId(pub Option<u32>);
+
+impl<'r, S> picoserve::extract::FromRequestParts<'r, S> for LastEventId {
+    type Rejection = core::convert::Infallible;
+
+    async fn from_request_parts(
+        _state: &'r S,
+        request_parts: &picoserve::request::RequestParts<'r>,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(Self(
+            request_parts
+                .headers()
+                .get("Last-Event-ID")
+                .and_then(|v| v.as_str().ok())
+                .and_then(|v| v.trim().parse().ok()),
+        ))
+    }
+}
+
+/// Writes the frames of [`sse`] to a stream.
+pub struct SseWriter<W> {
+    writer: W,
+}
+
+impl<W: Write> SseWriter<W> {
+    async fn frame(&mut self, frame: &str) -> Result<(), W::Error> {
+        self.writer.write_all(frame.as_bytes()).await?;
+        self.writer.flush().await
+    }
+
+    /// Send `event` as a `value_changed` event with its id.
+    async fn value_changed<T: Serialize>(&mut self, event: &Sequenced<T>) -> Result<(), W::Error> {
+        let mut buf = [0; EVENT_DATA_LEN];
+        let data = sse::data(&event.value, &mut buf).unwrap_or_else(|| {
+            warn!("Event data over {} bytes, sent as null", EVENT_DATA_LEN);
+            "null"
+        });
+        self.frame(&sse::event("value_changed", Some(event.id), data))
+            .await
+    }
+
+    /// Send the `events_lost` event, carrying the number of events `lost`.
+    async fn events_lost(&mut self, lost: u64) -> Result<(), W::Error> {
+        self.frame(&sse::event("events_lost", None, &alloc::format!("{lost}")))
+            .await
+    }
+
+    async fn keepalive(&mut self) -> Result<(), W::Error> {
+        self.frame(sse::KEEPALIVE).await
+    }
+}
+
+/// A source of events, written to the stream of an [`EventStream`].
+pub trait Events {
+    async fn write_events<W: Write>(self, writer: SseWriter<W>) -> Result<(), W::Error>;
+}
+
+/// The response streaming the events of `S`, with [`sse::HEADERS`].
+pub struct EventStream<S>(pub S);
+
+impl<S: Events> CustomBody for EventStream<S> {
+    async fn write_response_body<W: Write>(self, writer: W) -> Result<(), W::Error> {
+        self.0.write_events(SseWriter { writer }).await
+    }
+}
+
+impl<S: Events> IntoResponse for EventStream<S> {
+    async fn write_to<R: Read, W: ResponseWriter<Error = R::Error>>(
+        self,
+        connection: Connection<'_, R>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        CustomResponse::build(StatusCode::OK)
+            .with_headers(sse::HEADERS)
+            .with_body(self)
+            .write_to(connection, response_writer)
+            .await
+    }
+}
+
+/// Totals of the event streams, added into by each stream as it closes.
+pub struct Counters {
+    open: AtomicU32,
+    closed: AtomicU32,
+    delivered: AtomicU32,
+    dropped: AtomicU32,
+    slow_streams: AtomicU32,
+    max_lag: AtomicU32,
+}
+
+impl Counters {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            open: AtomicU32::new(0),
+            closed: AtomicU32::new(0),
+            delivered: AtomicU32::new(0),
+            dropped: AtomicU32::new(0),
+            slow_streams: AtomicU32::new(0),
+            max_lag: AtomicU32::new(0),
+        }
+    }
+
+    fn open(&self) {
+        self.open.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn close(&self, delivery: &Delivery) {
+        self.open.fetch_sub(1, Ordering::Relaxed);
+        self.closed.fetch_add(1, Ordering::Relaxed);
+        self.delivered
+            .fetch_add(delivery.delivered, Ordering::Relaxed);
+        self.dropped.fetch_add(delivery.dropped, Ordering::Relaxed);
+        if delivery.dropped > 0 {
+            self.slow_streams.fetch_add(1, Ordering::Relaxed);
+        }
+        self.max_lag.fetch_max(delivery.max_lag, Ordering::Relaxed);
+    }
+}
+
+impl Default for Counters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The [`Delivery`] of an open stream, added into
+/// [`EVENT_COUNTERS`] when the stream ends, also when the client
+/// went away mid-write.
+struct Tally(Delivery);
+
+impl Tally {
+    fn new() -> Self {
+        EVENT_COUNTERS.open();
+        Self(Delivery::default())
+    }
+
+    /// Send the counts as the last frame of a stream the Thing ends.
+    async fn close<W: Write>(&self, writer: &mut SseWriter<W>) -> Result<(), W::Error> {
+        writer
+            .frame(&sse::comment("stream_end", &self.0.comment()))
+            .await
+    }
+}
+
+impl Drop for Tally {
+    fn drop(&mut self) {
+        EVENT_COUNTERS.close(&self.0);
+    }
+}
+
+/// Handler for `GET /debug/events`.
+pub(crate) async fn serve(accept: Accept) -> impl IntoResponse {
+    let counters = &EVENT_COUNTERS;
+    let load = |count: &AtomicU32| count.load(Ordering::Relaxed);
+    respond_json_negotiated(
+        accept,
+        to_json_response(&json!({
+            "open": load(&counters.open),
+            "closed": load(&counters.closed),
+            "delivered": load(&counters.delivered),
+            "dropped": load(&counters.dropped),
+            "slowStreams": load(&counters.slow_streams),
+            "maxLag": load(&counters.max_lag),
+        })),
+    )
+}
+
+/// Replay the events of `log` newer than `last_event_id`, see
+/// [`EventLog::issued`].
+///
+/// Returns the id of the last event replayed, so the live loop can skip
+/// anything already sent.
+async fn replay<T, W>(
+    writer: &mut SseWriter<W>,
+    log: &EventLog<T>,
+    last_event_id: Option<u32>,
+    tally: &mut Tally,
+) -> Result<Option<u32>, W::Error>
+where
+    T: Copy + Serialize,
+    W: Write,
+{
+    let Some(last_event_id) = last_event_id else {
+        return Ok(None);
+    };
+
+    let mut replayed = None;
+    for event in log.since(last_event_id) {
+        writer.value_changed(&event).await?;
+        tally.0.deliver();
+        replayed = Some(event.id);
+    }
+
+    Ok(replayed)
+}
+
+/// Time to wait for the next event, `None` once the stream lived past
+/// `deadline`.
+fn next_wait(deadline: Instant) -> Option<Duration> {
+    let now = Instant::now();
+    (now < deadline).then(|| keepalive_interval().min(deadline - now))
+}
+
+/// Server-Sent Events source fed by a `Watch` of [`Sequenced`] values.
+///
+/// On connect, replays the events from the [`EventLog`] newer than the client's
+/// `Last-Event-ID`, then polls the watch with a 15s timeout, emitting
+/// `value_changed` events (or a keepalive on timeout), until
+/// [`MAX_STREAM_LIFETIME`] is reached. Generic over the value type `T`.
+///
+/// A `Watch` only retains the latest value: use it when intermediate values are
+/// uninteresting, [`PubSubEvents`] otherwise. Values superseded before they
+/// were sent are not counted as dropped.
+pub struct SseEvents<'a, T: Copy + Send + 'static> {
+    receiver: Receiver<'a, CriticalSectionRawMutex, Sequenced<T>, 2>,
+    log: &'a EventLog<T>,
+    last_event_id: Option<u32>,
+}
+
+impl<'a, T: Copy + Send + 'static> SseEvents<'a, T> {
+    #[must_use]
+    pub fn new(
+        receiver: Receiver<'a, CriticalSectionRawMutex, Sequenced<T>, 2>,
+        log: &'a EventLog<T>,
+        last_event_id: Option<u32>,
+    ) -> Self {
+        Self {
+            receiver,
+            log,
+            last_event_id,
+        }
+    }
+}
+
+impl<T> Events for SseEvents<'_, T>
+where
+    T: Copy + Send + Serialize + 'static,
+{
+    async fn write_events<W: Write>(mut self, mut writer: SseWriter<W>) -> Result<(), W::Error> {
+        let deadline = Instant::now() + MAX_STREAM_LIFETIME;
+        let mut tally = Tally::new();
+        let last_event_id = self.log.issued(self.last_event_id);
+        let replayed = replay(&mut writer, self.log, last_event_id, &mut tally).await?;
+        // The client already has the events up to its own id.
+        let mut last_sent = replayed.or(last_event_id).unwrap_or(0);
+
+        while let Some(wait) = next_wait(deadline) {
+            match with_timeout(wait, self.receiver.changed()).await {
+                // Already delivered through the replay.
+                Ok(event) if event.id <= last_sent => {}
+                Ok(event) => {
+                    writer.value_changed(&event).await?;
+                    tally.0.deliver();
+                    last_sent = event.id;
+                }
+                Err(_) => writer.keepalive().await?,
+            }
+        }
+
+        tally.close(&mut writer).await
+    }
+}
+
+/// Server-Sent Events source fed by a `PubSubChannel` of [`Sequenced`] values.
+///
+/// Unlike [`SseEvents`] every published value is queued per subscriber, so
+/// rapid changes are delivered one by one. If the subscriber falls behind and
+/// the channel drops messages, an `events_lost` event carrying the number of
+/// dropped messages is emitted before resuming, and counted in the stream's
+/// [`Delivery`] along with its lag. Ends after [`MAX_STREAM_LIFETIME`] as
+/// well. Only the events replayed are skipped in the queue, so a client back
+/// with an id from before a reboot misses no press.
+pub struct PubSubEvents<
+    'a,
+    T: Copy + Send + 'static,
+    const CAP: usize,
+    const SUBS: usize,
+    const PUBS: usize,
+> {
+    subscriber: Subscriber<'a, CriticalSectionRawMutex, Sequenced<T>, CAP, SUBS, PUBS>,
+    log: &'a EventLog<T>,
+    last_event_id: Option<u32>,
+}
+
+impl<'a, T, const CAP: usize, const SUBS: usize, const PUBS: usize>
+    PubSubEvents<'a, T, CAP, SUBS, PUBS>
+where
+    T: Copy + Send + 'static,
+{
+    #[must_use]
+    pub fn new(
+        subscriber: Subscriber<'a, CriticalSectionRawMutex, Sequenced<T>, CAP, SUBS, PUBS>,
+        log: &'a EventLog<T>,
+        last_event_id: Option<u32>,
+    ) -> Self {
+        Self {
+            subscriber,
+            log,
+            last_event_id,
+        }
+    }
+}
+
+impl<T, const CAP: usize, const SUBS: usize, const PUBS: usize> Events
+    for PubSubEvents<'_, T, CAP, SUBS, PUBS>
+where
+    T: Copy + Send + Serialize + 'static,
+{
+    async fn write_events<W: Write>(mut self, mut writer: SseWriter<W>) -> Result<(), W::Error> {
+        let deadline = Instant::now() + MAX_STREAM_LIFETIME;
+        let mut tally = Tally::new();
+        let last_event_id = self.log.issued(self.last_event_id);
+        // The subscriber only queues events published since it subscribed,
+        // none the client could have but the ones replayed meanwhile.
+        let mut last_sent = replay(&mut writer, self.log, last_event_id, &mut tally)
+            .await?
+            .unwrap_or(0);
+
+        while let Some(wait) = next_wait(deadline) {
+            tally.0.lag(self.subscriber.available());
+            match with_timeout(wait, self.subscriber.next_message()).await {
+                Ok(WaitResult::Message(event)) if event.id <= last_sent => {}
+                Ok(WaitResult::Message(event)) => {
+                    writer.value_changed(&event).await?;
+                    tally.0.deliver();
+                    last_sent = event.id;
+                }
+                Ok(WaitResult::Lagged(lost)) => {
+                    tally.0.drop_events(lost);
+                    writer.events_lost(lost).await?;
+                }
+                Err(_) => writer.keepalive().await?,
+            }
+        }
+
+        tally.close(&mut writer).await
+    }
+}
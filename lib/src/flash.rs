@@ -0,0 +1,222 @@
+//! The flash key-value store (the `flash-store` feature), and its wear,
+//! served at `/debug/flash`.
+//!
+//! [`open`] puts the store of [`wot_esp_demo_core::store`] on the `nvs` data
+//! partition, and the features persisting a setting go through [`read`] and
+//! [`write`], its value kept as JSON under a key of their own:
+//!
+//! ```ignore
+//! flash::open(peripherals.FLASH);
+//! let last: Option<LastActive<RGB8>> = flash::read("light");
+//! flash::write("light", &light.state.last_active());
+//! ```
+//!
+//! The store reports through [`Counters`]: [`init`] with its size, then
+//! [`record_write`] after each write of a key and [`record_erase`] after each
+//! sector erase, so the debouncing of the features persisting through it can
+//! be checked from the counts:
+//!
+//! ```json
+//! {"store":{"sectors":4,"erases":12,"cyclesPerSector":3.0},
+//!  "maxWritesPerHour":6,"warning":true,
+//!  "keys":{"rules":{"writes":3,"bytes":420,"writesPerHour":0.1,"warning":false},
+//!          "color":{"writes":180,"bytes":1440,"writesPerHour":7.5,"warning":true}}}
+//! ```
+//!
+//! A key whose writes per hour, averaged over the last day, are above
+//! `FLASH_MAX_WRITES_PER_HOUR` (6 by default) is flagged, see
+//! [`wot_esp_demo_core::wear`]. The counts are kept in RAM from boot.
+
+use core::cell::{Cell, RefCell};
+
+use alloc::string::String;
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use embassy_time::Instant;
+use picoserve::response::IntoResponse;
+use serde_json::{json, Map, Value};
+use wot_esp_demo_core::wear::{self, cycles_per_sector, KeyWear};
+
+#[cfg(feature = "flash-store")]
+use esp_bootloader_esp_idf::partitions::{
+    self, DataPartitionSubType, FlashRegion, PartitionType, PARTITION_TABLE_MAX_LEN,
+};
+#[cfg(feature = "flash-store")]
+use esp_hal::peripherals::FLASH;
+#[cfg(feature = "flash-store")]
+use esp_storage::FlashStorage;
+#[cfg(feature = "flash-store")]
+use serde::{de::DeserializeOwned, Serialize};
+#[cfg(feature = "flash-store")]
+use wot_esp_demo_core::store::{Store, StoreError};
+
+use crate::{config::FLASH_MAX_WRITES_PER_HOUR, respond_json_negotiated, to_json_response, Accept};
+
+/// Keys counted; the writes of others are only logged.
+pub const MAX_KEYS: usize = 8;
+
+/// Sectors of the store, once it called [`init`], and its sector erases.
+static STORE: CriticalSectionMutex<Cell<(Option<u32>, u32)>> =
+    CriticalSectionMutex::new(Cell::new((None, 0)));
+
+static KEYS: CriticalSectionMutex<RefCell<heapless::Vec<(&'static str, KeyWear), MAX_KEYS>>> =
+    CriticalSectionMutex::new(RefCell::new(heapless::Vec::new()));
+
+/// Declare the store, of `sectors` sectors.
+pub fn init(sectors: u32) {
+    STORE.lock(|store| store.set((Some(sectors), store.get().1)));
+}
+
+/// Count a write of `bytes` to `key`.
+pub fn record_write(key: &'static str, bytes: usize) {
+    let now = Instant::now().as_millis();
+    let counted = KEYS.lock(|keys| {
+        let mut keys = keys.borrow_mut();
+        if let Some((_, wear)) = keys.iter_mut().find(|(name, _)| *name == key) {
+            wear.record(bytes, now);
+            return true;
+        }
+        let mut wear = KeyWear::new();
+        wear.record(bytes, now);
+        keys.push((key, wear)).is_ok()
+    });
+    if !counted {
+        warn!("Flash: more than {} keys, {} not counted", MAX_KEYS, key);
+    }
+}
+
+/// Count a sector erase.
+pub fn record_erase() {
+    STORE.lock(|store| {
+        let (sectors, erases) = store.get();
+        store.set((sectors, erases.saturating_add(1)));
+    });
+}
+
+/// The counting hooks of the store: [`record_write`] and [`record_erase`].
+pub struct Counters;
+
+impl wot_esp_demo_core::store::Wear for Counters {
+    fn write(&mut self, key: &'static str, bytes: usize) {
+        record_write(key, bytes);
+    }
+
+    fn erase(&mut self) {
+        record_erase();
+    }
+}
+
+#[cfg(feature = "flash-store")]
+type Region = FlashRegion<'static, FlashStorage<'static>>;
+
+/// The store, once [`open`].
+#[cfg(feature = "flash-store")]
+static KV: CriticalSectionMutex<RefCell<Option<Store<Region, Counters>>>> =
+    CriticalSectionMutex::new(RefCell::new(None));
+
+/// Open the store on the `nvs` data partition of the partition table, and
+/// [`init`] the wear report with its size. Without the partition, or if it
+/// cannot be read, the store stays closed: [`read`] finds nothing and
+/// [`write`] does not persist.
+#[cfg(feature = "flash-store")]
+pub fn open(flash: FLASH<'static>) {
+    let flash = crate::mk_static!(FlashStorage<'static>, FlashStorage::new(flash));
+    let table = crate::mk_static!([u8; PARTITION_TABLE_MAX_LEN], [0; PARTITION_TABLE_MAX_LEN]);
+
+    let nvs = partitions::read_partition_table(flash, table)
+        .ok()
+        .and_then(|table| {
+            table
+                .find_partition(PartitionType::Data(DataPartitionSubType::Nvs))
+                .ok()
+                .flatten()
+        });
+    let Some(nvs) = nvs else {
+        warn!("Flash: no nvs partition, settings are not persisted");
+        return;
+    };
+
+    match Store::new(nvs.as_embedded_storage(flash), Counters) {
+        Ok(store) => {
+            info!("Flash: store of {} sectors", store.sectors());
+            init(store.sectors());
+            KV.lock(|kv| *kv.borrow_mut() = Some(store));
+        }
+        Err(_) => warn!("Flash: cannot read the store, settings are not persisted"),
+    }
+}
+
+/// The value last persisted under `key`, `None` if there is none or it does
+/// not parse as a `T`.
+#[cfg(feature = "flash-store")]
+pub fn read<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let value = KV.lock(|kv| kv.borrow_mut().as_mut()?.read(key).ok().flatten())?;
+    serde_json::from_slice(&value).ok()
+}
+
+/// Persist `value` under `key`, `false` if the store is closed or the write
+/// failed. Each call is a write of the flash: callers debounce.
+#[cfg(feature = "flash-store")]
+pub fn write<T: Serialize>(key: &'static str, value: &T) -> bool {
+    let Ok(value) = serde_json::to_vec(value) else {
+        return false;
+    };
+    let written = KV.lock(|kv| {
+        kv.borrow_mut()
+            .as_mut()
+            .map(|store| store.write(key, &value))
+    });
+    match written {
+        Some(Ok(())) => true,
+        Some(Err(e)) => {
+            let why = match e {
+                StoreError::Flash(_) => "flash error",
+                StoreError::TooLong => "value too long",
+                StoreError::Full => "store full",
+            };
+            warn!("Flash: cannot write {}: {}", key, why);
+            false
+        }
+        None => false,
+    }
+}
+
+/// Handler for `GET /debug/flash`.
+pub(crate) async fn serve(accept: Accept) -> impl IntoResponse {
+    let now = Instant::now().as_millis();
+    let max = wear::max_writes_per_hour(FLASH_MAX_WRITES_PER_HOUR);
+
+    let keys: Map<String, Value> = KEYS.lock(|keys| {
+        keys.borrow()
+            .iter()
+            .map(|(name, wear)| {
+                let key = json!({
+                    "writes": wear.writes,
+                    "bytes": wear.bytes,
+                    "writesPerHour": wear.rate(now),
+                    "warning": wear.is_worn(now, max),
+                });
+                ((*name).into(), key)
+            })
+            .collect()
+    });
+    let warning = keys.values().any(|key| key["warning"] == true);
+
+    let (sectors, erases) = STORE.lock(Cell::get);
+    let store = sectors.map(|sectors| {
+        json!({
+            "sectors": sectors,
+            "erases": erases,
+            "cyclesPerSector": cycles_per_sector(erases, sectors),
+        })
+    });
+
+    respond_json_negotiated(
+        accept,
+        to_json_response(&json!({
+            "store": store,
+            "maxWritesPerHour": max,
+            "warning": warning,
+            "keys": keys,
+        })),
+    )
+}
@@ -0,0 +1,64 @@
+//! Logging macros backed by `defmt` with the `defmt` feature and by `log`
+//! otherwise, so the same call sites build with either backend.
+//!
+//! Format strings must be understood by both: positional `{}`, `{:?}` and
+//! `{:02x}` only (no inline `{name}` arguments), and every argument must
+//! implement `defmt::Format` as well as `Display`/`Debug`.
+
+#[doc(hidden)]
+#[cfg(feature = "defmt")]
+pub use defmt as __defmt;
+#[doc(hidden)]
+#[cfg(not(feature = "defmt"))]
+pub use log as __log;
+
+#[cfg(feature = "defmt")]
+defmt::timestamp!("{=u64:us}", embassy_time::Instant::now().as_micros());
+
+#[cfg(feature = "defmt")]
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => { $crate::fmt::__defmt::error!($($arg)*) };
+}
+
+#[cfg(not(feature = "defmt"))]
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => { $crate::fmt::__log::error!($($arg)*) };
+}
+
+#[cfg(feature = "defmt")]
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => { $crate::fmt::__defmt::warn!($($arg)*) };
+}
+
+#[cfg(not(feature = "defmt"))]
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => { $crate::fmt::__log::warn!($($arg)*) };
+}
+
+#[cfg(feature = "defmt")]
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => { $crate::fmt::__defmt::info!($($arg)*) };
+}
+
+#[cfg(not(feature = "defmt"))]
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => { $crate::fmt::__log::info!($($arg)*) };
+}
+
+#[cfg(feature = "defmt")]
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => { $crate::fmt::__defmt::debug!($($arg)*) };
+}
+
+#[cfg(not(feature = "defmt"))]
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => { $crate::fmt::__log::debug!($($arg)*) };
+}
@@ -0,0 +1,194 @@
+//! Group control: forward property writes to the sibling Things.
+//!
+//! [`group_task`] browses `_wot._tcp` with one-shot mDNS queries and keeps the
+//! hosts whose TD has the same title as this Thing. The routes added by
+//! [`proxy`] write the value to every sibling at once, each with its own
+//! timeout, and answer with the outcome per sibling:
+//!
+//! ```json
+//! {"targets":[{"ip":"192.0.2.7","status":204},{"ip":"192.0.2.9","error":"timeout"}]}
+//! ```
+
+use core::cell::RefCell;
+
+use alloc::{format, vec, vec::Vec};
+use embassy_futures::join::join_array;
+use embassy_net::{
+    udp::{PacketMetadata, UdpSocket},
+    IpAddress, IpEndpoint, Stack,
+};
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use embassy_time::{with_deadline, Duration, Instant, Timer};
+use picoserve::{
+    routing::{put, PathRouter},
+    Router,
+};
+use serde_json::{json, Value};
+use wot_esp_demo_core::dns_sd;
+
+use crate::{
+    audit, connectivity, http_client::Client, to_json_response, write::MAX_WRITE_BODY, ValueBody,
+};
+
+/// Sibling Things written to at once.
+pub const MAX_SIBLINGS: usize = 4;
+
+/// Sockets used: the mDNS query socket and one per sibling written to.
+pub const GROUP_STACK_SIZE: usize = 1 + MAX_SIBLINGS;
+
+/// Hosts answering a browse, more than the siblings as other Things answer too.
+const MAX_CANDIDATES: usize = 8;
+
+/// How often the siblings are looked up again.
+const BROWSE_EVERY: Duration = Duration::from_secs(60);
+
+/// How long the answers to a browse are collected.
+const BROWSE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Time given to each sibling to serve its TD or take a write.
+const TARGET_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Largest TD read from a candidate.
+const MAX_TD_LEN: usize = 8 * 1024;
+
+static SIBLINGS: CriticalSectionMutex<RefCell<heapless::Vec<IpAddress, MAX_SIBLINGS>>> =
+    CriticalSectionMutex::new(RefCell::new(heapless::Vec::new()));
+
+/// The siblings found by the last browse.
+#[must_use]
+pub fn siblings() -> heapless::Vec<IpAddress, MAX_SIBLINGS> {
+    SIBLINGS.lock(|s| s.borrow().clone())
+}
+
+/// The hosts other than `own` answering a `_wot._tcp` browse.
+async fn browse(stack: Stack<'_>, own: IpAddress) -> heapless::Vec<IpAddress, MAX_CANDIDATES> {
+    let mut rx_meta = [PacketMetadata::EMPTY; MAX_CANDIDATES];
+    let mut rx_buffer = [0; 4096];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_buffer = [0; 64];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+
+    let mut found = heapless::Vec::new();
+    let mut query = [0; 64];
+    #[allow(clippy::cast_possible_truncation)]
+    let id = Instant::now().as_ticks() as u16;
+    let Some(len) = dns_sd::ptr_query(id, "_wot._tcp.local", &mut query) else {
+        return found;
+    };
+    let mdns = IpEndpoint::new(IpAddress::v4(224, 0, 0, 251), 5353);
+    if socket.bind(0).is_err() || socket.send_to(&query[..len], mdns).await.is_err() {
+        warn!("Group: cannot send the mDNS query");
+        return found;
+    }
+
+    let deadline = Instant::now() + BROWSE_WINDOW;
+    let mut response = [0; 1500];
+    while let Ok(received) = with_deadline(deadline, socket.recv_from(&mut response)).await {
+        let Ok((len, meta)) = received else {
+            continue;
+        };
+        let ip = meta.endpoint.addr;
+        if ip != own && dns_sd::is_answer(&response[..len], id) && !found.contains(&ip) {
+            let _ = found.push(ip);
+        }
+    }
+
+    found
+}
+
+fn client(stack: Stack<'_>) -> Client<'_> {
+    Client::new(stack).with_timeout(TARGET_TIMEOUT)
+}
+
+/// Whether the TD of `ip` has the title `name`.
+async fn is_sibling(stack: Stack<'_>, ip: IpAddress, name: &str) -> bool {
+    let mut buf = vec![0; MAX_TD_LEN];
+    let url = format!("http://{ip}/");
+    match client(stack).get(&url, &mut buf).await {
+        Ok(response) if response.status == 200 => {
+            serde_json::from_slice::<Value>(response.body).is_ok_and(|td| td["title"] == name)
+        }
+        _ => false,
+    }
+}
+
+/// Look up the other Things titled `name` every minute.
+#[embassy_executor::task]
+pub async fn group_task(stack: Stack<'static>, name: &'static str) {
+    loop {
+        if let Some(info) = connectivity::net_info().filter(|info| info.up) {
+            let own = IpAddress::Ipv4(info.config.address.address());
+            let mut found = heapless::Vec::new();
+            for ip in browse(stack, own).await {
+                if is_sibling(stack, ip, name).await && found.push(ip).is_err() {
+                    warn!("Group: more than {} siblings", MAX_SIBLINGS);
+                    break;
+                }
+            }
+
+            if found != siblings() {
+                info!("Group: {} siblings", found.len());
+            }
+            SIBLINGS.lock(|s| *s.borrow_mut() = found);
+        }
+
+        Timer::after(BROWSE_EVERY).await;
+    }
+}
+
+/// Write `body` to `path` of `target`, if any.
+async fn write_sibling(
+    stack: Stack<'_>,
+    target: Option<IpAddress>,
+    path: &str,
+    body: &[u8],
+) -> Option<Value> {
+    let ip = target?;
+    let mut buf = [0; 256];
+    let url = format!("http://{ip}{path}");
+    let ip = format!("{ip}");
+
+    Some(match client(stack).put_json(&url, body, &mut buf).await {
+        Ok(response) => json!({ "ip": ip, "status": response.status }),
+        Err(e) => json!({ "ip": ip, "error": e.as_str() }),
+    })
+}
+
+/// Write `body` to `path` of every sibling at once.
+async fn write_siblings(path: &str, body: &[u8]) -> Value {
+    let Some(stack) = connectivity::stack() else {
+        return json!({ "targets": [] });
+    };
+
+    let siblings = siblings();
+    let writes: [_; MAX_SIBLINGS] =
+        core::array::from_fn(|i| write_sibling(stack, siblings.get(i).copied(), path, body));
+    let targets: Vec<Value> = join_array(writes).await.into_iter().flatten().collect();
+
+    json!({ "targets": targets })
+}
+
+/// Serve the write-only group property at `path`, forwarding the value to
+/// the property at `property_path` of every sibling.
+///
+/// Declare it in the TD with [`crate::EspThing::GROUP`].
+pub fn proxy<S, R: PathRouter<S>>(
+    router: Router<R, S>,
+    path: &'static str,
+    property_path: &'static str,
+) -> Router<impl PathRouter<S>, S> {
+    router.route(
+        path,
+        put(move |ValueBody::<MAX_WRITE_BODY>(value)| async move {
+            let body = serde_json::to_vec(&value).unwrap_or_default();
+            audit::record(path.trim_start_matches("/properties/"), &value);
+            to_json_response(&write_siblings(property_path, &body).await)
+        }),
+    )
+}
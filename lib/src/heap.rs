@@ -0,0 +1,62 @@
+//! Heap sizing and the low-memory warning.
+//!
+//! The heap is a `static` sized by [`crate::EspThing::HEAP_SIZE`], so it is
+//! declared by the [`crate::run!`] macro in the binary rather than in the
+//! generic [`crate::EspThing::run`], which takes the [`Heap`] proof so a
+//! Thing run without the macro does not build.
+
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use embassy_time::{Duration, Timer};
+
+/// How often the free heap is checked against the threshold.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Proof that the heap was set up, handed by [`crate::run!`] to
+/// [`crate::EspThing::run`].
+pub struct Heap(());
+
+impl Heap {
+    /// # Safety
+    ///
+    /// A heap of [`crate::EspThing::HEAP_SIZE`] bytes must have been added to
+    /// the allocator, as [`crate::run!`] does.
+    #[doc(hidden)]
+    #[must_use]
+    pub const unsafe fn new_unchecked() -> Self {
+        Self(())
+    }
+}
+
+static LOW: CriticalSectionMutex<Cell<bool>> = CriticalSectionMutex::new(Cell::new(false));
+
+/// Bytes of heap in use and free.
+#[must_use]
+pub fn usage() -> (usize, usize) {
+    (esp_alloc::HEAP.used(), esp_alloc::HEAP.free())
+}
+
+/// Whether the free heap was below the threshold at the last check.
+#[must_use]
+pub fn is_low() -> bool {
+    LOW.lock(Cell::get)
+}
+
+/// Warn once each time the free heap drops below `threshold` bytes.
+#[embassy_executor::task]
+pub async fn heap_monitor_task(threshold: usize) {
+    loop {
+        crate::heartbeat!("heap", CHECK_INTERVAL);
+        let (used, free) = usage();
+        let low = free < threshold;
+        if low && !is_low() {
+            warn!("Heap low: {} bytes free, {} used", free, used);
+        } else if !low && is_low() {
+            info!("Heap recovered: {} bytes free", free);
+        }
+        LOW.lock(|l| l.set(low));
+
+        Timer::after(CHECK_INTERVAL).await;
+    }
+}
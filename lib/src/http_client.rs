@@ -0,0 +1,283 @@
+//! Outbound HTTP/1.1 requests over embassy-net TCP.
+//!
+//! One request per connection (`Connection: close`), the response read whole
+//! into a buffer of the caller. Nothing is allocated: the socket buffers live
+//! in the request future, so dropping it (e.g. in a `select` or on a timeout)
+//! aborts the connection and frees the socket.
+//!
+//! ```ignore
+//! let client = http_client::Client::new(stack).with_timeout(Duration::from_secs(3));
+//! let mut buf = [0; 512];
+//! let response = client.put_json("http://192.0.2.7/properties/on", b"true", &mut buf).await?;
+//! ```
+
+use core::fmt::Write as _;
+
+use embassy_net::{tcp::TcpSocket, IpEndpoint, Stack};
+use embassy_time::{with_timeout, Duration};
+use embedded_io_async::Write as _;
+use wot_esp_demo_core::http::{parse_response, parse_url};
+
+use crate::dns;
+
+/// Sockets used by the start-up self-test; the callers of [`Client`] count
+/// the sockets of their own requests.
+pub const HTTP_CLIENT_STACK_SIZE: usize = if cfg!(feature = "self-test") { 1 } else { 0 };
+
+/// Time a request may take unless set with [`Client::with_timeout`].
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const RX_BUFFER_LEN: usize = 1024;
+const TX_BUFFER_LEN: usize = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Put,
+    Post,
+}
+
+impl Method {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Get => "GET",
+            Self::Put => "PUT",
+            Self::Post => "POST",
+        }
+    }
+}
+
+/// Why a request failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// Not an `http://` URL.
+    Url,
+    /// The host name could not be resolved, see [`dns::resolve`].
+    Dns,
+    Connect,
+    Io,
+    Timeout,
+    /// The response is not HTTP.
+    Response,
+    /// The response does not fit in the buffer.
+    TooLarge,
+}
+
+impl Error {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Url => "invalid url",
+            Self::Dns => "dns",
+            Self::Connect => "connect",
+            Self::Io => "io",
+            Self::Timeout => "timeout",
+            Self::Response => "invalid response",
+            Self::TooLarge => "response too large",
+        }
+    }
+}
+
+/// A response, its body borrowed from the buffer it was read into.
+#[derive(Debug)]
+pub struct Response<'b> {
+    pub status: u16,
+    pub body: &'b [u8],
+}
+
+/// Makes requests over `stack`, each bounded by a timeout.
+#[derive(Clone, Copy)]
+pub struct Client<'a> {
+    stack: Stack<'a>,
+    timeout: Duration,
+}
+
+impl<'a> Client<'a> {
+    #[must_use]
+    pub const fn new(stack: Stack<'a>) -> Self {
+        Self {
+            stack,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Limit every request, name resolution included, to `timeout`.
+    #[must_use]
+    pub const fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    pub async fn get<'b>(&self, url: &str, buf: &'b mut [u8]) -> Result<Response<'b>, Error> {
+        self.request(Method::Get, url, None, buf).await
+    }
+
+    pub async fn put_json<'b>(
+        &self,
+        url: &str,
+        body: &[u8],
+        buf: &'b mut [u8],
+    ) -> Result<Response<'b>, Error> {
+        self.request(Method::Put, url, Some(body), buf).await
+    }
+
+    pub async fn post_json<'b>(
+        &self,
+        url: &str,
+        body: &[u8],
+        buf: &'b mut [u8],
+    ) -> Result<Response<'b>, Error> {
+        self.request(Method::Post, url, Some(body), buf).await
+    }
+
+    /// Send `method url` with the JSON `body` and read the response into
+    /// `buf`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request failed or timed out, or if the
+    /// response is not HTTP or is larger than `buf`.
+    pub async fn request<'b>(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<&[u8]>,
+        buf: &'b mut [u8],
+    ) -> Result<Response<'b>, Error> {
+        let (host, port, path) = parse_url(url).ok_or(Error::Url)?;
+
+        let len = with_timeout(
+            self.timeout,
+            self.exchange(method, host, port, path, body, buf),
+        )
+        .await
+        .map_err(|_| Error::Timeout)??;
+
+        let (status, body) = parse_response(&buf[..len]).ok_or(Error::Response)?;
+        Ok(Response { status, body })
+    }
+
+    /// Run the request, returning the length of the response in `buf`.
+    async fn exchange(
+        &self,
+        method: Method,
+        host: &str,
+        port: u16,
+        path: &str,
+        body: Option<&[u8]>,
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        let ip = dns::resolve(self.stack, host)
+            .await
+            .map_err(|_| Error::Dns)?;
+
+        let mut rx_buffer = [0; RX_BUFFER_LEN];
+        let mut tx_buffer = [0; TX_BUFFER_LEN];
+        let mut socket = TcpSocket::new(self.stack, &mut rx_buffer, &mut tx_buffer);
+        socket
+            .connect(IpEndpoint::new(ip, port))
+            .await
+            .map_err(|_| Error::Connect)?;
+
+        // The head goes out in pieces, so it needs no buffer of its own.
+        let mut content_length = heapless::String::<8>::new();
+        if let Some(body) = body {
+            let _ = write!(content_length, "{}", body.len());
+        }
+        let head = [
+            method.as_str(),
+            " ",
+            path,
+            " HTTP/1.1\r\nHost: ",
+            host,
+            "\r\nAccept: application/json, application/td+json\r\nConnection: close\r\n",
+        ];
+        let content = [
+            "Content-Type: application/json\r\nContent-Length: ",
+            content_length.as_str(),
+            "\r\n",
+        ];
+        let parts = head
+            .iter()
+            .chain(body.map_or(&[][..], |_| &content[..]))
+            .chain(&["\r\n"]);
+        for part in parts {
+            socket
+                .write_all(part.as_bytes())
+                .await
+                .map_err(|_| Error::Io)?;
+        }
+        if let Some(body) = body {
+            socket.write_all(body).await.map_err(|_| Error::Io)?;
+        }
+
+        let mut len = 0;
+        loop {
+            if len == buf.len() {
+                // Anything past the buffer means the response is cut.
+                let mut more = [0];
+                return match socket.read(&mut more).await {
+                    Ok(0) => Ok(len),
+                    _ => Err(Error::TooLarge),
+                };
+            }
+            match socket.read(&mut buf[len..]).await.map_err(|_| Error::Io)? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        socket.close();
+
+        Ok(len)
+    }
+}
+
+/// Read the Thing's own TD and each of its properties over the network, log
+/// a table of the outcomes and keep them for `lastSelfTest`.
+///
+/// Run at start-up with the `self-test` feature, it checks the stack, the web
+/// server, the routing and serialization of every property and the sensors
+/// behind them end to end, see [`wot_esp_demo_core::self_test::reads`]. The
+/// Thing goes on serving whatever the outcome.
+#[cfg(feature = "self-test")]
+#[embassy_executor::task]
+pub async fn self_test_task(stack: Stack<'static>) {
+    use alloc::{format, vec::Vec};
+    use wot_esp_demo_core::self_test::{read_outcome, reads, results, row, Outcome};
+
+    // Let the web tasks start listening.
+    embassy_time::Timer::after(Duration::from_secs(1)).await;
+
+    let Some(config) = stack.config_v4() else {
+        return;
+    };
+    let Some(td) = crate::td::served() else {
+        return;
+    };
+    let reads = reads(&serde_json::from_str(td.json()).unwrap_or_default());
+    drop(td);
+
+    let client = Client::new(stack);
+    let mut buf = [0; 8 * 1024];
+    let mut outcomes = Vec::with_capacity(reads.len());
+    for (name, path) in &reads {
+        let url = format!("http://{}{}", config.address.address(), path);
+        let (outcome, detail) = match client.get(&url, &mut buf).await {
+            Ok(response) => (
+                read_outcome(response.status),
+                format!("{}", response.status),
+            ),
+            Err(e) => (Outcome::Failed, e.as_str().into()),
+        };
+        let line = row(name, path, outcome, &detail);
+        if outcome == Outcome::Ok {
+            info!("Self-test: {}", line.as_str());
+        } else {
+            warn!("Self-test: {}", line.as_str());
+        }
+        outcomes.push((name.as_str(), outcome));
+    }
+
+    let passed = outcomes.iter().filter(|(_, o)| *o == Outcome::Ok).count();
+    info!("Self-test: {} of {} reads passed", passed, reads.len());
+    crate::self_test::record_startup(results(outcomes));
+}
@@ -0,0 +1,67 @@
+//! `POST /debug/i2c-scan`: the addresses acknowledging on the I2C bus of the
+//! app, when wiring a new sensor (the `i2c-scan` feature).
+//!
+//! The app owns the bus, so it adds the route itself and hands [`serve`] a
+//! scan run by the task of the sensors, between two measurements, with
+//! [`wot_esp_demo_core::i2c_scan::scan`]. The scan needs
+//! `Authorization: Bearer <WRITE_TOKEN>`, and runs at most once every
+//! 5 seconds; a request in between gets 429 with `Retry-After`:
+//!
+//! ```text
+//! $ curl -X POST -H 'Authorization: Bearer <WRITE_TOKEN>' http://<ip>/debug/i2c-scan
+//! {"addresses":["0x70"]}
+//! ```
+
+use core::{cell::Cell, future::Future};
+
+use alloc::{string::ToString, vec::Vec};
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use embassy_time::Instant;
+use picoserve::response::{IntoResponse, Response, StatusCode};
+use wot_esp_demo_core::i2c_scan::{retry_after_s, to_json};
+
+pub use crate::origin::Authorized;
+
+/// Uptime of the last scan, in milliseconds.
+static LAST_SCAN: CriticalSectionMutex<Cell<Option<u64>>> =
+    CriticalSectionMutex::new(Cell::new(None));
+
+/// Handler for `POST /debug/i2c-scan`: the addresses found by `scan`, 503
+/// if it returns `None`, e.g. when the task of the sensors is busy.
+///
+/// Answers 403 to a request not [`Authorized`] and 429 within 5 seconds of
+/// the last scan, without running `scan`.
+pub async fn serve(
+    Authorized(authorized): Authorized,
+    scan: impl Future<Output = Option<Vec<u8>>>,
+) -> impl IntoResponse {
+    if !authorized {
+        return Err(Err(Response::new(
+            StatusCode::FORBIDDEN,
+            "Send Authorization: Bearer <WRITE_TOKEN> to scan the I2C bus",
+        )
+        .with_header("Content-Type", "text/plain")));
+    }
+
+    let now = Instant::now().as_millis();
+    if let Some(wait) = retry_after_s(LAST_SCAN.lock(Cell::get), now) {
+        return Err(Ok(Response::new(
+            StatusCode::TOO_MANY_REQUESTS,
+            "The I2C bus was scanned less than 5 seconds ago",
+        )
+        .with_header("Content-Type", "text/plain")
+        .with_header("Retry-After", wait)));
+    }
+    LAST_SCAN.lock(|last| last.set(Some(now)));
+
+    info!("Scanning the I2C bus");
+    Ok(match scan.await {
+        Some(found) => Ok(Response::ok(to_json(&found).to_string())
+            .with_header("Content-Type", "application/json")),
+        None => Err(Response::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The I2C bus is busy, try again later",
+        )
+        .with_header("Content-Type", "text/plain")),
+    })
+}
@@ -0,0 +1,114 @@
+//! The title and location of the Thing, given at run time at
+//! `/config/identity` instead of at build time:
+//!
+//! ```sh
+//! curl -X PUT -d '{"title":"Greenhouse North","location":"north"}' http://<ip>/config/identity
+//! ```
+//!
+//! The title becomes the TD `title`, the TD and its compressed copy are
+//! regenerated, and mDNS announces the instance `<title>-<location>` from
+//! then on, see [`Identity::instance_name`]. Until a title is written the
+//! Thing is known by [`crate::EspThing::NAME`]. The instance name mDNS
+//! claimed follows the title in the TD, see
+//! [`wot_esp_demo_core::identity::titled`].
+//!
+//! There is no flash store in the tree yet, so the identity is kept in RAM
+//! and lost on reset.
+
+use core::cell::{Cell, RefCell};
+
+use alloc::string::String;
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, CriticalSectionMutex},
+    signal::Signal,
+};
+use picoserve::response::{IntoResponse, StatusCode};
+use serde_json::json;
+
+pub use wot_esp_demo_core::identity::{Identity, MAX_LEN};
+
+use crate::{
+    audit, respond_json_negotiated, td, to_json_response, write::InvalidValue, Accept, ValueBody,
+};
+
+/// The identity written, `None` until the first write.
+static IDENTITY: CriticalSectionMutex<RefCell<Option<Identity>>> =
+    CriticalSectionMutex::new(RefCell::new(None));
+
+/// The Thing name, the title until one is written.
+static NAME: CriticalSectionMutex<Cell<&'static str>> = CriticalSectionMutex::new(Cell::new(""));
+
+/// The mDNS instance name claimed, empty until mDNS is up.
+static INSTANCE: CriticalSectionMutex<RefCell<String>> =
+    CriticalSectionMutex::new(RefCell::new(String::new()));
+
+static TD_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static MDNS_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Fall back to the Thing `name` until an identity is written.
+pub(crate) fn init(name: &'static str) {
+    NAME.lock(|n| n.set(name));
+}
+
+/// The identity written, if any.
+#[must_use]
+pub fn identity() -> Option<Identity> {
+    IDENTITY.lock(|identity| identity.borrow().clone())
+}
+
+/// The title of the Thing.
+#[must_use]
+pub fn title() -> String {
+    identity().map_or_else(|| NAME.lock(Cell::get).into(), |identity| identity.title)
+}
+
+/// The next change of the identity, for the mDNS task.
+pub(crate) async fn mdns_changed() {
+    MDNS_CHANGED.wait().await;
+}
+
+/// Show `instance`, the instance name mDNS claimed, after the title in the
+/// TD.
+pub(crate) fn claimed(instance: &str) {
+    if INSTANCE.lock(|current| current.replace(instance.into()) != instance) {
+        TD_CHANGED.signal(());
+    }
+}
+
+/// Handler for `GET /config/identity`.
+pub(crate) async fn read(accept: Accept) -> impl IntoResponse {
+    let body = identity().map_or_else(
+        || json!({ "title": NAME.lock(Cell::get), "location": null }),
+        |identity| identity.to_json(),
+    );
+    respond_json_negotiated(accept, to_json_response(&body))
+}
+
+/// Handler for `PUT /config/identity`.
+pub(crate) async fn write(ValueBody(body): ValueBody) -> impl IntoResponse {
+    let identity = Identity::parse(&body).map_err(InvalidValue)?;
+
+    audit::record("identity", &identity.to_json());
+    info!("Identity: {}", identity.title.as_str());
+    IDENTITY.lock(|current| current.replace(Some(identity)));
+    TD_CHANGED.signal(());
+    MDNS_CHANGED.signal(());
+
+    Ok::<_, InvalidValue>(StatusCode::NO_CONTENT)
+}
+
+/// Regenerate the TD with the title of every identity written, and the
+/// instance name of every mDNS claim.
+pub(crate) async fn apply() {
+    loop {
+        TD_CHANGED.wait().await;
+        let title = wot_esp_demo_core::identity::titled(
+            &title(),
+            &INSTANCE.lock(|instance| instance.borrow().clone()),
+        );
+        if let Err(e) = td::update(|td| wot_esp_demo_core::identity::retitle_json(td, &title)).await
+        {
+            warn!("Cannot retitle the TD: {}", e);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! JSON responses serialized on the stack, so that a property read
+//! allocates nothing.
+//!
+//! [`crate::to_json_response`] answers with an [`InlineJson`]: a value of
+//! up to [`INLINE_LEN`] bytes is serialized with
+//! [`wot_esp_demo_core::inline::serialize`] into a buffer held by the
+//! handler, in the future of the web task serving it, and the headers are
+//! static strings. A larger value, e.g. a TD fragment, falls back to a
+//! `String` on the heap. Check it with the `alloc-stats` feature, see
+//! [`crate::alloc_stats`].
+
+use alloc::string::String;
+use picoserve::{
+    io::Read,
+    response::{Connection, IntoResponse, Response, ResponseWriter},
+    ResponseSent,
+};
+use serde::Serialize;
+
+pub use wot_esp_demo_core::inline::INLINE_LEN;
+
+/// A JSON body, inline when it fits in [`INLINE_LEN`] bytes.
+pub enum InlineJson {
+    Inline { buf: [u8; INLINE_LEN], len: usize },
+    Heap(String),
+}
+
+impl InlineJson {
+    /// Serialize `data`, on the heap only if it does not fit inline.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` cannot be serialized to JSON.
+    pub fn new<T: Serialize + ?Sized>(data: &T) -> Self {
+        let mut buf = [0; INLINE_LEN];
+        match wot_esp_demo_core::inline::serialize(data, &mut buf) {
+            Some(len) => Self::Inline { buf, len },
+            None => Self::Heap(serde_json::to_string(data).unwrap()),
+        }
+    }
+}
+
+impl IntoResponse for InlineJson {
+    async fn write_to<R: Read, W: ResponseWriter<Error = R::Error>>(
+        self,
+        connection: Connection<'_, R>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        match self {
+            Self::Inline { buf, len } => {
+                Response::ok(&buf[..len])
+                    .with_header("Content-Type", "application/json")
+                    .write_to(connection, response_writer)
+                    .await
+            }
+            Self::Heap(body) => {
+                Response::ok(body)
+                    .with_header("Content-Type", "application/json")
+                    .write_to(connection, response_writer)
+                    .await
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Request latency histograms, served as JSON at `/debug/latency`.
+//!
+//! Add [`LatencyLayer`] last in `build_app` to time every request, from its
+//! routing to the end of the response, into the buckets of its
+//! [`RouteClass`], and count it for [`crate::affordance_stats`]. The
+//! counters are atomics, so timing a request allocates nothing.
+//! `POST /debug/latency/reset` zeroes them:
+//!
+//! ```json
+//! {"td":{"le1ms":0,"le5ms":2,"le20ms":1,"le100ms":0,"le500ms":0,"gt500ms":0},..}
+//! ```
+
+use portable_atomic::{AtomicU32, Ordering};
+
+use alloc::string::String;
+use embassy_time::Instant;
+use picoserve::{
+    io::Read,
+    request::RequestParts,
+    response::{Body, Connection, HeadersIter, IntoResponse, Response, ResponseWriter, StatusCode},
+    routing::{Layer, Next},
+    ResponseSent,
+};
+use serde_json::{Map, Value};
+use wot_esp_demo_core::latency::{bucket, BUCKETS, BUCKET_NAMES};
+
+pub use wot_esp_demo_core::latency::RouteClass;
+
+use crate::{respond_json_negotiated, to_json_response, Accept, WriteGuard};
+
+static HISTOGRAMS: [[AtomicU32; BUCKETS]; RouteClass::ALL.len()] =
+    [const { [const { AtomicU32::new(0) }; BUCKETS] }; RouteClass::ALL.len()];
+
+fn histogram(class: RouteClass) -> &'static [AtomicU32; BUCKETS] {
+    &HISTOGRAMS[class as usize]
+}
+
+/// Times the requests of the routes it wraps, and counts them per
+/// affordance.
+pub struct LatencyLayer;
+
+impl<State, PathParameters> Layer<State, PathParameters> for LatencyLayer {
+    type NextState = State;
+    type NextPathParameters = PathParameters;
+
+    async fn call_layer<
+        'a,
+        R: Read + 'a,
+        NextLayer: Next<'a, R, Self::NextState, Self::NextPathParameters>,
+        W: ResponseWriter<Error = R::Error>,
+    >(
+        &self,
+        next: NextLayer,
+        state: &State,
+        path_parameters: PathParameters,
+        request_parts: RequestParts<'_>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        let path = crate::prefix::strip(request_parts.path().encoded());
+        crate::affordance_stats::count(request_parts.method(), path);
+
+        match RouteClass::of(request_parts.method(), path) {
+            Some(class) => {
+                let response_writer = TimedResponseWriter {
+                    class,
+                    start: Instant::now(),
+                    #[cfg(feature = "alloc-stats")]
+                    allocated: crate::alloc_stats::allocated(),
+                    response_writer,
+                };
+                next.run(state, path_parameters, response_writer).await
+            }
+            None => next.run(state, path_parameters, response_writer).await,
+        }
+    }
+}
+
+struct TimedResponseWriter<W> {
+    class: RouteClass,
+    start: Instant,
+    /// Bytes allocated on the heap when the request started.
+    #[cfg(feature = "alloc-stats")]
+    allocated: usize,
+    response_writer: W,
+}
+
+impl<W: ResponseWriter> ResponseWriter for TimedResponseWriter<W> {
+    type Error = W::Error;
+
+    async fn write_response<R: Read<Error = Self::Error>, H: HeadersIter, B: Body>(
+        self,
+        connection: Connection<'_, R>,
+        response: Response<H, B>,
+    ) -> Result<ResponseSent, Self::Error> {
+        let sent = self
+            .response_writer
+            .write_response(connection, response)
+            .await;
+
+        let us = self.start.elapsed().as_micros();
+        histogram(self.class)[bucket(us)].fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "alloc-stats")]
+        crate::alloc_stats::record(self.class, self.allocated);
+
+        sent
+    }
+}
+
+/// Handler for `GET /debug/latency`.
+pub(crate) async fn serve(accept: Accept) -> impl IntoResponse {
+    let report: Map<String, Value> = RouteClass::ALL
+        .iter()
+        .map(|&class| {
+            let counts: Map<String, Value> = BUCKET_NAMES
+                .iter()
+                .zip(histogram(class))
+                .map(|(name, count)| ((*name).into(), count.load(Ordering::Relaxed).into()))
+                .collect();
+            (class.as_str().into(), Value::Object(counts))
+        })
+        .collect();
+
+    respond_json_negotiated(accept, to_json_response(&report))
+}
+
+/// Handler for `POST /debug/latency/reset`.
+pub(crate) async fn reset(_guard: WriteGuard) -> impl IntoResponse {
+    for count in HISTOGRAMS.iter().flatten() {
+        count.store(0, Ordering::Relaxed);
+    }
+    StatusCode::NO_CONTENT
+}
@@ -7,23 +7,117 @@ extern crate alloc;
 use alloc::{
     format,
     string::{String, ToString},
+    vec::Vec,
 };
+use embassy_futures::select::{select, Either};
 use embassy_net::{Runner, Stack};
-use embassy_time::{Duration, Timer};
-use esp_println::println;
-use esp_radio::wifi::{
-    sta::StationConfig, Config, ControllerConfig, WifiController, Interface,
-};
+use embassy_time::{Duration, Instant, Timer};
+use esp_radio::wifi::{ControllerConfig, Interface, WifiController};
 
 pub use esp_radio::wifi::PowerSaveMode;
 use picoserve::{
     extract::State,
     response::{IntoResponse, Response, StatusCode},
-    routing::get,
+    routing::{get, post},
     AppRouter, AppWithStateBuilder,
 };
+use wot_esp_demo_core::cbor;
+
+// Must come first so the other modules see the logging macros.
+#[macro_use]
+#[doc(hidden)]
+pub mod fmt;
 
+pub mod affordance_stats;
+pub mod alarm;
+#[cfg(feature = "alloc-stats")]
+pub mod alloc_stats;
+pub mod audit;
+pub mod batch;
+pub mod boot_status;
+pub mod brownout;
+pub mod cached;
+pub mod capabilities;
+#[cfg(feature = "maintenance-ap")]
+pub mod captive_dns;
+pub mod clock;
+pub mod compress;
+pub mod config;
+pub mod configuration;
+pub mod connectivity;
+#[cfg(feature = "debug-port")]
+pub mod debug_port;
+#[cfg(feature = "directory")]
+pub mod directory;
+#[cfg(feature = "dns")]
+pub mod dns;
+pub mod entropy;
+pub mod error;
+pub mod events;
+pub mod flash;
+#[cfg(feature = "group")]
+pub mod group;
+pub mod heap;
+#[cfg(feature = "http-client")]
+pub mod http_client;
+#[cfg(feature = "i2c-scan")]
+pub mod i2c_scan;
+pub mod identity;
+pub mod inline;
+pub mod latency;
+pub mod link_format;
+#[cfg(feature = "debug-log")]
+pub mod log_ring;
+#[cfg(any(feature = "debug-log", feature = "syslog"))]
+mod logger;
+#[cfg(feature = "low-power")]
+pub mod low_power;
+#[cfg(feature = "maintenance-ap")]
+pub mod maintenance_ap;
 pub mod mdns;
+pub mod origin;
+pub mod peers;
+pub mod prefix;
+#[cfg(feature = "rules")]
+pub mod rules;
+pub mod self_test;
+#[cfg(feature = "shutdown")]
+pub mod shutdown;
+pub mod slot;
+#[cfg(feature = "smartled")]
+pub mod smartled;
+#[cfg(feature = "sntp")]
+pub mod sntp;
+#[cfg(feature = "syslog")]
+pub mod syslog;
+pub mod tasks;
+pub mod td;
+pub mod tm;
+#[cfg(feature = "udp")]
+pub mod udp;
+#[cfg(feature = "ui")]
+pub mod ui;
+pub mod wifi;
+pub mod write;
+
+pub use alarm::Alarm;
+pub use cached::{respond_cached_result, Cached};
+pub use compress::AcceptEncoding;
+pub use connectivity::Connectivity;
+pub use error::ThingError;
+pub use events::{
+    EventLog, EventStream, LastEventId, PubSubEvents, Sequenced, SseEvents, MAX_STREAM_LIFETIME,
+};
+pub use self_test::Outcome;
+pub use slot::Slot;
+pub use wot_esp_demo_core::{
+    forms::{sse_event_form, sse_observe_form},
+    negotiate::{accepts, prefers},
+};
+pub use write::{ActionBody, JsonBody, ValueBody, WriteGuard};
+
+/// Delivery totals of the event streams, served at `/debug/events`.
+pub(crate) static EVENT_COUNTERS: events::Counters = events::Counters::new();
 
 // https://github.com/embassy-rs/static-cell/issues/16
 #[macro_export]
@@ -34,8 +128,203 @@ macro_rules! mk_static {
     }};
 }
 
-pub const SSID: &str = env!("SSID");
-pub const PASSWORD: &str = env!("PASSWORD");
+#[doc(hidden)]
+pub use esp_alloc as __esp_alloc;
+#[doc(hidden)]
+pub use picoserve as __picoserve;
+
+/// Add `/properties/<name>` routes for properties kept in the app state to
+/// `router`.
+///
+/// `get` reads the value from the state and responds with it as JSON or
+/// CBOR, or with one member of it for `?field=`, see
+/// [`respond_negotiated_field`]. The
+/// optional `put` applies a value taken from a [`JsonBody`], records it in the
+/// [`audit`] trail and responds with 204; `validate` may reject the value
+/// first, responding with 400 and its message.
+///
+/// The same `get`, `validate` and `put` serve the properties in a `POST
+/// /batch`, see [`batch`].
+///
+/// ```ignore
+/// properties!(td_routes::<AppState>(), AppState, {
+///     "on" => get: |s| s.light.lock().await.on,
+///         put: |s, on: bool| s.light.lock().await.power(on);
+///     "level" => get: |s| s.level.get(),
+///         put: |s, level: u8| s.level.set(level),
+///         validate: |level| if *level <= 100 { Ok(()) } else { Err("Level above 100") };
+/// })
+/// ```
+#[macro_export]
+macro_rules! properties {
+    ($router:expr, $state:ty, {
+        $($name:literal => get: |$get_state:ident| $get:expr
+            $(, put: |$put_state:ident, $value:ident: $ty:ty| $put:expr
+                $(, validate: |$checked:ident| $validate:expr)?)?;)*
+    }) => {{
+        use $crate::__picoserve::{extract::State, response::StatusCode, routing::get};
+
+        let router = $router;
+        $(
+            let router = router.route(
+                concat!("/properties/", $name),
+                get(
+                    |State($get_state): State<$state>,
+                     accept: $crate::Accept,
+                     field: $crate::Field| async move {
+                        $crate::respond_negotiated_field(accept, &$get, field)
+                    },
+                )
+                $(.put(
+                    |State($put_state): State<$state>, $crate::JsonBody::<$ty>($value)| async move {
+                        $(
+                            let check = |$checked: &$ty| -> Result<(), &'static str> { $validate };
+                            check(&$value).map_err($crate::write::InvalidValue)?;
+                        )?
+                        $crate::audit::record($name, &$value);
+                        $put;
+                        Ok::<_, $crate::write::InvalidValue>(StatusCode::NO_CONTENT)
+                    },
+                ))?,
+            );
+        )*
+
+        fn dispatch<'a>(
+            state: &'a dyn core::any::Any,
+            op: &'a $crate::batch::Op,
+        ) -> $crate::batch::Pending<'a> {
+            $crate::batch::pending(async move {
+                let state: $state = *state.downcast_ref::<$state>()?;
+                #[allow(unreachable_patterns)]
+                let outcome = match (op.name.as_str(), &op.write) {
+                    $(
+                        ($name, None) => {
+                            let $get_state = state;
+                            $crate::batch::read(&$get)
+                        }
+                        $(($name, Some(value)) => {
+                            let $put_state = state;
+                            let $value: $ty = match $crate::batch::value(value) {
+                                Ok(value) => value,
+                                Err(outcome) => return Some(outcome),
+                            };
+                            $(
+                                let check = |$checked: &$ty| -> Result<(), &'static str> { $validate };
+                                if let Err(error) = check(&$value) {
+                                    return Some($crate::batch::Outcome::invalid(&error));
+                                }
+                            )?
+                            $crate::audit::record($name, &$value);
+                            $put;
+                            $crate::batch::Outcome::written()
+                        })?
+                        ($name, Some(_)) => $crate::batch::Outcome::read_only(),
+                    )*
+                    _ => return None,
+                };
+                Some(outcome)
+            })
+        }
+        $crate::batch::register(dispatch);
+
+        router
+    }};
+}
+
+/// Set up a heap of [`EspThing::HEAP_SIZE`] bytes and run the Thing `$props`.
+///
+/// Use it as the body of the `main` task: `wot_esp_thing::run!(AppProps, spawner);`
+#[macro_export]
+macro_rules! run {
+    ($props:ty, $spawner:expr) => {{
+        $crate::__esp_alloc::heap_allocator!(size: <$props as $crate::EspThing<$props>>::HEAP_SIZE);
+        // SAFETY: the heap was just added.
+        let heap = unsafe { $crate::heap::Heap::new_unchecked() };
+        <$props as $crate::EspThing<$props>>::run($spawner, heap).await
+    }};
+}
+
+#[cfg(feature = "syslog")]
+use syslog::SYSLOG_STACK_SIZE;
+#[cfg(not(feature = "syslog"))]
+const SYSLOG_STACK_SIZE: usize = 0;
+
+#[cfg(feature = "group")]
+use group::GROUP_STACK_SIZE;
+#[cfg(not(feature = "group"))]
+const GROUP_STACK_SIZE: usize = 0;
+
+#[cfg(feature = "directory")]
+use directory::DIRECTORY_STACK_SIZE;
+#[cfg(not(feature = "directory"))]
+const DIRECTORY_STACK_SIZE: usize = 0;
+
+#[cfg(feature = "dns")]
+use dns::DNS_STACK_SIZE;
+#[cfg(not(feature = "dns"))]
+const DNS_STACK_SIZE: usize = 0;
+
+#[cfg(feature = "http-client")]
+use http_client::HTTP_CLIENT_STACK_SIZE;
+#[cfg(not(feature = "http-client"))]
+const HTTP_CLIENT_STACK_SIZE: usize = 0;
+
+#[cfg(feature = "debug-port")]
+use debug_port::DEBUG_STACK_SIZE;
+#[cfg(not(feature = "debug-port"))]
+const DEBUG_STACK_SIZE: usize = 0;
+
+#[cfg(feature = "low-power")]
+use low_power::LOW_POWER_STACK_SIZE;
+#[cfg(not(feature = "low-power"))]
+const LOW_POWER_STACK_SIZE: usize = 0;
+
+#[cfg(feature = "sntp")]
+use sntp::SNTP_STACK_SIZE;
+#[cfg(not(feature = "sntp"))]
+const SNTP_STACK_SIZE: usize = 0;
+
+#[cfg(feature = "rules")]
+use rules::RULES_STACK_SIZE;
+#[cfg(not(feature = "rules"))]
+const RULES_STACK_SIZE: usize = 0;
+
+#[cfg(feature = "udp")]
+use udp::UDP_STACK_SIZE;
+#[cfg(not(feature = "udp"))]
+const UDP_STACK_SIZE: usize = 0;
+
+/// Sockets of the station's network stack.
+const SOCKETS: usize = 8 * mdns::MDNS_STACK_SIZE
+    + 2
+    + DNS_STACK_SIZE
+    + SYSLOG_STACK_SIZE
+    + GROUP_STACK_SIZE
+    + DIRECTORY_STACK_SIZE
+    + HTTP_CLIENT_STACK_SIZE
+    + DEBUG_STACK_SIZE
+    + LOW_POWER_STACK_SIZE
+    + SNTP_STACK_SIZE
+    + RULES_STACK_SIZE
+    + UDP_STACK_SIZE;
+
+/// Web tasks serving the app on the station, the concurrent connections.
+pub const WEB_TASKS: usize = 4;
+
+/// Bytes of the TCP receive buffer of a web task.
+pub const TCP_RX_BUFFER: usize = 1024;
+
+/// Bytes of the TCP send buffer of a web task.
+pub const TCP_TX_BUFFER: usize = 1024;
+
+/// Bytes of the buffer a web task reads a request into.
+pub const HTTP_BUFFER: usize = 2048;
+
+#[cfg(feature = "defmt")]
+use defmt_rtt as _;
+
+#[cfg(all(feature = "defmt", any(feature = "debug-log", feature = "syslog")))]
+compile_error!("`debug-log` and `syslog` sink `log` records and cannot be used with `defmt`");
 
 // TODO: Remove this horrible workaround once https://github.com/tkaitchuck/constrandom/issues/36 has been resolved
 const UUID_SEED: [u8; 16] = [
@@ -60,8 +349,9 @@ const UUID_SEED: [u8; 16] = [
 /// Produce an urn that can be used as id.
 ///
 /// When the `uuid-id` feature is enabled, returns a random UUID URN.
-/// Otherwise builds `urn:example/{name}/{mac}` from the thing name and
-/// the device hardware address.
+/// Otherwise builds `urn:dev:mac:{mac}` from the device hardware address,
+/// or `urn:{org}:{name}:{mac}` with the thing name when `URN_ORG` is set,
+/// see [`wot_esp_demo_core::names::device_urn`].
 #[must_use]
 pub fn get_urn_or_uuid(stack: Stack, name: &str) -> String {
     if cfg!(feature = "uuid-id") {
@@ -69,20 +359,23 @@ pub fn get_urn_or_uuid(stack: Stack, name: &str) -> String {
 
         uuid.urn().to_string()
     } else {
-        let device_id = stack.hardware_address().to_string();
-        format!("urn:example/{name}/{device_id}")
+        wot_esp_demo_core::names::device_urn(
+            config::URN_ORG,
+            name,
+            stack.hardware_address().as_bytes(),
+        )
     }
 }
 
-/// Serialize `data` as a JSON HTTP response.
+/// Serialize `data` as a JSON HTTP response, without the heap when it fits
+/// in an [`inline::InlineJson`].
 ///
 /// # Panics
 ///
 /// Panics if `data` cannot be serialized to JSON.
 #[must_use]
 pub fn to_json_response<T: serde::Serialize>(data: &T) -> impl IntoResponse {
-    let body = serde_json::to_string(data).unwrap();
-    Response::ok(body).with_header("Content-Type", "application/json")
+    inline::InlineJson::new(data)
 }
 
 /// Serialize `Ok` as JSON, or return HTTP 500 with `err_msg` on `Err`.
@@ -96,159 +389,607 @@ pub fn to_json_result<T: serde::Serialize, E>(
     err_msg: &'static str,
 ) -> impl IntoResponse {
     // `Result<impl IntoResponse, impl IntoResponse>` is itself `IntoResponse`.
-    result
-        .map(|data| {
-            let body = serde_json::to_string(&data).unwrap();
-            Response::ok(body).with_header("Content-Type", "application/json")
+    result.map(|data| to_json_response(&data)).map_err(|_| {
+        Response::new(StatusCode::INTERNAL_SERVER_ERROR, err_msg)
+            .with_header("Content-Type", "text/plain")
+    })
+}
+
+/// Serialize `data` as a JSON HTTP response, or only its member named by
+/// `?field=`, see [`wot_esp_demo_core::field`]. An unknown member, or a
+/// `field` on a value that is not an object, gets HTTP 404.
+///
+/// # Panics
+///
+/// Panics if `data` cannot be serialized to JSON.
+#[must_use]
+pub fn to_json_field<T: serde::Serialize>(data: &T, field: Field) -> impl IntoResponse {
+    let Some(name) = field.0 else {
+        return Ok(Ok(to_json_response(data)));
+    };
+    let value = serde_json::to_value(data).unwrap();
+    match wot_esp_demo_core::field::project(value, &name) {
+        Some(member) => Ok(Err(to_json_response(&member))),
+        None => Err(Response::new(
+            StatusCode::NOT_FOUND,
+            wot_esp_demo_core::field::not_found(&name).to_string(),
+        )
+        .with_header("Content-Type", "application/json")),
+    }
+}
+
+/// Add the read-only property at `path`, whose value `compute` derives from
+/// the app state, typically from other properties, to `router`.
+///
+/// `compute` returns `None` when the value cannot be derived, e.g. before
+/// the first measurement, and the property responds with HTTP 500 as
+/// [`respond_negotiated_result`] does. So does a value serializing to `null`, as a
+/// non-finite number does: a client never reads a `NaN` as a value.
+///
+/// ```ignore
+/// let router = computed(router, "/properties/dewPoint", |s: &AppState| {
+///     let (t, rh) = s.reading.lock(Cell::get)?;
+///     dew_point(t, rh)
+/// });
+/// ```
+pub fn computed<S, T, R, F>(
+    router: picoserve::Router<R, S>,
+    path: &'static str,
+    compute: F,
+) -> picoserve::Router<impl picoserve::routing::PathRouter<S>, S>
+where
+    S: Copy + 'static,
+    T: serde::Serialize,
+    R: picoserve::routing::PathRouter<S>,
+    F: Fn(&S) -> Option<T> + Copy + 'static,
+{
+    router.route(
+        path,
+        get(move |State(state): State<S>, accept: Accept| async move {
+            let value = compute(&state)
+                .and_then(|value| serde_json::to_value(&value).ok())
+                .filter(|value| !value.is_null())
+                .ok_or(());
+            respond_negotiated_result(accept, value, "Value not available.")
+        }),
+    )
+}
+
+/// What the client declared acceptable in its `Accept` request header.
+///
+/// A request without the header accepts anything.
+#[derive(Clone, Copy)]
+pub struct Accept {
+    json: bool,
+    td_json: bool,
+    link_format: bool,
+    cbor: bool,
+}
+
+impl Accept {
+    /// The client accepts `application/json`.
+    #[must_use]
+    pub fn json(&self) -> bool {
+        self.json
+    }
+
+    /// The client accepts `application/td+json` (or plain JSON).
+    #[must_use]
+    pub fn td_json(&self) -> bool {
+        self.td_json || self.json
+    }
+
+    /// The client accepts `application/link-format`.
+    #[must_use]
+    pub fn link_format(&self) -> bool {
+        self.link_format
+    }
+
+    /// The client prefers `application/cbor` to JSON, see
+    /// [`wot_esp_demo_core::negotiate::prefers`].
+    #[must_use]
+    pub fn cbor(&self) -> bool {
+        self.cbor
+    }
+}
+
+impl<'r, S> picoserve::extract::FromRequestParts<'r, S> for Accept {
+    type Rejection = core::convert::Infallible;
+
+    async fn from_request_parts(
+        _state: &'r S,
+        request_parts: &picoserve::request::RequestParts<'r>,
+    ) -> Result<Self, Self::Rejection> {
+        let header = request_parts
+            .headers()
+            .get("Accept")
+            .and_then(|v| v.as_str().ok())
+            .unwrap_or("");
+
+        Ok(Self {
+            json: accepts(header, "application/json"),
+            td_json: accepts(header, td::TD_MEDIA_TYPE),
+            link_format: accepts(header, link_format::CONTENT_TYPE),
+            cbor: prefers(header, cbor::CONTENT_TYPE, "application/json"),
         })
+    }
+}
+
+/// The member of an object-valued property asked for with `?field=`, `None`
+/// to read the whole value; see [`to_json_field`].
+pub struct Field(pub Option<String>);
+
+impl<'r, S> picoserve::extract::FromRequestParts<'r, S> for Field {
+    type Rejection = core::convert::Infallible;
+
+    async fn from_request_parts(
+        _state: &'r S,
+        request_parts: &picoserve::request::RequestParts<'r>,
+    ) -> Result<Self, Self::Rejection> {
+        let field = request_parts
+            .query()
+            .and_then(|query| wot_esp_demo_core::field::from_query(query.0))
+            .map(String::from);
+
+        Ok(Self(field))
+    }
+}
+
+/// Plain-text HTTP 406 for clients that cannot accept the media type we serve.
+#[must_use]
+pub fn not_acceptable(media_type: &'static str) -> impl IntoResponse {
+    Response::new(StatusCode::NOT_ACCEPTABLE, media_type).with_header("Content-Type", "text/plain")
+}
+
+/// Return the JSON `response` if the client accepts JSON, HTTP 406 otherwise.
+///
+/// Wrap [`to_json_response`] / [`to_json_result`] with it in property handlers.
+pub fn respond_json_negotiated(accept: Accept, response: impl IntoResponse) -> impl IntoResponse {
+    if accept.json() {
+        Ok(response)
+    } else {
+        Err(not_acceptable("application/json"))
+    }
+}
+
+/// Serialize `data` as CBOR when the client prefers it, see
+/// [`Accept::cbor`], as JSON otherwise; HTTP 406 if it accepts neither.
+///
+/// Property handlers use it in place of [`respond_json_negotiated`] with
+/// [`to_json_response`], so every property advertises CBOR in the TD.
+///
+/// # Panics
+///
+/// Panics if `data` cannot be serialized.
+pub fn respond_negotiated<T: serde::Serialize>(accept: Accept, data: &T) -> impl IntoResponse {
+    if accept.cbor() {
+        let body = cbor::to_vec(data).unwrap();
+        Ok(Ok(
+            Response::ok(body).with_header("Content-Type", cbor::CONTENT_TYPE)
+        ))
+    } else if accept.json() {
+        Ok(Err(to_json_response(data)))
+    } else {
+        Err(not_acceptable("application/json"))
+    }
+}
+
+/// [`respond_negotiated`] with the `Ok` value, or HTTP 500 with `err_msg` on
+/// `Err` as [`to_json_result`] does.
+pub fn respond_negotiated_result<T: serde::Serialize, E>(
+    accept: Accept,
+    result: Result<T, E>,
+    err_msg: &'static str,
+) -> impl IntoResponse {
+    result
+        .map(|data| respond_negotiated(accept, &data))
         .map_err(|_| {
             Response::new(StatusCode::INTERNAL_SERVER_ERROR, err_msg)
                 .with_header("Content-Type", "text/plain")
         })
 }
 
+/// [`respond_negotiated`] with `data`, or only its member named by
+/// `?field=` as [`to_json_field`] does.
+///
+/// # Panics
+///
+/// Panics if `data` cannot be serialized.
+pub fn respond_negotiated_field<T: serde::Serialize>(
+    accept: Accept,
+    data: &T,
+    field: Field,
+) -> impl IntoResponse {
+    // The whole value is serialized as it is, without a `Value` on the heap.
+    let Some(name) = field.0 else {
+        return Ok(Ok(respond_negotiated(accept, data)));
+    };
+    let value = serde_json::to_value(data).unwrap();
+    match wot_esp_demo_core::field::project(value, &name) {
+        Some(member) => Ok(Err(respond_negotiated(accept, &member))),
+        None => Err(Response::new(
+            StatusCode::NOT_FOUND,
+            wot_esp_demo_core::field::not_found(&name).to_string(),
+        )
+        .with_header("Content-Type", "application/json")),
+    }
+}
+
+/// Keep the station associated, publishing every transition as [`Connectivity`].
+///
+/// The configured networks are tried in turn, see [`wifi::candidates`].
 #[embassy_executor::task]
-pub async fn connection(mut controller: WifiController<'static>) {
-    println!("start connection task");
+pub async fn connection(
+    controller: WifiController<'static>,
+    stack: Stack<'static>,
+    prefer_strongest: bool,
+) {
+    info!("start connection task");
+    connectivity::set_stack(stack);
+    crate::heartbeat!(
+        "connection",
+        Duration::from_secs(30),
+        keep_connected(controller, stack, prefer_strongest)
+    );
+}
+
+async fn keep_connected(
+    mut controller: WifiController<'static>,
+    stack: Stack<'static>,
+    prefer_strongest: bool,
+) -> ! {
     loop {
-        if controller.is_connected() {
-            // wait until we're no longer connected
-            controller.wait_for_disconnect_async().await.ok();
-            Timer::after(Duration::from_millis(5000)).await;
+        let mut joined = None;
+        for network in wifi::candidates(&mut controller, prefer_strongest).await {
+            info!("About to connect to {}...", network.ssid);
+            if let Err(e) = controller.set_config(&wifi::config(network)) {
+                warn!("Cannot configure {}: {:?}", network.ssid, e);
+                continue;
+            }
+            match controller.connect_async().await {
+                Ok(_) => {
+                    joined = Some(network);
+                    break;
+                }
+                Err(e) => match wifi::in_range(&mut controller, network).await {
+                    Some(false) => warn!("Network {} not found", network.ssid),
+                    Some(true) => warn!(
+                        "Network {} rejected the authentication, check the credentials: {:?}",
+                        network.ssid, e
+                    ),
+                    None => warn!("Failed to connect to {}: {:?}", network.ssid, e),
+                },
+            }
         }
 
-        println!("About to connect...");
-        match controller.connect_async().await {
-            Ok(_) => println!("Wifi connected!"),
-            Err(e) => {
-                println!("Failed to connect to wifi: {e:?}");
+        match joined {
+            Some(network) => {
+                info!("Wifi connected!");
+                connectivity::associated();
+
+                // DHCP may not finish before the link drops again.
+                if let Either::First(()) = select(
+                    stack.wait_config_up(),
+                    controller.wait_for_disconnect_async(),
+                )
+                .await
+                {
+                    if let Some(config) = stack.config_v4() {
+                        connectivity::publish(Connectivity::Connected {
+                            ip: config.address.address(),
+                            ssid: network.ssid,
+                        });
+                    }
+                    // Wait until we're no longer connected, applying txPower
+                    // writes meanwhile.
+                    loop {
+                        let event = select(
+                            controller.wait_for_disconnect_async(),
+                            wifi::tx_power_requested(),
+                        )
+                        .await;
+                        match event {
+                            Either::First(_) => break,
+                            Either::Second(dbm) => wifi::apply_tx_power(&mut controller, dbm),
+                        }
+                    }
+                }
+
+                warn!("Wifi disconnected");
+                connectivity::publish(Connectivity::Disconnected);
+                Timer::after(Duration::from_millis(5000)).await;
+            }
+            None => {
+                warn!("Failed to connect to any configured network");
                 Timer::after(Duration::from_millis(5000)).await;
             }
         }
     }
 }
 
-#[embassy_executor::task]
+#[cfg_attr(not(feature = "maintenance-ap"), embassy_executor::task)]
+#[cfg_attr(feature = "maintenance-ap", embassy_executor::task(pool_size = 2))]
 pub async fn net_task(mut runner: Runner<'static, Interface<'static>>) {
     runner.run().await;
 }
 
-#[allow(clippy::similar_names)]
+/// Serve `app` on port 80, one connection at a time.
+///
+/// Each connection is served with the [`peers::config`] current when it is
+/// accepted, kept alive if `keep_alive` unless its client already holds
+/// [`peers::MAX_KEEP_ALIVE`] of them, see [`peers`]. New connections are
+/// reset once the Thing is shutting down, see `shutdown`, and those of a
+/// client refused for leaving partial requests, see [`peers::Strikes`].
 pub async fn web_task<Props: AppWithStateBuilder>(
     task_id: usize,
     stack: Stack<'static>,
     app: &'static AppRouter<Props>,
-    config: &'static picoserve::Config,
+    keep_alive: bool,
+    state: &'static Props::State,
+) {
+    serve_port::<Props>(80, task_id, stack, app, keep_alive, state).await;
+}
+
+/// Serve `app` on `port` like [`web_task`].
+#[allow(clippy::similar_names)]
+async fn serve_port<Props: AppWithStateBuilder>(
+    port: u16,
+    task_id: usize,
+    stack: Stack<'static>,
+    app: &'static AppRouter<Props>,
+    keep_alive: bool,
     state: &'static Props::State,
 ) {
-    let port = 80;
-    let mut tcp_rx_buffer = [0; 1024];
-    let mut tcp_tx_buffer = [0; 1024];
-    let mut http_buffer = [0; 2048];
+    let mut tcp_rx_buffer = [0; TCP_RX_BUFFER];
+    let mut tcp_tx_buffer = [0; TCP_TX_BUFFER];
+    let mut http_buffer = [0; HTTP_BUFFER];
 
-    picoserve::Server::new(&app.shared().with_state(state), config, &mut http_buffer)
-        .listen_and_serve(task_id, stack, port, &mut tcp_rx_buffer, &mut tcp_tx_buffer)
+    loop {
+        let mut socket =
+            embassy_net::tcp::TcpSocket::new(stack, &mut tcp_rx_buffer, &mut tcp_tx_buffer);
+        if let Err(e) = socket.accept(port).await {
+            warn!("Web task {}: accept failed: {:?}", task_id, e);
+            continue;
+        }
+        #[cfg(feature = "shutdown")]
+        if shutdown::stopping() {
+            socket.abort();
+            let _ = socket.flush().await;
+            continue;
+        }
+        let Some(peer) = socket.remote_endpoint().map(|endpoint| endpoint.addr) else {
+            continue;
+        };
+
+        if peers::refused(peer) {
+            socket.abort();
+            let _ = socket.flush().await;
+            continue;
+        }
+
+        // A client gone without a FIN stops acknowledging the probes, and
+        // the socket is aborted three intervals later, freeing the task.
+        let keepalive = events::keepalive_interval();
+        socket.set_keep_alive(Some(keepalive));
+        socket.set_timeout(Some(keepalive * 3));
+
+        let timeouts = peers::timeouts();
+        let accepted = Instant::now();
+        let config = peers::config(peers::open(peer) && keep_alive);
+        let served = audit::from_peer(
+            peer,
+            picoserve::Server::new(&app.shared().with_state(state), &config, &mut http_buffer)
+                .serve(socket),
+        )
         .await;
+        peers::close(peer);
+
+        let why = match served {
+            Ok(info)
+                if timeouts.start_read_timed_out(
+                    info.handled_requests_count,
+                    accepted.elapsed().as_millis(),
+                ) =>
+            {
+                Some(peers::Closed::StartReadTimeout)
+            }
+            Err(picoserve::Error::ReadTimeout(_)) => Some(peers::Closed::ReadTimeout),
+            Err(picoserve::Error::WriteTimeout(_)) => Some(peers::Closed::WriteTimeout),
+            Err(picoserve::Error::BadRequest) => Some(peers::Closed::ParseError),
+            _ => None,
+        };
+        if let Some(why) = why {
+            peers::closed_by(peer, why);
+        }
+    }
 }
 
-/// Thread-safe cell holding the serialized Thing Description string.
+/// What every app state holds in common: the Thing name, id and serialized
+/// Thing Description.
 ///
-/// Created empty and filled via [`EspThingState::set_td`] after the network is
-/// up (so the TD can include the device base URI).
-pub struct TdCell {
-    inner: embassy_sync::blocking_mutex::CriticalSectionMutex<core::cell::Cell<&'static str>>,
+/// [`EspThing::run`] creates it and hands it to [`EspThingState::new`]; the
+/// state embeds it and returns it from `AsRef<ThingCore>`, the bound the
+/// shared routes such as [`td_routes`] are written against. The id is only
+/// known once the network is up, so it reads empty before; the TD is kept by
+/// [`td`].
+pub struct ThingCore {
+    pub name: &'static str,
+    id: embassy_sync::blocking_mutex::CriticalSectionMutex<core::cell::Cell<&'static str>>,
 }
 
-impl TdCell {
-    /// Create an empty cell (TD not yet available).
+impl ThingCore {
     #[must_use]
-    pub const fn new() -> Self {
+    pub const fn new(name: &'static str) -> Self {
         Self {
-            inner: embassy_sync::blocking_mutex::CriticalSectionMutex::new(core::cell::Cell::new(
-                "",
-            )),
+            name,
+            id: embassy_sync::blocking_mutex::CriticalSectionMutex::new(core::cell::Cell::new("")),
         }
     }
 
-    /// Store the serialized TD (`td` must live for `'static`).
-    pub fn set(&self, td: &'static str) {
-        self.inner.lock(|c| c.set(td));
-    }
-
-    /// Current TD JSON, or an empty string before [`Self::set`].
+    /// The Thing id, e.g. `urn:uuid:…`, or an empty string before it is set.
     #[must_use]
-    pub fn get(&self) -> &'static str {
-        self.inner.lock(|c| c.get())
+    pub fn id(&self) -> &'static str {
+        self.id.lock(core::cell::Cell::get)
     }
-}
 
-impl Default for TdCell {
-    fn default() -> Self {
-        Self::new()
+    /// Store the Thing id, done by [`EspThing::run`] once the network is up.
+    pub fn set_id(&self, id: &'static str) {
+        self.id.lock(|c| c.set(id));
     }
 }
 
-/// A trait for application states that carry a serialized Thing Description.
-pub trait TdState {
-    /// The serialized Thing Description (JSON), served at `/`.
-    fn td(&self) -> &'static str;
+/// Hardware checks run by the `selfTest` action, implemented by the app state.
+///
+/// Name the checks in [`Self::CHECKS`] and run each in [`Self::check`]; a
+/// Thing without hardware to check keeps the defaults and reports none.
+pub trait SelfTest {
+    /// Names of the checks, run and reported in this order.
+    const CHECKS: &'static [&'static str] = &[];
+
+    /// Run the check `name` of [`Self::CHECKS`].
+    ///
+    /// Report a broken peripheral as [`Outcome::Failed`] rather than
+    /// panicking: finding it is the point of the test.
+    #[allow(async_fn_in_trait)]
+    async fn check(&self, name: &str) -> Outcome {
+        let _ = name;
+        Outcome::Skipped
+    }
 }
 
-/// Build the initial router with the standard WoT routes: the Thing Description
-/// at `/` (and `/` via `/.well-known/wot` redirect).
-///
-/// Call this instead of `picoserve::Router::new()` at the start of `build_app`.
-pub fn td_routes<S: TdState + Clone + Copy>() -> picoserve::Router<
-    impl picoserve::routing::PathRouter<S>,
-    S,
-> {
+/// The routes kept at the root whatever the [`prefix`]: the Thing
+/// Description at `/` (and `/` via `/.well-known/wot` redirect) with its
+/// SHA-256 at `/.well-known/wot.sha256`, and `/.well-known/core`.
+pub(crate) fn root_routes<S: AsRef<ThingCore> + Clone + Copy>(
+) -> picoserve::Router<impl picoserve::routing::PathRouter<S>, S> {
     picoserve::Router::new()
         .route(
             "/",
-            get(|State(state): State<S>| async move {
-                picoserve::response::Response::ok(state.td())
-                    .with_header("Content-Type", "application/td+json")
-            }),
+            get(
+                |accept: Accept,
+                 accept_encoding: AcceptEncoding,
+                 if_none_match: compress::IfNoneMatch,
+                 td::ForwardedBase(forwarded): td::ForwardedBase| async move {
+                    td::serve(accept, accept_encoding, &if_none_match, forwarded)
+                },
+            ),
         )
         .route(
             "/.well-known/wot",
             get(|| async { picoserve::response::Redirect::to("/") }),
         )
+        .route("/.well-known/wot.sha256", get(td::serve_sha256))
+        .route("/.well-known/core", get(link_format::serve))
 }
 
+/// Build the initial router with the standard WoT routes: the
+/// [`root_routes`], the `/batch` of property operations, see [`batch`], the
+/// `/debug/audit` trail of
+/// property writes, the `/debug/affordance-stats` request counters, plus the
+/// `/ui` status page, the `/debug/log` ring, the `/debug/tasks` inventory,
+/// the `energyMode` property and the `/rules` when the `ui`, `debug-log`,
+/// `debug-tasks`, `low-power` and `rules` features are enabled. Debug routes
+/// are never advertised in the TD, and are served on [`debug_port::port`]
+/// instead with the `debug-port` feature.
 ///
-/// Polls the watch with a 15s timeout, emitting `value_changed` events (or a
-/// keepalive on timeout). Generic over the value type `T`.
-pub struct SseEvents<'a, T: Clone + Send + 'static>(
-    pub embassy_sync::watch::Receiver<'a, embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex, T, 2>,
-);
+/// Call this instead of `picoserve::Router::new()` at the start of `build_app`.
+pub fn td_routes<S: AsRef<ThingCore> + SelfTest + Clone + Copy + 'static>(
+) -> picoserve::Router<impl picoserve::routing::PathRouter<S>, S> {
+    let router = root_routes()
+        .route(
+            "/properties/online_since",
+            get(connectivity::read_online_since),
+        )
+        .route("/properties/network", get(connectivity::read_network))
+        .route(
+            "/properties/txPower",
+            get(wifi::read_tx_power).put(wifi::write_tx_power),
+        )
+        .route("/properties/lastSelfTest", get(self_test::read_last))
+        .route("/properties/configuration", get(configuration::serve))
+        .route("/properties/capabilities", get(capabilities::serve))
+        .route("/config/identity", get(identity::read).put(identity::write))
+        .route(
+            "/actions/selfTest",
+            post(|_guard: WriteGuard, State(state): State<S>| async move {
+                self_test::invoke(&state).await
+            }),
+        )
+        .route(
+            "/batch",
+            post(
+                |State(state): State<S>, ValueBody::<{ batch::MAX_BATCH_BODY }>(body)| async move {
+                    batch::serve(&state, body).await
+                },
+            ),
+        )
+        .route("/events/connectivity", get(connectivity::events));
 
-impl<T> picoserve::response::sse::EventSource for SseEvents<'_, T>
-where
-    T: Clone + Send + core::fmt::Display + 'static,
-{
-    async fn write_events<W: picoserve::io::Write>(
-        mut self,
-        mut writer: picoserve::response::sse::EventWriter<'_, W>,
-    ) -> Result<(), W::Error> {
-        loop {
-            match embassy_time::with_timeout(
-                embassy_time::Duration::from_secs(15),
-                self.0.changed(),
-            )
-            .await
-            {
-                Ok(value) => {
-                    writer
-                        .write_event("value_changed", alloc::format!("{value}").as_str())
-                        .await?;
-                }
-                Err(_) => writer.write_keepalive().await?,
-            }
-        }
-    }
+    #[cfg(feature = "shutdown")]
+    let router = router.route("/actions/shutdown", post(shutdown::invoke));
+
+    // Served on their own port instead, see `debug_port`.
+    #[cfg(not(feature = "debug-port"))]
+    let router = debug_routes(router);
+
+    #[cfg(feature = "ui")]
+    let router = router.route("/ui", get(ui::index));
+
+    #[cfg(feature = "low-power")]
+    let router = router.route(
+        "/properties/energyMode",
+        get(low_power::read_energy_mode).put(low_power::write_energy_mode),
+    );
+
+    #[cfg(feature = "rules")]
+    let router = rules::routes(router);
+
+    router
 }
 
+/// Add the `/debug` routes to `router`: the audit trail, boot and peer
+/// reports, request latency and affordance counters, flash wear, entropy
+/// health and event delivery, plus the log ring and level, the task inventory
+/// and the allocation counters with the `debug-log`, `debug-tasks` and
+/// `alloc-stats` features.
+fn debug_routes<S, R: picoserve::routing::PathRouter<S>>(
+    router: picoserve::Router<R, S>,
+) -> picoserve::Router<impl picoserve::routing::PathRouter<S>, S> {
+    let router = router
+        .route("/debug/audit", get(audit::serve))
+        .route("/debug/boot", get(brownout::serve))
+        .route("/debug/peers", get(peers::serve))
+        .route(
+            "/debug/timeouts",
+            get(peers::read_timeouts).put(peers::write_timeouts),
+        )
+        .route("/debug/latency", get(latency::serve))
+        .route("/debug/latency/reset", post(latency::reset))
+        .route("/debug/affordance-stats", get(affordance_stats::serve))
+        .route(
+            "/debug/affordance-stats/reset",
+            post(affordance_stats::reset),
+        )
+        .route("/debug/flash", get(flash::serve))
+        .route("/debug/entropy", get(entropy::serve))
+        .route("/debug/events", get(events::serve));
+
+    #[cfg(feature = "debug-log")]
+    let router = router.route("/debug/log", get(log_ring::serve)).route(
+        "/debug/loglevel",
+        get(logger::read_level).put(logger::write_level),
+    );
+
+    #[cfg(feature = "debug-tasks")]
+    let router = router.route("/debug/tasks", get(tasks::serve));
+
+    #[cfg(feature = "alloc-stats")]
+    let router = router
+        .route("/debug/allocations", get(alloc_stats::serve))
+        .route("/debug/allocations/reset", post(alloc_stats::reset));
+
+    router
+}
 
 /// Peripherals consumed by the networking stack during [`EspThing::run`].
 ///
@@ -260,24 +1001,190 @@ pub struct NetworkPeripherals<'d> {
     pub wifi: esp_hal::peripherals::WIFI<'d>,
 }
 
-pub trait EspThingState {
+pub trait EspThingState: AsRef<ThingCore> {
     /// Consume the full `Peripherals`, extract hardware for the thing, and return
     /// the state alongside the peripherals the networking stack needs.
     ///
-    /// The serialized TD is set later via [`Self::set_td`] once the network is up.
+    /// Embed `core` in the state; its id and TD are set later, once the
+    /// network is up.
     fn new(
         spawner: embassy_executor::Spawner,
         peripherals: esp_hal::peripherals::Peripherals,
+        core: &'static ThingCore,
     ) -> (&'static Self, NetworkPeripherals<'static>);
 
-    /// Set the serialized Thing Description, called after the network is up.
-    fn set_td(&self, td: &'static str);
+    /// An LED blinking the [`boot_status`] until the Thing is running, none
+    /// by default.
+    fn status_led(&'static self) -> Option<&'static dyn boot_status::StatusLed> {
+        None
+    }
+
+    /// Put every actuator in its safe state, e.g. relay open and LEDs off.
+    ///
+    /// Applied right after [`Self::new`], before any task runs and before the
+    /// network comes up, so a reset in the middle of a write never leaves an
+    /// output latched on. Nothing by default.
+    fn safe_state(&self) {}
+
+    /// Whether the optional `hardware` of [`EspThing::OPTIONAL_HARDWARE`]
+    /// was found by the probing in [`Self::new`], see [`capabilities`].
+    /// All of it by default.
+    fn detected(&self, hardware: &str) -> bool {
+        let _ = hardware;
+        true
+    }
+
+    /// Whether to serve the Thing in the `batched` energy mode instead of
+    /// going back to sleep, e.g. a pin held at boot, see `low_power`.
+    /// Never by default.
+    fn stay_awake(&self) -> bool {
+        false
+    }
+
+    /// The reading pushed on each wake-up in the `batched` energy mode, an
+    /// object whose members are merged into the payload. None by default.
+    #[allow(async_fn_in_trait)]
+    async fn sample(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    /// Battery voltage in millivolts, pushed along with [`Self::sample`].
+    /// None by default.
+    fn battery_mv(&self) -> Option<u32> {
+        None
+    }
+
+    /// The value of the property `name` for the `udp` transport, the same
+    /// as its HTTP route serves: `None` for a property not read this way,
+    /// the reason if reading it failed. None by default.
+    #[allow(async_fn_in_trait)]
+    async fn read_property(&self, name: &str) -> Option<Result<serde_json::Value, &'static str>> {
+        let _ = name;
+        None
+    }
+}
+
+/// The station brought up by [`start_station`].
+struct Station {
+    stack: Stack<'static>,
+    rng: entropy::CountingRng,
+    /// The seed of the station's stack, for the access point's.
+    #[cfg(feature = "maintenance-ap")]
+    seed: u64,
+    #[cfg(feature = "maintenance-ap")]
+    access_point: Interface<'static>,
+}
+
+/// Start the radio and the station's network stack of the Thing `name` on
+/// `net`, and spawn the tasks keeping it connected.
+fn start_station(
+    spawner: embassy_executor::Spawner,
+    name: &str,
+    net: NetworkPeripherals<'static>,
+    power_save: PowerSaveMode,
+    prefer_strongest: bool,
+) -> Result<Station, ThingError> {
+    let timg0 = esp_hal::timer::timg::TimerGroup::new(net.timg0);
+    let sw_int = esp_hal::interrupt::software::SoftwareInterruptControl::new(net.sw_interrupt);
+    esp_rtos::start(timg0.timer0, sw_int.software_interrupt0);
+
+    // Invalid regulatory settings stop the Thing before the radio is on.
+    let radio = wifi::radio_settings()?;
+    info!(
+        "Wi-Fi country {}, at most {} dBm",
+        radio.country(),
+        radio.max_tx_power
+    );
+    let (mut controller, interfaces) = esp_radio::wifi::new(
+        net.wifi,
+        ControllerConfig::default().with_country_info(radio.country),
+    )?;
+
+    controller.set_power_saving(power_save)?;
+
+    #[cfg(feature = "maintenance-ap")]
+    maintenance_ap::set_ssid(name, interfaces.station.mac_address());
+    #[cfg(not(feature = "maintenance-ap"))]
+    let _ = name;
+
+    // At least one network is checked at build time.
+    controller.set_config(&wifi::config(wifi::configured().next().unwrap()))?;
+    wifi::apply_tx_power(&mut controller, radio.max_tx_power);
+
+    let wifi_interface = interfaces.station;
+
+    let config = embassy_net::Config::dhcpv4(Default::default());
+
+    let mut rng = entropy::CountingRng::new();
+
+    let mac_address = wifi_interface.mac_address();
+    info!(
+        "Device MAC address: {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+        mac_address[0],
+        mac_address[1],
+        mac_address[2],
+        mac_address[3],
+        mac_address[4],
+        mac_address[5]
+    );
+
+    // The radio is on: the generator draws from its noise.
+    let seed = entropy::net_seed(&mut rng, mac_address);
+
+    // Init network stack
+    let (stack, runner) = embassy_net::new(
+        wifi_interface,
+        config,
+        mk_static!(
+            embassy_net::StackResources<SOCKETS>,
+            embassy_net::StackResources::new()
+        ),
+        seed,
+    );
+
+    spawner.spawn(
+        connection(controller, stack, prefer_strongest)
+            .map_err(|_| ThingError::Spawn("connection"))?,
+    );
+    spawner.spawn(net_task(runner).map_err(|_| ThingError::Spawn("net_task"))?);
+    spawner.spawn(connectivity::net_info_task(stack).map_err(|_| ThingError::Spawn("net_info"))?);
+
+    Ok(Station {
+        stack,
+        rng,
+        #[cfg(feature = "maintenance-ap")]
+        seed,
+        #[cfg(feature = "maintenance-ap")]
+        access_point: interfaces.access_point,
+    })
+}
+
+/// Wait for the link, then for an address from DHCP; returns the base URI
+/// of the Thing.
+async fn wait_for_ip(stack: Stack<'static>) -> String {
+    boot_status::publish(boot_status::BootStatus::WifiConnecting);
+    loop {
+        if stack.is_link_up() {
+            break;
+        }
+        Timer::after(Duration::from_millis(500)).await;
+    }
+
+    boot_status::publish(boot_status::BootStatus::WaitingForIp);
+    info!("Waiting to get IP address...");
+    loop {
+        if let Some(config) = stack.config_v4() {
+            info!("Got IP: {}", config.address);
+            return format!("http://{}", config.address.address());
+        }
+        Timer::after(Duration::from_millis(500)).await;
+    }
 }
 
 pub trait EspThing<Props>
 where
     Props: AppWithStateBuilder + Default + 'static,
-    Props::State: EspThingState + 'static,
+    Props::State: EspThingState + SelfTest + Clone + Copy + 'static,
 {
     const NAME: &'static str;
 
@@ -288,123 +1195,449 @@ where
     /// there (esp-rs/esp-hal#3014, #3075, #3079).
     const WIFI_POWER_SAVE: PowerSaveMode = PowerSaveMode::Maximum;
 
-    fn build_td(name: &str, base_uri: String, id: String) -> wot_td::Thing;
+    /// Scan before connecting and try the strongest configured network first.
+    ///
+    /// Otherwise the networks are tried in the order they are configured.
+    const WIFI_PREFER_STRONGEST: bool = false;
+
+    /// Bytes of RAM given to the heap by [`run!`], shared with the Wi-Fi driver.
+    const HEAP_SIZE: usize = 200 * 1024;
+
+    /// Free heap bytes below which a warning is logged and
+    /// [`heap::is_low`] reports it.
+    const HEAP_LOW_THRESHOLD: usize = 16 * 1024;
+
+    /// Firmware version reported as the TD `version.instance`.
+    const VERSION: &'static str = td::FIRMWARE_VERSION;
 
+    /// Where to report issues, reported as the TD `support`.
+    ///
+    /// Defaults to the `SUPPORT_URL` env var at build time, omitted when unset.
+    const SUPPORT: Option<&'static str> = td::SUPPORT_URL;
+
+    /// Additional endpoints advertised in the TD `links`.
+    const LINKS: &'static [td::TdLink] = &[];
+
+    /// Write-only properties forwarded to the sibling Things, served with
+    /// [`group::proxy`] when the `group` feature is on.
+    const GROUP: &'static [td::GroupProperty] = &[];
+
+    /// Object-valued properties whose members can be read one at a time with
+    /// `?field=`, advertised with a `field` URI variable in the TD; their
+    /// routes answer with [`to_json_field`]. `network` always is.
+    const FIELD_PROPERTIES: &'static [&'static str] = &[];
+
+    /// Properties served over the experimental [`udp`] transport with the
+    /// `udp` feature, read with [`EspThingState::read_property`].
+    const UDP_PROPERTIES: &'static [&'static str] = &[];
+
+    /// Thing Model the TD is instantiated from instead of [`Self::build_td`].
+    const THING_MODEL: Option<tm::ThingModel> = None;
+
+    /// Hardware the board may lack, and the affordances left out of the TD
+    /// without it, see [`capabilities`].
+    const OPTIONAL_HARDWARE: &'static [capabilities::Hardware] = &[];
+
+    /// The board file the pins were taken from, see `boards/`; reported in
+    /// the [`configuration`] property.
+    const BOARD: Option<&'static str> = None;
+
+    /// GPIO of every pin role of [`Self::BOARD`].
+    const PINS: &'static [(&'static str, u8)] = &[];
+
+    /// Interval between two measurements, for a Thing that samples a sensor.
+    const SAMPLE_INTERVAL: Option<Duration> = None;
+
+    /// The TD of the Thing, from `wot_td`'s builder.
+    ///
+    /// An `Err`, e.g. for a malformed affordance, is logged and a minimal TD
+    /// served instead, see [`td::build`].
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the builder.
+    fn build_td(
+        name: &str,
+        base_uri: String,
+        id: String,
+    ) -> Result<wot_td::Thing, wot_td::builder::Error>;
+
+    /// The id of the Thing in its TD, [`get_urn_or_uuid`] by default.
+    ///
+    /// Override it to supply an id of the binary's own, e.g. from a serial
+    /// number burnt in eFuse.
+    #[must_use]
+    fn thing_id(stack: Stack<'_>) -> String {
+        get_urn_or_uuid(stack, Self::NAME)
+    }
+
+    /// How the Thing is advertised over mDNS: instance name, TTL, service
+    /// types and TXT entries.
+    #[must_use]
+    fn mdns_config() -> mdns::MdnsConfig {
+        mdns::MdnsConfig::default()
+    }
+
+    /// Bring up the Thing and serve it, logging the error if that fails.
+    ///
+    /// A [transient](ThingError::is_transient) error resets the chip after
+    /// 30 s; any other would fail again after the reset, so the Thing halts
+    /// with the error status until it is reflashed or power-cycled.
+    ///
+    /// Started by [`run!`], which sets up the `heap` first.
     #[allow(async_fn_in_trait, clippy::must_use_candidate)]
-    async fn run(spawner: embassy_executor::Spawner) {
+    async fn run(spawner: embassy_executor::Spawner, heap: heap::Heap) {
+        if let Err(e) = Self::try_run(spawner, heap).await {
+            error!("{} failed: {}", Self::NAME, e);
+            boot_status::publish(boot_status::BootStatus::Error);
+            if !e.is_transient() {
+                error!("Not retrying, halted");
+                core::future::pending::<()>().await;
+            }
+            // Leave time to read the log, then retry from a clean state.
+            Timer::after(Duration::from_secs(30)).await;
+            esp_hal::system::software_reset();
+        }
+    }
+
+    /// Push one reading and deep-sleep, instead of serving the Thing, in the
+    /// `batched` energy mode, see [`low_power`].
+    ///
+    /// Brings up the station as [`Self::try_run`] does, pushes
+    /// [`EspThingState::sample`] and [`EspThingState::battery_mv`] to the
+    /// webhook, announces the mDNS records and sleeps for the wake interval.
+    /// A step that fails is logged and the Thing sleeps all the same, so a
+    /// missing access point does not drain the battery.
+    #[cfg(feature = "low-power")]
+    #[allow(async_fn_in_trait)]
+    async fn run_low_power(
+        spawner: embassy_executor::Spawner,
+        app_state: &'static Props::State,
+        net: NetworkPeripherals<'static>,
+    ) -> ! {
+        let report = async {
+            let station = start_station(
+                spawner,
+                Self::NAME,
+                net,
+                Self::WIFI_POWER_SAVE,
+                Self::WIFI_PREFER_STRONGEST,
+            )?;
+            embassy_time::with_timeout(low_power::CONNECT_TIMEOUT, wait_for_ip(station.stack))
+                .await
+                .map_err(|_| ThingError::Net("dhcp"))?;
+
+            spawner.spawn(
+                mdns::mdns_task(station.stack, station.rng, Self::NAME, Self::mdns_config())
+                    .map_err(|_| ThingError::Spawn("mdns"))?,
+            );
+
+            let id = Self::thing_id(station.stack);
+            low_power::push(
+                station.stack,
+                &id,
+                app_state.sample().await,
+                app_state.battery_mv(),
+            )
+            .await;
+
+            Timer::after(low_power::MDNS_WINDOW).await;
+            Ok::<_, ThingError>(())
+        };
+
+        if let Err(e) = report.await {
+            warn!("{} not reported: {}", Self::NAME, e);
+        }
+        low_power::sleep(low_power::wake_interval())
+    }
+
+    /// Bring up the Thing and serve it.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first step that failed; the Wi-Fi and network stack are left
+    /// half-initialized, so reset the chip before retrying.
+    #[allow(async_fn_in_trait)]
+    async fn try_run(
+        spawner: embassy_executor::Spawner,
+        _heap: heap::Heap,
+    ) -> Result<(), ThingError> {
+        #[cfg(any(feature = "debug-log", feature = "syslog"))]
+        logger::init_logger_from_env();
+        #[cfg(not(any(feature = "defmt", feature = "debug-log", feature = "syslog")))]
         esp_println::logger::init_logger_from_env();
         let peripherals = esp_hal::init(
             esp_hal::Config::default().with_cpu_clock(esp_hal::clock::CpuClock::max()),
         );
+        boot_status::publish(boot_status::BootStatus::Starting);
 
-        esp_alloc::heap_allocator!(size: 200 * 1024);
+        let (used, free) = heap::usage();
+        info!("Heap: {} bytes, {} free", used + free, free);
+        config::validate();
+        prefix::init();
+        brownout::init();
+
+        configuration::init(&configuration::Configuration {
+            name: Self::NAME,
+            version: Self::VERSION,
+            board: Self::BOARD,
+            pins: Self::PINS,
+            features: configuration::FEATURES,
+            heap_size: Self::HEAP_SIZE,
+            web_tasks: WEB_TASKS,
+            sockets: SOCKETS,
+            tcp_buffers: (TCP_RX_BUFFER, TCP_TX_BUFFER),
+            http_buffer: HTTP_BUFFER,
+            sample_interval_ms: Self::SAMPLE_INTERVAL.map(|interval| interval.as_millis()),
+        });
 
         // Let the demo extract its hardware and hand back the network peripherals.
-        let (app_state, net_peripherals) = Props::State::new(spawner, peripherals);
+        let core = mk_static!(ThingCore, ThingCore::new(Self::NAME));
+        let (app_state, net_peripherals) = Props::State::new(spawner, peripherals, core);
+        app_state.safe_state();
 
-        let timg0 = esp_hal::timer::timg::TimerGroup::new(net_peripherals.timg0);
-        let sw_int = esp_hal::interrupt::software::SoftwareInterruptControl::new(
-            net_peripherals.sw_interrupt,
-        );
-        esp_rtos::start(timg0.timer0, sw_int.software_interrupt0);
+        if let Some(led) = app_state.status_led() {
+            spawner.spawn(
+                boot_status::status_led_task(led).map_err(|_| ThingError::Spawn("status_led"))?,
+            );
+        }
 
-        let (mut controller, interfaces) =
-            esp_radio::wifi::new(net_peripherals.wifi, ControllerConfig::default()).unwrap();
+        #[cfg(feature = "low-power")]
+        if low_power::mode() == low_power::EnergyMode::Batched {
+            if !app_state.stay_awake() {
+                Self::run_low_power(spawner, app_state, net_peripherals).await;
+            }
+            info!("Stay-awake pin held, serving until the next reset");
+        }
 
-        controller
-            .set_power_saving(Self::WIFI_POWER_SAVE)
-            .unwrap();
+        let station = start_station(
+            spawner,
+            Self::NAME,
+            net_peripherals,
+            Self::WIFI_POWER_SAVE,
+            Self::WIFI_PREFER_STRONGEST,
+        )?;
+        let stack = station.stack;
 
-        let station_config = Config::Station(
-            StationConfig::default()
-                .with_ssid(SSID)
-                .with_password(PASSWORD.into()),
-        );
-        controller.set_config(&station_config).unwrap();
+        #[cfg(feature = "maintenance-ap")]
+        let ap_stack = {
+            let (ap_stack, ap_runner) = embassy_net::new(
+                station.access_point,
+                maintenance_ap::stack_config(),
+                mk_static!(
+                    embassy_net::StackResources<{ maintenance_ap::AP_STACK_SIZE }>,
+                    embassy_net::StackResources::new()
+                ),
+                station.seed,
+            );
+            spawner.spawn(net_task(ap_runner).map_err(|_| ThingError::Spawn("net_task"))?);
+            spawner
+                .spawn(maintenance_ap::dhcp_task(ap_stack).map_err(|_| ThingError::Spawn("dhcp"))?);
+            spawner.spawn(
+                captive_dns::dns_task(ap_stack, maintenance_ap::AP_ADDRESS)
+                    .map_err(|_| ThingError::Spawn("dns"))?,
+            );
+            ap_stack
+        };
 
-        let wifi_interface = interfaces.station;
+        let serve = Self::serve(spawner, stack, station.rng, core, app_state);
 
-        let config = embassy_net::Config::dhcpv4(Default::default());
+        #[cfg(feature = "maintenance-ap")]
+        let serve = {
+            // Served before the station has an address; the TD is answered
+            // with 503 until it is set.
+            let ap_app = alloc::boxed::Box::leak(alloc::boxed::Box::new(
+                ApApp::<Props>::default().build_app(),
+            ));
+            let ap_web_tasks: [_; maintenance_ap::AP_WEB_TASKS] = core::array::from_fn(|id| {
+                alloc::boxed::Box::pin(<() as WebTask<ApApp<Props>>>::spawn(
+                    80,
+                    WEB_TASKS + id,
+                    ap_stack,
+                    ap_app,
+                    true,
+                    app_state,
+                ))
+            });
 
-        let rng = esp_hal::rng::Rng::new();
-        let seed = (rng.random() as u64) << 32 | rng.random() as u64;
+            // The web tasks never return: this ends with the station side.
+            async move {
+                match embassy_futures::select::select(
+                    serve,
+                    embassy_futures::join::join_array(ap_web_tasks),
+                )
+                .await
+                {
+                    embassy_futures::select::Either::First(result) => result,
+                    embassy_futures::select::Either::Second(()) => Ok(()),
+                }
+            }
+        };
 
-        let mac_address = wifi_interface.mac_address();
-        println!("Device MAC address: {mac_address:02x?}");
+        serve.await
+    }
 
-        // Init network stack
-        let (stack, runner) = embassy_net::new(
-            wifi_interface,
-            config,
-            mk_static!(embassy_net::StackResources<{ 8 * mdns::MDNS_STACK_SIZE + 2 }>, embassy_net::StackResources::new()),
-            seed,
-        );
+    /// Serve the Thing on the station network of [`Self::try_run`] once it
+    /// has an address: build the TD, start the tasks using the network and
+    /// run the web tasks.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first step that failed.
+    #[allow(async_fn_in_trait)]
+    async fn serve(
+        spawner: embassy_executor::Spawner,
+        stack: Stack<'static>,
+        rng: entropy::CountingRng,
+        core: &'static ThingCore,
+        app_state: &'static Props::State,
+    ) -> Result<(), ThingError> {
+        let base_uri = wait_for_ip(stack).await;
 
-        spawner.spawn(connection(controller).expect("connection"));
-        spawner.spawn(net_task(runner).expect("net_task"));
+        let id = Self::thing_id(stack);
+        core.set_id(mk_static!(String, id.clone()).as_str());
 
-        loop {
-            if stack.is_link_up() {
-                break;
-            }
-            Timer::after(Duration::from_millis(500)).await;
-        }
+        let name = Self::NAME;
+        identity::init(name);
 
-        let base_uri;
-        println!("Waiting to get IP address...");
-        loop {
-            if let Some(config) = stack.config_v4() {
-                println!("Got IP: {}", config.address);
-                base_uri = format!("http://{}", config.address.address());
-                break;
-            }
-            Timer::after(Duration::from_millis(500)).await;
-        }
+        let meta = td::TdMetadata {
+            version: Self::VERSION,
+            support: Self::SUPPORT,
+            links: Self::LINKS,
+            group: Self::GROUP,
+        };
 
-        let id = get_urn_or_uuid(stack, Self::NAME);
+        let td = match Self::THING_MODEL {
+            Some(model) => tm::instantiate(&model, &base_uri, &id, name, &meta),
+            None => td::build(name, base_uri, id, &meta, Self::build_td),
+        }?;
 
-        let name = Self::NAME;
+        #[cfg(feature = "low-power")]
+        let td = wot_esp_demo_core::low_power::add_energy_mode(&td)?;
 
-        let td = Self::build_td(Self::NAME, base_uri, id);
+        let fields: Vec<&str> = core::iter::once("network")
+            .chain(Self::FIELD_PROPERTIES.iter().copied())
+            .collect();
+        let td = wot_esp_demo_core::field::advertise(&td, &fields)?;
+        // Every property read is served by `respond_negotiated`.
+        let td = cbor::advertise(&td)?;
 
-        let td = serde_json::to_string(&td).unwrap();
+        // The state probed for the optional hardware when it was created.
+        let td = capabilities::restrict(td, Self::OPTIONAL_HARDWARE, |hardware| {
+            app_state.detected(hardware)
+        })?;
+        // After the restriction, which takes the path of every form removed
+        // for a route to hide.
+        #[cfg(feature = "udp")]
+        let td = wot_esp_demo_core::udp::advertise(&td, Self::UDP_PROPERTIES, udp::port())?;
+        // Last of the paths: the ones above are those the routes were registered at.
+        let td = match prefix::get() {
+            Some(prefix) => wot_esp_demo_core::prefix::prefix_json(&td, prefix)?,
+            None => td,
+        };
 
-        let td = mk_static!(String, td);
-        Props::State::set_td(app_state, td.as_str());
+        // Only the TD gets the public base: mDNS keeps announcing the local address.
+        let td = match td::PUBLIC_BASE_URL {
+            Some(base) => td::rebase_json(&td, base)?,
+            None => td,
+        };
 
-        let app = alloc::boxed::Box::leak(alloc::boxed::Box::new(Props::default().build_app()));
+        affordance_stats::init(&td);
+        td::set(td).await;
+        if let Some(td) = td::served() {
+            info!(
+                "TD is {} bytes, {} gzipped",
+                td.json().len(),
+                td.gzip().len()
+            );
+        }
 
-        let config = mk_static!(
-            picoserve::Config,
-            picoserve::Config::new(picoserve::Timeouts {
-                start_read_request: Duration::from_secs(5),
-                persistent_start_read_request: Duration::from_secs(1),
-                read_request: Duration::from_secs(1),
-                write: Duration::from_secs(1),
-            })
-            .keep_connection_alive()
+        let app = alloc::boxed::Box::leak(alloc::boxed::Box::new(
+            prefix::Prefixed::<Props>::default().build_app(),
+        ));
+
+        origin::init(alloc::boxed::Box::leak(
+            format!("{}.local", mdns::hostname(stack, name)).into_boxed_str(),
+        ));
+        spawner.spawn(
+            mdns::mdns_task(stack, rng, name, Self::mdns_config())
+                .map_err(|_| ThingError::Spawn("mdns"))?,
+        );
+        spawner.spawn(
+            heap::heap_monitor_task(Self::HEAP_LOW_THRESHOLD)
+                .map_err(|_| ThingError::Spawn("heap_monitor"))?,
         );
+        #[cfg(feature = "shutdown")]
+        spawner.spawn(shutdown::shutdown_task().map_err(|_| ThingError::Spawn("shutdown"))?);
+        #[cfg(feature = "low-power")]
+        spawner.spawn(low_power::switch_task().map_err(|_| ThingError::Spawn("low_power"))?);
 
-        spawner.spawn(mdns::mdns_task(stack, rng, name).expect("mdns"));
+        #[cfg(feature = "syslog")]
+        if let Some(host) = syslog::host() {
+            let hostname = alloc::boxed::Box::leak(mdns::hostname(stack, name).into_boxed_str());
+            spawner.spawn(
+                syslog::syslog_task(stack, host, hostname, name)
+                    .map_err(|_| ThingError::Spawn("syslog"))?,
+            );
+        }
 
-        let web_tasks: [_; 4] = core::array::from_fn(|id| {
-            alloc::boxed::Box::pin(<() as WebTask<Props>>::spawn(
-                id, stack, app, config, app_state,
+        #[cfg(feature = "sntp")]
+        spawner.spawn(sntp::sntp_task(stack).map_err(|_| ThingError::Spawn("sntp"))?);
+
+        #[cfg(feature = "group")]
+        if !Self::GROUP.is_empty() {
+            spawner.spawn(group::group_task(stack, name).map_err(|_| ThingError::Spawn("group"))?);
+        }
+
+        #[cfg(feature = "directory")]
+        spawner
+            .spawn(directory::directory_task(stack).map_err(|_| ThingError::Spawn("directory"))?);
+
+        #[cfg(feature = "self-test")]
+        spawner
+            .spawn(http_client::self_test_task(stack).map_err(|_| ThingError::Spawn("self_test"))?);
+
+        boot_status::publish(boot_status::BootStatus::Running);
+        let web_tasks: [_; WEB_TASKS] = core::array::from_fn(|id| {
+            alloc::boxed::Box::pin(<() as WebTask<prefix::Prefixed<Props>>>::spawn(
+                80, id, stack, app, true, app_state,
             ))
         });
 
-        embassy_futures::join::join_array(web_tasks).await;
+        #[cfg(feature = "debug-port")]
+        let debug_tasks = debug_port::serve(stack);
+        #[cfg(not(feature = "debug-port"))]
+        let debug_tasks = core::future::ready(());
+
+        #[cfg(feature = "udp")]
+        let udp_task = udp::serve(stack, app_state);
+        #[cfg(not(feature = "udp"))]
+        let udp_task = core::future::ready(());
+
+        embassy_futures::join::join3(
+            embassy_futures::join::join_array(web_tasks),
+            embassy_futures::join::join(debug_tasks, udp_task),
+            identity::apply(),
+        )
+        .await;
+
+        Ok(())
     }
 }
 
+/// The app served on the maintenance network.
+#[cfg(feature = "maintenance-ap")]
+type ApApp<Props> = maintenance_ap::Captive<prefix::Prefixed<Props>>;
+
 trait WebTask<Props: picoserve::AppWithStateBuilder> {
     type Fut: core::future::Future<Output = ()> + 'static;
 
     fn spawn(
+        port: u16,
         id: usize,
         stack: Stack<'static>,
         app: &'static AppRouter<Props>,
-        config: &'static picoserve::Config,
+        keep_alive: bool,
         state: &'static Props::State,
     ) -> Self::Fut;
 }
@@ -413,12 +1646,13 @@ impl<Props: picoserve::AppWithStateBuilder + 'static> WebTask<Props> for () {
     type Fut = impl core::future::Future<Output = ()> + 'static;
 
     fn spawn(
+        port: u16,
         id: usize,
         stack: Stack<'static>,
         app: &'static AppRouter<Props>,
-        config: &'static picoserve::Config,
+        keep_alive: bool,
         state: &'static Props::State,
     ) -> Self::Fut {
-        web_task::<Props>(id, stack, app, config, state)
+        serve_port::<Props>(port, id, stack, app, keep_alive, state)
     }
 }
@@ -0,0 +1,17 @@
+//! CoRE Link Format (RFC 6690) at `/.well-known/core`.
+//!
+//! Lets CoRE resource directories probing over HTTP find the TD as a
+//! `wot.thing` resource.
+
+use picoserve::response::{IntoResponse, Response};
+pub use wot_esp_demo_core::link_format::{link, well_known_core, CONTENT_TYPE, TD_CONTENT_FORMAT};
+
+use crate::{not_acceptable, Accept};
+
+pub(crate) async fn serve(accept: Accept) -> impl IntoResponse {
+    if accept.link_format() {
+        Ok(Response::ok(well_known_core()).with_header("Content-Type", CONTENT_TYPE))
+    } else {
+        Err(not_acceptable(CONTENT_TYPE))
+    }
+}
@@ -0,0 +1,85 @@
+//! The last [`LOG_RING_SIZE`] bytes of log output, kept in RAM.
+//!
+//! Records are formatted into a fixed ring buffer (no heap) by the
+//! logger and served as `text/plain` at `/debug/log`, so a board
+//! without a serial console can still be inspected.
+
+use core::{cell::RefCell, fmt::Write as _};
+
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use log::Record;
+use picoserve::response::{IntoResponse, Response};
+
+/// Capacity of the log ring in bytes.
+pub const LOG_RING_SIZE: usize = 4096;
+
+struct Ring {
+    buf: [u8; LOG_RING_SIZE],
+    /// Index of the next byte to write.
+    head: usize,
+    /// The ring wrapped at least once, so `buf[head..]` holds the oldest bytes.
+    wrapped: bool,
+}
+
+impl core::fmt::Write for Ring {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for &b in s.as_bytes() {
+            self.buf[self.head] = b;
+            self.head += 1;
+            if self.head == LOG_RING_SIZE {
+                self.head = 0;
+                self.wrapped = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+static RING: CriticalSectionMutex<RefCell<Ring>> = CriticalSectionMutex::new(RefCell::new(Ring {
+    buf: [0; LOG_RING_SIZE],
+    head: 0,
+    wrapped: false,
+}));
+
+/// Append `record` to the ring, overwriting the oldest bytes.
+pub(crate) fn record(record: &Record) {
+    let uptime = embassy_time::Instant::now().as_millis();
+    RING.lock(|ring| {
+        let _ = writeln!(
+            ring.borrow_mut(),
+            "[{uptime:>10}] {:<5} {}",
+            record.level(),
+            record.args()
+        );
+    });
+}
+
+/// Copy of the ring contents, oldest line first.
+fn snapshot() -> alloc::string::String {
+    let (bytes, wrapped) = RING.lock(|ring| {
+        let ring = ring.borrow();
+        let mut out = alloc::vec::Vec::with_capacity(LOG_RING_SIZE);
+        if ring.wrapped {
+            out.extend_from_slice(&ring.buf[ring.head..]);
+        }
+        out.extend_from_slice(&ring.buf[..ring.head]);
+        (out, ring.wrapped)
+    });
+
+    // Drop the partially overwritten oldest line.
+    let start = if wrapped {
+        bytes
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(0, |nl| nl + 1)
+    } else {
+        0
+    };
+
+    alloc::string::String::from_utf8_lossy(&bytes[start..]).into_owned()
+}
+
+/// Handler for `GET /debug/log`.
+pub async fn serve() -> impl IntoResponse {
+    Response::ok(snapshot()).with_header("Content-Type", "text/plain; charset=utf-8")
+}
@@ -0,0 +1,103 @@
+//! Logger fanning records out to the serial console and the optional sinks.
+//!
+//! Every record is printed through `esp-println` as usual, then handed to the
+//! RAM ring (`debug-log`) and the syslog forwarder (`syslog`). No sink
+//! allocates.
+//!
+//! With `debug-log` the level set by `ESP_LOG` can be changed at run time at
+//! `/debug/loglevel`, e.g. to chase an intermittent bug without a rebuild,
+//! by the holder of the `WRITE_TOKEN`:
+//!
+//! ```sh
+//! curl -X PUT -H 'Authorization: Bearer <token>' -d '"debug"' http://<ip>/debug/loglevel
+//! ```
+
+use log::{LevelFilter, Log, Metadata, Record};
+#[cfg(feature = "debug-log")]
+use picoserve::response::{IntoResponse, Response, StatusCode};
+#[cfg(feature = "debug-log")]
+use wot_esp_demo_core::origin::{unauthorized, CHALLENGE};
+
+#[cfg(feature = "debug-log")]
+use crate::{
+    audit, config, origin::Authorized, respond_json_negotiated, to_json_response,
+    write::InvalidValue, Accept, JsonBody,
+};
+
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        esp_println::println!("{} - {}", record.level(), record.args());
+
+        #[cfg(feature = "debug-log")]
+        crate::log_ring::record(record);
+
+        #[cfg(feature = "syslog")]
+        crate::syslog::record(record);
+    }
+
+    fn flush(&self) {}
+}
+
+/// Level from the `ESP_LOG` build-time env var, `Info` when unset or unknown.
+fn level_from_env() -> LevelFilter {
+    crate::config::ESP_LOG
+        .and_then(|level| level.split(',').next())
+        .and_then(|level| level.trim().parse().ok())
+        .unwrap_or(LevelFilter::Info)
+}
+
+/// Install the logger in place of `esp_println::logger`.
+pub fn init_logger_from_env() {
+    static LOGGER: Logger = Logger;
+
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level_from_env());
+    }
+}
+
+/// `level` as read and written at `/debug/loglevel`, e.g. `debug`.
+#[cfg(feature = "debug-log")]
+fn level_name(level: LevelFilter) -> alloc::string::String {
+    level.as_str().to_ascii_lowercase()
+}
+
+/// Handler for `GET /debug/loglevel`.
+#[cfg(feature = "debug-log")]
+pub(crate) async fn read_level(accept: Accept) -> impl IntoResponse {
+    respond_json_negotiated(accept, to_json_response(&level_name(log::max_level())))
+}
+
+/// Handler for `PUT /debug/loglevel`, applied to every record from then on
+/// and until the next reset. Answers 401 to a request not [`Authorized`].
+#[cfg(feature = "debug-log")]
+pub(crate) async fn write_level(
+    Authorized(authorized): Authorized,
+    JsonBody(level): JsonBody<alloc::string::String>,
+) -> impl IntoResponse {
+    if !authorized {
+        // Without the token, whatever the header held instead.
+        let body = unauthorized(None, config::WRITE_TOKEN).unwrap_or_default();
+        return Err(Err(Response::new(StatusCode::UNAUTHORIZED, body)
+            .with_header("Content-Type", "text/plain")
+            .with_header("WWW-Authenticate", CHALLENGE)));
+    }
+    let Ok(level) = level.parse::<LevelFilter>() else {
+        return Err(Ok(InvalidValue(
+            "Unknown log level, expected off, error, warn, info, debug or trace",
+        )));
+    };
+
+    audit::record("logLevel", &level_name(level));
+    log::set_max_level(level);
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,145 @@
+//! The `energyMode` property, for a Thing on battery.
+//!
+//! In the `continuous` mode, the default, the Thing is served as usual. In
+//! the `batched` mode [`crate::EspThing::run_low_power`] runs instead on each
+//! boot: it brings up the station, pushes [`crate::EspThingState::sample`]
+//! and the battery voltage to [`WEBHOOK_URL`], lets the mDNS records go out
+//! and deep-sleeps for [`wake_interval`]. No web task is spawned: to write the
+//! mode back to `continuous`, hold the stay-awake pin of the Thing at boot,
+//! see [`crate::EspThingState::stay_awake`], so it is served until the next
+//! reset:
+//!
+//! ```sh
+//! curl -X PUT -d '"continuous"' http://<ip>/properties/energyMode
+//! ```
+//!
+//! The mode is kept in RTC memory, which survives deep sleep and resets but
+//! not a power cycle: the Thing then starts `continuous`.
+
+use alloc::string::ToString;
+
+use embassy_net::Stack;
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{Duration, Timer};
+use esp_hal::{
+    peripherals::LPWR,
+    rtc_cntl::{sleep::TimerWakeupSource, Rtc},
+};
+use picoserve::response::{IntoResponse, StatusCode};
+use serde_json::Value;
+
+pub use wot_esp_demo_core::low_power::EnergyMode;
+use wot_esp_demo_core::low_power::{payload, wake_interval_min};
+
+use crate::{
+    audit, http_client::Client, respond_negotiated, write::InvalidValue, Accept, ValueBody,
+};
+
+/// Where the readings are pushed, set with `WEBHOOK_URL` at build time, e.g.
+/// `WEBHOOK_URL=http://192.168.1.10:8080/readings`.
+pub use crate::config::WEBHOOK_URL;
+
+/// Sockets used: the push to the webhook.
+pub const LOW_POWER_STACK_SIZE: usize = 1;
+
+/// Longest wait for the station to get an address before going back to
+/// sleep.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Time the push to the webhook may take.
+const PUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time left to the mDNS task to announce the records before sleeping.
+pub const MDNS_WINDOW: Duration = Duration::from_secs(1);
+
+/// The [`EnergyMode::to_word`] of the mode, left as is across deep sleep.
+#[esp_hal::ram(unstable(rtc_fast, persistent))]
+static mut MODE: u32 = 0;
+
+static SWITCH: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// Time between two wake-ups in the `batched` mode, from the
+/// `WAKE_INTERVAL_MIN` build-time env var, 10 minutes by default.
+#[must_use]
+pub fn wake_interval() -> Duration {
+    Duration::from_secs(60 * u64::from(wake_interval_min(crate::config::WAKE_INTERVAL_MIN)))
+}
+
+/// The mode in effect, `continuous` after a power cycle.
+#[must_use]
+pub fn mode() -> EnergyMode {
+    // Only the web tasks write it, and only on a single core.
+    EnergyMode::from_word(unsafe { (&raw const MODE).read_volatile() }).unwrap_or_default()
+}
+
+fn set_mode(mode: EnergyMode) {
+    unsafe { (&raw mut MODE).write_volatile(mode.to_word()) }
+}
+
+pub(crate) async fn read_energy_mode(accept: Accept) -> impl IntoResponse {
+    respond_negotiated(accept, &mode().as_str())
+}
+
+/// Handler for `PUT /properties/energyMode`. Switching to `batched` puts the
+/// Thing to sleep right after the response, see [`switch_task`].
+pub(crate) async fn write_energy_mode(ValueBody(body): ValueBody) -> impl IntoResponse {
+    let mode = body
+        .as_str()
+        .and_then(EnergyMode::parse)
+        .ok_or(InvalidValue("energyMode must be continuous or batched"))?;
+
+    audit::record("energyMode", &mode.as_str());
+    set_mode(mode);
+    if mode == EnergyMode::Batched {
+        SWITCH.signal(());
+    }
+
+    Ok::<_, InvalidValue>(StatusCode::NO_CONTENT)
+}
+
+/// Time the response to the switch gets to go out before the chip sleeps.
+const DRAIN: Duration = Duration::from_secs(1);
+
+/// Wait for a switch to the `batched` mode, then sleep for a second so the
+/// first reading is pushed right away.
+#[embassy_executor::task]
+pub async fn switch_task() {
+    SWITCH.wait().await;
+    info!("Switching to the batched energy mode");
+    Timer::after(DRAIN).await;
+    sleep(Duration::from_secs(1));
+}
+
+/// Push `reading` and `battery_mv` of the Thing `id` to [`WEBHOOK_URL`],
+/// logging the outcome.
+pub(crate) async fn push(
+    stack: Stack<'_>,
+    id: &str,
+    reading: Option<Value>,
+    battery_mv: Option<u32>,
+) {
+    let Some(url) = WEBHOOK_URL else {
+        warn!("No WEBHOOK_URL, the reading is not pushed");
+        return;
+    };
+
+    let body = payload(id, reading, battery_mv).to_string();
+    let mut buf = [0; 512];
+    match Client::new(stack)
+        .with_timeout(PUSH_TIMEOUT)
+        .post_json(url, body.as_bytes(), &mut buf)
+        .await
+    {
+        Ok(response) => info!("Pushed the reading, {} answered {}", url, response.status),
+        Err(e) => warn!("Cannot push the reading to {}: {}", url, e.as_str()),
+    }
+}
+
+/// Deep-sleep for `interval`; the chip then boots again from the start.
+pub fn sleep(interval: Duration) -> ! {
+    info!("Sleeping for {} s", interval.as_secs());
+    let timer = TimerWakeupSource::new(core::time::Duration::from_millis(interval.as_millis()));
+    // The state took the peripherals; nothing else uses the RTC.
+    let mut rtc = Rtc::new(unsafe { LPWR::steal() });
+    rtc.sleep_deep(&[&timer]);
+}
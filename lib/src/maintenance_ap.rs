@@ -0,0 +1,146 @@
+//! Local maintenance access point run alongside the station (AP+STA).
+//!
+//! The AP is named `wot-<name>-<mac>` and protected with the `AP_PASSPHRASE`
+//! build-time env var. It has its own network stack at [`AP_ADDRESS`], hands
+//! out addresses with a small DHCP server, resolves every name to itself (see
+//! [`crate::captive_dns`], which DHCP hands out as the DNS server) and serves
+//! the same HTTP app as the station side, plus the [`Captive`] redirects, so
+//! the Thing stays reachable when the building network is down; it is served
+//! from boot, before the station has an address. mDNS only runs on the
+//! station side.
+
+use core::{
+    cell::Cell,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+};
+
+use alloc::{boxed::Box, format};
+use edge_dhcp::{
+    io::{self, DEFAULT_SERVER_PORT},
+    server::{Server, ServerOptions},
+};
+use edge_nal::UdpBind;
+use edge_nal_embassy::{Udp, UdpBuffers};
+use embassy_net::{Ipv4Cidr, Stack, StaticConfigV4};
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use embassy_time::{Duration, Timer};
+use esp_radio::wifi::{
+    ap::{AccessPointConfig, AuthMethod},
+    Config,
+};
+use picoserve::{routing::get, AppWithStateBuilder};
+
+/// Address of the Thing on the maintenance network, a `/24`.
+pub const AP_ADDRESS: Ipv4Addr = Ipv4Addr::new(192, 168, 4, 1);
+
+/// WPA2 passphrase of the maintenance network.
+pub use crate::config::AP_PASSPHRASE;
+
+/// HTTP server tasks listening on the maintenance network.
+pub const AP_WEB_TASKS: usize = 2;
+
+/// Sockets on the maintenance network stack: the web tasks, DHCP and DNS.
+pub const AP_STACK_SIZE: usize = AP_WEB_TASKS + 2;
+
+/// Clients the maintenance network serves at once.
+const MAX_CLIENTS: u16 = 2;
+
+static SSID: CriticalSectionMutex<Cell<&'static str>> = CriticalSectionMutex::new(Cell::new(""));
+
+/// Name the access point after the Thing `name` and its MAC address.
+pub(crate) fn set_ssid(name: &str, mac: [u8; 6]) {
+    let ssid = format!("wot-{name}-{:02x}{:02x}{:02x}", mac[3], mac[4], mac[5]);
+    SSID.lock(|s| s.set(Box::leak(ssid.into_boxed_str())));
+}
+
+/// Add the access point to the station `config`.
+///
+/// Enterprise networks are joined without it.
+pub(crate) fn with_access_point(config: Config) -> Config {
+    match config {
+        Config::Station(station) => Config::AccessPointStation(
+            station,
+            AccessPointConfig::default()
+                .with_ssid(SSID.lock(Cell::get))
+                .with_password(AP_PASSPHRASE.into())
+                .with_auth_method(AuthMethod::Wpa2Personal)
+                .with_max_connections(MAX_CLIENTS),
+        ),
+        config => config,
+    }
+}
+
+/// Static configuration of the maintenance network stack.
+#[must_use]
+pub fn stack_config() -> embassy_net::Config {
+    embassy_net::Config::ipv4_static(StaticConfigV4 {
+        address: Ipv4Cidr::new(AP_ADDRESS, 24),
+        gateway: Some(AP_ADDRESS),
+        dns_servers: heapless::Vec::new(),
+    })
+}
+
+#[embassy_executor::task]
+pub async fn dhcp_task(stack: Stack<'static>) {
+    let mut buf = [0; 1500];
+    let mut gateways = [Ipv4Addr::UNSPECIFIED];
+
+    let buffers = UdpBuffers::<1, 1024, 1024, 4>::new();
+    let udp = Udp::new(stack, &buffers);
+    let mut socket = udp
+        .bind(SocketAddr::V4(SocketAddrV4::new(
+            Ipv4Addr::UNSPECIFIED,
+            DEFAULT_SERVER_PORT,
+        )))
+        .await
+        .unwrap();
+
+    loop {
+        if let Err(e) = io::server::run(
+            &mut Server::<_, { MAX_CLIENTS as usize }>::new_with_et(AP_ADDRESS),
+            &ServerOptions {
+                dns: &[AP_ADDRESS],
+                ..ServerOptions::new(AP_ADDRESS, Some(&mut gateways))
+            },
+            &mut socket,
+            &mut buf,
+        )
+        .await
+        {
+            warn!("DHCP server error: {:?}", e);
+        }
+        Timer::after(Duration::from_millis(500)).await;
+    }
+}
+
+/// The app of `Props` with the connectivity checks of Android, Apple and
+/// Windows redirected to the status page, for the maintenance network only:
+/// the captive DNS sends them there, the station side answers them with 404.
+#[derive(Default)]
+pub struct Captive<Props>(Props);
+
+impl<Props: AppWithStateBuilder> AppWithStateBuilder for Captive<Props> {
+    type State = Props::State;
+    type PathRouter = impl picoserve::routing::PathRouter<Self::State>;
+
+    fn build_app(self) -> picoserve::Router<Self::PathRouter, Self::State> {
+        self.0
+            .build_app()
+            .route("/generate_204", get(captive_redirect))
+            .route("/gen_204", get(captive_redirect))
+            .route("/hotspot-detect.html", get(captive_redirect))
+            .route("/connecttest.txt", get(captive_redirect))
+            .route("/ncsi.txt", get(captive_redirect))
+    }
+}
+
+/// Send captive portal probes to the status page on the maintenance network.
+async fn captive_redirect() -> impl picoserve::response::IntoResponse {
+    const TARGET: &str = if cfg!(feature = "ui") {
+        "http://192.168.4.1/ui"
+    } else {
+        "http://192.168.4.1/"
+    };
+
+    picoserve::response::Redirect::to(TARGET)
+}
@@ -1,97 +1,462 @@
-use core::net::{IpAddr, Ipv6Addr, SocketAddr};
+use core::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::atomic::{AtomicBool, Ordering},
+};
 
-use alloc::format;
+use alloc::{format, string::String, vec::Vec};
 use edge_mdns::{
     buf::VecBufAccess,
-    domain::base::Ttl,
+    domain::{
+        base::{iana::Class, Record, Ttl},
+        rdata::{AllRecordData, Ptr},
+    },
     host::{Host, Service, ServiceAnswers},
     io::{self, PORT},
-    HostAnswersMdnsHandler,
+    HostAnswer, HostAnswers, HostAnswersMdnsHandler, MdnsError, NameSlice, RecordDataChain,
 };
-use edge_nal::UdpSplit;
+use edge_nal::{UdpReceive, UdpSend, UdpSplit};
 use edge_nal_embassy::{Udp, UdpBuffers};
+use embassy_futures::select::{select4, Either4};
 use embassy_net::Stack;
 use embassy_sync::{
-    blocking_mutex::raw::NoopRawMutex,
+    blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex},
     signal::Signal,
 };
-use esp_hal::rng::Rng;
-use smoltcp::wire::MAX_HARDWARE_ADDRESS_LEN;
+use embassy_time::{with_deadline, Duration, Instant, Timer};
+
+use wot_esp_demo_core::{dns_sd, integrity};
+
+use crate::{connectivity, entropy::CountingRng, identity, origin, td};
 
 pub const MDNS_STACK_SIZE: usize = 2;
 
-#[embassy_executor::task]
-pub async fn mdns_task(stack: Stack<'static>, rng: Rng, name: &'static str) {
-    let ipv4 = stack.config_v4().unwrap().address.address();
-    let (recv_buf, send_buf) = (
-        VecBufAccess::<NoopRawMutex, 1500>::new(),
-        VecBufAccess::<NoopRawMutex, 1500>::new(),
-    );
+/// Set by [`goodbye`]: every record is then sent with a TTL of 0.
+static LEAVING: AtomicBool = AtomicBool::new(false);
+static GOODBYE: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static GONE: Signal<CriticalSectionRawMutex, ()> = Signal::new();
 
-    let b: UdpBuffers<MDNS_STACK_SIZE, 1500, 1500, 2> = UdpBuffers::new();
+/// A DNS-SD service type the Thing is advertised as.
+#[derive(Clone, Copy)]
+pub struct ServiceType {
+    /// E.g. `_wot`.
+    pub service: &'static str,
+    /// E.g. `_tcp`.
+    pub protocol: &'static str,
+    pub txt: &'static [(&'static str, &'static str)],
+}
 
-    let u = Udp::new(stack, &b);
+/// Web of Things discovery (`_wot._tcp`).
+pub const WOT: ServiceType = ServiceType {
+    service: "_wot",
+    protocol: "_tcp",
+    txt: &[
+        ("td", "/.well-known/wot"),
+        ("type", "Thing"),
+        ("scheme", "http"),
+    ],
+};
 
-    let mut socket = io::bind(
-        &u,
-        SocketAddr::new(IpAddr::V4(ipv4), PORT),
-        Some(stack.config_v4().unwrap().address.address()),
-        None,
-    )
-    .await
-    .unwrap();
-
-    let (send, recv) = socket.split();
-
-    let hw = stack.hardware_address();
-    let hw = hw.as_bytes();
-
-    let hostname = format!(
-        "{name}-{}{}{}{}",
-        hw[MAX_HARDWARE_ADDRESS_LEN - 1],
-        hw[MAX_HARDWARE_ADDRESS_LEN - 2],
-        hw[MAX_HARDWARE_ADDRESS_LEN - 3],
-        hw[MAX_HARDWARE_ADDRESS_LEN - 4]
-    );
-
-    let host = Host {
-        hostname: &hostname,
-        ipv4,
-        ipv6: Ipv6Addr::UNSPECIFIED,
-        ttl: Ttl::from_secs(60),
-    };
+/// Mozilla/WebThings gateways (`_webthing._tcp`).
+pub const WEBTHING: ServiceType = ServiceType {
+    service: "_webthing",
+    protocol: "_tcp",
+    txt: &[("path", "/.well-known/wot")],
+};
+
+/// Service types advertised unless a Thing overrides them.
+pub const DEFAULT_SERVICE_TYPES: &[ServiceType] = &[WOT, WEBTHING];
+
+/// How a Thing is advertised over mDNS, see [`crate::EspThing::mdns_config`].
+#[derive(Clone, Copy)]
+pub struct MdnsConfig {
+    /// Instance name, the [`hostname`] when `None`.
+    pub name: Option<&'static str>,
+    /// Appended to the instance name as `<name>-<location>`, e.g. `kitchen`.
+    pub location: Option<&'static str>,
+    /// Time to live of every record.
+    pub ttl: Ttl,
+    /// SRV priority and weight.
+    pub priority: u16,
+    pub weight: u16,
+    pub service_types: &'static [ServiceType],
+    /// TXT entries added to those of every service type.
+    pub extra_txt: &'static [(&'static str, &'static str)],
+}
 
-    let service = Service {
-        name,
+impl MdnsConfig {
+    /// One minute TTL, both service types and no extra TXT entries.
+    pub const DEFAULT: Self = Self {
+        name: None,
+        location: None,
+        ttl: Ttl::from_secs(60),
         priority: 1,
         weight: 5,
-        service: "_wot",
-        protocol: "_tcp",
-        port: 80,
-        service_subtypes: &[],
-        txt_kvs: &[
-            ("td", "/.well-known/wot"),
-            ("type", "Thing"),
-            ("scheme", "http"),
-        ],
+        service_types: DEFAULT_SERVICE_TYPES,
+        extra_txt: &[],
     };
+}
+
+impl Default for MdnsConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The mDNS hostname of the Thing: its name plus a suffix from the MAC address.
+#[must_use]
+pub fn hostname(stack: Stack<'_>, name: &str) -> String {
+    wot_esp_demo_core::names::hostname(name, stack.hardware_address().as_bytes())
+}
+
+/// `PTR` records [`ServiceAnswers`] does not provide.
+///
+/// The reverse lookup of the host address (`d.c.b.a.in-addr.arpa`) and the
+/// DNS-SD service type enumeration (`_services._dns-sd._udp.local`), so the
+/// Thing shows up in `avahi-browse -a` and `dns-sd -B`.
+struct ExtraAnswers<'a> {
+    host: &'a Host<'a>,
+    /// Reverse name labels, most specific octet first.
+    reverse: [&'a str; 6],
+    services: &'a [Service<'a>],
+}
+
+impl HostAnswers for ExtraAnswers<'_> {
+    fn visit<F, E>(&self, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(HostAnswer) -> Result<(), E>,
+        E: From<MdnsError>,
+    {
+        let hostname = [self.host.hostname, "local"];
+
+        f(Record::new(
+            NameSlice::new(&self.reverse),
+            Class::IN,
+            self.host.ttl,
+            RecordDataChain::Next(AllRecordData::Ptr(Ptr::new(NameSlice::new(&hostname)))),
+        ))?;
+
+        for service in self.services {
+            let service_type = [service.service, service.protocol, "local"];
+            f(Record::new(
+                NameSlice::new(&["_services", "_dns-sd", "_udp", "local"]),
+                Class::IN,
+                self.host.ttl,
+                RecordDataChain::Next(AllRecordData::Ptr(Ptr::new(NameSlice::new(&service_type)))),
+            ))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The [`ServiceAnswers`] of every service of the host.
+struct ServicesAnswers<'a> {
+    host: &'a Host<'a>,
+    services: &'a [Service<'a>],
+}
+
+impl HostAnswers for ServicesAnswers<'_> {
+    fn visit<F, E>(&self, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(HostAnswer) -> Result<(), E>,
+        E: From<MdnsError>,
+    {
+        for service in self.services {
+            ServiceAnswers::new(self.host, service).visit(&mut f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The answers of both sets, the first one first.
+struct Chained<A, B>(A, B);
+
+impl<A: HostAnswers, B: HostAnswers> HostAnswers for Chained<A, B> {
+    fn visit<F, E>(&self, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(HostAnswer) -> Result<(), E>,
+        E: From<MdnsError>,
+    {
+        self.0.visit(&mut f)?;
+        self.1.visit(f)
+    }
+}
+
+/// The answers of `A`, with a TTL of 0 once [`goodbye`] is called.
+struct Goodbye<A>(A);
+
+impl<A: HostAnswers> HostAnswers for Goodbye<A> {
+    fn visit<F, E>(&self, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(HostAnswer) -> Result<(), E>,
+        E: From<MdnsError>,
+    {
+        let leaving = LEAVING.load(Ordering::Relaxed);
+        self.0.visit(|mut answer| {
+            if leaving {
+                answer.set_ttl(Ttl::ZERO);
+            }
+            f(answer)
+        })
+    }
+}
+
+/// Probes sent for a set of names, and the time to wait for a conflicting
+/// answer after each (RFC 6762, section 8.1).
+const PROBES: usize = 3;
+const PROBE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Sets of names probed before announcing the last one anyway.
+const MAX_PROBED: u32 = 10;
+
+/// The mDNS group, where the probes go.
+const MDNS_GROUP: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 251)), PORT);
+
+/// Probe for `hostname.local` and the instance `instance` of every service
+/// type of `service_types`, and for the next alternative of each one another
+/// host answers for, see [`dns_sd`].
+///
+/// Returns the hostname and the instance name to announce: the first free,
+/// or the last probed after [`MAX_PROBED`] sets.
+async fn probe(
+    send: &mut impl UdpSend,
+    recv: &mut impl UdpReceive,
+    hostname: &str,
+    instance: &str,
+    service_types: &[ServiceType],
+) -> (String, String) {
+    let (mut query, mut response) = (alloc::vec![0; 512], alloc::vec![0; 1500]);
+    let (mut host_n, mut instance_n) = (1, 1);
+
+    loop {
+        let host = dns_sd::host_alternative(hostname, host_n);
+        let name = dns_sd::instance_alternative(instance, instance_n);
+        if host_n + instance_n > MAX_PROBED + 1 {
+            warn!("mDNS: no free name after {} probes", MAX_PROBED);
+            return (host, name);
+        }
+
+        let host_record = format!("{host}.local");
+        let instance_records: Vec<String> = service_types
+            .iter()
+            .map(|t| format!("{name}.{}.{}.local", t.service, t.protocol))
+            .collect();
+        let records: Vec<&str> = core::iter::once(host_record.as_str())
+            .chain(instance_records.iter().map(String::as_str))
+            .collect();
+        let Some(len) = dns_sd::probe_query(&records, &mut query) else {
+            warn!(
+                "mDNS: cannot probe for {}, announcing it as is",
+                name.as_str()
+            );
+            return (host, name);
+        };
+
+        let (mut host_taken, mut instance_taken) = (false, false);
+        for _ in 0..PROBES {
+            if send.send(MDNS_GROUP, &query[..len]).await.is_err() {
+                warn!("mDNS: cannot send a probe");
+            }
+            let deadline = Instant::now() + PROBE_INTERVAL;
+            while let Ok(Ok((len, _))) = with_deadline(deadline, recv.receive(&mut response)).await
+            {
+                let answer = &response[..len];
+                host_taken |= dns_sd::claims(answer, &host_record);
+                instance_taken |= instance_records
+                    .iter()
+                    .any(|record| dns_sd::claims(answer, record));
+            }
+            if host_taken || instance_taken {
+                break;
+            }
+        }
+
+        if !host_taken && !instance_taken {
+            return (host, name);
+        }
+        if host_taken {
+            info!("mDNS: {}.local is taken", host.as_str());
+            host_n += 1;
+        }
+        if instance_taken {
+            info!("mDNS: instance {} is taken", name.as_str());
+            instance_n += 1;
+        }
+    }
+}
+
+/// Send every record with a TTL of 0 (RFC 6762, section 10.1), so that
+/// caches drop them instead of keeping the Thing for the TTL, and stop
+/// answering.
+///
+/// Returns once the goodbye is sent; never if the mDNS task is not running.
+pub async fn goodbye() {
+    GOODBYE.signal(());
+    GONE.wait().await;
+}
+
+/// Wait for [`goodbye`], then have `broadcast` send the records again.
+async fn leave(broadcast: &Signal<NoopRawMutex, ()>) {
+    GOODBYE.wait().await;
+    LEAVING.store(true, Ordering::Relaxed);
+    broadcast.signal(());
+    // Leave the broadcast time to go out.
+    Timer::after(Duration::from_millis(250)).await;
+    GONE.signal(());
+}
+
+/// Advertise the Thing `name` on the station network as set by `config`.
+#[embassy_executor::task]
+pub async fn mdns_task(
+    stack: Stack<'static>,
+    rng: CountingRng,
+    name: &'static str,
+    config: MdnsConfig,
+) {
+    let ipv4 = connectivity::wait_address(stack).await;
+    let b: UdpBuffers<MDNS_STACK_SIZE, 1500, 1500, 2> = UdpBuffers::new();
+
+    let u = Udp::new(stack, &b);
+
+    // Start over with the new instance name on each identity change, and
+    // with the new TD digest on each TD change.
+    loop {
+        let (recv_buf, send_buf) = (
+            VecBufAccess::<NoopRawMutex, 1500>::new(),
+            VecBufAccess::<NoopRawMutex, 1500>::new(),
+        );
+
+        let mut socket = io::bind(
+            &u,
+            SocketAddr::new(IpAddr::V4(ipv4), PORT),
+            Some(ipv4),
+            None,
+        )
+        .await
+        .unwrap();
+
+        let (mut send, mut recv) = socket.split();
+
+        let default_hostname = hostname(stack, name);
+
+        let instance = match identity::identity() {
+            Some(identity) => identity.instance_name(),
+            None => {
+                // Boards flashed with the same binary would share the name.
+                let instance = config.name.unwrap_or(default_hostname.as_str());
+                match config.location {
+                    Some(location) => format!("{instance}-{location}"),
+                    None => instance.into(),
+                }
+            }
+        };
+
+        let (hostname, instance) = probe(
+            &mut send,
+            &mut recv,
+            &default_hostname,
+            &instance,
+            config.service_types,
+        )
+        .await;
+        info!(
+            "mDNS: announcing {}.local, instance {}",
+            hostname.as_str(),
+            instance.as_str()
+        );
+        if hostname != default_hostname {
+            origin::init(alloc::boxed::Box::leak(
+                format!("{hostname}.local").into_boxed_str(),
+            ));
+        }
+        identity::claimed(&instance);
+
+        let host = Host {
+            hostname: &hostname,
+            ipv4,
+            ipv6: Ipv6Addr::UNSPECIFIED,
+            ttl: config.ttl,
+        };
+
+        // The start of the TD digest, to cross-check the TD fetched with.
+        let td_sha256 = td::sha256();
+        let td_hash = td_sha256.as_ref().map(integrity::txt);
+        let txt: Vec<Vec<_>> = config
+            .service_types
+            .iter()
+            .map(|service_type| {
+                service_type
+                    .txt
+                    .iter()
+                    .chain(config.extra_txt)
+                    .copied()
+                    .chain(td_hash.as_deref().map(|hash| (integrity::TXT_KEY, hash)))
+                    .collect()
+            })
+            .collect();
+
+        // Two service types with their TXT records stay well under the MTU.
+        let services: Vec<_> = config
+            .service_types
+            .iter()
+            .zip(&txt)
+            .map(|(service_type, txt)| Service {
+                name: &instance,
+                priority: config.priority,
+                weight: config.weight,
+                service: service_type.service,
+                protocol: service_type.protocol,
+                port: 80,
+                service_subtypes: &[],
+                txt_kvs: txt,
+            })
+            .collect();
+
+        let octets = ipv4.octets().map(|octet| format!("{octet}"));
+        let extra = ExtraAnswers {
+            host: &host,
+            reverse: [
+                &octets[3], &octets[2], &octets[1], &octets[0], "in-addr", "arpa",
+            ],
+            services: &services,
+        };
+
+        let signal: Signal<NoopRawMutex, ()> = Signal::new();
+
+        let mdns = io::Mdns::new(
+            Some(ipv4),
+            None,
+            recv,
+            send,
+            recv_buf,
+            send_buf,
+            rng,
+            &signal,
+        );
 
-    let signal: Signal<NoopRawMutex, ()> = Signal::new();
-
-    let mdns = io::Mdns::new(
-        Some(ipv4),
-        None,
-        recv,
-        send,
-        recv_buf,
-        send_buf,
-        rng,
-        &signal,
-    );
-
-    mdns.run(HostAnswersMdnsHandler::new(ServiceAnswers::new(
-        &host, &service,
-    )))
-    .await
-    .unwrap();
+        let handler = HostAnswersMdnsHandler::new(Goodbye(Chained(
+            ServicesAnswers {
+                host: &host,
+                services: &services,
+            },
+            extra,
+        )));
+        let stopped = crate::heartbeat!(
+            "mdns",
+            Duration::from_secs(30),
+            select4(
+                mdns.run(handler),
+                identity::mdns_changed(),
+                td::sha256_changed(td_sha256),
+                leave(&signal),
+            )
+        );
+        match stopped {
+            Either4::First(result) => result.unwrap(),
+            Either4::Second(()) => info!("Identity changed, restarting mDNS"),
+            Either4::Third(()) => info!("TD changed, restarting mDNS"),
+            Either4::Fourth(()) => return,
+        }
+    }
 }
@@ -0,0 +1,108 @@
+//! Writes from web pages on other hosts, refused with HTTP 403.
+//!
+//! Every write route reading its body through [`crate::JsonBody`],
+//! [`crate::ValueBody`] or [`crate::ActionBody`], or taking a
+//! [`crate::WriteGuard`] when it has no body, checks the `Origin`, or
+//! `Referer`, of the request first, see [`wot_esp_demo_core::origin`]. The
+//! Thing's own hosts are its station address, its mDNS name, the host of
+//! `PUBLIC_BASE_URL` and the maintenance access point; `WRITE_ORIGINS` adds
+//! more, comma-separated, or `*` to allow every page. A request carrying
+//! `Authorization: Bearer <WRITE_TOKEN>` passes whatever its origin:
+//!
+//! ```text
+//! HTTP/1.1 403 Forbidden
+//!
+//! {"error":"writes from other sites are refused","origin":"http://evil.example",
+//!  "hint":"add the host to WRITE_ORIGINS, or send Authorization: Bearer <WRITE_TOKEN>"}
+//! ```
+
+use core::cell::Cell;
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use picoserve::request::RequestParts;
+use wot_esp_demo_core::origin::{bearer, check, origin, Headers, Policy};
+
+use crate::{config, connectivity};
+
+/// `<hostname>.local`, once mDNS is up.
+static HOSTNAME: CriticalSectionMutex<Cell<&'static str>> =
+    CriticalSectionMutex::new(Cell::new(""));
+
+/// Allow writes from pages served at the mDNS name `hostname`.
+pub(crate) fn init(hostname: &'static str) {
+    HOSTNAME.lock(|h| h.set(hostname));
+}
+
+/// Whether the request carries `Authorization: Bearer <WRITE_TOKEN>`; never
+/// without `WRITE_TOKEN`.
+///
+/// For the routes open to the token holder only, whatever the origin, such
+/// as `/debug/i2c-scan` and `/actions/shutdown`.
+pub struct Authorized(pub bool);
+
+impl<'r, S> picoserve::extract::FromRequestParts<'r, S> for Authorized {
+    type Rejection = core::convert::Infallible;
+
+    async fn from_request_parts(
+        _state: &'r S,
+        request_parts: &RequestParts<'r>,
+    ) -> Result<Self, Self::Rejection> {
+        let authorization = request_parts
+            .headers()
+            .get("Authorization")
+            .and_then(|v| v.as_str().ok());
+
+        Ok(Self(bearer(authorization, config::WRITE_TOKEN)))
+    }
+}
+
+/// Check the origin of the write `request_parts`.
+///
+/// # Errors
+///
+/// Returns the `Origin`, or the `Referer`, of a write refused.
+pub(crate) fn check_write(request_parts: &RequestParts<'_>) -> Result<(), String> {
+    let header = |name| {
+        request_parts
+            .headers()
+            .get(name)
+            .and_then(|v| v.as_str().ok())
+    };
+    let headers = Headers {
+        origin: header("Origin"),
+        referer: header("Referer"),
+        authorization: header("Authorization"),
+    };
+
+    let address = connectivity::net_info().map(|info| info.config.address.address().to_string());
+    let public = config::PUBLIC_BASE_URL
+        .and_then(origin)
+        .map(|(host, _)| host);
+    #[cfg(feature = "maintenance-ap")]
+    let ap = crate::maintenance_ap::AP_ADDRESS.to_string();
+    let hostname = HOSTNAME.lock(Cell::get);
+
+    let own: Vec<&str> = [address.as_deref(), public.as_deref(), Some(hostname)]
+        .into_iter()
+        .flatten()
+        .filter(|host| !host.is_empty())
+        .collect();
+    #[cfg(feature = "maintenance-ap")]
+    let own = [own.as_slice(), &[ap.as_str()]].concat();
+    let allowed: Vec<&str> = config::WRITE_ORIGINS
+        .into_iter()
+        .flat_map(|origins| origins.split(','))
+        .map(str::trim)
+        .collect();
+
+    let policy = Policy {
+        own: &own,
+        allowed: &allowed,
+        token: config::WRITE_TOKEN,
+    };
+    check(&policy, &headers).map_err(ToString::to_string)
+}
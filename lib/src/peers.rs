@@ -0,0 +1,210 @@
+//! Connections per client, so one client cannot hold every web task.
+//!
+//! Each accepted connection is counted against its remote address in a
+//! [`Peers`] table sized for every web task. A client already holding
+//! [`MAX_KEEP_ALIVE`] connections gets the next ones served with
+//! `Connection: close`, and a kept-alive connection with no request for
+//! [`Timeouts::persistent_start_read_request_ms`] is dropped.
+//!
+//! The connections the server gives up on are counted per [`Closed`]
+//! class. Only a partial request, one started but not finished in time, is
+//! held against the client: a connection left idle, such as a browser
+//! preconnect, is not. A client leaving more than `STRIKE_LIMIT` partial
+//! requests, 3 by default, within `STRIKE_WINDOW_S` seconds, 60 by default,
+//! e.g. a scanner opening connections and dribbling bytes, is counted in a
+//! [`Strikes`] table of [`STRIKE_PEERS`] addresses and its new connections
+//! are reset right away for [`REFUSE_MS`]. Both tables are fixed-size, nothing is allocated per
+//! connection. `GET /debug/peers` shows the tables and the counters, to
+//! check the slots are freed under load:
+//!
+//! ```json
+//! {"accepted":41,"closed":39,"forcedClose":6,"open":{"192.0.2.7":2},
+//!  "closedBy":{"startReadTimeout":5,"readTimeout":0,"writeTimeout":0,"parseError":1},
+//!  "refused":12,"refusing":{"192.0.2.66":240}}
+//! ```
+//!
+//! The [`Timeouts`] are read at `/debug/timeouts` and written there, to tune
+//! them without reflashing:
+//!
+//! ```sh
+//! curl -X PUT -d '{"write_ms":3000}' http://<ip>/debug/timeouts
+//! ```
+//!
+//! Every connection is served with the timeouts current when it was
+//! accepted. The buffer sizes, the number of web tasks and the ports are
+//! fixed at build time.
+
+use core::cell::{Cell, RefCell};
+
+use portable_atomic::{AtomicU32, Ordering};
+
+use alloc::{format, string::String};
+use embassy_net::IpAddress;
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use embassy_time::{Duration, Instant};
+use picoserve::response::{IntoResponse, StatusCode};
+use serde_json::{json, Map, Value};
+
+pub use wot_esp_demo_core::peers::{
+    Closed, Peers, StrikePolicy, Strikes, Timeouts, MAX_KEEP_ALIVE, REFUSE_MS,
+};
+
+use crate::{
+    audit, config, respond_json_negotiated, to_json_response, write::InvalidValue, Accept,
+    ValueBody, WEB_TASKS,
+};
+
+#[cfg(feature = "maintenance-ap")]
+use crate::maintenance_ap::AP_WEB_TASKS;
+#[cfg(not(feature = "maintenance-ap"))]
+const AP_WEB_TASKS: usize = 0;
+
+#[cfg(feature = "debug-port")]
+use crate::debug_port::DEBUG_TASKS;
+#[cfg(not(feature = "debug-port"))]
+const DEBUG_TASKS: usize = 0;
+
+const CONNECTIONS: usize = WEB_TASKS + AP_WEB_TASKS + DEBUG_TASKS;
+
+static PEERS: CriticalSectionMutex<RefCell<Peers<IpAddress, CONNECTIONS>>> =
+    CriticalSectionMutex::new(RefCell::new(Peers::new()));
+
+/// Addresses whose partial requests are counted.
+pub const STRIKE_PEERS: usize = 8;
+
+static STRIKES: CriticalSectionMutex<RefCell<Strikes<IpAddress, STRIKE_PEERS>>> =
+    CriticalSectionMutex::new(RefCell::new(Strikes::new()));
+
+static ACCEPTED: AtomicU32 = AtomicU32::new(0);
+static CLOSED: AtomicU32 = AtomicU32::new(0);
+static FORCED_CLOSE: AtomicU32 = AtomicU32::new(0);
+static REFUSED: AtomicU32 = AtomicU32::new(0);
+static CLOSED_BY: [AtomicU32; Closed::ALL.len()] = [const { AtomicU32::new(0) }; Closed::ALL.len()];
+
+static TIMEOUTS: CriticalSectionMutex<Cell<Timeouts>> =
+    CriticalSectionMutex::new(Cell::new(Timeouts::DEFAULT));
+
+/// The timeouts new connections are served with.
+#[must_use]
+pub fn timeouts() -> Timeouts {
+    TIMEOUTS.lock(Cell::get)
+}
+
+/// The server configuration with the current [`timeouts`], keeping
+/// connections alive or not.
+#[must_use]
+pub fn config(keep_alive: bool) -> picoserve::Config {
+    let timeouts = timeouts();
+    let ms = |ms: u32| Duration::from_millis(ms.into());
+    let config = picoserve::Config::new(picoserve::Timeouts {
+        start_read_request: ms(timeouts.start_read_request_ms),
+        persistent_start_read_request: ms(timeouts.persistent_start_read_request_ms),
+        read_request: ms(timeouts.read_request_ms),
+        write: ms(timeouts.write_ms),
+    });
+    if keep_alive {
+        config.keep_connection_alive()
+    } else {
+        config
+    }
+}
+
+/// Count a connection accepted from `peer`, returning whether to keep it
+/// alive.
+pub(crate) fn open(peer: IpAddress) -> bool {
+    ACCEPTED.fetch_add(1, Ordering::Relaxed);
+    let keep_alive = PEERS.lock(|peers| peers.borrow_mut().open(peer));
+    if !keep_alive {
+        FORCED_CLOSE.fetch_add(1, Ordering::Relaxed);
+        debug!(
+            "{} holds over {} connections, closing one",
+            peer, MAX_KEEP_ALIVE
+        );
+    }
+    keep_alive
+}
+
+/// Count the connection from `peer` as closed.
+pub(crate) fn close(peer: IpAddress) {
+    CLOSED.fetch_add(1, Ordering::Relaxed);
+    PEERS.lock(|peers| peers.borrow_mut().close(peer));
+}
+
+/// Whether to reset a connection from `peer` right away, see [`Strikes`].
+pub(crate) fn refused(peer: IpAddress) -> bool {
+    let now = Instant::now().as_millis();
+    let refused = STRIKES.lock(|strikes| strikes.borrow_mut().refused(peer, now));
+    if refused {
+        REFUSED.fetch_add(1, Ordering::Relaxed);
+    }
+    refused
+}
+
+/// Count a connection from `peer` the server gave up on, striking the peer
+/// if it left a partial request, see [`Closed::strikes`].
+pub(crate) fn closed_by(peer: IpAddress, why: Closed) {
+    CLOSED_BY[why as usize].fetch_add(1, Ordering::Relaxed);
+    if !why.strikes() {
+        return;
+    }
+    let policy = StrikePolicy::from_settings(config::STRIKE_LIMIT, config::STRIKE_WINDOW_S);
+    let now = Instant::now().as_millis();
+    if STRIKES.lock(|strikes| strikes.borrow_mut().strike(peer, now, &policy)) {
+        warn!(
+            "{} left over {} partial requests within {} s, refusing it for {} s",
+            peer,
+            policy.limit,
+            policy.window_ms / 1000,
+            REFUSE_MS / 1000
+        );
+    }
+}
+
+/// Handler for `GET /debug/peers`.
+pub(crate) async fn serve(accept: Accept) -> impl IntoResponse {
+    let open: Map<String, Value> = PEERS.lock(|peers| {
+        peers
+            .borrow()
+            .iter()
+            .map(|(peer, held)| (format!("{peer}"), held.into()))
+            .collect()
+    });
+    let now = Instant::now().as_millis();
+    let refusing: Map<String, Value> = STRIKES.lock(|strikes| {
+        strikes
+            .borrow()
+            .iter_refused(now)
+            .map(|(peer, left_ms)| (format!("{peer}"), left_ms.div_ceil(1000).into()))
+            .collect()
+    });
+    let closed_by: Map<String, Value> = Closed::ALL
+        .iter()
+        .zip(&CLOSED_BY)
+        .map(|(why, count)| (why.as_str().into(), count.load(Ordering::Relaxed).into()))
+        .collect();
+    let report = json!({
+        "accepted": ACCEPTED.load(Ordering::Relaxed),
+        "closed": CLOSED.load(Ordering::Relaxed),
+        "forcedClose": FORCED_CLOSE.load(Ordering::Relaxed),
+        "open": open,
+        "closedBy": closed_by,
+        "refused": REFUSED.load(Ordering::Relaxed),
+        "refusing": refusing,
+    });
+    respond_json_negotiated(accept, to_json_response(&report))
+}
+
+/// Handler for `GET /debug/timeouts`.
+pub(crate) async fn read_timeouts(accept: Accept) -> impl IntoResponse {
+    respond_json_negotiated(accept, to_json_response(&timeouts().to_json()))
+}
+
+/// Handler for `PUT /debug/timeouts`, applied from the next connection.
+pub(crate) async fn write_timeouts(ValueBody(body): ValueBody) -> impl IntoResponse {
+    let timeouts = timeouts().merge(&body).map_err(InvalidValue)?;
+
+    audit::record("timeouts", &timeouts.to_json());
+    TIMEOUTS.lock(|t| t.set(timeouts));
+
+    Ok::<_, InvalidValue>(StatusCode::NO_CONTENT)
+}
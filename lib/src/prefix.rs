@@ -0,0 +1,75 @@
+//! Every route under `ROUTE_PREFIX`, for Things sharing a reverse proxy.
+//!
+//! With `ROUTE_PREFIX=/light1` the app of [`crate::EspThing::run`] is nested
+//! under the prefix and the hrefs of the TD follow, see
+//! [`wot_esp_demo_core::prefix`]. [`crate::root_routes`] stay at the root:
+//! the TD at `/` for direct access, and the well-known URIs. The prefix is
+//! taken off the path of a request before it is matched against the routes
+//! of the TD, so the per-route counters and checks see the paths as
+//! registered:
+//!
+//! ```text
+//! GET /light1/properties/on     the `on` property
+//! GET /light1                   the TD
+//! GET /                         the TD
+//! GET /.well-known/wot          redirect to /
+//! ```
+
+use core::cell::Cell;
+
+use alloc::string::String;
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use picoserve::AppWithStateBuilder;
+use wot_esp_demo_core::prefix::normalize;
+
+use crate::{config, SelfTest, ThingCore};
+
+/// The normalized `ROUTE_PREFIX`, once [`init`] ran.
+static PREFIX: CriticalSectionMutex<Cell<Option<&'static str>>> =
+    CriticalSectionMutex::new(Cell::new(None));
+
+/// Normalize `ROUTE_PREFIX`, checked by [`config::validate`] before.
+pub(crate) fn init() {
+    let prefix = config::ROUTE_PREFIX
+        .and_then(|raw| normalize(raw).ok())
+        .map(|prefix| &*alloc::boxed::Box::leak(String::into_boxed_str(prefix)));
+    PREFIX.lock(|p| p.set(prefix));
+}
+
+/// The prefix of every route, e.g. `/light1`, if any.
+#[must_use]
+pub fn get() -> Option<&'static str> {
+    PREFIX.lock(Cell::get)
+}
+
+/// `path` of a request without the prefix, as the route was registered.
+pub(crate) fn strip(path: &str) -> &str {
+    match get() {
+        Some(prefix) => wot_esp_demo_core::prefix::strip(path, prefix),
+        None => path,
+    }
+}
+
+/// The app of `Props` under [`get`], next to [`crate::root_routes`]; the
+/// app itself when built without `ROUTE_PREFIX`.
+#[derive(Default)]
+pub struct Prefixed<Props>(Props);
+
+impl<Props> AppWithStateBuilder for Prefixed<Props>
+where
+    Props: AppWithStateBuilder,
+    Props::State: AsRef<ThingCore> + SelfTest + Clone + Copy,
+{
+    type State = Props::State;
+    type PathRouter = impl picoserve::routing::PathRouter<Self::State>;
+
+    fn build_app(self) -> picoserve::Router<Self::PathRouter, Self::State> {
+        let app = self.0.build_app();
+
+        // Set by build.rs along with `ROUTE_PREFIX`, which `init` checked.
+        #[cfg(route_prefix)]
+        let app = crate::root_routes::<Self::State>().nest(get().unwrap_or("/"), app);
+
+        app
+    }
+}
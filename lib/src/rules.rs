@@ -0,0 +1,182 @@
+//! Automation rules: write to another Thing when a local value crosses a
+//! threshold, e.g. switch on the fan of the room when it gets above 28 °C.
+//!
+//! The app feeds its properties and events to the rules with [`watch`], one
+//! task per source. `PUT /rules/{n}` sets rule `n` (0 to 3) as a
+//! [`Rule`], `null` clears it; `GET /rules` lists the sources and the rules
+//! with when they last fired, in milliseconds since boot:
+//!
+//! ```json
+//! {"sources":["temperature"],"rules":[{"source":"temperature","comparison":">",
+//!  "threshold":28.0,"target":"http://192.0.2.7/properties/on","payload":"true",
+//!  "cooldownS":300,"lastFired":81234},null,null,null]}
+//! ```
+//!
+//! A rule fires once per crossing and not again within its cooldown, see
+//! [`RuleState`]; the target is written with a PUT through
+//! [`crate::http_client`]. The rules are kept in RAM only, and are lost on
+//! reset.
+
+use core::cell::RefCell;
+
+use alloc::vec::Vec;
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, CriticalSectionMutex},
+    mutex::Mutex,
+    watch::Watch,
+};
+use embassy_time::{Duration, Instant};
+use picoserve::{
+    response::{IntoResponse, StatusCode},
+    routing::{get, parse_path_segment, put, PathRouter},
+    Router,
+};
+use serde_json::{json, Value};
+
+pub use wot_esp_demo_core::rules::{Comparison, Rule, RuleState, MAX_RULES};
+
+use crate::{
+    audit, connectivity, http_client::Client, to_json_response, write::InvalidValue, ValueBody,
+};
+
+/// Sockets used: the targets are written one at a time.
+pub const RULES_STACK_SIZE: usize = 1;
+
+/// Sources fed with [`watch`].
+pub const MAX_SOURCES: usize = 4;
+
+/// Time given to a target to take the write.
+const TARGET_TIMEOUT: Duration = Duration::from_secs(3);
+
+struct Slot {
+    rule: Option<Rule>,
+    state: RuleState,
+}
+
+static RULES: CriticalSectionMutex<RefCell<[Slot; MAX_RULES]>> =
+    CriticalSectionMutex::new(RefCell::new(
+        [const {
+            Slot {
+                rule: None,
+                state: RuleState::new(),
+            }
+        }; MAX_RULES],
+    ));
+
+static SOURCES: CriticalSectionMutex<RefCell<heapless::Vec<&'static str, MAX_SOURCES>>> =
+    CriticalSectionMutex::new(RefCell::new(heapless::Vec::new()));
+
+/// Held while a target is written, so the sources share one socket.
+static WRITING: Mutex<CriticalSectionRawMutex, ()> = Mutex::new(());
+
+fn sources() -> heapless::Vec<&'static str, MAX_SOURCES> {
+    SOURCES.lock(|s| s.borrow().clone())
+}
+
+/// Compare `value` of `source` with the rules on it, returning the rules
+/// fired with their number.
+fn evaluate(source: &str, value: f32) -> Vec<(usize, Rule)> {
+    let now = Instant::now().as_millis();
+    RULES.lock(|rules| {
+        rules
+            .borrow_mut()
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(n, slot)| {
+                let rule = slot.rule.as_ref().filter(|rule| rule.source == source)?;
+                slot.state
+                    .update(rule, value, now)
+                    .then(|| (n, rule.clone()))
+            })
+            .collect()
+    })
+}
+
+/// Write the payload of rule `n` for `value` to its target.
+async fn fire(n: usize, rule: &Rule, value: f32) {
+    let Some(stack) = connectivity::stack() else {
+        warn!("Rule {}: not connected", n);
+        return;
+    };
+
+    let body = rule.render(value);
+    let _writing = WRITING.lock().await;
+    let mut buf = [0; 256];
+    match Client::new(stack)
+        .with_timeout(TARGET_TIMEOUT)
+        .put_json(&rule.target, body.as_bytes(), &mut buf)
+        .await
+    {
+        Ok(response) => info!("Rule {}: {} -> {}", n, rule.target, response.status),
+        Err(e) => warn!("Rule {}: {} failed: {}", n, rule.target, e.as_str()),
+    }
+}
+
+/// Feed every value of `source` to the rules on `name`, as a number: `f32`
+/// sources with [`core::convert::identity`], a `bool` one with
+/// `|on| f32::from(u8::from(on))` (1 or 0), an event with the value of its
+/// data.
+///
+/// Run it in a task per source.
+pub async fn watch<T: Clone, const N: usize>(
+    name: &'static str,
+    source: &Watch<CriticalSectionRawMutex, T, N>,
+    to_number: impl Fn(T) -> f32,
+) -> ! {
+    if SOURCES.lock(|s| s.borrow_mut().push(name)).is_err() {
+        warn!("Rules: more than {} sources, {} ignored", MAX_SOURCES, name);
+    }
+
+    let mut receiver = source.receiver().expect("rules source receiver");
+    loop {
+        let value = to_number(receiver.changed().await);
+        for (n, rule) in evaluate(name, value) {
+            fire(n, &rule, value).await;
+        }
+    }
+}
+
+async fn read_rules() -> impl IntoResponse {
+    let rules: Vec<Value> = RULES.lock(|rules| {
+        rules
+            .borrow()
+            .iter()
+            .map(|slot| match serde_json::to_value(&slot.rule) {
+                Ok(Value::Object(mut rule)) => {
+                    rule.insert("lastFired".into(), json!(slot.state.last_fired_ms()));
+                    Value::Object(rule)
+                }
+                _ => Value::Null,
+            })
+            .collect()
+    });
+    to_json_response(&json!({ "sources": sources().as_slice(), "rules": rules }))
+}
+
+async fn write_rule(n: usize, ValueBody(value): ValueBody) -> Result<StatusCode, InvalidValue> {
+    if n >= MAX_RULES {
+        return Err(InvalidValue("Rules are numbered 0 to 3"));
+    }
+    let rule: Option<Rule> =
+        serde_json::from_value(value.clone()).map_err(|_| InvalidValue("Invalid rule"))?;
+    if let Some(rule) = &rule {
+        rule.validate(&sources()).map_err(InvalidValue)?;
+    }
+
+    // A new rule starts armed, with no cooldown.
+    RULES.lock(|rules| {
+        rules.borrow_mut()[n] = Slot {
+            rule,
+            state: RuleState::new(),
+        };
+    });
+    audit::record("rules", &value);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Add `GET /rules` and `PUT /rules/{n}` to `router`.
+pub fn routes<S, R: PathRouter<S>>(router: Router<R, S>) -> Router<impl PathRouter<S>, S> {
+    router
+        .route("/rules", get(read_rules))
+        .route(("/rules", parse_path_segment::<usize>()), put(write_rule))
+}
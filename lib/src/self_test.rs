@@ -0,0 +1,75 @@
+//! The `selfTest` action and the `lastSelfTest` property of every Thing.
+//!
+//! The action runs the checks of the app state's [`SelfTest`] implementation
+//! one after the other and answers with their outcome, which is also kept as
+//! `lastSelfTest` along with the uptime it ran at. With the `self-test`
+//! feature, `lastSelfTest` also carries the outcome of each read of the
+//! start-up check as `startup`, see [`crate::http_client::self_test_task`].
+
+use core::cell::RefCell;
+
+use alloc::vec::Vec;
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, CriticalSectionMutex},
+    mutex::Mutex,
+};
+use embassy_time::Instant;
+use picoserve::response::{IntoResponse, Response, StatusCode};
+use serde_json::{json, Value};
+use wot_esp_demo_core::self_test::results;
+
+pub use wot_esp_demo_core::self_test::Outcome;
+
+use crate::{respond_negotiated, to_json_response, Accept, SelfTest};
+
+static LAST: CriticalSectionMutex<RefCell<Option<Value>>> =
+    CriticalSectionMutex::new(RefCell::new(None));
+
+#[cfg(feature = "self-test")]
+static STARTUP: CriticalSectionMutex<RefCell<Option<Value>>> =
+    CriticalSectionMutex::new(RefCell::new(None));
+
+/// Held while the checks run, so two runs do not drive the hardware at once.
+static RUNNING: Mutex<CriticalSectionRawMutex, ()> = Mutex::new(());
+
+pub(crate) async fn invoke<S: SelfTest>(state: &S) -> impl IntoResponse {
+    let Ok(_running) = RUNNING.try_lock() else {
+        return Err(
+            Response::new(StatusCode::CONFLICT, "Self-test already running")
+                .with_header("Content-Type", "text/plain"),
+        );
+    };
+
+    let mut outcomes = Vec::with_capacity(S::CHECKS.len());
+    for &name in S::CHECKS {
+        let outcome = state.check(name).await;
+        info!("Self-test: {} {}", name, outcome.as_str());
+        outcomes.push((name, outcome));
+    }
+    let results = results(outcomes);
+
+    let at = Instant::now().as_millis();
+    LAST.lock(|last| *last.borrow_mut() = Some(json!({ "at": at, "results": results.clone() })));
+
+    Ok(to_json_response(&results))
+}
+
+/// Keep the outcome of each read of the start-up check for `lastSelfTest`.
+#[cfg(feature = "self-test")]
+pub(crate) fn record_startup(results: Value) {
+    STARTUP.lock(|startup| *startup.borrow_mut() = Some(results));
+}
+
+pub(crate) async fn read_last(accept: Accept) -> impl IntoResponse {
+    let last = LAST.lock(|l| l.borrow().clone());
+    #[cfg(feature = "self-test")]
+    let last = match (last, STARTUP.lock(|s| s.borrow().clone())) {
+        (Some(mut last), Some(startup)) => {
+            last["startup"] = startup;
+            Some(last)
+        }
+        (None, Some(startup)) => Some(json!({ "startup": startup })),
+        (last, None) => last,
+    };
+    respond_negotiated(accept, &last)
+}
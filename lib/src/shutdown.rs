@@ -0,0 +1,78 @@
+//! The `shutdown` action, to power a Thing down without consumers trying it
+//! for the TTL of its mDNS records (the `shutdown` feature).
+//!
+//! `POST /actions/shutdown` needs `Authorization: Bearer <WRITE_TOKEN>` on
+//! top of the origin check of every write, see [`crate::origin`], and
+//! answers right away. The Thing then resets every new connection, sends the
+//! mDNS goodbye (see [`mdns::goodbye`]), leaves the open connections
+//! [`DRAIN`] to finish and enters deep sleep. A timer wakes it up after
+//! `SHUTDOWN_WAKE_MIN` minutes, a day by default, see
+//! [`wot_esp_demo_core::shutdown::wake_min`]; a reset or a power cycle does
+//! so sooner.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use embassy_time::{with_timeout, Duration, Timer};
+use esp_hal::{
+    peripherals::LPWR,
+    rtc_cntl::{sleep::TimerWakeupSource, Rtc},
+};
+use picoserve::response::{IntoResponse, Response, StatusCode};
+
+use crate::{config, mdns, origin::Authorized, WriteGuard};
+
+/// Time the open connections get to finish before the chip sleeps.
+pub const DRAIN: Duration = Duration::from_secs(1);
+
+/// Longest wait for the mDNS goodbye to go out.
+const GOODBYE_TIMEOUT: Duration = Duration::from_secs(2);
+
+static REQUEST: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+static STOPPING: AtomicBool = AtomicBool::new(false);
+
+/// Whether the Thing is shutting down, and new connections are refused.
+#[must_use]
+pub fn stopping() -> bool {
+    STOPPING.load(Ordering::Relaxed)
+}
+
+/// Handler for `POST /actions/shutdown`, the shutdown is run by
+/// [`shutdown_task`]. Answers 403 to a request not [`Authorized`].
+pub(crate) async fn invoke(
+    _guard: WriteGuard,
+    Authorized(authorized): Authorized,
+) -> impl IntoResponse {
+    if !authorized {
+        return Err(Response::new(
+            StatusCode::FORBIDDEN,
+            "Send Authorization: Bearer <WRITE_TOKEN> to shut the Thing down",
+        )
+        .with_header("Content-Type", "text/plain"));
+    }
+
+    info!("Shutdown requested");
+    REQUEST.signal(());
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[embassy_executor::task]
+pub async fn shutdown_task() {
+    REQUEST.wait().await;
+    STOPPING.store(true, Ordering::Relaxed);
+
+    if with_timeout(GOODBYE_TIMEOUT, mdns::goodbye())
+        .await
+        .is_err()
+    {
+        warn!("mDNS goodbye not sent");
+    }
+    Timer::after(DRAIN).await;
+
+    let wake_min = wot_esp_demo_core::shutdown::wake_min(config::SHUTDOWN_WAKE_MIN);
+    info!("Shutting down for {} min", wake_min);
+    let timer = TimerWakeupSource::new(core::time::Duration::from_secs(60 * u64::from(wake_min)));
+    // The state took the peripherals; nothing else uses the RTC.
+    let mut rtc = Rtc::new(unsafe { LPWR::steal() });
+    rtc.sleep_deep(&[&timer]);
+}
@@ -0,0 +1,47 @@
+//! Drivers that can be dropped and built again while the Thing runs, e.g. to
+//! recover a sensor that stopped answering or to pick up one plugged in
+//! later.
+//!
+//! A driver built once in [`crate::EspThingState::new`] and leaked with
+//! [`mk_static!`] owns its peripheral and pins for good. One held in a
+//! [`Slot`] instead can be rebuilt by [`Slot::rebuild`]: the old driver is
+//! dropped first, releasing the bus, then the new one is built from the
+//! handles the app kept. The users of the driver wait for the slot meanwhile,
+//! and find it empty if the rebuild failed.
+
+use embassy_sync::{
+    blocking_mutex::raw::CriticalSectionRawMutex,
+    mutex::{Mutex, MutexGuard},
+};
+
+/// A driver, or nothing if it could not be built.
+pub struct Slot<T> {
+    driver: Mutex<CriticalSectionRawMutex, Option<T>>,
+}
+
+impl<T> Slot<T> {
+    /// A slot holding `driver`, if it could be built.
+    pub const fn new(driver: Option<T>) -> Self {
+        Self {
+            driver: Mutex::new(driver),
+        }
+    }
+
+    /// Wait for the driver, `None` in the guard if the slot is empty.
+    pub async fn lock(&self) -> MutexGuard<'_, CriticalSectionRawMutex, Option<T>> {
+        self.driver.lock().await
+    }
+
+    /// Drop the driver, then put the one returned by `build` in its place.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `build`, leaving the slot empty.
+    pub async fn rebuild<E>(&self, build: impl FnOnce() -> Result<T, E>) -> Result<(), E> {
+        let mut driver = self.driver.lock().await;
+        // Release the peripheral before building on it again.
+        *driver = None;
+        *driver = Some(build()?);
+        Ok(())
+    }
+}
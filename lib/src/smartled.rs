@@ -0,0 +1,192 @@
+//! Addressable LED pixels on an RMT channel (the `smartled` feature), in
+//! place of `esp_hal_smartled`.
+//!
+//! [`SmartLedsAdapter::new`] takes the channel and the data pin, and sizes
+//! the buffer of RMT pulses from the pixel count of its type,
+//! [`rmt_buffer_len`]: the count is named once, and a light holding a
+//! `SmartLedsAdapter<'_, 8>` cannot be given an adapter built for another
+//! count.
+//!
+//! ```ignore
+//! let rmt = Rmt::new(peripherals.RMT, Rate::from_mhz(80)).unwrap();
+//! let mut led = SmartLedsAdapter::<8>::new(rmt.channel0, peripherals.GPIO2);
+//! led.write([RGB8::new(255, 0, 0); 8])?;
+//! ```
+//!
+//! `new` sends WS2812B timings in GRB order. Other chips take their
+//! [`LedTiming`] and [`ColorOrder`] through [`SmartLedsAdapter::with_chip`],
+//! e.g. a WS2811 strip at 400 kHz:
+//!
+//! ```ignore
+//! let strip = SmartLedsAdapter::<50>::with_chip(
+//!     rmt.channel1, peripherals.GPIO6, LedTiming::WS2811, ColorOrder::Rgb,
+//! );
+//! ```
+//!
+//! The pulses are encoded by [`Symbols`] at the 80 MHz clock of
+//! [`RMT_CLOCK_HZ`]. [`SmartLedsAdapter::set_timing`] changes them on a
+//! running adapter, dividing the clock down for timings that fit a slower
+//! one, as the `conservative` [`TimingProfile`] of the light.
+
+use alloc::{boxed::Box, vec};
+
+use esp_hal::{
+    gpio::{interconnect::PeripheralOutput, Level},
+    rmt::{self, Channel, PulseCode, Tx, TxChannelConfig, TxChannelCreator},
+    Blocking,
+};
+use smart_leds_trait::{SmartLedsWrite, RGB8};
+
+pub use wot_esp_demo_core::smartled::{
+    clock_divider, rmt_buffer_len, ColorOrder, LedTiming, Symbols, TimingProfile,
+};
+
+/// Clock the RMT is created with, `Rmt::new(peripherals.RMT,
+/// Rate::from_mhz(80))`, in hertz.
+pub const RMT_CLOCK_HZ: u32 = 80_000_000;
+
+/// Why a frame was not sent.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LedAdapterError {
+    /// More pixels than the adapter was built for.
+    BufferSizeExceeded,
+    /// The RMT reported an error, see [`rmt::Error`].
+    Transmission(rmt::Error),
+    /// The clock asked of [`SmartLedsAdapter::set_timing`] is not a divider
+    /// of [`RMT_CLOCK_HZ`] from 1 to 255.
+    ClockDivider,
+    /// The channel refused the new divider, see [`rmt::ConfigError`].
+    Config(rmt::ConfigError),
+}
+
+/// `LEDS` pixels on an RMT channel.
+pub struct SmartLedsAdapter<'d, const LEDS: usize> {
+    /// Taken by a transmission, and given back when it ends.
+    channel: Option<Channel<'d, Blocking, Tx>>,
+    config: TxChannelConfig,
+    symbols: Symbols,
+    order: ColorOrder,
+    buffer: Box<[PulseCode]>,
+}
+
+impl<'d, const LEDS: usize> SmartLedsAdapter<'d, LEDS> {
+    /// The adapter of `LEDS` WS2812B pixels on `channel`, driven from `pin`.
+    ///
+    /// # Panics
+    ///
+    /// If the channel cannot be configured, as `Rmt::new` in the demos.
+    pub fn new<C>(channel: C, pin: impl PeripheralOutput<'d>) -> Self
+    where
+        C: TxChannelCreator<'d, Blocking>,
+    {
+        Self::with_chip(channel, pin, LedTiming::WS2812B, ColorOrder::Grb)
+    }
+
+    /// The adapter of `LEDS` pixels on `channel`, driven from `pin`, of a
+    /// chip with the pulse `timing` reading its color bytes in `order`.
+    ///
+    /// # Panics
+    ///
+    /// If the channel cannot be configured, as `Rmt::new` in the demos.
+    pub fn with_chip<C>(
+        channel: C,
+        pin: impl PeripheralOutput<'d>,
+        timing: LedTiming,
+        order: ColorOrder,
+    ) -> Self
+    where
+        C: TxChannelCreator<'d, Blocking>,
+    {
+        let config = TxChannelConfig::default()
+            .with_clk_divider(1)
+            .with_idle_output_level(Level::Low)
+            .with_idle_output(true)
+            .with_carrier_modulation(false);
+        let channel = channel.configure_tx(&config).unwrap().with_pin(pin);
+
+        Self {
+            channel: Some(channel),
+            config,
+            symbols: timing.symbols(RMT_CLOCK_HZ),
+            order,
+            buffer: vec![PulseCode::end_marker(); rmt_buffer_len(LEDS, order)].into_boxed_slice(),
+        }
+    }
+
+    /// Send `timing` from the next frame on, encoded at an RMT clock of
+    /// `clock_hz` divided down from [`RMT_CLOCK_HZ`].
+    ///
+    /// # Errors
+    ///
+    /// If `clock_hz` does not divide [`RMT_CLOCK_HZ`], or the channel
+    /// refuses the divider; the adapter keeps its previous timing.
+    pub fn set_timing(&mut self, timing: LedTiming, clock_hz: u32) -> Result<(), LedAdapterError> {
+        let divider = clock_divider(RMT_CLOCK_HZ, clock_hz).ok_or(LedAdapterError::ClockDivider)?;
+        let config = self.config.with_clk_divider(divider);
+        let Some(channel) = self.channel.as_mut() else {
+            unreachable!("every transmission gives the channel back");
+        };
+        channel
+            .apply_config(&config)
+            .map_err(LedAdapterError::Config)?;
+
+        self.config = config;
+        self.symbols = timing.symbols(clock_hz);
+        Ok(())
+    }
+
+    /// Encode `pixels` into the buffer in the color order of the chip,
+    /// followed by the reset latching them.
+    fn encode(&mut self, pixels: impl IntoIterator<Item = RGB8>) -> Result<(), LedAdapterError> {
+        let mut pulses = self.buffer.iter_mut();
+        for RGB8 { r, g, b } in pixels {
+            let bytes = self.order.bytes((r, g, b));
+            for code in self.symbols.encode(&bytes[..self.order.bytes_per_pixel()]) {
+                *pulses.next().ok_or(LedAdapterError::BufferSizeExceeded)? = PulseCode(code);
+            }
+        }
+        *pulses.next().ok_or(LedAdapterError::BufferSizeExceeded)? =
+            PulseCode(self.symbols.latch());
+        Ok(())
+    }
+}
+
+impl<const LEDS: usize> SmartLedsWrite for SmartLedsAdapter<'_, LEDS> {
+    type Error = LedAdapterError;
+    type Color = RGB8;
+
+    /// Send a frame of at most `LEDS` pixels, and wait until it is out.
+    fn write<T, I>(&mut self, iterator: T) -> Result<(), Self::Error>
+    where
+        T: IntoIterator<Item = I>,
+        I: Into<Self::Color>,
+    {
+        self.encode(iterator.into_iter().map(Into::into))?;
+
+        let Some(channel) = self.channel.take() else {
+            unreachable!("every transmission gives the channel back");
+        };
+        let (channel, sent) = match channel
+            .transmit(&self.buffer)
+            .and_then(rmt::TxTransaction::wait)
+        {
+            Ok(channel) => (channel, Ok(())),
+            Err((e, channel)) => (channel, Err(LedAdapterError::Transmission(e))),
+        };
+        self.channel = Some(channel);
+        sent
+    }
+}
+
+/// The adapter of `$leds` pixels on `$channel`, driven from `$pin`.
+///
+/// The buffer macro of `esp_hal_smartled`, kept for code written against
+/// it: the buffer is now sized by the adapter itself.
+#[deprecated(note = "use `SmartLedsAdapter::<LEDS>::new(channel, pin)`")]
+#[macro_export]
+macro_rules! smart_led_buffer {
+    ($channel:expr, $pin:expr, $leds:expr) => {
+        $crate::smartled::SmartLedsAdapter::<{ $leds }>::new($channel, $pin)
+    };
+}
@@ -0,0 +1,96 @@
+//! Set the [`crate::clock`] from an SNTP server, `NTP_SERVER` at build time
+//! (`pool.ntp.org` by default), at start-up and then every
+//! [`RESYNC_EVERY`].
+
+use embassy_net::{
+    udp::{PacketMetadata, UdpSocket},
+    Stack,
+};
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use wot_esp_demo_core::sntp::{parse, PACKET_LEN, REQUEST};
+
+use crate::{clock, dns};
+
+/// Sockets used: the SNTP socket.
+pub const SNTP_STACK_SIZE: usize = 1;
+
+/// The server asked unless `NTP_SERVER` is set.
+pub const DEFAULT_SERVER: &str = "pool.ntp.org";
+
+/// How often the clock is set again once synchronized.
+pub const RESYNC_EVERY: Duration = Duration::from_secs(60 * 60);
+
+/// Wait before asking again after a failure.
+const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Time the server has to answer.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(5);
+
+const NTP_PORT: u16 = 123;
+
+/// The server from the `NTP_SERVER` build-time env var.
+#[must_use]
+pub fn server() -> &'static str {
+    crate::config::NTP_SERVER.unwrap_or(DEFAULT_SERVER)
+}
+
+#[embassy_executor::task]
+pub async fn sntp_task(stack: Stack<'static>) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buffer = [0; PACKET_LEN + 16];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_buffer = [0; PACKET_LEN];
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    socket.bind(0).unwrap();
+
+    let server = server();
+    loop {
+        stack.wait_config_up().await;
+
+        let now = match dns::resolve(stack, server).await {
+            Ok(ip) => query(&socket, (ip, NTP_PORT)).await,
+            Err(_) => None,
+        };
+        match now {
+            Some(now) => {
+                if clock::unix_ms().is_none() {
+                    info!("Clock set from {}", server);
+                }
+                clock::set(now);
+                Timer::after(RESYNC_EVERY).await;
+            }
+            None => {
+                warn!("No time from {}, retrying", server);
+                Timer::after(RETRY_AFTER).await;
+            }
+        }
+    }
+}
+
+/// Ask `server` for the time, `None` without a valid answer in time.
+///
+/// The time is corrected by half the round trip.
+async fn query(socket: &UdpSocket<'_>, server: (embassy_net::IpAddress, u16)) -> Option<u64> {
+    let sent = Instant::now();
+    socket.send_to(&REQUEST, server).await.ok()?;
+
+    let mut answer = [0; PACKET_LEN + 16];
+    loop {
+        let (len, meta) = with_timeout(ANSWER_TIMEOUT, socket.recv_from(&mut answer))
+            .await
+            .ok()?
+            .ok()?;
+        // Stray packets on the port are not answers.
+        if meta.endpoint.addr != server.0 {
+            continue;
+        }
+        let now = parse(&answer[..len])?;
+        return Some(now + sent.elapsed().as_millis() / 2);
+    }
+}
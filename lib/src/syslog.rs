@@ -0,0 +1,130 @@
+//! Forward log records to a remote syslog collector over UDP (RFC 5424).
+//!
+//! The logger formats each record into a fixed-size line and queues it
+//! without blocking; [`syslog_task`] drains the queue once the network is up.
+//! Lines are dropped when the queue is full or the collector is unreachable,
+//! and counted in the next line sent. The task itself never logs, as its own
+//! lines would land in the queue it drains.
+
+use core::fmt::Write as _;
+
+use portable_atomic::{AtomicU32, Ordering};
+
+use embassy_net::{
+    udp::{PacketMetadata, UdpSocket},
+    Stack,
+};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use embassy_time::Instant;
+use log::{Level, Record};
+
+use crate::dns;
+
+/// Sockets used by the forwarder: the UDP socket.
+pub const SYSLOG_STACK_SIZE: usize = 1;
+
+/// Longest message forwarded, longer records are truncated.
+const LINE_LEN: usize = 192;
+
+/// Records buffered while the network is down or the task is busy.
+const QUEUE_LEN: usize = 8;
+
+/// Facility `local0`.
+const FACILITY: u8 = 16;
+
+struct Line {
+    severity: u8,
+    text: heapless::String<LINE_LEN>,
+}
+
+static QUEUE: Channel<CriticalSectionRawMutex, Line, QUEUE_LEN> = Channel::new();
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Collector host from the `SYSLOG_HOST` build-time env var.
+#[must_use]
+pub fn host() -> Option<&'static str> {
+    crate::config::SYSLOG_HOST.filter(|host| !host.is_empty())
+}
+
+/// Collector port from the `SYSLOG_PORT` build-time env var, 514 by default.
+fn port() -> u16 {
+    crate::config::SYSLOG_PORT
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(514)
+}
+
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Queue `record` for forwarding, dropping it if the queue is full.
+pub(crate) fn record(record: &Record) {
+    let mut text = heapless::String::new();
+    let uptime = Instant::now().as_millis();
+    let _ = write!(text, "[{uptime}] {}", record.args());
+
+    let line = Line {
+        severity: severity(record.level()),
+        text,
+    };
+    if QUEUE.try_send(line).is_err() {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[embassy_executor::task]
+pub async fn syslog_task(
+    stack: Stack<'static>,
+    host: &'static str,
+    hostname: &'static str,
+    app_name: &'static str,
+) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buffer = [0; 16];
+    let mut tx_meta = [PacketMetadata::EMPTY; 2];
+    let mut tx_buffer = [0; 2 * (LINE_LEN + 128)];
+
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    socket.bind(0).unwrap();
+
+    let port = port();
+    let mut target = None;
+    let mut packet = heapless::String::<{ LINE_LEN + 128 }>::new();
+
+    loop {
+        let line = QUEUE.receive().await;
+
+        // Cached, and the last address kept while the name does not resolve.
+        target = dns::resolve_quietly(stack, host).await.ok().or(target);
+        let Some(ip) = target else {
+            DROPPED.fetch_add(1, Ordering::Relaxed);
+            continue;
+        };
+
+        let dropped = DROPPED.swap(0, Ordering::Relaxed);
+
+        packet.clear();
+        let _ = write!(
+            packet,
+            "<{}>1 - {hostname} {app_name} - - - ",
+            FACILITY * 8 + line.severity,
+        );
+        if dropped > 0 {
+            let _ = write!(packet, "({dropped} lines dropped) ");
+        }
+        let _ = packet.push_str(&line.text);
+
+        let _ = socket.send_to(packet.as_bytes(), (ip, port)).await;
+    }
+}
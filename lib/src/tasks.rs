@@ -0,0 +1,124 @@
+//! Inventory of the long-running tasks and their heartbeats.
+//!
+//! A task beats with [`crate::heartbeat!`] once per loop, or wraps a future
+//! that runs for long with it; the first beat registers the task. With the
+//! `debug-tasks` feature the inventory is served as JSON at `/debug/tasks`,
+//! a task silent for over three periods being `stale`:
+//!
+//! ```json
+//! [{"name":"measurement","age_s":0,"period_s":1,"stale":false}]
+//! ```
+
+use core::{
+    cell::RefCell,
+    future::Future,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use embassy_time::{Duration, Instant, Timer};
+
+/// Tasks kept; beats of further tasks are not recorded.
+pub const MAX_TASKS: usize = 16;
+
+/// A task's heartbeat, declared by [`crate::heartbeat!`].
+pub struct Heartbeat {
+    name: &'static str,
+    period: Duration,
+    registered: AtomicBool,
+    /// Uptime in seconds at the last beat.
+    last_s: AtomicU32,
+}
+
+static REGISTRY: CriticalSectionMutex<RefCell<heapless::Vec<&'static Heartbeat, MAX_TASKS>>> =
+    CriticalSectionMutex::new(RefCell::new(heapless::Vec::new()));
+
+impl Heartbeat {
+    /// The heartbeat of the task `name`, expected every `period`.
+    #[must_use]
+    pub const fn new(name: &'static str, period: Duration) -> Self {
+        Self {
+            name,
+            period,
+            registered: AtomicBool::new(false),
+            last_s: AtomicU32::new(0),
+        }
+    }
+
+    /// Record that the task is alive.
+    pub fn beat(&'static self) {
+        #[allow(clippy::cast_possible_truncation)]
+        self.last_s
+            .store(Instant::now().as_secs() as u32, Ordering::Relaxed);
+
+        if !self.registered.load(Ordering::Relaxed) {
+            REGISTRY.lock(|registry| {
+                // Only load and store, the C3 has no compare-and-swap.
+                if !self.registered.load(Ordering::Relaxed) {
+                    let _ = registry.borrow_mut().push(self);
+                    self.registered.store(true, Ordering::Relaxed);
+                }
+            });
+        }
+    }
+
+    /// Run `future`, beating every period until it completes.
+    pub async fn beat_while<F: Future>(&'static self, future: F) -> F::Output {
+        let beating = async {
+            loop {
+                self.beat();
+                Timer::after(self.period).await;
+            }
+        };
+
+        match select(future, beating).await {
+            Either::First(output) => output,
+            Either::Second(()) => unreachable!(),
+        }
+    }
+}
+
+/// Beat the heartbeat of the task `name`, expected every `period`.
+///
+/// `heartbeat!("measurement", Duration::from_secs(1))` beats once, in the
+/// task's loop; `heartbeat!("mdns", Duration::from_secs(30), future)` awaits
+/// `future`, beating meanwhile.
+#[macro_export]
+macro_rules! heartbeat {
+    ($name:literal, $period:expr) => {{
+        static HEARTBEAT: $crate::tasks::Heartbeat = $crate::tasks::Heartbeat::new($name, $period);
+        HEARTBEAT.beat();
+    }};
+    ($name:literal, $period:expr, $future:expr) => {{
+        static HEARTBEAT: $crate::tasks::Heartbeat = $crate::tasks::Heartbeat::new($name, $period);
+        HEARTBEAT.beat_while($future).await
+    }};
+}
+
+/// Handler for `GET /debug/tasks`.
+#[cfg(feature = "debug-tasks")]
+pub async fn serve(accept: crate::Accept) -> impl picoserve::response::IntoResponse {
+    use serde_json::{json, Value};
+    use wot_esp_demo_core::tasks::is_stale;
+
+    let now_s = Instant::now().as_secs();
+    let tasks: Value = REGISTRY.lock(|registry| {
+        registry
+            .borrow()
+            .iter()
+            .map(|task| {
+                let age_s = now_s.saturating_sub(task.last_s.load(Ordering::Relaxed).into());
+                let period_s = task.period.as_secs();
+                json!({
+                    "name": task.name,
+                    "age_s": age_s,
+                    "period_s": period_s,
+                    "stale": is_stale(age_s, period_s),
+                })
+            })
+            .collect()
+    });
+
+    crate::respond_json_negotiated(accept, crate::to_json_response(&tasks))
+}
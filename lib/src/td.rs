@@ -0,0 +1,382 @@
+//! Post-processing applied to every Thing Description before it is served,
+//! and its replacement at run time with [`update`].
+//!
+//! The TD served and the one it replaced are kept, each freed once no
+//! response reads it any more, see [`served`]: a Thing whose TD changes with
+//! its settings does not leak a copy per change.
+//!
+//! The SHA-256 of the TD served is kept along with it, served in hex at
+//! `/.well-known/wot.sha256` and advertised over mDNS, see
+//! [`wot_esp_demo_core::integrity`]. [`serve`] answers `GET /` with the
+//! same headers as the simulator, see [`headers`], and 304 when the
+//! client's copy has the ETag of the TD served.
+
+use core::cell::{Cell, RefCell};
+
+use alloc::{string::String, vec::Vec};
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, CriticalSectionMutex},
+    mutex::Mutex,
+    signal::Signal,
+};
+use picoserve::{
+    io::Write,
+    response::{Content, IntoResponse, Response, StatusCode},
+};
+use wot_esp_demo_core::integrity;
+
+use crate::{
+    compress::{self, AcceptEncoding, IfNoneMatch},
+    error::ThingError,
+    heap, not_acceptable, Accept,
+};
+
+pub use wot_esp_demo_core::td::{
+    fallback, forwarded_base, headers, merge_metadata, rebase, rebase_json,
+    serialize_with_metadata, GroupProperty, TdLink, TdMetadata, TD_CACHE_CONTROL, TD_ERROR,
+    TD_MEDIA_TYPE, TD_VARY,
+};
+
+/// Firmware version: crate version plus the git revision it was built from.
+pub const FIRMWARE_VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "+", env!("GIT_HASH"));
+
+/// Support contact (URL or `mailto:`), from the `SUPPORT_URL` env var, and
+/// base of the served TD in place of the DHCP address, e.g. the URL of a
+/// reverse proxy, from the `PUBLIC_BASE_URL` env var.
+pub use crate::config::{PUBLIC_BASE_URL, SUPPORT_URL};
+
+/// The TD base a reverse proxy asked for with `X-Forwarded-Host` and
+/// `X-Forwarded-Proto`, see [`forwarded_base`].
+pub struct ForwardedBase(pub Option<String>);
+
+impl<'r, S> picoserve::extract::FromRequestParts<'r, S> for ForwardedBase {
+    type Rejection = core::convert::Infallible;
+
+    async fn from_request_parts(
+        _state: &'r S,
+        request_parts: &picoserve::request::RequestParts<'r>,
+    ) -> Result<Self, Self::Rejection> {
+        let header = |name| {
+            request_parts
+                .headers()
+                .get(name)
+                .and_then(|v| v.as_str().ok())
+        };
+
+        Ok(Self(forwarded_base(
+            header("X-Forwarded-Host"),
+            header("X-Forwarded-Proto"),
+        )))
+    }
+}
+
+/// Serialize with `meta` the TD `build` makes for the Thing `name`.
+///
+/// If the builder fails, e.g. on a malformed affordance added during
+/// development, the error is logged and the [`fallback`] TD served instead,
+/// with the error as `tdError`: the Thing stays reachable rather than
+/// resetting before the network is up. The size of the TD and the heap it
+/// took are logged at debug level.
+///
+/// # Errors
+///
+/// Returns an error if the TD cannot be serialized.
+pub fn build(
+    name: &str,
+    base_uri: String,
+    id: String,
+    meta: &TdMetadata,
+    build: fn(&str, String, String) -> Result<wot_td::Thing, wot_td::builder::Error>,
+) -> Result<String, serde_json::Error> {
+    let (used_before, _) = heap::usage();
+
+    let td = match build(name, base_uri.clone(), id.clone()) {
+        Ok(thing) => serialize_with_metadata(&thing, meta)?,
+        Err(e) => {
+            let error = alloc::format!("{e}");
+            error!(
+                "{}: building the TD failed, serving a minimal one: {}",
+                name,
+                error.as_str()
+            );
+            fallback(name, &base_uri, &id, &error, meta)?
+        }
+    };
+
+    let (used_after, free) = heap::usage();
+    debug!(
+        "TD of {} serialized: {} bytes, heap used {} -> {} bytes, {} free",
+        name,
+        td.len(),
+        used_before,
+        used_after,
+        free
+    );
+    Ok(td)
+}
+
+/// A TD served, with its compressed copy and their ETags.
+struct Served {
+    json: String,
+    gzip: Vec<u8>,
+    etag: String,
+    etag_gzip: String,
+}
+
+/// The TD served and the one it replaced, with the number of [`Td`] still
+/// reading each.
+struct Buffers {
+    slots: [Option<Served>; 2],
+    readers: [u32; 2],
+    current: usize,
+}
+
+static BUFFERS: CriticalSectionMutex<RefCell<Buffers>> =
+    CriticalSectionMutex::new(RefCell::new(Buffers {
+        slots: [None, None],
+        readers: [0, 0],
+        current: 0,
+    }));
+/// Signaled when the last [`Td`] reading a slot is dropped.
+static RELEASED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+/// Held by [`update`], so two patches do not both start from the same TD.
+static UPDATING: Mutex<CriticalSectionRawMutex, ()> = Mutex::new(());
+static SHA256: CriticalSectionMutex<Cell<Option<[u8; 32]>>> =
+    CriticalSectionMutex::new(Cell::new(None));
+static SHA256_CHANGED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+/// The TD served when [`served`] was called, kept by [`set`] until dropped.
+pub struct Td {
+    slot: usize,
+    json: &'static str,
+    gzip: &'static [u8],
+    etag: &'static str,
+    etag_gzip: &'static str,
+}
+
+impl Td {
+    /// The TD JSON.
+    #[must_use]
+    pub fn json(&self) -> &str {
+        self.json
+    }
+
+    /// The TD compressed with gzip.
+    #[must_use]
+    pub fn gzip(&self) -> &[u8] {
+        self.gzip
+    }
+
+    /// The ETag of the TD, or of its compressed copy if `gzip`.
+    #[must_use]
+    pub fn etag(&self, gzip: bool) -> &str {
+        if gzip {
+            self.etag_gzip
+        } else {
+            self.etag
+        }
+    }
+}
+
+impl Drop for Td {
+    fn drop(&mut self) {
+        let released = BUFFERS.lock(|buffers| {
+            let mut buffers = buffers.borrow_mut();
+            buffers.readers[self.slot] -= 1;
+            buffers.readers[self.slot] == 0
+        });
+        if released {
+            RELEASED.signal(());
+        }
+    }
+}
+
+/// The TD served, `None` before [`set`].
+#[must_use]
+pub fn served() -> Option<Td> {
+    /// Borrow `data` for as long as the [`Td`] holding it.
+    fn keep<T: ?Sized>(data: &T) -> &'static T {
+        // SAFETY: the data is on the heap, and `set` frees a slot only once
+        // no `Td` reads it.
+        unsafe { &*core::ptr::from_ref(data) }
+    }
+
+    BUFFERS.lock(|buffers| {
+        let mut buffers = buffers.borrow_mut();
+        let slot = buffers.current;
+        let served = buffers.slots[slot].as_ref()?;
+        let td = Td {
+            slot,
+            json: keep(served.json.as_str()),
+            gzip: keep(served.gzip.as_slice()),
+            etag: keep(served.etag.as_str()),
+            etag_gzip: keep(served.etag_gzip.as_str()),
+        };
+        buffers.readers[slot] += 1;
+        Some(td)
+    })
+}
+
+/// Serve `td`, and its compressed copy, as [`crate::EspThing::run`] does
+/// once the network is up.
+///
+/// The TD served until now is kept for the responses still sending it, and
+/// the one before it freed, once its last response is sent.
+pub async fn set(td: String) {
+    let digest = integrity::sha256(&td);
+    let mut served = Some(Served {
+        gzip: compress::gzip(td.as_bytes()),
+        etag: integrity::etag(&digest, false),
+        etag_gzip: integrity::etag(&digest, true),
+        json: td,
+    });
+
+    let replaced = loop {
+        let replaced = BUFFERS.lock(|buffers| {
+            let mut buffers = buffers.borrow_mut();
+            let next = 1 - buffers.current;
+            if buffers.readers[next] > 0 {
+                return None;
+            }
+            let replaced = core::mem::replace(&mut buffers.slots[next], served.take());
+            buffers.current = next;
+            Some(replaced)
+        });
+        match replaced {
+            Some(replaced) => break replaced,
+            None => RELEASED.wait().await,
+        }
+    };
+    drop(replaced);
+
+    SHA256.lock(|sha256| sha256.set(Some(digest)));
+    SHA256_CHANGED.signal(());
+}
+
+/// The SHA-256 of the TD served, `None` before [`set`].
+#[must_use]
+pub fn sha256() -> Option<[u8; 32]> {
+    SHA256.lock(Cell::get)
+}
+
+/// Wait for the SHA-256 of the TD served to differ from `advertised`, for
+/// the mDNS task.
+pub(crate) async fn sha256_changed(advertised: Option<[u8; 32]>) {
+    while sha256() == advertised {
+        SHA256_CHANGED.wait().await;
+    }
+}
+
+/// The body of a response sending the TD served, or its compressed copy if
+/// `gzip`.
+struct Body {
+    td: Td,
+    gzip: bool,
+}
+
+impl Body {
+    fn bytes(&self) -> &[u8] {
+        if self.gzip {
+            self.td.gzip()
+        } else {
+            self.td.json().as_bytes()
+        }
+    }
+}
+
+impl Content for Body {
+    fn content_type(&self) -> &'static str {
+        TD_MEDIA_TYPE
+    }
+
+    fn content_length(&self) -> usize {
+        self.bytes().len()
+    }
+
+    async fn write_content<W: Write>(self, mut writer: W) -> Result<(), W::Error> {
+        writer.write_all(self.bytes()).await
+    }
+}
+
+/// Answer `GET /` with the TD served: 406 unless the client accepts
+/// [`TD_MEDIA_TYPE`], 503 before [`set`], rebased and uncompressed per
+/// request when a reverse proxy sent `forwarded`, 304 when `if_none_match`
+/// has its ETag, gzipped when `accept_encoding` allows.
+pub(crate) fn serve(
+    accept: Accept,
+    accept_encoding: AcceptEncoding,
+    if_none_match: &IfNoneMatch,
+    forwarded: Option<String>,
+) -> impl IntoResponse {
+    if !accept.td_json() {
+        return Err(Ok(not_acceptable(TD_MEDIA_TYPE)));
+    }
+    let Some(td) = served() else {
+        return Err(Err(StatusCode::SERVICE_UNAVAILABLE));
+    };
+
+    // Through a reverse proxy the TD is rebased, uncompressed, per request.
+    if let Some(rebased) = forwarded.and_then(|base| rebase_json(td.json(), &base).ok()) {
+        return Ok(Err(Response::ok(rebased)
+            .with_header("Content-Type", TD_MEDIA_TYPE)
+            .with_header("Vary", "X-Forwarded-Host, X-Forwarded-Proto")
+            .with_header("Cache-Control", TD_CACHE_CONTROL)));
+    }
+
+    let gzip = accept_encoding.gzip();
+    let etag = String::from(td.etag(gzip));
+
+    if if_none_match.matches(&etag) {
+        return Ok(Ok(Err(Response::new(StatusCode::NOT_MODIFIED, "")
+            .with_header("ETag", etag)
+            .with_header("Vary", TD_VARY)
+            .with_header("Cache-Control", TD_CACHE_CONTROL))));
+    }
+
+    // The TD embeds the address the Thing got at start-up.
+    let response = Response::ok(Body { td, gzip })
+        .with_header("Vary", TD_VARY)
+        .with_header("Cache-Control", TD_CACHE_CONTROL)
+        .with_header("ETag", etag);
+    Ok(Ok(Ok(if gzip {
+        Ok(response.with_header("Content-Encoding", "gzip"))
+    } else {
+        Err(response)
+    })))
+}
+
+/// Handler for `GET /.well-known/wot.sha256`: the SHA-256 of the TD served
+/// at `/` in hex, 404 before the TD is set.
+pub(crate) async fn serve_sha256() -> impl IntoResponse {
+    sha256().map_or_else(
+        || Err(StatusCode::NOT_FOUND),
+        |digest| {
+            Ok(Response::ok(integrity::hex(&digest))
+                .with_header("Content-Type", "text/plain")
+                .with_header("Cache-Control", TD_CACHE_CONTROL))
+        },
+    )
+}
+
+/// Serve the TD served with `patch` applied, e.g. a setting that changes
+/// its units. Nothing is replaced when `patch` leaves the TD as it is, or
+/// before [`set`].
+///
+/// # Errors
+///
+/// Returns the error of `patch`, the TD served is then kept.
+pub async fn update(
+    patch: impl FnOnce(&str) -> Result<String, serde_json::Error>,
+) -> Result<(), ThingError> {
+    let _updating = UPDATING.lock().await;
+    let Some(td) = served() else {
+        return Ok(());
+    };
+    let patched = patch(td.json())?;
+    if patched == td.json() {
+        return Ok(());
+    }
+    drop(td);
+    set(patched).await;
+    Ok(())
+}
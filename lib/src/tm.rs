@@ -0,0 +1,89 @@
+//! Instantiate the Thing Description from a Thing Model.
+//!
+//! The model is embedded with `include_str!` and may use the `{{BASE}}`,
+//! `{{ID}}` and `{{TITLE}}` placeholders anywhere in its string values,
+//! including inside hrefs.
+
+use alloc::{string::String, vec::Vec};
+
+use serde_json::{json, Map, Value};
+
+pub use wot_esp_demo_core::tm::substitute;
+
+use crate::td::{merge_metadata, TdMetadata};
+
+/// A Thing Model embedded in the firmware.
+#[derive(Clone, Copy)]
+pub struct ThingModel {
+    /// The Thing Model JSON, usually `include_str!`-ed.
+    pub json: &'static str,
+    /// Canonical location of the model, linked from the TD with rel `type`.
+    pub url: &'static str,
+}
+
+/// Remove the `tm:ThingModel` type and every `tm:`-prefixed member.
+fn strip_tm_keywords(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|k, _| !k.starts_with("tm:"));
+
+            match map.get_mut("@type") {
+                Some(Value::String(ty)) if ty == "tm:ThingModel" => {
+                    map.remove("@type");
+                }
+                Some(Value::Array(types)) => {
+                    types.retain(|t| t.as_str() != Some("tm:ThingModel"));
+                    if types.is_empty() {
+                        map.remove("@type");
+                    }
+                }
+                _ => {}
+            }
+
+            map.values_mut().for_each(strip_tm_keywords);
+        }
+        Value::Array(items) => items.iter_mut().for_each(strip_tm_keywords),
+        _ => {}
+    }
+}
+
+/// Produce the serialized TD for `model`.
+///
+/// # Errors
+///
+/// Returns an error if the model is not valid JSON or is not an object.
+pub fn instantiate(
+    model: &ThingModel,
+    base: &str,
+    id: &str,
+    title: &str,
+    meta: &TdMetadata,
+) -> Result<String, serde_json::Error> {
+    let json = substitute(model.json, &[("BASE", base), ("ID", id), ("TITLE", title)]);
+
+    let mut td: Value = serde_json::from_str(&json)?;
+    strip_tm_keywords(&mut td);
+
+    let Value::Object(map) = &mut td else {
+        return Err(serde::de::Error::custom("Thing Model is not an object"));
+    };
+
+    add_type_link(map, model.url);
+    merge_metadata(map, meta);
+
+    serde_json::to_string(&td)
+}
+
+fn add_type_link(td: &mut Map<String, Value>, url: &str) {
+    let links = td
+        .entry("links")
+        .or_insert_with(|| Value::Array(Vec::new()));
+
+    if let Value::Array(links) = links {
+        links.push(json!({
+            "rel": "type",
+            "href": url,
+            "type": "application/tm+json",
+        }));
+    }
+}
@@ -0,0 +1,74 @@
+//! The properties over JSON in UDP datagrams, experimental: a border router
+//! script can bridge them to Thread or Zigbee until the Things speak those.
+//!
+//! The Thing listens on [`port`], set with the `UDP_PORT` env var at build
+//! time, 5684 by default. Each datagram is a request, answered with one
+//! datagram, see [`wot_esp_demo_core::udp`]:
+//!
+//! ```sh
+//! echo -n '{"op":"readproperty","name":"temperature"}' | nc -u -w1 <ip> 5684
+//! {"op":"readproperty","name":"temperature","value":21.5}
+//! ```
+//!
+//! The properties of [`crate::EspThing::UDP_PROPERTIES`] get a form with
+//! the `udp-json` subprotocol in the TD, and are read with
+//! [`crate::EspThingState::read_property`].
+
+use embassy_net::{
+    udp::{PacketMetadata, UdpSocket},
+    Stack,
+};
+use wot_esp_demo_core::udp::{read_reply, read_request, DEFAULT_PORT, MAX_DATAGRAM};
+
+use crate::EspThingState;
+
+/// Sockets used.
+pub const UDP_STACK_SIZE: usize = 1;
+
+/// Room for a request of up to the Wi-Fi MTU, so one too large for
+/// [`wot_esp_demo_core::udp::MAX_REQUEST`] still gets an error reply.
+const RX_BUFFER: usize = 1500;
+
+/// Port of the transport, from the `UDP_PORT` build-time env var, 5684 by
+/// default.
+#[must_use]
+pub fn port() -> u16 {
+    crate::config::UDP_PORT
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(DEFAULT_PORT)
+}
+
+/// Answer the requests to [`port`] on the station `stack`, reading the
+/// properties from `state`.
+pub(crate) async fn serve<S: EspThingState>(stack: Stack<'static>, state: &'static S) {
+    let mut rx_meta = [PacketMetadata::EMPTY; 4];
+    let mut rx_buffer = [0; RX_BUFFER];
+    let mut tx_meta = [PacketMetadata::EMPTY; 4];
+    let mut tx_buffer = [0; 2 * MAX_DATAGRAM];
+
+    let mut socket = UdpSocket::new(
+        stack,
+        &mut rx_meta,
+        &mut rx_buffer,
+        &mut tx_meta,
+        &mut tx_buffer,
+    );
+    let port = port();
+    if socket.bind(port).is_err() {
+        warn!("UDP: cannot listen on port {}", port);
+        return;
+    }
+    info!("UDP: listening on port {}", port);
+
+    let mut request = [0; RX_BUFFER];
+    loop {
+        let Ok((len, meta)) = socket.recv_from(&mut request).await else {
+            continue;
+        };
+        let reply = match read_request(&request[..len]) {
+            Ok(name) => read_reply(&name, state.read_property(&name).await),
+            Err(error) => error,
+        };
+        let _ = socket.send_to(&reply, meta.endpoint).await;
+    }
+}
@@ -0,0 +1,20 @@
+//! Human-friendly status page served at `/ui`.
+//!
+//! The page reads the TD from `/`, renders every property generically,
+//! subscribes to the SSE streams and offers controls for writable properties,
+//! so the same page works for every Thing.
+
+use picoserve::response::IntoResponse;
+
+use crate::compress::{asset, AcceptEncoding, Asset, IfNoneMatch};
+
+/// The files under `ui/`, gzip-compressed and hashed by the build script.
+static ASSETS: &[Asset] = &include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+
+/// Handler for `GET /ui`.
+pub async fn index(
+    accept_encoding: AcceptEncoding,
+    if_none_match: IfNoneMatch,
+) -> impl IntoResponse {
+    asset(&ASSETS[0], accept_encoding, &if_none_match)
+}
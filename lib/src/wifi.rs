@@ -0,0 +1,245 @@
+//! Wi-Fi networks the station may join and the order to try them in.
+//!
+//! Credentials are baked in at build time, see [`crate::config`]: `SSID`/`PASSWORD` first, then
+//! `SSID_1`/`PASSWORD_1` up to `SSID_4`/`PASSWORD_4`. Set `SSID_<n>_HIDDEN`
+//! for networks that do not broadcast their SSID.
+//!
+//! Setting `EAP_IDENTITY` (`EAP_IDENTITY_<n>`) turns the network into a
+//! WPA2-Enterprise one: PEAP with MSCHAPv2, authenticating as `EAP_USERNAME`
+//! (the identity when unset) with `PASSWORD`.
+//!
+//! `WIFI_COUNTRY` (e.g. `JP`) sets the regulatory domain and
+//! `WIFI_MAX_TX_POWER` caps the transmit power in dBm; the `txPower` property
+//! lowers it further at runtime.
+
+use core::cell::Cell;
+
+use alloc::vec::Vec;
+
+use embassy_sync::{
+    blocking_mutex::{raw::CriticalSectionRawMutex, CriticalSectionMutex},
+    signal::Signal,
+};
+use esp_radio::wifi::{
+    scan::ScanConfig,
+    sta::{
+        eap::{EapStationConfig, TtlsPhase2Method},
+        StationConfig,
+    },
+    Config, WifiController,
+};
+use picoserve::response::{IntoResponse, StatusCode};
+pub use wot_esp_demo_core::wifi::{
+    order_by_signal, quarter_dbm, Auth, Credentials, RadioSettings, TX_POWER_DBM,
+};
+
+use crate::{
+    audit,
+    config::{Network, NETWORKS, WIFI_COUNTRY, WIFI_MAX_TX_POWER},
+    respond_negotiated,
+    write::InvalidValue,
+    Accept, JsonBody, ThingError,
+};
+
+/// CA certificate validating the enterprise authentication server.
+///
+/// `None` skips the validation. To enable it, embed the PEM certificate with
+/// `Some(include_bytes!("../ca.pem"))`.
+pub const EAP_CA_CERT: Option<&[u8]> = None;
+
+const fn credentials(network: Network) -> Option<Credentials> {
+    let Some(ssid) = network.ssid else {
+        return None;
+    };
+    let password = match network.password {
+        Some(password) => password,
+        None => "",
+    };
+    let auth = match network.eap_identity {
+        Some(identity) => Auth::Enterprise {
+            identity,
+            username: match network.eap_username {
+                Some(username) => username,
+                None => identity,
+            },
+            password,
+        },
+        None => Auth::Psk(password),
+    };
+
+    Some(Credentials {
+        ssid,
+        auth,
+        hidden: network.hidden.is_some(),
+    })
+}
+
+/// Configured networks, highest priority first.
+pub const CREDENTIALS: [Option<Credentials>; 5] = [
+    credentials(NETWORKS[0]),
+    credentials(NETWORKS[1]),
+    credentials(NETWORKS[2]),
+    credentials(NETWORKS[3]),
+    credentials(NETWORKS[4]),
+];
+
+const _: () = {
+    let mut i = 0;
+    let mut any = false;
+    while i < CREDENTIALS.len() {
+        any |= CREDENTIALS[i].is_some();
+        i += 1;
+    }
+    assert!(any, "set SSID and PASSWORD (or SSID_1 and PASSWORD_1, ...)");
+};
+
+/// The configured networks, highest priority first.
+pub fn configured() -> impl Iterator<Item = Credentials> {
+    CREDENTIALS.into_iter().flatten()
+}
+
+/// Station configuration joining `network`.
+///
+/// With the `maintenance-ap` feature the maintenance access point is added.
+pub fn config(network: Credentials) -> Config {
+    let config = station_config(network);
+
+    #[cfg(feature = "maintenance-ap")]
+    let config = crate::maintenance_ap::with_access_point(config);
+
+    config
+}
+
+fn station_config(network: Credentials) -> Config {
+    match network.auth {
+        Auth::Psk(password) => Config::Station(
+            StationConfig::default()
+                .with_ssid(network.ssid)
+                .with_password(password.into()),
+        ),
+        Auth::Enterprise {
+            identity,
+            username,
+            password,
+        } => Config::EapStation(
+            EapStationConfig::default()
+                .with_ssid(network.ssid)
+                .with_identity(Some(identity.into()))
+                .with_username(Some(username.into()))
+                .with_password(Some(password.into()))
+                .with_ttls_phase2_method(TtlsPhase2Method::Mschapv2)
+                .with_ca_cert(EAP_CA_CERT),
+        ),
+    }
+}
+
+/// Networks to try, in order.
+///
+/// With `prefer_strongest` and more than one network configured, a scan
+/// decides the order (see [`order_by_signal`]); otherwise it is the priority
+/// order.
+pub async fn candidates(
+    controller: &mut WifiController<'static>,
+    prefer_strongest: bool,
+) -> Vec<Credentials> {
+    let networks: Vec<_> = configured().collect();
+    if !prefer_strongest || networks.len() < 2 {
+        return networks;
+    }
+
+    match controller.scan_async(&ScanConfig::default()).await {
+        Ok(aps) => {
+            let seen: Vec<_> = aps
+                .iter()
+                .map(|ap| (ap.ssid.as_str(), ap.signal_strength))
+                .collect();
+            order_by_signal(&networks, &seen)
+        }
+        Err(e) => {
+            warn!("Wifi scan failed: {:?}", e);
+            networks
+        }
+    }
+}
+
+/// Whether `network` is in range, `None` when a scan cannot tell.
+///
+/// Used after a failed connection to tell a missing access point from
+/// rejected credentials.
+pub async fn in_range(
+    controller: &mut WifiController<'static>,
+    network: Credentials,
+) -> Option<bool> {
+    if network.hidden {
+        return None;
+    }
+
+    let aps = controller.scan_async(&ScanConfig::default()).await.ok()?;
+    Some(aps.iter().any(|ap| ap.ssid == network.ssid))
+}
+
+static RADIO: CriticalSectionMutex<Cell<Option<RadioSettings>>> =
+    CriticalSectionMutex::new(Cell::new(None));
+
+/// Transmit power in effect, in dBm.
+static TX_POWER: CriticalSectionMutex<Cell<i8>> =
+    CriticalSectionMutex::new(Cell::new(*TX_POWER_DBM.end()));
+
+/// Transmit power written to `txPower`, applied by the connection task.
+static TX_POWER_REQUEST: Signal<CriticalSectionRawMutex, i8> = Signal::new();
+
+/// The radio settings baked in with `WIFI_COUNTRY` and `WIFI_MAX_TX_POWER`.
+///
+/// # Errors
+///
+/// If either is invalid, so a typo stops the Thing at start-up instead of
+/// transmitting out of the rules.
+pub fn radio_settings() -> Result<RadioSettings, ThingError> {
+    let settings =
+        RadioSettings::parse(WIFI_COUNTRY, WIFI_MAX_TX_POWER).map_err(ThingError::Config)?;
+    RADIO.lock(|radio| radio.set(Some(settings)));
+    Ok(settings)
+}
+
+/// The radio settings in effect, the defaults before start-up.
+#[must_use]
+pub fn radio() -> RadioSettings {
+    RADIO.lock(Cell::get).unwrap_or_default()
+}
+
+/// Transmit power in effect, in dBm.
+#[must_use]
+pub fn tx_power() -> i8 {
+    TX_POWER.lock(Cell::get)
+}
+
+/// Set the transmit power of `controller` to `dbm`.
+pub(crate) fn apply_tx_power(controller: &mut WifiController<'static>, dbm: i8) {
+    match controller.set_max_tx_power(quarter_dbm(dbm)) {
+        Ok(()) => TX_POWER.lock(|power| power.set(dbm)),
+        Err(e) => warn!("Cannot set the transmit power to {} dBm: {:?}", dbm, e),
+    }
+}
+
+/// The next transmit power written to `txPower`.
+pub(crate) async fn tx_power_requested() -> i8 {
+    TX_POWER_REQUEST.wait().await
+}
+
+pub(crate) async fn read_tx_power(accept: Accept) -> impl IntoResponse {
+    respond_negotiated(accept, &tx_power())
+}
+
+/// Handler for `PUT /properties/txPower`, the power is applied by the
+/// connection task.
+pub(crate) async fn write_tx_power(JsonBody(dbm): JsonBody<i8>) -> impl IntoResponse {
+    if !radio().allows(dbm) {
+        return Err(InvalidValue(
+            "txPower must be from 2 dBm to the WIFI_MAX_TX_POWER limit",
+        ));
+    }
+
+    audit::record("txPower", &dbm);
+    TX_POWER_REQUEST.signal(dbm);
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,230 @@
+//! Shared handling of property writes.
+//!
+//! [`JsonBody`] replaces `picoserve::extract::Json` in write routes: it rejects
+//! oversized bodies with HTTP 413 before reading them and closes the
+//! connection, so a misbehaving client cannot hold a pool slot until the read
+//! timeout. Bodies are deserialized with `serde-json-core`, see
+//! [`wot_esp_demo_core::body`]; a body that does not match the type gets a 400
+//! with the serde error as `{"error":"..."}`.
+//!
+//! [`ValueBody`] takes any JSON value, for routes forwarding it as is.
+//!
+//! [`ActionBody`] checks an action input against the schema of its type
+//! before deserializing it, see [`wot_esp_demo_core::schema`]; the first
+//! violation gets a 400 with its path, e.g.
+//! `{"error":"must be at most 255","path":"/target_brightness"}`.
+//!
+//! All three refuse a write from a web page on another host with a 403
+//! before reading the body, see [`crate::origin`]. Routes without a body,
+//! such as actions without input and the `/debug` resets, take a
+//! [`WriteGuard`] for the same check.
+
+use alloc::string::{String, ToString};
+
+use picoserve::{
+    io::Read,
+    request::{RequestBody, RequestParts},
+    response::{Connection, IntoResponse, Response, ResponseWriter, StatusCode},
+    ResponseSent,
+};
+use serde_json::{json, Value};
+use wot_esp_demo_core::{
+    body,
+    schema::{InputSchema, Violation},
+};
+
+/// Default body limit for property writes, in bytes.
+pub const MAX_WRITE_BODY: usize = 512;
+
+/// JSON request body of at most `MAX` bytes, deserialized as `T`.
+///
+/// Write routes use `JsonBody::<_>(value)` so the default limit applies; routes
+/// taking a struct pass the bound declared next to it, e.g.
+/// `JsonBody::<OffTimerInput, OFF_TIMER_MAX_BODY>(input)`.
+pub struct JsonBody<T, const MAX: usize = MAX_WRITE_BODY>(pub T);
+
+/// Any JSON request body of at most `MAX` bytes.
+///
+/// Parsed with `serde_json` onto the heap, unlike [`JsonBody`]; only for
+/// routes that do not know the shape of the value.
+pub struct ValueBody<const MAX: usize = MAX_WRITE_BODY>(pub Value);
+
+/// Action input of at most `MAX` bytes, valid for its [`InputSchema`] and
+/// deserialized as `T`.
+pub struct ActionBody<T, const MAX: usize = MAX_WRITE_BODY>(pub T);
+
+/// A write without a body whose origin is checked, see [`crate::origin`].
+///
+/// Taken by every `POST` route that has no [`JsonBody`], [`ValueBody`] or
+/// [`ActionBody`], e.g. `post(|_guard: WriteGuard| async { ... })`.
+pub struct WriteGuard;
+
+/// Why a [`JsonBody`] could not be extracted.
+pub enum BodyRejection {
+    /// The write comes from a web page on another host, with this origin.
+    CrossOrigin(String),
+    /// The declared body length exceeds the route limit.
+    TooLarge,
+    /// The body could not be read.
+    Read,
+    /// The body is not valid JSON for the expected type.
+    Invalid(body::Error),
+    /// The body is not valid JSON.
+    InvalidValue,
+    /// The body does not match the schema of the input.
+    Violation(Violation),
+}
+
+impl IntoResponse for BodyRejection {
+    async fn write_to<R: Read, W: ResponseWriter<Error = R::Error>>(
+        self,
+        connection: Connection<'_, R>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        match self {
+            Self::CrossOrigin(origin) => {
+                let error = json!({
+                    "error": "writes from other sites are refused",
+                    "origin": origin,
+                    "hint": "add the host to WRITE_ORIGINS, or send Authorization: Bearer <WRITE_TOKEN>",
+                });
+                Response::new(StatusCode::FORBIDDEN, error.to_string())
+                    .with_header("Content-Type", "application/json")
+                    .write_to(connection, response_writer)
+                    .await
+            }
+            Self::TooLarge => {
+                // The body is left unread, so the connection cannot be reused.
+                Response::new(StatusCode::PAYLOAD_TOO_LARGE, "Request body too large")
+                    .with_header("Content-Type", "text/plain")
+                    .with_header("Connection", "close")
+                    .write_to(connection, response_writer)
+                    .await
+            }
+            Self::Read => {
+                Response::new(StatusCode::BAD_REQUEST, "Cannot read the request body")
+                    .with_header("Content-Type", "text/plain")
+                    .write_to(connection, response_writer)
+                    .await
+            }
+            Self::Invalid(error) => {
+                Response::new(StatusCode::BAD_REQUEST, body::error(&error).to_string())
+                    .with_header("Content-Type", "application/json")
+                    .write_to(connection, response_writer)
+                    .await
+            }
+            Self::InvalidValue => {
+                Response::new(StatusCode::BAD_REQUEST, "Invalid JSON value")
+                    .with_header("Content-Type", "text/plain")
+                    .write_to(connection, response_writer)
+                    .await
+            }
+            Self::Violation(violation) => {
+                Response::new(StatusCode::BAD_REQUEST, violation.to_json().to_string())
+                    .with_header("Content-Type", "application/json")
+                    .write_to(connection, response_writer)
+                    .await
+            }
+        }
+    }
+}
+
+/// A property value rejected by its validation, with the reason.
+pub struct InvalidValue(pub &'static str);
+
+impl IntoResponse for InvalidValue {
+    async fn write_to<R: Read, W: ResponseWriter<Error = R::Error>>(
+        self,
+        connection: Connection<'_, R>,
+        response_writer: W,
+    ) -> Result<ResponseSent, W::Error> {
+        Response::new(StatusCode::BAD_REQUEST, self.0)
+            .with_header("Content-Type", "text/plain")
+            .write_to(connection, response_writer)
+            .await
+    }
+}
+
+impl<'r, S> picoserve::extract::FromRequestParts<'r, S> for WriteGuard {
+    type Rejection = BodyRejection;
+
+    async fn from_request_parts(
+        _state: &'r S,
+        request_parts: &RequestParts<'r>,
+    ) -> Result<Self, Self::Rejection> {
+        crate::origin::check_write(request_parts).map_err(BodyRejection::CrossOrigin)?;
+        Ok(Self)
+    }
+}
+
+/// Read a body of at most `MAX` bytes, once its origin is checked.
+async fn read_body<'r, R: Read, const MAX: usize>(
+    request_parts: &RequestParts<'r>,
+    request_body: RequestBody<'r, R>,
+) -> Result<&'r [u8], BodyRejection> {
+    crate::origin::check_write(request_parts).map_err(BodyRejection::CrossOrigin)?;
+    if request_body.content_length() > MAX {
+        return Err(BodyRejection::TooLarge);
+    }
+
+    request_body
+        .read_all()
+        .await
+        .map(|body| &*body)
+        .map_err(|_| BodyRejection::Read)
+}
+
+impl<'r, S, T, const MAX: usize> picoserve::extract::FromRequest<'r, S> for JsonBody<T, MAX>
+where
+    T: serde::de::DeserializeOwned,
+{
+    type Rejection = BodyRejection;
+
+    async fn from_request<R: Read>(
+        _state: &'r S,
+        request_parts: RequestParts<'r>,
+        request_body: RequestBody<'r, R>,
+    ) -> Result<Self, Self::Rejection> {
+        let bytes = read_body::<R, MAX>(&request_parts, request_body).await?;
+
+        body::parse(bytes).map(Self).map_err(BodyRejection::Invalid)
+    }
+}
+
+impl<'r, S, const MAX: usize> picoserve::extract::FromRequest<'r, S> for ValueBody<MAX> {
+    type Rejection = BodyRejection;
+
+    async fn from_request<R: Read>(
+        _state: &'r S,
+        request_parts: RequestParts<'r>,
+        request_body: RequestBody<'r, R>,
+    ) -> Result<Self, Self::Rejection> {
+        let bytes = read_body::<R, MAX>(&request_parts, request_body).await?;
+
+        serde_json::from_slice(bytes)
+            .map(Self)
+            .map_err(|_| BodyRejection::InvalidValue)
+    }
+}
+
+impl<'r, S, T, const MAX: usize> picoserve::extract::FromRequest<'r, S> for ActionBody<T, MAX>
+where
+    T: serde::de::DeserializeOwned + InputSchema,
+{
+    type Rejection = BodyRejection;
+
+    async fn from_request<R: Read>(
+        _state: &'r S,
+        request_parts: RequestParts<'r>,
+        request_body: RequestBody<'r, R>,
+    ) -> Result<Self, Self::Rejection> {
+        let bytes = read_body::<R, MAX>(&request_parts, request_body).await?;
+
+        let value: Value =
+            serde_json::from_slice(bytes).map_err(|_| BodyRejection::InvalidValue)?;
+        T::SCHEMA
+            .validate(&value)
+            .map_err(BodyRejection::Violation)?;
+        body::parse(bytes).map(Self).map_err(BodyRejection::Invalid)
+    }
+}
@@ -5,11 +5,17 @@ use clap::{Parser, Subcommand};
 /// Demo definitions: (binary name, package, target triple)
 const DEMOS: &[(&str, &str, &str)] = &[
     ("thermometer", "demo-c3", "riscv32imc-unknown-none-elf"),
+    ("multitherm", "demo-c3", "riscv32imc-unknown-none-elf"),
     ("light", "demo-c3", "riscv32imc-unknown-none-elf"),
     ("button", "demo-c3", "riscv32imc-unknown-none-elf"),
+    ("bare", "demo-c3", "riscv32imc-unknown-none-elf"),
     ("fan", "demo-c6", "riscv32imac-unknown-none-elf"),
 ];
 
+/// Feature sets `check-all` also checks, each with one demo: the code they
+/// gate is built by no default build.
+const FEATURE_CHECKS: &[(&str, &str)] = &[("light", "directory,self-test")];
+
 fn demo_names() -> Vec<&'static str> {
     DEMOS.iter().map(|(name, _, _)| *name).collect()
 }
@@ -33,12 +39,12 @@ struct Cli {
 enum Commands {
     /// Build a demo binary
     Build {
-        /// Demo name: thermometer, light, button, fan
+        /// Demo name: thermometer, multitherm, light, button, bare, fan
         demo: String,
     },
     /// Build and flash a demo to the connected board
     Run {
-        /// Demo name: thermometer, light, button, fan
+        /// Demo name: thermometer, multitherm, light, button, bare, fan
         demo: String,
         /// Serial port (e.g. /dev/cu.usbmodem101). If omitted, espflash auto-detects.
         #[arg(long)]
@@ -46,6 +52,8 @@ enum Commands {
     },
     /// `cargo check` every demo for its target triple
     CheckAll,
+    /// Run the host tests of the TD builders and helpers
+    Test,
     /// List available demos
     List,
 }
@@ -104,10 +112,13 @@ fn main() {
         }
         Commands::CheckAll => {
             let mut failed = false;
-            for (name, _, _) in DEMOS {
-                println!("=== check {name} ===");
+            let checks = DEMOS
+                .iter()
+                .map(|(name, _, _)| (*name, None))
+                .chain(FEATURE_CHECKS.iter().map(|(name, f)| (*name, Some(*f))));
+            for (name, features) in checks {
                 let (bin, pkg, target) = find_demo(name);
-                let args = [
+                let mut args = vec![
                     "check",
                     "-p",
                     pkg,
@@ -118,8 +129,14 @@ fn main() {
                     "-Z",
                     "build-std=alloc,core",
                 ];
+                if let Some(features) = features {
+                    println!("=== check {name} --features {features} ===");
+                    args.extend(["--features", features]);
+                } else {
+                    println!("=== check {name} ===");
+                }
                 println!("$ cargo {}", args.join(" "));
-                match Command::new("cargo").args(args).status() {
+                match Command::new("cargo").args(&args).status() {
                     Ok(s) if s.success() => {}
                     Ok(_) => {
                         eprintln!("check failed for {name}");
@@ -136,6 +153,18 @@ fn main() {
             }
             println!("All demos checked successfully.");
         }
+        Commands::Test => {
+            let args = ["test", "-p", "wot-esp-demo-core"];
+            println!("$ cargo {}", args.join(" "));
+            match Command::new("cargo").args(args).status() {
+                Ok(s) if !s.success() => std::process::exit(1),
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("cargo error: {e}");
+                    std::process::exit(1);
+                }
+            }
+        }
         Commands::List => {
             println!("Available demos:");
             for (name, pkg, target) in DEMOS {